@@ -0,0 +1,202 @@
+/*
+ * A plugin written in C: a Greeter named "hello-c" and a Transformer named
+ * "upper-c", registered at the newest registration ABI level with the
+ * newest vtables. Build it as a shared library against
+ * plugin-interface/include, e.g.
+ *
+ *   cc -shared -fPIC -I plugin-interface/include \
+ *       examples/plugin-c/plugin.c -o libplugin_c.so
+ */
+#include <plugin_interface.h>
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+static const HostContext *host;
+
+/* Copy `s` into memory the host frees, for the nul-terminated entries. */
+static const char *host_string(const char *s, size_t len) {
+    if (host == NULL || host->allocator == NULL) {
+        return NULL;
+    }
+    char *copy = host->allocator->alloc(len + 1);
+    if (copy != NULL) {
+        memcpy(copy, s, len);
+        copy[len] = '\0';
+    }
+    return copy;
+}
+
+static OwnedStr static_str(const char *s) {
+    OwnedStr out = {(uint8_t *)s, strlen(s), NULL};
+    return out;
+}
+
+static void free_bytes(uint8_t *ptr, size_t len) {
+    (void)len;
+    free(ptr);
+}
+
+static void no_op(void *user_data) { (void)user_data; }
+
+static void on_load(void *user_data, const HostInfo *info) {
+    (void)user_data;
+    if (host != NULL) {
+        host->log(host->user_data, LOG_LEVEL_DEBUG, "plugin-c loaded");
+    }
+    (void)info;
+}
+
+static void save_nothing(void *user_data, void *sink, StateSink write) {
+    (void)user_data;
+    (void)sink;
+    (void)write;
+}
+
+static void restore_nothing(void *user_data, const uint8_t *bytes, size_t len) {
+    (void)user_data;
+    (void)bytes;
+    (void)len;
+}
+
+static HealthStatus healthy(void *user_data) {
+    (void)user_data;
+    return HEALTH_STATUS_HEALTHY;
+}
+
+/* Greeter */
+
+static const char *greeter_name(void *user_data) {
+    (void)user_data;
+    return host_string("hello-c", 7);
+}
+
+static void greet(void *user_data, const char *target) {
+    (void)user_data;
+    printf("Hello from C, %s!\n", target);
+}
+
+static OwnedStr greeter_name_str(void *user_data) {
+    (void)user_data;
+    return static_str("hello-c");
+}
+
+static void greet_str(void *user_data, StrRef target) {
+    (void)user_data;
+    printf("Hello from C, %.*s!\n", (int)target.len, (const char *)target.ptr);
+}
+
+static const GreeterVTable greeter_vtable = {
+    .abi_version = PLUGIN_SIZED_VTABLE_ABI,
+    .user_data = NULL,
+    .name = greeter_name,
+    .greet = greet,
+    .on_load = on_load,
+    .on_unload = no_op,
+    .drop = no_op,
+    .save_state = save_nothing,
+    .restore_state = restore_nothing,
+    .name_str = greeter_name_str,
+    .greet_str = greet_str,
+    .struct_size = offsetof(GreeterVTable, context),
+    .flags = 0,
+    .optional = {.health = healthy},
+};
+
+static const GreeterRegistration greeter = {"hello-c", &greeter_vtable};
+static const void *const greeters[] = {&greeter};
+static const RegistrationArray greeter_array = {
+    .count = 1,
+    .registrations = greeters,
+    .factories = NULL,
+    .struct_size = sizeof(RegistrationArray),
+    .flags = 0,
+};
+
+const RegistrationArray *plugin_register_all_Greeter_v4(const HostContext *context) {
+    host = context;
+    return &greeter_array;
+}
+
+void plugin_unregister_all_Greeter_v4(const RegistrationArray *array) {
+    (void)array;
+}
+
+/* Transformer */
+
+static char *uppercase(const char *input, size_t len) {
+    char *out = malloc(len + 1);
+    if (out == NULL) {
+        return NULL;
+    }
+    for (size_t i = 0; i < len; i++) {
+        char c = input[i];
+        out[i] = (c >= 'a' && c <= 'z') ? (char)(c - 'a' + 'A') : c;
+    }
+    out[len] = '\0';
+    return out;
+}
+
+static const char *transform(void *user_data, const char *input) {
+    (void)user_data;
+    size_t len = strlen(input);
+    char *upper = uppercase(input, len);
+    if (upper == NULL) {
+        return NULL;
+    }
+    const char *out = host_string(upper, len);
+    free(upper);
+    return out;
+}
+
+static OwnedStr transform_str(void *user_data, StrRef input) {
+    (void)user_data;
+    OwnedStr out = {NULL, 0, NULL};
+    char *upper = uppercase((const char *)input.ptr, input.len);
+    if (upper != NULL) {
+        out.ptr = (uint8_t *)upper;
+        out.len = input.len;
+        out.free = free_bytes;
+    }
+    return out;
+}
+
+static OwnedStr describe(void *user_data) {
+    (void)user_data;
+    return static_str("uppercases ASCII letters, in C");
+}
+
+static const TransformerVTable transformer_vtable = {
+    .abi_version = PLUGIN_SIZED_VTABLE_ABI,
+    .user_data = NULL,
+    .transform = transform,
+    .on_load = on_load,
+    .on_unload = no_op,
+    .drop = no_op,
+    .save_state = save_nothing,
+    .restore_state = restore_nothing,
+    .transform_str = transform_str,
+    .struct_size = offsetof(TransformerVTable, context),
+    .flags = 0,
+    .optional = {.describe = describe, .health = healthy},
+};
+
+static const TransformerRegistration transformer = {"upper-c", &transformer_vtable};
+static const void *const transformers[] = {&transformer};
+static const RegistrationArray transformer_array = {
+    .count = 1,
+    .registrations = transformers,
+    .factories = NULL,
+    .struct_size = sizeof(RegistrationArray),
+    .flags = 0,
+};
+
+const RegistrationArray *plugin_register_all_Transformer_v4(const HostContext *context) {
+    host = context;
+    return &transformer_array;
+}
+
+void plugin_unregister_all_Transformer_v4(const RegistrationArray *array) {
+    (void)array;
+}
@@ -0,0 +1,33 @@
+//! A greeter and a transformer handed to the host through
+//! `plugin-abi-stable` instead of generated vtables.
+
+use plugin_annotations::{plugin_aggregates, plugin_impl};
+use plugin_interface::{Greeter, Transformer};
+
+#[plugin_aggregates(Greeter)]
+#[derive(Default)]
+struct Hello;
+
+#[plugin_impl(Greeter)]
+impl Greeter for Hello {
+    fn name(&self) -> &str {
+        "Hello"
+    }
+    fn greet(&self, target: &str) {
+        println!("Hello, {}", target);
+    }
+}
+
+#[derive(Default)]
+struct Shout;
+
+#[plugin_impl(Transformer, name = "shout")]
+impl Transformer for Shout {
+    fn transform(&self, input: &str) -> String {
+        input.to_uppercase()
+    }
+    #[optional]
+    fn describe(&self) -> String {
+        "uppercases its input".to_string()
+    }
+}
@@ -0,0 +1,279 @@
+//! An `abi_stable` backend for plugin-interface. Plugins built with the
+//! `abi-stable` feature of plugin-annotations export a `PluginModule` root
+//! module instead of the generated vtables: `#[plugin_impl]` submits each
+//! implementation here, and `#[plugin_aggregates]` exports the module that
+//! lists them as `StableGreeter` and `StableTransformer` trait objects.
+//! abi_stable checks the module's type layout against the host's when it is
+//! loaded.
+//!
+//! Hosts add `AbiStableLoader` with `PluginManager::add_module_loader`, and
+//! then load, watch and call these plugins like any other.
+
+use abi_stable::library::{lib_header_from_path, LibraryError, RootModule};
+use abi_stable::prefix_type::PrefixTypeTrait;
+use abi_stable::sabi_trait::TD_Opaque;
+use abi_stable::sabi_types::VersionStrings;
+use abi_stable::std_types::{RBox, RStr, RString, RVec};
+use abi_stable::{declare_root_module_statics, package_version_strings, sabi_trait, StableAbi};
+use plugin_interface::{
+    exports_symbol, Greeter, ModuleLoader, ModulePlugin, ModuleRegistration, PluginTrait,
+    Transformer,
+};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::Path;
+
+#[doc(hidden)]
+pub use abi_stable;
+#[doc(hidden)]
+pub use inventory;
+
+/// `Greeter` as it crosses the boundary.
+#[sabi_trait]
+pub trait StableGreeter: Send + Sync {
+    fn name(&self) -> RString;
+
+    fn greet(&self, target: RStr<'_>);
+
+    /// `Greeter::configure`. Methods added later go after this one.
+    #[sabi(last_prefix_field)]
+    fn configure(&mut self, config: RStr<'_>);
+}
+
+/// `Transformer` as it crosses the boundary.
+#[sabi_trait]
+pub trait StableTransformer: Send + Sync {
+    fn transform(&self, input: RStr<'_>) -> RString;
+
+    fn describe(&self) -> RString;
+
+    /// `Transformer::configure`. Methods added later go after this one.
+    #[sabi(last_prefix_field)]
+    fn configure(&mut self, config: RStr<'_>);
+}
+
+pub type StableGreeterBox = StableGreeter_TO<'static, RBox<()>>;
+pub type StableTransformerBox = StableTransformer_TO<'static, RBox<()>>;
+
+/// An implementation of one of the built-in traits.
+#[repr(u8)]
+#[derive(StableAbi)]
+pub enum StablePlugin {
+    Greeter(StableGreeterBox),
+    Transformer(StableTransformerBox),
+}
+
+/// One registration of a module, with its registration name.
+#[repr(C)]
+#[derive(StableAbi)]
+pub struct StableRegistration {
+    pub name: RString,
+    pub plugin: StablePlugin,
+}
+
+/// The root module plugins export. Fields added later go after
+/// `registrations`, which the host can always call.
+#[repr(C)]
+#[derive(StableAbi)]
+#[sabi(kind(Prefix(prefix_ref = PluginModuleRef)))]
+#[sabi(missing_field(panic))]
+pub struct PluginModule {
+    /// A new instance of every implementation of the trait named
+    /// `trait_name` in the plugin.
+    #[sabi(last_prefix_field)]
+    pub registrations: extern "C" fn(trait_name: RStr<'_>) -> RVec<StableRegistration>,
+}
+
+impl RootModule for PluginModuleRef {
+    declare_root_module_statics! {PluginModuleRef}
+    const BASE_NAME: &'static str = "plugin_module";
+    const NAME: &'static str = "plugin_module";
+    const VERSION_STRINGS: VersionStrings = package_version_strings!();
+}
+
+/// Load the `PluginModule` of the library at `path`, checking its layout
+/// against this host's. abi_stable keeps the library loaded for the rest
+/// of the process, even after the manager unloads its registrations.
+pub fn load_module(path: &Path) -> Result<PluginModuleRef, LibraryError> {
+    lib_header_from_path(path)?.init_root_module::<PluginModuleRef>()
+}
+
+/// A `ModuleLoader` for the libraries that export a `PluginModule`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AbiStableLoader;
+
+impl ModuleLoader for AbiStableLoader {
+    fn accepts(&self, path: &Path) -> bool {
+        exports_symbol(path, abi_stable::library::ROOT_MODULE_LOADER_NAME)
+    }
+
+    fn open(&self, path: &Path, trait_id: PluginTrait) -> Result<Vec<ModuleRegistration>, String> {
+        let module = load_module(path).map_err(|e| e.to_string())?;
+        let registrations = module.registrations()(trait_id.as_str().into());
+        Ok(registrations
+            .into_iter()
+            .map(|r| ModuleRegistration {
+                name: r.name.into_string(),
+                plugin: match r.plugin {
+                    StablePlugin::Greeter(plugin) => ModulePlugin::Greeter(Box::new(HostGreeter {
+                        name: plugin.name().into_string(),
+                        plugin,
+                    })),
+                    StablePlugin::Transformer(plugin) => {
+                        ModulePlugin::Transformer(Box::new(HostTransformer { plugin }))
+                    }
+                },
+            })
+            .collect())
+    }
+}
+
+/// A module's greeter on the host side. Its name is asked for once, when
+/// it is loaded.
+struct HostGreeter {
+    name: String,
+    plugin: StableGreeterBox,
+}
+
+impl Greeter for HostGreeter {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn greet(&self, target: &str) {
+        self.plugin.greet(target.into())
+    }
+    fn configure(&mut self, config: &str) {
+        self.plugin.configure(config.into())
+    }
+}
+
+struct HostTransformer {
+    plugin: StableTransformerBox,
+}
+
+impl Transformer for HostTransformer {
+    fn transform(&self, input: &str) -> String {
+        self.plugin.transform(input.into()).into_string()
+    }
+    fn describe(&self) -> String {
+        self.plugin.describe().into_string()
+    }
+    fn configure(&mut self, config: &str) {
+        self.plugin.configure(config.into())
+    }
+}
+
+/// An implementation `#[plugin_impl]` submitted, made afresh for every
+/// load of the module.
+#[doc(hidden)]
+pub struct StableFactory {
+    pub trait_name: &'static str,
+    pub name: &'static str,
+    pub make: fn() -> StablePlugin,
+}
+
+inventory::collect!(StableFactory);
+
+/// Wraps a plugin's implementation in the stable trait of its trait.
+/// Panics are caught here, since they cannot unwind across the module
+/// boundary.
+#[doc(hidden)]
+pub struct Adapter<T>(pub T);
+
+impl<T: Greeter + Send + Sync> StableGreeter for Adapter<T> {
+    fn name(&self) -> RString {
+        catch_unwind(AssertUnwindSafe(|| self.0.name().into())).unwrap_or_default()
+    }
+    fn greet(&self, target: RStr<'_>) {
+        let _ = catch_unwind(AssertUnwindSafe(|| self.0.greet(target.as_str())));
+    }
+    fn configure(&mut self, config: RStr<'_>) {
+        let _ = catch_unwind(AssertUnwindSafe(|| self.0.configure(config.as_str())));
+    }
+}
+
+impl<T: Transformer + Send + Sync> StableTransformer for Adapter<T> {
+    fn transform(&self, input: RStr<'_>) -> RString {
+        catch_unwind(AssertUnwindSafe(|| self.0.transform(input.as_str()).into()))
+            .unwrap_or_default()
+    }
+    fn describe(&self) -> RString {
+        catch_unwind(AssertUnwindSafe(|| self.0.describe().into())).unwrap_or_default()
+    }
+    fn configure(&mut self, config: RStr<'_>) {
+        let _ = catch_unwind(AssertUnwindSafe(|| self.0.configure(config.as_str())));
+    }
+}
+
+#[doc(hidden)]
+pub fn make_greeter<T: Greeter + Default + Send + Sync + 'static>() -> StablePlugin {
+    StablePlugin::Greeter(StableGreeter_TO::from_value(
+        Adapter(T::default()),
+        TD_Opaque,
+    ))
+}
+
+#[doc(hidden)]
+pub fn make_transformer<T: Transformer + Default + Send + Sync + 'static>() -> StablePlugin {
+    StablePlugin::Transformer(StableTransformer_TO::from_value(
+        Adapter(T::default()),
+        TD_Opaque,
+    ))
+}
+
+extern "C" fn registrations(trait_name: RStr<'_>) -> RVec<StableRegistration> {
+    inventory::iter::<StableFactory>
+        .into_iter()
+        .filter(|f| f.trait_name == trait_name.as_str())
+        .filter_map(|f| {
+            // A panicking `Default` leaves that implementation out.
+            let plugin = catch_unwind(f.make).ok()?;
+            Some(StableRegistration {
+                name: f.name.into(),
+                plugin,
+            })
+        })
+        .collect()
+}
+
+/// The module `export_module!` exports.
+#[doc(hidden)]
+pub fn module() -> PluginModuleRef {
+    PluginModule { registrations }.leak_into_prefix()
+}
+
+/// Submit the implementation of `Greeter` or `Transformer` by `$ty` under
+/// the registration name `$name`. Emitted by `#[plugin_impl]` with the
+/// `abi-stable` feature.
+#[macro_export]
+macro_rules! submit {
+    (Greeter, $ty:ty, $name:expr) => {
+        $crate::inventory::submit! {
+            $crate::StableFactory {
+                trait_name: "Greeter",
+                name: $name,
+                make: $crate::make_greeter::<$ty>,
+            }
+        }
+    };
+    (Transformer, $ty:ty, $name:expr) => {
+        $crate::inventory::submit! {
+            $crate::StableFactory {
+                trait_name: "Transformer",
+                name: $name,
+                make: $crate::make_transformer::<$ty>,
+            }
+        }
+    };
+}
+
+/// Export the crate's `PluginModule`. Emitted by `#[plugin_aggregates]`
+/// with the `abi-stable` feature.
+#[macro_export]
+macro_rules! export_module {
+    () => {
+        #[$crate::abi_stable::export_root_module]
+        pub fn __plugin_abi_stable_module() -> $crate::PluginModuleRef {
+            $crate::module()
+        }
+    };
+}
@@ -0,0 +1,47 @@
+use plugin_abi_stable::AbiStableLoader;
+use plugin_interface::{ModuleLoader, PluginManager, PluginTrait};
+use std::path::PathBuf;
+
+/// The example plugin, built next to this test binary by
+/// `cargo test --workspace`.
+fn example_plugin() -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    let lib = exe.parent()?.parent()?.join(format!(
+        "{}plugin_stable_example.{}",
+        std::env::consts::DLL_PREFIX,
+        std::env::consts::DLL_EXTENSION
+    ));
+    lib.exists().then_some(lib)
+}
+
+#[test]
+fn the_manager_loads_abi_stable_modules_through_the_loader() {
+    let Some(lib) = example_plugin() else {
+        eprintln!("example plugin not built; skipping");
+        return;
+    };
+    assert!(AbiStableLoader.accepts(&lib));
+    let mut mgr = PluginManager::new();
+    assert!(mgr.load_library(&lib, PluginTrait::Greeter).is_err());
+
+    mgr.add_module_loader(AbiStableLoader);
+    let handles = mgr
+        .load_library(&lib, PluginTrait::Greeter)
+        .expect("load greeters");
+    assert_eq!(handles.len(), 1);
+    assert_eq!(handles[0].registration_name().as_deref(), Some("Hello"));
+    let greeter = handles[0].as_greeter().expect("greeter");
+    assert_eq!(greeter.name(), "Hello");
+    assert_eq!(greeter.try_greet("stable"), Ok(()));
+
+    let handles = mgr
+        .load_library(&lib, PluginTrait::Transformer)
+        .expect("load transformers");
+    assert_eq!(handles[0].registration_name().as_deref(), Some("shout"));
+    let transformer = handles[0].as_transformer().expect("transformer");
+    assert_eq!(transformer.transform("abc"), "ABC");
+    assert_eq!(
+        transformer.try_describe().as_deref(),
+        Ok("uppercases its input")
+    );
+}
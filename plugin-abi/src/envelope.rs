@@ -0,0 +1,99 @@
+//! The JSON document `plugin_call_dynamic_<Trait>_v1` returns:
+//! `{"ok": <string or null>}` for a call that went through, or
+//! `{"error": "<kind>", "message": "..."}` with one of the `ErrorKind`
+//! names for one that did not. Plugins write it with `ok` and `error`;
+//! hosts read the kind back with `ErrorKind::parse`.
+
+use std::fmt::Write;
+
+/// Why a dynamic call failed, as named in the `error` field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The registration has no method of that name.
+    Unsupported,
+    /// The arguments do not match the method's parameters.
+    InvalidArguments,
+    /// The method was called and failed or panicked.
+    Failed,
+}
+
+impl ErrorKind {
+    /// The name written to the `error` field.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorKind::Unsupported => "unsupported",
+            ErrorKind::InvalidArguments => "invalid_arguments",
+            ErrorKind::Failed => "failed",
+        }
+    }
+
+    /// The kind named `name`. Names added by newer plugins read as `Failed`.
+    pub fn parse(name: &str) -> Self {
+        match name {
+            "unsupported" => ErrorKind::Unsupported,
+            "invalid_arguments" => ErrorKind::InvalidArguments,
+            _ => ErrorKind::Failed,
+        }
+    }
+}
+
+/// The document for a call that returned `value`, or nothing.
+pub fn ok(value: Option<&str>) -> String {
+    match value {
+        Some(value) => format!("{{\"ok\":{}}}", quoted(value)),
+        None => "{\"ok\":null}".to_string(),
+    }
+}
+
+/// The document for a call that failed.
+pub fn error(kind: ErrorKind, message: &str) -> String {
+    format!(
+        "{{\"error\":\"{}\",\"message\":{}}}",
+        kind.as_str(),
+        quoted(message)
+    )
+}
+
+/// `s` as a JSON string literal.
+pub fn quoted(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn documents_name_their_kind() {
+        assert_eq!(ok(Some("a\"b")), r#"{"ok":"a\"b"}"#);
+        assert_eq!(ok(None), r#"{"ok":null}"#);
+        assert_eq!(
+            error(ErrorKind::InvalidArguments, "line\n"),
+            r#"{"error":"invalid_arguments","message":"line\n"}"#
+        );
+        for kind in [
+            ErrorKind::Unsupported,
+            ErrorKind::InvalidArguments,
+            ErrorKind::Failed,
+        ] {
+            assert_eq!(ErrorKind::parse(kind.as_str()), kind);
+        }
+        assert_eq!(ErrorKind::parse("timed_out"), ErrorKind::Failed);
+    }
+}
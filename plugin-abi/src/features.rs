@@ -0,0 +1,114 @@
+//! Optional host subsystems, exchanged before registration through
+//! `plugin_handshake_v1(host_features) -> plugin_features`.
+//!
+//! The host passes the features it offers; the plugin keeps them to skip
+//! what the host lacks and returns the features it cannot do without. A
+//! host refuses a plugin whose result has bits it did not offer. Bits this
+//! crate does not name yet are reserved: plugins must not require them and
+//! hosts treat them like any other feature they lack.
+
+use std::fmt;
+use std::ops::BitOr;
+
+/// A set of host features, as a `u64` of bits.
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Features(pub u64);
+
+impl Features {
+    pub const NONE: Features = Features(0);
+    /// `HostContext::allocator`, for strings returned to the host.
+    pub const HOST_ALLOCATOR: Features = Features(1 << 0);
+    /// `HostContext::get_service`.
+    pub const SERVICES: Features = Features(1 << 1);
+    /// `HostContext::subscribe`, `unsubscribe` and `publish`.
+    pub const EVENT_BUS: Features = Features(1 << 2);
+    /// `HostContext::plugin_config`.
+    pub const PLUGIN_CONFIG: Features = Features(1 << 3);
+    /// `HostContext::kv_get` and `kv_set`.
+    pub const KV_STORE: Features = Features(1 << 4);
+    /// Calls through the `<method>_ctx` vtable entries, with a request
+    /// context.
+    pub const REQUEST_CONTEXT: Features = Features(1 << 5);
+
+    /// Every named feature with its name, in bit order.
+    const NAMES: [(Features, &'static str); 6] = [
+        (Features::HOST_ALLOCATOR, "host_allocator"),
+        (Features::SERVICES, "services"),
+        (Features::EVENT_BUS, "event_bus"),
+        (Features::PLUGIN_CONFIG, "plugin_config"),
+        (Features::KV_STORE, "kv_store"),
+        (Features::REQUEST_CONTEXT, "request_context"),
+    ];
+
+    pub const fn bits(self) -> u64 {
+        self.0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// True if every feature in `other` is in `self`.
+    pub const fn contains(self, other: Features) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn union(self, other: Features) -> Features {
+        Features(self.0 | other.0)
+    }
+
+    /// The features in `self` that are not in `other`.
+    pub const fn difference(self, other: Features) -> Features {
+        Features(self.0 & !other.0)
+    }
+}
+
+impl BitOr for Features {
+    type Output = Features;
+
+    fn bitor(self, other: Features) -> Features {
+        self.union(other)
+    }
+}
+
+/// The names of the features, separated by commas, with unnamed bits as a
+/// hexadecimal remainder; `none` for the empty set.
+impl fmt::Display for Features {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return f.write_str("none");
+        }
+        let mut names: Vec<String> = Vec::new();
+        for (feature, name) in Features::NAMES {
+            if self.contains(feature) {
+                names.push(name.to_string());
+            }
+        }
+        let unnamed = Features::NAMES
+            .iter()
+            .fold(*self, |rest, (feature, _)| rest.difference(*feature));
+        if !unnamed.is_empty() {
+            names.push(format!("{:#x}", unnamed.0));
+        }
+        f.write_str(&names.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_known_and_unknown_bits() {
+        let offered = Features::SERVICES | Features::KV_STORE;
+        let required = Features::KV_STORE | Features::EVENT_BUS | Features(1 << 40);
+        assert!(offered.contains(Features::KV_STORE));
+        assert!(!offered.contains(required));
+        assert_eq!(
+            required.difference(offered).to_string(),
+            "event_bus, 0x10000000000"
+        );
+        assert_eq!(Features::NONE.to_string(), "none");
+    }
+}
@@ -0,0 +1,17 @@
+//! What plugins and hosts agree on at the binary level, without the macros
+//! or the loader. `plugin-annotations` generates code against it and
+//! `plugin-interface` loads plugins with it; an alternative host or loader
+//! can be built on it alone.
+//!
+//! - `registration`: the tables `plugin_register_all_<Trait>_v<N>` returns
+//!   and the registration ABI levels.
+//! - `vtable`: the layout every vtable follows and the vtable levels.
+//! - `envelope`: the result document of `plugin_call_dynamic_<Trait>_v1`.
+//! - `features`: what host and plugin exchange in `plugin_handshake_v1`.
+//! - `symbols`: the names of the exported functions.
+
+pub mod envelope;
+pub mod features;
+pub mod registration;
+pub mod symbols;
+pub mod vtable;
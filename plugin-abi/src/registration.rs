@@ -0,0 +1,249 @@
+//! The tables a plugin crate hands to the host when it registers: a
+//! `RegistrationArray` from `plugin_register_all_<Trait>_v<N>`, and the
+//! `RegistrationFactory` entries behind it, one per implementation.
+//!
+//! Both end with `struct_size` and `flags` from `SIZED_STRUCTS_ABI` on.
+//! Fields appended later are read only when `struct_size` reaches them, so
+//! a host can load plugins built against an older version of this crate.
+
+use std::ffi::c_void;
+use std::os::raw::c_char;
+
+/// Oldest registration ABI level the loader accepts.
+pub const MIN_PLUGIN_ABI: u32 = 1;
+
+/// Newest registration ABI level the loader negotiates. Registration
+/// symbols carry their level as a `_v<N>` suffix and the loader probes from
+/// this level down to `MIN_PLUGIN_ABI`.
+///
+/// Level 2 keeps the level 1 vtable and registration layout and hands
+/// `register_all` a `HostContext`; see `HOST_CONTEXT_ABI`. A plugin that
+/// exports it relies on the context carrying the capability fields.
+/// Level 3 adds `HostContext::allocator`; see `HOST_ALLOCATOR_ABI`.
+/// Level 4 appends `struct_size` and `flags` to `RegistrationArray` and
+/// `RegistrationFactory`; see `SIZED_STRUCTS_ABI`.
+/// Level 5 hands `register_all` a `HostVersion`; see `HOST_VERSION_ABI`.
+pub const MAX_PLUGIN_ABI: u32 = 5;
+
+/// First registration ABI level whose `plugin_register_all_<Trait>_v<N>`
+/// takes the host's `HostContext`. Level 1 register functions take no
+/// arguments, as they did before hosts had a context to pass.
+pub const HOST_CONTEXT_ABI: u32 = 2;
+
+/// First registration ABI level whose plugins get `HostContext::allocator`
+/// and return strings allocated with it, which the host frees after
+/// copying them. Below it, returned strings are never freed.
+pub const HOST_ALLOCATOR_ABI: u32 = 3;
+
+/// First registration ABI level whose `RegistrationArray` and
+/// `RegistrationFactory` end with `struct_size` and `flags`, which the
+/// loader checks before trusting the rest of the layout.
+pub const SIZED_STRUCTS_ABI: u32 = 4;
+
+/// First registration ABI level whose `plugin_register_all_<Trait>_v<N>`
+/// and `plugin_list_impls_<Trait>_v<N>` take a `HostVersion` after the
+/// host context. A plugin that will not run on that host returns null and
+/// says why through `plugin_register_error_v1`.
+pub const HOST_VERSION_ABI: u32 = 5;
+
+/// The version of the host registering a plugin, from `HOST_VERSION_ABI`
+/// on. Valid for the duration of the call only.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HostVersion {
+    /// `size_of` the struct in the host that built it, so plugins can tell
+    /// which fields appended after this one it has.
+    pub struct_size: usize,
+    /// The version of the host's `plugin-interface`.
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    /// The host ABI version, as in `HostContext::abi_version`.
+    pub abi: u32,
+}
+
+impl HostVersion {
+    pub const fn new(major: u32, minor: u32, patch: u32, abi: u32) -> Self {
+        Self {
+            struct_size: std::mem::size_of::<Self>(),
+            major,
+            minor,
+            patch,
+            abi,
+        }
+    }
+
+    /// True if the host is `major.minor.patch` or newer.
+    pub const fn at_least(&self, major: u32, minor: u32, patch: u32) -> bool {
+        if self.major != major {
+            return self.major > major;
+        }
+        if self.minor != minor {
+            return self.minor > minor;
+        }
+        self.patch >= patch
+    }
+}
+
+/// `major.minor.patch`.
+impl std::fmt::Display for HostVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+#[repr(C)]
+pub struct RegistrationArray {
+    /// Number of registrations in the array.
+    pub count: usize,
+    /// Type-erased pointer array: *const *const c_void; callers cast entries to the
+    /// concrete registration pointer type they expect (for example, `*const GreeterRegistration`).
+    pub registrations: *const *const c_void,
+    /// Parallel array of pointers to the RegistrationFactory that produced the
+    /// corresponding registration entry. This allows precise, deterministic
+    /// unmaker calls for each registration.
+    pub factories: *const *const RegistrationFactory,
+    /// `size_of::<RegistrationArray>()` in the plugin that built the array.
+    /// Only present from registration ABI level `SIZED_STRUCTS_ABI` on.
+    pub struct_size: usize,
+    /// Reserved; no flags are defined yet and hosts ignore unknown bits.
+    /// Only present from registration ABI level `SIZED_STRUCTS_ABI` on.
+    pub flags: u32,
+}
+
+impl RegistrationArray {
+    /// An array with `struct_size` filled in and no flags.
+    pub fn new(
+        count: usize,
+        registrations: *const *const c_void,
+        factories: *const *const RegistrationFactory,
+    ) -> Self {
+        Self {
+            count,
+            registrations,
+            factories,
+            struct_size: std::mem::size_of::<Self>(),
+            flags: 0,
+        }
+    }
+}
+
+/// A small wrapper used with `inventory` so plugins can register their factory functions
+/// at link time. Each item holds a function pointer to the plugin's `plugin_register_*`.
+/// We store the function pointer as an erased extern "C" function pointer so it can be
+/// submitted via `inventory::submit!` without relying on pointer-to-integer casts.
+#[repr(C)]
+pub struct RegistrationFactory {
+    /// Erased factory function pointer: extern "C" fn() -> *const c_void
+    pub maker: extern "C" fn() -> *const c_void,
+    /// Erased unregister function pointer: extern "C" fn(*const c_void)
+    /// that releases a registration previously returned by `maker`.
+    pub unmaker: extern "C" fn(*const c_void),
+    /// Nul-terminated trait name to allow filtering by trait at runtime.
+    pub trait_name: *const c_char,
+    /// `size_of::<RegistrationFactory>()` in the plugin that built it. Only
+    /// present from registration ABI level `SIZED_STRUCTS_ABI` on.
+    pub struct_size: usize,
+    /// Reserved; no flags are defined yet and hosts ignore unknown bits.
+    /// Only present from registration ABI level `SIZED_STRUCTS_ABI` on.
+    pub flags: u32,
+    /// How many instances besides the registration itself
+    /// `plugin_interface::PluginHandle::instantiate` may have `maker` create at a time: 0 for
+    /// none, `UNLIMITED_INSTANCES` for as many as callers ask for. Appended
+    /// after `flags`; present as far as `struct_size` reaches.
+    pub max_instances: u32,
+    /// Nul-terminated name of the registrations `maker` makes, or null.
+    /// Appended after `max_instances`; present as far as `struct_size`
+    /// reaches.
+    pub name: *const c_char,
+    /// Where the registrations `maker` makes sort among those of other
+    /// plugins: higher first, see
+    /// `plugin_interface::PluginManager::iter_by_priority`.
+    /// Appended after `name`; present as far as `struct_size` reaches.
+    pub priority: i32,
+}
+
+/// `RegistrationFactory::max_instances` of factories whose `maker` may be
+/// called for as many instances as the host wants.
+pub const UNLIMITED_INSTANCES: u32 = u32::MAX;
+
+impl RegistrationFactory {
+    /// A factory with `struct_size` filled in, no flags and no instances
+    /// besides the registration.
+    pub const fn new(
+        maker: extern "C" fn() -> *const c_void,
+        unmaker: extern "C" fn(*const c_void),
+        trait_name: *const c_char,
+    ) -> Self {
+        Self {
+            maker,
+            unmaker,
+            trait_name,
+            struct_size: std::mem::size_of::<Self>(),
+            flags: 0,
+            max_instances: 0,
+            name: std::ptr::null(),
+            priority: 0,
+        }
+    }
+
+    /// This factory, letting the host create up to `max` instances with
+    /// `maker` (see `plugin_interface::PluginHandle::instantiate`).
+    pub const fn with_max_instances(mut self, max: u32) -> Self {
+        self.max_instances = max;
+        self
+    }
+
+    /// The same factory naming its registrations `name`, a nul-terminated
+    /// string that lives as long as the factory.
+    pub const fn with_name(mut self, name: *const c_char) -> Self {
+        self.name = name;
+        self
+    }
+
+    /// The same factory giving its registrations `priority`.
+    pub const fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// `max_instances`, or 0 if the plugin built the factory before the
+    /// field was appended.
+    pub fn instances_allowed(&self) -> u32 {
+        let end = std::mem::offset_of!(Self, max_instances) + std::mem::size_of::<u32>();
+        if self.struct_size < end {
+            return 0;
+        }
+        self.max_instances
+    }
+
+    /// `name`, or `None` if it is null, not UTF-8, or the plugin built the
+    /// factory before the field was appended.
+    ///
+    /// # Safety
+    /// `name`, if present and not null, must point to a nul-terminated
+    /// string that outlives `'a`.
+    pub unsafe fn registration_name<'a>(&self) -> Option<&'a str> {
+        let end = std::mem::offset_of!(Self, name) + std::mem::size_of::<*const c_char>();
+        if self.struct_size < end || self.name.is_null() {
+            return None;
+        }
+        std::ffi::CStr::from_ptr(self.name).to_str().ok()
+    }
+
+    /// `priority`, or 0 if the plugin built the factory before the field
+    /// was appended.
+    pub fn declared_priority(&self) -> i32 {
+        let end = std::mem::offset_of!(Self, priority) + std::mem::size_of::<i32>();
+        if self.struct_size < end {
+            return 0;
+        }
+        self.priority
+    }
+}
+
+#[cfg(feature = "inventory")]
+inventory::collect!(RegistrationFactory);
+// Raw pointers are inherently fine for static registration; assert thread-safety
+unsafe impl Send for RegistrationFactory {}
+unsafe impl Sync for RegistrationFactory {}
@@ -0,0 +1,96 @@
+//! Names of the symbols plugins export and hosts look up. The macros name
+//! the functions they generate with these helpers and the loader searches
+//! for the same names, so the two cannot drift apart.
+//!
+//! Per-trait symbols are `<prefix>_<Trait>_v<N>`, with `N` the registration
+//! ABI level, except `CALL_DYNAMIC`; the others carry their version in a
+//! fixed name.
+
+/// `plugin_register_<Trait>_v<N>`: returns a single registration.
+pub const REGISTER: &str = "plugin_register";
+/// `plugin_register_all_<Trait>_v<N>`: returns every registration of the
+/// crate in a `RegistrationArray`.
+pub const REGISTER_ALL: &str = "plugin_register_all";
+/// Releases what `REGISTER` returned.
+pub const UNREGISTER: &str = "plugin_unregister";
+/// Releases what `REGISTER_ALL` returned.
+pub const UNREGISTER_ALL: &str = "plugin_unregister_all";
+/// Lifecycle counters of the crate's registrations.
+pub const COUNTERS: &str = "plugin_counters";
+/// The unmaker counter alone, as exported before `COUNTERS`.
+pub const UNMAKER_COUNTER: &str = "plugin_unmaker_counter";
+/// Makes, checks and releases an instance of every registration.
+pub const SELFTEST: &str = "plugin_selftest";
+/// Lists the implementations and their factories without making any.
+pub const LIST_IMPLS: &str = "plugin_list_impls";
+/// `plugin_call_dynamic_<Trait>_v1`: calls a method of a registration of
+/// the trait by name with JSON arguments. Its `v1` is the version of the
+/// entry point, not the registration ABI level.
+pub const CALL_DYNAMIC: &str = "plugin_call_dynamic";
+/// The version `CALL_DYNAMIC` is exported at.
+pub const CALL_DYNAMIC_VERSION: u32 = 1;
+
+/// Why `REGISTER_ALL` or `LIST_IMPLS` returned null, if the plugin refused
+/// the host; null otherwise.
+pub const REGISTER_ERROR: &str = "plugin_register_error_v1";
+/// Exchanges `Features` with the host before it registers the crate.
+pub const HANDSHAKE: &str = "plugin_handshake_v1";
+/// JSON description of the crate's traits and registrations.
+pub const DESCRIBE: &str = "plugin_describe_v1";
+/// What the generated code allocated and released.
+pub const LEAK_REPORT: &str = "plugin_leak_report_v1";
+
+/// `<prefix>_<trait_name>_v<abi>`, such as `plugin_register_all_Greeter_v4`.
+pub fn versioned(prefix: &str, trait_name: &str, abi: u32) -> String {
+    format!("{}_{}_v{}", prefix, trait_name, abi)
+}
+
+/// `versioned` with the trailing nul `libloading::Library::get` expects.
+pub fn versioned_c(prefix: &str, trait_name: &str, abi: u32) -> String {
+    format!("{}\0", versioned(prefix, trait_name, abi))
+}
+
+/// `name` with a trailing nul, for the symbols with fixed names.
+pub fn c_name(name: &str) -> String {
+    format!("{}\0", name)
+}
+
+/// The ABI level in `symbol` if it is `<prefix>_<trait_name>_v<N>`.
+pub fn parse_versioned(symbol: &str, prefix: &str, trait_name: &str) -> Option<u32> {
+    symbol
+        .strip_prefix(prefix)?
+        .strip_prefix('_')?
+        .strip_prefix(trait_name)?
+        .strip_prefix("_v")?
+        .parse()
+        .ok()
+}
+
+/// The register or unregister function `#[plugin_impl]` generates for one
+/// implementation: `<prefix>_<trait_name>_<impl_name>_v1`. They are reached
+/// through the crate's factories, never looked up by name.
+pub fn per_impl(prefix: &str, trait_name: &str, impl_name: &str) -> String {
+    format!("{}_{}_{}_v1", prefix, trait_name, impl_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versioned_names_round_trip() {
+        let name = versioned(REGISTER_ALL, "Greeter", 4);
+        assert_eq!(name, "plugin_register_all_Greeter_v4");
+        assert_eq!(parse_versioned(&name, REGISTER_ALL, "Greeter"), Some(4));
+        // `plugin_register_all_...` is not a `plugin_register` symbol.
+        assert_eq!(parse_versioned(&name, REGISTER, "Greeter"), None);
+        assert_eq!(
+            versioned_c(COUNTERS, "Transformer", 1),
+            "plugin_counters_Transformer_v1\0"
+        );
+        assert_eq!(
+            versioned(CALL_DYNAMIC, "Greeter", CALL_DYNAMIC_VERSION),
+            "plugin_call_dynamic_Greeter_v1"
+        );
+    }
+}
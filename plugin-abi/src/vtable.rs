@@ -0,0 +1,43 @@
+//! Conventions every plugin vtable follows, whether `#[plugin_interface]`
+//! generated it or it was written by hand.
+//!
+//! A vtable starts with `abi_version: u32` and `user_data: *mut c_void`,
+//! which every entry receives as its first argument, followed by the
+//! trait's methods in declaration order, the lifecycle entries from
+//! `LIFECYCLE_VTABLE_ABI` on, and `drop`. From
+//! `SIZED_VTABLE_ABI` on it continues with `struct_size: usize` and
+//! `flags: u32`; entries added after that go last, in a `#[repr(C)]`
+//! struct of `Option<extern "C" fn>` fields named `optional`, and hosts read one only when
+//! `struct_size` covers it. From `CONTEXT_VTABLE_ABI` on, a context table follows at
+//! `struct_size`, so entries appended to either one never move the other's.
+
+use std::ffi::c_void;
+
+/// Callback a plugin's `save_state` entry passes its bytes to; the first
+/// argument is the sink pointer the host handed in.
+pub type StateSink = extern "C" fn(*mut c_void, *const u8, usize);
+
+/// First vtable `abi_version` with the `on_load` / `on_unload` entries
+/// between the methods and `drop`. Vtables built by older macros have
+/// `drop` right after the methods, and hosts do not call their hooks.
+pub const LIFECYCLE_VTABLE_ABI: u32 = 2;
+
+/// First vtable `abi_version` with the `save_state` / `restore_state`
+/// entries. Vtables built by older macros stop after `drop`.
+pub const STATE_VTABLE_ABI: u32 = 2;
+
+/// First vtable `abi_version` with a `<method>_str` entry for every method,
+/// passing strings as `StrRef` and returning them as `OwnedStr`. The
+/// nul-terminated entries stay for older hosts.
+pub const STR_VTABLE_ABI: u32 = 3;
+
+/// First vtable `abi_version` ending with `struct_size` and `flags`. From
+/// here on, entries are appended after them and hosts check `struct_size`
+/// before reading one, instead of bumping `abi_version`.
+pub const SIZED_VTABLE_ABI: u32 = 4;
+
+/// First vtable `abi_version` followed by a context table: a `#[repr(C)]`
+/// struct at `struct_size` bytes from the vtable's start, beginning with its
+/// own `struct_size: usize`, then the `<method>_ctx` entries. Hosts read an
+/// entry only when the table's `struct_size` covers it.
+pub const CONTEXT_VTABLE_ABI: u32 = 5;
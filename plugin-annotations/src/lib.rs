@@ -1,13 +1,74 @@
+use plugin_abi::registration::{
+    HOST_CONTEXT_ABI, HOST_VERSION_ABI, MAX_PLUGIN_ABI, MIN_PLUGIN_ABI,
+};
+use plugin_abi::symbols;
 use proc_macro::TokenStream;
 use quote::quote;
 use syn::{parse_macro_input, Ident, ImplItem, ItemImpl, ItemTrait, ReturnType, TraitItem, Type};
 
+/// Whether the registration entries are the statics `collect_registrations!`
+/// lists instead of `inventory` submissions.
+const EXPLICIT_REGISTRATION: bool = cfg!(feature = "explicit-registration");
+
+/// Whether implementations cross the boundary as `abi_stable` trait objects
+/// in the crate's `plugin_abi_stable::PluginModule` instead of vtables.
+const ABI_STABLE: bool = cfg!(feature = "abi-stable");
+
+/// Name of the static `#[plugin_impl]` emits for an entry of the
+/// implementation of `trait_name` by the type named `safe_name`, under the
+/// explicit registration backend.
+fn entry_name(kind: &str, trait_name: &str, safe_name: &str) -> String {
+    format!("__PLUGIN_{}_{}_{}", kind, trait_name, safe_name)
+}
+
+/// Name of the static `#[plugin_aggregates]` emits for the description of
+/// `trait_name`, under the explicit registration backend.
+fn trait_entry_name(trait_name: &str) -> String {
+    format!("__PLUGIN_TRAIT_{}", trait_name)
+}
+
 /// `#[plugin_interface]` reads a trait and emits a repr(C) vtable+registration and a small
 /// loader helper (prototype). It supports trait methods that take &self and either zero or one
-/// &str parameter, returning () or &str. This is intentionally narrow for the prototype.
+/// &str parameter, returning (), &str or String. This is intentionally narrow for the prototype.
+/// The `on_load` / `on_unload` lifecycle hooks and the `save_state` / `restore_state`
+/// hooks always get their own vtable entries. Every method also gets a `<method>_str`
+/// entry after them, taking `plugin_interface::StrRef` and returning
+/// `plugin_interface::OwnedStr` instead of nul-terminated strings. The vtable ends with
+/// `struct_size` and `flags`, like the built-in ones.
+///
+/// Methods added to a trait after plugins were built against it are marked `#[optional]`
+/// and need a default body. They only get a `StrRef` / `OwnedStr` entry, in a
+/// `<Trait>Optional` struct that is the vtable's last field, as `Option<extern "C" fn>`
+/// slots in declaration order; new optional methods go last. `<Trait>Optional::for_impl`
+/// fills every slot for an implementing type, counting calls and caught panics in the
+/// `plugin_interface::CountersSource` it is given, and the vtable's `optional_<method>()`
+/// returns the slot only if the plugin's `struct_size` reaches it and it is set, so hosts
+/// can fall back to a default of their own or report `PluginCallError::Unsupported`.
+/// Optional methods may also take no argument and return `plugin_interface::HealthStatus`,
+/// which crosses the boundary as is; a panicking one reports `HealthStatus::Unhealthy`.
+///
+/// The vtable is followed by a `<Trait>Context` table with its own `struct_size` and a
+/// `<method>_ctx` slot for every method that does not return `HealthStatus`: its `_str`
+/// entry with a trailing `*const plugin_interface::RawRequestContext`, which
+/// `plugin_interface::with_current_request` hands to the method while it runs. The table
+/// starts at the vtable's `struct_size` from `CONTEXT_VTABLE_ABI` on, so optional methods
+/// added to the trait never move it. Hosts read the slots through `optional_<method>_ctx()`.
+/// `<Trait>Context::for_impl` counts like `<Trait>Optional::for_impl`.
+///
+/// A `#[deprecated]` attribute on a method stays where it is and is recorded in
+/// `<Trait>VTable::METHODS`, so `plugin_describe_v1` lists the method as deprecated and
+/// proxies warn the first time it is called. The generated wrappers call deprecated
+/// methods without the compiler's warning.
+///
+/// It also implements `PluginProxy` for `<Trait>Proxy`, so `PluginHandle::as_proxy`
+/// hands out proxies for the trait's registrations, and `PluginObject` for
+/// `dyn Trait`, so `PluginManager::collect_trait_objects` boxes them as trait objects.
+/// The proxy type is declared next to the trait with a `for_registration(lib, index)`
+/// constructor and implements the trait, and the trait has a `PluginTrait` variant of
+/// the same name.
 #[proc_macro_attribute]
 pub fn plugin_interface(_attr: TokenStream, item: TokenStream) -> TokenStream {
-    let input = parse_macro_input!(item as ItemTrait);
+    let mut input = parse_macro_input!(item as ItemTrait);
 
     let trait_ident = &input.ident;
     let trait_name = trait_ident.to_string();
@@ -23,27 +84,163 @@ pub fn plugin_interface(_attr: TokenStream, item: TokenStream) -> TokenStream {
         &format!("load_{}_from_lib", trait_name.to_lowercase()),
         proc_macro2::Span::call_site(),
     );
-    let register_symbol = format!("plugin_register_{}_v1", trait_name);
-    let register_lit = proc_macro2::Literal::byte_string(register_symbol.as_bytes());
+    let trait_name_lit = proc_macro2::Literal::string(&trait_name);
+
+    let optional_ident = Ident::new(
+        &format!("{}Optional", trait_name),
+        proc_macro2::Span::call_site(),
+    );
+    let context_ident = Ident::new(
+        &format!("{}Context", trait_name),
+        proc_macro2::Span::call_site(),
+    );
+    let proxy_ident = Ident::new(
+        &format!("{}Proxy", trait_name),
+        proc_macro2::Span::call_site(),
+    );
 
     // Collect simple method shapes
     let mut method_fields = Vec::new();
-    for item in input.items.iter() {
+    let mut str_fields = Vec::new();
+    let mut optional_fields = Vec::new();
+    let mut optional_fns = Vec::new();
+    let mut optional_inits = Vec::new();
+    let mut optional_getters = Vec::new();
+    let mut context_slots = Vec::new();
+    let mut descriptions = Vec::new();
+    let mut dynamic_arms = Vec::new();
+    for item in input.items.iter_mut() {
         if let TraitItem::Fn(m) = item {
+            let optional = take_optional_attr(&mut m.attrs);
             let sig = &m.sig;
             let name = sig.ident.to_string();
+            if is_lifecycle_hook(&name) {
+                continue;
+            }
+            let deprecated = match deprecation_note(&m.attrs) {
+                Ok(note) => note,
+                Err(e) => return e.to_compile_error().into(),
+            };
+            descriptions.push(method_description(sig, optional, deprecated));
 
             let mut has_str_arg = false;
             if sig.inputs.len() > 1 {
                 has_str_arg = true;
             }
-            let ret_is_str = match &sig.output {
-                ReturnType::Type(_, ty) => {
-                    let s = quote! { #ty }.to_string();
-                    s.contains("str")
+            let ret_is_str = returns_string(&sig.output);
+            let ret_is_health = returns_health(&sig.output);
+            if ret_is_health && (!optional || has_str_arg) {
+                return syn::Error::new_spanned(
+                    &m.sig,
+                    "only #[optional] methods without arguments can return HealthStatus",
+                )
+                .to_compile_error()
+                .into();
+            }
+            dynamic_arms.push(if ret_is_health {
+                health_dynamic_arm(&sig.ident)
+            } else {
+                dynamic_arm(&sig.ident, optional, has_str_arg, ret_is_str)
+            });
+            if !ret_is_health {
+                context_slots.push(context_slot(
+                    trait_ident,
+                    &context_ident,
+                    &sig.ident,
+                    has_str_arg,
+                    ret_is_str,
+                ));
+            }
+
+            if optional {
+                if m.default.is_none() {
+                    return syn::Error::new_spanned(
+                        &m.sig,
+                        "#[optional] methods need a default body",
+                    )
+                    .to_compile_error()
+                    .into();
                 }
-                _ => false,
-            };
+                let method = &sig.ident;
+                let slot_ty = if ret_is_health {
+                    quote! { extern "C" fn(*mut std::ffi::c_void) -> plugin_interface::HealthStatus }
+                } else {
+                    str_field_ty(has_str_arg, ret_is_str)
+                };
+                let getter = Ident::new(
+                    &format!("optional_{}", name),
+                    proc_macro2::Span::call_site(),
+                );
+                let (arg_param, call_arg) = if has_str_arg {
+                    (
+                        quote! { , arg: plugin_interface::StrRef },
+                        quote! { unsafe { arg.as_str() } },
+                    )
+                } else {
+                    (quote! {}, quote! {})
+                };
+                let trampoline = if ret_is_health {
+                    quote! {
+                        extern "C" fn #method<T: #trait_ident, C: plugin_interface::CountersSource>(user_data: *mut std::ffi::c_void) -> plugin_interface::HealthStatus {
+                            let instance = unsafe { &*(user_data as *const T) };
+                            C::call();
+                            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| instance.#method()))
+                                .unwrap_or_else(|_| {
+                                    C::panic();
+                                    plugin_interface::HealthStatus::Unhealthy
+                                })
+                        }
+                    }
+                } else if ret_is_str {
+                    quote! {
+                        extern "C" fn #method<T: #trait_ident, C: plugin_interface::CountersSource>(user_data: *mut std::ffi::c_void #arg_param) -> plugin_interface::OwnedStr {
+                            let instance = unsafe { &*(user_data as *const T) };
+                            C::call();
+                            match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                                String::from(instance.#method(#call_arg))
+                            })) {
+                                Ok(s) => plugin_interface::OwnedStr::new(s),
+                                Err(_) => {
+                                    C::panic();
+                                    plugin_interface::OwnedStr::null()
+                                }
+                            }
+                        }
+                    }
+                } else {
+                    quote! {
+                        extern "C" fn #method<T: #trait_ident, C: plugin_interface::CountersSource>(user_data: *mut std::ffi::c_void #arg_param) {
+                            let instance = unsafe { &*(user_data as *const T) };
+                            C::call();
+                            if std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                                instance.#method(#call_arg);
+                            })).is_err() {
+                                C::panic();
+                            }
+                        }
+                    }
+                };
+                optional_fields.push(quote! { pub #method: Option<#slot_ty> });
+                optional_fns.push(trampoline);
+                optional_inits.push(quote! { #method: Some(#method::<T, C>) });
+                optional_getters.push(quote! {
+                    /// The plugin's entry for this optional method, if its vtable
+                    /// is long enough to have one and it is set.
+                    pub fn #getter(&self) -> Option<#slot_ty> {
+                        if self.abi_version < plugin_interface::SIZED_VTABLE_ABI {
+                            return None;
+                        }
+                        let end = std::mem::offset_of!(Self, optional)
+                            + std::mem::offset_of!(#optional_ident, #method)
+                            + std::mem::size_of::<Option<#slot_ty>>();
+                        if self.struct_size < end {
+                            return None;
+                        }
+                        self.optional.#method
+                    }
+                });
+                continue;
+            }
 
             let field_ident = Ident::new(&name, proc_macro2::Span::call_site());
             let field_ty = if has_str_arg && ret_is_str {
@@ -57,9 +254,18 @@ pub fn plugin_interface(_attr: TokenStream, item: TokenStream) -> TokenStream {
             };
 
             method_fields.push(quote! { pub #field_ident: #field_ty });
+
+            let str_ident = Ident::new(&format!("{}_str", name), proc_macro2::Span::call_site());
+            let str_ty = str_field_ty(has_str_arg, ret_is_str);
+            str_fields.push(quote! { pub #str_ident: #str_ty });
         }
     }
 
+    let context_fields = context_slots.iter().map(|c| &c.field);
+    let context_fns = context_slots.iter().map(|c| &c.trampoline);
+    let context_inits = context_slots.iter().map(|c| &c.init);
+    let context_getters = context_slots.iter().map(|c| &c.getter);
+
     let generated = quote! {
         #input
 
@@ -68,7 +274,95 @@ pub fn plugin_interface(_attr: TokenStream, item: TokenStream) -> TokenStream {
             pub abi_version: u32,
             pub user_data: *mut std::ffi::c_void,
             #(#method_fields,)*
+            pub on_load: extern "C" fn(*mut std::ffi::c_void, *const plugin_interface::HostInfo),
+            pub on_unload: extern "C" fn(*mut std::ffi::c_void),
             pub drop: extern "C" fn(*mut std::ffi::c_void),
+            pub save_state: extern "C" fn(*mut std::ffi::c_void, *mut std::ffi::c_void, plugin_interface::StateSink),
+            pub restore_state: extern "C" fn(*mut std::ffi::c_void, *const u8, usize),
+            #(#str_fields,)*
+            pub struct_size: usize,
+            pub flags: u32,
+            pub optional: #optional_ident,
+            /// At `struct_size` rather than here; read it through
+            /// `context_table`.
+            pub context: #context_ident,
+        }
+
+        /// Entries for the trait's `#[optional]` methods; see
+        /// `#[plugin_interface]`.
+        #[repr(C)]
+        pub struct #optional_ident {
+            #(#optional_fields,)*
+        }
+
+        impl #optional_ident {
+            /// Every optional entry, calling `T`'s implementation and
+            /// counting in `C`.
+            #[allow(deprecated)]
+            pub fn for_impl<T: #trait_ident, C: plugin_interface::CountersSource>() -> Self {
+                #(#optional_fns)*
+                Self {
+                    #(#optional_inits,)*
+                }
+            }
+        }
+
+        /// The `<method>_ctx` entries following the vtable; see
+        /// `#[plugin_interface]`.
+        #[repr(C)]
+        pub struct #context_ident {
+            pub struct_size: usize,
+            #(#context_fields,)*
+        }
+
+        impl #context_ident {
+            /// Every entry, calling `T`'s implementation and counting in `C`.
+            #[allow(deprecated)]
+            pub fn for_impl<T: #trait_ident, C: plugin_interface::CountersSource>() -> Self {
+                #(#context_fns)*
+                Self {
+                    struct_size: std::mem::size_of::<Self>(),
+                    #(#context_inits,)*
+                }
+            }
+        }
+
+        impl #vtable_ident {
+            /// The trait's methods, as plugins describe them in `plugin_describe_v1`.
+            pub const METHODS: &'static [plugin_interface::MethodDescription] = &[
+                #(#descriptions,)*
+            ];
+
+            /// The plugin's context table, if its vtable is followed by one.
+            pub fn context_table(&self) -> Option<&#context_ident> {
+                if self.abi_version < plugin_interface::CONTEXT_VTABLE_ABI {
+                    return None;
+                }
+                // The table starts where the plugin's vtable ends, wherever
+                // that is in this build of the type.
+                Some(unsafe {
+                    &*((self as *const Self as *const u8).add(self.struct_size) as *const #context_ident)
+                })
+            }
+
+            #(#optional_getters)*
+            #(#context_getters)*
+        }
+
+        impl plugin_interface::DynamicRegistration for #registration_ident {
+            const METHODS: &'static [plugin_interface::MethodDescription] = #vtable_ident::METHODS;
+
+            unsafe fn call(
+                reg: *const Self,
+                method: &str,
+                args: &[&str],
+            ) -> Result<Option<String>, plugin_interface::PluginCallError> {
+                let v = &*(*reg).vtable;
+                match method {
+                    #(#dynamic_arms)*
+                    _ => Err(plugin_interface::PluginCallError::Unsupported),
+                }
+            }
         }
 
         #[repr(C)]
@@ -77,16 +371,39 @@ pub fn plugin_interface(_attr: TokenStream, item: TokenStream) -> TokenStream {
             pub vtable: *const #vtable_ident,
         }
 
-        /// Prototype loader: opens the library and looks up the plugin_register_{Trait}_v1 symbol.
+        impl plugin_interface::PluginProxy for #proxy_ident {
+            const TRAIT: plugin_interface::PluginTrait = plugin_interface::PluginTrait::#trait_ident;
+
+            fn from_handle(handle: &plugin_interface::PluginHandle) -> Option<Self> {
+                handle
+                    .native_registration()
+                    .map(|(lib, index)| Self::for_registration(lib, index))
+            }
+        }
+
+        impl plugin_interface::PluginObject for dyn #trait_ident {
+            type Proxy = #proxy_ident;
+
+            fn boxed(proxy: #proxy_ident) -> Box<Self> {
+                Box::new(proxy)
+            }
+        }
+
+        /// Prototype loader: opens the library and looks up the newest
+        /// plugin_register_{Trait}_v<N> symbol the host supports.
         pub fn #loader_ident(path: &std::path::Path) -> Result<*const #registration_ident, String> {
             let lib = unsafe { libloading::Library::new(path) }.map_err(|e| e.to_string())?;
             unsafe {
-                let symbol: libloading::Symbol<unsafe extern "C" fn() -> *const #registration_ident> =
-                    lib.get(#register_lit).map_err(|e| e.to_string())?;
+                let (symbol, _abi) = plugin_interface::find_versioned_symbol::<
+                    unsafe extern "C" fn() -> *const #registration_ident,
+                >(&lib, plugin_interface::symbols::REGISTER, #trait_name_lit)
+                .ok_or_else(|| "plugin exports no registration symbol".to_string())?;
                 let reg = symbol();
                 if reg.is_null() {
                     Err("plugin returned null registration".to_string())
                 } else {
+                    // The registration points into the library, so it stays loaded.
+                    std::mem::forget(lib);
                     Ok(reg)
                 }
             }
@@ -96,24 +413,385 @@ pub fn plugin_interface(_attr: TokenStream, item: TokenStream) -> TokenStream {
     TokenStream::from(generated)
 }
 
+/// The `<method>_ctx` entry of a method: its `Context` field, the
+/// trampoline that fills it, and the vtable getter hosts read it through.
+struct ContextSlot {
+    field: proc_macro2::TokenStream,
+    trampoline: proc_macro2::TokenStream,
+    init: proc_macro2::TokenStream,
+    getter: proc_macro2::TokenStream,
+}
+
+/// The `<method>_ctx` entry for `method`: its `_str` entry with a trailing
+/// `*const RawRequestContext`, which is the current request while the
+/// method runs.
+fn context_slot(
+    trait_ident: &Ident,
+    context_ident: &Ident,
+    method: &Ident,
+    has_str_arg: bool,
+    ret_is_str: bool,
+) -> ContextSlot {
+    let slot = Ident::new(&format!("{}_ctx", method), method.span());
+    let getter = Ident::new(&format!("optional_{}_ctx", method), method.span());
+    let ctx_ty = quote! { *const plugin_interface::RawRequestContext };
+    let (arg_ty, arg_param, call_arg) = if has_str_arg {
+        (
+            quote! { plugin_interface::StrRef, },
+            quote! { arg: plugin_interface::StrRef, },
+            quote! { unsafe { arg.as_str() } },
+        )
+    } else {
+        (quote! {}, quote! {}, quote! {})
+    };
+    let (slot_ty, trampoline) = if ret_is_str {
+        (
+            quote! { extern "C" fn(*mut std::ffi::c_void, #arg_ty #ctx_ty) -> plugin_interface::OwnedStr },
+            quote! {
+                extern "C" fn #slot<T: #trait_ident, C: plugin_interface::CountersSource>(user_data: *mut std::ffi::c_void, #arg_param ctx: #ctx_ty) -> plugin_interface::OwnedStr {
+                    let instance = unsafe { &*(user_data as *const T) };
+                    C::call();
+                    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                        plugin_interface::enter_request(ctx, || String::from(instance.#method(#call_arg)))
+                    })) {
+                        Ok(s) => plugin_interface::OwnedStr::new(s),
+                        Err(_) => {
+                            C::panic();
+                            plugin_interface::OwnedStr::null()
+                        }
+                    }
+                }
+            },
+        )
+    } else {
+        (
+            quote! { extern "C" fn(*mut std::ffi::c_void, #arg_ty #ctx_ty) },
+            quote! {
+                extern "C" fn #slot<T: #trait_ident, C: plugin_interface::CountersSource>(user_data: *mut std::ffi::c_void, #arg_param ctx: #ctx_ty) {
+                    let instance = unsafe { &*(user_data as *const T) };
+                    C::call();
+                    if std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                        plugin_interface::enter_request(ctx, || {
+                            instance.#method(#call_arg);
+                        })
+                    })).is_err() {
+                        C::panic();
+                    }
+                }
+            },
+        )
+    };
+    ContextSlot {
+        field: quote! { pub #slot: Option<#slot_ty> },
+        trampoline,
+        init: quote! { #slot: Some(#slot::<T, C>) },
+        getter: quote! {
+            /// The plugin's context-taking entry for this method, if its
+            /// context table is long enough to have one and it is set.
+            pub fn #getter(&self) -> Option<#slot_ty> {
+                let context = self.context_table()?;
+                let end = std::mem::offset_of!(#context_ident, #slot)
+                    + std::mem::size_of::<Option<#slot_ty>>();
+                if context.struct_size < end {
+                    return None;
+                }
+                context.#slot
+            }
+        },
+    }
+}
+
+/// Whether a method returns `&str` or `String`, which cross the boundary as
+/// strings.
+fn returns_string(output: &ReturnType) -> bool {
+    match output {
+        ReturnType::Type(_, ty) => {
+            let s = quote! { #ty }.to_string();
+            s.contains("str") || s.contains("String")
+        }
+        ReturnType::Default => false,
+    }
+}
+
+/// Whether a method returns `HealthStatus`, which crosses the boundary as a
+/// C enum.
+fn returns_health(output: &ReturnType) -> bool {
+    match output {
+        ReturnType::Type(_, ty) => match &**ty {
+            Type::Path(p) => p
+                .path
+                .segments
+                .last()
+                .is_some_and(|seg| seg.ident == "HealthStatus"),
+            _ => false,
+        },
+        ReturnType::Default => false,
+    }
+}
+
+/// The `DynamicRegistration::call` match arm for an optional method
+/// returning `HealthStatus`, which is reported in its `Display` form.
+fn health_dynamic_arm(method: &Ident) -> proc_macro2::TokenStream {
+    let name = method.to_string();
+    let getter = Ident::new(
+        &format!("optional_{}", name),
+        proc_macro2::Span::call_site(),
+    );
+    quote! {
+        #name => {
+            let entry = v.#getter().ok_or(plugin_interface::PluginCallError::Unsupported)?;
+            Ok(Some(entry(v.user_data).to_string()))
+        }
+    }
+}
+
+/// The `DynamicRegistration::call` match arm for a trait method, calling its
+/// `<method>_str` entry, or its optional entry, with `args`.
+fn dynamic_arm(
+    method: &Ident,
+    optional: bool,
+    has_str_arg: bool,
+    ret_is_str: bool,
+) -> proc_macro2::TokenStream {
+    let name = method.to_string();
+    let entry = if optional {
+        let getter = Ident::new(
+            &format!("optional_{}", name),
+            proc_macro2::Span::call_site(),
+        );
+        quote! { v.#getter().ok_or(plugin_interface::PluginCallError::Unsupported)? }
+    } else {
+        let field = Ident::new(&format!("{}_str", name), proc_macro2::Span::call_site());
+        quote! { v.#field }
+    };
+    let arg = if has_str_arg {
+        quote! { , plugin_interface::StrRef::new(args.first().copied().unwrap_or("")) }
+    } else {
+        quote! {}
+    };
+    let result = if ret_is_str {
+        quote! {
+            (#entry)(v.user_data #arg)
+                .into_string()
+                .map(Some)
+                .ok_or(plugin_interface::PluginCallError::Failed)
+        }
+    } else {
+        quote! {{
+            (#entry)(v.user_data #arg);
+            Ok(None)
+        }}
+    };
+    quote! { #name => #result, }
+}
+
+/// A `plugin_interface::MethodDescription` literal for a trait method.
+fn method_description(
+    sig: &syn::Signature,
+    optional: bool,
+    deprecated: Option<String>,
+) -> proc_macro2::TokenStream {
+    let name = sig.ident.to_string();
+    let args = sig.inputs.iter().filter_map(|arg| match arg {
+        syn::FnArg::Typed(t) => {
+            let pat = &t.pat;
+            let ty = &t.ty;
+            let arg_name = tidy_tokens(quote! { #pat }.to_string());
+            let arg_ty = tidy_tokens(quote! { #ty }.to_string());
+            Some(quote! { (#arg_name, #arg_ty) })
+        }
+        syn::FnArg::Receiver(_) => None,
+    });
+    let returns = match &sig.output {
+        ReturnType::Type(_, ty) => tidy_tokens(quote! { #ty }.to_string()),
+        ReturnType::Default => String::new(),
+    };
+    let deprecated = match deprecated {
+        Some(note) => quote! { Some(#note) },
+        None => quote! { None },
+    };
+    quote! {
+        plugin_interface::MethodDescription {
+            name: #name,
+            args: &[#(#args),*],
+            returns: #returns,
+            optional: #optional,
+            deprecated: #deprecated,
+        }
+    }
+}
+
+/// The note of a trait method's `#[deprecated]` attribute, with
+/// `since <version>: ` in front if it names one; empty for a bare
+/// `#[deprecated]`. The attribute stays on the method, so callers in Rust
+/// still get the compiler's warning.
+fn deprecation_note(attrs: &[syn::Attribute]) -> syn::Result<Option<String>> {
+    let Some(attr) = attrs.iter().find(|a| a.path().is_ident("deprecated")) else {
+        return Ok(None);
+    };
+    match &attr.meta {
+        syn::Meta::Path(_) => Ok(Some(String::new())),
+        syn::Meta::NameValue(nv) => match &nv.value {
+            syn::Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Str(note),
+                ..
+            }) => Ok(Some(note.value())),
+            other => Err(syn::Error::new_spanned(other, "expected a string")),
+        },
+        syn::Meta::List(_) => {
+            let (mut since, mut note) = (None, None);
+            attr.parse_nested_meta(|meta| {
+                let value = meta.value()?.parse::<syn::LitStr>()?.value();
+                if meta.path.is_ident("since") {
+                    since = Some(value);
+                } else if meta.path.is_ident("note") {
+                    note = Some(value);
+                } else {
+                    return Err(meta.error("expected `since` or `note`"));
+                }
+                Ok(())
+            })?;
+            Ok(Some(match (since, note) {
+                (Some(since), Some(note)) => format!("since {}: {}", since, note),
+                (Some(since), None) => format!("since {}", since),
+                (None, note) => note.unwrap_or_default(),
+            }))
+        }
+    }
+}
+
+/// Token text as it would be written by hand: `& 'a str` becomes `&'a str`
+/// and `Vec < u8 >` becomes `Vec<u8>`.
+fn tidy_tokens(s: String) -> String {
+    s.replace("& ", "&")
+        .replace(" <", "<")
+        .replace("< ", "<")
+        .replace(" >", ">")
+        .replace(" ,", ",")
+        .replace(" :: ", "::")
+        .replace(":: ", "::")
+}
+
+/// Remove `#[optional]` from a trait or impl method's attributes and report
+/// whether it was there.
+fn take_optional_attr(attrs: &mut Vec<syn::Attribute>) -> bool {
+    let before = attrs.len();
+    attrs.retain(|a| !a.path().is_ident("optional"));
+    attrs.len() != before
+}
+
+/// Type of the `<method>_str` vtable entry, which passes strings as pointer
+/// and length.
+fn str_field_ty(has_str_arg: bool, ret_is_str: bool) -> proc_macro2::TokenStream {
+    if has_str_arg && ret_is_str {
+        quote! { extern "C" fn(*mut std::ffi::c_void, plugin_interface::StrRef) -> plugin_interface::OwnedStr }
+    } else if has_str_arg {
+        quote! { extern "C" fn(*mut std::ffi::c_void, plugin_interface::StrRef) }
+    } else if ret_is_str {
+        quote! { extern "C" fn(*mut std::ffi::c_void) -> plugin_interface::OwnedStr }
+    } else {
+        quote! { extern "C" fn(*mut std::ffi::c_void) }
+    }
+}
+
+/// Trait methods that map to dedicated lifecycle and state vtable entries
+/// instead of ordinary method wrappers, and `configure`, which only the
+/// plugin's own register function calls.
+fn is_lifecycle_hook(name: &str) -> bool {
+    matches!(
+        name,
+        "on_load" | "on_unload" | "save_state" | "restore_state" | "configure"
+    )
+}
+
+/// The static `#[plugin_aggregates(Trait)]` declares for the trait's
+/// lifecycle counters, e.g. `GREETER_COUNTERS`, and the `#[plugin_impl(Trait)]`
+/// code updates.
+fn counters_static(trait_name: &str) -> Ident {
+    Ident::new(
+        &format!("{}_COUNTERS", trait_name.to_uppercase()),
+        proc_macro2::Span::call_site(),
+    )
+}
+
+/// The type `#[plugin_aggregates(Trait)]` declares as the trait's
+/// `plugin_interface::CountersSource`, e.g. `__GreeterCounters`, which
+/// `#[plugin_impl(Trait)]` hands to `<Trait>Optional::for_impl` and
+/// `<Trait>Context::for_impl`.
+fn counters_source(trait_name: &str) -> Ident {
+    Ident::new(
+        &format!("__{}Counters", trait_name),
+        proc_macro2::Span::call_site(),
+    )
+}
+
+/// The function `#[plugin_aggregates(Trait)]` declares for returning strings
+/// from the trait's wrappers at its ABI level, e.g.
+/// `__plugin_return_string_greeter`, and the `#[plugin_impl(Trait)]` code
+/// calls.
+fn return_string_fn(trait_name: &str) -> Ident {
+    Ident::new(
+        &format!("__plugin_return_string_{}", trait_name.to_lowercase()),
+        proc_macro2::Span::call_site(),
+    )
+}
+
 /// `#[plugin_impl(TraitName)]` applied to `impl TraitName for Type` generates C wrappers for
 /// the trait methods, a register function that returns a pointer to a heap-allocated
 /// registration struct, and an unregister function that frees the heap allocations.
+/// `on_load` / `on_unload` (overridden or trait defaults) are wired into the vtable's
+/// lifecycle entries, which the host calls after loading and before unloading.
+///
+/// `save_state` / `restore_state` are wired the same way, so the trait has to declare
+/// them (with defaults) just like the lifecycle hooks.
+///
+/// The trait also declares `configure(&mut self, config: &str)`. The register function
+/// calls it on the freshly constructed instance with `HostContext::plugin_config`, when
+/// the host has configuration for the plugin; it has no vtable entry.
+///
+/// The registration's `name` is the implementing type's name, or the one given with
+/// `#[plugin_impl(TraitName, name = "...")]`.
+///
+/// `#[plugin_impl(TraitName, instances = N)]` lets the host make up to `N` more
+/// instances with the register function for `PluginHandle::instantiate`, each
+/// constructed and configured afresh; a bare `instances` sets no limit.
+///
+/// `#[plugin_impl(TraitName, priority = N)]` gives the registration an `i32` priority;
+/// the host hands out registrations with higher priorities first (see
+/// `PluginManager::iter_by_priority`). It defaults to 0, and the plugin's manifest can
+/// override it.
+///
+/// Returned strings go through the trait's `__plugin_return_string_<trait>` from
+/// `#[plugin_aggregates]`, which uses the host's allocator at `HOST_ALLOCATOR_ABI`
+/// and above.
+///
+/// Each method gets a second wrapper for its `<method>_str` entry, which borrows the
+/// argument through `StrRef` and hands the result back as `OwnedStr` without a
+/// `CString` copy. The vtable's `abi_version` is `CONTEXT_VTABLE_ABI` and its
+/// `struct_size` the offset of `context` in the vtable type the plugin was built against.
+///
+/// Overrides of the trait's `#[optional]` methods are marked `#[optional]` here too.
+/// They get no wrappers of their own: the vtable's `optional` entries come from
+/// `<Trait>Optional::for_impl` and its `context` from `<Trait>Context::for_impl`,
+/// both counting in the trait's `<TRAIT>_COUNTERS` like the wrappers.
+///
+/// With the `abi-stable` feature none of this is generated: the implementation is
+/// submitted to `plugin-abi-stable`, which hands it to the host as an `abi_stable`
+/// trait object. The crate then depends on `plugin-abi-stable` and `abi_stable`.
 #[proc_macro_attribute]
 pub fn plugin_impl(attr: TokenStream, item: TokenStream) -> TokenStream {
-    let input = parse_macro_input!(item as ItemImpl);
+    let mut input = parse_macro_input!(item as ItemImpl);
+    let args = parse_macro_input!(attr as ImplArgs);
 
-    let trait_path = if !attr.is_empty() {
-        Some(parse_macro_input!(attr as syn::Path))
-    } else {
-        None
-    };
-
-    let trait_ident = trait_path
+    let trait_ident = args
+        .trait_path
         .as_ref()
         .and_then(|p| p.segments.last())
         .map(|s| s.ident.to_string())
         .unwrap_or_else(|| "Greeter".to_string());
+    let counters_static = counters_static(&trait_ident);
+    let return_string = return_string_fn(&trait_ident);
+    let counters_source = counters_source(&trait_ident);
 
     // prepare a nul-terminated byte string literal for the trait name
     let mut trait_name_bytes = trait_ident.as_bytes().to_vec();
@@ -122,6 +800,13 @@ pub fn plugin_impl(attr: TokenStream, item: TokenStream) -> TokenStream {
 
     // self type
     let self_ty: &Type = &input.self_ty;
+    let impl_trait = match &input.trait_ {
+        Some((_, path, _)) => quote! { #path },
+        None => {
+            let ident = Ident::new(&trait_ident, proc_macro2::Span::call_site());
+            quote! { #ident }
+        }
+    };
 
     let ty_tokens = quote! { #self_ty };
     let ty_ident_string = ty_tokens.to_string();
@@ -137,31 +822,65 @@ pub fn plugin_impl(attr: TokenStream, item: TokenStream) -> TokenStream {
         })
         .collect();
 
+    // instances the host may make besides the registration itself
+    let max_instances = args.instances.clone().unwrap_or_else(|| quote! { 0 });
+    let priority = args.priority.clone().unwrap_or_else(|| quote! { 0 });
+
+    // nul-terminated registration name, stored in the plugin image
+    let registration_name = match &args.name {
+        Some(lit) => lit.value(),
+        None => match self_ty {
+            Type::Path(p) => p
+                .path
+                .segments
+                .last()
+                .map(|s| s.ident.to_string())
+                .unwrap_or_else(|| safe_name.clone()),
+            _ => safe_name.clone(),
+        },
+    };
+    let registration_name_str = registration_name.clone();
+    let mut registration_name_bytes = registration_name.into_bytes();
+    registration_name_bytes.push(0);
+    let registration_name_lit = proc_macro2::Literal::byte_string(&registration_name_bytes);
+
     // collect methods
     let mut methods: Vec<(String, bool, bool)> = Vec::new();
-    for item in input.items.iter() {
+    for item in input.items.iter_mut() {
         if let ImplItem::Fn(m) = item {
+            let optional = take_optional_attr(&mut m.attrs);
             let sig = &m.sig;
             let name = sig.ident.to_string();
+            // Lifecycle hooks get dedicated trampolines below, whether or not
+            // the impl overrides the trait's default.
+            if optional || is_lifecycle_hook(&name) {
+                continue;
+            }
             let mut has_str_arg = false;
             if sig.inputs.len() > 1 {
                 has_str_arg = true;
             }
-            let ret_is_str = match &sig.output {
-                ReturnType::Type(_, ty) => {
-                    let s = quote! { #ty }.to_string();
-                    s.contains("str")
-                }
-                _ => false,
-            };
+            let ret_is_str = returns_string(&sig.output);
             methods.push((name, has_str_arg, ret_is_str));
         }
     }
 
+    if ABI_STABLE {
+        // `plugin-abi-stable` wraps the implementation in its stable trait
+        // object and lists it in the crate's module.
+        let trait_id = Ident::new(&trait_ident, proc_macro2::Span::call_site());
+        return TokenStream::from(quote! {
+            #input
+
+            plugin_abi_stable::submit!(#trait_id, #self_ty, #registration_name_str);
+        });
+    }
+
     // build wrappers and vtable fields
     let mut wrapper_fns = Vec::new();
     let mut vtable_inits = Vec::new();
     let mut vtable_fields = Vec::new();
+    let mut str_inits = Vec::new();
     for (name, has_str_arg, ret_is_str) in &methods {
         let wrapper_ident = Ident::new(
             &format!("{}_{}_wrapper", safe_name, name),
@@ -169,60 +888,74 @@ pub fn plugin_impl(attr: TokenStream, item: TokenStream) -> TokenStream {
         );
         let field_ident = Ident::new(name.as_str(), proc_macro2::Span::call_site());
 
-            let wrapper = if *has_str_arg && *ret_is_str {
+        let wrapper = if *has_str_arg && *ret_is_str {
             quote! {
-                #[allow(clippy::not_unsafe_ptr_arg_deref)]
+                #[allow(clippy::not_unsafe_ptr_arg_deref, deprecated)]
                 #[no_mangle]
                 pub extern "C" fn #wrapper_ident(user_data: *mut std::ffi::c_void, arg: *const std::os::raw::c_char) -> *const std::os::raw::c_char {
                     let instance = unsafe { &*(user_data as *const #self_ty) };
+                    crate::#counters_static.call();
                     let cstr = unsafe { std::ffi::CStr::from_ptr(arg) };
                     let arg_str = cstr.to_str().unwrap_or("");
                     let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
                         instance.#field_ident(arg_str)
                     }));
                     match res {
-                        Ok(s) => std::ffi::CString::new(s).unwrap().into_raw() as *const std::os::raw::c_char,
-                        Err(_) => std::ptr::null(),
+                        Ok(s) => crate::#return_string(&s),
+                        Err(_) => {
+                            crate::#counters_static.panic();
+                            std::ptr::null()
+                        }
                     }
                 }
             }
         } else if *has_str_arg {
             quote! {
-                #[allow(clippy::not_unsafe_ptr_arg_deref)]
+                #[allow(clippy::not_unsafe_ptr_arg_deref, deprecated)]
                 #[no_mangle]
                 pub extern "C" fn #wrapper_ident(user_data: *mut std::ffi::c_void, arg: *const std::os::raw::c_char) {
                     let instance = unsafe { &*(user_data as *const #self_ty) };
+                    crate::#counters_static.call();
                     let cstr = unsafe { std::ffi::CStr::from_ptr(arg) };
                     let arg_str = cstr.to_str().unwrap_or("");
-                    let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                    if std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
                         instance.#field_ident(arg_str);
-                    }));
+                    })).is_err() {
+                        crate::#counters_static.panic();
+                    }
                 }
             }
         } else if *ret_is_str {
             quote! {
-                #[allow(clippy::not_unsafe_ptr_arg_deref)]
+                #[allow(clippy::not_unsafe_ptr_arg_deref, deprecated)]
                 #[no_mangle]
                 pub extern "C" fn #wrapper_ident(user_data: *mut std::ffi::c_void) -> *const std::os::raw::c_char {
                     let instance = unsafe { &*(user_data as *const #self_ty) };
+                    crate::#counters_static.call();
                     let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
                         instance.#field_ident()
                     }));
                     match res {
-                        Ok(s) => std::ffi::CString::new(s).unwrap().into_raw() as *const std::os::raw::c_char,
-                        Err(_) => std::ptr::null(),
+                        Ok(s) => crate::#return_string(&s),
+                        Err(_) => {
+                            crate::#counters_static.panic();
+                            std::ptr::null()
+                        }
                     }
                 }
             }
         } else {
             quote! {
-                #[allow(clippy::not_unsafe_ptr_arg_deref)]
+                #[allow(clippy::not_unsafe_ptr_arg_deref, deprecated)]
                 #[no_mangle]
                 pub extern "C" fn #wrapper_ident(user_data: *mut std::ffi::c_void) {
                     let instance = unsafe { &*(user_data as *const #self_ty) };
-                    let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                    crate::#counters_static.call();
+                    if std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
                         instance.#field_ident();
-                    }));
+                    })).is_err() {
+                        crate::#counters_static.panic();
+                    }
                 }
             }
         };
@@ -240,6 +973,57 @@ pub fn plugin_impl(attr: TokenStream, item: TokenStream) -> TokenStream {
         wrapper_fns.push(wrapper);
         vtable_fields.push(quote! { pub #field_ident: #field_ty });
         vtable_inits.push(quote! { #field_ident: #wrapper_ident as #field_ty });
+
+        let str_wrapper_ident = Ident::new(
+            &format!("{}_{}_str_wrapper", safe_name, name),
+            proc_macro2::Span::call_site(),
+        );
+        let str_field_ident = Ident::new(&format!("{}_str", name), proc_macro2::Span::call_site());
+        let (arg_param, call_arg) = if *has_str_arg {
+            (
+                quote! { , arg: plugin_interface::StrRef },
+                quote! { unsafe { arg.as_str() } },
+            )
+        } else {
+            (quote! {}, quote! {})
+        };
+        let str_wrapper = if *ret_is_str {
+            quote! {
+                #[allow(clippy::not_unsafe_ptr_arg_deref, deprecated)]
+                #[no_mangle]
+                pub extern "C" fn #str_wrapper_ident(user_data: *mut std::ffi::c_void #arg_param) -> plugin_interface::OwnedStr {
+                    let instance = unsafe { &*(user_data as *const #self_ty) };
+                    crate::#counters_static.call();
+                    let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                        String::from(instance.#field_ident(#call_arg))
+                    }));
+                    match res {
+                        Ok(s) => plugin_interface::OwnedStr::new(s),
+                        Err(_) => {
+                            crate::#counters_static.panic();
+                            plugin_interface::OwnedStr::null()
+                        }
+                    }
+                }
+            }
+        } else {
+            quote! {
+                #[allow(clippy::not_unsafe_ptr_arg_deref, deprecated)]
+                #[no_mangle]
+                pub extern "C" fn #str_wrapper_ident(user_data: *mut std::ffi::c_void #arg_param) {
+                    let instance = unsafe { &*(user_data as *const #self_ty) };
+                    crate::#counters_static.call();
+                    if std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                        instance.#field_ident(#call_arg);
+                    })).is_err() {
+                        crate::#counters_static.panic();
+                    }
+                }
+            }
+        };
+        let str_ty = str_field_ty(*has_str_arg, *ret_is_str);
+        wrapper_fns.push(str_wrapper);
+        str_inits.push(quote! { #str_field_ident: #str_wrapper_ident as #str_ty });
     }
 
     let trait_vtable_ident = Ident::new(
@@ -250,14 +1034,69 @@ pub fn plugin_impl(attr: TokenStream, item: TokenStream) -> TokenStream {
         &format!("{}Registration", trait_ident),
         proc_macro2::Span::call_site(),
     );
+    let trait_optional_ident = Ident::new(
+        &format!("{}Optional", trait_ident),
+        proc_macro2::Span::call_site(),
+    );
+    let trait_context_ident = Ident::new(
+        &format!("{}Context", trait_ident),
+        proc_macro2::Span::call_site(),
+    );
     // Make per-impl symbol names unique by including the implementing type name
-    let register_symbol = format!("plugin_register_{}_{}_v1", trait_ident, safe_name);
+    let register_symbol =
+        symbols::per_impl(symbols::REGISTER, &trait_ident.to_string(), &safe_name);
     let register_ident = Ident::new(&register_symbol, proc_macro2::Span::call_site());
-    let unregister_symbol = format!("plugin_unregister_{}_{}_v1", trait_ident, safe_name);
+    let unregister_symbol =
+        symbols::per_impl(symbols::UNREGISTER, &trait_ident.to_string(), &safe_name);
     let unregister_ident = Ident::new(&unregister_symbol, proc_macro2::Span::call_site());
     // We will submit a `plugin_interface::RegistrationFactory` instance which
     // contains an erased function pointer and the trait name. The host-side
     // aggregation helpers will filter by trait name.
+    let factory = quote! {
+        plugin_interface::RegistrationFactory::new(
+            #register_ident as extern "C" fn() -> *const std::ffi::c_void,
+            #unregister_ident as extern "C" fn(*const std::ffi::c_void),
+            #trait_name_lit.as_ptr() as *const std::os::raw::c_char,
+        )
+        .with_max_instances(#max_instances)
+        .with_priority(#priority)
+        .with_name(#registration_name_lit.as_ptr() as *const std::os::raw::c_char)
+    };
+    // Names this registration in the crate's `plugin_describe_v1` document.
+    let description = quote! {
+        plugin_interface::RegistrationDescription {
+            trait_name: #trait_ident,
+            name: #registration_name_str,
+        }
+    };
+    let registration_entries = if EXPLICIT_REGISTRATION {
+        // Statics `collect_registrations!` lists by the implementing type.
+        let factory_ident = Ident::new(
+            &entry_name("FACTORY", &trait_ident, &safe_name),
+            proc_macro2::Span::call_site(),
+        );
+        let description_ident = Ident::new(
+            &entry_name("DESCRIPTION", &trait_ident, &safe_name),
+            proc_macro2::Span::call_site(),
+        );
+        quote! {
+            #[doc(hidden)]
+            #[allow(non_upper_case_globals)]
+            pub(crate) static #factory_ident: plugin_interface::RegistrationFactory = #factory;
+
+            #[doc(hidden)]
+            #[allow(non_upper_case_globals)]
+            pub(crate) static #description_ident: plugin_interface::RegistrationDescription =
+                #description;
+        }
+    } else {
+        // Submit the factory to the crate's inventory, where the register_all
+        // of `#[plugin_aggregates(Trait)]` finds it by trait name.
+        quote! {
+            inventory::submit! { #factory }
+            inventory::submit! { #description }
+        }
+    };
 
     // final expansion
     let expanded = quote! {
@@ -268,25 +1107,97 @@ pub fn plugin_impl(attr: TokenStream, item: TokenStream) -> TokenStream {
     #[no_mangle]
     pub extern "C" fn #register_ident() -> *const std::ffi::c_void {
             unsafe {
-                let boxed: Box<#self_ty> = Box::new(<#self_ty>::default());
+                let mut instance = <#self_ty>::default();
+                if let Some(config) = crate::host_context().and_then(|h| h.plugin_config()) {
+                    if std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                        <#self_ty as #impl_trait>::configure(&mut instance, config);
+                    })).is_err() {
+                        crate::#counters_static.panic();
+                    }
+                }
+                let boxed: Box<#self_ty> = Box::new(instance);
                 let user_ptr = Box::into_raw(boxed) as *mut std::ffi::c_void;
+                plugin_interface::record_made(plugin_interface::Allocation::UserData);
 
                 extern "C" fn drop_trampoline(u: *mut std::ffi::c_void) {
                     if u.is_null() { return; }
                     unsafe {
                         let _boxed: Box<#self_ty> = Box::from_raw(u as *mut #self_ty);
                     }
+                    plugin_interface::record_released(plugin_interface::Allocation::UserData);
+                }
+
+                extern "C" fn on_load_trampoline(u: *mut std::ffi::c_void, host: *const plugin_interface::HostInfo) {
+                    if u.is_null() || host.is_null() { return; }
+                    let instance = unsafe { &*(u as *const #self_ty) };
+                    let host = unsafe { &*host };
+                    if std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                        <#self_ty as #impl_trait>::on_load(instance, host);
+                    })).is_err() {
+                        crate::#counters_static.panic();
+                    }
+                }
+
+                extern "C" fn on_unload_trampoline(u: *mut std::ffi::c_void) {
+                    if u.is_null() { return; }
+                    let instance = unsafe { &*(u as *const #self_ty) };
+                    if std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                        <#self_ty as #impl_trait>::on_unload(instance);
+                    })).is_err() {
+                        crate::#counters_static.panic();
+                    }
+                }
+
+                extern "C" fn save_state_trampoline(
+                    u: *mut std::ffi::c_void,
+                    out: *mut std::ffi::c_void,
+                    sink: plugin_interface::StateSink,
+                ) {
+                    if u.is_null() { return; }
+                    let instance = unsafe { &*(u as *const #self_ty) };
+                    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                        <#self_ty as #impl_trait>::save_state(instance)
+                    })) {
+                        Ok(bytes) => sink(out, bytes.as_ptr(), bytes.len()),
+                        Err(_) => crate::#counters_static.panic(),
+                    }
+                }
+
+                extern "C" fn restore_state_trampoline(u: *mut std::ffi::c_void, data: *const u8, len: usize) {
+                    if u.is_null() || data.is_null() { return; }
+                    let instance = unsafe { &mut *(u as *mut #self_ty) };
+                    let bytes = unsafe { std::slice::from_raw_parts(data, len) };
+                    if std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                        <#self_ty as #impl_trait>::restore_state(instance, bytes);
+                    })).is_err() {
+                        crate::#counters_static.panic();
+                    }
                 }
 
                 let vtable = Box::new(plugin_interface::#trait_vtable_ident {
-                    abi_version: 1,
+                    abi_version: plugin_interface::CONTEXT_VTABLE_ABI,
                     user_data: user_ptr,
                     #(#vtable_inits,)*
+                    on_load: on_load_trampoline,
+                    on_unload: on_unload_trampoline,
                     drop: drop_trampoline,
+                    save_state: save_state_trampoline,
+                    restore_state: restore_state_trampoline,
+                    #(#str_inits,)*
+                    struct_size: std::mem::offset_of!(plugin_interface::#trait_vtable_ident, context),
+                    flags: 0,
+                    optional: plugin_interface::#trait_optional_ident::for_impl::<#self_ty, crate::#counters_source>(),
+                    context: plugin_interface::#trait_context_ident::for_impl::<#self_ty, crate::#counters_source>(),
                 });
                 let vtable_ptr = Box::into_raw(vtable);
+                plugin_interface::record_made(plugin_interface::Allocation::VTable);
 
-                let reg = Box::new(plugin_interface::#trait_registration_ident { name: std::ptr::null(), vtable: vtable_ptr });
+                let reg = Box::new(plugin_interface::#trait_registration_ident {
+                    name: #registration_name_lit.as_ptr() as *const std::os::raw::c_char,
+                    vtable: vtable_ptr,
+                });
+                plugin_interface::record_made(plugin_interface::Allocation::Registration);
+                crate::#counters_static.created();
                 Box::into_raw(reg) as *const std::ffi::c_void
             }
         }
@@ -297,93 +1208,626 @@ pub fn plugin_impl(attr: TokenStream, item: TokenStream) -> TokenStream {
             unsafe {
                 let reg_box: Box<plugin_interface::#trait_registration_ident> = Box::from_raw(reg_ptr as *mut _);
                 let vtable_ptr = reg_box.vtable as *mut plugin_interface::#trait_vtable_ident;
+                drop(reg_box);
+                plugin_interface::record_released(plugin_interface::Allocation::Registration);
 
-                // In-process test hook: increment the per-crate `UNMAKER_COUNTER`
-                // exported by `#[plugin_aggregates]`. This avoids file I/O and
-                // allows the host test to read the counter via a library symbol.
-                // Note: `#[plugin_aggregates]` must be applied in the crate so the
-                // `UNMAKER_COUNTER` symbol exists; otherwise this will fail to
-                // compile for that crate.
-                crate::UNMAKER_COUNTER.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                // Counted in the trait's `<TRAIT>_COUNTERS` that
+                // `#[plugin_aggregates]` declares; hosts read them through
+                // its `plugin_counters_<Trait>_v<N>` getter.
+                crate::#counters_static.destroyed();
 
                 if !vtable_ptr.is_null() {
                     ((*vtable_ptr).drop)((*vtable_ptr).user_data);
                     let _ = Box::from_raw(vtable_ptr);
+                    plugin_interface::record_released(plugin_interface::Allocation::VTable);
                 }
             }
         }
 
-        // Submit this register function into the crate-local inventory type
-        // named `__RegistrationFactory_<Trait>` as an erased extern "C" fn pointer.
-        // The crate should apply `#[plugin_aggregates(Trait)]` once to declare
-        // the `__RegistrationFactory_<Trait>` type; here we assume that type
-        // exists and simply submit the function pointer.
-        inventory::submit! {
-            plugin_interface::RegistrationFactory {
-                maker: #register_ident as extern "C" fn() -> *const std::ffi::c_void,
-                unmaker: #unregister_ident as extern "C" fn(*const std::ffi::c_void),
-                trait_name: #trait_name_lit.as_ptr() as *const std::os::raw::c_char,
+        #registration_entries
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// Arguments of `#[plugin_impl]`: `()`, `(Trait)`, or `(Trait, ...)` followed by
+/// `name = "..."`, `instances [= N]` and/or `priority = N`.
+struct ImplArgs {
+    trait_path: Option<syn::Path>,
+    name: Option<syn::LitStr>,
+    /// `instances = N`, or a bare `instances` for no limit.
+    instances: Option<proc_macro2::TokenStream>,
+    /// `priority = N`, possibly negative.
+    priority: Option<proc_macro2::TokenStream>,
+}
+
+impl syn::parse::Parse for ImplArgs {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        if input.is_empty() {
+            return Ok(Self {
+                trait_path: None,
+                name: None,
+                instances: None,
+                priority: None,
+            });
+        }
+        let trait_path: syn::Path = input.parse()?;
+        let mut name = None;
+        let mut instances = None;
+        let mut priority = None;
+        while input.parse::<Option<syn::Token![,]>>()?.is_some() && !input.is_empty() {
+            let key: Ident = input.parse()?;
+            if key == "name" {
+                input.parse::<syn::Token![=]>()?;
+                let lit: syn::LitStr = input.parse()?;
+                if lit.value().contains('\0') {
+                    return Err(syn::Error::new(lit.span(), "name must not contain NUL"));
+                }
+                name = Some(lit);
+            } else if key == "instances" {
+                instances = Some(if input.parse::<Option<syn::Token![=]>>()?.is_some() {
+                    let max: syn::LitInt = input.parse()?;
+                    max.base10_parse::<u32>()?;
+                    quote! { #max }
+                } else {
+                    quote! { plugin_interface::UNLIMITED_INSTANCES }
+                });
+            } else if key == "priority" {
+                input.parse::<syn::Token![=]>()?;
+                let minus = input.parse::<Option<syn::Token![-]>>()?;
+                let value: syn::LitInt = input.parse()?;
+                let digits = format!(
+                    "{}{}",
+                    if minus.is_some() { "-" } else { "" },
+                    value.base10_digits()
+                );
+                digits
+                    .parse::<i32>()
+                    .map_err(|e| syn::Error::new(value.span(), e))?;
+                priority = Some(quote! { #minus #value });
+            } else {
+                return Err(syn::Error::new(
+                    key.span(),
+                    "expected `name = \"...\"`, `instances = N` or `priority = N`",
+                ));
             }
         }
+        Ok(Self {
+            trait_path: Some(trait_path),
+            name,
+            instances,
+            priority,
+        })
+    }
+}
 
-        // Note: aggregated register_all/unregister_all helpers are generated by the
-        // `#[plugin_aggregates(TraitName)]` attribute and are not emitted here to
-        // avoid duplicate symbol definitions when a crate contains multiple
-        // implementations. Each impl contributes a local inventory entry via the
-        // `#local_factory_ident` type above.
-    };
+/// Arguments of `#[plugin_aggregates(Trait)]` / `#[plugin_aggregates(Trait, abi = N)]`.
+struct AggregatesArgs {
+    trait_path: syn::Path,
+    abi: syn::LitInt,
+    /// The `Features` the crate cannot do without; none if not given.
+    requires: Option<syn::Expr>,
+    /// The oldest host version the crate registers with, as major, minor
+    /// and patch.
+    min_host: Option<[u32; 3]>,
+}
 
-    TokenStream::from(expanded)
+impl syn::parse::Parse for AggregatesArgs {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let trait_path: syn::Path = input.parse()?;
+        let mut abi = syn::LitInt::new("1", proc_macro2::Span::call_site());
+        let mut requires = None;
+        let mut min_host = None;
+        while input.parse::<Option<syn::Token![,]>>()?.is_some() && !input.is_empty() {
+            let key: Ident = input.parse()?;
+            input.parse::<syn::Token![=]>()?;
+            if key == "abi" {
+                abi = input.parse()?;
+                let level = abi.base10_parse::<u32>()?;
+                if !(MIN_PLUGIN_ABI..=MAX_PLUGIN_ABI).contains(&level) {
+                    return Err(syn::Error::new(
+                        abi.span(),
+                        format!(
+                            "unsupported plugin ABI level; expected {} to {}",
+                            MIN_PLUGIN_ABI, MAX_PLUGIN_ABI
+                        ),
+                    ));
+                }
+            } else if key == "requires" {
+                // Several add up, as handed down by earlier attributes.
+                let features: syn::Expr = input.parse()?;
+                requires = Some(match requires.take() {
+                    Some(earlier) => syn::parse_quote! { (#earlier) | (#features) },
+                    None => features,
+                });
+            } else if key == "min_host" {
+                let version: syn::LitStr = input.parse()?;
+                let parts: Vec<u32> = version
+                    .value()
+                    .split('.')
+                    .map(|part| part.parse().ok())
+                    .collect::<Option<_>>()
+                    .unwrap_or_default();
+                let [major, minor, patch] = parts[..] else {
+                    return Err(syn::Error::new(
+                        version.span(),
+                        "expected a version like \"0.2.0\"",
+                    ));
+                };
+                min_host = Some(([major, minor, patch], version.span()));
+            } else {
+                return Err(syn::Error::new(
+                    key.span(),
+                    "expected `abi = <level>`, `requires = <features>` or `min_host = \"...\"`",
+                ));
+            }
+        }
+        if let Some((_, span)) = min_host {
+            if abi.base10_parse::<u32>()? < HOST_VERSION_ABI {
+                return Err(syn::Error::new(
+                    span,
+                    format!(
+                        "plugins only learn the host version from `abi = {}` on",
+                        HOST_VERSION_ABI
+                    ),
+                ));
+            }
+        }
+        Ok(Self {
+            trait_path,
+            abi,
+            requires,
+            min_host: min_host.map(|(version, _)| version),
+        })
+    }
 }
 
 /// Emit aggregated register_all/unregister_all helpers for a trait by iterating
 /// the crate-local inventory entries produced by `#[plugin_impl]` expansions.
+/// From `abi = 2` on, `register_all` receives the host's `HostContext`, which the
+/// crate can reach through the generated `host_context()` function; at `abi = 1`
+/// it takes no arguments, as it did before hosts had a context to pass. A host
+/// the crate is linked into hands it the context through the `HostContextSlot`
+/// submitted here instead (see `PluginManager::load_static`).
+///
+/// Apply it at the crate root once per trait the crate provides, stacking the
+/// attributes on one item. What it emits for a trait is named after the trait;
+/// the last `#[plugin_aggregates]` on the item also emits what the crate exports
+/// only once, described below.
+///
+/// The exported symbols carry the registration ABI level as a `_v<N>` suffix;
+/// it defaults to 1 and is chosen with `#[plugin_aggregates(Trait, abi = N)]`.
+///
+/// `plugin_counters_<Trait>_v<N>()` returns the trait's `PluginCounters`: the
+/// registrations the crate's `#[plugin_impl(Trait)]` code made and released, the
+/// calls through its wrappers and the panics they caught. Each trait keeps its own
+/// counters. `plugin_unmaker_counter_<Trait>_v<N>()` returns just the released
+/// registrations, for hosts that predate the counters.
+///
+/// `plugin_selftest_<Trait>_v<N>()` makes a fresh instance of every registration,
+/// asks its `health` hook and releases it again, and returns how many instances
+/// could not be made or reported `HealthStatus::Unhealthy`. Hosts run it with
+/// `PluginManager::set_self_test`.
+///
+/// `plugin_list_impls_<Trait>_v<N>(host, count)` returns the crate's `RegistrationFactory`s
+/// for the trait and stores how many there are in `count`. Each factory carries the
+/// name its registrations get, and its `maker` and `unmaker` are the implementation's
+/// own `plugin_register_<Trait>_<Type>_v1` / `plugin_unregister_<Trait>_<Type>_v1`,
+/// so hosts can make registrations of some implementations only. A non-null `host`
+/// is stored and handed to the register hooks like `plugin_register_all_*` does; pass
+/// null to only list them. The array stays valid while the library is loaded.
+///
+/// From `abi = 5` on, register_all and list_impls also take the host version, which
+/// the crate reads back through `host_version()`.
+/// `#[plugin_aggregates(Trait, abi = 5, min_host = "0.2.0")]` refuses older hosts and
+/// says why through the crate's `plugin_register_error_v1`.
+///
+/// `plugin_call_dynamic_<Trait>_v1(registration, method, args_json)` calls a method of
+/// one of the crate's registrations of the trait by name, with JSON arguments, and
+/// returns a JSON result allocated like the strings the `#[plugin_impl]` wrappers
+/// return. Its `v1` does not follow the ABI level.
+///
+/// `plugin_describe_v1()` returns a nul-terminated JSON document describing the crate
+/// and every trait it aggregates, with each trait's ABI level, methods and
+/// registrations, without registering anything. The string stays valid while the
+/// library is loaded; the host must not free it.
+///
+/// `plugin_handshake_v1(host_features)` receives the `Features` the host offers before
+/// it registers the crate, which the crate reads back through the generated
+/// `host_features()` function, and returns the features the crate requires:
+/// `#[plugin_aggregates(Trait, requires = <Features expression>)]`, none by default.
+/// The `requires` of every `#[plugin_aggregates]` on the item add up. The host
+/// refuses the crate if it does not offer all of them.
+///
+/// `plugin_register_error_v1()` says why the crate refused the host, if a
+/// register_all or list_impls with `min_host` did.
+///
+/// `plugin_leak_report_v1(out)` fills in a `LeakReport` with what the crate's
+/// `#[plugin_impl]` code allocated and released, when `plugin-interface` is built
+/// with its `leak-check` feature, and returns false otherwise.
+///
+/// With the `abi-stable` feature it exports the crate's `plugin_abi_stable::PluginModule`
+/// instead, which lists the implementations of every trait. `host_context()`,
+/// `host_version()` and `host_features()` are then always `None`.
 #[proc_macro_attribute]
 pub fn plugin_aggregates(attr: TokenStream, item: TokenStream) -> TokenStream {
     // Expect the attribute to be the trait identifier, e.g. #[plugin_aggregates(Greeter)]
-    let trait_path: syn::Path = parse_macro_input!(attr as syn::Path);
-    let trait_ident = trait_path
+    let args = parse_macro_input!(attr as AggregatesArgs);
+    let trait_ident = args
+        .trait_path
         .segments
         .last()
         .expect("expected trait identifier")
         .ident
         .to_string();
+    let abi: u32 = args.abi.base10_parse().expect("validated while parsing");
+    let abi_lit = proc_macro2::Literal::u32_unsuffixed(abi);
 
     // literal for trait name, used in generated code comparisons
     let trait_name_lit = proc_macro2::Literal::string(&trait_ident);
-    let register_all_symbol = format!("plugin_register_all_{}_v1", trait_ident);
+    let trait_name_c = proc_macro2::Literal::string(&format!("{}\0", trait_ident));
+    let register_all_symbol = symbols::versioned(symbols::REGISTER_ALL, &trait_ident, abi);
     let register_all_ident = Ident::new(&register_all_symbol, proc_macro2::Span::call_site());
-    let unregister_all_symbol = format!("plugin_unregister_all_{}_v1", trait_ident);
+    let unregister_all_symbol = symbols::versioned(symbols::UNREGISTER_ALL, &trait_ident, abi);
     let unregister_all_ident = Ident::new(&unregister_all_symbol, proc_macro2::Span::call_site());
 
-    // Create a versioned getter symbol for the unmaker counter, e.g.
-    // `plugin_unmaker_counter_Greeter_v1` so hosts can call a stable, typed API.
-    let getter_symbol = format!("plugin_unmaker_counter_{}_v1", trait_ident);
+    // Versioned getters for the lifecycle counters, e.g.
+    // `plugin_counters_Greeter_v1`, and for the older unmaker counter alone.
+    let counters_ident = Ident::new(
+        &symbols::versioned(symbols::COUNTERS, &trait_ident, abi),
+        proc_macro2::Span::call_site(),
+    );
+    let getter_symbol = symbols::versioned(symbols::UNMAKER_COUNTER, &trait_ident, abi);
     let getter_ident = Ident::new(&getter_symbol, proc_macro2::Span::call_site());
+    let counters_static = counters_static(&trait_ident);
+    let counters_source = counters_source(&trait_ident);
+    let selftest_ident = Ident::new(
+        &symbols::versioned(symbols::SELFTEST, &trait_ident, abi),
+        proc_macro2::Span::call_site(),
+    );
+    let list_impls_ident = Ident::new(
+        &symbols::versioned(symbols::LIST_IMPLS, &trait_ident, abi),
+        proc_macro2::Span::call_site(),
+    );
+    let call_dynamic_ident = Ident::new(
+        &symbols::versioned(
+            symbols::CALL_DYNAMIC,
+            &trait_ident,
+            symbols::CALL_DYNAMIC_VERSION,
+        ),
+        proc_macro2::Span::call_site(),
+    );
+    // Crate-local helpers, named after the trait like the exports.
+    let lower = trait_ident.to_lowercase();
+    let return_string_ident = return_string_fn(&trait_ident);
+    let refuses_host_ident = Ident::new(
+        &format!("__plugin_refuses_host_{}", lower),
+        proc_macro2::Span::call_site(),
+    );
+    let store_host_context_ident = Ident::new(
+        &format!("__plugin_store_host_context_{}", lower),
+        proc_macro2::Span::call_site(),
+    );
+    // From `HOST_VERSION_ABI` on, register_all and list_impls take the host
+    // version after the context and refuse hosts older than `min_host`.
+    let version_arg = if abi >= HOST_VERSION_ABI {
+        quote! { version }
+    } else {
+        quote! { std::ptr::null() }
+    };
+    let (version_param, accept_host) = if abi >= HOST_VERSION_ABI {
+        let refusal = match args.min_host {
+            Some([major, minor, patch]) => quote! {
+                match version {
+                    Some(v) if !v.at_least(#major, #minor, #patch) => Some(format!(
+                        "host {} is older than the {}.{}.{} this plugin requires",
+                        v, #major, #minor, #patch
+                    )),
+                    _ => None,
+                }
+            },
+            None => quote! { None::<String> },
+        };
+        (
+            quote! { version: *const plugin_interface::HostVersion, },
+            quote! {
+                // SAFETY: the host passes null or a version valid for the call.
+                let version = unsafe { version.as_ref() }.copied();
+                *crate::HOST_VERSION.lock().unwrap_or_else(|e| e.into_inner()) = version;
+                let refusal: Option<String> = #refusal;
+                let refused = refusal.is_some();
+                *crate::REGISTER_ERROR.lock().unwrap_or_else(|e| e.into_inner()) =
+                    refusal.and_then(|r| std::ffi::CString::new(r).ok());
+                refused
+            },
+        )
+    } else {
+        (quote! {}, quote! { false })
+    };
+    let vtable_ident = Ident::new(
+        &format!("{}VTable", trait_ident),
+        proc_macro2::Span::call_site(),
+    );
+    let registration_ident = Ident::new(
+        &format!("{}Registration", trait_ident),
+        proc_macro2::Span::call_site(),
+    );
 
-    // We iterate over plugin_interface::RegistrationFactory and filter by trait_name.
+    // We iterate over plugin_interface::RegistrationFactory and filter by trait_name,
+    // from the crate's inventory or the lists of `collect_registrations!`.
+    let (factories, hooks) = if EXPLICIT_REGISTRATION {
+        (
+            quote! { crate::__plugin_factories().iter().copied() },
+            quote! { crate::__plugin_register_hooks().iter().copied() },
+        )
+    } else {
+        (
+            quote! { inventory::iter::<plugin_interface::RegistrationFactory> },
+            quote! { inventory::iter::<plugin_interface::RegisterHook> },
+        )
+    };
+    // From `HOST_CONTEXT_ABI` on, register_all takes the host context and
+    // stores it; at level 1 it takes nothing, as it did before hosts had one.
+    let (host_param, take_host) = if abi >= HOST_CONTEXT_ABI {
+        (
+            quote! { host: *const plugin_interface::HostContext, },
+            quote! {
+                crate::HOST_CONTEXT.store(host as *mut _, std::sync::atomic::Ordering::SeqCst);
+                if !host.is_null() {
+                    for hook in #hooks {
+                        (hook.run)(host);
+                    }
+                }
+            },
+        )
+    } else {
+        (quote! {}, quote! {})
+    };
+    // Lists the trait in the crate's `plugin_describe_v1` document.
+    let trait_description = quote! {
+        plugin_interface::TraitDescription {
+            name: #trait_name_lit,
+            abi_version: #abi_lit,
+            vtable_abi_version: plugin_interface::CONTEXT_VTABLE_ABI,
+            methods: plugin_interface::#vtable_ident::METHODS,
+        }
+    };
+    let trait_entry = if EXPLICIT_REGISTRATION {
+        // The static `collect_registrations!` lists by the trait.
+        let trait_entry_ident = Ident::new(
+            &trait_entry_name(&trait_ident),
+            proc_macro2::Span::call_site(),
+        );
+        quote! {
+            #[doc(hidden)]
+            #[allow(non_upper_case_globals)]
+            pub(crate) static #trait_entry_ident: plugin_interface::TraitDescription =
+                #trait_description;
+        }
+    } else {
+        quote! {
+            inventory::submit! { #trait_description }
+        }
+    };
+    // Lets a host this crate is linked into hand it the context without
+    // calling register_all. Only hosts iterating `inventory` look for it.
+    let host_context_slot = if EXPLICIT_REGISTRATION {
+        quote! {}
+    } else {
+        quote! {
+            extern "C" fn #store_host_context_ident(host: *const plugin_interface::HostContext) {
+                crate::HOST_CONTEXT.store(host as *mut _, std::sync::atomic::Ordering::SeqCst);
+            }
 
-    let input_item: syn::Item = syn::parse(item).expect("failed to parse input item");
+            inventory::submit! {
+                plugin_interface::HostContextSlot {
+                    trait_name: #trait_name_c.as_ptr() as *const std::os::raw::c_char,
+                    store: #store_host_context_ident,
+                }
+            }
+        }
+    };
+
+    let mut input_item: syn::Item = syn::parse(item).expect("failed to parse input item");
+    // Only the last `#[plugin_aggregates]` on the item emits the crate-wide
+    // exports; earlier ones hand their `requires` down to it.
+    let later = item_attrs(&mut input_item).and_then(|attrs| {
+        attrs.iter_mut().rev().find(|attr| {
+            attr.path()
+                .segments
+                .last()
+                .is_some_and(|seg| seg.ident == "plugin_aggregates")
+        })
+    });
+    let crate_exports = match later {
+        Some(attr) => {
+            if let (Some(requires), syn::Meta::List(list)) = (&args.requires, &mut attr.meta) {
+                let ends_with_comma = matches!(
+                    list.tokens.clone().into_iter().last(),
+                    Some(proc_macro2::TokenTree::Punct(p)) if p.as_char() == ','
+                );
+                if !ends_with_comma {
+                    list.tokens.extend(quote! { , });
+                }
+                list.tokens.extend(quote! { requires = (#requires) });
+            }
+            quote! {}
+        }
+        None => crate_exports(args.requires.as_ref()),
+    };
+    if ABI_STABLE {
+        // The module `crate_exports` exports serves every trait.
+        return TokenStream::from(quote! {
+            #input_item
+            #crate_exports
+        });
+    }
     let expanded = quote! {
     #input_item
+    #crate_exports
+
+    // Refuse ABI levels the plugin-interface version in use cannot load.
+    const _: () = assert!(
+        #abi_lit >= plugin_interface::MIN_PLUGIN_ABI && #abi_lit <= plugin_interface::MAX_PLUGIN_ABI,
+        "unsupported plugin ABI level"
+    );
+
+    // Lifecycle counters the `#[plugin_impl]` code for the trait updates,
+    // read by the host through the getter below.
+    static #counters_static: plugin_interface::AtomicPluginCounters =
+        plugin_interface::AtomicPluginCounters::new();
 
-    // Crate-level private counter that unmakers will increment. We emit a
-    // `no_mangle` extern "C" getter that returns the counter value as `u64`.
-    // We use `AtomicU64` for a fixed-width, cross-platform integer size.
-    static UNMAKER_COUNTER: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+    // Hands the counters to the optional and context entries' trampolines.
+    #[doc(hidden)]
+    pub(crate) struct #counters_source;
+
+    impl plugin_interface::CountersSource for #counters_source {
+        fn counters() -> Option<&'static plugin_interface::AtomicPluginCounters> {
+            Some(&#counters_static)
+        }
+    }
 
+    #[no_mangle]
+    pub extern "C" fn #counters_ident() -> plugin_interface::PluginCounters {
+        #counters_static.load()
+    }
+
+    // The released registrations alone, for hosts that predate the
+    // counters getter.
     #[no_mangle]
     pub extern "C" fn #getter_ident() -> u64 {
-        // Return the current counter value (atomic load as u64).
-        UNMAKER_COUNTER.load(std::sync::atomic::Ordering::SeqCst)
+        #counters_static.load().registrations_destroyed
+    }
+
+    #host_context_slot
+
+    /// Record the host `version` register_all or list_impls got; true if
+    /// the crate refuses to register the trait with it.
+    #[allow(unused_variables)]
+    fn #refuses_host_ident(version: *const plugin_interface::HostVersion) -> bool {
+        #accept_host
+    }
+
+    #[no_mangle]
+    pub extern "C" fn #selftest_ident() -> u32 {
+        // The instances made here were never registered; the counters
+        // leave them out.
+        let before = #counters_static.load();
+        let mut failed = 0u32;
+        for factory in #factories {
+            let tn = unsafe { std::ffi::CStr::from_ptr(factory.trait_name) };
+            if tn.to_str() != Ok(#trait_name_lit) {
+                continue;
+            }
+            let r = (factory.maker)();
+            if r.is_null() {
+                failed += 1;
+                continue;
+            }
+            let health = std::panic::catch_unwind(|| unsafe {
+                <plugin_interface::#registration_ident as plugin_interface::DynamicRegistration>::call(
+                    r as *const plugin_interface::#registration_ident,
+                    "health",
+                    &[],
+                )
+            });
+            let unhealthy = plugin_interface::HealthStatus::Unhealthy.to_string();
+            match health {
+                Ok(Ok(Some(status))) if status == unhealthy => failed += 1,
+                Ok(Ok(_)) | Ok(Err(plugin_interface::PluginCallError::Unsupported)) => {}
+                _ => failed += 1,
+            }
+            (factory.unmaker)(r);
+        }
+        #counters_static.forget_since(&before);
+        failed
+    }
+
+    // The crate's implementations of the trait, with the names they
+    // register under, so hosts can make only some of them. A host about to
+    // do so passes its context, as it would to register_all.
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    #[no_mangle]
+    pub extern "C" fn #list_impls_ident(
+        host: *const plugin_interface::HostContext,
+        #version_param
+        count: *mut usize,
+    ) -> *const *const plugin_interface::RegistrationFactory {
+        if #refuses_host_ident(#version_arg) {
+            if !count.is_null() {
+                unsafe { *count = 0 };
+            }
+            return std::ptr::null();
+        }
+        if !host.is_null() {
+            crate::HOST_CONTEXT.store(host as *mut _, std::sync::atomic::Ordering::SeqCst);
+            for hook in #hooks {
+                (hook.run)(host);
+            }
+        }
+        static IMPLS: plugin_interface::ImplTable = plugin_interface::ImplTable::new();
+        let impls = IMPLS.get_or_init(|| {
+            #factories
+                .into_iter()
+                .filter(|factory| {
+                    let tn = unsafe { std::ffi::CStr::from_ptr(factory.trait_name) };
+                    tn.to_str() == Ok(#trait_name_lit)
+                })
+                .collect()
+        });
+        if !count.is_null() {
+            unsafe { *count = impls.len() };
+        }
+        impls.as_ptr()
+    }
+
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    #[no_mangle]
+    pub extern "C" fn #call_dynamic_ident(
+        registration: *const std::ffi::c_void,
+        method: *const std::os::raw::c_char,
+        args_json: *const std::os::raw::c_char,
+    ) -> *const std::os::raw::c_char {
+        let result = unsafe {
+            plugin_interface::call_dynamic_json::<plugin_interface::#registration_ident>(
+                registration,
+                method,
+                args_json,
+            )
+        };
+        #return_string_ident(&result)
+    }
+
+    #trait_entry
+
+    // Strings the `#[plugin_impl]` wrappers for the trait return: allocated
+    // with the host's allocator from `HOST_ALLOCATOR_ABI` on, so the host
+    // frees them with the same C runtime.
+    #[doc(hidden)]
+    #[allow(dead_code)]
+    pub(crate) fn #return_string_ident(s: &str) -> *const std::os::raw::c_char {
+        const HOST_ALLOCATES: bool = #abi_lit >= plugin_interface::HOST_ALLOCATOR_ABI;
+        let allocator = if HOST_ALLOCATES {
+            crate::host_context().and_then(|host| host.allocator())
+        } else {
+            None
+        };
+        plugin_interface::export_string(allocator, s)
     }
 
     #[no_mangle]
-    pub extern "C" fn #register_all_ident() -> *const plugin_interface::RegistrationArray {
+    pub extern "C" fn #register_all_ident(
+        #host_param
+        #version_param
+    ) -> *const plugin_interface::RegistrationArray {
+            if #refuses_host_ident(#version_arg) {
+                return std::ptr::null();
+            }
+            #take_host
             unsafe {
                 let mut regs: Vec<*const std::ffi::c_void> = Vec::new();
                 let mut factories: Vec<*const plugin_interface::RegistrationFactory> = Vec::new();
-                for factory in inventory::iter::<plugin_interface::RegistrationFactory> {
+                for factory in #factories {
                     // Filter by the trait name
                     let tn = std::ffi::CStr::from_ptr(factory.trait_name);
                     if let Ok(s) = tn.to_str() {
@@ -408,7 +1852,7 @@ pub fn plugin_aggregates(attr: TokenStream, item: TokenStream) -> TokenStream {
                 let factories_box = factories.into_boxed_slice();
                 let factories_ptr = Box::into_raw(factories_box) as *const *const plugin_interface::RegistrationFactory;
 
-                let arr = Box::new(plugin_interface::RegistrationArray { count, registrations: regs_ptr, factories: factories_ptr });
+                let arr = Box::new(plugin_interface::RegistrationArray::new(count, regs_ptr, factories_ptr));
                 Box::into_raw(arr)
             }
         }
@@ -419,41 +1863,24 @@ pub fn plugin_aggregates(attr: TokenStream, item: TokenStream) -> TokenStream {
             unsafe {
                 let arr_box: Box<plugin_interface::RegistrationArray> = Box::from_raw(arr_ptr as *mut _);
                 let regs_ptr = arr_box.registrations as *mut *const std::ffi::c_void;
-                let count = arr_box.count as usize;
-                if !regs_ptr.is_null() && count > 0 {
-                    let slice = std::slice::from_raw_parts_mut(regs_ptr, count);
-                    let boxed_slice: Box<[*const std::ffi::c_void]> = Box::from_raw(slice as *mut [_]);
-
-                    // For each registration pointer we need to call the corresponding
-                    // unmaker function. We find unmakers by iterating the collected
-                    // RegistrationFactory entries and matching the trait_name; for each
-                    // factory we call its unmaker on the registrations it contributed.
-                    // This relies on plugin authors arranging that their maker returns
-                    // registrations that their unmaker understands.
-                    let mut idx = 0usize;
-                    for &r in boxed_slice.iter() {
-                        if r.is_null() { idx += 1; continue; }
-
-                        // Find the next factory that matches this trait and call its unmaker.
-                        // In most cases there will be a one-to-one ordering between factories
-                        // and registrations as produced by register_all; we conservatively
-                        // scan factories and call unmaker for each registration matching the trait.
-                        for factory in inventory::iter::<plugin_interface::RegistrationFactory> {
-                            let tn = std::ffi::CStr::from_ptr(factory.trait_name);
-                            if let Ok(s) = tn.to_str() {
-                                if s == #trait_name_lit {
-                                    (factory.unmaker)(r);
-                                    break;
-                                }
-                            }
-                        }
+                let factories_ptr = arr_box.factories as *mut *const plugin_interface::RegistrationFactory;
+                let count = arr_box.count;
+                if regs_ptr.is_null() || factories_ptr.is_null() || count == 0 {
+                    return;
+                }
+                let regs: Box<[*const std::ffi::c_void]> =
+                    Box::from_raw(std::ptr::slice_from_raw_parts_mut(regs_ptr, count));
+                let factories: Box<[*const plugin_interface::RegistrationFactory]> =
+                    Box::from_raw(std::ptr::slice_from_raw_parts_mut(factories_ptr, count));
 
-                        idx += 1;
+                // register_all recorded the factory that made each
+                // registration at the same index; only its unmaker knows
+                // how to release it.
+                for (&r, &factory) in regs.iter().zip(factories.iter()) {
+                    if r.is_null() || factory.is_null() {
+                        continue;
                     }
-
-                    // boxed_slice was allocated by register_all; drop it now to avoid leak.
-                    // The individual registrations are freed by the unmaker calls above.
-                    drop(boxed_slice);
+                    ((*factory).unmaker)(r);
                 }
             }
         }
@@ -461,3 +1888,318 @@ pub fn plugin_aggregates(attr: TokenStream, item: TokenStream) -> TokenStream {
 
     TokenStream::from(expanded)
 }
+
+/// The items a plugin crate exports once, whatever traits it aggregates:
+/// `host_context()`, `host_features()` and `host_version()` with the statics
+/// behind them, the handshake, the register error, the leak report and the
+/// describe document. `requires` is what the handshake returns.
+fn crate_exports(requires: Option<&syn::Expr>) -> proc_macro2::TokenStream {
+    if ABI_STABLE {
+        // One module serves every trait.
+        return quote! {
+            plugin_abi_stable::export_module!();
+
+            /// abi_stable modules are not handed a host context; always
+            /// `None`, so plugin code builds under either backend.
+            #[allow(dead_code)]
+            pub(crate) fn host_context() -> Option<&'static plugin_interface::HostContext> {
+                None
+            }
+
+            /// Nor do they take part in the handshake; always `None`.
+            #[allow(dead_code)]
+            pub(crate) fn host_features() -> Option<plugin_interface::Features> {
+                None
+            }
+
+            /// Nor are they told the host version; always `None`.
+            #[allow(dead_code)]
+            pub(crate) fn host_version() -> Option<plugin_interface::HostVersion> {
+                None
+            }
+        };
+    }
+
+    let leak_report_ident = Ident::new(symbols::LEAK_REPORT, proc_macro2::Span::call_site());
+    let describe_ident = Ident::new(symbols::DESCRIBE, proc_macro2::Span::call_site());
+    let handshake_ident = Ident::new(symbols::HANDSHAKE, proc_macro2::Span::call_site());
+    let register_error_ident = Ident::new(symbols::REGISTER_ERROR, proc_macro2::Span::call_site());
+    let requires = match requires {
+        Some(requires) => quote! { #requires },
+        None => quote! { plugin_interface::Features::NONE },
+    };
+    let describe = if EXPLICIT_REGISTRATION {
+        quote! {
+            |d| plugin_interface::describe_json_with(
+                d,
+                crate::__plugin_traits(),
+                crate::__plugin_descriptions(),
+            )
+        }
+    } else {
+        quote! { plugin_interface::describe_json }
+    };
+
+    quote! {
+    // Host context handed to register_all; null until the host registers
+    // the plugin (or when an older host passes none).
+    static HOST_CONTEXT: std::sync::atomic::AtomicPtr<plugin_interface::HostContext> =
+        std::sync::atomic::AtomicPtr::new(std::ptr::null_mut());
+
+    /// The host context this plugin was registered with, for logging and
+    /// configuration lookups. `None` before registration.
+    #[allow(dead_code)]
+    pub(crate) fn host_context() -> Option<&'static plugin_interface::HostContext> {
+        unsafe { HOST_CONTEXT.load(std::sync::atomic::Ordering::SeqCst).as_ref() }
+    }
+
+    // Features the host offered in the handshake; `None` until it ran.
+    static HOST_FEATURES: std::sync::Mutex<Option<plugin_interface::Features>> =
+        std::sync::Mutex::new(None);
+
+    // Called by the host before it registers the crate: remember what it
+    // offers and tell it what the crate requires.
+    #[no_mangle]
+    pub extern "C" fn #handshake_ident(host_features: u64) -> u64 {
+        *HOST_FEATURES.lock().unwrap_or_else(|e| e.into_inner()) =
+            Some(plugin_interface::Features(host_features));
+        let requires: plugin_interface::Features = #requires;
+        requires.bits()
+    }
+
+    /// The features the host offered before registering this plugin, so it
+    /// can skip what the host lacks. `None` with hosts that predate the
+    /// handshake and hosts the crate is linked into.
+    #[allow(dead_code)]
+    pub(crate) fn host_features() -> Option<plugin_interface::Features> {
+        *HOST_FEATURES.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // The host version a register_all or list_impls was last called with,
+    // and why the crate refused it, if it did.
+    #[allow(dead_code)]
+    static HOST_VERSION: std::sync::Mutex<Option<plugin_interface::HostVersion>> =
+        std::sync::Mutex::new(None);
+    #[allow(dead_code)]
+    static REGISTER_ERROR: std::sync::Mutex<Option<std::ffi::CString>> =
+        std::sync::Mutex::new(None);
+
+    #[no_mangle]
+    pub extern "C" fn #register_error_ident() -> *const std::os::raw::c_char {
+        REGISTER_ERROR
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_ref()
+            .map_or(std::ptr::null(), |e| e.as_ptr())
+    }
+
+    /// The version of the host that registered this plugin. `None` before
+    /// registration, with hosts that predate `HostVersion` and for traits
+    /// aggregated below `abi = 5`.
+    #[allow(dead_code)]
+    pub(crate) fn host_version() -> Option<plugin_interface::HostVersion> {
+        *HOST_VERSION.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // What the `#[plugin_impl]` code allocated and released, with the
+    // `leak-check` feature of `plugin-interface`; false without it.
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    #[no_mangle]
+    pub extern "C" fn #leak_report_ident(out: *mut plugin_interface::LeakReport) -> bool {
+        unsafe { plugin_interface::write_leak_report(out) }
+    }
+
+    #[no_mangle]
+    pub extern "C" fn #describe_ident() -> *const std::os::raw::c_char {
+        static DESCRIPTION: std::sync::OnceLock<std::ffi::CString> = std::sync::OnceLock::new();
+        DESCRIPTION
+            .get_or_init(|| {
+                let describe: fn(&plugin_interface::PluginDescription<'_>) -> String = #describe;
+                let json = describe(&plugin_interface::PluginDescription {
+                    package: env!("CARGO_PKG_NAME"),
+                    version: env!("CARGO_PKG_VERSION"),
+                });
+                std::ffi::CString::new(json).unwrap_or_default()
+            })
+            .as_ptr()
+    }
+    }
+}
+
+/// The attributes of `item`, for the kinds of item that have them.
+fn item_attrs(item: &mut syn::Item) -> Option<&mut Vec<syn::Attribute>> {
+    match item {
+        syn::Item::Const(i) => Some(&mut i.attrs),
+        syn::Item::Enum(i) => Some(&mut i.attrs),
+        syn::Item::ExternCrate(i) => Some(&mut i.attrs),
+        syn::Item::Fn(i) => Some(&mut i.attrs),
+        syn::Item::ForeignMod(i) => Some(&mut i.attrs),
+        syn::Item::Impl(i) => Some(&mut i.attrs),
+        syn::Item::Macro(i) => Some(&mut i.attrs),
+        syn::Item::Mod(i) => Some(&mut i.attrs),
+        syn::Item::Static(i) => Some(&mut i.attrs),
+        syn::Item::Struct(i) => Some(&mut i.attrs),
+        syn::Item::Trait(i) => Some(&mut i.attrs),
+        syn::Item::TraitAlias(i) => Some(&mut i.attrs),
+        syn::Item::Type(i) => Some(&mut i.attrs),
+        syn::Item::Union(i) => Some(&mut i.attrs),
+        syn::Item::Use(i) => Some(&mut i.attrs),
+        _ => None,
+    }
+}
+
+/// `#[plugin_logging]` (or `#[plugin_logging("name")]`) bridges the `log` crate
+/// to the host: it declares a `plugin_interface::PluginLogger` tagged with the
+/// plugin name (defaulting to the crate name) and installs it as the crate's
+/// global logger when the host calls `plugin_register_all_*`, so `log::info!`
+/// inside the plugin ends up in the host's logger. Apply it once to any item at
+/// the crate root; the crate needs `plugin-interface` with its `log` feature.
+///
+/// Under the `explicit-registration` backend the hook is the static
+/// `PLUGIN_LOGGING_HOOK`, which `collect_registrations!` has to list.
+#[proc_macro_attribute]
+pub fn plugin_logging(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let name = if attr.is_empty() {
+        quote! { env!("CARGO_PKG_NAME") }
+    } else {
+        let lit = parse_macro_input!(attr as syn::LitStr);
+        quote! { #lit }
+    };
+    let input_item: syn::Item = syn::parse(item).expect("failed to parse input item");
+    let hook = if EXPLICIT_REGISTRATION {
+        quote! {
+            pub(crate) static PLUGIN_LOGGING_HOOK: plugin_interface::RegisterHook =
+                plugin_interface::RegisterHook { run: __plugin_logging_install };
+        }
+    } else {
+        quote! {
+            inventory::submit! {
+                plugin_interface::RegisterHook { run: __plugin_logging_install }
+            }
+        }
+    };
+
+    let expanded = quote! {
+        #input_item
+
+        static PLUGIN_LOGGER: plugin_interface::PluginLogger =
+            plugin_interface::PluginLogger::new(#name);
+
+        extern "C" fn __plugin_logging_install(host: *const plugin_interface::HostContext) {
+            PLUGIN_LOGGER.install(host);
+        }
+
+        #hook
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// `collect_registrations!(Trait: TypeA, TypeB)` lists the `#[plugin_impl]`
+/// implementations of `Trait` a crate registers under the
+/// `explicit-registration` feature, which replaces the `inventory`
+/// submissions of the other macros for targets where its constructors do not
+/// run. Types are named as their `impl` blocks name them, prefixed with the
+/// module the `impl` is in if that is not the crate root. Register hooks
+/// follow after `; hooks:`, e.g. `; hooks: PLUGIN_LOGGING_HOOK` for
+/// `#[plugin_logging]`.
+///
+/// Invoke it once at the crate root. Without the feature it expands to
+/// nothing, so a crate can build either way.
+#[proc_macro]
+pub fn collect_registrations(input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(input as CollectArgs);
+    if !EXPLICIT_REGISTRATION {
+        return TokenStream::new();
+    }
+    let trait_name = args.trait_ident.to_string();
+    let entries = |kind: &str| -> Vec<proc_macro2::TokenStream> {
+        args.types
+            .iter()
+            .map(|ty| {
+                let mut path = ty.clone();
+                let last = path.segments.last_mut().expect("paths have a segment");
+                let safe_name = last.ident.to_string();
+                last.ident = Ident::new(
+                    &entry_name(kind, &trait_name, &safe_name),
+                    last.ident.span(),
+                );
+                quote! { &#path }
+            })
+            .collect()
+    };
+    let factories = entries("FACTORY");
+    let descriptions = entries("DESCRIPTION");
+    let trait_entry = Ident::new(&trait_entry_name(&trait_name), args.trait_ident.span());
+    let hooks = args.hooks.iter().map(|hook| quote! { &#hook });
+    let count = factories.len();
+    let hook_count = args.hooks.len();
+
+    let expanded = quote! {
+        #[doc(hidden)]
+        pub(crate) fn __plugin_factories() -> &'static [&'static plugin_interface::RegistrationFactory] {
+            static FACTORIES: [&plugin_interface::RegistrationFactory; #count] = [#(#factories),*];
+            &FACTORIES
+        }
+
+        #[doc(hidden)]
+        pub(crate) fn __plugin_traits() -> &'static [&'static plugin_interface::TraitDescription] {
+            static TRAITS: [&plugin_interface::TraitDescription; 1] = [&#trait_entry];
+            &TRAITS
+        }
+
+        #[doc(hidden)]
+        pub(crate) fn __plugin_descriptions() -> &'static [&'static plugin_interface::RegistrationDescription] {
+            static DESCRIPTIONS: [&plugin_interface::RegistrationDescription; #count] = [#(#descriptions),*];
+            &DESCRIPTIONS
+        }
+
+        #[doc(hidden)]
+        pub(crate) fn __plugin_register_hooks() -> &'static [&'static plugin_interface::RegisterHook] {
+            static HOOKS: [&plugin_interface::RegisterHook; #hook_count] = [#(#hooks),*];
+            &HOOKS
+        }
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// Arguments of `collect_registrations!(Trait: Type, ...; hooks: HOOK, ...)`.
+struct CollectArgs {
+    trait_ident: Ident,
+    types: Vec<syn::Path>,
+    hooks: Vec<syn::Path>,
+}
+
+impl syn::parse::Parse for CollectArgs {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let trait_ident: Ident = input.parse()?;
+        input.parse::<syn::Token![:]>()?;
+        let mut types = Vec::new();
+        while !input.is_empty() && !input.peek(syn::Token![;]) {
+            types.push(input.parse()?);
+            if input.parse::<Option<syn::Token![,]>>()?.is_none() {
+                break;
+            }
+        }
+        let mut hooks = Vec::new();
+        if input.parse::<Option<syn::Token![;]>>()?.is_some() && !input.is_empty() {
+            let key: Ident = input.parse()?;
+            if key != "hooks" {
+                return Err(syn::Error::new(key.span(), "expected `hooks: ...`"));
+            }
+            input.parse::<syn::Token![:]>()?;
+            let list =
+                syn::punctuated::Punctuated::<syn::Path, syn::Token![,]>::parse_terminated(input)?;
+            hooks.extend(list);
+        }
+        if !input.is_empty() {
+            return Err(input.error("expected `,`, `;` or the end of the list"));
+        }
+        Ok(Self {
+            trait_ident,
+            types,
+            hooks,
+        })
+    }
+}
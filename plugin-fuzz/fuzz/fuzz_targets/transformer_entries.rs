@@ -0,0 +1,14 @@
+//! Feed every input through each vtable entry of the Transformer plugin named by
+//! `PLUGIN_FUZZ_PATH`.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use plugin_fuzz::{FuzzHarness, PluginTrait};
+
+thread_local! {
+    static HARNESS: FuzzHarness = FuzzHarness::from_env(PluginTrait::Transformer);
+}
+
+fuzz_target!(|data: &[u8]| {
+    HARNESS.with(|harness| harness.run(data));
+});
@@ -0,0 +1,61 @@
+//! Fuzzing support for plugin libraries: `FuzzHarness` loads a library and
+//! feeds each input through every vtable entry of its registrations,
+//! checking what the generated FFI wrappers return with
+//! `plugin_interface::fuzz_entries`. The cargo-fuzz targets in `fuzz/` run
+//! it on the library named by `PLUGIN_FUZZ_PATH`.
+
+use plugin_interface::{fuzz_entries, PluginHandle, PluginLoadError, PluginManager};
+use std::path::Path;
+
+pub use plugin_interface::{FuzzViolation, PluginTrait};
+
+/// The environment variable `FuzzHarness::from_env` reads the library path
+/// from.
+pub const PATH_VAR: &str = "PLUGIN_FUZZ_PATH";
+
+/// A loaded plugin library and its registrations, ready to fuzz.
+pub struct FuzzHarness {
+    // Keeps the library loaded for as long as the handles are used.
+    _manager: PluginManager,
+    handles: Vec<PluginHandle>,
+}
+
+impl FuzzHarness {
+    /// Load the library at `path` as a provider of `trait_id`.
+    pub fn load(path: impl AsRef<Path>, trait_id: PluginTrait) -> Result<Self, PluginLoadError> {
+        let mut manager = PluginManager::new();
+        let handles = manager.load_library(path.as_ref(), trait_id)?;
+        Ok(Self {
+            _manager: manager,
+            handles,
+        })
+    }
+
+    /// Load the library named by `PLUGIN_FUZZ_PATH`. Panics if the variable
+    /// is not set or the library does not load, as a fuzz target cannot
+    /// run without it.
+    pub fn from_env(trait_id: PluginTrait) -> Self {
+        let path = std::env::var_os(PATH_VAR)
+            .unwrap_or_else(|| panic!("set {} to the plugin library to fuzz", PATH_VAR));
+        Self::load(&path, trait_id).unwrap_or_else(|e| panic!("cannot load {:?}: {:?}", path, e))
+    }
+
+    pub fn handles(&self) -> &[PluginHandle] {
+        &self.handles
+    }
+
+    /// Feed `input` through every registration, returning the first
+    /// violation found.
+    pub fn check(&self, input: &[u8]) -> Result<(), FuzzViolation> {
+        self.handles
+            .iter()
+            .try_for_each(|handle| fuzz_entries(handle, input))
+    }
+
+    /// `check`, panicking on a violation so the fuzzer records the input.
+    pub fn run(&self, input: &[u8]) {
+        if let Err(violation) = self.check(input) {
+            panic!("{}: {}", self.handles[0].path().display(), violation);
+        }
+    }
+}
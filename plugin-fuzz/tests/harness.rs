@@ -0,0 +1,47 @@
+use plugin_fuzz::{FuzzHarness, PluginTrait};
+use plugin_test_support::built_plugin;
+
+/// Inputs covering empty and long strings, inner nul bytes, invalid UTF-8
+/// and, read as a request context, cancelled and uncancelled requests.
+const INPUTS: [&[u8]; 6] = [
+    b"",
+    b"hello",
+    b"nul\0inside",
+    b"\xff\xfe not utf-8 \xc3",
+    "ünïcødé ✓".as_bytes(),
+    &[0x7b; 4096],
+];
+
+#[test]
+fn generated_wrappers_hold_up_to_arbitrary_input() {
+    let Some(lib) = built_plugin("plugin_upper") else {
+        return;
+    };
+
+    assert!(FuzzHarness::load(&lib, PluginTrait::Greeter).is_err());
+    let harness = FuzzHarness::load(&lib, PluginTrait::Transformer).expect("load");
+    assert_eq!(harness.handles().len(), 1);
+    for input in INPUTS {
+        assert_eq!(harness.check(input), Ok(()), "input {:?}", input);
+    }
+    let proxy = harness.handles()[0].as_transformer().expect("proxy");
+    assert_eq!(
+        proxy.try_transform("still works").as_deref(),
+        Ok("STILL WORKS")
+    );
+}
+
+#[test]
+fn greeter_entries_hold_up_to_arbitrary_input() {
+    let Some(lib) = built_plugin("plugin_pair") else {
+        return;
+    };
+
+    let harness = FuzzHarness::load(&lib, PluginTrait::Greeter).expect("load");
+    assert_eq!(harness.handles().len(), 1);
+    for input in INPUTS {
+        assert_eq!(harness.check(input), Ok(()), "input {:?}", input);
+    }
+    let proxy = harness.handles()[0].as_greeter().expect("proxy");
+    assert_eq!(proxy.try_name().as_deref(), Ok("Hello"));
+}
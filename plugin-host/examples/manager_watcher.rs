@@ -9,18 +9,16 @@ fn main() -> Result<(), Box<dyn Error>> {
     let opts = WatchOptions {
         auto_load: true,
         auto_unload: true,
-        emit_proxies: false,
         ..Default::default()
     };
 
     println!("Starting conservative background watcher for {:?}", dir);
     // start background watcher using a cloned options value created inline
-    let (rx, stop_tx, _join) = mgr.start_watch_background(
+    let (rx, watcher) = mgr.start_watch_background(
         dir.to_path_buf(),
         WatchOptions {
             auto_load: true,
             auto_unload: true,
-            emit_proxies: false,
             ..Default::default()
         },
     );
@@ -36,18 +34,18 @@ fn main() -> Result<(), Box<dyn Error>> {
                 println!("manager event: {:?}", ev);
                 true
             }
-            plugin_interface::ManagerNotification::Unloaded { path, counter } => {
-                println!("unloaded {:?} -> {:?}", path, counter);
-                true
-            }
             plugin_interface::ManagerNotification::Error(e) => {
                 eprintln!("watch error: {}", e);
                 true
             }
+            other => {
+                println!("manager notification: {:?}", other);
+                true
+            }
         },
     );
 
-    // stop background watcher
-    let _ = stop_tx.send(());
+    // stop background watcher and wait for its thread
+    let _ = watcher.stop();
     Ok(())
 }
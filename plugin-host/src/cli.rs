@@ -0,0 +1,185 @@
+//! The `plugin-host` command line: global options, subcommands, and
+//! running them against a `Host`.
+
+use crate::host::{parse_trait, Host, HostConfig};
+use plugin_interface::PluginDescriptor;
+use std::io::Write;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+usage: plugin-host [--dir <dir>]... [--trait <name>] [<command>]
+
+Loads the plugins in every --dir, then runs <command>. Without one, reads
+commands from stdin, one per line, until `quit`, end of input or ctrl-c.
+Plugins are unloaded in dependency order before exiting.
+
+commands:
+  list                             describe the loaded plugins
+  load <path>                      load a plugin library or a directory
+  call <plugin> <method> [args]    call a method, e.g. `call MyGreeter greet world`
+  unload <plugin>                  unload a plugin
+  watch <dir>                      load, reload and unload as files change
+  doctor <dir>                     check the plugins in a directory without loading them
+  help                             show this text
+  quit                             stop reading commands";
+
+/// One subcommand. Plugins are named by registration name, plugin name or
+/// path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    List,
+    Load(PathBuf),
+    Call {
+        plugin: String,
+        method: String,
+        args: Vec<String>,
+    },
+    Unload(String),
+    Watch(PathBuf),
+    Doctor(PathBuf),
+    Help,
+    Quit,
+}
+
+impl Command {
+    /// Parse a command from its words, for example
+    /// `["call", "MyGreeter", "greet", "world"]`.
+    pub fn parse<I, S>(words: I) -> Result<Self, String>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut words = words.into_iter().map(Into::into);
+        let Some(name) = words.next() else {
+            return Err("missing command".to_string());
+        };
+        let mut arg = |what: &str| {
+            words
+                .next()
+                .ok_or_else(|| format!("`{}` needs {}", name, what))
+        };
+        let command = match name.as_str() {
+            "list" => Command::List,
+            "load" => Command::Load(arg("a path")?.into()),
+            "call" => Command::Call {
+                plugin: arg("a plugin")?,
+                method: arg("a method")?,
+                args: words.by_ref().collect(),
+            },
+            "unload" => Command::Unload(arg("a plugin")?),
+            "watch" => Command::Watch(arg("a directory")?.into()),
+            "doctor" => Command::Doctor(arg("a directory")?.into()),
+            "help" => Command::Help,
+            "quit" | "exit" => Command::Quit,
+            _ => return Err(format!("unknown command {:?}", name)),
+        };
+        if let Some(extra) = words.next() {
+            return Err(format!("unexpected argument {:?}", extra));
+        }
+        Ok(command)
+    }
+}
+
+/// Parse the process arguments (without the program name) into the host
+/// configuration and the command to run, if any.
+pub fn parse_args<I, S>(args: I) -> Result<(HostConfig, Option<Command>), String>
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    let mut config = HostConfig::default();
+    let mut args = args.into_iter().map(Into::into).peekable();
+    while let Some(option) = args.next_if(|a| a.starts_with("--")) {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("`{}` needs a value", option))
+        };
+        match option.as_str() {
+            "--dir" => config.dirs.push(value()?.into()),
+            "--trait" => config.trait_id = parse_trait(&value()?)?,
+            "--help" => return Ok((config, Some(Command::Help))),
+            _ => return Err(format!("unknown option {:?}", option)),
+        }
+    }
+    let command = match args.peek() {
+        Some(_) => Some(Command::parse(args)?),
+        None => None,
+    };
+    Ok((config, command))
+}
+
+/// Run `command` against `host`, writing its output to `out`. Errors are
+/// returned as messages for the caller to report.
+pub fn run(host: &mut Host, command: Command, out: &mut dyn Write) -> Result<(), String> {
+    let io = |e: std::io::Error| e.to_string();
+    match command {
+        Command::List => {
+            for plugin in host.list() {
+                describe(&plugin, out).map_err(io)?;
+            }
+        }
+        Command::Load(path) => {
+            let loaded = host.load(&path).map_err(|e| format!("{:?}", e))?;
+            for plugin in &loaded {
+                describe(plugin, out).map_err(io)?;
+            }
+        }
+        Command::Call {
+            plugin,
+            method,
+            args,
+        } => {
+            let answer = host
+                .call(&plugin, &method, &args)
+                .map_err(|e| e.to_string())?;
+            if !answer.is_empty() {
+                writeln!(out, "{}", answer).map_err(io)?;
+            }
+        }
+        Command::Unload(plugin) => {
+            let counter = host.unload(&plugin).map_err(|e| e.to_string())?;
+            match counter {
+                Some(counter) => writeln!(out, "unloaded {} (counter {})", plugin, counter),
+                None => writeln!(out, "unloaded {}", plugin),
+            }
+            .map_err(io)?;
+        }
+        Command::Watch(dir) => {
+            writeln!(out, "watching {:?} (ctrl-c to stop)", dir).map_err(io)?;
+            host.watch(&dir, |notification| {
+                writeln!(out, "{:?}", notification).is_ok()
+            });
+        }
+        Command::Doctor(dir) => {
+            let report = host.validate(&dir).map_err(io)?;
+            for file in &report.files {
+                match file.abi_version {
+                    Some(abi) => writeln!(out, "{}\tABI {}", file.path.display(), abi),
+                    None => writeln!(out, "{}", file.path.display()),
+                }
+                .map_err(io)?;
+                for problem in &file.problems {
+                    writeln!(out, "  {}", problem).map_err(io)?;
+                }
+            }
+            let failed = report.failures().count();
+            if failed > 0 {
+                return Err(format!("{} file(s) with problems", failed));
+            }
+        }
+        Command::Help => writeln!(out, "{}", USAGE).map_err(io)?,
+        Command::Quit => {}
+    }
+    Ok(())
+}
+
+/// One line per plugin: name, path and registration names.
+fn describe(plugin: &PluginDescriptor, out: &mut dyn Write) -> std::io::Result<()> {
+    writeln!(
+        out,
+        "{}\t{}\t{}",
+        plugin.name,
+        plugin.path.display(),
+        plugin.registrations.join(",")
+    )
+}
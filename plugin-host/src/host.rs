@@ -0,0 +1,266 @@
+//! A `PluginManager` set up from a `HostConfig`, which keeps the plugins it
+//! loads alive and finds them by name or path.
+
+use plugin_interface::{
+    GreeterProxy, ManagerNotification, PluginCallError, PluginDescriptor, PluginHandle,
+    PluginLoadError, PluginManager, PluginTrait, UnloadReport, ValidationReport, WatchEvent,
+    WatchOptions,
+};
+use std::path::{Path, PathBuf};
+
+/// Traits a host can be set up for, by `PluginTrait::as_str` name.
+pub const TRAITS: [PluginTrait; 2] = [PluginTrait::Greeter, PluginTrait::Transformer];
+
+/// The trait in `TRAITS` called `name`, ignoring case.
+pub fn parse_trait(name: &str) -> Result<PluginTrait, String> {
+    TRAITS
+        .into_iter()
+        .find(|t| t.as_str().eq_ignore_ascii_case(name))
+        .ok_or_else(|| format!("unknown plugin trait {:?}", name))
+}
+
+/// Where a `Host` looks for plugins and which trait it loads them as.
+#[derive(Clone)]
+pub struct HostConfig {
+    /// Directories `Host::load_dirs` loads from, in precedence order.
+    pub dirs: Vec<PathBuf>,
+    pub trait_id: PluginTrait,
+    /// Options for `Host::watch`; auto-load and auto-unload by default.
+    pub watch: WatchOptions,
+}
+
+impl Default for HostConfig {
+    fn default() -> Self {
+        Self {
+            dirs: Vec::new(),
+            trait_id: PluginTrait::Greeter,
+            watch: WatchOptions {
+                auto_load: true,
+                auto_unload: true,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+impl HostConfig {
+    pub fn with_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dirs.push(dir.into());
+        self
+    }
+
+    pub fn with_trait(mut self, trait_id: PluginTrait) -> Self {
+        self.trait_id = trait_id;
+        self
+    }
+}
+
+/// Errors from the name-based `Host` operations.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum HostError {
+    /// No loaded plugin has this registration name, plugin name or path.
+    UnknownPlugin(String),
+    /// The host's trait has no method of this name.
+    UnknownMethod(String),
+    /// The plugin was found, but the call failed.
+    Call(PluginCallError),
+    /// `PluginManager::unload_by_path` failed.
+    Unload(String),
+}
+
+impl std::fmt::Display for HostError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HostError::UnknownPlugin(p) => write!(f, "no loaded plugin named {:?}", p),
+            HostError::UnknownMethod(m) => write!(f, "unknown method {:?}", m),
+            HostError::Call(e) => write!(f, "call failed: {}", e),
+            HostError::Unload(e) => write!(f, "unload failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for HostError {}
+
+/// A plugin manager with the handles of everything it loaded, so plugins
+/// stay loaded until `unload` rather than until the caller drops them.
+/// Plugins are named by registration name (`"MyGreeter"`), plugin name
+/// (the manifest or file name) or path.
+pub struct Host {
+    manager: PluginManager,
+    config: HostConfig,
+    handles: Vec<PluginHandle>,
+}
+
+impl Host {
+    pub fn new(config: HostConfig) -> Self {
+        Self::with_manager(PluginManager::new(), config)
+    }
+
+    /// A host around `manager`, for example one with host services or
+    /// trusted keys set.
+    pub fn with_manager(manager: PluginManager, config: HostConfig) -> Self {
+        Self {
+            manager,
+            config,
+            handles: Vec::new(),
+        }
+    }
+
+    pub fn config(&self) -> &HostConfig {
+        &self.config
+    }
+
+    pub fn manager(&mut self) -> &mut PluginManager {
+        &mut self.manager
+    }
+
+    /// Load the configured trait from every configured directory. Returns
+    /// a description of each plugin the call loaded.
+    pub fn load_dirs(&mut self) -> Result<Vec<PluginDescriptor>, PluginLoadError> {
+        if self.config.dirs.is_empty() {
+            return Ok(Vec::new());
+        }
+        let handles = self
+            .manager
+            .load_plugins(self.config.dirs.clone(), self.config.trait_id)?;
+        Ok(self.keep(handles))
+    }
+
+    /// Load the plugins in directory `path`, or the library file `path`.
+    pub fn load(&mut self, path: &Path) -> Result<Vec<PluginDescriptor>, PluginLoadError> {
+        let handles = if path.is_dir() {
+            self.manager.load_plugins(path, self.config.trait_id)?
+        } else {
+            self.manager.load_library(path, self.config.trait_id)?
+        };
+        Ok(self.keep(handles))
+    }
+
+    /// Keep `handles` and describe the plugins they belong to.
+    fn keep(&mut self, handles: Vec<PluginHandle>) -> Vec<PluginDescriptor> {
+        let loaded: Vec<PathBuf> = handles.iter().map(|h| h.path().to_path_buf()).collect();
+        self.handles.extend(handles);
+        self.list()
+            .into_iter()
+            .filter(|d| loaded.contains(&d.path))
+            .collect()
+    }
+
+    pub fn list(&self) -> Vec<PluginDescriptor> {
+        self.manager.list()
+    }
+
+    /// A handle for the plugin `plugin` names: a registration name first,
+    /// then a plugin name or path.
+    pub fn find(&self, plugin: &str) -> Option<PluginHandle> {
+        if let Some(handle) = self.manager.find_by_name(plugin) {
+            return Some(handle);
+        }
+        let path = self
+            .list()
+            .into_iter()
+            .find(|d| d.name == plugin || d.path == Path::new(plugin))?
+            .path;
+        self.handles.iter().find(|h| h.path() == path).cloned()
+    }
+
+    /// A proxy for every loaded Greeter registration, in load order.
+    pub fn greeters(&self) -> impl Iterator<Item = GreeterProxy> + '_ {
+        self.handles.iter().filter_map(PluginHandle::as_greeter)
+    }
+
+    /// Call `method` on the plugin `plugin` names with `args`, and return
+    /// what it answered, empty for methods without a result. Greeter
+    /// plugins take `name` and `greet <target>`, Transformer plugins
+    /// `transform <input>`.
+    pub fn call(&self, plugin: &str, method: &str, args: &[String]) -> Result<String, HostError> {
+        let handle = self
+            .find(plugin)
+            .ok_or_else(|| HostError::UnknownPlugin(plugin.to_string()))?;
+        match handle.trait_id() {
+            PluginTrait::Greeter => {
+                let proxy = handle.as_greeter().expect("Greeter handle");
+                match method {
+                    "name" => proxy.try_name().map_err(HostError::Call),
+                    "greet" => proxy
+                        .try_greet(&args.join(" "))
+                        .map(|()| String::new())
+                        .map_err(HostError::Call),
+                    _ => Err(HostError::UnknownMethod(method.to_string())),
+                }
+            }
+            PluginTrait::Transformer => {
+                let proxy = handle.as_transformer().expect("Transformer handle");
+                match method {
+                    "transform" => proxy
+                        .try_transform(&args.join(" "))
+                        .map_err(HostError::Call),
+                    _ => Err(HostError::UnknownMethod(method.to_string())),
+                }
+            }
+        }
+    }
+
+    /// Let go of the plugin `plugin` names and unload it. Returns the
+    /// unmaker counter, like `PluginManager::unload_by_path`.
+    pub fn unload(&mut self, plugin: &str) -> Result<Option<u64>, HostError> {
+        let handle = self
+            .find(plugin)
+            .ok_or_else(|| HostError::UnknownPlugin(plugin.to_string()))?;
+        let path = handle.path().to_path_buf();
+        drop(handle);
+        self.handles.retain(|h| h.path() != path);
+        self.manager
+            .unload_by_path(&path)
+            .map_err(HostError::Unload)
+    }
+
+    /// Let go of every plugin and shut the manager down: watchers stop and
+    /// plugins are unloaded dependents first, see
+    /// `PluginManager::shutdown`.
+    pub fn shutdown(&mut self) -> Vec<UnloadReport> {
+        self.handles.clear();
+        self.manager.shutdown()
+    }
+
+    /// Check the plugins in `dir` for the configured trait without loading
+    /// them; see `PluginManager::validate`.
+    pub fn validate(&self, dir: &Path) -> std::io::Result<ValidationReport> {
+        self.manager.validate(dir, self.config.trait_id)
+    }
+
+    /// Watch `dir` with the configured options, keeping what the watcher
+    /// loads, until `on_notification` returns false.
+    pub fn watch<F>(&mut self, dir: &Path, mut on_notification: F)
+    where
+        F: FnMut(&ManagerNotification) -> bool,
+    {
+        let (rx, _watcher) = self
+            .manager
+            .start_watch_background(dir.to_path_buf(), self.config.watch.clone());
+        let handles = &mut self.handles;
+        self.manager.process_watch_notifications_blocking(
+            dir,
+            rx,
+            self.config.trait_id,
+            self.config.watch.clone(),
+            |notification| {
+                let keep_going = on_notification(&notification);
+                if let ManagerNotification::Event(event) = notification {
+                    match event {
+                        WatchEvent::Added { handles: new, .. } => handles.extend(new),
+                        WatchEvent::Modified { path, handles: new }
+                        | WatchEvent::Reloaded { path, new, .. } => {
+                            handles.retain(|h| h.path() != path);
+                            handles.extend(new);
+                        }
+                        // lets a deferred auto_unload finish
+                        WatchEvent::Removed { path, .. } => handles.retain(|h| h.path() != path),
+                    }
+                }
+                keep_going
+            },
+        );
+    }
+}
@@ -1,3 +1,9 @@
-pub mod plugin_manager;
+//! A reusable plugin host over `plugin_interface::PluginManager`: `Host`
+//! loads plugins from configured directories and drives them by name, and
+//! `cli` is the command line the `plugin-host` binary runs on top of it.
 
-pub use plugin_manager::PluginManager;
+pub mod cli;
+mod host;
+
+pub use host::{parse_trait, Host, HostConfig, HostError, TRAITS};
+pub use plugin_interface::PluginManager;
@@ -1,44 +1,93 @@
 // plugin-host/src/main.rs
-// Simple example: start the conservative background watcher, then process
-// notifications on the manager-owning thread so the manager performs
-// load/unload actions. Adjust the plugin directory path as needed.
+// Command-line front end to `plugin_host::Host`: loads the plugins in every
+// --dir, then runs one command, or reads commands from stdin, and unloads
+// everything on the way out, also on ctrl-c. See `plugin_host::cli::USAGE`.
 
-use plugin_interface::{PluginManager, PluginTrait, WatchOptions};
-use std::path::PathBuf;
+use plugin_host::cli::{self, Command};
+use plugin_host::Host;
+use plugin_interface::ShutdownSignal;
+use std::io::BufRead;
+use std::process::ExitCode;
+use std::sync::mpsc;
+use std::time::Duration;
 
-fn main() {
-    // Directory to watch - change to your plugins output directory
-    let watch_dir = PathBuf::from("./plugins_out");
-
-    let mut mgr = PluginManager::new();
-
-    let opts = WatchOptions {
-        auto_load: true,
-        auto_unload: true,
-        emit_proxies: false,
-        ..Default::default()
+fn main() -> ExitCode {
+    let (config, command) = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("plugin-host: {}\n\n{}", e, cli::USAGE);
+            return ExitCode::from(2);
+        }
     };
+    let mut host = Host::new(config);
+    // With the handler in place, ctrl-c ends a `watch` or the command loop
+    // and the plugins are unloaded in order instead of the process dying.
+    let shutdown = host
+        .manager()
+        .install_shutdown_handler()
+        .unwrap_or_else(|e| {
+            eprintln!("plugin-host: signals end the process at once: {}", e);
+            host.manager().shutdown_signal()
+        });
+    if let Err(e) = host.load_dirs() {
+        eprintln!("plugin-host: loading plugins failed: {:?}", e);
+        return ExitCode::FAILURE;
+    }
+    let mut out = std::io::stdout();
 
-    // Start background watcher (create a fresh options copy inline)
-    let (rx, stop_tx, _jh) = mgr.start_watch_background(
-        watch_dir.clone(),
-        WatchOptions {
-            auto_load: true,
-            auto_unload: true,
-            emit_proxies: false,
-            ..Default::default()
+    let code = match command {
+        Some(command) => match cli::run(&mut host, command, &mut out) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("plugin-host: {}", e);
+                ExitCode::FAILURE
+            }
         },
-    );
-
-    println!("Started background watcher for {:?}", watch_dir);
+        None => {
+            run_interactive(&mut host, &shutdown, &mut out);
+            ExitCode::SUCCESS
+        }
+    };
+    for report in host.shutdown() {
+        if let Err(e) = report.result {
+            eprintln!("plugin-host: unloading {}: {}", report.path.display(), e);
+        }
+    }
+    code
+}
 
-    // Process events on the manager thread. This will call load_plugins/unload_by_path
-    // as needed and invoke the callback with ManagerNotification values.
-    mgr.process_watch_notifications_blocking(&watch_dir, rx, PluginTrait::Greeter, opts, |note| {
-        println!("manager notification: {:?}", note);
-        true // keep processing
+/// Run the commands read from stdin until `quit`, end of input or a
+/// shutdown request. Lines are read on a thread of their own so a signal
+/// is noticed while waiting for the next one.
+fn run_interactive(host: &mut Host, shutdown: &ShutdownSignal, out: &mut dyn std::io::Write) {
+    let (tx, lines) = mpsc::channel::<String>();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
     });
 
-    // To stop the watcher, send stop signal. (In this example we never reach here.)
-    let _ = stop_tx.send(());
+    while !shutdown.is_requested() {
+        let line = match lines.recv_timeout(Duration::from_millis(100)) {
+            Ok(line) => line,
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.is_empty() {
+            continue;
+        }
+        match Command::parse(words) {
+            Ok(Command::Quit) => break,
+            Ok(command) => {
+                if let Err(e) = cli::run(host, command, out) {
+                    eprintln!("error: {}", e);
+                }
+            }
+            Err(e) => eprintln!("error: {}", e),
+        }
+    }
 }
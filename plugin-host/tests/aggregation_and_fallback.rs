@@ -65,3 +65,27 @@ fn test_fallback_to_single_registration() {
     mgr.call_greet(idx, "fallback").expect("greet");
     mgr.unload_plugin(idx).expect("unload");
 }
+
+#[test]
+fn test_explicit_registration_backend() {
+    use plugin_interface::PluginTrait;
+
+    // plugin-explicit registers through `collect_registrations!` and is
+    // built without `inventory`.
+    build_plugin("plugin-explicit");
+    let path = plugin_path("plugin-explicit");
+    let mut mgr = PluginManager::new();
+    let handles = mgr.load_library(&path, PluginTrait::Greeter).expect("load");
+    let names: Vec<String> = handles
+        .iter()
+        .map(|h| h.as_greeter().unwrap().name())
+        .collect();
+    assert_eq!(names, ["Hello", "Goodbye"]);
+    handles[1].as_greeter().unwrap().greet("explicit");
+    let description = mgr.describe(&path).expect("describe");
+    assert!(
+        description.contains(r#""registrations":["Hello","goodbye"]"#),
+        "{}",
+        description
+    );
+}
@@ -0,0 +1,81 @@
+use plugin_host::cli::{self, Command};
+use plugin_host::{Host, HostConfig};
+use plugin_interface::PluginTrait;
+use plugin_test_support::built_plugin;
+use std::path::PathBuf;
+
+#[test]
+fn parses_options_and_commands() {
+    let (config, command) =
+        cli::parse_args(["--dir", "a", "--dir", "b", "--trait", "greeter", "list"]).unwrap();
+    assert_eq!(config.dirs, [PathBuf::from("a"), PathBuf::from("b")]);
+    assert_eq!(config.trait_id, PluginTrait::Greeter);
+    assert_eq!(command, Some(Command::List));
+
+    let (_, command) = cli::parse_args(["call", "MyGreeter", "greet", "big", "world"]).unwrap();
+    assert_eq!(
+        command,
+        Some(Command::Call {
+            plugin: "MyGreeter".into(),
+            method: "greet".into(),
+            args: vec!["big".into(), "world".into()],
+        })
+    );
+    assert_eq!(cli::parse_args(Vec::<String>::new()).unwrap().1, None);
+
+    assert!(cli::parse_args(["--trait", "Painter"]).is_err());
+    assert!(cli::parse_args(["--dir"]).is_err());
+    assert!(Command::parse(["unload"]).is_err());
+    assert!(Command::parse(["list", "extra"]).is_err());
+    assert!(Command::parse(["frobnicate"]).is_err());
+    assert_eq!(
+        Command::parse(["doctor", "plugins"]),
+        Ok(Command::Doctor("plugins".into()))
+    );
+}
+
+#[test]
+fn runs_commands_against_a_host() {
+    let Some(lib) = built_plugin("plugin_a") else {
+        eprintln!("plugin artifact not found; skipping");
+        return;
+    };
+    let mut host = Host::new(HostConfig::default());
+    let mut out = Vec::new();
+
+    // the target directory holds more than plugins, so doctor may fail
+    let _ = cli::run(
+        &mut host,
+        Command::Doctor(lib.parent().unwrap().into()),
+        &mut out,
+    );
+    let report = String::from_utf8(std::mem::take(&mut out)).unwrap();
+    assert!(
+        report.contains(&format!("{}\tABI 3", lib.display())),
+        "{}",
+        report
+    );
+    assert!(host.list().is_empty());
+
+    cli::run(&mut host, Command::Load(lib.clone()), &mut out).expect("load");
+    let loaded = String::from_utf8(std::mem::take(&mut out)).unwrap();
+    assert!(loaded.contains("MyGreeter"), "{}", loaded);
+    // the host keeps the plugin loaded between commands
+    assert_eq!(host.greeters().count(), 1);
+
+    let call = Command::parse(["call", "MyGreeter", "name"]).unwrap();
+    cli::run(&mut host, call, &mut out).expect("call");
+    assert_eq!(
+        String::from_utf8(std::mem::take(&mut out)).unwrap(),
+        "MyGreeter\n"
+    );
+    let by_path = lib.display().to_string();
+    assert_eq!(host.call(&by_path, "greet", &["host".into()]).unwrap(), "");
+    assert!(host.call("MyGreeter", "shout", &[]).is_err());
+    assert!(host.call("Nobody", "name", &[]).is_err());
+
+    cli::run(&mut host, Command::Unload("MyGreeter".into()), &mut out).expect("unload");
+    assert!(host.list().is_empty());
+    assert_eq!(host.greeters().count(), 0);
+    assert!(cli::run(&mut host, Command::Unload("MyGreeter".into()), &mut out).is_err());
+}
@@ -1,4 +1,4 @@
-use plugin_host::plugin_manager::PluginManager;
+use plugin_host::PluginManager;
 use std::path::PathBuf;
 
 #[test]
@@ -1,8 +1,8 @@
 use std::path::PathBuf;
 
 // This test verifies that plugin-side unmaker code runs by calling the
-// aggregated `plugin_unregister_all_Greeter_v1` helper and then reading the
-// plugin-exported `UNMAKER_COUNTER` static before unloading the library.
+// aggregated `plugin_unregister_all_Greeter_v2` helper and then reading the
+// plugin's lifecycle counters before unloading the library.
 #[test]
 fn unload_and_reload_plugin() {
     // Path to the compiled plugin library (same as before).
@@ -29,26 +29,25 @@ fn unload_and_reload_plugin() {
 
     // Call the plugin's bulk-unregister helper (if present). This will run
     // the generated unregister_all which calls each factory.unmaker and thus
-    // increments the crate's `UNMAKER_COUNTER`.
+    // counts a destroyed registration in the crate's `PluginCounters`.
     unsafe {
         if let Ok(unreg_all) = lib
             .get::<unsafe extern "C" fn(*const plugin_interface::RegistrationArray)>(
-                b"plugin_unregister_all_Greeter_v1",
+                b"plugin_unregister_all_Greeter_v2",
             )
         {
             unreg_all(arr_ptr);
 
-            // Call the versioned getter to obtain the current counter value (u64) and assert > 0
-            if let Ok(getter_sym) =
-                lib.get::<unsafe extern "C" fn() -> u64>(b"plugin_unmaker_counter_Greeter_v1")
-            {
-                let val = getter_sym();
-                assert!(val > 0, "UNMAKER_COUNTER was not incremented by unmaker");
-            } else {
-                panic!("plugin did not export plugin_unmaker_counter_Greeter_v1");
-            }
+            let counters =
+                plugin_interface::get_counters_for(&lib, plugin_interface::PluginTrait::Greeter)
+                    .expect("plugin did not export plugin_counters_Greeter_v2");
+            assert!(
+                counters.registrations_destroyed > 0,
+                "unmaker did not count the released registration"
+            );
+            assert_eq!(counters.calls, 1);
         } else {
-            panic!("plugin did not export plugin_unregister_all_Greeter_v1");
+            panic!("plugin did not export plugin_unregister_all_Greeter_v2");
         }
 
         // Finally, drop the library (unload)
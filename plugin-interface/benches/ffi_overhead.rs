@@ -0,0 +1,40 @@
+//! Per-call overhead of each way the host calls a plugin (see
+//! `CallBackend`), on plugin-upper's `transform` and plugin-a's `name`.
+//! Every sample is one `PluginManager::bench_call` run, timed by the backend
+//! under test. Plugins that were not built are skipped.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use plugin_interface::{PluginManager, PluginTrait};
+use plugin_test_support::built_plugin;
+
+fn ffi_overhead(c: &mut Criterion) {
+    for (name, trait_id) in [
+        ("plugin_upper", PluginTrait::Transformer),
+        ("plugin_a", PluginTrait::Greeter),
+    ] {
+        let Some(path) = built_plugin(name) else {
+            continue;
+        };
+        let mut mgr = PluginManager::new();
+        let handles = mgr.load_library(&path, trait_id).expect("load plugin");
+        let handle = &handles[0];
+        let measured = mgr.bench_call(handle, 1).expect("call plugin");
+
+        let mut group = c.benchmark_group(format!("{}::{}", name, measured.method));
+        for (backend, _) in measured.totals {
+            group.bench_function(format!("{:?}", backend), |b| {
+                b.iter_custom(|iters| {
+                    let iterations = u32::try_from(iters).unwrap_or(u32::MAX);
+                    mgr.bench_call(handle, iterations)
+                        .expect("call plugin")
+                        .total(backend)
+                        .expect("backend measured")
+                })
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, ffi_overhead);
+criterion_main!(benches);
@@ -0,0 +1,328 @@
+/*
+ * The plugin ABI of plugin-interface, for plugins written in C or any
+ * language that can export C functions. Types carry the names of their Rust
+ * counterparts and have the same `#[repr(C)]` layout; the
+ * `c_plugin` test of plugin-interface checks the two against each other.
+ *
+ * A plugin is a shared library exporting, for each trait `<Trait>` it
+ * implements (`Greeter` or `Transformer`) and the registration ABI level
+ * `<N>` it speaks (PLUGIN_MIN_ABI to PLUGIN_MAX_ABI):
+ *
+ *   const RegistrationArray *plugin_register_all_<Trait>_v<N>(const HostContext *host);
+ *   void plugin_unregister_all_<Trait>_v<N>(const RegistrationArray *array);
+ *
+ * Below PLUGIN_HOST_CONTEXT_ABI the register function takes no arguments.
+ * The host calls the register function once per load, with a context that
+ * stays valid until the library is unloaded, and may call it again after
+ * the matching unregister function. A null array means the library has no
+ * implementations of the trait. From PLUGIN_HOST_VERSION_ABI on the
+ * register function takes a second argument,
+ *
+ *   const RegistrationArray *plugin_register_all_<Trait>_v<N>(const HostContext *host,
+ *                                                             const HostVersion *version);
+ *
+ * and a plugin that will not run on that host returns null and reports why
+ * from `const char *plugin_register_error_v1(void)`. The array, the registrations and the
+ * vtables belong to the plugin and must stay valid until the unregister
+ * function returns; the host never frees them. The host calls every
+ * `on_unload` entry before the unregister function, which should call the
+ * vtables' `drop` entries and release what the register function made.
+ *
+ * Instead of the pair above, a plugin may export
+ *
+ *   const void *plugin_register_<Trait>_v<N>(void);
+ *   void plugin_unregister_<Trait>_v<N>(const void *registration);
+ *
+ * for a single `<Trait>Registration`; it gets no `HostContext`. Optionally,
+ * `PluginCounters plugin_counters_<Trait>_v<N>(void)` reports what the
+ * plugin did with its registrations (or, for older hosts,
+ * `uint64_t plugin_unmaker_counter_<Trait>_v<N>(void)` how many it has
+ * released),
+ * `uint32_t plugin_selftest_<Trait>_v<N>(void)` returns how many of its
+ * registrations fail a self-test (see `PluginManager::set_self_test`),
+ * `bool plugin_leak_report_v1(LeakReport *out)` reports what the plugin
+ * allocated and released (see `LeakReport`), and
+ * `const char *plugin_describe_v1(void)` returns a static JSON description
+ * (see `PluginDescription`). Crates built with `#[plugin_aggregates]` also
+ * list their factories in `plugin_list_impls_<Trait>_v<N>`, which C
+ * plugins need not export.
+ *
+ * Every function pointer in a vtable up to `struct_size` must be set, and
+ * all of them use the C calling convention of the platform. Entries and
+ * callbacks may be called from any thread, but never concurrently with
+ * `drop`, and must not unwind. Strings passed as `const char *` are
+ * nul-terminated UTF-8; `StrRef` and `OwnedStr` carry a length instead.
+ */
+#ifndef PLUGIN_INTERFACE_H
+#define PLUGIN_INTERFACE_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Registration ABI levels, the `_v<N>` suffix of the symbols. */
+#define PLUGIN_MIN_ABI 1
+#define PLUGIN_MAX_ABI 5
+/* From this level on, `plugin_register_all_<Trait>_v<N>` gets the
+ * `HostContext`. */
+#define PLUGIN_HOST_CONTEXT_ABI 2
+/* From this level on, strings returned as `const char *` are allocated
+ * with `HostContext::allocator` and freed by the host. */
+#define PLUGIN_HOST_ALLOCATOR_ABI 3
+/* From this level on, `RegistrationArray` ends with `struct_size` and
+ * `flags`. */
+#define PLUGIN_SIZED_STRUCTS_ABI 4
+/* From this level on, `plugin_register_all_<Trait>_v<N>` also gets a
+ * `HostVersion`. */
+#define PLUGIN_HOST_VERSION_ABI 5
+
+/* Vtable `abi_version`s. Below PLUGIN_LIFECYCLE_VTABLE_ABI a vtable has
+ * no `on_load` / `on_unload`, so `drop` follows the methods. It ends after
+ * `drop` below PLUGIN_STATE_VTABLE_ABI, after `restore_state` below
+ * PLUGIN_STR_VTABLE_ABI and after the `_str` entries below
+ * PLUGIN_SIZED_VTABLE_ABI. From PLUGIN_CONTEXT_VTABLE_ABI on, its
+ * `context` table starts at `struct_size`. */
+#define PLUGIN_LIFECYCLE_VTABLE_ABI 2
+#define PLUGIN_STATE_VTABLE_ABI 2
+#define PLUGIN_STR_VTABLE_ABI 3
+#define PLUGIN_SIZED_VTABLE_ABI 4
+#define PLUGIN_CONTEXT_VTABLE_ABI 5
+
+/* `HostContext::abi_version` of this host, and the first versions with
+ * each of the fields appended to it. */
+#define PLUGIN_HOST_ABI_VERSION 5
+#define PLUGIN_SERVICES_HOST_ABI 2
+#define PLUGIN_EVENT_BUS_HOST_ABI 3
+#define PLUGIN_CONFIG_HOST_ABI 4
+#define PLUGIN_KV_HOST_ABI 5
+
+/* A borrowed string; not nul-terminated. */
+typedef struct StrRef {
+    const uint8_t *ptr;
+    size_t len;
+} StrRef;
+
+/* A returned string. The host copies the bytes, then calls `free` with
+ * `ptr` and `len` unless it is null. A null `ptr` means the call failed. */
+typedef struct OwnedStr {
+    uint8_t *ptr;
+    size_t len;
+    void (*free)(uint8_t *ptr, size_t len);
+} OwnedStr;
+
+/* Request context for the `<method>_ctx` entries, valid during the call.
+ * `correlation_id` and `deadline_ms` (milliseconds since the Unix epoch)
+ * are 0 when unset; `cancelled`, when not null, is set by the host to ask
+ * the plugin to stop and should be read atomically. */
+typedef struct RawRequestContext {
+    size_t struct_size;
+    uint64_t correlation_id;
+    uint64_t deadline_ms;
+    const volatile bool *cancelled;
+} RawRequestContext;
+
+/* The host's version, valid during the register call. `abi` is the
+ * host's `HostContext::abi_version`. */
+typedef struct HostVersion {
+    size_t struct_size;
+    uint32_t major;
+    uint32_t minor;
+    uint32_t patch;
+    uint32_t abi;
+} HostVersion;
+
+/* Lifecycle counters for `plugin_counters_<Trait>_v<N>`. */
+typedef struct PluginCounters {
+    uint64_t registrations_created;
+    uint64_t registrations_destroyed;
+    uint64_t calls;
+    uint64_t panics;
+} PluginCounters;
+
+/* Allocations a plugin made and released, for `plugin_leak_report_v1`. */
+typedef struct LeakCount {
+    uint64_t made;
+    uint64_t released;
+} LeakCount;
+
+typedef struct LeakReport {
+    size_t struct_size;
+    LeakCount registrations;
+    LeakCount vtables;
+    LeakCount user_data;
+    LeakCount strings;
+} LeakReport;
+
+typedef enum HealthStatus {
+    HEALTH_STATUS_HEALTHY = 0,
+    HEALTH_STATUS_DEGRADED = 1,
+    HEALTH_STATUS_UNHEALTHY = 2,
+} HealthStatus;
+
+typedef enum LogLevel {
+    LOG_LEVEL_ERROR = 1,
+    LOG_LEVEL_WARN = 2,
+    LOG_LEVEL_INFO = 3,
+    LOG_LEVEL_DEBUG = 4,
+    LOG_LEVEL_TRACE = 5,
+} LogLevel;
+
+/* Handed to `on_load`. */
+typedef struct HostInfo {
+    uint32_t abi_version;
+    const char *host_version;
+} HostInfo;
+
+/* Receives the bytes of `save_state` and `kv_get`; `sink` is the pointer
+ * the host passed in. */
+typedef void (*StateSink)(void *sink, const uint8_t *bytes, size_t len);
+
+typedef void (*EventCallback)(void *data, const char *topic, const uint8_t *payload, size_t len);
+
+typedef struct LogRecord {
+    uint32_t level;
+    const char *plugin;
+    const char *target;
+    const char *message;
+} LogRecord;
+
+typedef struct HostAllocator {
+    void *(*alloc)(size_t size);
+    void (*free)(void *ptr);
+} HostAllocator;
+
+/* The capability services; see the Rust documentation of each. */
+typedef struct FilesystemService FilesystemService;
+typedef struct NetworkService NetworkService;
+typedef struct ThreadService ThreadService;
+
+/* Host services handed to the register function. Fields after
+ * `allocator` are only present when `abi_version` says so. */
+typedef struct HostContext {
+    uint32_t abi_version;
+    const char *host_version;
+    void *user_data;
+    void (*log)(void *user_data, uint32_t level, const char *message);
+    const char *(*config_get)(void *user_data, const char *key);
+    void (*log_record)(void *user_data, const LogRecord *record);
+    uint32_t capabilities;
+    const FilesystemService *filesystem;
+    const NetworkService *network;
+    const ThreadService *threads;
+    /* Only present for plugins registering at PLUGIN_HOST_ALLOCATOR_ABI
+     * or later. */
+    const HostAllocator *allocator;
+    /* PLUGIN_SERVICES_HOST_ABI */
+    const void *(*get_service)(void *user_data, const char *trait_name, const char *name);
+    /* PLUGIN_EVENT_BUS_HOST_ABI */
+    uint64_t (*subscribe)(void *user_data, const char *topic, EventCallback callback, void *data);
+    void (*unsubscribe)(void *user_data, uint64_t id);
+    int32_t (*publish)(void *user_data, const char *topic, const uint8_t *payload, size_t len);
+    /* PLUGIN_CONFIG_HOST_ABI: the plugin's configuration, or null. */
+    const char *plugin_config;
+    /* PLUGIN_KV_HOST_ABI */
+    int32_t (*kv_get)(void *user_data, const char *key, void *out, StateSink sink);
+    int32_t (*kv_set)(void *user_data, const char *key, const uint8_t *bytes, size_t len);
+} HostContext;
+
+typedef struct RegistrationFactory RegistrationFactory;
+
+/* The registrations of one trait. `factories` may be null. */
+typedef struct RegistrationArray {
+    size_t count;
+    const void *const *registrations;
+    const RegistrationFactory *const *factories;
+    /* PLUGIN_SIZED_STRUCTS_ABI: sizeof(RegistrationArray) and 0. */
+    size_t struct_size;
+    uint32_t flags;
+} RegistrationArray;
+
+/* Entries appended to `GreeterVTable` after its first release; present as
+ * far as `struct_size` reaches, and may be null. */
+typedef struct GreeterOptional {
+    HealthStatus (*health)(void *user_data);
+} GreeterOptional;
+
+/* The `_str` entries with the caller's request context as trailing
+ * argument; present as far as `struct_size` reaches, and may be null. */
+typedef struct GreeterContext {
+    size_t struct_size;
+    OwnedStr (*name_ctx)(void *user_data, const RawRequestContext *ctx);
+    void (*greet_ctx)(void *user_data, StrRef target, const RawRequestContext *ctx);
+} GreeterContext;
+
+typedef struct GreeterVTable {
+    uint32_t abi_version;
+    void *user_data;
+    const char *(*name)(void *user_data);
+    void (*greet)(void *user_data, const char *target);
+    /* PLUGIN_LIFECYCLE_VTABLE_ABI */
+    void (*on_load)(void *user_data, const HostInfo *host);
+    void (*on_unload)(void *user_data);
+    void (*drop)(void *user_data);
+    /* PLUGIN_STATE_VTABLE_ABI */
+    void (*save_state)(void *user_data, void *sink, StateSink write);
+    void (*restore_state)(void *user_data, const uint8_t *bytes, size_t len);
+    /* PLUGIN_STR_VTABLE_ABI */
+    OwnedStr (*name_str)(void *user_data);
+    void (*greet_str)(void *user_data, StrRef target);
+    /* PLUGIN_SIZED_VTABLE_ABI: offsetof(GreeterVTable, context) and 0. */
+    size_t struct_size;
+    uint32_t flags;
+    GreeterOptional optional;
+    /* PLUGIN_CONTEXT_VTABLE_ABI */
+    GreeterContext context;
+} GreeterVTable;
+
+/* `name` is the registration name, or null to use the `name` entry. */
+typedef struct GreeterRegistration {
+    const char *name;
+    const GreeterVTable *vtable;
+} GreeterRegistration;
+
+typedef struct TransformerOptional {
+    OwnedStr (*describe)(void *user_data);
+    HealthStatus (*health)(void *user_data);
+} TransformerOptional;
+
+typedef struct TransformerContext {
+    size_t struct_size;
+    OwnedStr (*transform_ctx)(void *user_data, StrRef input, const RawRequestContext *ctx);
+    OwnedStr (*describe_ctx)(void *user_data, const RawRequestContext *ctx);
+} TransformerContext;
+
+typedef struct TransformerVTable {
+    uint32_t abi_version;
+    void *user_data;
+    const char *(*transform)(void *user_data, const char *input);
+    /* PLUGIN_LIFECYCLE_VTABLE_ABI */
+    void (*on_load)(void *user_data, const HostInfo *host);
+    void (*on_unload)(void *user_data);
+    void (*drop)(void *user_data);
+    /* PLUGIN_STATE_VTABLE_ABI */
+    void (*save_state)(void *user_data, void *sink, StateSink write);
+    void (*restore_state)(void *user_data, const uint8_t *bytes, size_t len);
+    /* PLUGIN_STR_VTABLE_ABI */
+    OwnedStr (*transform_str)(void *user_data, StrRef input);
+    /* PLUGIN_SIZED_VTABLE_ABI: offsetof(TransformerVTable, context) and 0. */
+    size_t struct_size;
+    uint32_t flags;
+    TransformerOptional optional;
+    /* PLUGIN_CONTEXT_VTABLE_ABI */
+    TransformerContext context;
+} TransformerVTable;
+
+typedef struct TransformerRegistration {
+    const char *name;
+    const TransformerVTable *vtable;
+} TransformerRegistration;
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* PLUGIN_INTERFACE_H */
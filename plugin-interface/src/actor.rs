@@ -0,0 +1,378 @@
+//! A `PluginManager` on a thread of its own, driven through a channel.
+
+use crate::{
+    HealthCheckGuard, HealthCheckOptions, PluginDescriptor, PluginHandle, PluginLoadError,
+    PluginManager, PluginSearchPath, PluginTrait, QuarantineAction,
+};
+use std::path::PathBuf;
+use std::sync::mpsc;
+
+#[cfg(all(feature = "watch", feature = "pinning"))]
+use crate::DigestMismatch;
+#[cfg(all(feature = "watch", feature = "isolation"))]
+use crate::ProbeFailure;
+#[cfg(feature = "watch")]
+use crate::{
+    manager::spawn_watch_thread, watch_filter::PathFilter, DirNotification, ManagerNotification,
+    ShutdownSignal, WatchEvent, WatchGuard, WatchNotification, WatchOptions,
+};
+#[cfg(feature = "watch")]
+use std::path::Path;
+#[cfg(feature = "watch")]
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "watch")]
+use std::sync::mpsc::RecvTimeoutError;
+#[cfg(feature = "watch")]
+use std::sync::Arc;
+#[cfg(feature = "watch")]
+use std::time::Duration;
+
+/// How often a watch session's forwarder looks for failed loads that are
+/// due for a retry.
+#[cfg(feature = "watch")]
+const RETRY_POLL: Duration = Duration::from_millis(100);
+
+/// What the owner thread keeps: the manager and the handles that keep the
+/// plugins loaded through it alive.
+pub(crate) struct Owned {
+    handles: Vec<PluginHandle>,
+    pub(crate) manager: PluginManager,
+    #[cfg(feature = "watch")]
+    subscribers: Vec<mpsc::Sender<ActorEvent>>,
+}
+
+pub(crate) type Job = Box<dyn FnOnce(&mut Owned) + Send>;
+
+impl Owned {
+    /// Load from `search_path` and keep the handles. Describes each plugin
+    /// the call loaded.
+    pub(crate) fn load(
+        &mut self,
+        search_path: PluginSearchPath,
+        trait_id: PluginTrait,
+    ) -> Result<Vec<PluginDescriptor>, PluginLoadError> {
+        let handles = self.manager.load_plugins(search_path, trait_id)?;
+        let loaded: Vec<PathBuf> = handles.iter().map(|h| h.path().to_path_buf()).collect();
+        self.handles.extend(handles);
+        Ok(self
+            .manager
+            .list()
+            .into_iter()
+            .filter(|d| loaded.contains(&d.path))
+            .collect())
+    }
+
+    /// Release the handles kept for `path`, then unload it.
+    pub(crate) fn unload(&mut self, path: PathBuf) -> Result<Option<u64>, String> {
+        self.handles.retain(|h| h.path() != path);
+        self.manager.unload_by_path(&path)
+    }
+}
+
+/// A `PluginManager` owned by a dedicated thread. Neither the manager nor
+/// its handles can leave the thread they were made on, so the actor sends
+/// every request to that thread and blocks until it is done; the actor
+/// itself is `Send` and `Clone`. Requests run one at a time in the order
+/// they were sent. The thread stops, unloading everything, once the last
+/// clone is dropped.
+///
+/// Plugins loaded through `load` stay loaded until `unload`, since the
+/// owner thread keeps their handles. Use `run` to call into them.
+#[derive(Clone)]
+pub struct PluginManagerActor {
+    jobs: mpsc::Sender<Job>,
+}
+
+impl PluginManagerActor {
+    /// Start an owner thread with a default `PluginManager`.
+    pub fn new() -> Self {
+        Self::with_manager(PluginManager::new)
+    }
+
+    /// Start an owner thread with the manager `make` builds there, for
+    /// example one with host services or trusted keys set.
+    pub fn with_manager<M>(make: M) -> Self
+    where
+        M: FnOnce() -> PluginManager + Send + 'static,
+    {
+        let (jobs, rx) = mpsc::channel::<Job>();
+        std::thread::Builder::new()
+            .name("plugin-manager".into())
+            .spawn(move || {
+                let mut owned = Owned {
+                    handles: Vec::new(),
+                    manager: make(),
+                    #[cfg(feature = "watch")]
+                    subscribers: Vec::new(),
+                };
+                for job in rx {
+                    job(&mut owned);
+                }
+            })
+            .expect("spawn plugin manager thread");
+        Self { jobs }
+    }
+
+    /// Queue `job` for the owner thread without waiting for it.
+    pub(crate) fn submit(&self, job: Job) {
+        self.jobs.send(job).expect("plugin manager thread stopped");
+    }
+
+    fn request<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut Owned) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (reply, answer) = mpsc::channel();
+        self.submit(Box::new(move |owned| {
+            let _ = reply.send(f(owned));
+        }));
+        answer.recv().expect("plugin manager thread stopped")
+    }
+
+    /// `PluginManager::load_plugins` on the owner thread. Returns a
+    /// description of each plugin the call loaded.
+    pub fn load(
+        &self,
+        search_path: impl Into<PluginSearchPath>,
+        trait_id: PluginTrait,
+    ) -> Result<Vec<PluginDescriptor>, PluginLoadError> {
+        let search_path = search_path.into();
+        self.request(move |owned| owned.load(search_path, trait_id))
+    }
+
+    /// Release the handles kept for `path`, then
+    /// `PluginManager::unload_by_path` on the owner thread.
+    pub fn unload(&self, path: impl Into<PathBuf>) -> Result<Option<u64>, String> {
+        let path = path.into();
+        self.request(move |owned| owned.unload(path))
+    }
+
+    /// `PluginManager::list` on the owner thread.
+    pub fn list(&self) -> Vec<PluginDescriptor> {
+        self.request(|owned| owned.manager.list())
+    }
+
+    /// Run `f` with the manager on the owner thread and return its result,
+    /// for example to look up a plugin and call it. Handles and proxies
+    /// made inside `f` cannot be returned; they are dropped when it ends.
+    pub fn run<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut PluginManager) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        self.request(move |owned| f(&mut owned.manager))
+    }
+
+    /// Set `options` on the manager and run `PluginManager::check_health`
+    /// on the owner thread every `options.interval` until the returned
+    /// guard is dropped. Quarantined plugins are reported to the manager's
+    /// subscribers; with `QuarantineAction::Unload`, the owner thread also
+    /// releases the handles it keeps for them. The owner thread keeps
+    /// running while the guard is alive, even with every clone of the actor
+    /// dropped.
+    pub fn start_health_checks(&self, options: HealthCheckOptions) -> HealthCheckGuard {
+        let (interval, action) = (options.interval, options.action);
+        self.request(move |owned| owned.manager.set_health_checks(options));
+        let jobs = self.jobs.clone();
+        HealthCheckGuard::spawn(interval, move || {
+            let job: Job = Box::new(move |owned| {
+                for check in owned.manager.check_health() {
+                    if check.quarantined && action == QuarantineAction::Unload {
+                        owned.handles.retain(|h| h.path() != check.path);
+                    }
+                }
+            });
+            jobs.send(job).is_ok()
+        })
+    }
+}
+
+impl Default for PluginManagerActor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "watch")]
+/// What the actor's watch mode did, sent to every `subscribe`r. The `Send`
+/// counterpart of `ManagerNotification`: plugins are described rather than
+/// handed out, and the actor keeps their handles.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum ActorEvent {
+    /// A new plugin file appeared; `plugin` is what was loaded from it.
+    Added {
+        path: PathBuf,
+        plugin: Option<PluginDescriptor>,
+    },
+    /// A known plugin file changed without being reloaded.
+    Modified {
+        path: PathBuf,
+        plugin: Option<PluginDescriptor>,
+    },
+    /// A plugin file was removed; `counter` is the unmaker counter when
+    /// `auto_unload` unloaded it.
+    Removed { path: PathBuf, counter: Option<u64> },
+    /// A loaded plugin was replaced by the new version of its file.
+    Reloaded {
+        path: PathBuf,
+        plugin: Option<PluginDescriptor>,
+    },
+    /// A load, reload or unload failed.
+    Error(String),
+    /// An artifact failed its digest check.
+    #[cfg(feature = "pinning")]
+    DigestMismatch(DigestMismatch),
+    /// A library failed its probe and was not loaded.
+    #[cfg(feature = "isolation")]
+    ProbeFailed(ProbeFailure),
+}
+
+#[cfg(feature = "watch")]
+impl Owned {
+    /// Act on one watcher notification, keep the handles it produced and
+    /// send the outcome to the subscribers.
+    fn react(
+        &mut self,
+        dir: &Path,
+        trait_id: PluginTrait,
+        opts: &WatchOptions,
+        filter: &PathFilter,
+        notification: WatchNotification,
+    ) {
+        if let WatchNotification::Unloaded { path, .. } = &notification {
+            if opts.auto_unload {
+                // let the unload happen now rather than with our last handle
+                self.handles.retain(|h| h.path() != path);
+            }
+        }
+        let notifications = self
+            .manager
+            .react(dir, trait_id, opts, filter, notification);
+        self.dispatch(notifications);
+    }
+
+    /// Retry the failed loads under each of the `watched` directories that
+    /// are due, and send the outcomes to the subscribers.
+    fn retry_loads(
+        &mut self,
+        watched: &[(PathBuf, WatchOptions, PathFilter)],
+        trait_id: PluginTrait,
+    ) {
+        for (dir, opts, filter) in watched {
+            let notifications = self.manager.retry_loads(dir, trait_id, opts, filter);
+            self.dispatch(notifications);
+        }
+    }
+
+    /// Keep the handles `notifications` bring and send each of them to the
+    /// subscribers as an `ActorEvent`.
+    fn dispatch(&mut self, notifications: Vec<ManagerNotification>) {
+        for notification in notifications {
+            let event = match notification {
+                ManagerNotification::Event(WatchEvent::Added { path, handles }) => {
+                    let plugin = PluginDescriptor::from_handles(&handles);
+                    self.handles.extend(handles);
+                    ActorEvent::Added { path, plugin }
+                }
+                ManagerNotification::Event(WatchEvent::Modified { path, handles }) => {
+                    let plugin = PluginDescriptor::from_handles(&handles);
+                    self.handles.retain(|h| h.path() != path);
+                    self.handles.extend(handles);
+                    ActorEvent::Modified { path, plugin }
+                }
+                ManagerNotification::Event(WatchEvent::Removed { path, counter, .. }) => {
+                    ActorEvent::Removed { path, counter }
+                }
+                ManagerNotification::Event(WatchEvent::Reloaded { path, new, .. }) => {
+                    let plugin = PluginDescriptor::from_handles(&new);
+                    self.handles.retain(|h| h.path() != path);
+                    self.handles.extend(new);
+                    ActorEvent::Reloaded { path, plugin }
+                }
+                ManagerNotification::Error(e) => ActorEvent::Error(e),
+                #[cfg(feature = "pinning")]
+                ManagerNotification::DigestMismatch(m) => ActorEvent::DigestMismatch(m),
+                #[cfg(feature = "isolation")]
+                ManagerNotification::ProbeFailed(f) => ActorEvent::ProbeFailed(f),
+            };
+            self.subscribers
+                .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+        }
+    }
+}
+
+#[cfg(feature = "watch")]
+impl PluginManagerActor {
+    /// A receiver for the events of this actor's watch sessions. Every
+    /// subscriber gets every event; dropped receivers are forgotten.
+    pub fn subscribe(&self) -> mpsc::Receiver<ActorEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.request(move |owned| owned.subscribers.push(tx));
+        rx
+    }
+
+    /// Watch `dirs` in the background and let the owner thread load,
+    /// reload and unload as each directory's options ask, like
+    /// `process_dir_notifications_blocking` would. Outcomes go to the
+    /// subscribers as `ActorEvent`s. Returns a `WatchGuard` that ends the
+    /// session when dropped, or an error for a bad include or exclude
+    /// pattern. The owner thread keeps running while a session is active,
+    /// even with every clone of the actor dropped.
+    pub fn watch(
+        &self,
+        dirs: Vec<(PathBuf, WatchOptions)>,
+        trait_id: PluginTrait,
+    ) -> Result<WatchGuard, String> {
+        let mut watched = Vec::with_capacity(dirs.len());
+        for (dir, opts) in &dirs {
+            let filter = PathFilter::new(&opts.include, &opts.exclude)?;
+            watched.push((dir.clone(), opts.clone(), filter));
+        }
+        let watched = Arc::new(watched);
+
+        let (tx, rx) = mpsc::channel::<DirNotification>();
+        let watcher = spawn_watch_thread(dirs, ShutdownSignal::new(), move |dir, notification| {
+            tx.send(DirNotification {
+                dir: dir.to_path_buf(),
+                notification,
+            })
+            .is_ok()
+        });
+        let control = watcher.control().clone();
+        let jobs = self.jobs.clone();
+        // Set by the owner thread while failed loads wait for a retry, so
+        // the forwarder only schedules retry jobs when there are some.
+        let retrying = Arc::new(AtomicBool::new(false));
+        let forwarder = std::thread::spawn(move || {
+            loop {
+                let watched = watched.clone();
+                let retrying = retrying.clone();
+                let job: Job = match rx.recv_timeout(RETRY_POLL) {
+                    Ok(DirNotification { dir, notification }) => Box::new(move |owned| {
+                        if let Some((dir, opts, filter)) = watched.iter().find(|(d, ..)| *d == dir)
+                        {
+                            owned.react(dir, trait_id, opts, filter, notification);
+                        }
+                        retrying.store(owned.manager.next_load_retry().is_some(), Ordering::SeqCst);
+                    }),
+                    Err(RecvTimeoutError::Timeout) if retrying.load(Ordering::SeqCst) => {
+                        Box::new(move |owned| {
+                            owned.retry_loads(&watched, trait_id);
+                            retrying
+                                .store(owned.manager.next_load_retry().is_some(), Ordering::SeqCst);
+                        })
+                    }
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
+                if jobs.send(job).is_err() {
+                    break;
+                }
+            }
+            drop(watcher);
+        });
+        Ok(WatchGuard::new(control, forwarder))
+    }
+}
@@ -0,0 +1,99 @@
+//! A `Send` front end to a `PluginManager` for async services.
+
+use crate::actor::Owned;
+use crate::{
+    PluginDescriptor, PluginLoadError, PluginManager, PluginManagerActor, PluginSearchPath,
+    PluginTrait,
+};
+use std::path::PathBuf;
+use tokio::sync::oneshot;
+
+/// A `PluginManagerActor` driven through futures: everything that touches
+/// the manager or its handles runs on the actor's owner thread, one
+/// request at a time, while async tasks only await the replies. The
+/// wrapper itself is `Send` and `Clone`; the thread stops, unloading
+/// everything, once the last clone is dropped.
+///
+/// Plugins loaded through `load_plugins` stay loaded until
+/// `unload_by_path`, since the owner thread keeps their handles. Use `run`
+/// to call into them.
+#[derive(Clone)]
+pub struct AsyncPluginManager {
+    actor: PluginManagerActor,
+}
+
+impl AsyncPluginManager {
+    /// Start an owner thread with a default `PluginManager`.
+    pub fn new() -> Self {
+        Self::with_manager(PluginManager::new)
+    }
+
+    /// Start an owner thread with the manager `make` builds there, for
+    /// example one with host services or trusted keys set.
+    pub fn with_manager<M>(make: M) -> Self
+    where
+        M: FnOnce() -> PluginManager + Send + 'static,
+    {
+        Self {
+            actor: PluginManagerActor::with_manager(make),
+        }
+    }
+
+    /// The actor behind this manager, for its blocking API or watch mode.
+    pub fn actor(&self) -> &PluginManagerActor {
+        &self.actor
+    }
+
+    async fn request<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut Owned) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (reply, answer) = oneshot::channel();
+        self.actor.submit(Box::new(move |owned| {
+            let _ = reply.send(f(owned));
+        }));
+        answer.await.expect("plugin manager thread stopped")
+    }
+
+    /// `PluginManager::load_plugins` on the owner thread. Returns a
+    /// description of each plugin the call loaded.
+    pub async fn load_plugins(
+        &self,
+        search_path: impl Into<PluginSearchPath>,
+        trait_id: PluginTrait,
+    ) -> Result<Vec<PluginDescriptor>, PluginLoadError> {
+        let search_path = search_path.into();
+        self.request(move |owned| owned.load(search_path, trait_id))
+            .await
+    }
+
+    /// Release the handles kept for `path`, then
+    /// `PluginManager::unload_by_path` on the owner thread.
+    pub async fn unload_by_path(&self, path: impl Into<PathBuf>) -> Result<Option<u64>, String> {
+        let path = path.into();
+        self.request(move |owned| owned.unload(path)).await
+    }
+
+    /// `PluginManager::list` on the owner thread.
+    pub async fn list(&self) -> Vec<PluginDescriptor> {
+        self.request(|owned| owned.manager.list()).await
+    }
+
+    /// Run `f` with the manager on the owner thread and return its result,
+    /// for example to look up a plugin and call it. Handles and proxies
+    /// made inside `f` cannot be returned; they are dropped when it ends.
+    pub async fn run<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut PluginManager) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        self.request(move |owned| f(&mut owned.manager)).await
+    }
+}
+
+impl Default for AsyncPluginManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
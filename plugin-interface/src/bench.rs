@@ -0,0 +1,188 @@
+//! Per-call overhead of each way the host can call a plugin, for
+//! `PluginManager::bench_call` and the criterion suite in `benches/`.
+//!
+//! Every backend calls the same method: `name` for greeters, `transform`
+//! of a short input for transformers. The raw backends hold one unload
+//! permit for the whole run, so they measure the indirection and the
+//! marshalling only; `CallBackend::Proxy` adds what every proxy call pays.
+
+use crate::call::PluginCallError;
+use crate::handle::{registration_ptr, PluginHandle};
+use crate::request::RequestContext;
+use crate::{GreeterRegistration, PluginTrait, StrRef, TransformerRegistration, STR_VTABLE_ABI};
+use std::ffi::CString;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+/// Input `transform` is benchmarked with.
+const BENCH_INPUT: &str = "the quick brown fox";
+
+/// How a benchmarked call crosses the plugin boundary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CallBackend {
+    /// The first-release entry, with a `CString` per argument and a
+    /// nul-terminated result.
+    CStr,
+    /// The `<method>_str` entry, passing `StrRef` and returning `OwnedStr`.
+    StrRef,
+    /// The `<method>_ctx` entry, with an empty request context.
+    Context,
+    /// The proxy's `try_` method: the `_str` entry plus the stale and
+    /// disabled checks, the unload permit, interceptors and statistics.
+    Proxy,
+}
+
+/// What `PluginManager::bench_call` measured: the total time of
+/// `iterations` calls through each backend the registration has entries
+/// for, in `CallBackend` order.
+#[derive(Debug, Clone)]
+pub struct CallBenchmark {
+    /// The method that was called.
+    pub method: &'static str,
+    pub iterations: u32,
+    pub totals: Vec<(CallBackend, Duration)>,
+}
+
+impl CallBenchmark {
+    /// Total time of the calls through `backend`, if it was measured.
+    pub fn total(&self, backend: CallBackend) -> Option<Duration> {
+        self.totals
+            .iter()
+            .find(|(b, _)| *b == backend)
+            .map(|(_, total)| *total)
+    }
+
+    /// Mean time of one call through `backend`, if it was measured.
+    pub fn per_call(&self, backend: CallBackend) -> Option<Duration> {
+        self.total(backend)
+            .map(|total| total / self.iterations.max(1))
+    }
+}
+
+/// Time `iterations` calls of `f`, which fails the run if the plugin does.
+fn time(
+    iterations: u32,
+    mut f: impl FnMut() -> Result<(), PluginCallError>,
+) -> Result<Duration, PluginCallError> {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        f()?;
+    }
+    Ok(start.elapsed())
+}
+
+pub(crate) fn bench_call(
+    handle: &PluginHandle,
+    iterations: u32,
+) -> Result<CallBenchmark, PluginCallError> {
+    if handle.is_stale() {
+        return Err(PluginCallError::Stale);
+    }
+    let mut totals = Vec::new();
+    let method = match handle.trait_id() {
+        PluginTrait::Greeter => "name",
+        PluginTrait::Transformer => "transform",
+    };
+    if let Some((lib, index)) = handle.native_registration() {
+        let _permit = lib.guard.enter().ok_or(PluginCallError::Unloading)?;
+        let reg = registration_ptr(&lib, index);
+        let ctx = RequestContext::new().raw();
+        match handle.trait_id() {
+            PluginTrait::Greeter => {
+                let v = unsafe { &*(*(reg as *const GreeterRegistration)).vtable };
+                totals.push((
+                    CallBackend::CStr,
+                    time(iterations, || {
+                        let c = (v.name)(v.user_data);
+                        if c.is_null() {
+                            return Err(PluginCallError::Failed);
+                        }
+                        black_box(unsafe { lib.returned_string(c) });
+                        Ok(())
+                    })?,
+                ));
+                if v.abi_version >= STR_VTABLE_ABI {
+                    totals.push((
+                        CallBackend::StrRef,
+                        time(iterations, || {
+                            let s = unsafe { (v.name_str)(v.user_data).into_string() };
+                            black_box(s.ok_or(PluginCallError::Failed)?);
+                            Ok(())
+                        })?,
+                    ));
+                }
+                if let Some(name_ctx) = v.optional_name_ctx() {
+                    totals.push((
+                        CallBackend::Context,
+                        time(iterations, || {
+                            let s = unsafe { name_ctx(v.user_data, &ctx).into_string() };
+                            black_box(s.ok_or(PluginCallError::Failed)?);
+                            Ok(())
+                        })?,
+                    ));
+                }
+            }
+            PluginTrait::Transformer => {
+                let v = unsafe { &*(*(reg as *const TransformerRegistration)).vtable };
+                totals.push((
+                    CallBackend::CStr,
+                    time(iterations, || {
+                        let input = CString::new(BENCH_INPUT).expect("input contains null");
+                        let c = (v.transform)(v.user_data, input.as_ptr());
+                        if c.is_null() {
+                            return Err(PluginCallError::Failed);
+                        }
+                        black_box(unsafe { lib.returned_string(c) });
+                        Ok(())
+                    })?,
+                ));
+                if v.abi_version >= STR_VTABLE_ABI {
+                    totals.push((
+                        CallBackend::StrRef,
+                        time(iterations, || {
+                            let s = unsafe {
+                                (v.transform_str)(v.user_data, StrRef::new(BENCH_INPUT))
+                                    .into_string()
+                            };
+                            black_box(s.ok_or(PluginCallError::Failed)?);
+                            Ok(())
+                        })?,
+                    ));
+                }
+                if let Some(transform_ctx) = v.optional_transform_ctx() {
+                    totals.push((
+                        CallBackend::Context,
+                        time(iterations, || {
+                            let s = unsafe {
+                                transform_ctx(v.user_data, StrRef::new(BENCH_INPUT), &ctx)
+                                    .into_string()
+                            };
+                            black_box(s.ok_or(PluginCallError::Failed)?);
+                            Ok(())
+                        })?,
+                    ));
+                }
+            }
+        }
+    }
+    let proxy = match handle.trait_id() {
+        PluginTrait::Greeter => {
+            let proxy = handle.as_greeter().ok_or(PluginCallError::Unsupported)?;
+            time(iterations, || proxy.try_name().map(|s| drop(black_box(s))))?
+        }
+        PluginTrait::Transformer => {
+            let proxy = handle
+                .as_transformer()
+                .ok_or(PluginCallError::Unsupported)?;
+            time(iterations, || {
+                proxy.try_transform(BENCH_INPUT).map(|s| drop(black_box(s)))
+            })?
+        }
+    };
+    totals.push((CallBackend::Proxy, proxy));
+    Ok(CallBenchmark {
+        method,
+        iterations,
+        totals,
+    })
+}
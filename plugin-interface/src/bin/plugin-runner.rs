@@ -0,0 +1,29 @@
+// plugin-interface/src/bin/plugin-runner.rs
+// Runner process for `PluginManager::load_isolated`: loads the library given
+// as the only argument and serves proxy calls over stdin/stdout. With
+// `--probe <trait> <library>` it loads and unloads the library once for
+// `PluginManager::set_probe` and exits.
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let args: Vec<_> = std::env::args_os().skip(1).collect();
+    let result = match args.as_slice() {
+        [flag, trait_name, lib] if flag == "--probe" => {
+            plugin_interface::serve_probe(&trait_name.to_string_lossy(), &PathBuf::from(lib))
+        }
+        [lib] => plugin_interface::serve_isolated(&PathBuf::from(lib)),
+        _ => {
+            eprintln!("usage: plugin-runner [--probe <trait>] <plugin library>");
+            return ExitCode::from(2);
+        }
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("plugin-runner: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! Error budgets: registrations whose proxy calls keep failing are turned
+//! off automatically, so a flaky plugin cannot degrade the whole host (see
+//! `PluginManager::set_error_budget`).
+
+use crate::call::DisabledPolicy;
+use crate::events::{ManagerEvent, Subscribers};
+use crate::handle::PluginId;
+use crate::instrument::trace_event;
+use crate::stats::CallState;
+use crate::RegistrationArray;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+/// How many proxy calls to a registration may fail within `window` before
+/// the manager turns it off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorBudget {
+    /// Failed calls within `window` that turn a registration off; 0 never
+    /// does. Defaults to 5.
+    pub max_errors: u32,
+    /// Defaults to one minute.
+    pub window: Duration,
+}
+
+impl Default for ErrorBudget {
+    fn default() -> Self {
+        Self {
+            max_errors: 5,
+            window: Duration::from_secs(60),
+        }
+    }
+}
+
+/// A manager's error budget and its subscribers, shared with the libraries
+/// it loads so their proxies can spend the budget.
+pub(crate) struct SharedBudget {
+    /// The budget and what calls to registrations it turns off do.
+    settings: RwLock<Option<(ErrorBudget, DisabledPolicy)>>,
+    subscribers: Subscribers,
+}
+
+impl SharedBudget {
+    pub(crate) fn new(subscribers: Subscribers) -> Self {
+        Self {
+            settings: RwLock::new(None),
+            subscribers,
+        }
+    }
+
+    pub(crate) fn set(&self, budget: Option<ErrorBudget>, policy: DisabledPolicy) {
+        *self.settings.write().unwrap_or_else(|e| e.into_inner()) =
+            budget.map(|budget| (budget, policy));
+    }
+
+    pub(crate) fn get(&self) -> Option<ErrorBudget> {
+        self.settings
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .map(|(budget, _)| budget)
+    }
+
+    /// Count a failed call of the registration `id`, at `index` of the
+    /// library loaded from `path`. If that uses up the budget, turn the
+    /// registration off and tell the subscribers.
+    pub(crate) fn spend(&self, calls: &CallState, index: usize, id: PluginId, path: &Path) {
+        let Some((budget, policy)) = *self.settings.read().unwrap_or_else(|e| e.into_inner())
+        else {
+            return;
+        };
+        if calls.disabled(index).is_some() {
+            return;
+        }
+        let Some(errors) = calls
+            .error_window(index)
+            .and_then(|w| w.record(Instant::now(), &budget))
+        else {
+            return;
+        };
+        calls.set_disabled(index, Some(policy));
+        trace_event!(
+            warn,
+            path = %path.display(),
+            index,
+            errors,
+            "plugin disabled after exceeding its error budget"
+        );
+        self.subscribers.send(ManagerEvent::ErrorBudgetExceeded {
+            id,
+            path: path.to_path_buf(),
+            errors,
+        });
+    }
+}
+
+/// The error budget a library's calls spend, with the key its registration
+/// ids are derived from.
+pub(crate) struct BudgetBinding {
+    budget: Arc<SharedBudget>,
+    key: usize,
+}
+
+impl std::fmt::Debug for BudgetBinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BudgetBinding")
+            .field("budget", &self.budget.get())
+            .finish()
+    }
+}
+
+impl BudgetBinding {
+    pub(crate) fn new(budget: Arc<SharedBudget>, key: *const RegistrationArray) -> Self {
+        Self {
+            budget,
+            key: key as usize,
+        }
+    }
+
+    /// Count a failed call of the registration at `index` against the
+    /// manager's error budget.
+    pub(crate) fn spend(&self, calls: &CallState, index: usize, path: &Path) {
+        let id = PluginId::for_registration(self.key as *const RegistrationArray, index);
+        self.budget.spend(calls, index, id, path);
+    }
+}
+
+/// The times of a registration's recent failed calls.
+#[derive(Debug, Default)]
+pub(crate) struct ErrorWindow(Mutex<VecDeque<Instant>>);
+
+impl ErrorWindow {
+    /// Record a failure at `now`. Returns the failures within
+    /// `budget.window` before it if they reach `budget.max_errors`, and
+    /// starts counting over.
+    pub(crate) fn record(&self, now: Instant, budget: &ErrorBudget) -> Option<u32> {
+        if budget.max_errors == 0 {
+            return None;
+        }
+        let mut times = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        while times
+            .front()
+            .is_some_and(|&t| now.saturating_duration_since(t) > budget.window)
+        {
+            times.pop_front();
+        }
+        times.push_back(now);
+        let errors = u32::try_from(times.len()).unwrap_or(u32::MAX);
+        if errors < budget.max_errors {
+            return None;
+        }
+        times.clear();
+        Some(errors)
+    }
+
+    pub(crate) fn clear(&self) {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_failures_within_the_window_count() {
+        let budget = ErrorBudget {
+            max_errors: 3,
+            window: Duration::from_secs(10),
+        };
+        let window = ErrorWindow::default();
+        let start = Instant::now();
+        assert_eq!(window.record(start, &budget), None);
+        assert_eq!(window.record(start + Duration::from_secs(5), &budget), None);
+        // The first failure is out of the window by now.
+        assert_eq!(
+            window.record(start + Duration::from_secs(12), &budget),
+            None
+        );
+        assert_eq!(
+            window.record(start + Duration::from_secs(13), &budget),
+            Some(3)
+        );
+        // Counting starts over once the budget is used up.
+        assert_eq!(
+            window.record(start + Duration::from_secs(14), &budget),
+            None
+        );
+
+        let unlimited = ErrorBudget {
+            max_errors: 0,
+            ..budget
+        };
+        assert_eq!(window.record(start, &unlimited), None);
+    }
+}
@@ -0,0 +1,568 @@
+//! Event bus between the host and plugins: named topics carrying byte
+//! payloads, delivered in publish order on a dedicated dispatcher thread.
+//!
+//! Plugins subscribe and publish through `HostContext::subscribe` and
+//! `HostContext::publish`; the host through `PluginManager::event_bus()`.
+//! A payload is whatever both sides agree on for the topic, typically a
+//! serialized document or the bytes of a `#[repr(C)]` struct.
+
+use crate::handle::LoadedLib;
+use crate::instrument::trace_event;
+use std::collections::{HashMap, VecDeque};
+use std::ffi::{c_void, CStr, CString};
+use std::os::raw::c_char;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock, Weak};
+use std::thread::{JoinHandle, ThreadId};
+use std::time::{Duration, Instant};
+
+/// Called on the dispatcher thread with the subscriber's data pointer, the
+/// nul-terminated topic and the payload. The pointers are only valid for
+/// the duration of the call.
+pub type EventCallback = extern "C" fn(*mut c_void, *const c_char, *const u8, usize);
+
+/// What `publish` does when the queue already holds `BusOptions::capacity`
+/// events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Wait up to the duration for room, then fail with `BusError::Full`.
+    /// Publishing from the dispatcher thread, i.e. from a subscriber, never
+    /// waits.
+    Block(Duration),
+    /// Fail with `BusError::Full` at once.
+    Reject,
+    /// Make room by dropping the oldest queued event.
+    DropOldest,
+}
+
+/// Queue limits of an `EventBus`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusOptions {
+    /// Events queued for delivery at most; at least 1.
+    pub capacity: usize,
+    pub backpressure: Backpressure,
+}
+
+impl Default for BusOptions {
+    /// 1024 events, blocking publishers for up to 100 ms.
+    fn default() -> Self {
+        Self {
+            capacity: 1024,
+            backpressure: Backpressure::Block(Duration::from_millis(100)),
+        }
+    }
+}
+
+/// Why an event could not be published or a subscription made.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BusError {
+    /// The topic is empty or contains a nul byte.
+    InvalidTopic,
+    /// The queue was full and the backpressure policy gave up on the event.
+    Full,
+    /// The context has no bus: it was not handed out by a `PluginManager`,
+    /// the manager is gone, or the host predates the event bus.
+    Unavailable,
+}
+
+impl std::fmt::Display for BusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BusError::InvalidTopic => write!(f, "topic is empty or contains a nul byte"),
+            BusError::Full => write!(f, "event queue is full"),
+            BusError::Unavailable => write!(f, "no event bus"),
+        }
+    }
+}
+
+impl std::error::Error for BusError {}
+
+impl BusError {
+    /// The status `HostContext::publish` carries the error as.
+    pub(crate) fn code(self) -> i32 {
+        match self {
+            BusError::InvalidTopic => -1,
+            BusError::Full => 1,
+            BusError::Unavailable => -2,
+        }
+    }
+
+    pub(crate) fn from_code(code: i32) -> Result<(), BusError> {
+        match code {
+            0 => Ok(()),
+            1 => Err(BusError::Full),
+            -1 => Err(BusError::InvalidTopic),
+            _ => Err(BusError::Unavailable),
+        }
+    }
+}
+
+/// Identifies a subscription for `EventBus::unsubscribe`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(u64);
+
+impl SubscriptionId {
+    pub fn get(self) -> u64 {
+        self.0
+    }
+}
+
+type HostCallback = dyn Fn(&str, &[u8]) + Send + Sync;
+
+/// Topics the host and the plugins of one `PluginManager` publish to.
+///
+/// Events are queued and handed to the subscribers of their topic, in
+/// subscription order, on a dispatcher thread started by the first publish.
+/// A plugin's subscriptions only receive events while its library is loaded
+/// and not closed, and are removed when the library is unloaded. Events
+/// still queued when the bus is dropped are discarded.
+pub struct EventBus {
+    inner: Arc<Inner>,
+    dispatcher: Mutex<Option<JoinHandle<()>>>,
+}
+
+struct Inner {
+    state: Mutex<State>,
+    changed: Condvar,
+    next_id: AtomicU64,
+    dispatcher_thread: OnceLock<ThreadId>,
+}
+
+struct State {
+    options: BusOptions,
+    queue: VecDeque<(CString, Arc<[u8]>)>,
+    /// An event is being handed to subscribers.
+    in_flight: bool,
+    subscriptions: Vec<Arc<Subscription>>,
+    /// Libraries by the owner id of the context they were handed.
+    owners: HashMap<u64, Weak<LoadedLib>>,
+    dropped: u64,
+    stopping: bool,
+}
+
+struct Subscription {
+    id: u64,
+    topic: String,
+    target: Target,
+}
+
+enum Target {
+    Host(Box<HostCallback>),
+    /// A callback in the library whose context has `owner`.
+    Plugin {
+        owner: u64,
+        callback: EventCallback,
+        data: *mut c_void,
+    },
+}
+
+// Plugin data pointers are only passed back to the plugin's own callback,
+// which must accept them on the dispatcher thread; libraries are only
+// checked and entered through their call guard.
+unsafe impl Send for Subscription {}
+unsafe impl Sync for Subscription {}
+unsafe impl Send for Inner {}
+unsafe impl Sync for Inner {}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(BusOptions::default())
+    }
+}
+
+impl std::fmt::Debug for EventBus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = self.inner.lock();
+        f.debug_struct("EventBus")
+            .field("options", &state.options)
+            .field("queued", &state.queue.len())
+            .field("subscriptions", &state.subscriptions.len())
+            .field("dropped", &state.dropped)
+            .finish()
+    }
+}
+
+impl EventBus {
+    pub fn new(options: BusOptions) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                state: Mutex::new(State {
+                    options: clamp(options),
+                    queue: VecDeque::new(),
+                    in_flight: false,
+                    subscriptions: Vec::new(),
+                    owners: HashMap::new(),
+                    dropped: 0,
+                    stopping: false,
+                }),
+                changed: Condvar::new(),
+                next_id: AtomicU64::new(1),
+                dispatcher_thread: OnceLock::new(),
+            }),
+            dispatcher: Mutex::new(None),
+        }
+    }
+
+    pub fn options(&self) -> BusOptions {
+        self.inner.lock().options
+    }
+
+    /// Change the queue limits. Events already queued past a smaller
+    /// capacity are still delivered.
+    pub fn set_options(&self, options: BusOptions) {
+        self.inner.lock().options = clamp(options);
+        self.inner.changed.notify_all();
+    }
+
+    /// Call `callback` on the dispatcher thread with the topic and payload
+    /// of every event published to `topic`. A panic in the callback is
+    /// caught and the event goes on to the next subscriber.
+    pub fn subscribe<F>(&self, topic: &str, callback: F) -> Result<SubscriptionId, BusError>
+    where
+        F: Fn(&str, &[u8]) + Send + Sync + 'static,
+    {
+        self.inner
+            .add(topic, Target::Host(Box::new(callback)))
+            .map(SubscriptionId)
+    }
+
+    /// Stop a subscription. Returns false if it was already gone. The
+    /// callback may still be running for an event taken off the queue
+    /// before.
+    pub fn unsubscribe(&self, id: SubscriptionId) -> bool {
+        self.inner.remove(id.0)
+    }
+
+    /// Queue `payload` for the subscribers of `topic`, applying the
+    /// backpressure policy when the queue is full.
+    pub fn publish(&self, topic: &str, payload: &[u8]) -> Result<(), BusError> {
+        let topic = CString::new(topic).map_err(|_| BusError::InvalidTopic)?;
+        if topic.as_bytes().is_empty() {
+            return Err(BusError::InvalidTopic);
+        }
+        self.start_dispatcher();
+        self.inner.push(topic, payload)
+    }
+
+    /// Wait up to `timeout` until every queued event has been delivered.
+    /// Returns false on timeout, and at once when called from a subscriber.
+    pub fn flush(&self, timeout: Duration) -> bool {
+        if self.inner.on_dispatcher() {
+            return false;
+        }
+        let deadline = Instant::now() + timeout;
+        let mut state = self.inner.lock();
+        while !state.queue.is_empty() || state.in_flight {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            state = self
+                .inner
+                .changed
+                .wait_timeout(state, deadline - now)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+        true
+    }
+
+    /// Events waiting for delivery.
+    pub fn queued(&self) -> usize {
+        self.inner.lock().queue.len()
+    }
+
+    /// Events the backpressure policy rejected or dropped so far.
+    pub fn dropped(&self) -> u64 {
+        self.inner.lock().dropped
+    }
+
+    /// Deliver events for plugin subscriptions made through contexts with
+    /// `owner` to `lib` from now on.
+    pub(crate) fn bind(&self, owner: u64, lib: &Arc<LoadedLib>) {
+        let mut state = self.inner.lock();
+        state.owners.retain(|_, l| l.strong_count() > 0);
+        state.owners.insert(owner, Arc::downgrade(lib));
+    }
+
+    /// Subscribe a plugin callback on behalf of the context with `owner`.
+    pub(crate) fn subscribe_plugin(
+        &self,
+        owner: u64,
+        topic: &str,
+        callback: EventCallback,
+        data: *mut c_void,
+    ) -> Result<u64, BusError> {
+        self.inner.add(
+            topic,
+            Target::Plugin {
+                owner,
+                callback,
+                data,
+            },
+        )
+    }
+
+    /// Remove the subscription `id` if the context with `owner` made it.
+    pub(crate) fn unsubscribe_plugin(&self, owner: u64, id: u64) {
+        self.inner.lock().subscriptions.retain(|s| {
+            s.id != id || !matches!(s.target, Target::Plugin { owner: o, .. } if o == owner)
+        });
+    }
+
+    /// Forget everything the context with `owner` subscribed, once its
+    /// library is gone.
+    pub(crate) fn remove_owner(&self, owner: u64) {
+        let mut state = self.inner.lock();
+        state
+            .subscriptions
+            .retain(|s| !matches!(s.target, Target::Plugin { owner: o, .. } if o == owner));
+        state.owners.remove(&owner);
+    }
+
+    fn start_dispatcher(&self) {
+        let mut dispatcher = self.dispatcher.lock().unwrap_or_else(|e| e.into_inner());
+        if dispatcher.is_some() {
+            return;
+        }
+        let inner = self.inner.clone();
+        match std::thread::Builder::new()
+            .name("plugin-event-bus".to_string())
+            .spawn(move || inner.dispatch())
+        {
+            Ok(handle) => {
+                let _ = self.inner.dispatcher_thread.set(handle.thread().id());
+                *dispatcher = Some(handle);
+            }
+            Err(_e) => {
+                trace_event!(error, error = %_e, "failed to start the event bus dispatcher");
+            }
+        }
+    }
+}
+
+impl Drop for EventBus {
+    fn drop(&mut self) {
+        self.inner.lock().stopping = true;
+        self.inner.changed.notify_all();
+        let handle = self
+            .dispatcher
+            .get_mut()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+        // A subscriber may hold the last reference; it cannot wait for itself.
+        if let Some(handle) = handle.filter(|_| !self.inner.on_dispatcher()) {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Inner {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn on_dispatcher(&self) -> bool {
+        self.dispatcher_thread.get() == Some(&std::thread::current().id())
+    }
+
+    fn add(&self, topic: &str, target: Target) -> Result<u64, BusError> {
+        if topic.is_empty() || topic.contains('\0') {
+            return Err(BusError::InvalidTopic);
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.lock().subscriptions.push(Arc::new(Subscription {
+            id,
+            topic: topic.to_string(),
+            target,
+        }));
+        Ok(id)
+    }
+
+    fn remove(&self, id: u64) -> bool {
+        let mut state = self.lock();
+        let before = state.subscriptions.len();
+        state.subscriptions.retain(|s| s.id != id);
+        state.subscriptions.len() != before
+    }
+
+    fn push(&self, topic: CString, payload: &[u8]) -> Result<(), BusError> {
+        let mut state = self.lock();
+        let mut deadline = None;
+        while state.queue.len() >= state.options.capacity {
+            match state.options.backpressure {
+                Backpressure::DropOldest => {
+                    state.queue.pop_front();
+                    state.dropped += 1;
+                }
+                Backpressure::Block(timeout) if !self.on_dispatcher() => {
+                    let deadline = *deadline.get_or_insert_with(|| Instant::now() + timeout);
+                    let now = Instant::now();
+                    if now >= deadline {
+                        state.dropped += 1;
+                        return Err(BusError::Full);
+                    }
+                    state = self
+                        .changed
+                        .wait_timeout(state, deadline - now)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0;
+                }
+                Backpressure::Block(_) | Backpressure::Reject => {
+                    state.dropped += 1;
+                    return Err(BusError::Full);
+                }
+            }
+        }
+        state.queue.push_back((topic, payload.into()));
+        self.changed.notify_all();
+        Ok(())
+    }
+
+    fn dispatch(&self) {
+        loop {
+            let mut state = self.lock();
+            state.in_flight = false;
+            self.changed.notify_all();
+            let (topic, payload) = loop {
+                if state.stopping {
+                    return;
+                }
+                if let Some(event) = state.queue.pop_front() {
+                    break event;
+                }
+                state = self.changed.wait(state).unwrap_or_else(|e| e.into_inner());
+            };
+            state.in_flight = true;
+            // Room was made for a blocked publisher.
+            self.changed.notify_all();
+            let name = topic.to_str().unwrap_or_default();
+            let targets: Vec<(Arc<Subscription>, Option<Arc<LoadedLib>>)> = state
+                .subscriptions
+                .iter()
+                .filter(|s| s.topic == name)
+                .filter_map(|s| match s.target {
+                    Target::Host(_) => Some((s.clone(), None)),
+                    // Subscriptions of libraries not yet recorded wait for it.
+                    Target::Plugin { owner, .. } => {
+                        let lib = state.owners.get(&owner)?.upgrade()?;
+                        Some((s.clone(), Some(lib)))
+                    }
+                })
+                .collect();
+            drop(state);
+            for (subscription, lib) in targets {
+                deliver(&subscription, lib.as_deref(), &topic, &payload);
+            }
+        }
+    }
+}
+
+fn deliver(subscription: &Subscription, lib: Option<&LoadedLib>, topic: &CStr, payload: &[u8]) {
+    match (&subscription.target, lib) {
+        (Target::Host(callback), _) => {
+            let name = topic.to_str().unwrap_or_default();
+            let _ =
+                std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| callback(name, payload)));
+        }
+        (&Target::Plugin { callback, data, .. }, Some(lib)) => {
+            if lib.closed.load(Ordering::SeqCst) || lib.is_unloaded() {
+                return;
+            }
+            // Held across the call, so the library cannot be unloaded under it.
+            let Some(_permit) = lib.guard.enter() else {
+                return;
+            };
+            callback(data, topic.as_ptr(), payload.as_ptr(), payload.len());
+        }
+        (Target::Plugin { .. }, None) => {}
+    }
+}
+
+fn clamp(options: BusOptions) -> BusOptions {
+    BusOptions {
+        capacity: options.capacity.max(1),
+        ..options
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delivers_to_the_topic_subscribers_in_order() {
+        let bus = EventBus::default();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        let id = bus
+            .subscribe("greetings", move |topic, payload| {
+                sink.lock()
+                    .unwrap()
+                    .push(format!("{} {}", topic, String::from_utf8_lossy(payload)))
+            })
+            .unwrap();
+        bus.subscribe("greetings", |_, _| panic!("a broken subscriber"))
+            .unwrap();
+
+        bus.publish("greetings", b"hello").unwrap();
+        bus.publish("other", b"ignored").unwrap();
+        bus.publish("greetings", b"again").unwrap();
+        assert!(bus.flush(Duration::from_secs(5)));
+        assert!(bus.unsubscribe(id));
+        assert!(!bus.unsubscribe(id));
+        bus.publish("greetings", b"unheard").unwrap();
+        assert!(bus.flush(Duration::from_secs(5)));
+
+        assert_eq!(
+            *seen.lock().unwrap(),
+            ["greetings hello", "greetings again"]
+        );
+        assert_eq!(bus.publish("", b""), Err(BusError::InvalidTopic));
+        assert_eq!(bus.publish("a\0b", b""), Err(BusError::InvalidTopic));
+    }
+
+    #[test]
+    fn full_queues_follow_the_backpressure_policy() {
+        let bus = EventBus::new(BusOptions {
+            capacity: 1,
+            backpressure: Backpressure::Reject,
+        });
+        let (entered, wait_entered) = std::sync::mpsc::channel();
+        let release = Arc::new(Mutex::new(()));
+        let held = release.lock().unwrap();
+        let gate = release.clone();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        bus.subscribe("t", move |_, payload| {
+            let _ = entered.send(());
+            drop(gate.lock().unwrap());
+            sink.lock().unwrap().push(payload.to_vec());
+        })
+        .unwrap();
+
+        // The dispatcher takes the first event and blocks in the subscriber.
+        bus.publish("t", b"1").unwrap();
+        wait_entered.recv().unwrap();
+        bus.publish("t", b"2").unwrap();
+        assert_eq!(bus.publish("t", b"3"), Err(BusError::Full));
+
+        bus.set_options(BusOptions {
+            capacity: 1,
+            backpressure: Backpressure::Block(Duration::from_millis(10)),
+        });
+        assert_eq!(bus.publish("t", b"4"), Err(BusError::Full));
+
+        bus.set_options(BusOptions {
+            capacity: 1,
+            backpressure: Backpressure::DropOldest,
+        });
+        bus.publish("t", b"5").unwrap();
+        assert_eq!(bus.queued(), 1);
+        assert_eq!(bus.dropped(), 3);
+
+        drop(held);
+        assert!(bus.flush(Duration::from_secs(5)));
+        assert_eq!(*seen.lock().unwrap(), [b"1".to_vec(), b"5".to_vec()]);
+    }
+}
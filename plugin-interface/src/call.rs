@@ -0,0 +1,344 @@
+use crate::handle::GreeterProxy;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// How long closing or unloading a library waits for calls into it to
+/// return unless `PluginManager::set_unload_timeout` says otherwise.
+pub const DEFAULT_UNLOAD_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Options for proxy calls that run under a watchdog (`GreeterProxy::greet_with`,
+/// `GreeterProxy::name_with`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallOptions {
+    /// How long to wait for the plugin before giving up on the call.
+    pub timeout: Duration,
+}
+
+impl CallOptions {
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self { timeout }
+    }
+}
+
+/// What proxy calls to a registration turned off with
+/// `PluginManager::set_enabled` do.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DisabledPolicy {
+    /// Fail with `PluginCallError::Disabled`.
+    #[default]
+    Error,
+    /// Return what the method returns by default (an empty string, or
+    /// nothing) without calling the plugin.
+    Skip,
+}
+
+/// Errors returned by watchdog proxy calls.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PluginCallError {
+    /// The plugin did not return within the configured timeout. The call
+    /// keeps running on its worker thread and the library is flagged as
+    /// stuck (see `PluginManager::stuck_plugins`) until it returns.
+    Timeout(Duration),
+    /// The plugin returned but reported a failure (for example a panic
+    /// caught inside a generated wrapper).
+    Failed,
+    /// The runner process hosting an isolated plugin died during the call
+    /// (for example because the plugin crashed) or could not be restarted.
+    Crashed(String),
+    /// A WebAssembly plugin trapped (for example on `unreachable` or an
+    /// out-of-bounds access). The trap is contained in the module instance.
+    Trapped(String),
+    /// The library was being closed or unloaded when the call started, so
+    /// the plugin was not called.
+    Unloading,
+    /// The plugin was replaced by `PluginManager::reload_by_path`; ask the
+    /// manager for handles to the new version.
+    Stale,
+    /// The plugin was built before the method was added to its trait and
+    /// has no vtable entry for it.
+    Unsupported,
+    /// The registration was turned off with `PluginManager::set_enabled`;
+    /// the plugin was not called.
+    Disabled,
+    /// The arguments of a dynamic call do not fit the method's parameters,
+    /// or the result could not be read; says why.
+    InvalidArguments(String),
+    /// `PluginHandle::instantiate` found as many instances in use as the
+    /// plugin allows.
+    PoolExhausted,
+    /// The call's `RequestContext` was cancelled or past its deadline
+    /// before the plugin was called.
+    Cancelled,
+}
+
+impl std::fmt::Display for PluginCallError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PluginCallError::Timeout(d) => write!(f, "plugin call timed out after {:?}", d),
+            PluginCallError::Failed => write!(f, "plugin call failed"),
+            PluginCallError::Crashed(e) => write!(f, "plugin runner crashed: {}", e),
+            PluginCallError::Trapped(e) => write!(f, "wasm plugin trapped: {}", e),
+            PluginCallError::Unloading => write!(f, "plugin is being unloaded"),
+            PluginCallError::Stale => write!(f, "plugin was reloaded; handle is stale"),
+            PluginCallError::Unsupported => write!(f, "plugin does not provide this method"),
+            PluginCallError::Disabled => write!(f, "plugin is disabled"),
+            PluginCallError::InvalidArguments(e) => write!(f, "invalid arguments: {}", e),
+            PluginCallError::PoolExhausted => write!(f, "plugin instance pool is exhausted"),
+            PluginCallError::Cancelled => write!(f, "request was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for PluginCallError {}
+
+/// Reader/writer guard around calls into a loaded library.
+///
+/// Proxy calls hold a shared permit while they are inside the plugin;
+/// closing or unloading takes exclusive access, which waits up to the
+/// configured timeout for those calls to return and refuses new ones in the
+/// meantime. Permits never block, so a plugin calling back into itself
+/// through the host does not deadlock; a plugin that closes its own library
+/// from inside a call gets a timeout error instead.
+#[derive(Debug)]
+pub(crate) struct CallGuard {
+    state: Mutex<GuardState>,
+    idle: Condvar,
+    timeout_ms: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct GuardState {
+    active: usize,
+    exclusive: bool,
+    // set by a forced unload; no call may enter again
+    retired: bool,
+}
+
+impl Default for CallGuard {
+    fn default() -> Self {
+        Self {
+            state: Mutex::default(),
+            idle: Condvar::new(),
+            timeout_ms: AtomicU64::new(DEFAULT_UNLOAD_TIMEOUT.as_millis() as u64),
+        }
+    }
+}
+
+impl CallGuard {
+    pub(crate) fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn set_timeout(&self, timeout: Duration) {
+        self.timeout_ms
+            .store(timeout.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, GuardState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Permit for one call, or `None` while the library is being closed or
+    /// unloaded, or after it was unloaded by force.
+    pub(crate) fn enter(&self) -> Option<CallPermit<'_>> {
+        let mut state = self.lock();
+        if state.exclusive || state.retired {
+            return None;
+        }
+        state.active += 1;
+        Some(CallPermit(self))
+    }
+
+    /// Wait for the calls in flight to return and keep new ones out until
+    /// the returned access is dropped. Fails with the number of calls still
+    /// running once the timeout has passed.
+    pub(crate) fn exclusive(&self) -> Result<ExclusiveAccess<'_>, usize> {
+        let state = self.lock();
+        let (mut state, _) = self
+            .idle
+            .wait_timeout_while(state, self.timeout(), |s| s.active > 0 || s.exclusive)
+            .unwrap_or_else(|e| e.into_inner());
+        if state.active > 0 || state.exclusive {
+            return Err(state.active);
+        }
+        state.exclusive = true;
+        Ok(ExclusiveAccess(self))
+    }
+}
+
+/// A call in flight; see `CallGuard::enter`.
+pub(crate) struct CallPermit<'a>(&'a CallGuard);
+
+impl Drop for CallPermit<'_> {
+    fn drop(&mut self) {
+        let mut state = self.0.lock();
+        state.active -= 1;
+        if state.active == 0 {
+            self.0.idle.notify_all();
+        }
+    }
+}
+
+/// Exclusive access for closing or unloading; see `CallGuard::exclusive`.
+pub(crate) struct ExclusiveAccess<'a>(&'a CallGuard);
+
+impl ExclusiveAccess<'_> {
+    /// Keep calls out for good, also once this access is dropped.
+    pub(crate) fn retire(&self) {
+        self.0.lock().retired = true;
+    }
+}
+
+impl Drop for ExclusiveAccess<'_> {
+    fn drop(&mut self) {
+        self.0.lock().exclusive = false;
+        self.0.idle.notify_all();
+    }
+}
+
+/// Moves a proxy to the worker thread (or a freshly opened library back from
+/// a loader thread). Proxies are not `Send` because they hold raw plugin
+/// pointers; the worker keeps its own strong reference so the library stays
+/// mapped until the call returns, even after a timeout.
+pub(crate) struct AssertSend<T>(pub(crate) T);
+unsafe impl<T> Send for AssertSend<T> {}
+
+const RUNNING: u8 = 0;
+const FINISHED: u8 = 1;
+const ABANDONED: u8 = 2;
+
+/// Run `f` against a clone of `proxy` on a worker thread and wait at most
+/// `opts.timeout` for it.
+pub(crate) fn with_watchdog<R, F>(
+    proxy: &GreeterProxy,
+    opts: &CallOptions,
+    f: F,
+) -> Result<R, PluginCallError>
+where
+    R: Send + 'static,
+    F: FnOnce(&GreeterProxy) -> Result<R, PluginCallError> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel();
+    // Decides who wins when the call finishes right at the deadline, so the
+    // overdue count is incremented and decremented exactly once.
+    let state = Arc::new(AtomicU8::new(RUNNING));
+    let worker_state = state.clone();
+    let worker_proxy = AssertSend(proxy.clone());
+    let spawned = std::thread::Builder::new()
+        .name("plugin-call".to_string())
+        .spawn(move || {
+            let proxy = worker_proxy;
+            let out = f(&proxy.0);
+            if worker_state
+                .compare_exchange(RUNNING, FINISHED, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+            {
+                let _ = tx.send(AssertSend(out));
+            } else {
+                proxy.0.call_state().finish_overdue_call();
+            }
+        });
+    if spawned.is_err() {
+        return Err(PluginCallError::Failed);
+    }
+
+    match rx.recv_timeout(opts.timeout) {
+        Ok(out) => out.0,
+        Err(_) => {
+            if state
+                .compare_exchange(RUNNING, ABANDONED, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+            {
+                proxy.call_state().start_overdue_call(proxy.index());
+                Err(PluginCallError::Timeout(opts.timeout))
+            } else {
+                // Finished between the timeout and the state change.
+                rx.recv().map_err(|_| PluginCallError::Failed)?.0
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::handle::test_support::fake_greeter_handle;
+
+    #[test]
+    fn fast_calls_return_their_result() {
+        let proxy = fake_greeter_handle().as_greeter().unwrap();
+        let opts = CallOptions::with_timeout(Duration::from_secs(5));
+        assert_eq!(proxy.name_with(&opts).as_deref(), Ok("fake"));
+        assert_eq!(proxy.greet_with("0", &opts), Ok(()));
+        assert!(!proxy.call_state().is_stuck());
+    }
+
+    #[test]
+    fn slow_calls_time_out_and_flag_the_library() {
+        let proxy = fake_greeter_handle().as_greeter().unwrap();
+        let opts = CallOptions::with_timeout(Duration::from_millis(20));
+        assert_eq!(
+            proxy.greet_with("300", &opts),
+            Err(PluginCallError::Timeout(Duration::from_millis(20)))
+        );
+        assert!(proxy.call_state().is_stuck());
+        let stats = proxy.call_state().counters[0].snapshot(crate::PluginId(0), "fake".into(), 0);
+        assert_eq!(stats.timeouts, 1);
+
+        // The late call eventually returns and clears the flag.
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while proxy.call_state().is_stuck() && std::time::Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!proxy.call_state().is_stuck());
+    }
+
+    #[test]
+    fn close_waits_for_calls_in_flight() {
+        let handle = fake_greeter_handle();
+        let proxy = handle.as_greeter().unwrap();
+        // The abandoned call keeps running inside the plugin for a while.
+        let opts = CallOptions::with_timeout(Duration::from_millis(20));
+        assert!(proxy.greet_with("200", &opts).is_err());
+        let start = std::time::Instant::now();
+        assert_eq!(handle.close(), Ok(None));
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn exclusive_access_waits_for_calls_in_flight() {
+        let guard = Arc::new(CallGuard::default());
+        guard.set_timeout(Duration::from_millis(20));
+        let permit = guard.enter().expect("permit");
+        // Nested calls count again instead of blocking.
+        drop(guard.enter().expect("nested permit"));
+        assert_eq!(guard.exclusive().err(), Some(1));
+
+        guard.set_timeout(Duration::from_secs(5));
+        let worker = guard.clone();
+        let (entered, wait_entered) = mpsc::channel();
+        let call = std::thread::spawn(move || {
+            let _permit = worker.enter().expect("permit");
+            entered.send(()).unwrap();
+            std::thread::sleep(Duration::from_millis(50));
+        });
+        wait_entered.recv().unwrap();
+        drop(permit);
+        let access = guard.exclusive().expect("calls returned");
+        call.join().unwrap();
+        assert!(guard.enter().is_none(), "new calls wait for the unload");
+        drop(access);
+        assert!(guard.enter().is_some());
+    }
+
+    #[test]
+    fn retired_guard_refuses_calls_for_good() {
+        let guard = CallGuard::default();
+        guard.exclusive().expect("idle").retire();
+        assert!(guard.enter().is_none());
+        // A later unload still gets through.
+        drop(guard.exclusive().expect("idle"));
+        assert!(guard.enter().is_none());
+    }
+}
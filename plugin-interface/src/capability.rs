@@ -0,0 +1,338 @@
+//! Capabilities plugins request in their manifest and the host services that
+//! back them.
+//!
+//! A plugin lists what it needs under `capabilities` in its sidecar manifest.
+//! The host grants capabilities per plugin name with
+//! `HostServices::grant`/`grant_to_all`. Only granted capabilities get their
+//! service table in the plugin's `HostContext`; the pointers for everything
+//! else are null. Plugins should reach the filesystem, the network and new
+//! threads only through these services so hosts can rely on the manifest as
+//! the plugin's sandbox contract.
+
+use crate::host::HostServices;
+use crate::HostContext;
+use serde::Deserialize;
+use std::ffi::{c_void, CStr, CString};
+use std::io::{Read, Write};
+use std::os::raw::c_char;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+/// Something a plugin may ask the host for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Capability {
+    /// Read and write files below the host's plugin data root.
+    Filesystem,
+    /// Exchange data with TCP peers.
+    Network,
+    /// Run work on additional host-managed threads.
+    SpawnThreads,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 3] = [
+        Capability::Filesystem,
+        Capability::Network,
+        Capability::SpawnThreads,
+    ];
+
+    /// The name used in manifests.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Capability::Filesystem => "filesystem",
+            Capability::Network => "network",
+            Capability::SpawnThreads => "spawn-threads",
+        }
+    }
+
+    /// Bit of this capability in `HostContext::capabilities`.
+    pub fn bit(self) -> u32 {
+        match self {
+            Capability::Filesystem => 1,
+            Capability::Network => 2,
+            Capability::SpawnThreads => 4,
+        }
+    }
+
+    pub(crate) fn mask(caps: &[Capability]) -> u32 {
+        caps.iter().fold(0, |m, c| m | c.bit())
+    }
+
+    pub(crate) fn from_mask(mask: u32) -> Vec<Capability> {
+        Self::ALL
+            .into_iter()
+            .filter(|c| mask & c.bit() != 0)
+            .collect()
+    }
+}
+
+/// File access for plugins granted `Capability::Filesystem`. Paths are
+/// relative to the host's plugin data root (`HostServices::with_filesystem_root`);
+/// absolute paths and `..` components are refused.
+#[repr(C)]
+pub struct FilesystemService {
+    /// Read the file at the nul-terminated `path`. Returns a buffer to
+    /// release with `free_buffer` and stores its length in `len`, or returns
+    /// null on failure.
+    pub read_file: extern "C" fn(*mut c_void, *const c_char, *mut usize) -> *mut u8,
+    /// Replace the file at `path` with `len` bytes from `data`. Returns 0 on
+    /// success.
+    pub write_file: extern "C" fn(*mut c_void, *const c_char, *const u8, usize) -> i32,
+    pub free_buffer: extern "C" fn(*mut u8, usize),
+}
+
+/// Network access for plugins granted `Capability::Network`.
+#[repr(C)]
+pub struct NetworkService {
+    /// Connect to the nul-terminated `addr` (`host:port`), send `len` bytes
+    /// from `data`, close the write half and read the reply until the peer
+    /// closes. Returns a buffer to release with `free_buffer` and stores its
+    /// length in `reply_len`, or returns null on failure.
+    pub tcp_exchange:
+        extern "C" fn(*mut c_void, *const c_char, *const u8, usize, *mut usize) -> *mut u8,
+    pub free_buffer: extern "C" fn(*mut u8, usize),
+}
+
+/// Thread creation for plugins granted `Capability::SpawnThreads`.
+#[repr(C)]
+pub struct ThreadService {
+    /// Run `entry(arg)` on a new host thread. Returns 0 if the thread was
+    /// started.
+    pub spawn: extern "C" fn(*mut c_void, extern "C" fn(*mut c_void), *mut c_void) -> i32,
+}
+
+pub(crate) static FILESYSTEM_SERVICE: FilesystemService = FilesystemService {
+    read_file: fs_read_file,
+    write_file: fs_write_file,
+    free_buffer,
+};
+
+pub(crate) static NETWORK_SERVICE: NetworkService = NetworkService {
+    tcp_exchange: net_tcp_exchange,
+    free_buffer,
+};
+
+pub(crate) static THREAD_SERVICE: ThreadService = ThreadService {
+    spawn: thread_spawn,
+};
+
+impl HostContext {
+    /// Whether the host granted `cap` to this plugin.
+    pub fn has_capability(&self, cap: Capability) -> bool {
+        self.capabilities & cap.bit() != 0
+    }
+
+    /// Read a file through the filesystem service. `None` if the capability
+    /// was not granted or the read failed.
+    pub fn read_file(&self, path: &str) -> Option<Vec<u8>> {
+        let fs = unsafe { self.filesystem.as_ref() }?;
+        let c_path = CString::new(path).ok()?;
+        let mut len = 0usize;
+        let buf = (fs.read_file)(self.user_data, c_path.as_ptr(), &mut len);
+        take_buffer(buf, len, fs.free_buffer)
+    }
+
+    /// Write a file through the filesystem service. Returns false if the
+    /// capability was not granted or the write failed.
+    pub fn write_file(&self, path: &str, data: &[u8]) -> bool {
+        let Some(fs) = (unsafe { self.filesystem.as_ref() }) else {
+            return false;
+        };
+        let Ok(c_path) = CString::new(path) else {
+            return false;
+        };
+        (fs.write_file)(self.user_data, c_path.as_ptr(), data.as_ptr(), data.len()) == 0
+    }
+
+    /// Send `request` to `addr` and return the peer's reply. `None` if the
+    /// capability was not granted or the exchange failed.
+    pub fn tcp_exchange(&self, addr: &str, request: &[u8]) -> Option<Vec<u8>> {
+        let net = unsafe { self.network.as_ref() }?;
+        let c_addr = CString::new(addr).ok()?;
+        let mut len = 0usize;
+        let buf = (net.tcp_exchange)(
+            self.user_data,
+            c_addr.as_ptr(),
+            request.as_ptr(),
+            request.len(),
+            &mut len,
+        );
+        take_buffer(buf, len, net.free_buffer)
+    }
+
+    /// Run `entry(arg)` on a new host thread. Returns false if the capability
+    /// was not granted or the thread could not be started.
+    pub fn spawn_thread(&self, entry: extern "C" fn(*mut c_void), arg: *mut c_void) -> bool {
+        match unsafe { self.threads.as_ref() } {
+            Some(threads) => (threads.spawn)(self.user_data, entry, arg) == 0,
+            None => false,
+        }
+    }
+}
+
+fn take_buffer(buf: *mut u8, len: usize, free: extern "C" fn(*mut u8, usize)) -> Option<Vec<u8>> {
+    if buf.is_null() {
+        return None;
+    }
+    let out = unsafe { std::slice::from_raw_parts(buf, len) }.to_vec();
+    free(buf, len);
+    Some(out)
+}
+
+fn into_buffer(data: Vec<u8>, len: *mut usize) -> *mut u8 {
+    unsafe { *len = data.len() };
+    Box::into_raw(data.into_boxed_slice()) as *mut u8
+}
+
+extern "C" fn free_buffer(buf: *mut u8, len: usize) {
+    if !buf.is_null() {
+        drop(unsafe { Box::from_raw(std::ptr::slice_from_raw_parts_mut(buf, len)) });
+    }
+}
+
+fn services<'a>(user_data: *mut c_void) -> Option<&'a HostServices> {
+    unsafe { (user_data as *const HostServices).as_ref() }
+}
+
+fn c_str<'a>(p: *const c_char) -> Option<&'a str> {
+    if p.is_null() {
+        return None;
+    }
+    unsafe { CStr::from_ptr(p) }.to_str().ok()
+}
+
+/// Resolve a plugin-supplied relative path below `root`.
+pub(crate) fn resolve_in_root(root: &Path, path: &str) -> Option<PathBuf> {
+    let rel = Path::new(path);
+    if rel
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return None;
+    }
+    Some(root.join(rel))
+}
+
+fn fs_path(user_data: *mut c_void, path: *const c_char) -> Option<PathBuf> {
+    let root = services(user_data)?.filesystem_root()?;
+    resolve_in_root(root, c_str(path)?)
+}
+
+extern "C" fn fs_read_file(
+    user_data: *mut c_void,
+    path: *const c_char,
+    len: *mut usize,
+) -> *mut u8 {
+    if len.is_null() {
+        return std::ptr::null_mut();
+    }
+    match fs_path(user_data, path).and_then(|p| std::fs::read(p).ok()) {
+        Some(data) => into_buffer(data, len),
+        None => std::ptr::null_mut(),
+    }
+}
+
+extern "C" fn fs_write_file(
+    user_data: *mut c_void,
+    path: *const c_char,
+    data: *const u8,
+    len: usize,
+) -> i32 {
+    let Some(path) = fs_path(user_data, path) else {
+        return -1;
+    };
+    if data.is_null() && len != 0 {
+        return -1;
+    }
+    let bytes: &[u8] = if len == 0 {
+        &[]
+    } else {
+        unsafe { std::slice::from_raw_parts(data, len) }
+    };
+    match std::fs::write(path, bytes) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+const NETWORK_TIMEOUT: Duration = Duration::from_secs(30);
+
+extern "C" fn net_tcp_exchange(
+    _user_data: *mut c_void,
+    addr: *const c_char,
+    data: *const u8,
+    len: usize,
+    reply_len: *mut usize,
+) -> *mut u8 {
+    if reply_len.is_null() || (data.is_null() && len != 0) {
+        return std::ptr::null_mut();
+    }
+    let Some(addr) = c_str(addr) else {
+        return std::ptr::null_mut();
+    };
+    let request: &[u8] = if len == 0 {
+        &[]
+    } else {
+        unsafe { std::slice::from_raw_parts(data, len) }
+    };
+    let exchange = || -> std::io::Result<Vec<u8>> {
+        let mut stream = std::net::TcpStream::connect(addr)?;
+        stream.set_read_timeout(Some(NETWORK_TIMEOUT))?;
+        stream.set_write_timeout(Some(NETWORK_TIMEOUT))?;
+        stream.write_all(request)?;
+        stream.shutdown(std::net::Shutdown::Write)?;
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply)?;
+        Ok(reply)
+    };
+    match exchange() {
+        Ok(reply) => into_buffer(reply, reply_len),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// Moves the plugin's argument pointer to the new thread; the plugin is
+/// responsible for it being safe to use there.
+struct PluginArg(*mut c_void);
+unsafe impl Send for PluginArg {}
+
+extern "C" fn thread_spawn(
+    _user_data: *mut c_void,
+    entry: extern "C" fn(*mut c_void),
+    arg: *mut c_void,
+) -> i32 {
+    let arg = PluginArg(arg);
+    let spawned = std::thread::Builder::new()
+        .name("plugin-worker".into())
+        .spawn(move || {
+            let arg = arg;
+            entry(arg.0)
+        });
+    match spawned {
+        Ok(_) => 0,
+        Err(_) => -1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_cannot_escape_the_root() {
+        let root = Path::new("/data/plugins");
+        assert_eq!(
+            resolve_in_root(root, "cache/state.json"),
+            Some(root.join("cache/state.json"))
+        );
+        assert_eq!(resolve_in_root(root, "../secrets"), None);
+        assert_eq!(resolve_in_root(root, "/etc/passwd"), None);
+    }
+
+    #[test]
+    fn masks_round_trip() {
+        let caps = [Capability::Network, Capability::SpawnThreads];
+        assert_eq!(Capability::from_mask(Capability::mask(&caps)), caps);
+    }
+}
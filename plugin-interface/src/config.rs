@@ -0,0 +1,156 @@
+//! Per-plugin configuration handed to plugins as they are registered, read
+//! back with `HostContext::plugin_config` and passed to the trait's
+//! `configure` hook by `#[plugin_impl]`.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Configuration strings keyed by plugin name, for
+/// `PluginManager::load_plugins_with_config`.
+///
+/// A library's entry is the one named after its manifest, its file name
+/// (`libplugin_upper.so`), or its file name without the platform's prefix
+/// and extension (`plugin_upper`), tried in that order. The string is
+/// handed to the plugin as is, so JSON and TOML both work.
+///
+/// ```
+/// use plugin_interface::ConfigSource;
+///
+/// let config = ConfigSource::from_toml(
+///     r#"
+///     [plugin-upper]
+///     suffix = "!"
+///
+///     [plugin_a]
+///     greeting = "hi"
+///     "#,
+/// )
+/// .unwrap()
+/// .with_plugin("plugin-multi", r#"{"loud": true}"#);
+/// assert_eq!(config.get("plugin-upper"), Some("suffix = \"!\"\n"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConfigSource {
+    entries: BTreeMap<String, String>,
+}
+
+impl ConfigSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hand `config` to the plugin named `plugin`, replacing any entry it
+    /// had.
+    pub fn with_plugin(mut self, plugin: impl Into<String>, config: impl Into<String>) -> Self {
+        self.entries.insert(plugin.into(), config.into());
+        self
+    }
+
+    /// One entry per top-level key of the TOML document: a table becomes
+    /// that table as a TOML document, a string is taken as is (e.g. JSON),
+    /// and any other value is written out as TOML.
+    pub fn from_toml(text: &str) -> Result<Self, toml::de::Error> {
+        let table: toml::Table = text.parse()?;
+        let entries = table
+            .into_iter()
+            .map(|(plugin, value)| {
+                let config = match value {
+                    toml::Value::Table(t) => t.to_string(),
+                    toml::Value::String(s) => s,
+                    other => other.to_string(),
+                };
+                (plugin, config)
+            })
+            .collect();
+        Ok(Self { entries })
+    }
+
+    /// The contents of every `<plugin>.toml` and `<plugin>.json` file in
+    /// `dir`, keyed by file stem.
+    pub fn from_dir(dir: &Path) -> std::io::Result<Self> {
+        let mut entries = BTreeMap::new();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            let is_config = path.extension().is_some_and(|e| e == "toml" || e == "json");
+            let Some(stem) = path.file_stem().filter(|_| is_config && path.is_file()) else {
+                continue;
+            };
+            entries.insert(
+                stem.to_string_lossy().into_owned(),
+                std::fs::read_to_string(&path)?,
+            );
+        }
+        Ok(Self { entries })
+    }
+
+    /// The entry for `plugin`, if any.
+    pub fn get(&self, plugin: &str) -> Option<&str> {
+        self.entries.get(plugin).map(String::as_str)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Take over `other`'s entries, replacing those of the same plugin.
+    pub(crate) fn merge(&mut self, other: &ConfigSource) {
+        self.entries
+            .extend(other.entries.iter().map(|(k, v)| (k.clone(), v.clone())));
+    }
+
+    /// The entry for the library at `path`, whose manifest is named
+    /// `manifest_name` if it has one.
+    pub(crate) fn for_library(&self, path: &Path, manifest_name: Option<&str>) -> Option<&str> {
+        let file_name = path.file_name().map(|n| n.to_string_lossy());
+        let bare = path.file_stem().map(|s| {
+            let s = s.to_string_lossy();
+            s.strip_prefix(std::env::consts::DLL_PREFIX)
+                .unwrap_or(&s)
+                .to_string()
+        });
+        manifest_name
+            .and_then(|n| self.get(n))
+            .or_else(|| file_name.and_then(|n| self.get(&n)))
+            .or_else(|| bare.and_then(|n| self.get(&n)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_are_found_by_manifest_or_file_name() {
+        let lib = format!(
+            "{}shout.{}",
+            std::env::consts::DLL_PREFIX,
+            std::env::consts::DLL_EXTENSION
+        );
+        let config = ConfigSource::from_toml("shout = '{\"volume\": 11}'\nother = 3")
+            .unwrap()
+            .with_plugin("named", "level = 1");
+        let path = Path::new("/plugins").join(&lib);
+
+        assert_eq!(config.for_library(&path, None), Some("{\"volume\": 11}"));
+        assert_eq!(config.for_library(&path, Some("named")), Some("level = 1"));
+        assert_eq!(
+            config.for_library(&path, Some("unknown")),
+            Some("{\"volume\": 11}")
+        );
+        assert_eq!(config.get("other"), Some("3"));
+        assert_eq!(config.for_library(Path::new("/plugins/x.so"), None), None);
+    }
+
+    #[test]
+    fn directories_hold_one_file_per_plugin() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.toml"), "x = 1\n").unwrap();
+        std::fs::write(dir.path().join("b.json"), "{}").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "ignored").unwrap();
+
+        let config = ConfigSource::from_dir(dir.path()).unwrap();
+        assert_eq!(config.get("a"), Some("x = 1\n"));
+        assert_eq!(config.get("b"), Some("{}"));
+        assert_eq!(config.get("notes"), None);
+    }
+}
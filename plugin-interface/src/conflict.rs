@@ -0,0 +1,207 @@
+//! What `PluginManager` does when a library it loads registers a name that
+//! a registration of another loaded library already uses.
+
+use crate::handle::{PluginHandle, PluginId};
+use std::path::PathBuf;
+
+/// How `PluginManager` handles two libraries registering the same name
+/// for the same trait. Registrations without a name never conflict, and
+/// with `PluginManager::set_multi_version` neither do two versions of the
+/// same plugin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictPolicy {
+    /// Load both and only report the conflict.
+    #[default]
+    KeepAll,
+    /// Fail the load with `PluginLoadError::Conflict`.
+    Error,
+    /// Keep the registration loaded first. The new one is not returned and
+    /// is disabled; a library left with no registration is unloaded again.
+    FirstWins,
+    /// Keep the registration whose manifest has the highest semver version.
+    /// A losing registration that was already loaded is disabled (see
+    /// `PluginManager::set_enabled`). Ties and libraries without a parsable
+    /// version fall back to `FirstWins`.
+    HighestVersion,
+}
+
+/// What the conflict policy did about a conflict.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictResolution {
+    /// Both registrations stayed enabled.
+    KeptBoth,
+    /// The registration already loaded won; the new one was left out.
+    KeptExisting,
+    /// The new registration won; the one already loaded was disabled.
+    KeptIncoming,
+    /// The new library was not loaded.
+    Refused,
+}
+
+/// Two libraries registering the same name; see
+/// `PluginManager::take_conflicts`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistrationConflict {
+    /// The registration name both use.
+    pub name: String,
+    /// Library of the registration that was already loaded.
+    pub existing: PathBuf,
+    /// Library being loaded that registers the name again.
+    pub incoming: PathBuf,
+    pub resolution: ConflictResolution,
+}
+
+/// A named registration, as conflicts are evaluated.
+pub(crate) struct Registered {
+    pub(crate) id: PluginId,
+    name: String,
+    path: PathBuf,
+    // manifest name of the plugin the registration belongs to
+    plugin: Option<String>,
+    version: Option<semver::Version>,
+}
+
+impl Registered {
+    /// `handle`'s registration, if it has a name.
+    pub(crate) fn of(handle: &PluginHandle) -> Option<Self> {
+        Some(Self {
+            id: handle.id(),
+            name: handle.registration_name()?,
+            path: handle.path().to_path_buf(),
+            plugin: handle.manifest().map(|m| m.name.clone()),
+            version: handle
+                .manifest()
+                .and_then(|m| semver::Version::parse(&m.version).ok()),
+        })
+    }
+
+    /// Whether `self` and `other` come from different versions of the same
+    /// plugin.
+    fn other_version_of(&self, other: &Registered) -> bool {
+        self.plugin.is_some()
+            && self.plugin == other.plugin
+            && self.version.is_some()
+            && other.version.is_some()
+            && self.version != other.version
+    }
+}
+
+/// The conflicts of `incoming` with the enabled registrations `existing`
+/// under `policy`, each with the id of the registration already loaded.
+/// `incoming` is kept unless one of them resolved otherwise. With
+/// `versions_coexist`, registrations of other versions of the same plugin
+/// are not rivals.
+pub(crate) fn resolve(
+    policy: ConflictPolicy,
+    existing: &[Registered],
+    incoming: &Registered,
+    versions_coexist: bool,
+) -> Vec<(PluginId, RegistrationConflict)> {
+    let rivals: Vec<&Registered> = existing
+        .iter()
+        .filter(|e| e.name == incoming.name && e.path != incoming.path)
+        .filter(|e| !(versions_coexist && e.other_version_of(incoming)))
+        .collect();
+    let resolution = match policy {
+        ConflictPolicy::KeepAll => ConflictResolution::KeptBoth,
+        ConflictPolicy::Error => ConflictResolution::Refused,
+        ConflictPolicy::FirstWins => ConflictResolution::KeptExisting,
+        ConflictPolicy::HighestVersion => {
+            let newest = rivals.iter().all(
+                |e| matches!((&incoming.version, &e.version), (Some(new), Some(old)) if new > old),
+            );
+            if newest {
+                ConflictResolution::KeptIncoming
+            } else {
+                ConflictResolution::KeptExisting
+            }
+        }
+    };
+    rivals
+        .into_iter()
+        .map(|e| {
+            let conflict = RegistrationConflict {
+                name: incoming.name.clone(),
+                existing: e.path.clone(),
+                incoming: incoming.path.clone(),
+                resolution,
+            };
+            (e.id, conflict)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registered(id: u128, name: &str, path: &str, version: Option<&str>) -> Registered {
+        Registered {
+            id: PluginId(id),
+            name: name.to_string(),
+            path: PathBuf::from(path),
+            plugin: Some("plugin".to_string()),
+            version: version.map(|v| semver::Version::parse(v).unwrap()),
+        }
+    }
+
+    #[test]
+    fn policies_pick_a_winner() {
+        let existing = [
+            registered(1, "greeter", "/a.so", Some("1.0.0")),
+            registered(2, "other", "/a.so", Some("1.0.0")),
+        ];
+        let newer = registered(3, "greeter", "/b.so", Some("1.2.0"));
+        let unversioned = registered(4, "greeter", "/c.so", None);
+        let resolution = |policy, incoming| {
+            resolve(policy, &existing, incoming, false)
+                .into_iter()
+                .map(|(id, c)| (id, c.resolution))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            resolution(ConflictPolicy::KeepAll, &newer),
+            [(PluginId(1), ConflictResolution::KeptBoth)]
+        );
+        assert_eq!(
+            resolution(ConflictPolicy::Error, &newer),
+            [(PluginId(1), ConflictResolution::Refused)]
+        );
+        assert_eq!(
+            resolution(ConflictPolicy::FirstWins, &newer),
+            [(PluginId(1), ConflictResolution::KeptExisting)]
+        );
+        assert_eq!(
+            resolution(ConflictPolicy::HighestVersion, &newer),
+            [(PluginId(1), ConflictResolution::KeptIncoming)]
+        );
+        assert_eq!(
+            resolution(ConflictPolicy::HighestVersion, &unversioned),
+            [(PluginId(1), ConflictResolution::KeptExisting)]
+        );
+        assert!(resolve(
+            ConflictPolicy::Error,
+            &existing,
+            &registered(5, "greeter", "/a.so", None),
+            false
+        )
+        .is_empty());
+
+        // Side by side, only another plugin or the same version conflicts.
+        assert!(resolve(ConflictPolicy::Error, &existing, &newer, true).is_empty());
+        let same_version = registered(6, "greeter", "/d.so", Some("1.0.0"));
+        assert_eq!(
+            resolve(ConflictPolicy::Error, &existing, &same_version, true).len(),
+            1
+        );
+        let other_plugin = Registered {
+            plugin: Some("other".to_string()),
+            ..registered(7, "greeter", "/e.so", Some("2.0.0"))
+        };
+        assert_eq!(
+            resolve(ConflictPolicy::Error, &existing, &other_plugin, true).len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,232 @@
+//! Lifecycle counters a plugin keeps for the registrations of one trait,
+//! exported by `#[plugin_aggregates]` as `plugin_counters_<Trait>_v<N>`.
+
+use crate::{find_versioned_symbol, symbols, PluginTrait};
+use libloading::Library;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// What a plugin did with the registrations of one trait since it was
+/// loaded, as `plugin_counters_<Trait>_v<N>` reports it.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PluginCounters {
+    /// Registrations and instances the register function made.
+    pub registrations_created: u64,
+    /// Registrations and instances the unregister function released.
+    pub registrations_destroyed: u64,
+    /// Calls into the trait's methods through the generated wrappers.
+    pub calls: u64,
+    /// Panics the generated wrappers caught, in methods and hooks alike.
+    pub panics: u64,
+}
+
+impl PluginCounters {
+    /// Registrations made and not released yet.
+    pub fn live_registrations(&self) -> u64 {
+        self.registrations_created
+            .saturating_sub(self.registrations_destroyed)
+    }
+}
+
+/// The atomics behind `PluginCounters`, which `#[plugin_aggregates]`
+/// declares once per crate and the `#[plugin_impl]` code updates.
+#[doc(hidden)]
+#[derive(Debug, Default)]
+pub struct AtomicPluginCounters {
+    registrations_created: AtomicU64,
+    registrations_destroyed: AtomicU64,
+    calls: AtomicU64,
+    panics: AtomicU64,
+}
+
+impl AtomicPluginCounters {
+    pub const fn new() -> Self {
+        Self {
+            registrations_created: AtomicU64::new(0),
+            registrations_destroyed: AtomicU64::new(0),
+            calls: AtomicU64::new(0),
+            panics: AtomicU64::new(0),
+        }
+    }
+
+    pub fn created(&self) {
+        self.registrations_created.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn destroyed(&self) {
+        self.registrations_destroyed.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn call(&self) {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn panic(&self) {
+        self.panics.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn load(&self) -> PluginCounters {
+        PluginCounters {
+            registrations_created: self.registrations_created.load(Ordering::SeqCst),
+            registrations_destroyed: self.registrations_destroyed.load(Ordering::SeqCst),
+            calls: self.calls.load(Ordering::SeqCst),
+            panics: self.panics.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Take back the registrations made and released since `before`, for
+    /// instances the host never sees, such as the self-test's.
+    pub fn forget_since(&self, before: &PluginCounters) {
+        let now = self.load();
+        self.registrations_created.fetch_sub(
+            now.registrations_created
+                .saturating_sub(before.registrations_created),
+            Ordering::SeqCst,
+        );
+        self.registrations_destroyed.fetch_sub(
+            now.registrations_destroyed
+                .saturating_sub(before.registrations_destroyed),
+            Ordering::SeqCst,
+        );
+    }
+}
+
+/// The counters the entries of `<Trait>Optional::for_impl` and
+/// `<Trait>Context::for_impl` update, as the `#[plugin_impl]` wrappers
+/// update the trait's `<TRAIT>_COUNTERS`. `#[plugin_aggregates]`
+/// implements it for that static.
+#[doc(hidden)]
+pub trait CountersSource {
+    fn counters() -> Option<&'static AtomicPluginCounters>;
+
+    fn call() {
+        if let Some(counters) = Self::counters() {
+            counters.call();
+        }
+    }
+
+    fn panic() {
+        if let Some(counters) = Self::counters() {
+            counters.panic();
+        }
+    }
+}
+
+/// Counts nothing, for vtables the host builds for itself, such as a
+/// module loader's.
+#[doc(hidden)]
+pub struct Uncounted;
+
+impl CountersSource for Uncounted {
+    fn counters() -> Option<&'static AtomicPluginCounters> {
+        None
+    }
+}
+
+/// Read the lifecycle counters a loaded plugin keeps for `trait_name`.
+///
+/// Looks up `plugin_counters_<Trait>_v<N>` at the supported ABI levels.
+/// Plugins built before it existed only export the
+/// `plugin_unmaker_counter_<Trait>_v<N>` getter; for those only
+/// `registrations_destroyed` is filled in.
+pub fn get_counters(lib: &Library, trait_name: &str) -> Result<PluginCounters, String> {
+    unsafe {
+        if let Some((func, _)) = find_versioned_symbol::<unsafe extern "C" fn() -> PluginCounters>(
+            lib,
+            symbols::COUNTERS,
+            trait_name,
+        ) {
+            return Ok(func());
+        }
+        let (func, _) = find_versioned_symbol::<unsafe extern "C" fn() -> u64>(
+            lib,
+            symbols::UNMAKER_COUNTER,
+            trait_name,
+        )
+        .ok_or_else(|| format!("plugin exports no counters for {}", trait_name))?;
+        Ok(PluginCounters {
+            registrations_destroyed: func(),
+            ..PluginCounters::default()
+        })
+    }
+}
+
+/// Typed variant of `get_counters` that accepts a `PluginTrait` enum
+/// instead of a raw string.
+pub fn get_counters_for(lib: &Library, trait_id: PluginTrait) -> Result<PluginCounters, String> {
+    get_counters(lib, trait_id.as_str())
+}
+
+/// The counters of a library at the ABI level it registered with, if it
+/// exports them.
+///
+/// # Safety
+/// `lib` must be a plugin library whose counter getters, if any, have the
+/// signatures `#[plugin_aggregates]` gives them.
+pub(crate) unsafe fn library_counters(
+    lib: &Library,
+    trait_id: PluginTrait,
+    abi: u32,
+) -> Option<PluginCounters> {
+    let counters_sym = symbols::versioned_c(symbols::COUNTERS, trait_id.as_str(), abi);
+    if let Ok(getter) = lib.get::<unsafe extern "C" fn() -> PluginCounters>(counters_sym.as_bytes())
+    {
+        return Some(getter());
+    }
+    let counter_sym = symbols::versioned_c(symbols::UNMAKER_COUNTER, trait_id.as_str(), abi);
+    let getter = lib
+        .get::<unsafe extern "C" fn() -> u64>(counter_sym.as_bytes())
+        .ok()?;
+    Some(PluginCounters {
+        registrations_destroyed: getter(),
+        ..PluginCounters::default()
+    })
+}
+
+/// Call a raw counters getter function pointer and return its value.
+/// This is provided to make it easy to unit-test the calling convention and
+/// the helper logic without requiring a real dynamic library export.
+pub fn call_counters_fn(func: unsafe extern "C" fn() -> PluginCounters) -> PluginCounters {
+    unsafe { func() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Export a test getter symbol from this test module so the counters can
+    // be looked up in the current process.
+    #[no_mangle]
+    pub extern "C" fn plugin_counters_TestTrait_v1() -> PluginCounters {
+        PluginCounters {
+            registrations_created: 3,
+            registrations_destroyed: 2,
+            calls: 42,
+            panics: 1,
+        }
+    }
+
+    #[test]
+    fn counters_getter_returns_the_struct_by_value() {
+        let counters = call_counters_fn(plugin_counters_TestTrait_v1);
+        assert_eq!(counters.calls, 42);
+        assert_eq!(counters.live_registrations(), 1);
+    }
+
+    #[test]
+    fn forgetting_takes_back_only_registrations() {
+        let counters = AtomicPluginCounters::new();
+        counters.created();
+        let before = counters.load();
+        counters.created();
+        counters.call();
+        counters.destroyed();
+        counters.forget_since(&before);
+        let after = counters.load();
+        assert_eq!(
+            (after.registrations_created, after.registrations_destroyed),
+            (1, 0)
+        );
+        assert_eq!(after.calls, 1);
+    }
+}
@@ -0,0 +1,295 @@
+use crate::manifest::Candidate;
+use std::collections::{HashMap, HashSet};
+
+/// Errors produced while resolving plugin dependencies declared in manifests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DependencyError {
+    /// `plugin` depends on `dependency`, which is neither loaded nor part of
+    /// the batch being loaded.
+    Missing {
+        plugin: String,
+        dependency: String,
+        requirement: String,
+    },
+    /// The dependency is available but its version does not satisfy the
+    /// requirement (or either side is not valid semver).
+    VersionMismatch {
+        plugin: String,
+        dependency: String,
+        requirement: String,
+        found: String,
+    },
+    /// The listed plugins depend on each other in a cycle.
+    Cycle(Vec<String>),
+}
+
+impl std::fmt::Display for DependencyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DependencyError::Missing {
+                plugin,
+                dependency,
+                requirement,
+            } => write!(
+                f,
+                "plugin {} requires {} {} which is not available",
+                plugin, dependency, requirement
+            ),
+            DependencyError::VersionMismatch {
+                plugin,
+                dependency,
+                requirement,
+                found,
+            } => write!(
+                f,
+                "plugin {} requires {} {} but found version {}",
+                plugin, dependency, requirement, found
+            ),
+            DependencyError::Cycle(names) => {
+                write!(f, "dependency cycle between plugins: {}", names.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for DependencyError {}
+
+/// Returns true if `version` satisfies the semver requirement `req`.
+pub(crate) fn version_satisfies(version: &str, req: &str) -> bool {
+    match (
+        semver::Version::parse(version),
+        semver::VersionReq::parse(req),
+    ) {
+        (Ok(v), Ok(r)) => r.matches(&v),
+        _ => false,
+    }
+}
+
+/// Order `candidates` so every plugin comes after the plugins it depends on.
+///
+/// `loaded` maps the names of already-loaded plugins to their versions, more
+/// than one when several versions are loaded side by side; dependencies
+/// satisfied by those are not reordered. A dependency with several copies
+/// in the batch waits on the first one whose version satisfies it. Candidates without a
+/// manifest have no dependencies and keep their relative position after the
+/// manifest-described ones. Among plugins that are ready at the same time the
+/// discovery order is preserved so loading stays deterministic.
+pub(crate) fn order_candidates(
+    candidates: Vec<Candidate>,
+    loaded: &HashMap<String, Vec<String>>,
+) -> Result<Vec<Candidate>, DependencyError> {
+    let (with_manifest, bare): (Vec<Candidate>, Vec<Candidate>) =
+        candidates.into_iter().partition(|c| c.manifest.is_some());
+
+    let mut batch: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, c) in with_manifest.iter().enumerate() {
+        batch
+            .entry(c.manifest.as_ref().unwrap().name.as_str())
+            .or_default()
+            .push(i);
+    }
+    let version = |i: usize| with_manifest[i].manifest.as_ref().unwrap().version.as_str();
+
+    // Edges from each candidate index to the batch indices it waits on.
+    let mut waits_on: Vec<HashSet<usize>> = vec![HashSet::new(); with_manifest.len()];
+    for (i, c) in with_manifest.iter().enumerate() {
+        let m = c.manifest.as_ref().unwrap();
+        for (dep, req) in &m.dependencies {
+            let in_batch = batch.get(dep.as_str()).map_or(&[][..], Vec::as_slice);
+            let in_loaded = loaded.get(dep).map_or(&[][..], Vec::as_slice);
+            if let Some(&j) = in_batch
+                .iter()
+                .find(|&&j| version_satisfies(version(j), req))
+            {
+                waits_on[i].insert(j);
+            } else if !in_loaded.iter().any(|v| version_satisfies(v, req)) {
+                let found = in_batch
+                    .first()
+                    .map(|&j| version(j))
+                    .or(in_loaded.first().map(String::as_str));
+                return Err(match found {
+                    Some(found) => DependencyError::VersionMismatch {
+                        plugin: m.name.clone(),
+                        dependency: dep.clone(),
+                        requirement: req.clone(),
+                        found: found.to_string(),
+                    },
+                    None => DependencyError::Missing {
+                        plugin: m.name.clone(),
+                        dependency: dep.clone(),
+                        requirement: req.clone(),
+                    },
+                });
+            }
+        }
+    }
+
+    let mut order = Vec::with_capacity(with_manifest.len());
+    let mut done = vec![false; with_manifest.len()];
+    while order.len() < with_manifest.len() {
+        let next =
+            (0..with_manifest.len()).find(|&i| !done[i] && waits_on[i].iter().all(|&j| done[j]));
+        match next {
+            Some(i) => {
+                done[i] = true;
+                order.push(i);
+            }
+            None => {
+                let names = (0..with_manifest.len())
+                    .filter(|&i| !done[i])
+                    .map(|i| with_manifest[i].manifest.as_ref().unwrap().name.clone())
+                    .collect();
+                return Err(DependencyError::Cycle(names));
+            }
+        }
+    }
+
+    let mut slots: Vec<Option<Candidate>> = with_manifest.into_iter().map(Some).collect();
+    let mut out: Vec<Candidate> = order
+        .into_iter()
+        .map(|i| slots[i].take().expect("each index ordered once"))
+        .collect();
+    out.extend(bare);
+    Ok(out)
+}
+
+/// Split a batch ordered by `order_candidates` into waves: every plugin comes
+/// in a later wave than the batch plugins it depends on, so the plugins of one
+/// wave can be opened concurrently. Each wave keeps the batch order.
+pub(crate) fn waves(ordered: Vec<Candidate>) -> Vec<Vec<Candidate>> {
+    let mut wave_of: HashMap<String, usize> = HashMap::new();
+    let mut waves: Vec<Vec<Candidate>> = Vec::new();
+    for c in ordered {
+        let wave = match &c.manifest {
+            Some(m) => {
+                let wave = m
+                    .dependencies
+                    .keys()
+                    .filter_map(|dep| wave_of.get(dep))
+                    .map(|w| w + 1)
+                    .max()
+                    .unwrap_or(0);
+                // With several versions in the batch, dependents wait for
+                // the latest wave any of them is in.
+                let entry = wave_of.entry(m.name.clone()).or_default();
+                *entry = (*entry).max(wave);
+                wave
+            }
+            None => 0,
+        };
+        if waves.len() <= wave {
+            waves.resize_with(wave + 1, Vec::new);
+        }
+        waves[wave].push(c);
+    }
+    waves
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PluginManifest;
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
+    fn candidate(name: &str, deps: &[(&str, &str)]) -> Candidate {
+        Candidate {
+            path: PathBuf::from(format!("lib{}.so", name)),
+            manifest: Some(PluginManifest {
+                name: name.to_string(),
+                version: "1.0.0".to_string(),
+                traits: vec!["Greeter".to_string()],
+                min_host_abi: 1,
+                library: None,
+                dependencies: deps
+                    .iter()
+                    .map(|(n, r)| (n.to_string(), r.to_string()))
+                    .collect::<BTreeMap<_, _>>(),
+                capabilities: Vec::new(),
+                groups: Vec::new(),
+                priority: None,
+            }),
+        }
+    }
+
+    fn names(cands: &[Candidate]) -> Vec<String> {
+        cands
+            .iter()
+            .map(|c| c.manifest.as_ref().unwrap().name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn orders_dependencies_first() {
+        let cands = vec![
+            candidate("app", &[("core", "1")]),
+            candidate("core", &[]),
+            candidate("ext", &[("app", "^1.0"), ("core", "1")]),
+        ];
+        let ordered = order_candidates(cands, &HashMap::new()).expect("order");
+        assert_eq!(names(&ordered), vec!["core", "app", "ext"]);
+    }
+
+    #[test]
+    fn waves_follow_dependency_depth() {
+        let cands = vec![
+            candidate("core", &[]),
+            candidate("log", &[]),
+            candidate("app", &[("core", "1")]),
+            candidate("ext", &[("app", "1"), ("log", "1")]),
+        ];
+        let waves: Vec<Vec<String>> = waves(cands).iter().map(|w| names(w)).collect();
+        assert_eq!(waves, vec![vec!["core", "log"], vec!["app"], vec!["ext"]]);
+    }
+
+    #[test]
+    fn reports_missing_dependency() {
+        let cands = vec![candidate("app", &[("core", "1")])];
+        let err = order_candidates(cands, &HashMap::new()).unwrap_err();
+        assert!(matches!(err, DependencyError::Missing { .. }));
+    }
+
+    #[test]
+    fn accepts_already_loaded_dependency() {
+        let cands = vec![candidate("app", &[("core", "1")])];
+        let mut loaded = HashMap::new();
+        loaded.insert("core".to_string(), vec!["1.2.0".to_string()]);
+        assert!(order_candidates(cands, &loaded).is_ok());
+    }
+
+    #[test]
+    fn picks_the_version_that_satisfies_the_requirement() {
+        let mut core2 = candidate("core", &[]);
+        core2.manifest.as_mut().unwrap().version = "2.0.0".to_string();
+        core2.path = PathBuf::from("libcore2.so");
+        let cands = vec![
+            candidate("app", &[("core", "1")]),
+            core2,
+            candidate("core", &[]),
+        ];
+        let ordered = order_candidates(cands, &HashMap::new()).expect("order");
+        let paths: Vec<_> = ordered.iter().map(|c| c.path.clone()).collect();
+        assert_eq!(paths[0], PathBuf::from("libcore2.so"));
+        assert_eq!(paths[1], PathBuf::from("libcore.so"));
+        assert_eq!(paths[2], PathBuf::from("libapp.so"));
+
+        let cands = vec![candidate("app", &[("core", "^2")])];
+        let loaded = HashMap::from([(
+            "core".to_string(),
+            vec!["1.2.0".to_string(), "2.1.0".to_string()],
+        )]);
+        assert!(order_candidates(cands.clone(), &loaded).is_ok());
+        let loaded = HashMap::from([("core".to_string(), vec!["1.2.0".to_string()])]);
+        assert!(matches!(
+            order_candidates(cands, &loaded),
+            Err(DependencyError::VersionMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn reports_cycles() {
+        let cands = vec![candidate("a", &[("b", "1")]), candidate("b", &[("a", "1")])];
+        let err = order_candidates(cands, &HashMap::new()).unwrap_err();
+        assert!(matches!(err, DependencyError::Cycle(_)));
+    }
+}
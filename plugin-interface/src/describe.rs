@@ -0,0 +1,257 @@
+//! The JSON document `#[plugin_aggregates]` exports as `plugin_describe_v1`,
+//! read by `PluginManager::describe`. It lists every trait the crate
+//! aggregates, each at its own ABI level; the top-level `abi_version` is the
+//! highest of them.
+//!
+//! ```json
+//! {
+//!   "plugin": {"name": "plugin-upper", "version": "0.1.0"},
+//!   "abi_version": 4,
+//!   "traits": [{
+//!     "name": "Transformer",
+//!     "abi_version": 4,
+//!     "vtable_abi_version": 4,
+//!     "registrations": ["Upper"],
+//!     "methods": [{
+//!       "name": "transform",
+//!       "signature": "fn transform(&self, input: &str) -> String",
+//!       "args": [{"name": "input", "type": "&str"}],
+//!       "returns": "String",
+//!       "optional": false,
+//!       "deprecated": null
+//!     }]
+//!   }]
+//! }
+//! ```
+//!
+//! `deprecated` is the note of a `#[deprecated]` attribute on the trait
+//! method, `""` if it has none, and `null` for methods that are not
+//! deprecated. Lifecycle hooks are not listed; the host calls them, not its
+//! users.
+
+pub(crate) use plugin_abi::envelope::quoted;
+use std::fmt::Write;
+
+/// One trait method, as `#[plugin_interface]` records it in
+/// `<Trait>VTable::METHODS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MethodDescription {
+    pub name: &'static str,
+    /// Arguments after `&self`, as name and type.
+    pub args: &'static [(&'static str, &'static str)],
+    /// Return type; empty for `()`.
+    pub returns: &'static str,
+    /// Marked `#[optional]`; plugins built before it was added have no
+    /// entry for it.
+    pub optional: bool,
+    /// Marked `#[deprecated]`: its note, prefixed with `since <version>: `
+    /// if the attribute names one, or empty. Proxies warn the first time
+    /// such a method is called.
+    pub deprecated: Option<&'static str>,
+}
+
+/// A registration a plugin crate provides, submitted by `#[plugin_impl]`
+/// so the description can name it without registering anything.
+#[derive(Debug)]
+pub struct RegistrationDescription {
+    pub trait_name: &'static str,
+    pub name: &'static str,
+}
+
+#[cfg(feature = "inventory")]
+inventory::collect!(RegistrationDescription);
+
+/// A trait a plugin crate aggregates, submitted by `#[plugin_aggregates]`
+/// so the description lists every trait the crate provides.
+#[derive(Debug)]
+pub struct TraitDescription {
+    pub name: &'static str,
+    /// The ABI level the trait's register functions are exported at.
+    pub abi_version: u32,
+    pub vtable_abi_version: u32,
+    pub methods: &'static [MethodDescription],
+}
+
+#[cfg(feature = "inventory")]
+inventory::collect!(TraitDescription);
+
+/// What `plugin_describe_v1` reports about the crate it is built into,
+/// besides its traits and registrations.
+#[doc(hidden)]
+pub struct PluginDescription<'a> {
+    pub package: &'a str,
+    pub version: &'a str,
+}
+
+/// The JSON document for `plugin_describe_v1`, listing the crate's
+/// `TraitDescription` entries with the names of their
+/// `RegistrationDescription` entries.
+#[cfg(feature = "inventory")]
+#[doc(hidden)]
+pub fn describe_json(d: &PluginDescription<'_>) -> String {
+    let traits: Vec<&TraitDescription> = inventory::iter::<TraitDescription>.into_iter().collect();
+    let registrations: Vec<&RegistrationDescription> = inventory::iter::<RegistrationDescription>
+        .into_iter()
+        .collect();
+    describe_json_with(d, &traits, &registrations)
+}
+
+/// `describe_json` with the crate's entries listed by
+/// `collect_registrations!` rather than collected by `inventory`.
+#[doc(hidden)]
+pub fn describe_json_with(
+    d: &PluginDescription<'_>,
+    traits: &[&TraitDescription],
+    registrations: &[&RegistrationDescription],
+) -> String {
+    let abi_version = traits.iter().map(|t| t.abi_version).max().unwrap_or(0);
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        "{{\"plugin\":{{\"name\":{},\"version\":{}}},\"abi_version\":{},\"traits\":[",
+        quoted(d.package),
+        quoted(d.version),
+        abi_version
+    );
+    out.push_str(&list(traits.iter().map(|t| trait_json(t, registrations))));
+    out.push_str("]}");
+    out
+}
+
+fn trait_json(t: &TraitDescription, registrations: &[&RegistrationDescription]) -> String {
+    let registrations = registrations
+        .iter()
+        .filter(|r| r.trait_name == t.name)
+        .map(|r| quoted(r.name));
+    format!(
+        "{{\"name\":{},\"abi_version\":{},\"vtable_abi_version\":{},\"registrations\":[{}],\"methods\":[{}]}}",
+        quoted(t.name),
+        t.abi_version,
+        t.vtable_abi_version,
+        list(registrations),
+        list(t.methods.iter().map(method_json))
+    )
+}
+
+fn method_json(m: &MethodDescription) -> String {
+    let mut signature = format!("fn {}(&self", m.name);
+    for (name, ty) in m.args {
+        let _ = write!(signature, ", {}: {}", name, ty);
+    }
+    signature.push(')');
+    if !m.returns.is_empty() {
+        let _ = write!(signature, " -> {}", m.returns);
+    }
+    let args = list(
+        m.args
+            .iter()
+            .map(|(name, ty)| format!("{{\"name\":{},\"type\":{}}}", quoted(name), quoted(ty))),
+    );
+    format!(
+        "{{\"name\":{},\"signature\":{},\"args\":[{}],\"returns\":{},\"optional\":{},\"deprecated\":{}}}",
+        quoted(m.name),
+        quoted(&signature),
+        args,
+        quoted(m.returns),
+        m.optional,
+        m.deprecated.map_or_else(|| "null".to_string(), quoted)
+    )
+}
+
+fn list(items: impl Iterator<Item = String>) -> String {
+    items.collect::<Vec<_>>().join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describes_methods_and_escapes_strings() {
+        static METHODS: [MethodDescription; 2] = [
+            MethodDescription {
+                name: "greet",
+                args: &[("target", "&str")],
+                returns: "",
+                optional: false,
+                deprecated: None,
+            },
+            MethodDescription {
+                name: "name",
+                args: &[],
+                returns: "&str",
+                optional: true,
+                deprecated: Some("since 0.2.0: use \"label\""),
+            },
+        ];
+        let json = describe_json_with(
+            &PluginDescription {
+                package: "a \"quoted\"\nname",
+                version: "1.0.0",
+            },
+            &[&TraitDescription {
+                name: "NoSuchTrait",
+                abi_version: 4,
+                vtable_abi_version: 4,
+                methods: &METHODS,
+            }],
+            &[],
+        );
+        assert_eq!(
+            json,
+            concat!(
+                r#"{"plugin":{"name":"a \"quoted\"\nname","version":"1.0.0"},"abi_version":4,"#,
+                r#""traits":[{"name":"NoSuchTrait","abi_version":4,"vtable_abi_version":4,"#,
+                r#""registrations":[],"methods":["#,
+                r#"{"name":"greet","signature":"fn greet(&self, target: &str)","#,
+                r#""args":[{"name":"target","type":"&str"}],"returns":"","optional":false,"deprecated":null},"#,
+                r#"{"name":"name","signature":"fn name(&self) -> &str","args":[],"returns":"&str","optional":true,"#,
+                r#""deprecated":"since 0.2.0: use \"label\""}"#,
+                "]}]}"
+            )
+        );
+        assert_eq!(quoted("\u{1}"), "\"\\u0001\"");
+    }
+
+    #[test]
+    fn lists_every_trait_with_its_registrations() {
+        let trait_description = |name, abi_version| TraitDescription {
+            name,
+            abi_version,
+            vtable_abi_version: 4,
+            methods: &[],
+        };
+        let registrations = [
+            &RegistrationDescription {
+                trait_name: "Greeter",
+                name: "One",
+            },
+            &RegistrationDescription {
+                trait_name: "Transformer",
+                name: "Two",
+            },
+        ];
+        let json = describe_json_with(
+            &PluginDescription {
+                package: "p",
+                version: "0.1.0",
+            },
+            &[
+                &trait_description("Greeter", 3),
+                &trait_description("Transformer", 5),
+            ],
+            &registrations,
+        );
+        assert!(json.contains(r#""abi_version":5,"traits""#), "{}", json);
+        assert!(
+            json.contains(r#""name":"Greeter","abi_version":3,"vtable_abi_version":4,"registrations":["One"],"#),
+            "{}",
+            json
+        );
+        assert!(
+            json.contains(r#""name":"Transformer","abi_version":5,"vtable_abi_version":4,"registrations":["Two"],"#),
+            "{}",
+            json
+        );
+    }
+}
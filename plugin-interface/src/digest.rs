@@ -0,0 +1,169 @@
+//! SHA-256 pinning of plugin artifacts.
+//!
+//! Hosts pin the expected digest of an artifact by path, file name or
+//! manifest name. The manager hashes each artifact before opening it and
+//! either rejects or skips artifacts whose digest differs.
+
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// A SHA-256 digest, displayed as lowercase hex.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Sha256Digest(pub [u8; 32]);
+
+impl Sha256Digest {
+    /// Digest of `bytes`.
+    pub fn of_bytes(bytes: &[u8]) -> Self {
+        Self(Sha256::digest(bytes).into())
+    }
+
+    /// Digest of the file at `path`.
+    pub fn of_file(path: &Path) -> std::io::Result<Self> {
+        Ok(Self::of_bytes(&std::fs::read(path)?))
+    }
+
+    /// Parse 64 hex digits (either case). Returns `None` for anything else.
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.trim().as_bytes();
+        if hex.len() != 64 {
+            return None;
+        }
+        let nibble = |c: u8| (c as char).to_digit(16).map(|d| d as u8);
+        let mut out = [0u8; 32];
+        for (i, pair) in hex.chunks(2).enumerate() {
+            out[i] = nibble(pair[0])? << 4 | nibble(pair[1])?;
+        }
+        Some(Self(out))
+    }
+}
+
+impl std::fmt::Display for Sha256Digest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for b in self.0 {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+/// An artifact whose digest did not match its pin, or that had no pin while
+/// pins are required.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DigestMismatch {
+    pub path: PathBuf,
+    /// The pinned digest, or `None` if the artifact was not pinned at all.
+    pub expected: Option<Sha256Digest>,
+    pub actual: Sha256Digest,
+}
+
+impl std::fmt::Display for DigestMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.expected {
+            Some(expected) => write!(
+                f,
+                "{:?} has sha256 {} but {} is pinned",
+                self.path, self.actual, expected
+            ),
+            None => write!(
+                f,
+                "{:?} (sha256 {}) is not on the allowlist",
+                self.path, self.actual
+            ),
+        }
+    }
+}
+
+/// Expected digests for plugin artifacts, set with
+/// `PluginManager::set_digest_pins`.
+///
+/// A pin's key is matched against the artifact's full path, then its file
+/// name, then the name in its sidecar manifest.
+#[derive(Debug, Clone, Default)]
+pub struct DigestPins {
+    pins: HashMap<String, Sha256Digest>,
+    skip_mismatches: bool,
+    require_pins: bool,
+}
+
+impl DigestPins {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expect the artifact identified by `path_or_name` to hash to `digest`.
+    pub fn with_pin(mut self, path_or_name: impl Into<String>, digest: Sha256Digest) -> Self {
+        self.pins.insert(path_or_name.into(), digest);
+        self
+    }
+
+    /// Leave mismatching artifacts out and keep loading the rest, instead of
+    /// failing the load with `PluginLoadError::DigestMismatch`.
+    pub fn skip_mismatches(mut self) -> Self {
+        self.skip_mismatches = true;
+        self
+    }
+
+    /// Treat the pins as an allowlist: artifacts without a pin are handled
+    /// like mismatches.
+    pub fn require_pins(mut self) -> Self {
+        self.require_pins = true;
+        self
+    }
+
+    pub(crate) fn skips_mismatches(&self) -> bool {
+        self.skip_mismatches
+    }
+
+    fn pin_for(&self, path: &Path, manifest_name: Option<&str>) -> Option<Sha256Digest> {
+        let by_path = self.pins.get(path.to_string_lossy().as_ref());
+        let by_file = || {
+            path.file_name()
+                .and_then(|n| self.pins.get(n.to_string_lossy().as_ref()))
+        };
+        let by_name = || manifest_name.and_then(|n| self.pins.get(n));
+        by_path.or_else(by_file).or_else(by_name).copied()
+    }
+
+    /// Hash the artifact at `path` and compare it with its pin. Does not
+    /// read the file when there is nothing to check.
+    pub(crate) fn check(
+        &self,
+        path: &Path,
+        manifest_name: Option<&str>,
+    ) -> std::io::Result<Result<(), DigestMismatch>> {
+        let expected = self.pin_for(path, manifest_name);
+        if expected.is_none() && !self.require_pins {
+            return Ok(Ok(()));
+        }
+        let actual = Sha256Digest::of_file(path)?;
+        if expected == Some(actual) {
+            return Ok(Ok(()));
+        }
+        Ok(Err(DigestMismatch {
+            path: path.to_path_buf(),
+            expected,
+            actual,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_round_trips() {
+        let d = Sha256Digest::of_bytes(b"abc");
+        assert_eq!(
+            d.to_string(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            Sha256Digest::from_hex(&d.to_string().to_uppercase()),
+            Some(d)
+        );
+        assert_eq!(Sha256Digest::from_hex("abc"), None);
+        assert_eq!(Sha256Digest::from_hex(&"zz".repeat(32)), None);
+    }
+}
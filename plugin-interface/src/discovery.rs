@@ -0,0 +1,135 @@
+//! Which files in a plugin directory are taken for plugin libraries.
+
+use std::path::Path;
+
+/// The file names discovery and the watchers recognize as plugin
+/// libraries. The default accepts any name with the platform's extension
+/// (`so`, `dylib` or `dll`) and, with the `wasm` feature, `.wasm` modules.
+///
+/// ```
+/// # use plugin_interface::DiscoveryPolicy;
+/// let policy = DiscoveryPolicy::new()
+///     .with_extension("plugin")
+///     .with_prefix("myapp_")
+///     .with_versioned_names(true);
+/// assert!(policy.matches("plugins/myapp_greeter.plugin".as_ref()));
+/// assert!(!policy.matches("plugins/other.plugin".as_ref()));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiscoveryPolicy {
+    extensions: Vec<String>,
+    prefixes: Vec<String>,
+    versioned: bool,
+}
+
+impl DiscoveryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also recognize files ending in `.<extension>`, compared without
+    /// regard to case. A leading dot is ignored.
+    pub fn with_extension(mut self, extension: impl Into<String>) -> Self {
+        let extension = extension.into();
+        self.extensions
+            .push(extension.trim_start_matches('.').to_string());
+        self
+    }
+
+    /// Only recognize files whose name starts with one of the prefixes
+    /// added, such as `lib` or `myapp_`. Without any, names are not
+    /// checked.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefixes.push(prefix.into());
+        self
+    }
+
+    /// Also recognize names with a numeric version after the extension,
+    /// such as `libfoo.so.1` or `libfoo.so.1.2.3`.
+    pub fn with_versioned_names(mut self, versioned: bool) -> Self {
+        self.versioned = versioned;
+        self
+    }
+
+    /// Extensions recognized besides the platform's.
+    pub fn extensions(&self) -> &[String] {
+        &self.extensions
+    }
+
+    pub fn prefixes(&self) -> &[String] {
+        &self.prefixes
+    }
+
+    pub fn versioned_names(&self) -> bool {
+        self.versioned
+    }
+
+    /// Whether the file at `path` is taken for a plugin library.
+    pub fn matches(&self, path: &Path) -> bool {
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            return false;
+        };
+        if !self.prefixes.is_empty() && !self.prefixes.iter().any(|p| name.starts_with(p.as_str()))
+        {
+            return false;
+        }
+        #[cfg(feature = "wasm")]
+        if crate::wasm::is_wasm_module(path) {
+            return true;
+        }
+        let mut name = name;
+        if self.versioned {
+            while let Some((rest, last)) = name.rsplit_once('.') {
+                if last.is_empty() || !last.bytes().all(|b| b.is_ascii_digit()) {
+                    break;
+                }
+                name = rest;
+            }
+        }
+        let Some((stem, extension)) = name.rsplit_once('.') else {
+            return false;
+        };
+        !stem.is_empty()
+            && (extension.eq_ignore_ascii_case(std::env::consts::DLL_EXTENSION)
+                || self
+                    .extensions
+                    .iter()
+                    .any(|e| e.eq_ignore_ascii_case(extension)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lib(name: &str) -> String {
+        format!("{}.{}", name, std::env::consts::DLL_EXTENSION)
+    }
+
+    #[test]
+    fn default_policy_takes_the_platform_extension_only() {
+        let policy = DiscoveryPolicy::default();
+        assert!(policy.matches(lib("libfoo").as_ref()));
+        assert!(policy.matches(lib("foo").as_ref()));
+        assert!(policy.matches(lib("LIBFOO").to_uppercase().as_ref()));
+        assert!(!policy.matches(format!("{}.1", lib("libfoo")).as_ref()));
+        assert!(!policy.matches("foo.plugin".as_ref()));
+        assert!(!policy.matches(format!(".{}", std::env::consts::DLL_EXTENSION).as_ref()));
+        assert!(!policy.matches("README".as_ref()));
+    }
+
+    #[test]
+    fn extra_extensions_prefixes_and_versions() {
+        let policy = DiscoveryPolicy::new()
+            .with_extension(".plugin")
+            .with_prefix("myapp_")
+            .with_versioned_names(true);
+        assert!(policy.matches("myapp_foo.plugin".as_ref()));
+        assert!(policy.matches("myapp_foo.PLUGIN".as_ref()));
+        assert!(policy.matches(format!("{}.1.2.3", lib("myapp_foo")).as_ref()));
+        assert!(policy.matches("myapp_foo.plugin.2".as_ref()));
+        assert!(!policy.matches(lib("foo").as_ref()));
+        assert!(!policy.matches(format!("{}.beta", lib("myapp_foo")).as_ref()));
+        assert_eq!(policy.extensions(), ["plugin"]);
+    }
+}
@@ -0,0 +1,324 @@
+//! Per-plugin dispatcher threads: proxies from `PluginHandle::dispatcher`
+//! queue their calls to a thread of the plugin's own, so a plugin whose
+//! state is not thread-safe is only ever entered from that thread, while
+//! the proxy itself can be shared between threads.
+
+use crate::call::PluginCallError;
+use crate::handle::LoadedLib;
+use crate::{PluginHandle, PluginId};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{mpsc, Arc, Condvar, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::thread::ThreadId;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// The dispatcher thread of one library. The thread runs until the library
+/// and every proxy using it are gone.
+#[derive(Clone)]
+pub(crate) struct Dispatcher {
+    jobs: mpsc::Sender<Job>,
+    thread: ThreadId,
+}
+
+impl Dispatcher {
+    /// The dispatcher of `lib`, started on first use.
+    pub(crate) fn of(lib: &LoadedLib) -> Result<Self, PluginCallError> {
+        let mut slot = lock(&lib.dispatcher);
+        if let Some(dispatcher) = slot.as_ref() {
+            return Ok(dispatcher.clone());
+        }
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let thread = std::thread::Builder::new()
+            .name("plugin-dispatch".to_string())
+            .spawn(move || {
+                for job in queue {
+                    // A panicking call fails alone; its reply reports it.
+                    let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(job));
+                }
+            })
+            .map_err(|_| PluginCallError::Failed)?;
+        let dispatcher = Self {
+            jobs,
+            thread: thread.thread().id(),
+        };
+        *slot = Some(dispatcher.clone());
+        Ok(dispatcher)
+    }
+
+    /// Run `job` on the dispatcher thread, or right away when already on
+    /// it, so a plugin calling back into a dispatched proxy does not wait
+    /// for itself.
+    fn run(&self, job: Job) {
+        if std::thread::current().id() == self.thread {
+            job();
+        } else {
+            // The thread only stops once every sender is gone; a job that
+            // cannot be queued is dropped and its reply reports the failure.
+            let _ = self.jobs.send(job);
+        }
+    }
+}
+
+fn lock<T>(m: &Mutex<T>) -> MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// The handle a `DispatchProxy` calls through.
+#[derive(Clone)]
+struct DispatchedHandle(PluginHandle);
+
+// The handle is only cloned and dropped away from the dispatcher thread,
+// which the library's reference count and holder list allow from any
+// thread; it is used to enter the plugin on the dispatcher thread alone.
+unsafe impl Send for DispatchedHandle {}
+unsafe impl Sync for DispatchedHandle {}
+
+/// A proxy that runs every call on the plugin's dispatcher thread, one at
+/// a time, in the order they were made. Unlike `GreeterProxy` and
+/// `TransformerProxy` it is `Send + Sync` and cheap to clone, and calls
+/// never run concurrently with other dispatched calls into the same
+/// library. Each call has a blocking form and an `_async` form returning a
+/// `DispatchFuture`.
+///
+/// Calls made through ordinary proxies are not serialized with dispatched
+/// ones. Like a handle, the proxy keeps its library loaded.
+#[derive(Clone)]
+pub struct DispatchProxy {
+    handle: DispatchedHandle,
+    dispatcher: Dispatcher,
+}
+
+impl std::fmt::Debug for DispatchProxy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DispatchProxy")
+            .field("id", &self.handle.0.id())
+            .field("path", &self.handle.0.path())
+            .finish()
+    }
+}
+
+impl DispatchProxy {
+    pub(crate) fn new(handle: PluginHandle, dispatcher: Dispatcher) -> Self {
+        Self {
+            handle: DispatchedHandle(handle),
+            dispatcher,
+        }
+    }
+
+    pub fn id(&self) -> PluginId {
+        self.handle.0.id()
+    }
+
+    /// Queue `f` to run with the plugin's handle on the dispatcher thread.
+    /// Proxies made from the handle inside `f` call into the plugin from
+    /// that thread; they cannot be returned.
+    pub fn call_async<R, F>(&self, f: F) -> DispatchFuture<R>
+    where
+        F: FnOnce(&PluginHandle) -> Result<R, PluginCallError> + Send + 'static,
+        R: Send + 'static,
+    {
+        let (reply, future) = reply_slot();
+        let handle = self.handle.clone();
+        self.dispatcher.run(Box::new(move || {
+            let handle = handle;
+            reply.send(f(&handle.0));
+        }));
+        future
+    }
+
+    /// `call_async`, waiting for the result.
+    pub fn call<R, F>(&self, f: F) -> Result<R, PluginCallError>
+    where
+        F: FnOnce(&PluginHandle) -> Result<R, PluginCallError> + Send + 'static,
+        R: Send + 'static,
+    {
+        self.call_async(f).wait()
+    }
+
+    /// `GreeterProxy::try_greet` on the dispatcher thread. Fails with
+    /// `PluginCallError::Unsupported` for other traits.
+    pub fn greet_async(&self, target: &str) -> DispatchFuture<()> {
+        let target = target.to_string();
+        self.call_async(move |handle| {
+            let proxy = handle.as_greeter().ok_or(PluginCallError::Unsupported)?;
+            proxy.try_greet(&target)
+        })
+    }
+
+    pub fn greet(&self, target: &str) -> Result<(), PluginCallError> {
+        self.greet_async(target).wait()
+    }
+
+    /// `TransformerProxy::try_transform` on the dispatcher thread. Fails
+    /// with `PluginCallError::Unsupported` for other traits.
+    pub fn transform_async(&self, input: &str) -> DispatchFuture<String> {
+        let input = input.to_string();
+        self.call_async(move |handle| {
+            let proxy = handle
+                .as_transformer()
+                .ok_or(PluginCallError::Unsupported)?;
+            proxy.try_transform(&input)
+        })
+    }
+
+    pub fn transform(&self, input: &str) -> Result<String, PluginCallError> {
+        self.transform_async(input).wait()
+    }
+}
+
+struct Slot<R> {
+    state: Mutex<SlotState<R>>,
+    filled: Condvar,
+}
+
+struct SlotState<R> {
+    result: Option<Result<R, PluginCallError>>,
+    waker: Option<Waker>,
+}
+
+/// The dispatcher's end of a `DispatchFuture`. Dropped without sending, as
+/// when the call panicked, it fails the call.
+struct Reply<R: Send>(Option<Arc<Slot<R>>>);
+
+fn reply_slot<R: Send>() -> (Reply<R>, DispatchFuture<R>) {
+    let slot = Arc::new(Slot {
+        state: Mutex::new(SlotState {
+            result: None,
+            waker: None,
+        }),
+        filled: Condvar::new(),
+    });
+    (Reply(Some(slot.clone())), DispatchFuture { slot })
+}
+
+impl<R: Send> Reply<R> {
+    fn send(mut self, result: Result<R, PluginCallError>) {
+        if let Some(slot) = self.0.take() {
+            fill(&slot, result);
+        }
+    }
+}
+
+impl<R: Send> Drop for Reply<R> {
+    fn drop(&mut self) {
+        if let Some(slot) = self.0.take() {
+            fill(&slot, Err(PluginCallError::Failed));
+        }
+    }
+}
+
+fn fill<R>(slot: &Slot<R>, result: Result<R, PluginCallError>) {
+    let waker = {
+        let mut state = lock(&slot.state);
+        state.result = Some(result);
+        state.waker.take()
+    };
+    slot.filled.notify_all();
+    if let Some(waker) = waker {
+        waker.wake();
+    }
+}
+
+/// The result of a call queued by a `DispatchProxy`, for any executor to
+/// await, or to `wait` for on a thread that may block.
+pub struct DispatchFuture<R> {
+    slot: Arc<Slot<R>>,
+}
+
+impl<R> DispatchFuture<R> {
+    /// Block until the call has returned.
+    pub fn wait(self) -> Result<R, PluginCallError> {
+        let mut state = lock(&self.slot.state);
+        loop {
+            if let Some(result) = state.result.take() {
+                return result;
+            }
+            state = self
+                .slot
+                .filled
+                .wait(state)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
+
+impl<R> Future for DispatchFuture<R> {
+    type Output = Result<R, PluginCallError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = lock(&self.slot.state);
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<R> std::fmt::Debug for DispatchFuture<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ready = lock(&self.slot.state).result.is_some();
+        f.debug_struct("DispatchFuture")
+            .field("ready", &ready)
+            .finish()
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::handle::test_support::fake_greeter_handle;
+    use std::time::{Duration, Instant};
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn dispatched_calls_run_one_at_a_time_on_the_plugin_thread() {
+        assert_send_sync::<DispatchProxy>();
+        let handle = fake_greeter_handle();
+        let proxy = handle.dispatcher().expect("dispatcher");
+
+        let thread = proxy
+            .call(|_| Ok(std::thread::current().name().map(str::to_string)))
+            .unwrap();
+        assert_eq!(thread.as_deref(), Some("plugin-dispatch"));
+        let name = proxy.call(|h| h.as_greeter().unwrap().try_name());
+        assert_eq!(name.as_deref(), Ok("fake"));
+        assert_eq!(proxy.transform("x"), Err(PluginCallError::Unsupported));
+
+        // Each greet sleeps for 30ms; queued behind each other they take
+        // at least three times as long.
+        let start = Instant::now();
+        let callers: Vec<_> = (0..3)
+            .map(|_| {
+                let proxy = proxy.clone();
+                std::thread::spawn(move || proxy.greet("30"))
+            })
+            .collect();
+        for caller in callers {
+            assert_eq!(caller.join().unwrap(), Ok(()));
+        }
+        assert!(start.elapsed() >= Duration::from_millis(90));
+
+        let mut future = std::pin::pin!(proxy.greet_async("0"));
+        let mut cx = Context::from_waker(Waker::noop());
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let result = loop {
+            if let Poll::Ready(result) = future.as_mut().poll(&mut cx) {
+                break result;
+            }
+            assert!(Instant::now() < deadline, "dispatched call never returned");
+            std::thread::sleep(Duration::from_millis(1));
+        };
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            proxy.call(|_| -> Result<(), _> { panic!("boom") }),
+            Err(PluginCallError::Failed)
+        );
+        assert_eq!(proxy.greet("0"), Ok(()), "the thread survives a panic");
+    }
+}
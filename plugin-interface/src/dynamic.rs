@@ -0,0 +1,321 @@
+//! Calling plugin methods by name with JSON arguments, for scripting hosts
+//! that learn the methods from `PluginManager::describe`.
+//!
+//! `#[plugin_aggregates(Trait)]` exports
+//! `plugin_call_dynamic_<Trait>_v1(registration, method, args_json) -> result_json`
+//! for registrations of `Trait`.
+//! Arguments are a JSON array with one string per parameter, or an object
+//! mapping parameter names to strings; `null` stands for no arguments. The
+//! result is `{"ok": <string or null>}` or
+//! `{"error": "unsupported" | "invalid_arguments" | "failed", "message": "..."}`,
+//! as written and read by `plugin_abi::envelope`.
+//! Hosts call it through `PluginHandle::call_dynamic`.
+
+use crate::{MethodDescription, PluginCallError};
+use plugin_abi::envelope::{self, ErrorKind};
+use std::ffi::{c_void, CStr};
+use std::os::raw::c_char;
+
+/// Registration types whose methods can be called by name. Implemented by
+/// `#[plugin_interface]` for the registrations it generates.
+#[doc(hidden)]
+pub trait DynamicRegistration {
+    /// The trait's methods, as in `<Trait>VTable::METHODS`.
+    const METHODS: &'static [MethodDescription];
+
+    /// Call `method` through the registration's vtable with one string per
+    /// parameter. `Ok(None)` for methods that return nothing.
+    ///
+    /// # Safety
+    /// `reg` must point to a live registration of this type.
+    unsafe fn call(
+        reg: *const Self,
+        method: &str,
+        args: &[&str],
+    ) -> Result<Option<String>, PluginCallError>;
+}
+
+/// The result document of `plugin_call_dynamic_<Trait>_v1` for the registration at
+/// `reg`, an `R`.
+///
+/// # Safety
+/// `reg` must point to a live `R`; `method` and `args` must be null or
+/// nul-terminated strings.
+#[doc(hidden)]
+pub unsafe fn call_dynamic_json<R: DynamicRegistration>(
+    reg: *const c_void,
+    method: *const c_char,
+    args: *const c_char,
+) -> String {
+    let result = (|| {
+        if reg.is_null() || method.is_null() {
+            return Err(PluginCallError::InvalidArguments(
+                "no registration or method".to_string(),
+            ));
+        }
+        let method = CStr::from_ptr(method)
+            .to_str()
+            .map_err(|e| PluginCallError::InvalidArguments(e.to_string()))?;
+        let description = R::METHODS
+            .iter()
+            .find(|m| m.name == method)
+            .ok_or(PluginCallError::Unsupported)?;
+        let args = if args.is_null() {
+            "null"
+        } else {
+            CStr::from_ptr(args)
+                .to_str()
+                .map_err(|e| PluginCallError::InvalidArguments(e.to_string()))?
+        };
+        let args = parse_args(args, description).map_err(PluginCallError::InvalidArguments)?;
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            R::call(reg as *const R, method, &args)
+        }))
+        .unwrap_or(Err(PluginCallError::Failed))
+    })();
+    match result {
+        Ok(value) => envelope::ok(value.as_deref()),
+        Err(e) => {
+            let (kind, message) = match e {
+                PluginCallError::Unsupported => (ErrorKind::Unsupported, e.to_string()),
+                PluginCallError::InvalidArguments(message) => {
+                    (ErrorKind::InvalidArguments, message)
+                }
+                e => (ErrorKind::Failed, e.to_string()),
+            };
+            envelope::error(kind, &message)
+        }
+    }
+}
+
+/// The arguments in `json` for `method`, in declaration order.
+fn parse_args(json: &str, method: &MethodDescription) -> Result<Vec<String>, String> {
+    let mut parser = Parser {
+        rest: json.trim_start(),
+    };
+    let value = parser.value()?;
+    if !parser.rest.trim().is_empty() {
+        return Err("trailing characters after the arguments".to_string());
+    }
+    let values = match value {
+        Json::Null => Vec::new(),
+        Json::Array(values) => values,
+        Json::Object(mut fields) => {
+            let mut values = Vec::new();
+            for (name, _) in method.args {
+                let at = fields
+                    .iter()
+                    .position(|(field, _)| field == name)
+                    .ok_or_else(|| format!("missing argument `{}`", name))?;
+                values.push(fields.swap_remove(at).1);
+            }
+            if let Some((field, _)) = fields.first() {
+                return Err(format!("unknown argument `{}`", field));
+            }
+            values
+        }
+        _ => return Err("arguments must be an array, an object or null".to_string()),
+    };
+    if values.len() != method.args.len() {
+        return Err(format!(
+            "`{}` takes {} argument(s), got {}",
+            method.name,
+            method.args.len(),
+            values.len()
+        ));
+    }
+    values
+        .into_iter()
+        .map(|v| match v {
+            Json::String(s) => Ok(s),
+            _ => Err("arguments must be strings".to_string()),
+        })
+        .collect()
+}
+
+/// Just enough JSON to read call arguments.
+enum Json {
+    Null,
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+    /// A number or boolean; never a valid argument.
+    Scalar,
+}
+
+struct Parser<'a> {
+    rest: &'a str,
+}
+
+impl Parser<'_> {
+    fn value(&mut self) -> Result<Json, String> {
+        let value = match self.rest.chars().next() {
+            Some('"') => Json::String(self.string()?),
+            Some('[') => {
+                self.eat('[')?;
+                let mut items = Vec::new();
+                if !self.try_eat(']') {
+                    loop {
+                        items.push(self.value()?);
+                        if self.try_eat(']') {
+                            break;
+                        }
+                        self.eat(',')?;
+                    }
+                }
+                Json::Array(items)
+            }
+            Some('{') => {
+                self.eat('{')?;
+                let mut fields = Vec::new();
+                if !self.try_eat('}') {
+                    loop {
+                        let name = self.string()?;
+                        self.eat(':')?;
+                        fields.push((name, self.value()?));
+                        if self.try_eat('}') {
+                            break;
+                        }
+                        self.eat(',')?;
+                    }
+                }
+                Json::Object(fields)
+            }
+            Some(_) => {
+                let end = self
+                    .rest
+                    .find(|c: char| matches!(c, ',' | ']' | '}') || c.is_whitespace())
+                    .unwrap_or(self.rest.len());
+                let token = &self.rest[..end];
+                let value = match token {
+                    "null" => Json::Null,
+                    "true" | "false" => Json::Scalar,
+                    _ if token.parse::<f64>().is_ok() => Json::Scalar,
+                    _ => return Err(format!("unexpected `{}`", token)),
+                };
+                self.rest = &self.rest[end..];
+                value
+            }
+            None => return Err("unexpected end of arguments".to_string()),
+        };
+        self.rest = self.rest.trim_start();
+        Ok(value)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.eat_raw('"')?;
+        let mut out = String::new();
+        let mut chars = self.rest.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.rest = self.rest[i + 1..].trim_start();
+                    return Ok(out);
+                }
+                '\\' => {
+                    let escaped = match chars.next().map(|(_, c)| c) {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => {
+                            let high = hex_unit(&mut chars)?;
+                            let code = if (0xd800..0xdc00).contains(&high) {
+                                let low = match (chars.next(), chars.next()) {
+                                    (Some((_, '\\')), Some((_, 'u'))) => hex_unit(&mut chars)?,
+                                    _ => 0,
+                                };
+                                if !(0xdc00..0xe000).contains(&low) {
+                                    return Err("unpaired surrogate".to_string());
+                                }
+                                0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+                            } else {
+                                high
+                            };
+                            char::from_u32(code).ok_or("unpaired surrogate")?
+                        }
+                        _ => return Err("bad escape in string".to_string()),
+                    };
+                    out.push(escaped);
+                }
+                c => out.push(c),
+            }
+        }
+        Err("unterminated string".to_string())
+    }
+
+    fn eat_raw(&mut self, c: char) -> Result<(), String> {
+        self.rest = self
+            .rest
+            .strip_prefix(c)
+            .ok_or_else(|| format!("expected `{}`", c))?;
+        Ok(())
+    }
+
+    fn eat(&mut self, c: char) -> Result<(), String> {
+        self.eat_raw(c)?;
+        self.rest = self.rest.trim_start();
+        Ok(())
+    }
+
+    fn try_eat(&mut self, c: char) -> bool {
+        self.eat(c).is_ok()
+    }
+}
+
+/// The four hex digits of a `\u` escape.
+fn hex_unit(chars: &mut std::str::CharIndices<'_>) -> Result<u32, String> {
+    let hex: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+    u32::from_str_radix(&hex, 16).map_err(|_| format!("bad escape `\\u{}`", hex))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GREET: MethodDescription = MethodDescription {
+        name: "greet",
+        args: &[("target", "&str")],
+        returns: "",
+        optional: false,
+        deprecated: None,
+    };
+
+    #[test]
+    fn arguments_by_position_or_name() {
+        assert_eq!(
+            parse_args(r#"["a\"é😀"]"#, &GREET),
+            Ok(vec!["a\"é😀".to_string()])
+        );
+        assert_eq!(
+            parse_args(r#" { "target" : "x" } "#, &GREET),
+            Ok(vec!["x".to_string()])
+        );
+        assert_eq!(
+            parse_args(r#"["\u00e9\ud83d\ude00\n"]"#, &GREET),
+            Ok(vec!["é😀\n".to_string()])
+        );
+        assert!(parse_args("[]", &GREET)
+            .unwrap_err()
+            .contains("takes 1 argument"));
+        assert!(parse_args("[1]", &GREET).unwrap_err().contains("strings"));
+        assert!(parse_args(r#"{"who":"x"}"#, &GREET)
+            .unwrap_err()
+            .contains("`target`"));
+        assert!(parse_args(r#"["x"] ["#, &GREET).is_err());
+        let name = MethodDescription {
+            name: "name",
+            args: &[],
+            returns: "&str",
+            optional: false,
+            deprecated: None,
+        };
+        assert_eq!(parse_args("null", &name), Ok(vec![]));
+        assert_eq!(parse_args("{}", &name), Ok(vec![]));
+    }
+}
@@ -0,0 +1,81 @@
+//! Plugin lifecycle events for `PluginManager::subscribe`.
+
+use crate::{PluginDescriptor, PluginId, QuarantineAction, QuarantinedArtifact};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+/// A change to what a `PluginManager` has loaded, sent to every receiver
+/// returned by `PluginManager::subscribe`. Libraries and WebAssembly
+/// modules are reported however they were loaded, including by lazy
+/// lookups and watchers; plugins served by runner processes are not.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ManagerEvent {
+    /// A plugin was loaded.
+    Loaded(PluginDescriptor),
+    /// `unload_by_path`, or a call built on it such as `unload_all`,
+    /// unloaded the plugin loaded from `path`. `counter` is the unmaker
+    /// counter when the library was unloaded right away; when handles still
+    /// use it, the last of them unloads it. A plugin that unloads because
+    /// all its handles were dropped is not reported.
+    Unloaded { path: PathBuf, counter: Option<u64> },
+    /// `reload_by_path` replaced a plugin with the new version of its
+    /// artifact.
+    Reloaded(PluginDescriptor),
+    /// `PluginManager::check_health` quarantined the registration `id` of
+    /// the plugin loaded from `path` after `failures` unhealthy reports in
+    /// a row.
+    Quarantined {
+        id: PluginId,
+        path: PathBuf,
+        failures: u32,
+        action: QuarantineAction,
+    },
+    /// Proxy calls to the registration `id` of the plugin loaded from
+    /// `path` failed `errors` times within the window of the manager's
+    /// `ErrorBudget`, so it was turned off like `set_enabled` does.
+    ErrorBudgetExceeded {
+        id: PluginId,
+        path: PathBuf,
+        errors: u32,
+    },
+    /// An artifact failed validation or loading too many times in a row
+    /// and is skipped from now on; see `PluginManager::set_quarantine`.
+    ArtifactQuarantined(QuarantinedArtifact),
+    /// Loading, reloading or unloading failed. `path` is the artifact
+    /// involved, unless the call covered a whole search path.
+    Error {
+        path: Option<PathBuf>,
+        error: String,
+    },
+}
+
+/// The senders behind `PluginManager::subscribe`. Clones share them, so
+/// proxies can report what their calls did to the manager's subscribers.
+#[derive(Default, Clone)]
+pub(crate) struct Subscribers {
+    senders: Arc<Mutex<Vec<Sender<ManagerEvent>>>>,
+}
+
+impl Subscribers {
+    pub(crate) fn subscribe(&self) -> Receiver<ManagerEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.senders().push(tx);
+        rx
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.senders().is_empty()
+    }
+
+    /// Send `event` to every subscriber, forgetting the ones that dropped
+    /// their receiver.
+    pub(crate) fn send(&self, event: ManagerEvent) {
+        self.senders().retain(|tx| tx.send(event.clone()).is_ok());
+    }
+
+    fn senders(&self) -> std::sync::MutexGuard<'_, Vec<Sender<ManagerEvent>>> {
+        self.senders.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -0,0 +1,226 @@
+//! Support for fuzzing the plugin boundary: `fuzz_entries` feeds one input
+//! through every vtable entry of a registration and checks what the
+//! generated wrappers hand back. The `plugin-fuzz` crate builds its
+//! cargo-fuzz targets on it.
+
+use crate::handle::registration_vtable;
+use crate::{
+    GreeterVTable, OwnedStr, PluginHandle, PluginTrait, RawRequestContext, StrRef,
+    TransformerVTable, STATE_VTABLE_ABI, STR_VTABLE_ABI,
+};
+use std::ffi::{c_void, CStr, CString};
+use std::os::raw::c_char;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// What `fuzz_entries` found wrong with a vtable entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FuzzViolation {
+    /// The entry returned a string that is not valid UTF-8.
+    InvalidUtf8 { entry: &'static str },
+    /// The entry returned an `OwnedStr` with a null pointer but a length.
+    NullWithLength { entry: &'static str, len: usize },
+    /// The entry returned a pointer into the argument it was given, which
+    /// the host frees once the call returns.
+    ReturnedArgument { entry: &'static str },
+}
+
+impl std::fmt::Display for FuzzViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FuzzViolation::InvalidUtf8 { entry } => {
+                write!(f, "`{}` returned a string that is not UTF-8", entry)
+            }
+            FuzzViolation::NullWithLength { entry, len } => {
+                write!(f, "`{}` returned a null string of length {}", entry, len)
+            }
+            FuzzViolation::ReturnedArgument { entry } => {
+                write!(f, "`{}` returned a pointer into its argument", entry)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FuzzViolation {}
+
+/// Call every entry of the registration behind `handle` with `input`: the
+/// string entries get it up to its first nul byte, the `_str` entries get
+/// it with invalid UTF-8 replaced, and `restore_state` gets the raw bytes.
+/// Entries without arguments are called too. The `<method>_ctx` entries get
+/// the `_str` arguments twice: without a request context, and with one made
+/// up from `input` (see `made_up_context`). Returned strings are checked and
+/// freed as a proxy would.
+///
+/// A panic that escapes a wrapper aborts the process, which the fuzzer
+/// reports as a crash. WebAssembly modules and unloaded libraries are
+/// skipped.
+pub fn fuzz_entries(handle: &PluginHandle, input: &[u8]) -> Result<(), FuzzViolation> {
+    let Some(lib) = handle.native_lib() else {
+        return Ok(());
+    };
+    let Some(_permit) = lib.guard.enter() else {
+        return Ok(());
+    };
+    let c_input = CString::new(input.split(|&b| b == 0).next().unwrap_or_default())
+        .expect("cut at the first nul");
+    let text = String::from_utf8_lossy(input);
+    let c_arg = c_input.as_bytes_with_nul();
+    let str_arg = text.as_bytes();
+    let vtable = registration_vtable(lib, handle.index());
+    let flag = AtomicBool::new(false);
+    let made_up = made_up_context(input, &flag);
+    let contexts: [*const RawRequestContext; 2] = [std::ptr::null(), &made_up];
+    let returned_c = |entry, c: *const c_char, arg: &[u8]| unsafe {
+        check_c_string(entry, c, arg, |c| {
+            lib.returned_string(c);
+        })
+    };
+    unsafe {
+        match handle.trait_id() {
+            PluginTrait::Greeter => {
+                let v = &*(vtable as *const GreeterVTable);
+                returned_c("name", (v.name)(v.user_data), &[])?;
+                (v.greet)(v.user_data, c_input.as_ptr());
+                if v.abi_version >= STR_VTABLE_ABI {
+                    check_owned("name_str", (v.name_str)(v.user_data), &[])?;
+                    (v.greet_str)(v.user_data, StrRef::new(&text));
+                }
+                if let Some(health) = v.optional_health() {
+                    health(v.user_data);
+                }
+                for ctx in contexts {
+                    if let Some(name_ctx) = v.optional_name_ctx() {
+                        check_owned("name_ctx", name_ctx(v.user_data, ctx), &[])?;
+                    }
+                    if let Some(greet_ctx) = v.optional_greet_ctx() {
+                        greet_ctx(v.user_data, StrRef::new(&text), ctx);
+                    }
+                }
+                if v.abi_version >= STATE_VTABLE_ABI {
+                    fuzz_state(v.user_data, v.save_state, v.restore_state, input);
+                }
+            }
+            PluginTrait::Transformer => {
+                let v = &*(vtable as *const TransformerVTable);
+                returned_c(
+                    "transform",
+                    (v.transform)(v.user_data, c_input.as_ptr()),
+                    c_arg,
+                )?;
+                if v.abi_version >= STR_VTABLE_ABI {
+                    let out = (v.transform_str)(v.user_data, StrRef::new(&text));
+                    check_owned("transform_str", out, str_arg)?;
+                }
+                if let Some(describe) = v.optional_describe() {
+                    check_owned("describe", describe(v.user_data), &[])?;
+                }
+                if let Some(health) = v.optional_health() {
+                    health(v.user_data);
+                }
+                for ctx in contexts {
+                    if let Some(transform_ctx) = v.optional_transform_ctx() {
+                        let out = transform_ctx(v.user_data, StrRef::new(&text), ctx);
+                        check_owned("transform_ctx", out, str_arg)?;
+                    }
+                    if let Some(describe_ctx) = v.optional_describe_ctx() {
+                        check_owned("describe_ctx", describe_ctx(v.user_data, ctx), &[])?;
+                    }
+                }
+                if v.abi_version >= STATE_VTABLE_ABI {
+                    fuzz_state(v.user_data, v.save_state, v.restore_state, input);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn points_into(ptr: *const u8, arg: &[u8]) -> bool {
+    arg.as_ptr_range().contains(&ptr)
+}
+
+/// Check a nul-terminated string an entry returned, then hand it to
+/// `free`.
+///
+/// # Safety
+/// `c` must be null or a string the entry just returned.
+unsafe fn check_c_string(
+    entry: &'static str,
+    c: *const c_char,
+    arg: &[u8],
+    free: impl FnOnce(*const c_char),
+) -> Result<(), FuzzViolation> {
+    if c.is_null() {
+        return Ok(());
+    }
+    if points_into(c as *const u8, arg) {
+        return Err(FuzzViolation::ReturnedArgument { entry });
+    }
+    let valid = CStr::from_ptr(c).to_str().is_ok();
+    free(c);
+    if !valid {
+        return Err(FuzzViolation::InvalidUtf8 { entry });
+    }
+    Ok(())
+}
+
+/// Check an `OwnedStr` an entry returned, then free it.
+///
+/// # Safety
+/// `s` must be a string the entry just returned.
+unsafe fn check_owned(entry: &'static str, s: OwnedStr, arg: &[u8]) -> Result<(), FuzzViolation> {
+    if s.ptr.is_null() {
+        if s.len != 0 {
+            return Err(FuzzViolation::NullWithLength { entry, len: s.len });
+        }
+        return Ok(());
+    }
+    if points_into(s.ptr, arg) {
+        return Err(FuzzViolation::ReturnedArgument { entry });
+    }
+    let valid = std::str::from_utf8(std::slice::from_raw_parts(s.ptr, s.len)).is_ok();
+    if let Some(free) = s.free {
+        free(s.ptr, s.len);
+    }
+    if !valid {
+        return Err(FuzzViolation::InvalidUtf8 { entry });
+    }
+    Ok(())
+}
+
+/// A request context with its size, correlation ID and deadline taken from
+/// the first bytes of `input`, whatever they are: a size no host sends, an
+/// ID of 0 or a deadline long past or centuries away. Its cancellation flag
+/// is `flag`, set from the input too, or null; plugins have to trust the
+/// pointer, so it is never made up.
+fn made_up_context(input: &[u8], flag: &AtomicBool) -> RawRequestContext {
+    let word = |i: usize| {
+        let mut bytes = [0u8; 8];
+        for (b, x) in bytes.iter_mut().zip(input.iter().skip(i * 8)) {
+            *b = *x;
+        }
+        u64::from_le_bytes(bytes)
+    };
+    flag.store(word(3) & 1 != 0, Ordering::Release);
+    RawRequestContext {
+        struct_size: word(0) as usize,
+        correlation_id: word(1),
+        deadline_ms: word(2),
+        cancelled: if word(3) & 2 != 0 {
+            flag
+        } else {
+            std::ptr::null()
+        },
+    }
+}
+
+/// Restore `input` as state, then save it again.
+fn fuzz_state(
+    user_data: *mut c_void,
+    save_state: extern "C" fn(*mut c_void, *mut c_void, crate::StateSink),
+    restore_state: extern "C" fn(*mut c_void, *const u8, usize),
+    input: &[u8],
+) {
+    extern "C" fn discard(_: *mut c_void, _: *const u8, _: usize) {}
+    restore_state(user_data, input.as_ptr(), input.len());
+    save_state(user_data, std::ptr::null_mut(), discard);
+}
@@ -0,0 +1,78 @@
+//! Named groups of plugins ("editor", "export", "experimental") that
+//! `PluginManager` loads, unloads and turns on or off together.
+
+use crate::manifest::PluginManifest;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+/// Group membership the host assigned, on top of the `groups` manifests
+/// list.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PluginGroups {
+    /// Plugin names by group.
+    assigned: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl PluginGroups {
+    pub(crate) fn assign(&mut self, group: String, plugin: String) {
+        self.assigned.entry(group).or_default().insert(plugin);
+    }
+
+    /// Whether the library at `path`, described by `manifest` if it has
+    /// one, is in `group`: its manifest lists the group, or the host
+    /// assigned its manifest name, file name, or file name without the
+    /// platform's prefix and extension to it.
+    pub(crate) fn contains(
+        &self,
+        group: &str,
+        path: &Path,
+        manifest: Option<&PluginManifest>,
+    ) -> bool {
+        if manifest.is_some_and(|m| m.groups.iter().any(|g| g == group)) {
+            return true;
+        }
+        let Some(assigned) = self.assigned.get(group) else {
+            return false;
+        };
+        let file_name = path.file_name().map(|n| n.to_string_lossy().into_owned());
+        let bare = path.file_stem().map(|s| {
+            let s = s.to_string_lossy();
+            s.strip_prefix(std::env::consts::DLL_PREFIX)
+                .unwrap_or(&s)
+                .to_string()
+        });
+        manifest
+            .map(|m| m.name.clone())
+            .into_iter()
+            .chain(file_name)
+            .chain(bare)
+            .any(|name| assigned.contains(&name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_come_from_manifests_and_the_host() {
+        let lib = Path::new("/plugins").join(format!(
+            "{}shout.{}",
+            std::env::consts::DLL_PREFIX,
+            std::env::consts::DLL_EXTENSION
+        ));
+        let manifest =
+            PluginManifest::parse("name = \"loud\"\nversion = \"0.1.0\"\ngroups = [\"editor\"]\n")
+                .unwrap();
+        let mut groups = PluginGroups::default();
+        groups.assign("export".to_string(), "shout".to_string());
+        groups.assign("experimental".to_string(), "loud".to_string());
+
+        assert!(groups.contains("editor", &lib, Some(&manifest)));
+        assert!(groups.contains("export", &lib, Some(&manifest)));
+        assert!(groups.contains("export", &lib, None));
+        assert!(groups.contains("experimental", &lib, Some(&manifest)));
+        assert!(!groups.contains("experimental", &lib, None));
+        assert!(!groups.contains("editor", &lib, None));
+    }
+}
@@ -1,21 +1,79 @@
-use crate::{GreeterRegistration, PluginTrait, RegistrationArray};
+use crate::call::{self, CallGuard, CallOptions, ExclusiveAccess, PluginCallError};
+use crate::dispatch::{DispatchProxy, Dispatcher};
+use crate::holders::{Holders, LibRef};
+use crate::host::SharedHostContext;
+use crate::impls::ImplFilter;
+use crate::instances::{Instance, InstancePools, PluginInstance};
+use crate::instrument;
+use crate::request::{RawRequestContext, RequestContext};
+use crate::stats::{CallState, PluginStats};
+use crate::{
+    symbols, Greeter, GreeterRegistration, GreeterVTable, HostInfo, PluginCounters, PluginManifest,
+    PluginTrait, RegistrationArray, RegistrationFactory, StateSink, StrRef, Transformer,
+    TransformerRegistration, TransformerVTable, HOST_ALLOCATOR_ABI, LIFECYCLE_VTABLE_ABI,
+    SIZED_STRUCTS_ABI, SIZED_VTABLE_ABI, STATE_VTABLE_ABI, STR_VTABLE_ABI,
+};
 use libloading::Library;
 use std::ffi::{CStr, CString};
 use std::sync::{
     atomic::{AtomicBool, Ordering},
-    Arc,
+    Arc, Mutex, OnceLock, Weak,
 };
 
 /// Internal shared data for a loaded library
 pub struct LoadedLib {
-    pub lib: Library,
+    /// The open library; taken out and closed once it is unloaded.
+    pub lib: Mutex<Option<Library>>,
     pub arr_ptr: *const RegistrationArray,
     /// Path from which this library was loaded (for manager bookkeeping)
     pub path: std::path::PathBuf,
+    /// `path` with symlinks and relative components resolved.
+    pub canonical_path: std::path::PathBuf,
     // We keep ownership flags: true if the RegistrationArray was created by host
     pub host_owned: bool,
     pub trait_id: PluginTrait,
+    /// Registration ABI level negotiated when the library was opened.
+    pub abi_version: u32,
     pub closed: AtomicBool,
+    /// Sidecar manifest the library was discovered through, if any.
+    pub manifest: Option<PluginManifest>,
+    /// Host context handed to the library's register function; kept alive
+    /// for as long as the library is loaded.
+    pub(crate) host_context: Option<Arc<SharedHostContext>>,
+    /// Call counters and watchdog state for the registrations.
+    pub(crate) calls: CallState,
+    /// Keeps closing and unloading from running while proxies are inside
+    /// the library.
+    pub(crate) guard: CallGuard,
+    /// Dependencies opened together with this library by a lazy lookup.
+    /// Dropped after the library, so they outlive it.
+    pub(crate) dependencies: Vec<PluginHandle>,
+    /// Private copy the library was opened from, if any; `path` is still
+    /// the artifact it was copied from. Removed after the library is closed.
+    pub(crate) shadow: Option<crate::shadow::ShadowCopy>,
+    /// The handles and proxies currently referring to the library.
+    pub(crate) holders: Holders,
+    /// Whether the library was opened into a linker namespace of its own.
+    pub(crate) own_namespace: bool,
+    /// Whether the registrations come from plugins linked into the host,
+    /// made and released through their factories alone; `lib` is then the
+    /// host's own image.
+    #[cfg(feature = "static-plugins")]
+    pub(crate) linked: bool,
+    /// Whether a `ModuleLoader` made the registrations; they are released
+    /// by the host instead of the library.
+    pub(crate) module: bool,
+    /// Instances made by `PluginHandle::instantiate` that are not in use.
+    pub(crate) instances: InstancePools,
+    /// Thread `DispatchProxy` calls run on, once one was asked for.
+    pub(crate) dispatcher: Mutex<Option<Dispatcher>>,
+    /// The names `load_plugins_filtered` made registrations for; reloads
+    /// make the same ones.
+    pub(crate) impl_filter: Option<ImplFilter>,
+    /// What each registration's `name` returned the first time a proxy
+    /// asked, memoized since names do not change while the library is
+    /// loaded. Empty until then.
+    pub(crate) names: Box<[OnceLock<String>]>,
 }
 
 impl std::fmt::Debug for LoadedLib {
@@ -23,8 +81,10 @@ impl std::fmt::Debug for LoadedLib {
         f.debug_struct("LoadedLib")
             .field("path", &self.path)
             .field("trait_id", &self.trait_id)
+            .field("abi_version", &self.abi_version)
             .field("host_owned", &self.host_owned)
             .field("closed", &self.closed.load(Ordering::SeqCst))
+            .field("manifest", &self.manifest)
             .finish()
     }
 }
@@ -36,13 +96,34 @@ impl LoadedLib {
         trait_id: PluginTrait,
         path: std::path::PathBuf,
     ) -> Self {
+        #[cfg(feature = "leak-check")]
+        crate::leaks::opened(&lib);
         Self {
-            lib,
+            lib: Mutex::new(Some(lib)),
             arr_ptr,
+            canonical_path: crate::identity::canonical_path(&path),
             path,
             host_owned: false,
             trait_id,
+            abi_version: crate::MIN_PLUGIN_ABI,
             closed: AtomicBool::new(false),
+            manifest: None,
+            host_context: None,
+            calls: CallState::for_count(registration_count(arr_ptr)),
+            guard: CallGuard::default(),
+            dependencies: Vec::new(),
+            shadow: None,
+            holders: Holders::default(),
+            own_namespace: false,
+            #[cfg(feature = "static-plugins")]
+            linked: false,
+            module: false,
+            instances: InstancePools::for_count(registration_count(arr_ptr)),
+            dispatcher: Mutex::new(None),
+            impl_filter: None,
+            names: (0..registration_count(arr_ptr))
+                .map(|_| OnceLock::new())
+                .collect(),
         }
     }
 
@@ -52,193 +133,2014 @@ impl LoadedLib {
         trait_id: PluginTrait,
         path: std::path::PathBuf,
     ) -> Self {
+        #[cfg(feature = "leak-check")]
+        crate::leaks::opened(&lib);
         Self {
-            lib,
+            lib: Mutex::new(Some(lib)),
             arr_ptr,
+            canonical_path: crate::identity::canonical_path(&path),
             path,
             host_owned: true,
             trait_id,
+            abi_version: crate::MIN_PLUGIN_ABI,
             closed: AtomicBool::new(false),
+            manifest: None,
+            host_context: None,
+            calls: CallState::for_count(registration_count(arr_ptr)),
+            guard: CallGuard::default(),
+            dependencies: Vec::new(),
+            shadow: None,
+            holders: Holders::default(),
+            own_namespace: false,
+            #[cfg(feature = "static-plugins")]
+            linked: false,
+            module: false,
+            instances: InstancePools::for_count(registration_count(arr_ptr)),
+            dispatcher: Mutex::new(None),
+            impl_filter: None,
+            names: (0..registration_count(arr_ptr))
+                .map(|_| OnceLock::new())
+                .collect(),
+        }
+    }
+}
+
+impl LoadedLib {
+    /// Copy the string a call into this library returned, and free it with
+    /// the allocator the library was given if its ABI level says the
+    /// string was allocated with it.
+    ///
+    /// # Safety
+    /// `c` must be a non-null, nul-terminated string a vtable call into
+    /// this library just returned.
+    pub(crate) unsafe fn returned_string(&self, c: *const std::os::raw::c_char) -> String {
+        let allocator = self
+            .host_context
+            .as_ref()
+            .filter(|_| self.abi_version >= HOST_ALLOCATOR_ABI)
+            .and_then(|host| host.context().allocator());
+        match allocator {
+            Some(allocator) => allocator.take_c_string(c as *mut _),
+            None => CStr::from_ptr(c).to_string_lossy().into_owned(),
         }
     }
+
+    /// True while a watchdog call into this library is past its timeout.
+    pub fn is_stuck(&self) -> bool {
+        self.calls.is_stuck()
+    }
+
+    /// Exclusive access for closing or unloading, once the calls in flight
+    /// have returned.
+    pub(crate) fn quiesce(&self) -> Result<ExclusiveAccess<'_>, String> {
+        self.guard.exclusive().map_err(|active| {
+            format!(
+                "{} call(s) into {:?} still in flight after {:?}",
+                active,
+                self.path,
+                self.guard.timeout()
+            )
+        })
+    }
+
+    /// True once the library has been unloaded, possibly ahead of its
+    /// last handle by `PluginManager::unload_by_path_with`.
+    pub(crate) fn is_unloaded(&self) -> bool {
+        self.lib.lock().unwrap_or_else(|e| e.into_inner()).is_none()
+    }
+
+    /// Unload the library now although handles or proxies still refer to
+    /// it, once the calls in flight have returned. Calls are refused from
+    /// then on and proxies report themselves stale.
+    ///
+    /// # Safety
+    /// The remaining holders must not reach into the library other than
+    /// through the proxy calls and `PluginHandle::registration_name`, which
+    /// check for this.
+    pub(crate) unsafe fn force_unload(&self) -> Result<Option<u64>, String> {
+        let quiet = self.quiesce()?;
+        quiet.retire();
+        self.calls.mark_stale();
+        self.closed.store(true, Ordering::SeqCst);
+        unload_registrations(self).map(unmaker_count)
+    }
+}
+
+fn registration_count(arr_ptr: *const RegistrationArray) -> usize {
+    if arr_ptr.is_null() {
+        0
+    } else {
+        unsafe { (*arr_ptr).count }
+    }
 }
 
 /// Opaque handle id type
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct PluginId(pub u128);
 
+impl PluginId {
+    /// Id of the registration at `index` of the array at `arr_ptr`.
+    pub(crate) fn for_registration(arr_ptr: *const RegistrationArray, index: usize) -> Self {
+        PluginId((index as u128) ^ (arr_ptr as usize as u128))
+    }
+}
+
 /// A handle representing a single registration inside a loaded library.
 #[derive(Clone, Debug)]
 pub struct PluginHandle {
-    inner: Arc<LoadedLib>,
+    inner: HandleTarget,
+    index: usize,
+    trait_id: PluginTrait,
+    id: PluginId,
+}
+
+/// Where a handle's registration lives.
+#[derive(Clone, Debug)]
+enum HandleTarget {
+    Native(LibRef),
+    #[cfg(feature = "wasm")]
+    Wasm(Arc<crate::wasm::WasmPlugin>),
+}
+
+/// A registration a long-lived host component refers to without keeping
+/// it loaded, made by `PluginHandle::downgrade`. It is not one of the
+/// library's holders, so unloading never waits for it; `upgrade` it for
+/// the duration of a call.
+#[derive(Clone, Debug)]
+pub struct WeakPluginHandle {
+    inner: WeakTarget,
     index: usize,
     trait_id: PluginTrait,
     id: PluginId,
 }
 
+#[derive(Clone, Debug)]
+enum WeakTarget {
+    Native(Weak<LoadedLib>),
+    #[cfg(feature = "wasm")]
+    Wasm(Weak<crate::wasm::WasmPlugin>),
+}
+
+impl WeakPluginHandle {
+    /// A handle to the registration, or `None` once its plugin was
+    /// unloaded, closed or replaced by a reload. The handle keeps the
+    /// library loaded until it is dropped.
+    pub fn upgrade(&self) -> Option<PluginHandle> {
+        let inner = match &self.inner {
+            WeakTarget::Native(lib) => HandleTarget::Native(LibRef::new(lib.upgrade()?, self.id)),
+            #[cfg(feature = "wasm")]
+            WeakTarget::Wasm(plugin) => HandleTarget::Wasm(plugin.upgrade()?),
+        };
+        let handle = PluginHandle {
+            inner,
+            index: self.index,
+            trait_id: self.trait_id,
+            id: self.id,
+        };
+        handle.is_alive().then_some(handle)
+    }
+
+    /// Whether `upgrade` would return a handle now.
+    pub fn is_alive(&self) -> bool {
+        match &self.inner {
+            WeakTarget::Native(lib) => lib.upgrade().is_some_and(|lib| {
+                !lib.closed.load(Ordering::SeqCst) && !lib.is_unloaded() && !lib.calls.is_stale()
+            }),
+            #[cfg(feature = "wasm")]
+            WeakTarget::Wasm(plugin) => plugin
+                .upgrade()
+                .is_some_and(|plugin| !plugin.calls.is_stale()),
+        }
+    }
+
+    /// The id of the handle this was made from.
+    pub fn id(&self) -> PluginId {
+        self.id
+    }
+
+    pub fn trait_id(&self) -> PluginTrait {
+        self.trait_id
+    }
+}
+
 impl PluginHandle {
     pub fn new(inner: Arc<LoadedLib>, index: usize, trait_id: PluginTrait) -> Self {
-        let ptr_val = inner.arr_ptr as usize as u128;
-        let id = PluginId((index as u128) ^ ptr_val);
+        let id = PluginId::for_registration(inner.arr_ptr, index);
         Self {
-            inner,
+            inner: HandleTarget::Native(LibRef::new(inner, id)),
             index,
             trait_id,
             id,
         }
     }
 
+    #[cfg(feature = "wasm")]
+    pub(crate) fn wasm(
+        plugin: Arc<crate::wasm::WasmPlugin>,
+        index: usize,
+        trait_id: PluginTrait,
+    ) -> Self {
+        // A module has no registration array; its shared state is just as
+        // unique a key.
+        let key = Arc::as_ptr(&plugin) as *const RegistrationArray;
+        Self {
+            inner: HandleTarget::Wasm(plugin),
+            index,
+            trait_id,
+            id: PluginId::for_registration(key, index),
+        }
+    }
+
     pub fn id(&self) -> PluginId {
         self.id
     }
 
-    pub fn as_greeter(&self) -> Option<GreeterProxy> {
-        if self.trait_id != PluginTrait::Greeter {
-            return None;
+    /// Index of the registration in its library.
+    #[cfg(any(feature = "record", feature = "fuzzing"))]
+    pub(crate) fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The library the registration lives in, unless it is a WebAssembly
+    /// module.
+    #[cfg(feature = "fuzzing")]
+    pub(crate) fn native_lib(&self) -> Option<&LoadedLib> {
+        match &self.inner {
+            HandleTarget::Native(lib) => Some(lib),
+            #[cfg(feature = "wasm")]
+            HandleTarget::Wasm(_) => None,
         }
-        Some(GreeterProxy {
-            inner: self.inner.clone(),
+    }
+
+    /// Path of the library or module this registration came from.
+    pub fn path(&self) -> &std::path::Path {
+        match &self.inner {
+            HandleTarget::Native(lib) => &lib.path,
+            #[cfg(feature = "wasm")]
+            HandleTarget::Wasm(plugin) => plugin.path(),
+        }
+    }
+
+    /// `path` with symlinks and relative components resolved when the
+    /// plugin was loaded. Plugins loaded through different paths to the
+    /// same file share it.
+    pub fn canonical_path(&self) -> &std::path::Path {
+        match &self.inner {
+            HandleTarget::Native(lib) => &lib.canonical_path,
+            #[cfg(feature = "wasm")]
+            HandleTarget::Wasm(plugin) => plugin.canonical_path(),
+        }
+    }
+
+    pub fn trait_id(&self) -> PluginTrait {
+        self.trait_id
+    }
+
+    /// The private copy the library was opened from, when it was loaded with
+    /// shadow copies (see `PluginManager::set_shadow_copies`) or reloaded;
+    /// `path` is always the original artifact.
+    pub fn shadow_path(&self) -> Option<&std::path::Path> {
+        match &self.inner {
+            HandleTarget::Native(lib) => lib.shadow.as_ref().map(|s| s.path()),
+            #[cfg(feature = "wasm")]
+            HandleTarget::Wasm(_) => None,
+        }
+    }
+
+    /// True if the library was opened into a linker namespace of its own;
+    /// see `LoadFlags::own_namespace`.
+    pub fn has_own_namespace(&self) -> bool {
+        match &self.inner {
+            HandleTarget::Native(lib) => lib.own_namespace,
+            #[cfg(feature = "wasm")]
+            HandleTarget::Wasm(_) => false,
+        }
+    }
+
+    /// True once `PluginManager::reload_by_path` replaced the plugin this
+    /// registration came from; proxies made from it fail with
+    /// `PluginCallError::Stale`.
+    pub fn is_stale(&self) -> bool {
+        match &self.inner {
+            HandleTarget::Native(lib) => lib.calls.is_stale(),
+            #[cfg(feature = "wasm")]
+            HandleTarget::Wasm(plugin) => plugin.calls.is_stale(),
+        }
+    }
+
+    /// True once the library was closed or unloaded, by `close` on another
+    /// handle or ahead of its handles by the manager. WebAssembly modules
+    /// live as long as their handles and are never closed.
+    pub fn is_closed(&self) -> bool {
+        match &self.inner {
+            HandleTarget::Native(lib) => lib.closed.load(Ordering::SeqCst) || lib.is_unloaded(),
+            #[cfg(feature = "wasm")]
+            HandleTarget::Wasm(_) => false,
+        }
+    }
+
+    /// True while calls can still reach the registration: the plugin is
+    /// neither closed nor replaced by a reload.
+    pub fn is_alive(&self) -> bool {
+        !self.is_closed() && !self.is_stale()
+    }
+
+    /// A reference to this registration that does not keep its library
+    /// loaded; see `WeakPluginHandle`.
+    pub fn downgrade(&self) -> WeakPluginHandle {
+        let inner = match &self.inner {
+            HandleTarget::Native(lib) => WeakTarget::Native(Arc::downgrade(lib)),
+            #[cfg(feature = "wasm")]
+            HandleTarget::Wasm(plugin) => WeakTarget::Wasm(Arc::downgrade(plugin)),
+        };
+        WeakPluginHandle {
+            inner,
             index: self.index,
+            trait_id: self.trait_id,
+            id: self.id,
+        }
+    }
+
+    /// Call statistics of this registration, as `PluginManager::stats`
+    /// reports them.
+    pub(crate) fn stats(&self) -> PluginStats {
+        let (path, calls) = match &self.inner {
+            HandleTarget::Native(lib) => (lib.path.clone(), &lib.calls),
+            #[cfg(feature = "wasm")]
+            HandleTarget::Wasm(plugin) => (plugin.path().to_path_buf(), &plugin.calls),
+        };
+        calls.counters[self.index].snapshot(self.id, path, self.index)
+    }
+
+    /// The registration's name: the `name` field `#[plugin_impl]` fills in
+    /// (the implementing type's name unless the attribute sets one), or for
+    /// registrations that leave it null, the name the plugin reports. Read
+    /// directly rather than through a proxy, so it does not show up in call
+    /// statistics.
+    pub fn registration_name(&self) -> Option<String> {
+        match &self.inner {
+            HandleTarget::Native(lib) if lib.is_unloaded() => None,
+            HandleTarget::Native(lib) => match self.trait_id {
+                PluginTrait::Greeter => unsafe {
+                    let reg = GreeterProxy::registration(lib, self.index);
+                    if !reg.name.is_null() {
+                        return Some(CStr::from_ptr(reg.name).to_string_lossy().into_owned());
+                    }
+                    let cache = &lib.names[self.index];
+                    if let Some(name) = cache.get() {
+                        return Some(name.clone());
+                    }
+                    let _permit = lib.guard.enter()?;
+                    let v = &*reg.vtable;
+                    let name = if v.abi_version >= STR_VTABLE_ABI {
+                        (v.name_str)(v.user_data).into_string()?
+                    } else {
+                        let c = (v.name)(v.user_data);
+                        if c.is_null() {
+                            return None;
+                        }
+                        lib.returned_string(c)
+                    };
+                    Some(cache.get_or_init(|| name).clone())
+                },
+                PluginTrait::Transformer => {
+                    let name = registration_name_ptr(lib, self.index);
+                    (!name.is_null()).then(|| {
+                        unsafe { CStr::from_ptr(name) }
+                            .to_string_lossy()
+                            .into_owned()
+                    })
+                }
+            },
+            #[cfg(feature = "wasm")]
+            HandleTarget::Wasm(plugin) => plugin.name(self.index).ok(),
+        }
+    }
+
+    /// The version in the manifest of the library this registration came
+    /// from, or `None` if the library was loaded without one.
+    pub fn version(&self) -> Option<&str> {
+        self.manifest().map(|m| m.version.as_str())
+    }
+
+    /// The registration name with the plugin's version, such as
+    /// `"upper@1.2.0"`, which `PluginManager::find_by_name` takes to tell
+    /// two loaded versions of a plugin apart. Just the registration name
+    /// for libraries without a manifest.
+    pub fn versioned_name(&self) -> Option<String> {
+        let name = self.registration_name()?;
+        Some(match self.version() {
+            Some(version) => format!("{}@{}", name, version),
+            None => name,
         })
     }
 
+    /// The parsed sidecar manifest of the library this registration came
+    /// from, or `None` if the library was loaded without one.
+    pub fn manifest(&self) -> Option<&PluginManifest> {
+        match &self.inner {
+            HandleTarget::Native(lib) => lib.manifest.as_ref(),
+            #[cfg(feature = "wasm")]
+            HandleTarget::Wasm(plugin) => plugin.manifest.as_ref(),
+        }
+    }
+
+    /// Where the registration sorts in `PluginManager::iter_by_priority`,
+    /// higher first: the priority in the library's manifest, else the one
+    /// its `#[plugin_impl]` declares, else 0.
+    pub fn priority(&self) -> i32 {
+        if let Some(priority) = self.manifest().and_then(|m| m.priority) {
+            return priority;
+        }
+        match &self.inner {
+            HandleTarget::Native(lib) if !lib.is_unloaded() => {
+                registration_factory(lib, self.index).map_or(0, |f| f.declared_priority())
+            }
+            _ => 0,
+        }
+    }
+
+    /// Registration ABI level negotiated with the library this registration
+    /// came from, between `MIN_PLUGIN_ABI` and `MAX_PLUGIN_ABI`. WebAssembly
+    /// modules always report level 1.
+    pub fn abi_version(&self) -> u32 {
+        match &self.inner {
+            HandleTarget::Native(lib) => lib.abi_version,
+            #[cfg(feature = "wasm")]
+            HandleTarget::Wasm(_) => crate::MIN_PLUGIN_ABI,
+        }
+    }
+
+    /// Capabilities the host granted to the library this registration came
+    /// from. Empty for WebAssembly modules.
+    pub fn granted_capabilities(&self) -> Vec<crate::Capability> {
+        match &self.inner {
+            HandleTarget::Native(lib) => lib
+                .host_context
+                .as_ref()
+                .map(|h| h.capabilities())
+                .unwrap_or_default(),
+            #[cfg(feature = "wasm")]
+            HandleTarget::Wasm(_) => Vec::new(),
+        }
+    }
+
+    /// Call the `extern "C" fn()` the library exports as `name`, holding a
+    /// call permit so the library cannot be unloaded meanwhile.
+    ///
+    /// # Safety
+    /// The symbol must have that signature.
+    pub(crate) unsafe fn call_export(&self, name: &str) -> Result<(), String> {
+        #[allow(clippy::infallible_destructuring_match)]
+        let lib = match &self.inner {
+            HandleTarget::Native(lib) => lib,
+            #[cfg(feature = "wasm")]
+            HandleTarget::Wasm(plugin) => {
+                return Err(format!("{:?} is not a native library", plugin.path()))
+            }
+        };
+        if self.is_stale() {
+            return Err(PluginCallError::Stale.to_string());
+        }
+        let _permit = lib
+            .guard
+            .enter()
+            .ok_or_else(|| PluginCallError::Unloading.to_string())?;
+        let func = {
+            let library = lib.lib.lock().unwrap_or_else(|e| e.into_inner());
+            let library = library
+                .as_ref()
+                .ok_or_else(|| PluginCallError::Unloading.to_string())?;
+            *library
+                .get::<unsafe extern "C" fn()>(name.as_bytes())
+                .map_err(|e| e.to_string())?
+        };
+        func();
+        Ok(())
+    }
+
+    /// Call `method` by name through the plugin's
+    /// `plugin_call_dynamic_<Trait>_v1` for the handle's trait, for
+    /// scripting hosts that found it with `PluginManager::describe`.
+    /// `args` is an array with one string per parameter, an object mapping
+    /// parameter names to strings, or `null` for none. Returns the method's
+    /// result as a JSON string, or `null` for methods that return nothing.
+    /// Unknown methods and libraries without the entry point fail with
+    /// `PluginCallError::Unsupported`, as do WebAssembly modules.
+    #[cfg(feature = "dynamic")]
+    pub fn call_dynamic(
+        &self,
+        method: &str,
+        args: serde_json::Value,
+    ) -> Result<serde_json::Value, PluginCallError> {
+        use plugin_abi::envelope::ErrorKind;
+        #[allow(clippy::infallible_destructuring_match)]
+        let lib = match &self.inner {
+            HandleTarget::Native(lib) => lib,
+            #[cfg(feature = "wasm")]
+            HandleTarget::Wasm(_) => return Err(PluginCallError::Unsupported),
+        };
+        if self.is_stale() {
+            return Err(PluginCallError::Stale);
+        }
+        if let Some(skipped) = lib.calls.disabled_result(self.index) {
+            return skipped;
+        }
+        let invalid = |e: &dyn std::fmt::Display| PluginCallError::InvalidArguments(e.to_string());
+        let c_method = CString::new(method).map_err(|e| invalid(&e))?;
+        let args = args.to_string();
+        let c_args = CString::new(args.as_str()).map_err(|e| invalid(&e))?;
+        let _permit = lib.guard.enter().ok_or(PluginCallError::Unloading)?;
+        let func = {
+            let library = lib.lib.lock().unwrap_or_else(|e| e.into_inner());
+            let library = library.as_ref().ok_or(PluginCallError::Unloading)?;
+            type CallDynamic = unsafe extern "C" fn(
+                *const std::ffi::c_void,
+                *const std::os::raw::c_char,
+                *const std::os::raw::c_char,
+            ) -> *const std::os::raw::c_char;
+            let symbol = symbols::versioned_c(
+                symbols::CALL_DYNAMIC,
+                lib.trait_id.as_str(),
+                symbols::CALL_DYNAMIC_VERSION,
+            );
+            *unsafe { library.get::<CallDynamic>(symbol.as_bytes()) }
+                .map_err(|_| PluginCallError::Unsupported)?
+        };
+        let reg = registration_ptr(lib, self.index);
+        instrument::warn_if_deprecated(lib.trait_id, method);
+        let reply = instrument::proxy_call(
+            &lib.path,
+            &lib.calls,
+            self.index,
+            || self.registration_name().unwrap_or_default(),
+            "call_dynamic",
+            &[method, &args],
+            || {
+                let c = unsafe { func(reg, c_method.as_ptr(), c_args.as_ptr()) };
+                if c.is_null() {
+                    return Err(PluginCallError::Failed);
+                }
+                Ok(unsafe { lib.returned_string(c) })
+            },
+        )?;
+        let mut reply: serde_json::Value = serde_json::from_str(&reply).map_err(|e| invalid(&e))?;
+        if let Some(ok) = reply.get_mut("ok") {
+            return Ok(ok.take());
+        }
+        let message = reply["message"].as_str().unwrap_or_default().to_string();
+        Err(
+            match ErrorKind::parse(reply["error"].as_str().unwrap_or_default()) {
+                ErrorKind::Unsupported => PluginCallError::Unsupported,
+                ErrorKind::InvalidArguments => PluginCallError::InvalidArguments(message),
+                ErrorKind::Failed => PluginCallError::Failed,
+            },
+        )
+    }
+
+    /// What the registration's `health` hook reports; see
+    /// `PluginManager::check_health`. Plugins built before the hook was
+    /// added report `HealthStatus::Healthy`, and WebAssembly modules fail
+    /// with `PluginCallError::Unsupported`. Not counted in the statistics.
+    pub fn health(&self) -> Result<crate::HealthStatus, PluginCallError> {
+        #[allow(clippy::infallible_destructuring_match)]
+        let lib = match &self.inner {
+            HandleTarget::Native(lib) => lib,
+            #[cfg(feature = "wasm")]
+            HandleTarget::Wasm(_) => return Err(PluginCallError::Unsupported),
+        };
+        if self.is_stale() {
+            return Err(PluginCallError::Stale);
+        }
+        if let Some(skipped) = lib.calls.disabled_result(self.index) {
+            return skipped;
+        }
+        let _permit = lib.guard.enter().ok_or(PluginCallError::Unloading)?;
+        let vtable = registration_vtable(lib, self.index);
+        let entry = unsafe {
+            match self.trait_id {
+                PluginTrait::Greeter => {
+                    let v = &*(vtable as *const GreeterVTable);
+                    v.optional_health().map(|f| (f, v.user_data))
+                }
+                PluginTrait::Transformer => {
+                    let v = &*(vtable as *const TransformerVTable);
+                    v.optional_health().map(|f| (f, v.user_data))
+                }
+            }
+        };
+        Ok(
+            entry.map_or(crate::HealthStatus::Healthy, |(health, user_data)| {
+                health(user_data)
+            }),
+        )
+    }
+
+    /// An instance of this registration of its own, for plugins with
+    /// per-request mutable state. Idle instances in the pool are handed out
+    /// first; a new one is made with the registration's factory, configured
+    /// and sent `on_load` as the registration was. The plugin sets how many
+    /// may exist at a time with `#[plugin_impl(..., instances = N)]`; past
+    /// that this fails with `PluginCallError::PoolExhausted` until one is
+    /// dropped. Registrations without a factory that allows instances, and
+    /// WebAssembly modules, fail with `PluginCallError::Unsupported`.
+    pub fn instantiate(&self) -> Result<PluginInstance, PluginCallError> {
+        #[allow(clippy::infallible_destructuring_match)]
+        let lib = match &self.inner {
+            HandleTarget::Native(lib) => lib,
+            #[cfg(feature = "wasm")]
+            HandleTarget::Wasm(_) => return Err(PluginCallError::Unsupported),
+        };
+        if self.is_stale() {
+            return Err(PluginCallError::Stale);
+        }
+        let _permit = lib.guard.enter().ok_or(PluginCallError::Unloading)?;
+        let factory = registration_factory(lib, self.index).ok_or(PluginCallError::Unsupported)?;
+        let max = factory.instances_allowed();
+        if max == 0 {
+            return Err(PluginCallError::Unsupported);
+        }
+        if let Some(registration) = lib.instances.acquire(self.index, max)? {
+            return Ok(PluginInstance::new(lib.clone(), self.index, registration));
+        }
+        let registration = (factory.maker)();
+        if registration.is_null() {
+            lib.instances.abandon(self.index);
+            return Err(PluginCallError::Failed);
+        }
+        if unsafe { check_registration(registration, self.trait_id) }.is_err() {
+            (factory.unmaker)(registration);
+            lib.instances.abandon(self.index);
+            return Err(PluginCallError::Failed);
+        }
+        if let Some(v) = unsafe { hook_entries(registration, self.trait_id) } {
+            (v.on_load)(v.user_data, HostInfo::current());
+        }
+        Ok(PluginInstance::new(lib.clone(), self.index, registration))
+    }
+
+    /// A `Send + Sync` proxy whose calls run one at a time on a thread the
+    /// plugin's library gets to itself, started by the first call of this;
+    /// see `DispatchProxy`. WebAssembly modules fail with
+    /// `PluginCallError::Unsupported`.
+    pub fn dispatcher(&self) -> Result<DispatchProxy, PluginCallError> {
+        #[allow(clippy::infallible_destructuring_match)]
+        let lib = match &self.inner {
+            HandleTarget::Native(lib) => lib,
+            #[cfg(feature = "wasm")]
+            HandleTarget::Wasm(_) => return Err(PluginCallError::Unsupported),
+        };
+        if self.is_stale() {
+            return Err(PluginCallError::Stale);
+        }
+        Ok(DispatchProxy::new(self.clone(), Dispatcher::of(lib)?))
+    }
+
+    /// A proxy of type `P` for this registration, such as
+    /// `handle.as_proxy::<TransformerProxy>()`. `None` if the registration
+    /// is of another trait or `P` cannot call it, as for WebAssembly
+    /// modules, which only provide `Greeter`.
+    pub fn as_proxy<P: PluginProxy>(&self) -> Option<P> {
+        if self.trait_id != P::TRAIT {
+            return None;
+        }
+        P::from_handle(self)
+    }
+
+    /// `as_proxy::<GreeterProxy>()`.
+    pub fn as_greeter(&self) -> Option<GreeterProxy> {
+        self.as_proxy()
+    }
+
+    /// `as_proxy::<TransformerProxy>()`.
+    pub fn as_transformer(&self) -> Option<TransformerProxy> {
+        self.as_proxy()
+    }
+
+    /// The library and index of a native registration, for the
+    /// `PluginProxy` implementations `#[plugin_interface]` generates.
+    pub(crate) fn native_registration(&self) -> Option<(LibRef, usize)> {
+        match &self.inner {
+            HandleTarget::Native(lib) => Some((lib.clone(), self.index)),
+            #[cfg(feature = "wasm")]
+            HandleTarget::Wasm(_) => None,
+        }
+    }
+
     /// Close/unload this plugin registration. If we are the last Arc owner
-    /// perform unload now and return the plugin unmaker counter if available.
+    /// perform unload now and return the plugin's lifecycle counters, read
+    /// after its registrations were released, if it exports them.
     /// Otherwise set closed and defer unload to the final Drop.
-    pub fn close(self) -> Result<Option<u64>, String> {
-        let was_closed = self.inner.closed.swap(true, Ordering::SeqCst);
-        if was_closed {
+    ///
+    /// Closing first waits for calls other proxies have in flight to return
+    /// and fails, leaving the library open, if they are still running after
+    /// the unload timeout.
+    ///
+    /// WebAssembly modules have no counters; their instance is
+    /// dropped with the last handle or proxy.
+    pub fn close(self) -> Result<Option<PluginCounters>, String> {
+        #[allow(clippy::infallible_destructuring_match)]
+        let inner = match self.inner {
+            HandleTarget::Native(lib) => lib.into_arc(),
+            #[cfg(feature = "wasm")]
+            HandleTarget::Wasm(_) => return Ok(None),
+        };
+        if inner.closed.load(Ordering::SeqCst) {
             return Ok(None);
         }
+        {
+            let _quiet = inner.quiesce()?;
+            if inner.closed.swap(true, Ordering::SeqCst) {
+                return Ok(None);
+            }
+        }
 
-        match Arc::try_unwrap(self.inner) {
+        match Arc::try_unwrap(inner) {
             Ok(loaded) => unload_loaded_lib(loaded),
             Err(_arc) => Ok(None),
         }
     }
 }
 
-pub(crate) fn unload_loaded_lib(mut loaded: LoadedLib) -> Result<Option<u64>, String> {
-    let res = perform_unload_mut(&mut loaded);
+pub(crate) fn unload_loaded_lib(mut loaded: LoadedLib) -> Result<Option<PluginCounters>, String> {
+    let res = perform_unload(&loaded);
     loaded.closed.store(true, Ordering::SeqCst);
+    // Already unloaded; keep Drop from doing it again.
+    loaded.arr_ptr = std::ptr::null();
     res
 }
 
-fn perform_unload_mut(loaded: &mut LoadedLib) -> Result<Option<u64>, String> {
-    unsafe {
-        let lib = &loaded.lib;
-        let arr_ptr = loaded.arr_ptr;
-        let trait_id = loaded.trait_id;
-        if arr_ptr.is_null() {
-            return Ok(None);
-        }
+/// The unmaker counter `PluginManager`'s unload methods report: how many
+/// registrations the plugin released.
+pub(crate) fn unmaker_count(counters: Option<PluginCounters>) -> Option<u64> {
+    counters.map(|c| c.registrations_destroyed)
+}
 
-        let arr_ref = &*arr_ptr;
-        let count = arr_ref.count;
-        if count == 0 || arr_ref.registrations.is_null() {
-            return Ok(None);
+fn perform_unload(loaded: &LoadedLib) -> Result<Option<PluginCounters>, String> {
+    // Never free registrations a proxy is still calling into.
+    let _quiet = loaded.quiesce()?;
+    unsafe { unload_registrations(loaded) }
+}
+
+/// Release the registrations and close the library, which is dropped on
+/// the way out. Does nothing if the library is already closed.
+///
+/// # Safety
+/// No calls may be running in the library, nor start while this runs.
+unsafe fn unload_registrations(loaded: &LoadedLib) -> Result<Option<PluginCounters>, String> {
+    let Some(lib) = loaded.lib.lock().unwrap_or_else(|e| e.into_inner()).take() else {
+        return Ok(None);
+    };
+    let lib = &lib;
+    // Checks the library's allocations once it was the last load of its
+    // image and everything below has been released.
+    #[cfg(feature = "leak-check")]
+    let _leaks = {
+        #[cfg(feature = "static-plugins")]
+        let host_image = loaded.linked || loaded.module;
+        #[cfg(not(feature = "static-plugins"))]
+        let host_image = loaded.module;
+        crate::leaks::UnloadCheck::new(lib, &loaded.path, !host_image)
+    };
+    let arr_ptr = loaded.arr_ptr;
+    let trait_id = loaded.trait_id;
+    if arr_ptr.is_null() {
+        return Ok(None);
+    }
+
+    let arr_ref = &*arr_ptr;
+    let count = arr_ref.count;
+    if count == 0 || arr_ref.registrations.is_null() {
+        return Ok(None);
+    }
+
+    let regs_slice = std::slice::from_raw_parts(arr_ref.registrations, count);
+
+    // Instances made from the registrations go first. Ones still in use
+    // hold the library, so only a forced unload can leave any behind.
+    for (index, r) in loaded.instances.drain() {
+        if let Some(v) = hook_entries(r, trait_id) {
+            (v.on_unload)(v.user_data);
         }
+        if let Some(factory) = registration_factory(loaded, index) {
+            (factory.unmaker)(r);
+        }
+    }
 
-        let regs_slice = std::slice::from_raw_parts(arr_ref.registrations, count);
+    // Give every registration a chance to shut down before it is released.
+    notify_unloading(arr_ptr, trait_id);
 
-        let unreg_all_sym = format!("plugin_unregister_all_{}_v1\0", trait_id.as_str());
-        let unreg_single_sym = format!("plugin_unregister_{}_v1\0", trait_id.as_str());
-        let counter_sym = format!("plugin_unmaker_counter_{}_v1\0", trait_id.as_str());
+    // Unregister through the same ABI level the library registered with.
+    let abi = loaded.abi_version;
+    let unreg_all_sym = symbols::versioned_c(symbols::UNREGISTER_ALL, trait_id.as_str(), abi);
+    let unreg_single_sym = symbols::versioned_c(symbols::UNREGISTER, trait_id.as_str(), abi);
 
-        if arr_ref.factories.is_null() {
-            if let Ok(f_all_unreg) =
-                lib.get::<unsafe extern "C" fn(*const RegistrationArray)>(unreg_all_sym.as_bytes())
-            {
-                f_all_unreg(arr_ptr);
-            } else if let Ok(fsym) = lib
-                .get::<unsafe extern "C" fn(*const std::ffi::c_void)>(unreg_single_sym.as_bytes())
-            {
-                for &r in regs_slice.iter() {
-                    if !r.is_null() {
-                        fsym(r);
-                    }
+    #[cfg(feature = "static-plugins")]
+    if loaded.linked {
+        crate::static_plugins::release(arr_ptr);
+        return Ok(None);
+    }
+    if loaded.module {
+        crate::modules::release(arr_ptr);
+        return Ok(None);
+    }
+
+    if arr_ref.factories.is_null() {
+        if let Ok(f_all_unreg) =
+            lib.get::<unsafe extern "C" fn(*const RegistrationArray)>(unreg_all_sym.as_bytes())
+        {
+            f_all_unreg(arr_ptr);
+        } else if let Ok(fsym) =
+            lib.get::<unsafe extern "C" fn(*const std::ffi::c_void)>(unreg_single_sym.as_bytes())
+        {
+            for &r in regs_slice.iter() {
+                if !r.is_null() {
+                    fsym(r);
                 }
             }
+        }
 
-            let counter = match lib.get::<unsafe extern "C" fn() -> u64>(counter_sym.as_bytes()) {
-                Ok(getter) => Some(getter()),
-                Err(_) => None,
-            };
+        let counters = crate::counters::library_counters(lib, trait_id, abi);
 
+        // Arrays the plugin returned are its own to release, whatever
+        // language it is written in.
+        if loaded.host_owned {
             let regs_ptr = arr_ref.registrations as *mut *const std::ffi::c_void;
             let _boxed_slice: Box<[*const std::ffi::c_void]> =
                 Box::from_raw(core::ptr::slice_from_raw_parts_mut(regs_ptr, count));
             let _ = Box::from_raw(arr_ptr as *mut RegistrationArray);
-            return Ok(counter);
         }
+        return Ok(counters);
+    }
 
-        if let Ok(f_all_unreg) =
-            lib.get::<unsafe extern "C" fn(*const RegistrationArray)>(unreg_all_sym.as_bytes())
-        {
-            f_all_unreg(arr_ptr);
+    // The host made these registrations through the factories.
+    if loaded.host_owned {
+        crate::impls::release_accepted(arr_ptr as *mut RegistrationArray);
+        return Ok(crate::counters::library_counters(lib, trait_id, abi));
+    }
+
+    if let Ok(f_all_unreg) =
+        lib.get::<unsafe extern "C" fn(*const RegistrationArray)>(unreg_all_sym.as_bytes())
+    {
+        f_all_unreg(arr_ptr);
+    } else {
+        let fac_slice = std::slice::from_raw_parts(arr_ref.factories, count);
+        for i in 0..count {
+            let r = regs_slice[i];
+            if r.is_null() {
+                continue;
+            }
+            let fac_ptr = fac_slice[i];
+            if !fac_ptr.is_null() {
+                let fac_ref: &crate::RegistrationFactory = &*fac_ptr;
+                (fac_ref.unmaker)(r);
+            } else if let Ok(fsym) = lib
+                .get::<unsafe extern "C" fn(*const std::ffi::c_void)>(unreg_single_sym.as_bytes())
+            {
+                fsym(r);
+            }
+        }
+    }
+
+    let counters = crate::counters::library_counters(lib, trait_id, abi);
+    Ok(counters)
+}
+
+/// Hand the state each registration of `old` saves to the registration of
+/// `new` with the same name, or for unnamed registrations, the same index.
+/// Registrations whose vtable predates the state entries are skipped, as is
+/// empty state. `new` must not be shared yet and no calls may be running in
+/// `old`.
+pub(crate) fn transfer_state(old: &LoadedLib, new: &LoadedLib) {
+    let count = registration_count(new.arr_ptr);
+    for index in 0..registration_count(old.arr_ptr) {
+        let saved = save_registration_state(registration_ptr(old, index), old.trait_id);
+        let Some(bytes) = saved.filter(|b| !b.is_empty()) else {
+            continue;
+        };
+        let name = registration_name_ptr(old, index);
+        let target = if name.is_null() {
+            (index < count).then_some(index)
         } else {
-            let fac_slice = std::slice::from_raw_parts(arr_ref.factories, count);
-            for i in 0..count {
-                let r = regs_slice[i];
-                if r.is_null() {
-                    continue;
-                }
-                let fac_ptr = fac_slice[i];
-                if !fac_ptr.is_null() {
-                    let fac_ref: &crate::RegistrationFactory = &*fac_ptr;
-                    (fac_ref.unmaker)(r);
-                } else if let Ok(fsym) = lib.get::<unsafe extern "C" fn(*const std::ffi::c_void)>(
-                    unreg_single_sym.as_bytes(),
-                ) {
-                    fsym(r);
+            let name = unsafe { CStr::from_ptr(name) };
+            (0..count).find(|&j| {
+                let other = registration_name_ptr(new, j);
+                !other.is_null() && unsafe { CStr::from_ptr(other) } == name
+            })
+        };
+        let Some(v) = target.and_then(|j| state_entries(registration_ptr(new, j), new.trait_id))
+        else {
+            continue;
+        };
+        (v.restore_state)(v.user_data, bytes.as_ptr(), bytes.len());
+    }
+}
+
+/// The entries every trait's vtable has from `LIFECYCLE_VTABLE_ABI` on,
+/// which sit at different offsets in each layout.
+struct HookEntries {
+    abi_version: u32,
+    user_data: *mut std::ffi::c_void,
+    on_load: extern "C" fn(*mut std::ffi::c_void, *const HostInfo),
+    on_unload: extern "C" fn(*mut std::ffi::c_void),
+    save_state: extern "C" fn(*mut std::ffi::c_void, *mut std::ffi::c_void, StateSink),
+    restore_state: extern "C" fn(*mut std::ffi::c_void, *const u8, usize),
+}
+
+/// The hook entries of the registration `r`, if its vtable has them.
+///
+/// # Safety
+/// `r` must point to a valid registration for `trait_id`.
+unsafe fn hook_entries(r: *const std::ffi::c_void, trait_id: PluginTrait) -> Option<HookEntries> {
+    macro_rules! entries {
+        ($registration:ty) => {{
+            let v = &*(*(r as *const $registration)).vtable;
+            // Older vtables end with `drop` right after the methods.
+            if v.abi_version < LIFECYCLE_VTABLE_ABI {
+                return None;
+            }
+            Some(HookEntries {
+                abi_version: v.abi_version,
+                user_data: v.user_data,
+                on_load: v.on_load,
+                on_unload: v.on_unload,
+                save_state: v.save_state,
+                restore_state: v.restore_state,
+            })
+        }};
+    }
+    match trait_id {
+        PluginTrait::Greeter => entries!(GreeterRegistration),
+        PluginTrait::Transformer => entries!(TransformerRegistration),
+    }
+}
+
+/// Check the sizes a plugin recorded in the array its register function
+/// returned, in the factories and in the vtables against the layouts this
+/// host reads, before anything else reads them. `abi` is the negotiated
+/// registration ABI level.
+///
+/// # Safety
+/// `arr_ptr` must point to a valid `RegistrationArray` of registrations
+/// for `trait_id`, as laid out at level `abi`.
+pub(crate) unsafe fn check_layouts(
+    arr_ptr: *const RegistrationArray,
+    trait_id: PluginTrait,
+    abi: u32,
+) -> Result<(), String> {
+    let arr = &*arr_ptr;
+    if arr.count > 0 {
+        if arr.registrations.is_null() {
+            return Err(format!(
+                "RegistrationArray lists {} registrations but no pointer to them",
+                arr.count
+            ));
+        }
+        let regs = std::slice::from_raw_parts(arr.registrations, arr.count);
+        if let Some(index) = regs.iter().position(|r| r.is_null()) {
+            return Err(format!("registration {} is null", index));
+        }
+    }
+    if abi >= SIZED_STRUCTS_ABI {
+        check_size(
+            "RegistrationArray",
+            arr.struct_size,
+            std::mem::size_of::<RegistrationArray>(),
+        )?;
+        if !arr.factories.is_null() && arr.count > 0 {
+            for &factory in std::slice::from_raw_parts(arr.factories, arr.count) {
+                if let Some(factory) = factory.as_ref() {
+                    check_size(
+                        "RegistrationFactory",
+                        factory.struct_size,
+                        // Entries appended later are checked when read.
+                        std::mem::offset_of!(RegistrationFactory, max_instances),
+                    )?;
                 }
             }
         }
+    }
+    let mut checked = Ok(());
+    for_each_registration(arr_ptr, |r| {
+        if checked.is_ok() {
+            checked = check_registration(r, trait_id);
+        }
+    });
+    checked
+}
 
-        let counter = match lib.get::<unsafe extern "C" fn() -> u64>(counter_sym.as_bytes()) {
-            Ok(getter) => Some(getter()),
-            Err(_) => None,
-        };
-        Ok(counter)
+/// Check that the registration `r` has a vtable, and the size the vtable
+/// records, if it is new enough to record one.
+///
+/// # Safety
+/// `r` must point to a valid registration for `trait_id`.
+pub(crate) unsafe fn check_registration(
+    r: *const std::ffi::c_void,
+    trait_id: PluginTrait,
+) -> Result<(), String> {
+    macro_rules! check {
+        ($registration:ty, $vtable:ty) => {{
+            let v = (*(r as *const $registration)).vtable;
+            if v.is_null() {
+                return Err("registration has no vtable".to_string());
+            }
+            let v = &*v;
+            // Optional entries are looked up one by one when called.
+            if v.abi_version >= SIZED_VTABLE_ABI {
+                check_size(
+                    stringify!($vtable),
+                    v.struct_size,
+                    std::mem::offset_of!($vtable, optional),
+                )?;
+            }
+            Ok(())
+        }};
+    }
+    match trait_id {
+        PluginTrait::Greeter => check!(GreeterRegistration, GreeterVTable),
+        PluginTrait::Transformer => check!(TransformerRegistration, TransformerVTable),
+    }
+}
+
+/// A struct recorded as `recorded` bytes must hold at least the `known`
+/// bytes this host reads; anything after them was appended later.
+fn check_size(name: &str, recorded: usize, known: usize) -> Result<(), String> {
+    if recorded < known {
+        return Err(format!(
+            "{} is {} bytes, the host reads {}",
+            name, recorded, known
+        ));
+    }
+    Ok(())
+}
+
+/// The registration at `index` of `lib`, still type-erased.
+pub(crate) fn registration_ptr(lib: &LoadedLib, index: usize) -> *const std::ffi::c_void {
+    unsafe {
+        let arr = &*lib.arr_ptr;
+        std::slice::from_raw_parts(arr.registrations, arr.count)[index]
+    }
+}
+
+/// The factory of the registration at `index` of `lib`, if the plugin
+/// provided factories.
+fn registration_factory(lib: &LoadedLib, index: usize) -> Option<&RegistrationFactory> {
+    unsafe {
+        let arr = &*lib.arr_ptr;
+        if arr.factories.is_null() {
+            return None;
+        }
+        std::slice::from_raw_parts(arr.factories, arr.count)[index].as_ref()
+    }
+}
+
+/// The `name` field of the registration at `index` of `lib`; null if the
+/// plugin left it unset.
+fn registration_name_ptr(lib: &LoadedLib, index: usize) -> *const std::os::raw::c_char {
+    let r = registration_ptr(lib, index);
+    unsafe {
+        match lib.trait_id {
+            PluginTrait::Greeter => (*(r as *const GreeterRegistration)).name,
+            PluginTrait::Transformer => (*(r as *const TransformerRegistration)).name,
+        }
+    }
+}
+
+/// The vtable of the registration at `index`, for the service registry.
+pub(crate) fn registration_vtable(lib: &LoadedLib, index: usize) -> *const std::ffi::c_void {
+    let r = registration_ptr(lib, index);
+    unsafe {
+        match lib.trait_id {
+            PluginTrait::Greeter => (*(r as *const GreeterRegistration)).vtable as *const _,
+            PluginTrait::Transformer => (*(r as *const TransformerRegistration)).vtable as *const _,
+        }
+    }
+}
+
+/// The name of every registration in `lib`, in order; `#<index>` for
+/// registrations without one.
+pub(crate) fn registration_services(lib: &LoadedLib) -> Vec<String> {
+    let count = unsafe { (*lib.arr_ptr).count };
+    (0..count)
+        .map(|index| {
+            let name = registration_name_ptr(lib, index);
+            if name.is_null() {
+                format!("#{}", index)
+            } else {
+                unsafe { CStr::from_ptr(name) }
+                    .to_string_lossy()
+                    .into_owned()
+            }
+        })
+        .collect()
+}
+
+/// The hook entries of the registration `r` if its vtable has the state
+/// entries.
+fn state_entries(r: *const std::ffi::c_void, trait_id: PluginTrait) -> Option<HookEntries> {
+    unsafe { hook_entries(r, trait_id) }.filter(|v| v.abi_version >= STATE_VTABLE_ABI)
+}
+
+fn save_registration_state(r: *const std::ffi::c_void, trait_id: PluginTrait) -> Option<Vec<u8>> {
+    extern "C" fn sink(out: *mut std::ffi::c_void, data: *const u8, len: usize) {
+        if out.is_null() || data.is_null() {
+            return;
+        }
+        let out = unsafe { &mut *(out as *mut Vec<u8>) };
+        out.extend_from_slice(unsafe { std::slice::from_raw_parts(data, len) });
+    }
+    let v = state_entries(r, trait_id)?;
+    let mut out: Vec<u8> = Vec::new();
+    (v.save_state)(
+        v.user_data,
+        &mut out as *mut Vec<u8> as *mut std::ffi::c_void,
+        sink,
+    );
+    Some(out)
+}
+
+/// Invoke the `on_load` hook of every registration in `arr_ptr`.
+///
+/// # Safety
+/// `arr_ptr` must be null or point to a valid `RegistrationArray` whose
+/// entries are registrations for `trait_id`.
+pub(crate) unsafe fn notify_loaded(
+    arr_ptr: *const RegistrationArray,
+    trait_id: PluginTrait,
+    host: &HostInfo,
+) {
+    for_each_registration(arr_ptr, |r| {
+        if let Some(v) = hook_entries(r, trait_id) {
+            (v.on_load)(v.user_data, host);
+        }
+    });
+}
+
+/// Invoke the `on_unload` hook of every registration in `arr_ptr`.
+///
+/// # Safety
+/// Same requirements as [`notify_loaded`].
+pub(crate) unsafe fn notify_unloading(arr_ptr: *const RegistrationArray, trait_id: PluginTrait) {
+    for_each_registration(arr_ptr, |r| {
+        if let Some(v) = hook_entries(r, trait_id) {
+            (v.on_unload)(v.user_data);
+        }
+    });
+}
+
+unsafe fn for_each_registration(
+    arr_ptr: *const RegistrationArray,
+    mut f: impl FnMut(*const std::ffi::c_void),
+) {
+    if arr_ptr.is_null() {
+        return;
+    }
+    let arr = &*arr_ptr;
+    if arr.count == 0 || arr.registrations.is_null() {
+        return;
+    }
+    for &r in std::slice::from_raw_parts(arr.registrations, arr.count) {
+        if !r.is_null() {
+            f(r);
+        }
     }
 }
 
 impl Drop for LoadedLib {
     fn drop(&mut self) {
-        if !self.closed.load(Ordering::SeqCst) {
-            let _ = perform_unload_mut(self);
-            self.closed.store(true, Ordering::SeqCst);
-        }
+        // Libraries marked closed while other owners were alive are unloaded
+        // here by the last owner; `unload_loaded_lib` clears `arr_ptr` once
+        // it has unloaded, which makes this a no-op.
+        let _ = perform_unload(self);
+        self.closed.store(true, Ordering::SeqCst);
+    }
+}
+
+/// A typed proxy `PluginHandle::as_proxy` makes for the registrations of
+/// one trait. `#[plugin_interface]` implements it for the proxy of each
+/// trait it generates; `GreeterProxy` implements it by hand.
+pub trait PluginProxy: Sized {
+    /// The trait whose registrations the proxy calls.
+    const TRAIT: PluginTrait;
+
+    /// A proxy for the registration `handle` refers to, which is of
+    /// `TRAIT`; `None` if this proxy cannot call it.
+    fn from_handle(handle: &PluginHandle) -> Option<Self>;
+}
+
+impl PluginProxy for GreeterProxy {
+    const TRAIT: PluginTrait = PluginTrait::Greeter;
+
+    fn from_handle(handle: &PluginHandle) -> Option<Self> {
+        let target = match &handle.inner {
+            HandleTarget::Native(lib) => ProxyTarget::InProcess(lib.clone()),
+            #[cfg(feature = "wasm")]
+            HandleTarget::Wasm(plugin) => ProxyTarget::Wasm(plugin.clone()),
+        };
+        Some(GreeterProxy {
+            target,
+            index: handle.index,
+            instance: None,
+            cached_name: OnceLock::new(),
+        })
+    }
+}
+
+/// A trait object type, such as `dyn Greeter`, that
+/// `PluginManager::collect_trait_objects` boxes registrations as. The
+/// proxy implements the trait, so host code written against the trait
+/// calls plugins like built-in implementations. `#[plugin_interface]`
+/// implements it for its traits; `dyn Greeter` implements it by hand.
+pub trait PluginObject {
+    /// The proxy standing in for the trait's implementations.
+    type Proxy: PluginProxy;
+
+    fn boxed(proxy: Self::Proxy) -> Box<Self>;
+}
+
+impl PluginObject for dyn Greeter {
+    type Proxy = GreeterProxy;
+
+    fn boxed(proxy: GreeterProxy) -> Box<Self> {
+        Box::new(proxy)
+    }
+}
+
+/// Calls go through the proxy as its own methods do. The lifecycle and
+/// state hooks are the manager's to call, so they keep their defaults.
+impl Greeter for GreeterProxy {
+    /// The registration's name, as `GreeterProxy::name` serves it; "" while
+    /// the plugin fails to produce one.
+    fn name(&self) -> &str {
+        self.cached_name().unwrap_or("")
+    }
+
+    fn greet(&self, target: &str) {
+        GreeterProxy::greet(self, target)
     }
 }
 
 /// Safe proxy for Greeter trait that hides vtable access.
+///
+/// The proxy calls into a library loaded in this process, into a
+/// WebAssembly module instance, or, for plugins loaded with
+/// `PluginManager::load_isolated`, forwards calls to the runner process
+/// hosting the plugin; the API is the same for all of them.
 #[derive(Clone, Debug)]
 pub struct GreeterProxy {
-    inner: Arc<LoadedLib>,
+    target: ProxyTarget,
     index: usize,
+    /// The instance calls go to instead of the registration, for proxies
+    /// made by `PluginInstance::as_greeter`.
+    instance: Option<Arc<Instance>>,
+    /// The name `name` serves for proxies whose registration has no
+    /// library-wide slot: instances, and isolated and wasm plugins.
+    cached_name: OnceLock<String>,
+}
+
+#[derive(Clone, Debug)]
+enum ProxyTarget {
+    InProcess(LibRef),
+    #[cfg(feature = "isolation")]
+    Isolated(Arc<crate::isolated::IsolatedPlugin>),
+    #[cfg(feature = "wasm")]
+    Wasm(Arc<crate::wasm::WasmPlugin>),
 }
 
 impl GreeterProxy {
-    pub fn name(&self) -> String {
+    #[cfg(feature = "isolation")]
+    pub(crate) fn isolated(plugin: Arc<crate::isolated::IsolatedPlugin>, index: usize) -> Self {
+        Self {
+            target: ProxyTarget::Isolated(plugin),
+            index,
+            instance: None,
+            cached_name: OnceLock::new(),
+        }
+    }
+
+    pub(crate) fn for_instance(instance: Arc<Instance>) -> Self {
+        Self {
+            target: ProxyTarget::InProcess(instance.lib.clone()),
+            index: instance.index,
+            instance: Some(instance),
+            cached_name: OnceLock::new(),
+        }
+    }
+
+    fn registration(lib: &LoadedLib, index: usize) -> &GreeterRegistration {
         unsafe {
-            let arr = &*self.inner.arr_ptr;
+            let arr = &*lib.arr_ptr;
             let regs = std::slice::from_raw_parts(arr.registrations, arr.count);
-            let reg = &*(regs[self.index] as *const GreeterRegistration);
-            let v = &*reg.vtable;
-            let c = (v.name)(v.user_data);
-            CStr::from_ptr(c).to_string_lossy().into_owned()
+            &*(regs[index] as *const GreeterRegistration)
+        }
+    }
+
+    /// The registration calls go to: this proxy's instance if it has one.
+    fn target_registration<'a>(&'a self, lib: &'a LoadedLib) -> &'a GreeterRegistration {
+        match &self.instance {
+            Some(instance) => unsafe { &*(instance.registration as *const GreeterRegistration) },
+            None => Self::registration(lib, self.index),
+        }
+    }
+
+    /// Label identifying this registration in diagnostics: the registration
+    /// name when the plugin provides one, otherwise its index.
+    fn label(&self) -> String {
+        let name = match &self.target {
+            ProxyTarget::InProcess(lib) => Self::registration(lib, self.index).name,
+            #[cfg(feature = "isolation")]
+            ProxyTarget::Isolated(_) => std::ptr::null(),
+            #[cfg(feature = "wasm")]
+            ProxyTarget::Wasm(_) => std::ptr::null(),
+        };
+        if name.is_null() {
+            format!("#{}", self.index)
+        } else {
+            unsafe { CStr::from_ptr(name) }
+                .to_string_lossy()
+                .into_owned()
+        }
+    }
+
+    fn path(&self) -> &std::path::Path {
+        match &self.target {
+            ProxyTarget::InProcess(lib) => &lib.path,
+            #[cfg(feature = "isolation")]
+            ProxyTarget::Isolated(plugin) => plugin.path(),
+            #[cfg(feature = "wasm")]
+            ProxyTarget::Wasm(plugin) => plugin.path(),
+        }
+    }
+
+    pub(crate) fn call_state(&self) -> &CallState {
+        match &self.target {
+            ProxyTarget::InProcess(lib) => &lib.calls,
+            #[cfg(feature = "isolation")]
+            ProxyTarget::Isolated(plugin) => &plugin.calls,
+            #[cfg(feature = "wasm")]
+            ProxyTarget::Wasm(plugin) => &plugin.calls,
+        }
+    }
+
+    pub(crate) fn index(&self) -> usize {
+        self.index
+    }
+
+    /// True once the plugin was replaced by `PluginManager::reload_by_path`.
+    pub fn is_stale(&self) -> bool {
+        self.call_state().is_stale()
+    }
+
+    /// The registration's name. The plugin is asked the first time any
+    /// proxy of the library wants it, and the answer is memoized on the host
+    /// side, so later calls neither cross the boundary nor allocate in the
+    /// plugin, and do not show up in call statistics. A registration that is
+    /// turned off answers like `try_name` does, memoized or not. Returns an
+    /// empty string (and counts an error) while the plugin fails to produce
+    /// one.
+    pub fn name(&self) -> String {
+        self.cached_name().map(str::to_owned).unwrap_or_default()
+    }
+
+    /// Where `name` keeps the name: the library's slot for the
+    /// registration, or the proxy's own for instances and out-of-process
+    /// plugins.
+    fn name_cache(&self) -> &OnceLock<String> {
+        match &self.target {
+            ProxyTarget::InProcess(lib) if self.instance.is_none() => &lib.names[self.index],
+            _ => &self.cached_name,
+        }
+    }
+
+    fn cached_name(&self) -> Result<&str, PluginCallError> {
+        if self.is_stale() {
+            return Err(PluginCallError::Stale);
+        }
+        if let Some(skipped) = self.call_state().disabled_result(self.index) {
+            return skipped;
+        }
+        let cache = self.name_cache();
+        if let Some(name) = cache.get() {
+            return Ok(name);
         }
+        let name = self.try_name()?;
+        Ok(cache.get_or_init(|| name))
+    }
+
+    /// `name`, asking the plugin every time and reporting why the call
+    /// failed instead of returning an empty string (for example
+    /// `PluginCallError::Crashed` for isolated plugins).
+    pub fn try_name(&self) -> Result<String, PluginCallError> {
+        if self.is_stale() {
+            return Err(PluginCallError::Stale);
+        }
+        if let Some(skipped) = self.call_state().disabled_result(self.index) {
+            return skipped;
+        }
+        instrument::warn_if_deprecated(PluginTrait::Greeter, "name");
+        instrument::proxy_call(
+            self.path(),
+            self.call_state(),
+            self.index,
+            || self.label(),
+            "name",
+            &[],
+            || match &self.target {
+                ProxyTarget::InProcess(lib) => unsafe {
+                    let _permit = lib.guard.enter().ok_or(PluginCallError::Unloading)?;
+                    let v = &*self.target_registration(lib).vtable;
+                    if v.abi_version >= STR_VTABLE_ABI {
+                        return (v.name_str)(v.user_data)
+                            .into_string()
+                            .ok_or(PluginCallError::Failed);
+                    }
+                    let c = (v.name)(v.user_data);
+                    if c.is_null() {
+                        return Err(PluginCallError::Failed);
+                    }
+                    Ok(lib.returned_string(c))
+                },
+                #[cfg(feature = "isolation")]
+                ProxyTarget::Isolated(plugin) => plugin.name(self.index),
+                #[cfg(feature = "wasm")]
+                ProxyTarget::Wasm(plugin) => plugin.name(self.index),
+            },
+        )
     }
 
     pub fn greet(&self, target: &str) {
-        let c_target = CString::new(target).expect("target contains null");
+        let _ = self.try_greet(target);
+    }
+
+    /// The state the registration's `save_state` hook returns, as it would
+    /// be handed to a new version by `PluginManager::reload_by_path`. `None`
+    /// for registrations built without the state hooks, for isolated and
+    /// WebAssembly plugins, and for stale or unloading libraries.
+    pub fn save_state(&self) -> Option<Vec<u8>> {
+        match &self.target {
+            ProxyTarget::InProcess(lib) => {
+                if self.is_stale() {
+                    return None;
+                }
+                let _permit = lib.guard.enter()?;
+                let r = self.target_registration(lib) as *const GreeterRegistration;
+                save_registration_state(r as *const std::ffi::c_void, lib.trait_id)
+            }
+            #[cfg(feature = "isolation")]
+            ProxyTarget::Isolated(_) => None,
+            #[cfg(feature = "wasm")]
+            ProxyTarget::Wasm(_) => None,
+        }
+    }
+
+    /// `greet`, reporting why the call failed.
+    pub fn try_greet(&self, target: &str) -> Result<(), PluginCallError> {
+        if self.is_stale() {
+            return Err(PluginCallError::Stale);
+        }
+        if let Some(skipped) = self.call_state().disabled_result(self.index) {
+            return skipped;
+        }
+        instrument::warn_if_deprecated(PluginTrait::Greeter, "greet");
+        instrument::proxy_call(
+            self.path(),
+            self.call_state(),
+            self.index,
+            || self.label(),
+            "greet",
+            &[target],
+            || self.call_greet(target, None),
+        )
+    }
+
+    /// `greet`, with `ctx` as the plugin's current request (see
+    /// `with_current_request`). Fails with `PluginCallError::Cancelled`
+    /// without calling the plugin if `ctx` is already cancelled or past its
+    /// deadline. Plugins without a `greet_ctx` entry, and isolated and wasm
+    /// ones, are called without it.
+    pub fn greet_in(&self, target: &str, ctx: &RequestContext) -> Result<(), PluginCallError> {
+        if self.is_stale() {
+            return Err(PluginCallError::Stale);
+        }
+        if let Some(skipped) = self.call_state().disabled_result(self.index) {
+            return skipped;
+        }
+        if ctx.is_cancelled() {
+            return Err(PluginCallError::Cancelled);
+        }
+        let raw = ctx.raw();
+        instrument::warn_if_deprecated(PluginTrait::Greeter, "greet");
+        instrument::proxy_call(
+            self.path(),
+            self.call_state(),
+            self.index,
+            || self.label(),
+            "greet",
+            &[target],
+            || self.call_greet(target, Some(&raw)),
+        )
+    }
+
+    fn call_greet(
+        &self,
+        target: &str,
+        ctx: Option<&RawRequestContext>,
+    ) -> Result<(), PluginCallError> {
+        match &self.target {
+            ProxyTarget::InProcess(lib) => {
+                let _permit = lib.guard.enter().ok_or(PluginCallError::Unloading)?;
+                let v = unsafe { &*self.target_registration(lib).vtable };
+                match (ctx, v.optional_greet_ctx()) {
+                    (Some(ctx), Some(greet_ctx)) => {
+                        greet_ctx(v.user_data, StrRef::new(target), ctx);
+                    }
+                    _ if v.abi_version >= STR_VTABLE_ABI => {
+                        (v.greet_str)(v.user_data, StrRef::new(target));
+                    }
+                    _ => {
+                        let c_target = CString::new(target).expect("target contains null");
+                        (v.greet)(v.user_data, c_target.as_ptr());
+                    }
+                }
+                Ok(())
+            }
+            #[cfg(feature = "isolation")]
+            ProxyTarget::Isolated(plugin) => plugin.greet(self.index, target),
+            #[cfg(feature = "wasm")]
+            ProxyTarget::Wasm(plugin) => plugin.greet(self.index, target),
+        }
+    }
+
+    /// `name` under a watchdog: fails with `PluginCallError::Timeout` if the
+    /// plugin does not answer within `opts.timeout`.
+    pub fn name_with(&self, opts: &CallOptions) -> Result<String, PluginCallError> {
+        call::with_watchdog(self, opts, |p| p.try_name())
+    }
+
+    /// `greet` under a watchdog: fails with `PluginCallError::Timeout` if the
+    /// plugin does not return within `opts.timeout`.
+    pub fn greet_with(&self, target: &str, opts: &CallOptions) -> Result<(), PluginCallError> {
+        let target = target.to_string();
+        call::with_watchdog(self, opts, move |p| p.try_greet(&target))
+    }
+}
+
+/// Safe proxy for the `Transformer` trait. Transformers are only loaded in
+/// process.
+#[derive(Clone, Debug)]
+pub struct TransformerProxy {
+    lib: LibRef,
+    index: usize,
+    /// The instance calls go to instead of the registration, for proxies
+    /// made by `PluginInstance::as_transformer`.
+    instance: Option<Arc<Instance>>,
+}
+
+/// Calls go through the proxy as its own methods do; the hooks keep their
+/// defaults, like `GreeterProxy`'s.
+impl Transformer for TransformerProxy {
+    fn transform(&self, input: &str) -> String {
+        TransformerProxy::transform(self, input)
+    }
+
+    fn describe(&self) -> String {
+        TransformerProxy::describe(self)
+    }
+}
+
+impl TransformerProxy {
+    pub(crate) fn for_registration(lib: LibRef, index: usize) -> Self {
+        Self {
+            lib,
+            index,
+            instance: None,
+        }
+    }
+
+    pub(crate) fn for_instance(instance: Arc<Instance>) -> Self {
+        Self {
+            lib: instance.lib.clone(),
+            index: instance.index,
+            instance: Some(instance),
+        }
+    }
+
+    fn vtable(&self) -> &crate::TransformerVTable {
+        let r = match &self.instance {
+            Some(instance) => instance.registration,
+            None => registration_ptr(&self.lib, self.index),
+        };
+        unsafe { &*(*(r as *const TransformerRegistration)).vtable }
+    }
+
+    fn label(&self) -> String {
+        let name = registration_name_ptr(&self.lib, self.index);
+        if name.is_null() {
+            format!("#{}", self.index)
+        } else {
+            unsafe { CStr::from_ptr(name) }
+                .to_string_lossy()
+                .into_owned()
+        }
+    }
+
+    /// True once the plugin was replaced by `PluginManager::reload_by_path`.
+    pub fn is_stale(&self) -> bool {
+        self.lib.calls.is_stale()
+    }
+
+    /// The transformed `input`. Returns an empty string (and counts an
+    /// error) if the plugin fails to produce one.
+    pub fn transform(&self, input: &str) -> String {
+        self.try_transform(input).unwrap_or_default()
+    }
+
+    /// `transform`, reporting why the call failed.
+    pub fn try_transform(&self, input: &str) -> Result<String, PluginCallError> {
+        if self.is_stale() {
+            return Err(PluginCallError::Stale);
+        }
+        if let Some(skipped) = self.lib.calls.disabled_result(self.index) {
+            return skipped;
+        }
+        let lib = &self.lib;
+        instrument::warn_if_deprecated(PluginTrait::Transformer, "transform");
+        instrument::proxy_call(
+            &lib.path,
+            &lib.calls,
+            self.index,
+            || self.label(),
+            "transform",
+            &[input],
+            || self.call_transform(input, None),
+        )
+    }
+
+    /// `transform`, with `ctx` as the plugin's current request (see
+    /// `with_current_request`). Fails with `PluginCallError::Cancelled`
+    /// without calling the plugin if `ctx` is already cancelled or past its
+    /// deadline. Plugins without a `transform_ctx` entry are called without
+    /// it.
+    pub fn transform_in(
+        &self,
+        input: &str,
+        ctx: &RequestContext,
+    ) -> Result<String, PluginCallError> {
+        if self.is_stale() {
+            return Err(PluginCallError::Stale);
+        }
+        if let Some(skipped) = self.lib.calls.disabled_result(self.index) {
+            return skipped;
+        }
+        if ctx.is_cancelled() {
+            return Err(PluginCallError::Cancelled);
+        }
+        let raw = ctx.raw();
+        let lib = &self.lib;
+        instrument::warn_if_deprecated(PluginTrait::Transformer, "transform");
+        instrument::proxy_call(
+            &lib.path,
+            &lib.calls,
+            self.index,
+            || self.label(),
+            "transform",
+            &[input],
+            || self.call_transform(input, Some(&raw)),
+        )
+    }
+
+    fn call_transform(
+        &self,
+        input: &str,
+        ctx: Option<&RawRequestContext>,
+    ) -> Result<String, PluginCallError> {
+        let lib = &self.lib;
+        let _permit = lib.guard.enter().ok_or(PluginCallError::Unloading)?;
+        let v = self.vtable();
+        if let (Some(ctx), Some(transform_ctx)) = (ctx, v.optional_transform_ctx()) {
+            return unsafe { transform_ctx(v.user_data, StrRef::new(input), ctx).into_string() }
+                .ok_or(PluginCallError::Failed);
+        }
+        if v.abi_version >= STR_VTABLE_ABI {
+            return unsafe { (v.transform_str)(v.user_data, StrRef::new(input)).into_string() }
+                .ok_or(PluginCallError::Failed);
+        }
+        let c_input = CString::new(input).map_err(|_| PluginCallError::Failed)?;
+        let c = (v.transform)(v.user_data, c_input.as_ptr());
+        if c.is_null() {
+            return Err(PluginCallError::Failed);
+        }
+        Ok(unsafe { lib.returned_string(c) })
+    }
+
+    /// What the transformation does, or the registration name for plugins
+    /// built before `Transformer::describe` was added or whose call fails.
+    pub fn describe(&self) -> String {
+        self.try_describe().unwrap_or_else(|_| self.label())
+    }
+
+    /// `describe`, failing with `PluginCallError::Unsupported` instead of
+    /// falling back when the plugin has no entry for it.
+    pub fn try_describe(&self) -> Result<String, PluginCallError> {
+        if self.is_stale() {
+            return Err(PluginCallError::Stale);
+        }
+        if let Some(skipped) = self.lib.calls.disabled_result(self.index) {
+            return skipped;
+        }
+        let lib = &self.lib;
+        let _permit = lib.guard.enter().ok_or(PluginCallError::Unloading)?;
+        let v = self.vtable();
+        // A missing entry is not a failed call; check before counting one.
+        let describe = v.optional_describe().ok_or(PluginCallError::Unsupported)?;
+        instrument::warn_if_deprecated(PluginTrait::Transformer, "describe");
+        instrument::proxy_call(
+            &lib.path,
+            &lib.calls,
+            self.index,
+            || self.label(),
+            "describe",
+            &[],
+            || unsafe { describe(v.user_data).into_string() }.ok_or(PluginCallError::Failed),
+        )
+    }
+}
+
+#[cfg(all(test, unix))]
+pub(crate) mod test_support {
+    use super::*;
+    use crate::GreeterVTable;
+    use std::ffi::c_void;
+    use std::os::raw::c_char;
+
+    extern "C" fn fake_name(_: *mut c_void) -> *const c_char {
+        c"fake".as_ptr()
+    }
+
+    extern "C" fn fake_name_str(_: *mut c_void) -> crate::OwnedStr {
+        crate::OwnedStr::new("fake".to_string())
+    }
+
+    // Sleeps for as many milliseconds as `target` parses to.
+    fn sleep_for(target: &str) {
+        let ms: u64 = target.parse().unwrap_or(0);
+        std::thread::sleep(std::time::Duration::from_millis(ms));
+    }
+
+    extern "C" fn fake_greet(_: *mut c_void, target: *const c_char) {
+        sleep_for(unsafe { CStr::from_ptr(target) }.to_str().unwrap_or(""));
+    }
+
+    extern "C" fn fake_greet_str(_: *mut c_void, target: crate::StrRef) {
+        sleep_for(unsafe { target.as_str() });
+    }
+
+    extern "C" fn fake_on_load(_: *mut c_void, _: *const HostInfo) {}
+    extern "C" fn noop(_: *mut c_void) {}
+    extern "C" fn no_state(_: *mut c_void, _: *mut c_void, _: crate::StateSink) {}
+    extern "C" fn no_restore(_: *mut c_void, _: *const u8, _: usize) {}
+
+    /// A `Greeter` vtable backed by host-side function pointers.
+    pub(crate) fn fake_greeter_vtable() -> GreeterVTable {
+        GreeterVTable {
+            abi_version: crate::SIZED_VTABLE_ABI,
+            user_data: std::ptr::null_mut(),
+            name: fake_name,
+            greet: fake_greet,
+            on_load: fake_on_load,
+            on_unload: noop,
+            drop: noop,
+            save_state: no_state,
+            restore_state: no_restore,
+            name_str: fake_name_str,
+            greet_str: fake_greet_str,
+            struct_size: std::mem::size_of::<GreeterVTable>(),
+            flags: 0,
+            optional: crate::GreeterOptional { health: None },
+            context: crate::GreeterContext {
+                struct_size: std::mem::size_of::<crate::GreeterContext>(),
+                name_ctx: None,
+                greet_ctx: None,
+            },
+        }
+    }
+
+    /// A `Greeter` handle backed by `fake_greeter_vtable` and the current
+    /// process image instead of a real plugin library.
+    pub(crate) fn fake_greeter_handle() -> PluginHandle {
+        let vtable = Box::into_raw(Box::new(fake_greeter_vtable()));
+        let reg = Box::into_raw(Box::new(GreeterRegistration {
+            name: std::ptr::null(),
+            vtable,
+        }));
+        let regs: Box<[*const std::ffi::c_void]> = vec![reg as *const std::ffi::c_void].into();
+        let arr = Box::into_raw(Box::new(RegistrationArray::new(
+            1,
+            Box::into_raw(regs) as *const *const std::ffi::c_void,
+            std::ptr::null(),
+        )));
+        let lib: Library = libloading::os::unix::Library::this().into();
+        let loaded = LoadedLib::new_host_owned(
+            lib,
+            arr,
+            PluginTrait::Greeter,
+            std::path::PathBuf::from("fake"),
+        );
+        #[allow(clippy::arc_with_non_send_sync)]
+        PluginHandle::new(Arc::new(loaded), 0, PluginTrait::Greeter)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::test_support::fake_greeter_vtable;
+    use super::*;
+
+    #[test]
+    fn vtables_older_than_the_lifecycle_entries_get_no_hooks() {
+        let current = fake_greeter_vtable();
+        let old = GreeterVTable {
+            abi_version: LIFECYCLE_VTABLE_ABI - 1,
+            ..fake_greeter_vtable()
+        };
+        for (vtable, has_hooks) in [(&current, true), (&old, false)] {
+            let reg = GreeterRegistration {
+                name: std::ptr::null(),
+                vtable,
+            };
+            let r = &reg as *const GreeterRegistration as *const std::ffi::c_void;
+            let entries = unsafe { hook_entries(r, PluginTrait::Greeter) };
+            assert_eq!(entries.is_some(), has_hooks);
+        }
+    }
+
+    #[test]
+    fn layouts_smaller_than_promised_are_refused() {
+        let vtable = fake_greeter_vtable();
+        let reg = GreeterRegistration {
+            name: std::ptr::null(),
+            vtable: &vtable,
+        };
+        let regs = [&reg as *const GreeterRegistration as *const std::ffi::c_void];
+        let mut arr = RegistrationArray::new(1, regs.as_ptr(), std::ptr::null());
         unsafe {
-            let arr = &*self.inner.arr_ptr;
-            let regs = std::slice::from_raw_parts(arr.registrations, arr.count);
-            let reg = &*(regs[self.index] as *const GreeterRegistration);
-            let v = &*reg.vtable;
-            (v.greet)(v.user_data, c_target.as_ptr());
+            assert_eq!(
+                check_layouts(&arr, PluginTrait::Greeter, SIZED_STRUCTS_ABI),
+                Ok(())
+            );
+            arr.struct_size = 8;
+            assert!(check_layouts(&arr, PluginTrait::Greeter, SIZED_STRUCTS_ABI).is_err());
+            // Below the level that has `struct_size`, the field is not read.
+            assert_eq!(
+                check_layouts(&arr, PluginTrait::Greeter, SIZED_STRUCTS_ABI - 1),
+                Ok(())
+            );
+        }
+
+        let small = GreeterVTable {
+            struct_size: 16,
+            ..fake_greeter_vtable()
+        };
+        let reg = GreeterRegistration {
+            name: std::ptr::null(),
+            vtable: &small,
+        };
+        let r = &reg as *const GreeterRegistration as *const std::ffi::c_void;
+        let err = unsafe { check_registration(r, PluginTrait::Greeter) }.unwrap_err();
+        assert!(err.contains("GreeterVTable is 16 bytes"), "{}", err);
+    }
+
+    #[test]
+    fn null_registrations_and_vtables_are_refused() {
+        let reg = GreeterRegistration {
+            name: std::ptr::null(),
+            vtable: std::ptr::null(),
+        };
+        let regs = [&reg as *const GreeterRegistration as *const std::ffi::c_void];
+        let arr = RegistrationArray::new(1, regs.as_ptr(), std::ptr::null());
+        let err = unsafe { check_layouts(&arr, PluginTrait::Greeter, 1) }.unwrap_err();
+        assert_eq!(err, "registration has no vtable");
+
+        let regs = [std::ptr::null()];
+        let arr = RegistrationArray::new(1, regs.as_ptr(), std::ptr::null());
+        let err = unsafe { check_layouts(&arr, PluginTrait::Greeter, 1) }.unwrap_err();
+        assert_eq!(err, "registration 0 is null");
+
+        let arr = RegistrationArray::new(2, std::ptr::null(), std::ptr::null());
+        assert!(unsafe { check_layouts(&arr, PluginTrait::Greeter, 1) }.is_err());
+    }
+
+    #[derive(Default)]
+    struct Shout;
+
+    impl crate::Transformer for Shout {
+        fn transform(&self, input: &str) -> String {
+            input.to_uppercase()
+        }
+
+        fn describe(&self) -> String {
+            "shouts".to_string()
         }
     }
+
+    extern "C" fn no_transform(
+        _: *mut std::ffi::c_void,
+        _: *const std::os::raw::c_char,
+    ) -> *const std::os::raw::c_char {
+        std::ptr::null()
+    }
+    extern "C" fn no_transform_str(_: *mut std::ffi::c_void, _: StrRef) -> crate::OwnedStr {
+        crate::OwnedStr::null()
+    }
+    extern "C" fn no_load(_: *mut std::ffi::c_void, _: *const HostInfo) {}
+    extern "C" fn noop(_: *mut std::ffi::c_void) {}
+    extern "C" fn no_state(_: *mut std::ffi::c_void, _: *mut std::ffi::c_void, _: StateSink) {}
+    extern "C" fn no_restore(_: *mut std::ffi::c_void, _: *const u8, _: usize) {}
+
+    /// A `Shout` vtable at `abi_version` that claims to be `struct_size`
+    /// bytes.
+    fn shout_vtable(abi_version: u32, struct_size: usize) -> TransformerVTable {
+        static SHOUT: Shout = Shout;
+        TransformerVTable {
+            abi_version,
+            user_data: &SHOUT as *const Shout as *mut std::ffi::c_void,
+            transform: no_transform,
+            on_load: no_load,
+            on_unload: noop,
+            drop: noop,
+            save_state: no_state,
+            restore_state: no_restore,
+            transform_str: no_transform_str,
+            struct_size,
+            flags: 0,
+            optional: crate::TransformerOptional::for_impl::<Shout, crate::Uncounted>(),
+            context: crate::TransformerContext::for_impl::<Shout, crate::Uncounted>(),
+        }
+    }
+
+    /// A `Shout` transformer whose vtable claims to be `struct_size` bytes.
+    fn shout_handle(struct_size: usize) -> PluginHandle {
+        let vtable = Box::into_raw(Box::new(shout_vtable(SIZED_VTABLE_ABI, struct_size)));
+        let reg = Box::into_raw(Box::new(TransformerRegistration {
+            name: c"shout".as_ptr(),
+            vtable,
+        }));
+        let regs: Box<[*const std::ffi::c_void]> = vec![reg as *const std::ffi::c_void].into();
+        let arr = Box::into_raw(Box::new(RegistrationArray::new(
+            1,
+            Box::into_raw(regs) as *const *const std::ffi::c_void,
+            std::ptr::null(),
+        )));
+        let lib: Library = libloading::os::unix::Library::this().into();
+        let loaded = LoadedLib::new_host_owned(
+            lib,
+            arr,
+            PluginTrait::Transformer,
+            std::path::PathBuf::from("fake"),
+        );
+        #[allow(clippy::arc_with_non_send_sync)]
+        PluginHandle::new(Arc::new(loaded), 0, PluginTrait::Transformer)
+    }
+
+    #[test]
+    fn optional_entries_past_struct_size_are_unsupported() {
+        let full = shout_handle(std::mem::size_of::<TransformerVTable>());
+        let proxy = full.as_transformer().unwrap();
+        assert_eq!(proxy.try_describe().as_deref(), Ok("shouts"));
+        assert_eq!(proxy.describe(), "shouts");
+
+        // Built before `describe` was appended: the vtable stops at `optional`.
+        let old = shout_handle(std::mem::offset_of!(TransformerVTable, optional));
+        let proxy = old.as_transformer().unwrap();
+        assert_eq!(proxy.try_describe(), Err(PluginCallError::Unsupported));
+        assert_eq!(proxy.describe(), "shout");
+    }
+
+    #[test]
+    fn context_entries_follow_the_plugins_own_vtable() {
+        // Built before `health` was appended: its context table starts
+        // where `health` is in this build.
+        let struct_size = std::mem::offset_of!(TransformerVTable, optional)
+            + std::mem::offset_of!(crate::TransformerOptional, health);
+        let full = shout_vtable(crate::CONTEXT_VTABLE_ABI, struct_size);
+        let mut words = vec![0usize; std::mem::size_of::<TransformerVTable>()];
+        let v = unsafe {
+            let base = words.as_mut_ptr() as *mut u8;
+            std::ptr::copy_nonoverlapping(&full as *const _ as *const u8, base, struct_size);
+            std::ptr::write(
+                base.add(struct_size) as *mut crate::TransformerContext,
+                crate::TransformerContext::for_impl::<Shout, crate::Uncounted>(),
+            );
+            &*(base as *const TransformerVTable)
+        };
+        assert!(v.optional_describe().is_some());
+        assert!(v.optional_health().is_none());
+        let transform_ctx = v.optional_transform_ctx().expect("context entry");
+        let out = transform_ctx(v.user_data, StrRef::new("hi"), std::ptr::null());
+        assert_eq!(unsafe { out.into_string() }.as_deref(), Some("HI"));
+
+        // Vtables below `CONTEXT_VTABLE_ABI` have no table.
+        let sized = shout_vtable(SIZED_VTABLE_ABI, std::mem::size_of::<TransformerVTable>());
+        assert!(sized.optional_transform_ctx().is_none());
+    }
 }
@@ -0,0 +1,119 @@
+//! Health checks: the traits' optional `health` hook, which
+//! `PluginManager::check_health` asks every registration, and quarantine of
+//! registrations that keep reporting themselves unhealthy.
+
+use crate::handle::PluginId;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// What a plugin's `health` hook reports. Crosses the boundary as a C enum.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HealthStatus {
+    #[default]
+    Healthy,
+    /// Working, but not as well as it should; never quarantined for it.
+    Degraded,
+    /// Counts towards `HealthCheckOptions::max_failures`. A `health` hook
+    /// that panics reports this too.
+    Unhealthy,
+}
+
+impl std::fmt::Display for HealthStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            HealthStatus::Healthy => "healthy",
+            HealthStatus::Degraded => "degraded",
+            HealthStatus::Unhealthy => "unhealthy",
+        })
+    }
+}
+
+/// What `PluginManager::check_health` does with a registration that failed
+/// too many checks in a row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QuarantineAction {
+    /// Turn the registration off, like `PluginManager::set_enabled`.
+    #[default]
+    Disable,
+    /// Turn it off and unload its library with `unload_by_path`.
+    Unload,
+}
+
+/// Settings for `PluginManager::check_health` and the heartbeat
+/// `PluginManagerActor::start_health_checks` runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthCheckOptions {
+    /// Time between two rounds of the heartbeat. Defaults to 30 seconds.
+    pub interval: Duration,
+    /// Consecutive `Unhealthy` reports after which a registration is
+    /// quarantined; 0 never quarantines. Defaults to 3.
+    pub max_failures: u32,
+    pub action: QuarantineAction,
+}
+
+impl Default for HealthCheckOptions {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(30),
+            max_failures: 3,
+            action: QuarantineAction::default(),
+        }
+    }
+}
+
+/// One registration's answer in a round of `PluginManager::check_health`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthCheck {
+    pub id: PluginId,
+    /// Library the registration was loaded from.
+    pub path: PathBuf,
+    /// Index of the registration inside the library.
+    pub index: usize,
+    pub status: HealthStatus,
+    /// `Unhealthy` reports in a row, this one included.
+    pub failures: u32,
+    /// Whether this round quarantined the registration.
+    pub quarantined: bool,
+}
+
+/// Owns the heartbeat thread of `PluginManagerActor::start_health_checks`.
+/// Dropping the guard stops the thread and waits for it to exit.
+#[derive(Debug)]
+#[must_use = "dropping the guard stops the health checks"]
+pub struct HealthCheckGuard {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl HealthCheckGuard {
+    /// Run `round` every `interval` on a new thread until the guard is
+    /// dropped or `round` returns false.
+    pub(crate) fn spawn(interval: Duration, round: impl Fn() -> bool + Send + 'static) -> Self {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = std::thread::Builder::new()
+            .name("plugin-health-checks".into())
+            .spawn(move || {
+                while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                    if !round() {
+                        break;
+                    }
+                }
+            })
+            .expect("spawn health check thread");
+        Self {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for HealthCheckGuard {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! Bookkeeping of the handles and proxies that keep a native library
+//! loaded, so a deferred unload can say who is still holding on.
+
+use crate::handle::LoadedLib;
+use crate::PluginId;
+use std::collections::BTreeMap;
+use std::mem::ManuallyDrop;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// The live holders of one library, in creation order.
+#[derive(Default)]
+pub(crate) struct Holders {
+    next: AtomicU64,
+    live: Mutex<BTreeMap<u64, Holder>>,
+}
+
+struct Holder {
+    id: PluginId,
+    #[cfg(feature = "holder-backtraces")]
+    created_at: String,
+}
+
+impl Holders {
+    fn add(&self, id: PluginId) -> u64 {
+        let key = self.next.fetch_add(1, Ordering::Relaxed);
+        let holder = Holder {
+            id,
+            #[cfg(feature = "holder-backtraces")]
+            created_at: std::backtrace::Backtrace::force_capture().to_string(),
+        };
+        lock(&self.live).insert(key, holder);
+        key
+    }
+
+    fn remove(&self, key: u64) {
+        lock(&self.live).remove(&key);
+    }
+
+    /// Registration id of every live holder.
+    pub(crate) fn ids(&self) -> Vec<PluginId> {
+        lock(&self.live).values().map(|h| h.id).collect()
+    }
+
+    /// Where every live holder was created, in the order of `ids`.
+    #[cfg(feature = "holder-backtraces")]
+    pub(crate) fn origins(&self) -> Vec<String> {
+        lock(&self.live)
+            .values()
+            .map(|h| h.created_at.clone())
+            .collect()
+    }
+}
+
+fn lock<T>(m: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// The reference a handle or proxy keeps to its library. It counts as one
+/// of the library's holders for as long as it lives; clones count
+/// separately.
+pub(crate) struct LibRef {
+    lib: Arc<LoadedLib>,
+    key: u64,
+    id: PluginId,
+}
+
+impl LibRef {
+    pub(crate) fn new(lib: Arc<LoadedLib>, id: PluginId) -> Self {
+        let key = lib.holders.add(id);
+        Self { lib, key, id }
+    }
+
+    /// Stop counting as a holder and hand back the plain reference.
+    pub(crate) fn into_arc(self) -> Arc<LoadedLib> {
+        let this = ManuallyDrop::new(self);
+        this.lib.holders.remove(this.key);
+        // SAFETY: `this` is never dropped, so the Arc is moved out once.
+        unsafe { std::ptr::read(&this.lib) }
+    }
+}
+
+impl Clone for LibRef {
+    fn clone(&self) -> Self {
+        Self::new(self.lib.clone(), self.id)
+    }
+}
+
+impl Drop for LibRef {
+    fn drop(&mut self) {
+        self.lib.holders.remove(self.key);
+    }
+}
+
+impl std::ops::Deref for LibRef {
+    type Target = Arc<LoadedLib>;
+
+    fn deref(&self) -> &Arc<LoadedLib> {
+        &self.lib
+    }
+}
+
+impl std::fmt::Debug for LibRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.lib.fmt(f)
+    }
+}
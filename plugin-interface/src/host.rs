@@ -0,0 +1,1065 @@
+use crate::bus::{BusError, EventBus, EventCallback};
+use crate::capability::{
+    Capability, FilesystemService, NetworkService, ThreadService, FILESYSTEM_SERVICE,
+    NETWORK_SERVICE, THREAD_SERVICE,
+};
+use crate::kv::KvStore;
+use crate::services::ServiceRegistry;
+use crate::StateSink;
+use std::collections::HashMap;
+use std::ffi::{c_void, CStr, CString};
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+
+/// Severity of a message a plugin logs through its `HostContext`.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+    Trace = 5,
+}
+
+#[cfg(feature = "log")]
+impl From<LogLevel> for log::Level {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => log::Level::Error,
+            LogLevel::Warn => log::Level::Warn,
+            LogLevel::Info => log::Level::Info,
+            LogLevel::Debug => log::Level::Debug,
+            LogLevel::Trace => log::Level::Trace,
+        }
+    }
+}
+
+#[cfg(feature = "log")]
+impl From<log::Level> for LogLevel {
+    fn from(level: log::Level) -> Self {
+        match level {
+            log::Level::Error => LogLevel::Error,
+            log::Level::Warn => LogLevel::Warn,
+            log::Level::Info => LogLevel::Info,
+            log::Level::Debug => LogLevel::Debug,
+            log::Level::Trace => LogLevel::Trace,
+        }
+    }
+}
+
+impl LogLevel {
+    /// Convert the raw value received over FFI; unknown values map to `Info`.
+    pub fn from_u32(v: u32) -> Self {
+        match v {
+            1 => LogLevel::Error,
+            2 => LogLevel::Warn,
+            4 => LogLevel::Debug,
+            5 => LogLevel::Trace,
+            _ => LogLevel::Info,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LogLevel::Error => "ERROR",
+            LogLevel::Warn => "WARN",
+            LogLevel::Info => "INFO",
+            LogLevel::Debug => "DEBUG",
+            LogLevel::Trace => "TRACE",
+        }
+    }
+}
+
+/// A structured log record sent by a plugin, e.g. by the `log` bridge
+/// generated with `#[plugin_logging]`. All strings are nul-terminated.
+#[repr(C)]
+pub struct LogRecord {
+    /// A `LogLevel` value.
+    pub level: u32,
+    /// Name of the plugin that produced the record.
+    pub plugin: *const c_char,
+    /// Module path or `log` target inside the plugin.
+    pub target: *const c_char,
+    pub message: *const c_char,
+}
+
+/// Host services handed to `plugin_register_all_<Trait>_v1` so plugins can
+/// call back into the host for logging and configuration.
+///
+/// The context stays valid until the library it was handed to is unloaded.
+#[repr(C)]
+pub struct HostContext {
+    /// ABI version implemented by the host (`HOST_ABI_VERSION`).
+    pub abi_version: u32,
+    /// Nul-terminated version string of the host's `plugin-interface` crate.
+    pub host_version: *const c_char,
+    /// Opaque host pointer passed back to `log` and `config_get`.
+    pub user_data: *mut c_void,
+    /// Log `message` (nul-terminated) at `level` (a `LogLevel` value).
+    pub log: extern "C" fn(*mut c_void, u32, *const c_char),
+    /// Look up the nul-terminated configuration `key`. Returns null when the
+    /// key is not set; otherwise the string is owned by the host and valid
+    /// for the lifetime of the context.
+    pub config_get: extern "C" fn(*mut c_void, *const c_char) -> *const c_char,
+    /// Log a structured record attributed to a named plugin.
+    pub log_record: extern "C" fn(*mut c_void, *const LogRecord),
+    /// Bits (`Capability::bit`) of the capabilities the host granted.
+    pub capabilities: u32,
+    /// File access; null unless `Capability::Filesystem` was granted.
+    pub filesystem: *const FilesystemService,
+    /// TCP access; null unless `Capability::Network` was granted.
+    pub network: *const NetworkService,
+    /// Thread creation; null unless `Capability::SpawnThreads` was granted.
+    pub threads: *const ThreadService,
+    /// Allocation functions for buffers handed across the boundary. Only
+    /// plugins registering at `HOST_ALLOCATOR_ABI` or later may rely on the
+    /// field being there.
+    pub allocator: *const HostAllocator,
+    /// Look up another loaded plugin's vtable by nul-terminated trait name
+    /// and registration or plugin name (null for any). Returns null when
+    /// there is none. Only present when `abi_version >= SERVICES_HOST_ABI`.
+    pub get_service: extern "C" fn(*mut c_void, *const c_char, *const c_char) -> *const c_void,
+    /// Subscribe `callback` with its data pointer to the nul-terminated
+    /// topic of the manager's event bus. Returns the subscription id, or 0
+    /// when there is no bus or the topic is invalid. The fields from here
+    /// on are only present when `abi_version >= EVENT_BUS_HOST_ABI`.
+    pub subscribe: extern "C" fn(*mut c_void, *const c_char, EventCallback, *mut c_void) -> u64,
+    /// End a subscription this library made.
+    pub unsubscribe: extern "C" fn(*mut c_void, u64),
+    /// Queue `len` bytes for the subscribers of the nul-terminated topic.
+    /// Returns 0 when queued, 1 when the queue was full, -1 for an invalid
+    /// topic and -2 when there is no bus.
+    pub publish: extern "C" fn(*mut c_void, *const c_char, *const u8, usize) -> i32,
+    /// Nul-terminated configuration the host has for this plugin, or null.
+    /// Only present when `abi_version >= PLUGIN_CONFIG_HOST_ABI`.
+    pub plugin_config: *const c_char,
+    /// Pass the value stored under the nul-terminated key in this plugin's
+    /// namespace to the sink, with the `out` pointer given. Returns 1 when
+    /// found, 0 when not and -1 when the host keeps no store or the read
+    /// failed. Only present when `abi_version >= KV_HOST_ABI`.
+    pub kv_get: extern "C" fn(*mut c_void, *const c_char, *mut c_void, StateSink) -> i32,
+    /// Store `len` bytes under the nul-terminated key, or remove the key
+    /// when the bytes are null. Returns 0 on success and -1 on failure.
+    pub kv_set: extern "C" fn(*mut c_void, *const c_char, *const u8, usize) -> i32,
+}
+
+// The context is immutable once handed out and its callbacks are thread-safe.
+unsafe impl Send for HostContext {}
+unsafe impl Sync for HostContext {}
+
+impl HostContext {
+    /// The host version as a string slice.
+    pub fn host_version(&self) -> &str {
+        if self.host_version.is_null() {
+            return "";
+        }
+        unsafe { CStr::from_ptr(self.host_version) }
+            .to_str()
+            .unwrap_or("")
+    }
+
+    /// Send `message` to the host's logger. Interior nul bytes are dropped.
+    pub fn log(&self, level: LogLevel, message: &str) {
+        let c_msg = CString::new(message.replace('\0', "")).unwrap_or_default();
+        (self.log)(self.user_data, level as u32, c_msg.as_ptr());
+    }
+
+    /// Send a record attributed to `plugin` to the host's logger. Interior
+    /// nul bytes are dropped.
+    pub fn log_record(&self, plugin: &str, target: &str, level: LogLevel, message: &str) {
+        let c = |s: &str| CString::new(s.replace('\0', "")).unwrap_or_default();
+        let (plugin, target, message) = (c(plugin), c(target), c(message));
+        let record = LogRecord {
+            level: level as u32,
+            plugin: plugin.as_ptr(),
+            target: target.as_ptr(),
+            message: message.as_ptr(),
+        };
+        (self.log_record)(self.user_data, &record);
+    }
+
+    /// The host's allocator, if this context carries one. Plugins must
+    /// only ask when they registered at `HOST_ALLOCATOR_ABI` or later.
+    pub fn allocator(&self) -> Option<&HostAllocator> {
+        unsafe { self.allocator.as_ref() }
+    }
+
+    /// The vtable of another loaded plugin's `trait_name` registration,
+    /// e.g. a `*const TransformerVTable` for `"Transformer"`. `name` picks
+    /// a registration name or manifest plugin name; `None` takes the first
+    /// provider. The provider stays loaded at least as long as this plugin.
+    /// `None` when no such plugin is loaded or the host predates services.
+    pub fn get_service(&self, trait_name: &str, name: Option<&str>) -> Option<*const c_void> {
+        if self.abi_version < crate::SERVICES_HOST_ABI {
+            return None;
+        }
+        let trait_name = CString::new(trait_name).ok()?;
+        let name = name.map(CString::new).transpose().ok()?;
+        let vtable = (self.get_service)(
+            self.user_data,
+            trait_name.as_ptr(),
+            name.as_ref().map_or(std::ptr::null(), |n| n.as_ptr()),
+        );
+        (!vtable.is_null()).then_some(vtable)
+    }
+
+    /// Have `callback` called on the host's dispatcher thread, with `data`,
+    /// for every event published to `topic`. The subscription ends when
+    /// this library is unloaded, or with `unsubscribe`. `None` when the
+    /// host has no event bus for this plugin or predates it.
+    pub fn subscribe(
+        &self,
+        topic: &str,
+        callback: EventCallback,
+        data: *mut c_void,
+    ) -> Option<u64> {
+        if self.abi_version < crate::EVENT_BUS_HOST_ABI {
+            return None;
+        }
+        let topic = CString::new(topic).ok()?;
+        let id = (self.subscribe)(self.user_data, topic.as_ptr(), callback, data);
+        (id != 0).then_some(id)
+    }
+
+    /// End the subscription `id` returned by `subscribe`.
+    pub fn unsubscribe(&self, id: u64) {
+        if self.abi_version >= crate::EVENT_BUS_HOST_ABI {
+            (self.unsubscribe)(self.user_data, id);
+        }
+    }
+
+    /// Queue `payload` for the subscribers of `topic`, host and plugins
+    /// alike. Fails with `BusError::Full` when the host's backpressure
+    /// policy gives up on the event.
+    pub fn publish(&self, topic: &str, payload: &[u8]) -> Result<(), BusError> {
+        if self.abi_version < crate::EVENT_BUS_HOST_ABI {
+            return Err(BusError::Unavailable);
+        }
+        let topic = CString::new(topic).map_err(|_| BusError::InvalidTopic)?;
+        BusError::from_code((self.publish)(
+            self.user_data,
+            topic.as_ptr(),
+            payload.as_ptr(),
+            payload.len(),
+        ))
+    }
+
+    /// The configuration the host has for this plugin (see `ConfigSource`),
+    /// which `#[plugin_impl]` hands to the trait's `configure` hook.
+    pub fn plugin_config(&self) -> Option<&str> {
+        if self.abi_version < crate::PLUGIN_CONFIG_HOST_ABI || self.plugin_config.is_null() {
+            return None;
+        }
+        unsafe { CStr::from_ptr(self.plugin_config) }.to_str().ok()
+    }
+
+    /// The value this plugin stored under `key` with `kv_set`, kept by the
+    /// host across runs. `None` when there is none, or the host keeps no
+    /// store (see `HostServices::with_kv_dir`) or predates it.
+    pub fn kv_get(&self, key: &str) -> Option<Vec<u8>> {
+        if self.abi_version < crate::KV_HOST_ABI {
+            return None;
+        }
+        extern "C" fn sink(out: *mut c_void, data: *const u8, len: usize) {
+            let out = unsafe { &mut *(out as *mut Vec<u8>) };
+            if !data.is_null() {
+                out.extend_from_slice(unsafe { std::slice::from_raw_parts(data, len) });
+            }
+        }
+        let key = CString::new(key).ok()?;
+        let mut value = Vec::new();
+        let found = (self.kv_get)(
+            self.user_data,
+            key.as_ptr(),
+            &mut value as *mut Vec<u8> as *mut c_void,
+            sink,
+        );
+        (found == 1).then_some(value)
+    }
+
+    /// Store `value` under `key` in this plugin's namespace. Returns false
+    /// if the host keeps no store or the write failed.
+    pub fn kv_set(&self, key: &str, value: &[u8]) -> bool {
+        self.kv_write(key, value.as_ptr(), value.len())
+    }
+
+    /// Remove `key` from this plugin's namespace.
+    pub fn kv_delete(&self, key: &str) -> bool {
+        self.kv_write(key, std::ptr::null(), 0)
+    }
+
+    fn kv_write(&self, key: &str, value: *const u8, len: usize) -> bool {
+        if self.abi_version < crate::KV_HOST_ABI {
+            return false;
+        }
+        let Ok(key) = CString::new(key) else {
+            return false;
+        };
+        (self.kv_set)(self.user_data, key.as_ptr(), value, len) == 0
+    }
+
+    /// Look up a configuration value provided by the host.
+    pub fn config(&self, key: &str) -> Option<String> {
+        let c_key = CString::new(key).ok()?;
+        let value = (self.config_get)(self.user_data, c_key.as_ptr());
+        if value.is_null() {
+            return None;
+        }
+        Some(
+            unsafe { CStr::from_ptr(value) }
+                .to_string_lossy()
+                .into_owned(),
+        )
+    }
+}
+
+static HOST_VERSION: &CStr =
+    match CStr::from_bytes_with_nul(concat!(env!("CARGO_PKG_VERSION"), "\0").as_bytes()) {
+        Ok(s) => s,
+        Err(_) => panic!("host version contains a nul byte"),
+    };
+
+static DEFAULT_CONTEXT: HostContext = HostContext {
+    abi_version: crate::HOST_ABI_VERSION,
+    host_version: HOST_VERSION.as_ptr(),
+    user_data: std::ptr::null_mut(),
+    log: host_log,
+    config_get: host_config_get,
+    log_record: host_log_record,
+    capabilities: 0,
+    filesystem: std::ptr::null(),
+    network: std::ptr::null(),
+    threads: std::ptr::null(),
+    allocator: &HOST_ALLOCATOR,
+    get_service: host_get_service,
+    subscribe: host_subscribe,
+    unsubscribe: host_unsubscribe,
+    publish: host_publish,
+    plugin_config: std::ptr::null(),
+    kv_get: host_kv_get,
+    kv_set: host_kv_set,
+};
+
+/// Allocation functions the host hands to plugins, so a buffer allocated
+/// on one side of the boundary is freed by the same C runtime on the
+/// other. Strings a plugin returns from a call are allocated with `alloc`
+/// and the host frees them with `free` once it has copied them.
+#[repr(C)]
+pub struct HostAllocator {
+    /// Allocate `size` bytes aligned for any type. Returns null on failure.
+    pub alloc: extern "C" fn(usize) -> *mut c_void,
+    /// Free a block `alloc` returned; null is ignored.
+    pub free: extern "C" fn(*mut c_void),
+}
+
+impl HostAllocator {
+    /// Copy `s` into a nul-terminated buffer from `alloc`, dropping
+    /// interior nul bytes. Returns null if the allocation fails.
+    pub fn alloc_c_string(&self, s: &str) -> *mut c_char {
+        let bytes: Vec<u8> = s.bytes().filter(|&b| b != 0).collect();
+        let buf = (self.alloc)(bytes.len() + 1) as *mut u8;
+        if !buf.is_null() {
+            unsafe {
+                std::ptr::copy_nonoverlapping(bytes.as_ptr(), buf, bytes.len());
+                *buf.add(bytes.len()) = 0;
+            }
+        }
+        buf as *mut c_char
+    }
+
+    /// Copy the string at `ptr` and free it.
+    ///
+    /// # Safety
+    /// `ptr` must be a nul-terminated buffer from this allocator's `alloc`
+    /// that nothing else frees.
+    pub unsafe fn take_c_string(&self, ptr: *mut c_char) -> String {
+        let s = CStr::from_ptr(ptr).to_string_lossy().into_owned();
+        (self.free)(ptr as *mut c_void);
+        s
+    }
+}
+
+extern "C" fn host_alloc(size: usize) -> *mut c_void {
+    unsafe { libc::malloc(size.max(1)) }
+}
+
+extern "C" fn host_free(ptr: *mut c_void) {
+    unsafe { libc::free(ptr) }
+}
+
+static HOST_ALLOCATOR: HostAllocator = HostAllocator {
+    alloc: host_alloc,
+    free: host_free,
+};
+
+/// Hand `s` to the host as the string result of a call: allocated with
+/// `allocator` when the host offers one, otherwise leaked, since hosts
+/// without an allocator never free results. Used by the wrappers
+/// `#[plugin_impl]` generates.
+pub fn export_string(allocator: Option<&HostAllocator>, s: &str) -> *const c_char {
+    match allocator {
+        Some(allocator) => allocator.alloc_c_string(s),
+        None => {
+            // Never freed; `leak-check` reports these.
+            crate::record_made(crate::Allocation::String);
+            CString::new(s.replace('\0', ""))
+                .unwrap_or_default()
+                .into_raw()
+        }
+    }
+}
+
+/// A process-wide context that logs to stderr and has no configuration.
+/// Used by the free-standing loader helpers that have no `PluginManager`.
+pub fn default_host_context() -> &'static HostContext {
+    &DEFAULT_CONTEXT
+}
+
+type LogFn = dyn Fn(LogLevel, &str) + Send + Sync;
+type RecordFn = dyn Fn(&PluginLogRecord<'_>) + Send + Sync;
+
+/// Borrowed view of a `LogRecord` handed to the host's record logger.
+#[derive(Debug, Clone, Copy)]
+pub struct PluginLogRecord<'a> {
+    pub plugin: &'a str,
+    pub target: &'a str,
+    pub level: LogLevel,
+    pub message: &'a str,
+}
+
+/// Host-side implementation behind a `HostContext`: the logger plugins write
+/// to and the configuration values they can look up.
+///
+/// ```
+/// use plugin_interface::{HostServices, PluginManager};
+///
+/// let services = HostServices::new()
+///     .with_config("greeting", "hi")
+///     .with_logger(|level, msg| println!("[{}] {}", level.as_str(), msg));
+/// let _mgr = PluginManager::with_host_services(services);
+/// ```
+#[derive(Clone)]
+pub struct HostServices {
+    logger: Arc<LogFn>,
+    record_logger: Option<Arc<RecordFn>>,
+    config: HashMap<String, CString>,
+    filesystem_root: Option<PathBuf>,
+    granted_to_all: u32,
+    granted: HashMap<String, u32>,
+    /// The manager's services; weak, since libraries keep their context.
+    registry: Weak<ServiceRegistry>,
+    /// Library the context was handed to, which `get_service` records as
+    /// the user of what it looks up.
+    user: Option<PathBuf>,
+    /// The manager's event bus; weak for the same reason.
+    bus: Weak<EventBus>,
+    /// Tells the event subscriptions of the library the context was handed
+    /// to apart; 0 outside a library.
+    owner: u64,
+    /// What `HostContext::plugin_config` points at.
+    plugin_config: Option<CString>,
+    /// Where `kv_get` and `kv_set` keep plugin data, if anywhere.
+    kv: Option<Arc<KvStore>>,
+    /// The plugin's namespace in `kv`; none outside a library.
+    namespace: Option<String>,
+}
+
+impl Default for HostServices {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for HostServices {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HostServices")
+            .field("config", &self.config)
+            .field("filesystem_root", &self.filesystem_root)
+            .field(
+                "granted_to_all",
+                &Capability::from_mask(self.granted_to_all),
+            )
+            .finish_non_exhaustive()
+    }
+}
+
+impl HostServices {
+    /// Services that log to stderr and expose no configuration.
+    pub fn new() -> Self {
+        Self {
+            logger: Arc::new(log_to_stderr),
+            record_logger: None,
+            config: HashMap::new(),
+            filesystem_root: None,
+            granted_to_all: 0,
+            granted: HashMap::new(),
+            registry: Weak::new(),
+            user: None,
+            bus: Weak::new(),
+            owner: 0,
+            plugin_config: None,
+            kv: None,
+            namespace: None,
+        }
+    }
+
+    /// Route plugin log messages to `logger`.
+    pub fn with_logger<F>(mut self, logger: F) -> Self
+    where
+        F: Fn(LogLevel, &str) + Send + Sync + 'static,
+    {
+        self.logger = Arc::new(logger);
+        self
+    }
+
+    /// Route structured records (those carrying a plugin name, such as the
+    /// ones from the `log` bridge) to `logger`. Without one, records are
+    /// passed to the plain logger as `"<plugin>: <message>"`.
+    pub fn with_record_logger<F>(mut self, logger: F) -> Self
+    where
+        F: Fn(&PluginLogRecord<'_>) + Send + Sync + 'static,
+    {
+        self.record_logger = Some(Arc::new(logger));
+        self
+    }
+
+    /// Forward plugin records into the host's `log` logger, using the plugin
+    /// name as the record target.
+    #[cfg(feature = "log")]
+    pub fn forward_to_log(self) -> Self {
+        self.with_record_logger(|r| {
+            log::logger().log(
+                &log::Record::builder()
+                    .level(r.level.into())
+                    .target(r.plugin)
+                    .module_path(Some(r.target))
+                    .args(format_args!("{}", r.message))
+                    .build(),
+            )
+        })
+    }
+
+    /// Make `value` visible to plugins under `key`. Values containing a nul
+    /// byte are ignored.
+    pub fn with_config(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        if let Ok(v) = CString::new(value.into()) {
+            self.config.insert(key.into(), v);
+        }
+        self
+    }
+
+    /// The configured value for `key`, if any.
+    pub fn config(&self, key: &str) -> Option<&str> {
+        self.config.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    /// Directory the filesystem service resolves plugin paths against.
+    /// Without one, plugins granted `Capability::Filesystem` cannot access
+    /// any file.
+    pub fn with_filesystem_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.filesystem_root = Some(root.into());
+        self
+    }
+
+    /// Grant `caps` to the plugin whose manifest is named `plugin`, if it
+    /// requests them.
+    pub fn grant(mut self, plugin: impl Into<String>, caps: &[Capability]) -> Self {
+        *self.granted.entry(plugin.into()).or_default() |= Capability::mask(caps);
+        self
+    }
+
+    /// Grant `caps` to every plugin that requests them.
+    pub fn grant_to_all(mut self, caps: &[Capability]) -> Self {
+        self.granted_to_all |= Capability::mask(caps);
+        self
+    }
+
+    /// Keep the data plugins store with `HostContext::kv_set` under `dir`,
+    /// in a directory per plugin. Without one, `kv_get` finds nothing and
+    /// `kv_set` fails.
+    pub fn with_kv_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.kv = Some(Arc::new(KvStore::new(dir)));
+        self
+    }
+
+    /// The store set with `with_kv_dir`, to read or seed plugin data.
+    pub fn kv_store(&self) -> Option<&KvStore> {
+        self.kv.as_deref()
+    }
+
+    /// These services, answering `get_service` from `registry`.
+    pub(crate) fn with_registry(mut self, registry: &Arc<ServiceRegistry>) -> Self {
+        self.registry = Arc::downgrade(registry);
+        self
+    }
+
+    /// These services, subscribing and publishing on `bus`.
+    pub(crate) fn with_event_bus(mut self, bus: &Arc<EventBus>) -> Self {
+        self.bus = Arc::downgrade(bus);
+        self
+    }
+
+    pub(crate) fn filesystem_root(&self) -> Option<&Path> {
+        self.filesystem_root.as_deref()
+    }
+
+    /// The subset of `requested` granted to the plugin named `plugin`.
+    fn granted_mask(&self, plugin: Option<&str>, requested: &[Capability]) -> u32 {
+        let for_plugin = plugin
+            .and_then(|p| self.granted.get(p))
+            .copied()
+            .unwrap_or(0);
+        Capability::mask(requested) & (self.granted_to_all | for_plugin)
+    }
+}
+
+fn log_to_stderr(level: LogLevel, message: &str) {
+    eprintln!("[plugin {}] {}", level.as_str(), message);
+}
+
+/// A `HostContext` together with the services it points at. Libraries keep
+/// an `Arc` to it so the context outlives every registration it was given to.
+pub(crate) struct SharedHostContext {
+    context: HostContext,
+    // Boxed so `context.user_data` stays valid when this struct moves.
+    _services: Box<HostServices>,
+}
+
+// `HostServices` is Send + Sync; the raw pointers only refer to it and to
+// static data.
+unsafe impl Send for SharedHostContext {}
+unsafe impl Sync for SharedHostContext {}
+
+impl SharedHostContext {
+    pub(crate) fn new(services: HostServices) -> Arc<Self> {
+        let services = Box::new(services);
+        let context = HostContext {
+            user_data: &*services as *const HostServices as *mut c_void,
+            ..DEFAULT_CONTEXT
+        };
+        Arc::new(Self {
+            context,
+            _services: services,
+        })
+    }
+
+    pub(crate) fn as_ptr(&self) -> *const HostContext {
+        &self.context
+    }
+
+    /// Context for a plugin named `plugin` that requests `requested`: the
+    /// same services, with the service tables for granted capabilities
+    /// filled in. Shares `self` when nothing is granted.
+    pub(crate) fn for_plugin(
+        self: &Arc<Self>,
+        plugin: Option<&str>,
+        requested: &[Capability],
+    ) -> Arc<Self> {
+        let granted = self._services.granted_mask(plugin, requested);
+        if granted == 0 {
+            return self.clone();
+        }
+        let services = Box::new((*self._services).clone());
+        let has = |cap: Capability| granted & cap.bit() != 0;
+        let context = HostContext {
+            user_data: &*services as *const HostServices as *mut c_void,
+            capabilities: granted,
+            filesystem: if has(Capability::Filesystem) {
+                &FILESYSTEM_SERVICE
+            } else {
+                std::ptr::null()
+            },
+            network: if has(Capability::Network) {
+                &NETWORK_SERVICE
+            } else {
+                std::ptr::null()
+            },
+            threads: if has(Capability::SpawnThreads) {
+                &THREAD_SERVICE
+            } else {
+                std::ptr::null()
+            },
+            ..DEFAULT_CONTEXT
+        };
+        Arc::new(Self {
+            context,
+            _services: services,
+        })
+    }
+
+    /// The same context for the library at `path`, carrying `config` for
+    /// it, so the services it looks up are recorded as used by it, its
+    /// event subscriptions are told apart from other libraries' and its
+    /// stored values are kept under `namespace`.
+    pub(crate) fn for_library(
+        self: &Arc<Self>,
+        path: &Path,
+        namespace: String,
+        config: Option<&str>,
+    ) -> Arc<Self> {
+        static NEXT_OWNER: AtomicU64 = AtomicU64::new(1);
+        let mut services = Box::new((*self._services).clone());
+        services.user = Some(path.to_path_buf());
+        services.namespace = Some(namespace);
+        services.owner = NEXT_OWNER.fetch_add(1, Ordering::Relaxed);
+        services.plugin_config = config.and_then(|c| CString::new(c).ok());
+        let context = HostContext {
+            user_data: &*services as *const HostServices as *mut c_void,
+            plugin_config: services
+                .plugin_config
+                .as_ref()
+                .map_or(std::ptr::null(), |c| c.as_ptr()),
+            ..self.context
+        };
+        Arc::new(Self {
+            context,
+            _services: services,
+        })
+    }
+
+    /// Capabilities granted in this context.
+    pub(crate) fn capabilities(&self) -> Vec<Capability> {
+        Capability::from_mask(self.context.capabilities)
+    }
+
+    pub(crate) fn context(&self) -> &HostContext {
+        &self.context
+    }
+
+    /// Identifies the event subscriptions made through this context.
+    pub(crate) fn owner(&self) -> u64 {
+        self._services.owner
+    }
+}
+
+impl Drop for SharedHostContext {
+    /// The library the context was handed to is gone, or failed to load;
+    /// its callbacks must not be called any more.
+    fn drop(&mut self) {
+        if self._services.owner != 0 {
+            if let Some(bus) = self._services.bus.upgrade() {
+                bus.remove_owner(self._services.owner);
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for SharedHostContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedHostContext")
+            .field("services", &self._services)
+            .finish()
+    }
+}
+
+extern "C" fn host_log(user_data: *mut c_void, level: u32, message: *const c_char) {
+    if message.is_null() {
+        return;
+    }
+    let msg = unsafe { CStr::from_ptr(message) }.to_string_lossy();
+    let level = LogLevel::from_u32(level);
+    if user_data.is_null() {
+        log_to_stderr(level, &msg);
+        return;
+    }
+    let services = unsafe { &*(user_data as *const HostServices) };
+    let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        (services.logger)(level, &msg)
+    }));
+}
+
+extern "C" fn host_log_record(user_data: *mut c_void, record: *const LogRecord) {
+    if record.is_null() {
+        return;
+    }
+    let record = unsafe { &*record };
+    let text = |p: *const c_char| {
+        if p.is_null() {
+            std::borrow::Cow::Borrowed("")
+        } else {
+            unsafe { CStr::from_ptr(p) }.to_string_lossy()
+        }
+    };
+    let (plugin, target, message) = (
+        text(record.plugin),
+        text(record.target),
+        text(record.message),
+    );
+    let view = PluginLogRecord {
+        plugin: &plugin,
+        target: &target,
+        level: LogLevel::from_u32(record.level),
+        message: &message,
+    };
+    if user_data.is_null() {
+        log_to_stderr(view.level, &format!("{}: {}", view.plugin, view.message));
+        return;
+    }
+    let services = unsafe { &*(user_data as *const HostServices) };
+    let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        match &services.record_logger {
+            Some(sink) => sink(&view),
+            None => (services.logger)(view.level, &format!("{}: {}", view.plugin, view.message)),
+        }
+    }));
+}
+
+extern "C" fn host_config_get(user_data: *mut c_void, key: *const c_char) -> *const c_char {
+    if user_data.is_null() || key.is_null() {
+        return std::ptr::null();
+    }
+    let services = unsafe { &*(user_data as *const HostServices) };
+    let key = unsafe { CStr::from_ptr(key) }.to_string_lossy();
+    services
+        .config
+        .get(key.as_ref())
+        .map(|v| v.as_ptr())
+        .unwrap_or(std::ptr::null())
+}
+
+extern "C" fn host_get_service(
+    user_data: *mut c_void,
+    trait_name: *const c_char,
+    name: *const c_char,
+) -> *const c_void {
+    if user_data.is_null() || trait_name.is_null() {
+        return std::ptr::null();
+    }
+    let services = unsafe { &*(user_data as *const HostServices) };
+    let Some(registry) = services.registry.upgrade() else {
+        return std::ptr::null();
+    };
+    let trait_name = unsafe { CStr::from_ptr(trait_name) }.to_string_lossy();
+    let name = (!name.is_null()).then(|| unsafe { CStr::from_ptr(name) }.to_string_lossy());
+    registry.lookup(services.user.as_deref(), &trait_name, name.as_deref())
+}
+
+/// The services behind `user_data` and their bus, when the context was
+/// handed to a library by a manager that is still around.
+fn bus_of<'a>(user_data: *mut c_void) -> Option<(&'a HostServices, Arc<EventBus>)> {
+    if user_data.is_null() {
+        return None;
+    }
+    let services = unsafe { &*(user_data as *const HostServices) };
+    let bus = services.bus.upgrade()?;
+    Some((services, bus))
+}
+
+extern "C" fn host_subscribe(
+    user_data: *mut c_void,
+    topic: *const c_char,
+    callback: EventCallback,
+    data: *mut c_void,
+) -> u64 {
+    let Some((services, bus)) = bus_of(user_data).filter(|(s, _)| s.owner != 0) else {
+        return 0;
+    };
+    if topic.is_null() {
+        return 0;
+    }
+    let topic = unsafe { CStr::from_ptr(topic) }.to_string_lossy();
+    bus.subscribe_plugin(services.owner, &topic, callback, data)
+        .unwrap_or(0)
+}
+
+extern "C" fn host_unsubscribe(user_data: *mut c_void, id: u64) {
+    if let Some((services, bus)) = bus_of(user_data) {
+        bus.unsubscribe_plugin(services.owner, id);
+    }
+}
+
+extern "C" fn host_publish(
+    user_data: *mut c_void,
+    topic: *const c_char,
+    payload: *const u8,
+    len: usize,
+) -> i32 {
+    let Some((_, bus)) = bus_of(user_data) else {
+        return BusError::Unavailable.code();
+    };
+    if topic.is_null() || (payload.is_null() && len != 0) {
+        return BusError::InvalidTopic.code();
+    }
+    let topic = unsafe { CStr::from_ptr(topic) }.to_string_lossy();
+    let payload = if len == 0 {
+        &[][..]
+    } else {
+        unsafe { std::slice::from_raw_parts(payload, len) }
+    };
+    match bus.publish(&topic, payload) {
+        Ok(()) => 0,
+        Err(e) => e.code(),
+    }
+}
+
+/// The store and namespace behind `user_data`, for a library's context of
+/// services that keep a store.
+fn kv_of<'a>(user_data: *mut c_void) -> Option<(&'a KvStore, &'a str)> {
+    if user_data.is_null() {
+        return None;
+    }
+    let services = unsafe { &*(user_data as *const HostServices) };
+    Some((services.kv.as_deref()?, services.namespace.as_deref()?))
+}
+
+extern "C" fn host_kv_get(
+    user_data: *mut c_void,
+    key: *const c_char,
+    out: *mut c_void,
+    sink: StateSink,
+) -> i32 {
+    let Some((store, namespace)) = kv_of(user_data) else {
+        return -1;
+    };
+    if key.is_null() {
+        return -1;
+    }
+    let key = unsafe { CStr::from_ptr(key) }.to_string_lossy();
+    match store.get(namespace, &key) {
+        Ok(Some(value)) => {
+            sink(out, value.as_ptr(), value.len());
+            1
+        }
+        Ok(None) => 0,
+        Err(_) => -1,
+    }
+}
+
+extern "C" fn host_kv_set(
+    user_data: *mut c_void,
+    key: *const c_char,
+    value: *const u8,
+    len: usize,
+) -> i32 {
+    let Some((store, namespace)) = kv_of(user_data) else {
+        return -1;
+    };
+    if key.is_null() {
+        return -1;
+    }
+    let key = unsafe { CStr::from_ptr(key) }.to_string_lossy();
+    let result = if value.is_null() {
+        store.remove(namespace, &key).map(|_| ())
+    } else {
+        let value = unsafe { std::slice::from_raw_parts(value, len) };
+        store.set(namespace, &key, value)
+    };
+    if result.is_ok() {
+        0
+    } else {
+        -1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn context_routes_logs_and_config_to_services() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        let services = HostServices::new()
+            .with_config("color", "blue")
+            .with_logger(move |level, msg| sink.lock().unwrap().push((level, msg.to_string())));
+        let shared = SharedHostContext::new(services);
+        let ctx = unsafe { &*shared.as_ptr() };
+
+        ctx.log(LogLevel::Warn, "careful");
+        assert_eq!(ctx.config("color").as_deref(), Some("blue"));
+        assert_eq!(ctx.config("missing"), None);
+        assert_eq!(ctx.host_version(), env!("CARGO_PKG_VERSION"));
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![(LogLevel::Warn, "careful".to_string())]
+        );
+    }
+
+    #[test]
+    fn records_carry_the_plugin_name() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        let services = HostServices::new().with_record_logger(move |r| {
+            sink.lock()
+                .unwrap()
+                .push(format!("{} {} {}", r.plugin, r.target, r.message))
+        });
+        let shared = SharedHostContext::new(services);
+        let ctx = unsafe { &*shared.as_ptr() };
+
+        ctx.log_record("plugin-a", "plugin_a::greet", LogLevel::Info, "hello");
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec!["plugin-a plugin_a::greet hello"]
+        );
+    }
+
+    #[test]
+    fn default_context_has_no_config() {
+        assert_eq!(default_host_context().config("anything"), None);
+    }
+
+    #[test]
+    fn only_granted_capabilities_get_services() {
+        let dir = tempfile::tempdir().unwrap();
+        let services = HostServices::new()
+            .with_filesystem_root(dir.path())
+            .grant("files", &[Capability::Filesystem])
+            .grant_to_all(&[Capability::SpawnThreads]);
+        let shared = SharedHostContext::new(services);
+        let requested = [Capability::Filesystem, Capability::Network];
+
+        let files = shared.for_plugin(Some("files"), &requested);
+        let ctx = unsafe { &*files.as_ptr() };
+        assert_eq!(files.capabilities(), [Capability::Filesystem]);
+        assert!(ctx.network.is_null() && ctx.threads.is_null());
+        assert!(ctx.write_file("state.txt", b"saved"));
+        assert_eq!(ctx.read_file("state.txt").as_deref(), Some(&b"saved"[..]));
+        assert_eq!(ctx.read_file("../state.txt"), None);
+        assert_eq!(ctx.tcp_exchange("127.0.0.1:1", b""), None);
+
+        // Nothing requested by "other" is granted, so it shares the base context.
+        let other = shared.for_plugin(Some("other"), &requested);
+        assert!(Arc::ptr_eq(&other, &shared));
+        let ctx = unsafe { &*other.as_ptr() };
+        assert!(!ctx.has_capability(Capability::Filesystem));
+        assert!(!ctx.write_file("state.txt", b"nope"));
+    }
+
+    #[test]
+    fn libraries_store_values_in_their_own_namespace() {
+        let dir = tempfile::tempdir().unwrap();
+        let shared = SharedHostContext::new(HostServices::new().with_kv_dir(dir.path()));
+        let a = shared.for_library(Path::new("a.so"), "a".to_string(), None);
+        let b = shared.for_library(Path::new("b.so"), "b".to_string(), None);
+        let (a, b) = (a.context(), b.context());
+
+        assert!(a.kv_set("count", b"1"));
+        assert!(b.kv_set("count", b"2"));
+        assert!(a.kv_set("empty", b""));
+        assert_eq!(a.kv_get("count").as_deref(), Some(&b"1"[..]));
+        assert_eq!(b.kv_get("count").as_deref(), Some(&b"2"[..]));
+        assert_eq!(a.kv_get("empty").as_deref(), Some(&b""[..]));
+        assert!(a.kv_delete("count"));
+        assert_eq!(a.kv_get("count"), None);
+
+        // Outside a library, or without a store, there is nothing to use.
+        assert!(!shared.context().kv_set("count", b"3"));
+        assert_eq!(default_host_context().kv_get("count"), None);
+    }
+
+    #[test]
+    fn strings_round_trip_through_the_host_allocator() {
+        let shared = SharedHostContext::new(HostServices::new());
+        let allocator = shared.context().allocator().unwrap();
+        let ptr = allocator.alloc_c_string("hel\0lo");
+        assert_eq!(unsafe { allocator.take_c_string(ptr) }, "hello");
+
+        let ptr = export_string(Some(allocator), "greeting") as *mut c_char;
+        assert_eq!(unsafe { allocator.take_c_string(ptr) }, "greeting");
+    }
+}
@@ -0,0 +1,95 @@
+//! What makes two paths the same plugin file, so a library reached through
+//! a symlink, a relative path or a hard link is only loaded once.
+
+use std::path::{Path, PathBuf};
+
+/// Identifies the file at a path however it was reached: the device and
+/// inode on Unix, the volume serial number and file index on Windows, and
+/// the canonical path elsewhere or when the file cannot be opened.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum FileKey {
+    #[cfg(unix)]
+    Inode {
+        dev: u64,
+        ino: u64,
+    },
+    #[cfg(windows)]
+    FileId {
+        volume: u32,
+        index: u64,
+    },
+    Path(PathBuf),
+}
+
+impl FileKey {
+    pub(crate) fn of(path: &Path) -> Self {
+        file_id(path).unwrap_or_else(|| FileKey::Path(canonical_path(path)))
+    }
+}
+
+/// `path` with symlinks and `.`/`..` resolved, or `path` itself if it
+/// cannot be resolved, for example because the file is gone.
+pub(crate) fn canonical_path(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(unix)]
+fn file_id(path: &Path) -> Option<FileKey> {
+    use std::os::unix::fs::MetadataExt;
+    let meta = std::fs::metadata(path).ok()?;
+    Some(FileKey::Inode {
+        dev: meta.dev(),
+        ino: meta.ino(),
+    })
+}
+
+#[cfg(windows)]
+fn file_id(path: &Path) -> Option<FileKey> {
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::Storage::FileSystem::{
+        GetFileInformationByHandle, BY_HANDLE_FILE_INFORMATION,
+    };
+    let file = std::fs::File::open(path).ok()?;
+    let mut info = BY_HANDLE_FILE_INFORMATION::default();
+    // SAFETY: the handle is open for the duration of the call and `info`
+    // is a valid out pointer.
+    if unsafe { GetFileInformationByHandle(file.as_raw_handle(), &mut info) } == 0 {
+        return None;
+    }
+    Some(FileKey::FileId {
+        volume: info.dwVolumeSerialNumber,
+        index: (u64::from(info.nFileIndexHigh) << 32) | u64::from(info.nFileIndexLow),
+    })
+}
+
+#[cfg(not(any(unix, windows)))]
+fn file_id(_path: &Path) -> Option<FileKey> {
+    None
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn symlinks_hard_links_and_relative_paths_share_a_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("lib.so");
+        std::fs::write(&file, b"x").unwrap();
+        let link = dir.path().join("link.so");
+        std::os::unix::fs::symlink(&file, &link).unwrap();
+        let hard = dir.path().join("hard.so");
+        std::fs::hard_link(&file, &hard).unwrap();
+        let dotted = dir.path().join(".").join("lib.so");
+
+        let key = FileKey::of(&file);
+        assert_eq!(FileKey::of(&link), key);
+        assert_eq!(FileKey::of(&hard), key);
+        assert_eq!(FileKey::of(&dotted), key);
+        assert_eq!(canonical_path(&link), canonical_path(&file));
+
+        let other = dir.path().join("other.so");
+        std::fs::write(&other, b"x").unwrap();
+        assert_ne!(FileKey::of(&other), key);
+    }
+}
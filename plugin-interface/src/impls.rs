@@ -0,0 +1,154 @@
+//! The implementations a plugin crate provides for a trait, listed by
+//! `plugin_list_impls_<Trait>_v<N>` so hosts can make registrations of
+//! some of them without registering the rest.
+
+use crate::{
+    find_versioned_symbol, HostContext, HostInfo, HostVersion, PluginTrait, RegistrationArray,
+    RegistrationFactory, HOST_VERSION_ABI,
+};
+use libloading::Library;
+use std::sync::{Arc, OnceLock};
+
+/// Which registration names `PluginManager::load_plugins_filtered` makes.
+pub(crate) type ImplFilter = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// One implementation `plugin_list_impls_<Trait>_v<N>` lists.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ImplFactory {
+    /// The registration name, if the plugin's factories carry one.
+    pub(crate) name: Option<&'static str>,
+    pub(crate) factory: *const RegistrationFactory,
+}
+
+/// The factories behind `plugin_list_impls_<Trait>_v<N>`, collected on
+/// first use and kept for as long as the library is loaded.
+#[doc(hidden)]
+#[derive(Default)]
+pub struct ImplTable(OnceLock<Box<[*const RegistrationFactory]>>);
+
+// The pointers refer to statics of the crate the table is declared in.
+unsafe impl Send for ImplTable {}
+unsafe impl Sync for ImplTable {}
+
+impl ImplTable {
+    pub const fn new() -> Self {
+        Self(OnceLock::new())
+    }
+
+    /// The table's factories, collected from `factories` the first time.
+    pub fn get_or_init<'a>(
+        &self,
+        factories: impl FnOnce() -> Vec<&'a RegistrationFactory>,
+    ) -> &[*const RegistrationFactory] {
+        self.0.get_or_init(|| {
+            factories()
+                .into_iter()
+                .map(|f| f as *const RegistrationFactory)
+                .collect()
+        })
+    }
+}
+
+type ListImplsFn =
+    unsafe extern "C" fn(*const HostContext, *mut usize) -> *const *const RegistrationFactory;
+
+/// `ListImplsFn` from `HOST_VERSION_ABI` on.
+type ListImplsWithVersionFn = unsafe extern "C" fn(
+    *const HostContext,
+    *const HostVersion,
+    *mut usize,
+) -> *const *const RegistrationFactory;
+
+/// The implementations `lib` lists for `trait_id` and the ABI level it
+/// lists them at, or `None` if it exports no
+/// `plugin_list_impls_<Trait>_v<N>`. A non-null `host` is handed to the
+/// library as `register_all` would, with this host's version from
+/// `HOST_VERSION_ABI` on.
+///
+/// # Safety
+/// `lib` must be a plugin library whose `plugin_list_impls` symbols, if
+/// any, have the signature `#[plugin_aggregates]` gives them. The names
+/// and factories are only valid while it is loaded.
+pub(crate) unsafe fn library_impls(
+    lib: &Library,
+    trait_id: PluginTrait,
+    host: *const HostContext,
+) -> Option<(Vec<ImplFactory>, u32)> {
+    let (list, abi) =
+        find_versioned_symbol::<ListImplsFn>(lib, crate::symbols::LIST_IMPLS, trait_id.as_str())?;
+    let mut count = 0usize;
+    let factories = if abi < HOST_VERSION_ABI {
+        list(host, &mut count)
+    } else {
+        let list = std::mem::transmute::<ListImplsFn, ListImplsWithVersionFn>(*list);
+        list(host, &HostInfo::current_version(), &mut count)
+    };
+    if factories.is_null() || count == 0 {
+        return Some((Vec::new(), abi));
+    }
+    let impls = std::slice::from_raw_parts(factories, count)
+        .iter()
+        .filter(|f| !f.is_null())
+        .map(|&factory| ImplFactory {
+            name: (*factory).registration_name(),
+            factory,
+        })
+        .collect();
+    Some((impls, abi))
+}
+
+/// Make registrations of the implementations in `impls` whose names
+/// `accept` takes (unnamed ones as ""), in a host-owned array that records
+/// the factory of each. Null if none was accepted or made; release it
+/// with `release_accepted`.
+///
+/// # Safety
+/// The factories must be valid, as `library_impls` returns them.
+pub(crate) unsafe fn make_accepted(
+    impls: &[ImplFactory],
+    accept: &dyn Fn(&str) -> bool,
+) -> *mut RegistrationArray {
+    let mut regs: Vec<*const std::ffi::c_void> = Vec::new();
+    let mut factories: Vec<*const RegistrationFactory> = Vec::new();
+    for i in impls.iter().filter(|i| accept(i.name.unwrap_or_default())) {
+        let r = ((*i.factory).maker)();
+        if !r.is_null() {
+            regs.push(r);
+            factories.push(i.factory);
+        }
+    }
+    if regs.is_empty() {
+        return std::ptr::null_mut();
+    }
+    let count = regs.len();
+    let regs_ptr = Box::into_raw(regs.into_boxed_slice()) as *const *const std::ffi::c_void;
+    let factories_ptr =
+        Box::into_raw(factories.into_boxed_slice()) as *const *const RegistrationFactory;
+    Box::into_raw(Box::new(RegistrationArray::new(
+        count,
+        regs_ptr,
+        factories_ptr,
+    )))
+}
+
+/// Release every registration of an array `make_accepted` built with the
+/// factory that made it, then free the array.
+///
+/// # Safety
+/// `arr_ptr` must come from `make_accepted` and not be released yet; the
+/// library its factories belong to must still be loaded.
+pub(crate) unsafe fn release_accepted(arr_ptr: *mut RegistrationArray) {
+    let arr = Box::from_raw(arr_ptr);
+    let regs: Box<[*const std::ffi::c_void]> = Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+        arr.registrations as *mut *const std::ffi::c_void,
+        arr.count,
+    ));
+    let factories: Box<[*const RegistrationFactory]> =
+        Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+            arr.factories as *mut *const RegistrationFactory,
+            arr.count,
+        ));
+    for (&r, &factory) in regs.iter().zip(factories.iter()) {
+        ((*factory).unmaker)(r);
+    }
+}
@@ -0,0 +1,142 @@
+//! Instances of a registration beyond the shared one, for plugins that keep
+//! per-request mutable state: `PluginHandle::instantiate` and the pools
+//! released instances wait in until they are used again or the library is
+//! unloaded.
+
+use crate::call::PluginCallError;
+use crate::holders::LibRef;
+use crate::{GreeterProxy, PluginId, PluginTrait, TransformerProxy, UNLIMITED_INSTANCES};
+use std::ffi::c_void;
+use std::sync::{Arc, Mutex};
+
+/// One pool per registration of a library.
+pub(crate) struct InstancePools {
+    pools: Vec<Mutex<Pool>>,
+}
+
+#[derive(Default)]
+struct Pool {
+    /// Released instances, ready to be handed out again.
+    idle: Vec<*const c_void>,
+    /// Instances made and not yet unmade, idle ones included.
+    live: u32,
+}
+
+fn lock(pool: &Mutex<Pool>) -> std::sync::MutexGuard<'_, Pool> {
+    pool.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl InstancePools {
+    pub(crate) fn for_count(count: usize) -> Self {
+        Self {
+            pools: (0..count).map(|_| Mutex::default()).collect(),
+        }
+    }
+
+    /// An idle instance of the registration at `index`, or `None` when a
+    /// new one may be made, which then counts against `max` until it is
+    /// unmade or `abandon`ed.
+    pub(crate) fn acquire(
+        &self,
+        index: usize,
+        max: u32,
+    ) -> Result<Option<*const c_void>, PluginCallError> {
+        let mut pool = lock(&self.pools[index]);
+        if let Some(instance) = pool.idle.pop() {
+            return Ok(Some(instance));
+        }
+        if max != UNLIMITED_INSTANCES && pool.live >= max {
+            return Err(PluginCallError::PoolExhausted);
+        }
+        pool.live += 1;
+        Ok(None)
+    }
+
+    /// The instance `acquire` allowed could not be made.
+    pub(crate) fn abandon(&self, index: usize) {
+        let mut pool = lock(&self.pools[index]);
+        pool.live = pool.live.saturating_sub(1);
+    }
+
+    /// Put `instance` of the registration at `index` back for reuse.
+    pub(crate) fn release(&self, index: usize, instance: *const c_void) {
+        lock(&self.pools[index]).idle.push(instance);
+    }
+
+    /// Take every idle instance out, with its registration index, for the
+    /// library to unmake before it unregisters.
+    pub(crate) fn drain(&self) -> Vec<(usize, *const c_void)> {
+        let mut drained = Vec::new();
+        for (index, pool) in self.pools.iter().enumerate() {
+            let mut pool = lock(pool);
+            pool.live = pool.live.saturating_sub(pool.idle.len() as u32);
+            drained.extend(pool.idle.drain(..).map(|r| (index, r)));
+        }
+        drained
+    }
+}
+
+/// An instance of a registration of its own, made by
+/// `PluginHandle::instantiate`, with state no other caller shares. Proxies
+/// from `as_greeter` and `as_transformer` call into this instance only.
+///
+/// Once the instance and its proxies are dropped it goes back to the
+/// registration's pool and a later `instantiate` may hand it out again, state
+/// and all. Pooled instances are released with the library. Like a handle,
+/// an instance keeps its library loaded.
+#[derive(Debug)]
+pub struct PluginInstance {
+    inner: Arc<Instance>,
+}
+
+/// What a `PluginInstance` and its proxies share.
+pub(crate) struct Instance {
+    pub(crate) lib: LibRef,
+    pub(crate) index: usize,
+    /// The registration `maker` returned for this instance.
+    pub(crate) registration: *const c_void,
+}
+
+impl std::fmt::Debug for Instance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Instance")
+            .field("path", &self.lib.path)
+            .field("index", &self.index)
+            .finish()
+    }
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        self.lib.instances.release(self.index, self.registration);
+    }
+}
+
+impl PluginInstance {
+    pub(crate) fn new(lib: LibRef, index: usize, registration: *const c_void) -> Self {
+        // Shared by proxies like the library, on the caller's thread.
+        #[allow(clippy::arc_with_non_send_sync)]
+        Self {
+            inner: Arc::new(Instance {
+                lib,
+                index,
+                registration,
+            }),
+        }
+    }
+
+    /// Id of the registration this is an instance of.
+    pub fn id(&self) -> PluginId {
+        PluginId::for_registration(self.inner.lib.arr_ptr, self.inner.index)
+    }
+
+    pub fn as_greeter(&self) -> Option<GreeterProxy> {
+        (self.inner.lib.trait_id == PluginTrait::Greeter)
+            .then(|| GreeterProxy::for_instance(self.inner.clone()))
+    }
+
+    pub fn as_transformer(&self) -> Option<TransformerProxy> {
+        (self.inner.lib.trait_id == PluginTrait::Transformer)
+            .then(|| TransformerProxy::for_instance(self.inner.clone()))
+    }
+}
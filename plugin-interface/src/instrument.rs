@@ -0,0 +1,166 @@
+//! Instrumentation shared by the proxies and the manager: call statistics
+//! are always collected, `tracing` spans and events only with the `tracing`
+//! feature.
+
+use crate::call::PluginCallError;
+use crate::intercept::CallValue;
+use crate::stats::CallState;
+use crate::{MethodDescription, PluginTrait};
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Emit a `tracing` event when the `tracing` feature is enabled; expands to
+/// nothing otherwise.
+macro_rules! trace_event {
+    ($level:ident, $($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::$level!($($arg)*);
+    };
+}
+pub(crate) use trace_event;
+
+/// The trait methods a deprecation warning was logged for, by trait and
+/// method name.
+static WARNED_DEPRECATED: Mutex<Option<HashSet<(&'static str, &'static str)>>> = Mutex::new(None);
+
+/// Log a warning, through `tracing` and `log` when those features are
+/// enabled, the first time in this process that `method` of `trait_id` is
+/// called if the trait marks it `#[deprecated]`.
+pub(crate) fn warn_if_deprecated(trait_id: PluginTrait, method: &str) {
+    warn_once(trait_id.as_str(), trait_id.methods(), method);
+}
+
+/// `warn_if_deprecated` for the trait named `trait_name` with `methods`;
+/// true if this call logged the warning.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+fn warn_once(
+    trait_name: &'static str,
+    methods: &'static [MethodDescription],
+    method: &str,
+) -> bool {
+    let Some(m) = methods.iter().find(|m| m.name == method) else {
+        return false;
+    };
+    let Some(note) = m.deprecated else {
+        return false;
+    };
+    let mut warned = WARNED_DEPRECATED.lock().unwrap_or_else(|e| e.into_inner());
+    if !warned
+        .get_or_insert_with(HashSet::new)
+        .insert((trait_name, m.name))
+    {
+        return false;
+    }
+    drop(warned);
+    trace_event!(
+        warn,
+        trait_name,
+        method = m.name,
+        note,
+        "deprecated plugin method called"
+    );
+    #[cfg(feature = "log")]
+    log::warn!(
+        "{}::{} is deprecated{}{}",
+        trait_name,
+        m.name,
+        if note.is_empty() { "" } else { ": " },
+        note
+    );
+    true
+}
+
+/// Run one proxy call into the plugin, through the interceptors bound to
+/// `calls`, and record it in the registration's call counters; an `Err`
+/// from `f` counts as a failed call. Calls an interceptor skips are not
+/// recorded. `args` are the call's arguments as interceptors see them.
+///
+/// With `tracing` enabled the call runs inside a `plugin_call` span carrying
+/// the library path, registration (as produced by `registration`, which is
+/// only evaluated when needed), method and the call duration in microseconds.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn proxy_call<R: CallValue>(
+    path: &Path,
+    calls: &CallState,
+    index: usize,
+    registration: impl FnOnce() -> String,
+    method: &'static str,
+    args: &[&str],
+    f: impl FnOnce() -> Result<R, PluginCallError>,
+) -> Result<R, PluginCallError> {
+    match calls.interceptors() {
+        Some(binding) => binding.run(path, index, method, args, || {
+            record_call(path, calls, index, registration, method, f)
+        }),
+        None => record_call(path, calls, index, registration, method, f),
+    }
+}
+
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+fn record_call<R>(
+    path: &Path,
+    calls: &CallState,
+    index: usize,
+    registration: impl FnOnce() -> String,
+    method: &'static str,
+    f: impl FnOnce() -> Result<R, PluginCallError>,
+) -> Result<R, PluginCallError> {
+    #[cfg(feature = "tracing")]
+    let span = tracing::debug_span!(
+        "plugin_call",
+        plugin = %path.display(),
+        registration = registration(),
+        method = method,
+        duration_us = tracing::field::Empty,
+    );
+    #[cfg(feature = "tracing")]
+    let _enter = span.enter();
+
+    let start = Instant::now();
+    let out = f();
+    let elapsed = start.elapsed();
+    if let Some(counters) = calls.counters.get(index) {
+        counters.record(elapsed, out.is_err());
+    }
+    if out.is_err() {
+        calls.spend_error(index, path);
+    }
+
+    #[cfg(feature = "tracing")]
+    span.record("duration_us", elapsed.as_micros() as u64);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static METHODS: [MethodDescription; 2] = [
+        MethodDescription {
+            name: "old",
+            args: &[],
+            returns: "",
+            optional: false,
+            deprecated: Some("use `new`"),
+        },
+        MethodDescription {
+            name: "new",
+            args: &[],
+            returns: "",
+            optional: false,
+            deprecated: None,
+        },
+    ];
+
+    #[test]
+    fn deprecated_methods_warn_once() {
+        assert!(warn_once("Legacy", &METHODS, "old"));
+        assert!(!warn_once("Legacy", &METHODS, "old"));
+        assert!(!warn_once("Legacy", &METHODS, "new"));
+        assert!(!warn_once("Legacy", &METHODS, "missing"));
+        // Other traits keep their own first warning.
+        assert!(warn_once("Other", &METHODS, "old"));
+    }
+}
@@ -0,0 +1,185 @@
+//! Interceptors the host adds with `PluginManager::add_interceptor`, which
+//! run around every proxy call for cross-cutting concerns such as access
+//! checks, logging, caching or argument validation.
+
+use crate::call::PluginCallError;
+use crate::handle::PluginId;
+use crate::RegistrationArray;
+use std::ops::ControlFlow;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// The proxy call an interceptor runs around.
+#[derive(Debug, Clone, Copy)]
+pub struct CallContext<'a> {
+    /// The registration being called.
+    pub id: PluginId,
+    /// Its index in its library, as in `PluginStats::index`.
+    pub index: usize,
+    /// Library or module the registration came from.
+    pub path: &'a Path,
+    /// The proxy method, e.g. `greet`, `transform` or `call_dynamic`.
+    pub method: &'static str,
+    /// The call's arguments: the string `greet` and `transform` take, or
+    /// the method name and JSON arguments of `call_dynamic`. Empty for
+    /// methods without any.
+    pub args: &'a [&'a str],
+}
+
+/// A call's result as interceptors see it: the string the method returned,
+/// `None` for methods that return nothing, or why it failed. For
+/// `call_dynamic` it is the plugin's JSON reply.
+pub type CallOutcome = Result<Option<String>, PluginCallError>;
+
+/// Code run around every proxy call into the plugins of a manager. Both
+/// methods default to doing nothing.
+pub trait CallInterceptor: Send + Sync {
+    /// Called before the plugin is. Returning `ControlFlow::Break` skips the
+    /// plugin and the interceptors added after this one, and the call
+    /// returns the outcome given, e.g. a cached result or
+    /// `Err(PluginCallError::Unsupported)` to refuse it. Skipped calls are
+    /// not counted in the call statistics.
+    fn before(&self, ctx: &CallContext<'_>) -> ControlFlow<CallOutcome> {
+        let _ = ctx;
+        ControlFlow::Continue(())
+    }
+
+    /// Called with the call's outcome once it returns, in the reverse order
+    /// of `before`, by every interceptor whose `before` ran.
+    fn after(&self, ctx: &CallContext<'_>, outcome: &CallOutcome) {
+        let _ = (ctx, outcome);
+    }
+}
+
+/// Identifies an interceptor for `PluginManager::remove_interceptor`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InterceptorId(u64);
+
+type Chain = Arc<Vec<(u64, Arc<dyn CallInterceptor>)>>;
+
+/// A manager's interceptors, in the order they were added. The libraries
+/// it loads keep a reference, so changes apply to calls made from then on.
+#[derive(Default)]
+pub(crate) struct Interceptors {
+    next: AtomicU64,
+    chain: RwLock<Chain>,
+}
+
+impl Interceptors {
+    pub(crate) fn add(&self, interceptor: Arc<dyn CallInterceptor>) -> InterceptorId {
+        let id = self.next.fetch_add(1, Ordering::Relaxed);
+        let mut chain = self.chain.write().unwrap_or_else(|e| e.into_inner());
+        let mut next = chain.as_ref().clone();
+        next.push((id, interceptor));
+        *chain = Arc::new(next);
+        InterceptorId(id)
+    }
+
+    pub(crate) fn remove(&self, id: InterceptorId) -> bool {
+        let mut chain = self.chain.write().unwrap_or_else(|e| e.into_inner());
+        if !chain.iter().any(|(i, _)| *i == id.0) {
+            return false;
+        }
+        *chain = Arc::new(chain.iter().filter(|(i, _)| *i != id.0).cloned().collect());
+        true
+    }
+
+    fn chain(&self) -> Chain {
+        self.chain.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+/// The interceptors a library's calls go through, with the key its
+/// registration ids are derived from.
+pub(crate) struct Binding {
+    interceptors: Arc<Interceptors>,
+    key: usize,
+}
+
+impl std::fmt::Debug for Binding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Binding")
+            .field("interceptors", &self.interceptors.chain().len())
+            .finish()
+    }
+}
+
+impl Binding {
+    pub(crate) fn new(interceptors: Arc<Interceptors>, key: *const RegistrationArray) -> Self {
+        Self {
+            interceptors,
+            key: key as usize,
+        }
+    }
+
+    /// Run `f`, the call to the registration at `index`, through the
+    /// interceptors.
+    pub(crate) fn run<R: CallValue>(
+        &self,
+        path: &Path,
+        index: usize,
+        method: &'static str,
+        args: &[&str],
+        f: impl FnOnce() -> Result<R, PluginCallError>,
+    ) -> Result<R, PluginCallError> {
+        let chain = self.interceptors.chain();
+        if chain.is_empty() {
+            return f();
+        }
+        let ctx = CallContext {
+            id: PluginId::for_registration(self.key as *const RegistrationArray, index),
+            index,
+            path,
+            method,
+            args,
+        };
+        let mut entered = 0;
+        let mut skipped = None;
+        for (_, interceptor) in chain.iter() {
+            entered += 1;
+            if let ControlFlow::Break(outcome) = interceptor.before(&ctx) {
+                skipped = Some(outcome);
+                break;
+            }
+        }
+        let (out, outcome) = match skipped {
+            Some(outcome) => (outcome.clone().and_then(R::from_outcome), outcome),
+            None => {
+                let out = f();
+                let outcome = out.as_ref().map(R::to_outcome).map_err(Clone::clone);
+                (out, outcome)
+            }
+        };
+        for (_, interceptor) in chain[..entered].iter().rev() {
+            interceptor.after(&ctx, &outcome);
+        }
+        out
+    }
+}
+
+/// What proxy calls return, as interceptors see it.
+pub(crate) trait CallValue: Sized {
+    fn to_outcome(&self) -> Option<String>;
+    fn from_outcome(value: Option<String>) -> Result<Self, PluginCallError>;
+}
+
+impl CallValue for () {
+    fn to_outcome(&self) -> Option<String> {
+        None
+    }
+
+    fn from_outcome(_: Option<String>) -> Result<Self, PluginCallError> {
+        Ok(())
+    }
+}
+
+impl CallValue for String {
+    fn to_outcome(&self) -> Option<String> {
+        Some(self.clone())
+    }
+
+    fn from_outcome(value: Option<String>) -> Result<Self, PluginCallError> {
+        value.ok_or(PluginCallError::Failed)
+    }
+}
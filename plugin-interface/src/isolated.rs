@@ -0,0 +1,356 @@
+//! Out-of-process plugin backend: a `plugin-runner` child process loads the
+//! library and serves proxy calls over a JSON-lines protocol on its
+//! stdin/stdout, so a crashing plugin takes down the runner, not the host.
+//! With `PluginManager::set_shared_memory`, each runner is asked to attach
+//! to a shared region when it starts, and large strings go there.
+
+use crate::call::PluginCallError;
+use crate::shared_memory::{Attach, Payload, SharedMemoryOptions, SharedRegion};
+use crate::stats::CallState;
+use crate::{PluginManager, PluginTrait};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+
+/// Request sent from the host to the runner, one JSON object per line.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Request {
+    Count,
+    Attach(Attach),
+    Name { index: usize },
+    Greet { index: usize, target: Payload },
+    Shutdown,
+}
+
+/// Runner reply to a `Request`, one JSON object per line.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum Response {
+    Count { count: usize },
+    Name { name: String },
+    Done,
+    Error { message: String },
+}
+
+/// How `PluginManager::load_isolated` runs plugins out of process.
+#[derive(Debug, Clone)]
+pub struct IsolationOptions {
+    /// The runner executable (the `plugin-runner` binary built with the
+    /// `isolation` feature).
+    pub runner: PathBuf,
+    /// Start a fresh runner on the next call after the previous one died.
+    pub auto_restart: bool,
+    /// Stop restarting after this many restarts.
+    pub max_restarts: u32,
+}
+
+impl Default for IsolationOptions {
+    fn default() -> Self {
+        Self {
+            runner: default_runner_path(),
+            auto_restart: true,
+            max_restarts: 3,
+        }
+    }
+}
+
+/// `plugin-runner` next to the current executable, or one directory up (so
+/// test binaries under `target/<profile>/deps` find it too).
+pub(crate) fn default_runner_path() -> PathBuf {
+    let name = format!("plugin-runner{}", std::env::consts::EXE_SUFFIX);
+    let exe = std::env::current_exe().unwrap_or_default();
+    let dir = exe.parent().unwrap_or(Path::new("."));
+    let beside = dir.join(&name);
+    if beside.exists() {
+        return beside;
+    }
+    dir.parent().map(|d| d.join(&name)).unwrap_or(beside)
+}
+
+struct Runner {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    /// Whether the runner attached to the shared region.
+    attached: bool,
+}
+
+impl Runner {
+    /// Start a runner for `lib` and offer it the shared region, if any. A
+    /// runner that cannot attach gets every payload inline.
+    fn spawn(runner: &Path, lib: &Path, shared: Option<&Attach>) -> Result<Self, String> {
+        let mut child = Command::new(runner)
+            .arg(lib)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| format!("failed to start runner {:?}: {}", runner, e))?;
+        let stdin = child.stdin.take().expect("piped stdin");
+        let stdout = BufReader::new(child.stdout.take().expect("piped stdout"));
+        let mut runner = Self {
+            child,
+            stdin,
+            stdout,
+            attached: false,
+        };
+        if let Some(attach) = shared {
+            runner.attached = matches!(
+                runner.roundtrip(&Request::Attach(attach.clone()))?,
+                Response::Done
+            );
+        }
+        Ok(runner)
+    }
+
+    fn roundtrip(&mut self, req: &Request) -> Result<Response, String> {
+        let mut line = serde_json::to_string(req).map_err(|e| e.to_string())?;
+        line.push('\n');
+        self.stdin
+            .write_all(line.as_bytes())
+            .and_then(|_| self.stdin.flush())
+            .map_err(|e| format!("runner stdin: {}", e))?;
+        let mut reply = String::new();
+        match self.stdout.read_line(&mut reply) {
+            Ok(0) => Err(self.exit_reason()),
+            Ok(_) => serde_json::from_str(&reply).map_err(|e| format!("bad runner reply: {}", e)),
+            Err(e) => Err(format!("runner stdout: {}", e)),
+        }
+    }
+
+    fn exit_reason(&mut self) -> String {
+        match self.child.wait() {
+            Ok(status) => format!("runner exited with {}", status),
+            Err(e) => format!("runner lost: {}", e),
+        }
+    }
+
+    fn shutdown(mut self) {
+        let _ = self.roundtrip(&Request::Shutdown);
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Host-side end of a plugin library loaded in a runner process.
+pub(crate) struct IsolatedPlugin {
+    path: PathBuf,
+    opts: IsolationOptions,
+    runner: Mutex<Option<Runner>>,
+    restarts: AtomicU32,
+    count: usize,
+    shared: Option<(SharedRegion, Attach)>,
+    pub(crate) calls: CallState,
+}
+
+impl std::fmt::Debug for IsolatedPlugin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IsolatedPlugin")
+            .field("path", &self.path)
+            .field("runner", &self.opts.runner)
+            .field("restarts", &self.restarts.load(Ordering::SeqCst))
+            .field("count", &self.count)
+            .finish()
+    }
+}
+
+impl IsolatedPlugin {
+    /// Start a runner for the library at `path` and ask how many
+    /// registrations it exposes. Without a shared region, payloads go
+    /// inline; the plugin still works.
+    pub(crate) fn spawn(
+        path: &Path,
+        opts: IsolationOptions,
+        shared_memory: Option<&SharedMemoryOptions>,
+    ) -> Result<Self, String> {
+        let shared = shared_memory.and_then(|opts| SharedRegion::create(opts).ok());
+        let mut runner = Runner::spawn(
+            &opts.runner,
+            path,
+            shared.as_ref().map(|(_, attach)| attach),
+        )?;
+        let count = match runner.roundtrip(&Request::Count)? {
+            Response::Count { count } => count,
+            Response::Error { message } => return Err(message),
+            other => return Err(format!("unexpected runner reply {:?}", other)),
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            opts,
+            runner: Mutex::new(Some(runner)),
+            restarts: AtomicU32::new(0),
+            count,
+            shared,
+            calls: CallState::for_count(count),
+        })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn count(&self) -> usize {
+        self.count
+    }
+
+    /// How many times the runner was restarted after dying.
+    pub(crate) fn restarts(&self) -> u32 {
+        self.restarts.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn name(&self, index: usize) -> Result<String, PluginCallError> {
+        match self.request(|_| Request::Name { index })? {
+            Response::Name { name } => Ok(name),
+            _ => Err(PluginCallError::Failed),
+        }
+    }
+
+    pub(crate) fn greet(&self, index: usize, target: &str) -> Result<(), PluginCallError> {
+        match self.request(|region| Request::Greet {
+            index,
+            target: Payload::new(target, region, 0),
+        })? {
+            Response::Done => Ok(()),
+            _ => Err(PluginCallError::Failed),
+        }
+    }
+
+    /// Send the request `make` builds, given the shared region when the
+    /// runner is attached to it.
+    fn request(
+        &self,
+        make: impl FnOnce(Option<&SharedRegion>) -> Request,
+    ) -> Result<Response, PluginCallError> {
+        let mut slot = self.runner.lock().unwrap_or_else(|e| e.into_inner());
+        if slot.is_none() {
+            if !self.opts.auto_restart || self.restarts() >= self.opts.max_restarts {
+                return Err(PluginCallError::Crashed(
+                    "runner is not running and will not be restarted".to_string(),
+                ));
+            }
+            self.restarts.fetch_add(1, Ordering::SeqCst);
+            let attach = self.shared.as_ref().map(|(_, attach)| attach);
+            let runner = Runner::spawn(&self.opts.runner, &self.path, attach)
+                .map_err(PluginCallError::Crashed)?;
+            *slot = Some(runner);
+        }
+        let runner = slot.as_mut().expect("runner present");
+        let region = self
+            .shared
+            .as_ref()
+            .map(|(region, _)| region)
+            .filter(|_| runner.attached);
+        match runner.roundtrip(&make(region)) {
+            Ok(Response::Error { .. }) => Err(PluginCallError::Failed),
+            Ok(resp) => Ok(resp),
+            Err(e) => {
+                // The runner is gone or unusable; reap it so the next call
+                // can start a fresh one.
+                if let Some(mut dead) = slot.take() {
+                    let _ = dead.child.kill();
+                    let _ = dead.child.wait();
+                }
+                Err(PluginCallError::Crashed(e))
+            }
+        }
+    }
+}
+
+impl Drop for IsolatedPlugin {
+    fn drop(&mut self) {
+        let slot = self.runner.get_mut().unwrap_or_else(|e| e.into_inner());
+        if let Some(runner) = slot.take() {
+            runner.shutdown();
+        }
+    }
+}
+
+/// Entry point of the `plugin-runner` executable: load the library at
+/// `lib_path` and serve requests from stdin until stdin closes or the host
+/// asks to shut down.
+pub fn serve_isolated(lib_path: &Path) -> Result<(), String> {
+    let mut mgr = PluginManager::new();
+    let handles = mgr
+        .load_library(lib_path, PluginTrait::Greeter)
+        .map_err(|e| format!("failed to load {:?}: {:?}", lib_path, e))?;
+    let proxies: Vec<_> = handles.iter().filter_map(|h| h.as_greeter()).collect();
+
+    let stdin = std::io::stdin();
+    let mut stdout = protocol_output();
+    let mut region = None;
+    for line in stdin.lock().lines() {
+        let line = line.map_err(|e| e.to_string())?;
+        let resp = match serde_json::from_str::<Request>(&line) {
+            Ok(Request::Count) => Response::Count {
+                count: proxies.len(),
+            },
+            Ok(Request::Attach(attach)) => match SharedRegion::attach(&attach) {
+                Ok(attached) => {
+                    region = Some(attached);
+                    Response::Done
+                }
+                Err(message) => Response::Error { message },
+            },
+            Ok(Request::Name { index }) => match proxies.get(index) {
+                Some(p) => Response::Name { name: p.name() },
+                None => no_such_registration(index),
+            },
+            Ok(Request::Greet { index, target }) => match proxies.get(index) {
+                Some(p) => match target.text(region.as_ref()) {
+                    Ok(target) => {
+                        p.greet(target);
+                        Response::Done
+                    }
+                    Err(message) => Response::Error { message },
+                },
+                None => no_such_registration(index),
+            },
+            Ok(Request::Shutdown) => {
+                write_response(&mut stdout, &Response::Done)?;
+                break;
+            }
+            Err(e) => Response::Error {
+                message: format!("bad request: {}", e),
+            },
+        };
+        write_response(&mut stdout, &resp)?;
+    }
+    Ok(())
+}
+
+/// The channel responses are written to. On unix the original stdout is
+/// moved to a private descriptor and fd 1 is pointed at stderr, so anything a
+/// plugin prints cannot corrupt the protocol stream.
+#[cfg(unix)]
+fn protocol_output() -> Box<dyn Write> {
+    use std::os::unix::io::FromRawFd;
+    unsafe {
+        let fd = libc::dup(libc::STDOUT_FILENO);
+        if fd >= 0 && libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) >= 0 {
+            return Box::new(std::fs::File::from_raw_fd(fd));
+        }
+    }
+    Box::new(std::io::stdout())
+}
+
+#[cfg(not(unix))]
+fn protocol_output() -> Box<dyn Write> {
+    Box::new(std::io::stdout())
+}
+
+fn no_such_registration(index: usize) -> Response {
+    Response::Error {
+        message: format!("no registration at index {}", index),
+    }
+}
+
+fn write_response(out: &mut impl Write, resp: &Response) -> Result<(), String> {
+    let line = serde_json::to_string(resp).map_err(|e| e.to_string())?;
+    writeln!(out, "{}", line)
+        .and_then(|_| out.flush())
+        .map_err(|e| e.to_string())
+}
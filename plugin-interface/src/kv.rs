@@ -0,0 +1,202 @@
+//! Persistent key-value storage the host keeps for each plugin, reached
+//! through `HostContext::kv_get` and `HostContext::kv_set`.
+//!
+//! Every plugin gets its own namespace, a directory under the store's
+//! root named after its manifest (or its library file, without the
+//! platform's prefix and extension). Each key is a file in it whose name
+//! is the key hex-encoded, so any key is safe to use.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Longest key, in bytes, the store accepts.
+pub const MAX_KV_KEY_LEN: usize = 100;
+
+/// The directory plugin key-value data is kept in; see
+/// `HostServices::with_kv_dir`. The host can read and seed plugin data
+/// through it too.
+#[derive(Debug)]
+pub struct KvStore {
+    root: PathBuf,
+    // Writers of one store take turns, so a key's temporary file is never
+    // shared.
+    writing: Mutex<()>,
+}
+
+impl KvStore {
+    /// A store kept under `root`, which is created on the first write.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            writing: Mutex::new(()),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The value `namespace` stored under `key`.
+    pub fn get(&self, namespace: &str, key: &str) -> std::io::Result<Option<Vec<u8>>> {
+        let path = self.key_path(namespace, key)?;
+        match std::fs::read(path) {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Store `value` under `key` for `namespace`. The value is written to
+    /// a temporary file first, so readers see the old or the new value.
+    pub fn set(&self, namespace: &str, key: &str, value: &[u8]) -> std::io::Result<()> {
+        let path = self.key_path(namespace, key)?;
+        let _writing = self.writing.lock().unwrap_or_else(|e| e.into_inner());
+        std::fs::create_dir_all(path.parent().expect("key has a namespace"))?;
+        let tmp = path.with_extension("tmp");
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(value)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, &path)
+    }
+
+    /// Remove `key` from `namespace`. Returns whether it was there.
+    pub fn remove(&self, namespace: &str, key: &str) -> std::io::Result<bool> {
+        let path = self.key_path(namespace, key)?;
+        let _writing = self.writing.lock().unwrap_or_else(|e| e.into_inner());
+        match std::fs::remove_file(path) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The keys stored for `namespace`, sorted.
+    pub fn keys(&self, namespace: &str) -> std::io::Result<Vec<String>> {
+        let dir = self.root.join(sanitize(namespace));
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut keys = Vec::new();
+        for entry in entries {
+            let name = entry?.file_name();
+            if let Some(key) = name.to_str().and_then(decode_key) {
+                keys.push(key);
+            }
+        }
+        keys.sort();
+        Ok(keys)
+    }
+
+    fn key_path(&self, namespace: &str, key: &str) -> std::io::Result<PathBuf> {
+        if namespace.is_empty() || key.is_empty() || key.len() > MAX_KV_KEY_LEN {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "keys are 1 to {} bytes in a named namespace",
+                    MAX_KV_KEY_LEN
+                ),
+            ));
+        }
+        Ok(self.root.join(sanitize(namespace)).join(encode_key(key)))
+    }
+}
+
+/// The namespace of the library at `path`: its manifest name, or its file
+/// name without the platform's prefix and extension.
+pub(crate) fn namespace_for(path: &Path, manifest_name: Option<&str>) -> String {
+    match manifest_name {
+        Some(name) => name.to_string(),
+        None => {
+            let stem = path
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            stem.strip_prefix(std::env::consts::DLL_PREFIX)
+                .map(str::to_string)
+                .unwrap_or(stem)
+        }
+    }
+}
+
+/// `namespace` as a single directory name.
+fn sanitize(namespace: &str) -> String {
+    let name: String = namespace
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    // Never `.` or `..`.
+    if name.chars().all(|c| c == '.') {
+        name.replace('.', "_")
+    } else {
+        name
+    }
+}
+
+fn encode_key(key: &str) -> String {
+    key.bytes().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_key(name: &str) -> Option<String> {
+    // Odd lengths and non-hex names fail on the last or offending pair.
+    let bytes = (0..name.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(name.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn namespaces_keep_plugins_apart() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = KvStore::new(dir.path().join("kv"));
+
+        assert_eq!(store.get("a", "color").unwrap(), None);
+        store.set("a", "color", b"blue").unwrap();
+        store.set("a", "../odd key/", b"x").unwrap();
+        store.set("b", "color", b"red").unwrap();
+        store.set("a", "color", b"green").unwrap();
+
+        assert_eq!(
+            store.get("a", "color").unwrap().as_deref(),
+            Some(&b"green"[..])
+        );
+        assert_eq!(
+            store.get("b", "color").unwrap().as_deref(),
+            Some(&b"red"[..])
+        );
+        assert_eq!(store.keys("a").unwrap(), ["../odd key/", "color"]);
+        assert!(store.remove("a", "color").unwrap());
+        assert!(!store.remove("a", "color").unwrap());
+        assert_eq!(store.keys("a").unwrap(), ["../odd key/"]);
+        assert!(store.set("a", "", b"").is_err());
+        assert!(store
+            .set("a", &"k".repeat(MAX_KV_KEY_LEN + 1), b"")
+            .is_err());
+        assert_eq!(sanitize(".."), "__");
+        assert_eq!(sanitize("my plugin/1"), "my_plugin_1");
+    }
+
+    #[test]
+    fn libraries_without_manifest_use_their_bare_name() {
+        let lib = format!(
+            "{}shout.{}",
+            std::env::consts::DLL_PREFIX,
+            std::env::consts::DLL_EXTENSION
+        );
+        assert_eq!(namespace_for(Path::new(&lib), None), "shout");
+        assert_eq!(namespace_for(Path::new(&lib), Some("loud")), "loud");
+    }
+}
@@ -0,0 +1,93 @@
+//! Plugins recorded by `PluginManager::index_plugins` and opened the first
+//! time something asks for them.
+
+use crate::manifest::Candidate;
+use crate::{PluginManifest, PluginTrait};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+/// A plugin found by `PluginManager::index_plugins` that is opened on first
+/// use.
+#[derive(Debug, Clone)]
+pub struct IndexedPlugin {
+    /// Manifest name, or file name for plugins without a manifest.
+    pub name: String,
+    pub path: PathBuf,
+    pub manifest: Option<PluginManifest>,
+    pub trait_id: PluginTrait,
+}
+
+impl IndexedPlugin {
+    pub(crate) fn candidate(&self) -> Candidate {
+        Candidate {
+            path: self.path.clone(),
+            manifest: self.manifest.clone(),
+        }
+    }
+}
+
+/// The indexed plugin `name` and the indexed plugins it transitively depends
+/// on, leaving out anything in `loaded`. Dependencies missing from the index
+/// are left for dependency ordering to report.
+pub(crate) fn with_dependencies<'a>(
+    index: &'a [IndexedPlugin],
+    name: &str,
+    loaded: &HashMap<String, PathBuf>,
+) -> Vec<&'a IndexedPlugin> {
+    let mut out = Vec::new();
+    let mut seen = HashSet::new();
+    let mut queue = vec![name.to_string()];
+    while let Some(next) = queue.pop() {
+        if loaded.contains_key(&next) || !seen.insert(next.clone()) {
+            continue;
+        }
+        let Some(entry) = index.iter().find(|e| e.name == next) else {
+            continue;
+        };
+        if let Some(m) = &entry.manifest {
+            queue.extend(m.dependencies.keys().cloned());
+        }
+        out.push(entry);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, deps: &[&str]) -> IndexedPlugin {
+        let deps: String = deps.iter().map(|d| format!("{} = \"1\"\n", d)).collect();
+        IndexedPlugin {
+            name: name.to_string(),
+            path: PathBuf::from(format!("lib{}.so", name)),
+            manifest: Some(
+                PluginManifest::parse(&format!(
+                    "name = \"{}\"\nversion = \"1.0.0\"\ntraits = [\"Greeter\"]\n[dependencies]\n{}",
+                    name, deps
+                ))
+                .unwrap(),
+            ),
+            trait_id: PluginTrait::Greeter,
+        }
+    }
+
+    #[test]
+    fn collects_unloaded_transitive_dependencies() {
+        let index = vec![
+            entry("app", &["core", "log"]),
+            entry("core", &["alloc"]),
+            entry("alloc", &[]),
+            entry("log", &[]),
+            entry("unrelated", &[]),
+        ];
+        let loaded = HashMap::from([("log".to_string(), PathBuf::from("liblog.so"))]);
+        let mut names: Vec<&str> = with_dependencies(&index, "app", &loaded)
+            .iter()
+            .map(|e| e.name.as_str())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["alloc", "app", "core"]);
+        assert!(with_dependencies(&index, "missing", &loaded).is_empty());
+    }
+}
@@ -0,0 +1,269 @@
+//! Leak accounting for the `leak-check` feature: the code `#[plugin_impl]`
+//! generates counts the registrations, vtables, instances and strings it
+//! allocates and frees, `#[plugin_aggregates]` exports the counts as
+//! `plugin_leak_report_v1`, and the manager checks at unload that every
+//! allocation was released.
+//!
+//! The counters live in this crate, which every plugin links its own copy
+//! of, so each library keeps its own. Without the feature recording does
+//! nothing and plugins report no counts.
+
+use std::fmt;
+
+/// How many allocations of one kind were made and released.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LeakCount {
+    pub made: u64,
+    pub released: u64,
+}
+
+impl LeakCount {
+    /// Allocations not released yet.
+    pub fn outstanding(&self) -> u64 {
+        self.made.saturating_sub(self.released)
+    }
+}
+
+/// What a plugin allocated and released, as `plugin_leak_report_v1` hands
+/// it over. Only strings the plugin allocates itself are counted; those
+/// made with the host's allocator are the host's to free.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LeakReport {
+    /// `size_of::<LeakReport>()` as the plugin was built.
+    pub struct_size: usize,
+    pub registrations: LeakCount,
+    pub vtables: LeakCount,
+    /// The trait implementations the vtables point to.
+    pub user_data: LeakCount,
+    pub strings: LeakCount,
+}
+
+impl Default for LeakReport {
+    fn default() -> Self {
+        Self {
+            struct_size: std::mem::size_of::<Self>(),
+            registrations: LeakCount::default(),
+            vtables: LeakCount::default(),
+            user_data: LeakCount::default(),
+            strings: LeakCount::default(),
+        }
+    }
+}
+
+impl LeakReport {
+    /// True when everything made was released.
+    pub fn is_balanced(&self) -> bool {
+        self.entries()
+            .iter()
+            .all(|(_, count)| count.outstanding() == 0)
+    }
+
+    fn entries(&self) -> [(&'static str, LeakCount); 4] {
+        [
+            ("registrations", self.registrations),
+            ("vtables", self.vtables),
+            ("user_data", self.user_data),
+            ("strings", self.strings),
+        ]
+    }
+}
+
+impl fmt::Display for LeakReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let leaked: Vec<String> = self
+            .entries()
+            .iter()
+            .filter(|(_, count)| count.outstanding() > 0)
+            .map(|(kind, count)| format!("{} of {} {kind}", count.outstanding(), count.made))
+            .collect();
+        if leaked.is_empty() {
+            write!(f, "nothing leaked")
+        } else {
+            write!(f, "leaked {}", leaked.join(", "))
+        }
+    }
+}
+
+/// What an allocation recorded with `record_made` / `record_released` is.
+#[doc(hidden)]
+#[derive(Debug, Clone, Copy)]
+pub enum Allocation {
+    Registration,
+    VTable,
+    UserData,
+    String,
+}
+
+#[cfg(feature = "leak-check")]
+mod counters {
+    use super::{Allocation, LeakCount, LeakReport};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    static COUNTS: [[AtomicU64; 2]; 4] = [const { [AtomicU64::new(0), AtomicU64::new(0)] }; 4];
+
+    pub(super) fn record(kind: Allocation, released: bool) {
+        COUNTS[kind as usize][released as usize].fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(super) fn report() -> LeakReport {
+        let count = |kind: Allocation| LeakCount {
+            made: COUNTS[kind as usize][0].load(Ordering::SeqCst),
+            released: COUNTS[kind as usize][1].load(Ordering::SeqCst),
+        };
+        LeakReport {
+            registrations: count(Allocation::Registration),
+            vtables: count(Allocation::VTable),
+            user_data: count(Allocation::UserData),
+            strings: count(Allocation::String),
+            ..LeakReport::default()
+        }
+    }
+}
+
+/// Count an allocation of `kind` the plugin made.
+#[doc(hidden)]
+#[inline]
+pub fn record_made(kind: Allocation) {
+    #[cfg(feature = "leak-check")]
+    counters::record(kind, false);
+    #[cfg(not(feature = "leak-check"))]
+    let _ = kind;
+}
+
+/// Count an allocation of `kind` the plugin released.
+#[doc(hidden)]
+#[inline]
+pub fn record_released(kind: Allocation) {
+    #[cfg(feature = "leak-check")]
+    counters::record(kind, true);
+    #[cfg(not(feature = "leak-check"))]
+    let _ = kind;
+}
+
+/// Body of `plugin_leak_report_v1`: write the counts to `out`, or return
+/// false if this copy of the crate was built without `leak-check`.
+///
+/// # Safety
+/// `out` must be null or point to a writable `LeakReport`.
+#[doc(hidden)]
+pub unsafe fn write_leak_report(out: *mut LeakReport) -> bool {
+    #[cfg(feature = "leak-check")]
+    if let Some(out) = out.as_mut() {
+        *out = counters::report();
+        return true;
+    }
+    let _ = out;
+    false
+}
+
+#[cfg(feature = "leak-check")]
+type ReportFn = unsafe extern "C" fn(*mut LeakReport) -> bool;
+
+/// How many `LoadedLib`s hold each library image, by the address of its
+/// `plugin_leak_report_v1`. Loading the same file twice maps it once, so
+/// the loads share one set of counters.
+#[cfg(feature = "leak-check")]
+static OPEN: std::sync::Mutex<std::collections::BTreeMap<usize, usize>> =
+    std::sync::Mutex::new(std::collections::BTreeMap::new());
+
+#[cfg(feature = "leak-check")]
+fn report_fn(lib: &libloading::Library) -> Option<ReportFn> {
+    unsafe { lib.get::<ReportFn>(crate::symbols::c_name(crate::symbols::LEAK_REPORT).as_bytes()) }
+        .ok()
+        .map(|f| *f)
+}
+
+/// Note that a `LoadedLib` holds `lib`.
+#[cfg(feature = "leak-check")]
+pub(crate) fn opened(lib: &libloading::Library) {
+    if let Some(f) = report_fn(lib) {
+        let mut open = OPEN.lock().unwrap_or_else(|e| e.into_inner());
+        *open.entry(f as usize).or_default() += 1;
+    }
+}
+
+/// Made when a `LoadedLib` lets go of `lib`. If it was the last one, the
+/// check runs when this is dropped, which must be after the registrations
+/// are released and before the library is closed: it panics if the
+/// library reports allocations it did not release.
+#[cfg(feature = "leak-check")]
+pub(crate) struct UnloadCheck<'a> {
+    last: Option<ReportFn>,
+    path: &'a std::path::Path,
+}
+
+#[cfg(feature = "leak-check")]
+impl<'a> UnloadCheck<'a> {
+    /// `check` is false for images whose registrations the host releases,
+    /// such as its own.
+    pub(crate) fn new(lib: &libloading::Library, path: &'a std::path::Path, check: bool) -> Self {
+        let last = report_fn(lib).filter(|f| {
+            let mut open = OPEN.lock().unwrap_or_else(|e| e.into_inner());
+            let Some(count) = open.get_mut(&(*f as usize)) else {
+                return false;
+            };
+            *count -= 1;
+            if *count == 0 {
+                open.remove(&(*f as usize));
+                return check;
+            }
+            false
+        });
+        Self { last, path }
+    }
+}
+
+#[cfg(feature = "leak-check")]
+impl Drop for UnloadCheck<'_> {
+    fn drop(&mut self) {
+        let Some(report_fn) = self.last else {
+            return;
+        };
+        let mut report = LeakReport::default();
+        // Unloading while unwinding must not panic again.
+        if unsafe { report_fn(&mut report) } && !report.is_balanced() && !std::thread::panicking() {
+            panic!("{}: {report}", self.path.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_name_what_was_not_released() {
+        let mut report = LeakReport::default();
+        assert!(report.is_balanced());
+        assert_eq!(report.to_string(), "nothing leaked");
+
+        report.registrations = LeakCount {
+            made: 2,
+            released: 2,
+        };
+        report.strings = LeakCount {
+            made: 5,
+            released: 3,
+        };
+        assert!(!report.is_balanced());
+        assert_eq!(report.to_string(), "leaked 2 of 5 strings");
+    }
+
+    #[cfg(feature = "leak-check")]
+    #[test]
+    fn recorded_allocations_show_up_in_the_report() {
+        let before = unsafe {
+            let mut report = LeakReport::default();
+            assert!(write_leak_report(&mut report));
+            report
+        };
+        record_made(Allocation::VTable);
+        record_released(Allocation::VTable);
+        let mut after = LeakReport::default();
+        assert!(unsafe { write_leak_report(&mut after) });
+        assert!(after.vtables.made > before.vtables.made);
+        assert!(after.vtables.released > before.vtables.released);
+    }
+}
@@ -2,6 +2,9 @@ use libloading::Library;
 use std::ffi::c_void;
 use std::os::raw::c_char;
 
+// Lets `#[plugin_interface]` expansions in this crate name it like plugins do.
+extern crate self as plugin_interface;
+
 // Vtable definition that plugin-annotations macro will generate-compatible vtables for.
 #[repr(C)]
 pub struct GreeterVTable {
@@ -9,47 +12,283 @@ pub struct GreeterVTable {
     pub user_data: *mut c_void,
     pub name: extern "C" fn(*mut c_void) -> *const c_char,
     pub greet: extern "C" fn(*mut c_void, *const c_char),
+    /// Lifecycle hook invoked by the host once the registration is loaded.
+    /// Only present when `abi_version >= LIFECYCLE_VTABLE_ABI`.
+    pub on_load: extern "C" fn(*mut c_void, *const HostInfo),
+    /// Lifecycle hook invoked by the host right before the registration is
+    /// released. Only present when `abi_version >= LIFECYCLE_VTABLE_ABI`.
+    pub on_unload: extern "C" fn(*mut c_void),
     pub drop: extern "C" fn(*mut c_void),
+    /// Writes the registration's state into the sink handed to it. Only
+    /// present when `abi_version >= STATE_VTABLE_ABI`.
+    pub save_state: extern "C" fn(*mut c_void, *mut c_void, StateSink),
+    /// Restores state written by `save_state` of an earlier instance. Only
+    /// present when `abi_version >= STATE_VTABLE_ABI`.
+    pub restore_state: extern "C" fn(*mut c_void, *const u8, usize),
+    /// `name`, returning the string with its length and free function
+    /// instead of a nul-terminated copy. Only present when
+    /// `abi_version >= STR_VTABLE_ABI`.
+    pub name_str: extern "C" fn(*mut c_void) -> OwnedStr,
+    /// `greet`, taking the target as pointer and length. Only present when
+    /// `abi_version >= STR_VTABLE_ABI`.
+    pub greet_str: extern "C" fn(*mut c_void, StrRef),
+    /// `size_of` the vtable in the plugin that built it, up to `context`,
+    /// so hosts can tell which entries appended after this one it has. Only
+    /// present when `abi_version >= SIZED_VTABLE_ABI`.
+    pub struct_size: usize,
+    /// Reserved; no flags are defined yet and hosts ignore unknown bits.
+    /// Only present when `abi_version >= SIZED_VTABLE_ABI`.
+    pub flags: u32,
+    /// Entries appended after the trait's first release; present as far as
+    /// `struct_size` reaches.
+    pub optional: GreeterOptional,
+    /// Only present when `abi_version >= CONTEXT_VTABLE_ABI`, and then at
+    /// `struct_size` rather than here; read it through `context_table`.
+    pub context: GreeterContext,
+}
+
+impl GreeterVTable {
+    /// The `Greeter` methods plugins describe in `plugin_describe_v1`.
+    pub const METHODS: &'static [MethodDescription] = &[
+        MethodDescription {
+            name: "name",
+            args: &[],
+            returns: "&str",
+            optional: false,
+            deprecated: None,
+        },
+        MethodDescription {
+            name: "greet",
+            args: &[("target", "&str")],
+            returns: "",
+            optional: false,
+            deprecated: None,
+        },
+        MethodDescription {
+            name: "health",
+            args: &[],
+            returns: "HealthStatus",
+            optional: true,
+            deprecated: None,
+        },
+    ];
+
+    /// The plugin's `health` entry, if its vtable is long enough to have
+    /// one and it is set.
+    pub fn optional_health(&self) -> Option<extern "C" fn(*mut c_void) -> HealthStatus> {
+        if self.abi_version < SIZED_VTABLE_ABI {
+            return None;
+        }
+        let end = std::mem::offset_of!(Self, optional)
+            + std::mem::offset_of!(GreeterOptional, health)
+            + std::mem::size_of::<Option<extern "C" fn(*mut c_void) -> HealthStatus>>();
+        if self.struct_size < end {
+            return None;
+        }
+        self.optional.health
+    }
+
+    /// The plugin's context table, if its vtable is followed by one.
+    pub fn context_table(&self) -> Option<&GreeterContext> {
+        if self.abi_version < CONTEXT_VTABLE_ABI {
+            return None;
+        }
+        // The table starts where the plugin's vtable ends, wherever that is
+        // in this build of the type.
+        Some(unsafe {
+            &*((self as *const Self as *const u8).add(self.struct_size) as *const GreeterContext)
+        })
+    }
+
+    /// The plugin's `name_ctx` entry, if its context table is long enough
+    /// to have one and it is set.
+    pub fn optional_name_ctx(
+        &self,
+    ) -> Option<extern "C" fn(*mut c_void, *const RawRequestContext) -> OwnedStr> {
+        let context = self.context_table()?;
+        let end = std::mem::offset_of!(GreeterContext, name_ctx)
+            + std::mem::size_of::<
+                Option<extern "C" fn(*mut c_void, *const RawRequestContext) -> OwnedStr>,
+            >();
+        if context.struct_size < end {
+            return None;
+        }
+        context.name_ctx
+    }
+
+    /// The plugin's `greet_ctx` entry, if its context table is long enough
+    /// to have one and it is set.
+    pub fn optional_greet_ctx(
+        &self,
+    ) -> Option<extern "C" fn(*mut c_void, StrRef, *const RawRequestContext)> {
+        let context = self.context_table()?;
+        let end = std::mem::offset_of!(GreeterContext, greet_ctx)
+            + std::mem::size_of::<
+                Option<extern "C" fn(*mut c_void, StrRef, *const RawRequestContext)>,
+            >();
+        if context.struct_size < end {
+            return None;
+        }
+        context.greet_ctx
+    }
+}
+
+impl DynamicRegistration for GreeterRegistration {
+    const METHODS: &'static [MethodDescription] = GreeterVTable::METHODS;
+
+    unsafe fn call(
+        reg: *const Self,
+        method: &str,
+        args: &[&str],
+    ) -> Result<Option<String>, PluginCallError> {
+        let v = &*(*reg).vtable;
+        match method {
+            "name" => (v.name_str)(v.user_data)
+                .into_string()
+                .map(Some)
+                .ok_or(PluginCallError::Failed),
+            "greet" => {
+                (v.greet_str)(
+                    v.user_data,
+                    StrRef::new(args.first().copied().unwrap_or("")),
+                );
+                Ok(None)
+            }
+            "health" => {
+                let health = v.optional_health().ok_or(PluginCallError::Unsupported)?;
+                Ok(Some(health(v.user_data).to_string()))
+            }
+            _ => Err(PluginCallError::Unsupported),
+        }
+    }
+}
+
+/// Optional `GreeterVTable` entries, as `#[plugin_interface]` generates
+/// them for other traits. Methods added to `Greeter` go last, as
+/// `Option<extern "C" fn>` fields.
+#[repr(C)]
+pub struct GreeterOptional {
+    pub health: Option<extern "C" fn(*mut c_void) -> HealthStatus>,
+}
+
+impl GreeterOptional {
+    /// Every optional entry, calling `T`'s implementation and counting in
+    /// `C`.
+    pub fn for_impl<T: Greeter, C: CountersSource>() -> Self {
+        extern "C" fn health<T: Greeter, C: CountersSource>(
+            user_data: *mut c_void,
+        ) -> HealthStatus {
+            let instance = unsafe { &*(user_data as *const T) };
+            C::call();
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| instance.health()))
+                .unwrap_or_else(|_| {
+                    C::panic();
+                    HealthStatus::Unhealthy
+                })
+        }
+        Self {
+            health: Some(health::<T, C>),
+        }
+    }
+}
+
+/// The context table following a `GreeterVTable`: the `_str` entries with
+/// the caller's request context as trailing argument. Entries for methods
+/// added to `Greeter` go last.
+#[repr(C)]
+pub struct GreeterContext {
+    /// `size_of` the table in the plugin that built it.
+    pub struct_size: usize,
+    /// `name_str`, with the caller's request context as trailing argument.
+    pub name_ctx: Option<extern "C" fn(*mut c_void, *const RawRequestContext) -> OwnedStr>,
+    /// `greet_str`, with the caller's request context as trailing argument.
+    pub greet_ctx: Option<extern "C" fn(*mut c_void, StrRef, *const RawRequestContext)>,
+}
+
+impl GreeterContext {
+    /// Every entry, calling `T`'s implementation and counting in `C`.
+    pub fn for_impl<T: Greeter, C: CountersSource>() -> Self {
+        extern "C" fn name_ctx<T: Greeter, C: CountersSource>(
+            user_data: *mut c_void,
+            ctx: *const RawRequestContext,
+        ) -> OwnedStr {
+            let instance = unsafe { &*(user_data as *const T) };
+            C::call();
+            match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                enter_request(ctx, || String::from(instance.name()))
+            })) {
+                Ok(s) => OwnedStr::new(s),
+                Err(_) => {
+                    C::panic();
+                    OwnedStr::null()
+                }
+            }
+        }
+        extern "C" fn greet_ctx<T: Greeter, C: CountersSource>(
+            user_data: *mut c_void,
+            target: StrRef,
+            ctx: *const RawRequestContext,
+        ) {
+            let instance = unsafe { &*(user_data as *const T) };
+            C::call();
+            if std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                enter_request(ctx, || instance.greet(unsafe { target.as_str() }))
+            }))
+            .is_err()
+            {
+                C::panic();
+            }
+        }
+        Self {
+            struct_size: std::mem::size_of::<Self>(),
+            name_ctx: Some(name_ctx::<T, C>),
+            greet_ctx: Some(greet_ctx::<T, C>),
+        }
+    }
 }
 
+pub use plugin_abi::features::Features;
+pub use plugin_abi::registration::{
+    HostVersion, RegistrationArray, RegistrationFactory, HOST_ALLOCATOR_ABI, HOST_CONTEXT_ABI,
+    HOST_VERSION_ABI, MAX_PLUGIN_ABI, MIN_PLUGIN_ABI, SIZED_STRUCTS_ABI, UNLIMITED_INSTANCES,
+};
+pub use plugin_abi::vtable::{
+    StateSink, CONTEXT_VTABLE_ABI, LIFECYCLE_VTABLE_ABI, SIZED_VTABLE_ABI, STATE_VTABLE_ABI,
+    STR_VTABLE_ABI,
+};
+
 #[repr(C)]
 pub struct GreeterRegistration {
     pub name: *const c_char,
     pub vtable: *const GreeterVTable,
 }
 
+/// Callback run by the generated `plugin_register_all_<Trait>_v1` once it has
+/// stored the host's `HostContext`. Macros such as `#[plugin_logging]` submit
+/// one via `inventory::submit!` to hook into registration.
 #[repr(C)]
-pub struct RegistrationArray {
-    /// Number of registrations in the array.
-    pub count: usize,
-    /// Type-erased pointer array: *const *const c_void; callers cast entries to the
-    /// concrete registration pointer type they expect (for example, `*const GreeterRegistration`).
-    pub registrations: *const *const c_void,
-    /// Parallel array of pointers to the RegistrationFactory that produced the
-    /// corresponding registration entry. This allows precise, deterministic
-    /// unmaker calls for each registration.
-    pub factories: *const *const RegistrationFactory,
+pub struct RegisterHook {
+    pub run: extern "C" fn(*const HostContext),
 }
 
-/// A small wrapper used with `inventory` so plugins can register their factory functions
-/// at link time. Each item holds a function pointer to the plugin's `plugin_register_*`.
-/// We store the function pointer as an erased extern "C" function pointer so it can be
-/// submitted via `inventory::submit!` without relying on pointer-to-integer casts.
+#[cfg(feature = "inventory")]
+inventory::collect!(RegisterHook);
+
+/// Stores the host context in the `host_context()` of a crate with
+/// `#[plugin_aggregates]` for `trait_name`. The generated register
+/// function stores it itself; `PluginManager::load_static` goes through
+/// these for plugins linked into the host.
 #[repr(C)]
-pub struct RegistrationFactory {
-    /// Erased factory function pointer: extern "C" fn() -> *const c_void
-    pub maker: extern "C" fn() -> *const c_void,
-    /// Erased unregister function pointer: extern "C" fn(*const c_void)
-    /// that releases a registration previously returned by `maker`.
-    pub unmaker: extern "C" fn(*const c_void),
-    /// Nul-terminated trait name to allow filtering by trait at runtime.
+pub struct HostContextSlot {
+    /// Nul-terminated name of the trait the crate aggregates.
     pub trait_name: *const c_char,
+    pub store: extern "C" fn(*const HostContext),
 }
 
-inventory::collect!(RegistrationFactory);
-// Raw pointers are inherently fine for static registration; assert thread-safety
-unsafe impl Send for RegistrationFactory {}
-unsafe impl Sync for RegistrationFactory {}
+#[cfg(feature = "inventory")]
+inventory::collect!(HostContextSlot);
+unsafe impl Send for HostContextSlot {}
+unsafe impl Sync for HostContextSlot {}
 
 #[repr(C)]
 pub struct PluginMetadata {
@@ -58,34 +297,426 @@ pub struct PluginMetadata {
     pub vtable: *const c_void,
 }
 
+/// Information about the host handed to a plugin's `on_load` hook.
+#[repr(C)]
+pub struct HostInfo {
+    /// ABI version implemented by the host (`HOST_ABI_VERSION`).
+    pub abi_version: u32,
+    /// Nul-terminated version string of the host's `plugin-interface` crate.
+    pub host_version: *const c_char,
+}
+
+// Only ever points at static, immutable data.
+unsafe impl Sync for HostInfo {}
+
+static HOST_INFO: HostInfo = HostInfo {
+    abi_version: HOST_ABI_VERSION,
+    host_version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+};
+
+impl HostInfo {
+    /// The `HostInfo` describing this host.
+    pub fn current() -> &'static HostInfo {
+        &HOST_INFO
+    }
+
+    /// This host's version, as register functions get it from
+    /// `HOST_VERSION_ABI` on.
+    pub fn current_version() -> HostVersion {
+        let part = |s: &str| s.parse().unwrap_or(0);
+        HostVersion::new(
+            part(env!("CARGO_PKG_VERSION_MAJOR")),
+            part(env!("CARGO_PKG_VERSION_MINOR")),
+            part(env!("CARGO_PKG_VERSION_PATCH")),
+            HOST_ABI_VERSION,
+        )
+    }
+
+    /// The host version as a string slice.
+    pub fn host_version(&self) -> &str {
+        if self.host_version.is_null() {
+            return "";
+        }
+        unsafe { std::ffi::CStr::from_ptr(self.host_version) }
+            .to_str()
+            .unwrap_or("")
+    }
+}
+
 // Example trait to demonstrate prototype
 pub trait Greeter {
     fn name(&self) -> &str;
     fn greet(&self, target: &str);
+
+    /// Called by the host after the registration has been loaded.
+    fn on_load(&self, _host: &HostInfo) {}
+
+    /// Called by the host before the registration is unregistered and the
+    /// library is unloaded.
+    fn on_unload(&self) {}
+
+    /// State to hand to the instance that replaces this one when
+    /// `PluginManager::reload_by_path` swaps in a new version.
+    fn save_state(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Receive the bytes the previous version's `save_state` returned. Called
+    /// after `on_load` and before any other call; not called when the
+    /// previous version saved nothing.
+    fn restore_state(&mut self, _bytes: &[u8]) {}
+
+    /// Receive the configuration the host has for this plugin (see
+    /// `ConfigSource`), right after the registration is constructed and
+    /// before `on_load`. Not called when there is none.
+    fn configure(&mut self, _config: &str) {}
+
+    /// How the plugin is doing, asked by `PluginManager::check_health`.
+    /// Added after the trait's first release; plugins without it count as
+    /// healthy. Implementations mark their override `#[optional]`.
+    fn health(&self) -> HealthStatus {
+        HealthStatus::Healthy
+    }
+}
+
+/// Second built-in trait: a plugin that rewrites text. Unlike `Greeter`,
+/// its `TransformerVTable` and `TransformerRegistration` are generated by
+/// `#[plugin_interface]`, with a `transform` entry taking and returning
+/// nul-terminated strings and a `transform_str` entry using `StrRef` /
+/// `OwnedStr`.
+#[plugin_annotations::plugin_interface]
+pub trait Transformer {
+    fn transform(&self, input: &str) -> String;
+
+    /// One line on what the transformation does. Added after the trait's
+    /// first release, so plugins built before it have no entry for it and
+    /// `TransformerProxy::describe` falls back to the registration name.
+    #[optional]
+    fn describe(&self) -> String {
+        String::new()
+    }
+
+    /// How the plugin is doing, asked by `PluginManager::check_health`.
+    /// Plugins without it count as healthy.
+    #[optional]
+    fn health(&self) -> HealthStatus {
+        HealthStatus::Healthy
+    }
+
+    /// Called by the host after the registration has been loaded.
+    fn on_load(&self, _host: &HostInfo) {}
+
+    /// Called by the host before the registration is unregistered and the
+    /// library is unloaded.
+    fn on_unload(&self) {}
+
+    /// State to hand to the instance that replaces this one on reload.
+    fn save_state(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Receive the bytes the previous version's `save_state` returned.
+    fn restore_state(&mut self, _bytes: &[u8]) {}
+
+    /// Receive the configuration the host has for this plugin (see
+    /// `ConfigSource`), right after the registration is constructed and
+    /// before `on_load`. Not called when there is none.
+    fn configure(&mut self, _config: &str) {}
 }
 
+/// ABI version implemented by this host. Manifests declaring a larger
+/// `min_host_abi` are rejected before the library is opened.
+pub const HOST_ABI_VERSION: u32 = 5;
+
+/// First host ABI version whose `HostContext` ends with `get_service`.
+/// Plugins check `HostContext::abi_version` before reading it.
+pub const SERVICES_HOST_ABI: u32 = 2;
+
+/// First host ABI version whose `HostContext` ends with `subscribe`,
+/// `unsubscribe` and `publish`.
+pub const EVENT_BUS_HOST_ABI: u32 = 3;
+
+/// First host ABI version whose `HostContext` ends with `plugin_config`.
+pub const PLUGIN_CONFIG_HOST_ABI: u32 = 4;
+
+/// First host ABI version whose `HostContext` ends with `kv_get` and
+/// `kv_set`.
+pub const KV_HOST_ABI: u32 = 5;
+
+/// Features this host offers plugins in `plugin_handshake_v1`, unless
+/// narrowed with `PluginManager::set_host_features`.
+pub const HOST_FEATURES: Features = Features(
+    Features::HOST_ALLOCATOR.bits()
+        | Features::SERVICES.bits()
+        | Features::EVENT_BUS.bits()
+        | Features::PLUGIN_CONFIG.bits()
+        | Features::KV_STORE.bits()
+        | Features::REQUEST_CONTEXT.bits(),
+);
+
+/// Look up `<prefix>_<trait_name>_v<N>` in `lib`, trying ABI levels from
+/// `MAX_PLUGIN_ABI` down to `MIN_PLUGIN_ABI`. Returns the symbol together
+/// with the level it was found at.
+///
+/// # Safety
+/// `T` must match the signature of the exported symbol.
+pub unsafe fn find_versioned_symbol<'lib, T>(
+    lib: &'lib Library,
+    prefix: &str,
+    trait_name: &str,
+) -> Option<(libloading::Symbol<'lib, T>, u32)> {
+    (MIN_PLUGIN_ABI..=MAX_PLUGIN_ABI).rev().find_map(|abi| {
+        let name = symbols::versioned_c(prefix, trait_name, abi);
+        lib.get::<T>(name.as_bytes()).ok().map(|sym| (sym, abi))
+    })
+}
+
+/// `plugin_register_all_<Trait>_v<N>` with the signature of the levels
+/// below `HOST_CONTEXT_ABI`, as `find_versioned_symbol` looks it up.
+pub(crate) type RegisterAllFn = unsafe extern "C" fn() -> *const RegistrationArray;
+
+/// Call `register_all`, found at ABI level `abi`, with `host` from
+/// `HOST_CONTEXT_ABI` on and this host's version from `HOST_VERSION_ABI` on.
+///
+/// # Safety
+/// `register_all` must be the library's register function for `abi`.
+pub(crate) unsafe fn call_register_all(
+    register_all: RegisterAllFn,
+    abi: u32,
+    host: *const HostContext,
+) -> *const RegistrationArray {
+    if abi < HOST_CONTEXT_ABI {
+        return register_all();
+    }
+    if abi < HOST_VERSION_ABI {
+        let register_all = std::mem::transmute::<
+            RegisterAllFn,
+            unsafe extern "C" fn(*const HostContext) -> *const RegistrationArray,
+        >(register_all);
+        return register_all(host);
+    }
+    let register_all = std::mem::transmute::<
+        RegisterAllFn,
+        unsafe extern "C" fn(*const HostContext, *const HostVersion) -> *const RegistrationArray,
+    >(register_all);
+    let version = HostInfo::current_version();
+    register_all(host, &version)
+}
+
+/// Why `lib`'s register function returned null, if its
+/// `plugin_register_error_v1` says.
+///
+/// # Safety
+/// `lib`'s `plugin_register_error_v1`, if any, must have the signature
+/// `#[plugin_aggregates]` gives it.
+pub(crate) unsafe fn registration_error(lib: &Library) -> Option<String> {
+    let error = lib
+        .get::<unsafe extern "C" fn() -> *const c_char>(
+            symbols::c_name(symbols::REGISTER_ERROR).as_bytes(),
+        )
+        .ok()?();
+    if error.is_null() {
+        return None;
+    }
+    Some(
+        std::ffi::CStr::from_ptr(error)
+            .to_string_lossy()
+            .into_owned(),
+    )
+}
+
+mod actor;
+#[cfg(feature = "async")]
+mod async_manager;
+mod bench;
+mod budget;
+mod bus;
+mod call;
+mod capability;
+mod config;
+mod conflict;
+mod counters;
+mod deps;
+mod describe;
+#[cfg(feature = "pinning")]
+mod digest;
+mod discovery;
+mod dispatch;
+mod dynamic;
+mod events;
+#[cfg(feature = "fuzzing")]
+mod fuzz;
+mod groups;
 mod handle;
+mod health;
+mod holders;
+mod host;
+mod identity;
+mod impls;
+mod instances;
+mod instrument;
+mod intercept;
+#[cfg(feature = "isolation")]
+mod isolated;
+mod kv;
+mod lazy;
+mod leaks;
+mod loader;
+#[cfg(feature = "log")]
+mod log_bridge;
 mod manager;
-pub use handle::{GreeterProxy, PluginHandle};
+mod manifest;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod modules;
+mod persist;
+#[cfg(feature = "isolation")]
+mod probe;
+mod quarantine;
+mod query;
+#[cfg(feature = "record")]
+mod record;
+#[cfg(feature = "registry")]
+mod registry;
+#[cfg(feature = "remote")]
+mod remote;
+mod request;
+#[cfg(feature = "watch")]
+mod retry;
+mod search_path;
+mod services;
+#[cfg(feature = "watch")]
+mod settle;
+mod shadow;
+#[cfg(any(feature = "isolation", feature = "remote"))]
+mod shared_memory;
+mod shutdown;
+#[cfg(feature = "signing")]
+mod signing;
+mod split;
+#[cfg(feature = "static-plugins")]
+mod static_plugins;
+mod stats;
+mod strings;
+mod validate;
+#[cfg(feature = "wasm")]
+mod wasm;
+#[cfg(feature = "watch")]
+mod watch_filter;
 #[cfg(feature = "watch")]
-pub use manager::{ManagerNotification, WatchEvent, WatchNotification, WatchOptions};
-pub use manager::{PluginLoadError, PluginManager, PluginUnloadError};
+pub use actor::ActorEvent;
+pub use actor::PluginManagerActor;
+#[cfg(feature = "async")]
+pub use async_manager::AsyncPluginManager;
+pub use bench::{CallBackend, CallBenchmark};
+pub use budget::ErrorBudget;
+pub use bus::{Backpressure, BusError, BusOptions, EventBus, EventCallback, SubscriptionId};
+pub use call::{CallOptions, DisabledPolicy, PluginCallError, DEFAULT_UNLOAD_TIMEOUT};
+pub use capability::{Capability, FilesystemService, NetworkService, ThreadService};
+pub use config::ConfigSource;
+pub use conflict::{ConflictPolicy, ConflictResolution, RegistrationConflict};
+pub use counters::{call_counters_fn, get_counters, get_counters_for, PluginCounters};
+#[doc(hidden)]
+pub use counters::{AtomicPluginCounters, CountersSource, Uncounted};
+pub use deps::DependencyError;
+#[cfg(feature = "inventory")]
+#[doc(hidden)]
+pub use describe::describe_json;
+#[doc(hidden)]
+pub use describe::{describe_json_with, PluginDescription};
+pub use describe::{MethodDescription, RegistrationDescription, TraitDescription};
+#[cfg(feature = "pinning")]
+pub use digest::{DigestMismatch, DigestPins, Sha256Digest};
+pub use discovery::DiscoveryPolicy;
+pub use dispatch::{DispatchFuture, DispatchProxy};
+#[doc(hidden)]
+pub use dynamic::{call_dynamic_json, DynamicRegistration};
+pub use events::ManagerEvent;
+#[cfg(feature = "fuzzing")]
+pub use fuzz::{fuzz_entries, FuzzViolation};
+pub use handle::{
+    GreeterProxy, PluginHandle, PluginId, PluginObject, PluginProxy, TransformerProxy,
+    WeakPluginHandle,
+};
+pub use health::{
+    HealthCheck, HealthCheckGuard, HealthCheckOptions, HealthStatus, QuarantineAction,
+};
+pub use host::{
+    default_host_context, export_string, HostAllocator, HostContext, HostServices, LogLevel,
+    LogRecord, PluginLogRecord,
+};
+#[doc(hidden)]
+pub use impls::ImplTable;
+pub use instances::PluginInstance;
+pub use intercept::{CallContext, CallInterceptor, CallOutcome, InterceptorId};
+#[cfg(feature = "isolation")]
+pub use isolated::{serve_isolated, IsolationOptions};
+pub use kv::{KvStore, MAX_KV_KEY_LEN};
+pub use lazy::IndexedPlugin;
+#[doc(hidden)]
+pub use leaks::{record_made, record_released, write_leak_report, Allocation};
+pub use leaks::{LeakCount, LeakReport};
+pub use loader::LoadFlags;
+#[cfg(feature = "log")]
+pub use log_bridge::PluginLogger;
+pub use manager::{
+    Deadline, DeferredUnload, PluginLoadError, PluginManager, PluginUnloadError, StagedId,
+    UnloadOutcome, UnloadReport,
+};
+#[cfg(feature = "watch")]
+pub use manager::{
+    DirNotification, ManagerNotification, WatchControl, WatchEvent, WatchGuard, WatchNotification,
+    WatchOptions,
+};
+pub use manifest::{sidecar_path, ManifestError, PluginManifest, MANIFEST_SUFFIX};
+pub use modules::{exports_symbol, ModuleLoader, ModulePlugin, ModuleRegistration};
+pub use persist::RestoredState;
+/// Names of the symbols plugins export, shared with the macros.
+pub use plugin_abi::symbols;
+#[cfg(feature = "isolation")]
+pub use probe::{serve_probe, ProbeFailure, ProbeOptions};
+pub use quarantine::{QuarantineOptions, QuarantinedArtifact};
+pub use query::PluginDescriptor;
+#[cfg(feature = "record")]
+pub use record::{replay_calls, CallRecorder, RecordedCall, ReplayedCall};
+#[cfg(feature = "registry")]
+pub use registry::{
+    host_target, DefaultFetcher, RegistryEntry, RegistryError, RegistryFetcher, RegistryIndex,
+    RegistryOptions,
+};
+#[cfg(feature = "remote")]
+pub use remote::serve_remote;
+#[doc(hidden)]
+pub use request::enter_request;
+pub use request::{with_current_request, RawRequestContext, RequestContext};
+pub use search_path::{PluginSearchPath, SearchPrecedence, ShadowedPlugin, PLUGIN_PATH_ENV};
+pub use services::{ServiceProvider, ServiceRegistry};
+#[cfg(any(feature = "isolation", feature = "remote"))]
+pub use shared_memory::SharedMemoryOptions;
+pub use shutdown::ShutdownSignal;
+#[cfg(feature = "signing")]
+pub use signing::{signature_path, SignatureError, SIGNATURE_SUFFIX};
+pub use split::{SplitStats, TrafficSplit, VersionStats};
+pub use stats::PluginStats;
+pub use strings::{OwnedStr, StrRef};
+pub use validate::{FileReport, ValidationProblem, ValidationReport};
 
 // A tiny loader helper that expects the plugin to export an extern "C" fn
-// named `plugin_register_Greeter_v1` returning *const PluginMetadata.
+// named `plugin_register_Greeter_v<N>` returning *const PluginMetadata.
+// Aggregated plugins receive the process-wide `default_host_context()`.
 pub fn load_greeter_from_lib(
     path: &std::path::Path,
 ) -> Result<(Library, *const RegistrationArray), String> {
     let lib = unsafe { Library::new(path) }.map_err(|e| e.to_string())?;
     unsafe {
         // Try the aggregated symbol first
-        let all_sym = lib.get::<unsafe extern "C" fn() -> *const RegistrationArray>(
-            b"plugin_register_all_Greeter_v1",
-        );
-        if let Ok(f_all) = all_sym {
-            let arr_ptr = f_all();
+        let all_sym =
+            find_versioned_symbol::<RegisterAllFn>(&lib, symbols::REGISTER_ALL, "Greeter");
+        if let Some((f_all, abi)) = all_sym {
+            let arr_ptr = call_register_all(*f_all, abi, default_host_context());
             if arr_ptr.is_null() {
-                return Err("plugin returned null registration array".to_string());
+                return Err(registration_error(&lib)
+                    .unwrap_or_else(|| "plugin returned null registration array".to_string()));
             }
             let arr = &*arr_ptr;
             if arr.count == 0 || arr.registrations.is_null() {
@@ -95,9 +726,12 @@ pub fn load_greeter_from_lib(
         }
 
         // Fallback: single registration symbol (erased pointer)
-        let symbol: libloading::Symbol<unsafe extern "C" fn() -> *const std::ffi::c_void> = lib
-            .get(b"plugin_register_Greeter_v1")
-            .map_err(|e| e.to_string())?;
+        let (symbol, _) = find_versioned_symbol::<unsafe extern "C" fn() -> *const c_void>(
+            &lib,
+            symbols::REGISTER,
+            "Greeter",
+        )
+        .ok_or("plugin exports no Greeter registration symbol")?;
         let reg_ptr = symbol();
         let reg = reg_ptr as *const GreeterRegistration;
         if reg.is_null() {
@@ -108,11 +742,7 @@ pub fn load_greeter_from_lib(
             let boxed_slice = erased.into_boxed_slice();
             let regs_ptr = Box::into_raw(boxed_slice) as *const *const c_void;
             // No factory pointer available for fallback; set factories to null.
-            let arr = Box::new(RegistrationArray {
-                count: 1,
-                registrations: regs_ptr,
-                factories: std::ptr::null(),
-            });
+            let arr = Box::new(RegistrationArray::new(1, regs_ptr, std::ptr::null()));
             let arr_ptr = Box::into_raw(arr);
             Ok((lib, arr_ptr))
         }
@@ -168,13 +798,16 @@ pub unsafe fn unload_greeter(
     // and is responsible for freeing them after calling any unregister helpers.
     if arr_ref.factories.is_null() {
         // Prefer plugin bulk unregister if present.
-        if let Ok(f_all_unreg) = lib.get::<unsafe extern "C" fn(*const RegistrationArray)>(
-            b"plugin_unregister_all_Greeter_v1",
-        ) {
-            f_all_unreg(arr_ptr);
-        } else if let Ok(fsym) = lib
-            .get::<unsafe extern "C" fn(*const std::ffi::c_void)>(b"plugin_unregister_Greeter_v1")
+        if let Some((f_all_unreg, _)) = find_versioned_symbol::<
+            unsafe extern "C" fn(*const RegistrationArray),
+        >(&lib, symbols::UNREGISTER_ALL, "Greeter")
         {
+            f_all_unreg(arr_ptr);
+        } else if let Some((fsym, _)) = find_versioned_symbol::<unsafe extern "C" fn(*const c_void)>(
+            &lib,
+            symbols::UNREGISTER,
+            "Greeter",
+        ) {
             for &r in regs_slice.iter() {
                 if !r.is_null() {
                     fsym(r);
@@ -193,8 +826,9 @@ pub unsafe fn unload_greeter(
 
     // Plugin-provided RegistrationArray: prefer plugin bulk-unregister helper,
     // otherwise deterministically invoke each registration's factory.unmaker.
-    if let Ok(f_all_unreg) = lib
-        .get::<unsafe extern "C" fn(*const RegistrationArray)>(b"plugin_unregister_all_Greeter_v1")
+    if let Some((f_all_unreg, _)) = find_versioned_symbol::<
+        unsafe extern "C" fn(*const RegistrationArray),
+    >(&lib, symbols::UNREGISTER_ALL, "Greeter")
     {
         f_all_unreg(arr_ptr);
         drop(lib);
@@ -211,9 +845,11 @@ pub unsafe fn unload_greeter(
         if !fac_ptr.is_null() {
             let fac_ref: &RegistrationFactory = &*fac_ptr;
             (fac_ref.unmaker)(r);
-        } else if let Ok(fsym) = lib
-            .get::<unsafe extern "C" fn(*const std::ffi::c_void)>(b"plugin_unregister_Greeter_v1")
-        {
+        } else if let Some((fsym, _)) = find_versioned_symbol::<unsafe extern "C" fn(*const c_void)>(
+            &lib,
+            symbols::UNREGISTER,
+            "Greeter",
+        ) {
             fsym(r);
         }
     }
@@ -222,23 +858,6 @@ pub unsafe fn unload_greeter(
     Ok(())
 }
 
-/// Helper to read the generated versioned unmaker counter for a trait from a
-/// loaded plugin `Library`.
-///
-/// The generated symbol name is `plugin_unmaker_counter_<Trait>_v1` and the
-/// symbol is an `extern "C" fn() -> usize` that returns the current counter
-/// value (atomic load). This helper constructs the symbol name, looks it up
-/// in the provided `lib`, calls it, and returns the value.
-pub fn get_unmaker_counter(lib: &Library, trait_name: &str) -> Result<u64, String> {
-    // Build null-terminated symbol name expected by libloading::Library::get
-    let sym = format!("plugin_unmaker_counter_{}_v1\0", trait_name);
-    unsafe {
-        let func: libloading::Symbol<unsafe extern "C" fn() -> u64> =
-            lib.get(sym.as_bytes()).map_err(|e| e.to_string())?;
-        Ok(func())
-    }
-}
-
 /// Typed identifier for known traits exposed by plugins.
 ///
 /// Prefer passing this enum to host helpers instead of raw strings so callers
@@ -246,6 +865,7 @@ pub fn get_unmaker_counter(lib: &Library, trait_name: &str) -> Result<u64, Strin
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum PluginTrait {
     Greeter,
+    Transformer,
 }
 
 impl PluginTrait {
@@ -253,51 +873,92 @@ impl PluginTrait {
     pub fn as_str(self) -> &'static str {
         match self {
             PluginTrait::Greeter => "Greeter",
+            PluginTrait::Transformer => "Transformer",
+        }
+    }
+
+    /// The trait's methods, as its vtable's `METHODS` lists them.
+    pub fn methods(self) -> &'static [MethodDescription] {
+        match self {
+            PluginTrait::Greeter => GreeterVTable::METHODS,
+            PluginTrait::Transformer => TransformerVTable::METHODS,
+        }
+    }
+
+    /// The trait whose `as_str` is `name`.
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Greeter" => Some(PluginTrait::Greeter),
+            "Transformer" => Some(PluginTrait::Transformer),
+            _ => None,
         }
     }
 
     /// Build the C-style null-terminated symbol name bytes expected by
-    /// `libloading::Library::get` for the generated unmaker counter getter.
+    /// `libloading::Library::get` for the generated unmaker counter getter,
+    /// `plugin_unmaker_counter_<Trait>_v1`. Plugins still export it, but at
+    /// their own ABI level only, and it returns just the released
+    /// registrations; see `counters_symbol_name` for the full counters.
     pub fn symbol_name_bytes(self) -> Vec<u8> {
-        format!("plugin_unmaker_counter_{}_v1\0", self.as_str()).into_bytes()
+        symbols::versioned_c(symbols::UNMAKER_COUNTER, self.as_str(), 1).into_bytes()
     }
-}
 
-/// Typed variant of `get_unmaker_counter` that accepts a `PluginTrait` enum
-/// instead of a raw string. This is safer for callers that work with a known
-/// set of traits at compile time.
-pub fn get_unmaker_counter_for(lib: &Library, trait_id: PluginTrait) -> Result<u64, String> {
-    let sym_bytes = trait_id.symbol_name_bytes();
-    unsafe {
-        let func: libloading::Symbol<unsafe extern "C" fn() -> u64> =
-            lib.get(&sym_bytes).map_err(|e| e.to_string())?;
-        Ok(func())
+    /// The nul-terminated name of the `plugin_counters_<Trait>_v<abi>`
+    /// getter a plugin registered at ABI level `abi` exports, returning its
+    /// `PluginCounters`.
+    pub fn counters_symbol_name(self, abi: u32) -> Vec<u8> {
+        symbols::versioned_c(symbols::COUNTERS, self.as_str(), abi).into_bytes()
     }
 }
 
-/// Call a raw unmaker counter getter function pointer and return its value.
-/// This is provided to make it easy to unit-test the calling convention and
-/// the helper logic without requiring a real dynamic library export.
-pub fn call_unmaker_getter_fn(func: unsafe extern "C" fn() -> u64) -> u64 {
-    unsafe { func() }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    // Export a test getter symbol from this test module so we can call
-    // `get_unmaker_counter` against the current process library.
-    #[no_mangle]
-    pub extern "C" fn plugin_unmaker_counter_TestTrait_v1() -> u64 {
-        42u64
+    #[test]
+    fn host_info_describes_this_host() {
+        let info = HostInfo::current();
+        assert_eq!(info.abi_version, HOST_ABI_VERSION);
+        assert_eq!(info.host_version(), env!("CARGO_PKG_VERSION"));
     }
 
     #[test]
-    fn get_unmaker_counter_calls_local_exported_getter() {
-        // Directly call the test getter via the helper to ensure the calling
-        // convention and return value are correct.
-        let val = call_unmaker_getter_fn(plugin_unmaker_counter_TestTrait_v1);
-        assert_eq!(val, 42u64);
+    fn names_the_counter_getters() {
+        assert_eq!(
+            PluginTrait::Greeter.symbol_name_bytes(),
+            b"plugin_unmaker_counter_Greeter_v1\0"
+        );
+        assert_eq!(
+            PluginTrait::Transformer.counters_symbol_name(4),
+            b"plugin_counters_Transformer_v4\0"
+        );
+    }
+
+    #[test]
+    fn register_all_gets_the_context_from_level_2_on() {
+        use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        static HOST: AtomicPtr<HostContext> = AtomicPtr::new(std::ptr::null_mut());
+        extern "C" fn register_v1() -> *const RegistrationArray {
+            CALLS.fetch_add(1, Ordering::SeqCst);
+            std::ptr::null()
+        }
+        extern "C" fn register_v2(host: *const HostContext) -> *const RegistrationArray {
+            HOST.store(host as *mut _, Ordering::SeqCst);
+            std::ptr::null()
+        }
+
+        let host = default_host_context();
+        unsafe {
+            call_register_all(register_v1, 1, host);
+            let v2 = std::mem::transmute::<
+                extern "C" fn(*const HostContext) -> *const RegistrationArray,
+                RegisterAllFn,
+            >(register_v2);
+            call_register_all(v2, HOST_CONTEXT_ABI, host);
+        }
+        assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+        assert!(std::ptr::eq(HOST.load(Ordering::SeqCst), host));
     }
 }
@@ -0,0 +1,163 @@
+//! Opening native libraries with the options the host picked for the
+//! platform loader.
+
+use crate::instrument::trace_event;
+use libloading::Library;
+use std::path::Path;
+
+/// How `PluginManager` asks the platform loader to open native libraries;
+/// see `PluginManager::set_load_flags`. The default matches
+/// `libloading::Library::new`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadFlags {
+    /// Unix: make the library's symbols available to libraries opened
+    /// after it (`RTLD_GLOBAL`) instead of keeping them to itself
+    /// (`RTLD_LOCAL`). Off by default.
+    pub global: bool,
+    /// Unix: resolve functions on first call (`RTLD_LAZY`) rather than all
+    /// at once when the library is opened (`RTLD_NOW`). With `false`, a
+    /// library with an undefined symbol fails to load instead of failing
+    /// later in a call. On by default.
+    pub lazy: bool,
+    /// Windows: also look for the library's own DLL dependencies in the
+    /// directory of the plugin artifact, and not the shadow copy's, while
+    /// it is loaded. Off by default. On Unix, link plugins with an
+    /// `$ORIGIN` rpath instead.
+    pub search_plugin_dir: bool,
+    /// Linux with glibc: open every library into a linker namespace of its
+    /// own (`dlmopen` with `LM_ID_NEWLM`), with its own copies of the
+    /// shared libraries it links, so two plugins built against conflicting
+    /// versions of one C library do not share its symbols or state. Such a
+    /// library cannot be opened with `global`. glibc allows only a handful
+    /// of namespaces per process; when no new one can be made, and on
+    /// other platforms, the library is opened the usual way, which
+    /// `PluginHandle::has_own_namespace` reports. Off by default.
+    pub own_namespace: bool,
+}
+
+impl Default for LoadFlags {
+    fn default() -> Self {
+        Self {
+            global: false,
+            lazy: true,
+            search_plugin_dir: false,
+            own_namespace: false,
+        }
+    }
+}
+
+/// Open the library at `open_path` with `flags`. `artifact` is the file it
+/// was copied from, or `open_path` itself. Returns the library and whether
+/// it has a linker namespace of its own.
+#[cfg(unix)]
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) unsafe fn open(
+    open_path: &Path,
+    artifact: &Path,
+    flags: LoadFlags,
+) -> Result<(Library, bool), String> {
+    use libloading::os::unix;
+    let resolve = if flags.lazy {
+        unix::RTLD_LAZY
+    } else {
+        unix::RTLD_NOW
+    };
+    let visibility = if flags.global {
+        unix::RTLD_GLOBAL
+    } else {
+        unix::RTLD_LOCAL
+    };
+    if flags.own_namespace {
+        #[cfg(all(target_os = "linux", target_env = "gnu"))]
+        match open_in_new_namespace(open_path, resolve | unix::RTLD_LOCAL) {
+            Ok(lib) => return Ok((lib, true)),
+            Err(e) => {
+                trace_event!(warn, path = %artifact.display(), error = %e, "no linker namespace of its own; opening in the shared one");
+            }
+        }
+        #[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+        trace_event!(debug, path = %artifact.display(), "linker namespaces are not supported here");
+    }
+    unix::Library::open(Some(open_path), resolve | visibility)
+        .map(|lib| (Library::from(lib), false))
+        .map_err(|e| e.to_string())
+}
+
+/// `dlmopen` the library at `path` into a new linker namespace.
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+unsafe fn open_in_new_namespace(path: &Path, flags: libc::c_int) -> Result<Library, String> {
+    use std::os::unix::ffi::OsStrExt;
+    let name = std::ffi::CString::new(path.as_os_str().as_bytes()).map_err(|e| e.to_string())?;
+    let handle = libc::dlmopen(libc::LM_ID_NEWLM, name.as_ptr(), flags);
+    if handle.is_null() {
+        let error = libc::dlerror();
+        return Err(if error.is_null() {
+            "dlmopen failed".to_string()
+        } else {
+            std::ffi::CStr::from_ptr(error)
+                .to_string_lossy()
+                .into_owned()
+        });
+    }
+    Ok(libloading::os::unix::Library::from_raw(handle).into())
+}
+
+/// Open the library at `open_path` with `flags`. `artifact` is the file it
+/// was copied from, or `open_path` itself. Windows has no linker
+/// namespaces, so the library never has one of its own.
+#[cfg(windows)]
+pub(crate) unsafe fn open(
+    open_path: &Path,
+    artifact: &Path,
+    flags: LoadFlags,
+) -> Result<(Library, bool), String> {
+    use libloading::os::windows;
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::System::LibraryLoader::{AddDllDirectory, RemoveDllDirectory};
+
+    if !flags.search_plugin_dir {
+        return Library::new(open_path)
+            .map(|lib| (lib, false))
+            .map_err(|e| e.to_string());
+    }
+    let absolute = |p: &Path| std::path::absolute(p).map_err(|e| e.to_string());
+    let (open_path, artifact) = (absolute(open_path)?, absolute(artifact)?);
+    let dir: Vec<u16> = artifact
+        .parent()
+        .unwrap_or(&artifact)
+        .as_os_str()
+        .encode_wide()
+        .chain(Some(0))
+        .collect();
+    let cookie = AddDllDirectory(dir.as_ptr());
+    if cookie.is_null() {
+        return Err(format!(
+            "cannot add {:?} to the DLL search path: {}",
+            artifact.parent(),
+            std::io::Error::last_os_error()
+        ));
+    }
+    let lib = windows::Library::load_with_flags(
+        &open_path,
+        windows::LOAD_LIBRARY_SEARCH_DEFAULT_DIRS
+            | windows::LOAD_LIBRARY_SEARCH_DLL_LOAD_DIR
+            | windows::LOAD_LIBRARY_SEARCH_USER_DIRS,
+    );
+    RemoveDllDirectory(cookie);
+    lib.map(|lib| (Library::from(lib), false))
+        .map_err(|e| e.to_string())
+}
+
+/// The host's own image, standing in for the library of registrations
+/// that do not come from one: linked and remote plugins.
+#[cfg(all(unix, any(feature = "static-plugins", feature = "remote")))]
+pub(crate) fn process_image() -> Result<Library, String> {
+    Ok(libloading::os::unix::Library::this().into())
+}
+
+#[cfg(all(windows, any(feature = "static-plugins", feature = "remote")))]
+pub(crate) fn process_image() -> Result<Library, String> {
+    libloading::os::windows::Library::this()
+        .map(Into::into)
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,88 @@
+use crate::{HostContext, LogLevel};
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+/// Plugin-side `log::Log` implementation that forwards records to the host
+/// through `HostContext::log_record`, tagged with the plugin's name.
+///
+/// Plugins normally don't use this directly: `#[plugin_logging]` declares a
+/// static `PluginLogger` and installs it when the host registers the plugin.
+pub struct PluginLogger {
+    name: &'static str,
+    host: AtomicPtr<HostContext>,
+}
+
+impl PluginLogger {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            host: AtomicPtr::new(std::ptr::null_mut()),
+        }
+    }
+
+    /// Point the logger at `host` and make it the plugin's global `log`
+    /// logger. Level filtering is left to the host, so every level is
+    /// forwarded. Calling this again only swaps the host context.
+    pub fn install(&'static self, host: *const HostContext) {
+        self.host.store(host as *mut _, Ordering::SeqCst);
+        if log::set_logger(self).is_ok() {
+            log::set_max_level(log::LevelFilter::Trace);
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl log::Log for PluginLogger {
+    fn enabled(&self, _metadata: &log::Metadata<'_>) -> bool {
+        !self.host.load(Ordering::SeqCst).is_null()
+    }
+
+    fn log(&self, record: &log::Record<'_>) {
+        let host = match unsafe { self.host.load(Ordering::SeqCst).as_ref() } {
+            Some(h) => h,
+            None => return,
+        };
+        host.log_record(
+            self.name,
+            record.target(),
+            LogLevel::from(record.level()),
+            &record.args().to_string(),
+        );
+    }
+
+    fn flush(&self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host::SharedHostContext;
+    use crate::HostServices;
+    use std::sync::{Arc, Mutex};
+
+    static LOGGER: PluginLogger = PluginLogger::new("bridge-test");
+
+    #[test]
+    fn log_macros_reach_the_host_with_plugin_name() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        let shared = SharedHostContext::new(HostServices::new().with_record_logger(move |r| {
+            sink.lock()
+                .unwrap()
+                .push((r.plugin.to_string(), r.level, r.message.to_string()))
+        }));
+        LOGGER.install(shared.as_ptr());
+
+        log::warn!("disk almost full");
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![(
+                "bridge-test".to_string(),
+                LogLevel::Warn,
+                "disk almost full".to_string()
+            )]
+        );
+    }
+}
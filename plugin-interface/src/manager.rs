@@ -1,186 +1,3847 @@
-use crate::{PluginTrait, RegistrationArray};
-use libloading::Library;
-use std::collections::HashSet;
-use std::path::Path;
+use crate::{
+    call_register_all, find_versioned_symbol, registration_error, symbols, DiscoveryPolicy,
+    Features, HostInfo, PluginTrait, RegisterAllFn, RegistrationArray, HOST_FEATURES,
+};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 #[cfg(feature = "watch")]
-use std::path::PathBuf;
-#[cfg(feature = "watch")]
-use std::sync::mpsc::{self, Receiver};
-use std::sync::{Arc, Weak};
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex, Weak};
 #[cfg(feature = "watch")]
 use std::thread;
-#[cfg(feature = "watch")]
 use std::time::Duration;
 
-use crate::handle::{unload_loaded_lib, LoadedLib, PluginHandle};
+use crate::bench::CallBenchmark;
+use crate::budget::{BudgetBinding, ErrorBudget, SharedBudget};
+use crate::bus::EventBus;
+use crate::call::{AssertSend, DisabledPolicy, PluginCallError, DEFAULT_UNLOAD_TIMEOUT};
+use crate::config::ConfigSource;
+use crate::conflict::{self, ConflictPolicy, ConflictResolution, Registered, RegistrationConflict};
+use crate::deps::{self, DependencyError};
+#[cfg(feature = "pinning")]
+use crate::digest::{DigestMismatch, DigestPins, Sha256Digest};
+use crate::events::{ManagerEvent, Subscribers};
+use crate::groups::PluginGroups;
+use crate::handle::{
+    check_layouts, check_registration, notify_loaded, transfer_state, unload_loaded_lib,
+    unmaker_count, LoadedLib, PluginHandle, PluginId, PluginObject,
+};
+use crate::health::{HealthCheck, HealthCheckOptions, HealthStatus, QuarantineAction};
+use crate::host::{HostServices, SharedHostContext};
+use crate::identity::FileKey;
+use crate::impls::ImplFilter;
+use crate::instrument::trace_event;
+use crate::intercept::{Binding, CallInterceptor, InterceptorId, Interceptors};
+#[cfg(feature = "isolation")]
+use crate::isolated::{IsolatedPlugin, IsolationOptions};
+use crate::kv;
+use crate::lazy::{self, IndexedPlugin};
+use crate::loader::{self, LoadFlags};
+use crate::manifest::{self, Candidate, ManifestError, PluginManifest};
+#[cfg(feature = "metrics")]
+use crate::metrics::{self, Series, WatchEventCounts};
+use crate::modules::{self, ModuleLoader};
+use crate::persist::{RestoredState, SavedPlugin, SavedState};
+#[cfg(feature = "isolation")]
+use crate::probe::{self, ProbeFailure, ProbeOptions};
+use crate::quarantine::{Quarantine, QuarantineOptions, QuarantinedArtifact};
+use crate::query::PluginDescriptor;
+#[cfg(feature = "registry")]
+use crate::registry::{self, RegistryError, RegistryOptions};
+#[cfg(feature = "remote")]
+use crate::remote;
+#[cfg(feature = "watch")]
+use crate::retry::LoadRetries;
+use crate::search_path::{self, PluginSearchPath, ShadowedPlugin};
+use crate::services::ServiceRegistry;
+#[cfg(feature = "watch")]
+use crate::settle::PendingFiles;
+use crate::shadow::ShadowCopy;
+#[cfg(any(feature = "isolation", feature = "remote"))]
+use crate::shared_memory::SharedMemoryOptions;
+use crate::shutdown::ShutdownSignal;
+#[cfg(feature = "signing")]
+use crate::signing::{SignatureError, TrustedKeys};
+use crate::split::TrafficSplit;
+#[cfg(feature = "static-plugins")]
+use crate::static_plugins;
+use crate::stats::{CallState, PluginStats};
+use crate::validate::{self, ValidationProblem, ValidationReport};
+#[cfg(feature = "wasm")]
+use crate::wasm::{self, WasmPlugin};
+#[cfg(feature = "watch")]
+use crate::watch_filter::PathFilter;
+use crate::GreeterProxy;
+
+/// Errors when loading plugins
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum PluginLoadError {
+    Io(std::io::Error),
+    Lib(String),
+    NoRegistrations,
+    /// A sidecar manifest could not be read or its requirements are not met.
+    Manifest {
+        path: PathBuf,
+        error: ManifestError,
+    },
+    /// A manifest dependency is missing, has the wrong version, or is cyclic.
+    Dependency(DependencyError),
+    /// Trusted keys are configured and the artifact is unsigned or its
+    /// signature does not verify.
+    #[cfg(feature = "signing")]
+    SignatureInvalid {
+        path: PathBuf,
+        error: SignatureError,
+    },
+    /// A registration name is already used by another loaded library and
+    /// the conflict policy is `ConflictPolicy::Error`.
+    Conflict(RegistrationConflict),
+    /// The artifact's SHA-256 digest does not match its pin, or it has no pin
+    /// while pins are required.
+    #[cfg(feature = "pinning")]
+    DigestMismatch(DigestMismatch),
+    /// No plugin with this name is loaded or indexed.
+    UnknownPlugin(String),
+    /// The file is not a dynamic library, for example a renamed text file
+    /// or an executable; `found` says what it is instead. Checked before
+    /// the library is opened.
+    NotADynamicLibrary {
+        path: PathBuf,
+        found: String,
+    },
+    /// The library was built for another platform, for example a 32-bit
+    /// DLL for a 64-bit host. Checked before the library is opened.
+    WrongArchitecture {
+        path: PathBuf,
+        expected: String,
+        found: String,
+    },
+    /// The registration array, a factory or a vtable the plugin returned is
+    /// smaller than its ABI level promises. Its registrations were released
+    /// without calling into them.
+    IncompatibleLayout {
+        path: PathBuf,
+        reason: String,
+    },
+    /// Registrations failed the library's self-test while
+    /// `PluginManager::set_self_test` is on. They were released without
+    /// `on_load`.
+    SelfTestFailed {
+        path: PathBuf,
+        failed: u32,
+    },
+    /// A file given to `PluginManager::restore_state` is not a saved state.
+    InvalidState {
+        path: PathBuf,
+        error: String,
+    },
+    /// `PluginManager::install_from_registry` found nothing to install.
+    #[cfg(feature = "registry")]
+    Registry(RegistryError),
+    /// With `PluginManager::set_strict` on, the library would have been
+    /// skipped: its register function returned null or no registrations,
+    /// it exports a register function without the matching unregister one,
+    /// its manifest lists the trait but it exports no register function for
+    /// it, or, for `load_plugins_filtered`, it lists no implementations.
+    Anomaly {
+        path: PathBuf,
+        reason: String,
+    },
+    /// The library's register function returned null and its
+    /// `plugin_register_error_v1` says why, for example because the host is
+    /// older than the plugin supports (see `HostVersion`).
+    Refused {
+        path: PathBuf,
+        reason: String,
+    },
+    /// The library's `plugin_handshake_v1` requires features the host does
+    /// not offer (see `PluginManager::set_host_features`). Nothing was
+    /// registered.
+    MissingFeatures {
+        path: PathBuf,
+        missing: Features,
+    },
+}
+
+/// Errors when unloading
+#[derive(Debug)]
+pub enum PluginUnloadError {
+    Lib(String),
+}
+
+/// What `PluginManager::unload_by_path_detailed` did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnloadOutcome {
+    /// The plugin was unloaded, or nothing was loaded from the path. Holds
+    /// the unmaker counter, if the library has one.
+    Unloaded(Option<u64>),
+    /// The library is closed, but handles or proxies still refer to it; the
+    /// last of them unloads it when dropped.
+    Deferred(DeferredUnload),
+}
+
+impl UnloadOutcome {
+    /// The value `unload_by_path` returns for this outcome.
+    pub fn counter(&self) -> Option<u64> {
+        match self {
+            UnloadOutcome::Unloaded(counter) => *counter,
+            UnloadOutcome::Deferred(_) => None,
+        }
+    }
+}
+
+/// Who kept a library loaded when `unload_by_path_detailed` was called.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeferredUnload {
+    pub path: PathBuf,
+    /// `PluginHandle::id` of the registration each remaining handle or
+    /// proxy refers to, one entry per handle or proxy, oldest first.
+    pub holders: Vec<PluginId>,
+    /// With the `holder-backtraces` feature, the backtrace captured when
+    /// each holder in `holders` was created, in the same order.
+    #[cfg(feature = "holder-backtraces")]
+    pub created_at: Vec<String>,
+}
+
+/// How long `PluginManager::unload_by_path_with` waits for the handles and
+/// proxies of a plugin to go away, and what it does if some remain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deadline {
+    grace: Duration,
+    force: bool,
+}
+
+impl Deadline {
+    /// Wait up to `grace`, then leave the unload to the last remaining
+    /// holder and report who they are.
+    pub fn after(grace: Duration) -> Self {
+        Self {
+            grace,
+            force: false,
+        }
+    }
+
+    /// Wait up to `grace`, then unload the library even though handles or
+    /// proxies still refer to it, so its file is released. From then on
+    /// their proxies fail with `PluginCallError::Stale` and
+    /// `PluginHandle::registration_name` returns `None`.
+    ///
+    /// # Safety
+    /// The library's code and data are unmapped under the remaining
+    /// holders. Anything else that still points into it, such as a string
+    /// or function pointer a plugin handed out, must not be used again.
+    pub unsafe fn force_after(grace: Duration) -> Self {
+        Self { grace, force: true }
+    }
+
+    pub fn grace(&self) -> Duration {
+        self.grace
+    }
+
+    /// True for deadlines made with `force_after`.
+    pub fn is_forced(&self) -> bool {
+        self.force
+    }
+}
+
+fn deferred_unload(lib: &LoadedLib) -> DeferredUnload {
+    DeferredUnload {
+        path: lib.path.clone(),
+        holders: lib.holders.ids(),
+        #[cfg(feature = "holder-backtraces")]
+        created_at: lib.holders.origins(),
+    }
+}
+
+/// Outcome of unloading one plugin in `PluginManager::unload_all`.
+#[derive(Debug)]
+pub struct UnloadReport {
+    pub path: PathBuf,
+    /// What `unload_by_path` returned for this plugin: the unmaker counter
+    /// if it was unloaded immediately, `None` if the unload was left to the
+    /// last handle or the plugin has no counter.
+    pub result: Result<Option<u64>, String>,
+}
+
+/// A plugin `PluginManager::stage` opened that calls are not routed to
+/// yet; hand it to `promote` or `discard_staged`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StagedId(u64);
+
+/// A plugin opened by `stage`, waiting for `promote`.
+struct Staged {
+    id: StagedId,
+    path: PathBuf,
+    trait_id: PluginTrait,
+    opened: Opened,
+}
+
+pub struct PluginManager {
+    // Weak refs to loaded libs; handles own the strong Arcs so unload can occur
+    libs: Vec<Weak<LoadedLib>>,
+    // the files we've already loaded, by identity, to avoid loading one
+    // twice through different paths; the value is the path it was loaded from
+    loaded_files: HashMap<FileKey, PathBuf>,
+    // copies passed over by the most recent load_plugins in favor of another
+    shadowed: Vec<ShadowedPlugin>,
+    // threads load_plugins opens libraries on; 1 loads them one by one
+    load_threads: usize,
+    // how long closing or unloading waits for calls into a library to return
+    unload_timeout: Duration,
+    // open native libraries from private copies so the artifacts stay unlocked
+    shadow_copies: bool,
+    // how the platform loader opens native libraries
+    load_flags: LoadFlags,
+    // run each library's self-test before using its registrations
+    self_test: bool,
+    // refuse libraries that would otherwise be skipped, see set_strict
+    strict: bool,
+    // offered to plugins in their handshake, see set_host_features
+    host_features: Features,
+    // registration names the load in progress makes, if it filters them
+    impl_filter: Option<ImplFilter>,
+    // loaders asked, in order, to open libraries built for another ABI
+    module_loaders: Vec<Arc<dyn ModuleLoader>>,
+    // which files load_plugins and index_plugins take for plugin libraries
+    discovery: DiscoveryPolicy,
+    // plugins recorded by index_plugins, opened on first lookup
+    indexed: Vec<IndexedPlugin>,
+    // handles load_plugin keeps, in load order; their position is the
+    // index the index-based helpers take
+    kept: Vec<(PathBuf, Vec<PluginHandle>)>,
+    // plugins stage opened and promote has not swapped in yet
+    staged: Vec<Staged>,
+    // the id the next staged plugin gets
+    next_staged: u64,
+    // requested by `shutdown`, or by the process's signals once
+    // `install_shutdown_handler` ran; stops the watchers started here
+    shutdown: ShutdownSignal,
+    // context handed to register functions of libraries loaded from now on
+    host: Arc<SharedHostContext>,
+    // registrations plugins can look up through `HostContext::get_service`
+    services: Arc<ServiceRegistry>,
+    // topics the host and plugins publish events to
+    bus: Arc<EventBus>,
+    // per-plugin configuration handed to libraries as they are registered
+    config: ConfigSource,
+    // what calls to registrations turned off with set_enabled do
+    disabled_policy: DisabledPolicy,
+    // failed calls after which proxies turn a registration off
+    error_budget: Arc<SharedBudget>,
+    // run around every proxy call into the libraries this manager loads
+    interceptors: Arc<Interceptors>,
+    // what loading a registration name already in use does
+    conflict_policy: ConflictPolicy,
+    // registration name conflicts seen but not yet taken
+    conflicts: Vec<RegistrationConflict>,
+    // whether different versions of a plugin load side by side
+    multi_version: bool,
+    // plugins the host put in groups, on top of their manifests' groups
+    groups: PluginGroups,
+    // when check_health quarantines a registration, and what it does then
+    health: HealthCheckOptions,
+    // unhealthy reports in a row, by registration
+    health_failures: HashMap<PluginId, u32>,
+    // watcher events produced so far, for render_metrics
+    #[cfg(feature = "metrics")]
+    watch_events: WatchEventCounts,
+    // files the watcher failed to load and tries again
+    #[cfg(feature = "watch")]
+    load_retries: LoadRetries,
+    // failure counts of artifacts and the ones set aside for failing
+    quarantine: Quarantine,
+    // plugins served by runner processes; proxies own the strong Arcs
+    #[cfg(feature = "isolation")]
+    isolated: Vec<Weak<IsolatedPlugin>>,
+    // how libraries are probed in a child process before they are opened
+    #[cfg(feature = "isolation")]
+    probe: Option<ProbeOptions>,
+    // libraries that failed their probe and were skipped, not yet taken
+    #[cfg(feature = "isolation")]
+    probe_failures: Vec<ProbeFailure>,
+    // shared region offered to runners and remote servers for large payloads
+    #[cfg(any(feature = "isolation", feature = "remote"))]
+    shared_memory: Option<SharedMemoryOptions>,
+    // instantiated WebAssembly modules; handles own the strong Arcs
+    #[cfg(feature = "wasm")]
+    wasm: Vec<Weak<WasmPlugin>>,
+    // keys artifact signatures are checked against; empty disables the check
+    #[cfg(feature = "signing")]
+    trusted_keys: TrustedKeys,
+    // where install_from_registry finds and installs plugins
+    #[cfg(feature = "registry")]
+    registry: Option<RegistryOptions>,
+    // expected artifact digests and the mismatches seen but not yet taken
+    #[cfg(feature = "pinning")]
+    digest_pins: DigestPins,
+    #[cfg(feature = "pinning")]
+    digest_mismatches: Vec<DigestMismatch>,
+    // receivers handed out by subscribe
+    subscribers: Subscribers,
+}
+
+impl Default for PluginManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for PluginManager {
+    /// Unloads everything still loaded, like `unload_all`. Libraries that
+    /// handles or proxies still use are unloaded when the last of them is
+    /// dropped.
+    fn drop(&mut self) {
+        #[allow(unused_variables)]
+        for report in self.unload_all() {
+            trace_event!(
+                debug,
+                path = %report.path.display(),
+                result = ?report.result,
+                "plugin unloaded with manager"
+            );
+        }
+    }
+}
+
+impl PluginManager {
+    /// Attempt to unload the library previously loaded from `path`.
+    /// If the manager is the only owner (strong_count == 1) this will
+    /// perform the unload immediately and return the plugin unmaker counter
+    /// if available. If there are other owners the manager will mark the
+    /// LoadedLib as closed so the final owner will perform the unload on Drop
+    /// and return None.
+    ///
+    /// Unloading is refused while another loaded plugin declares a manifest
+    /// dependency on this one; use `unload_with_dependents` to tear down the
+    /// dependents first. It also fails, leaving the library loaded, if proxy
+    /// calls into it are still running after the unload timeout (see
+    /// `set_unload_timeout`).
+    pub fn unload_by_path(&mut self, path: &std::path::Path) -> Result<Option<u64>, String> {
+        self.unload_by_path_detailed(path)
+            .map(|outcome| outcome.counter())
+    }
+
+    /// `unload_by_path`, but when handles or proxies keep the library
+    /// loaded, the outcome lists them (see `DeferredUnload`) so the
+    /// component holding on to the plugin can be found.
+    pub fn unload_by_path_detailed(&mut self, path: &Path) -> Result<UnloadOutcome, String> {
+        self.unload_by_path_with(path, Deadline::after(Duration::ZERO))
+    }
+
+    /// `unload_by_path_detailed`, giving handles and proxies that still
+    /// refer to the library until `deadline` to go away. The library is
+    /// closed first; if its last holder is dropped within the grace
+    /// period, for example a watchdog worker finishing an abandoned call,
+    /// it is unloaded here and the counter returned. Otherwise the outcome
+    /// names the remaining holders, or with `Deadline::force_after`, the
+    /// library is unloaded under them.
+    pub fn unload_by_path_with(
+        &mut self,
+        path: &Path,
+        deadline: Deadline,
+    ) -> Result<UnloadOutcome, String> {
+        self.kept.retain(|(kept, _)| kept != path);
+        let result = match self.unload_step(path) {
+            Ok(UnloadStep::Done(res)) => return Ok(UnloadOutcome::Unloaded(res)),
+            #[cfg(feature = "wasm")]
+            Ok(UnloadStep::Forgotten) => Ok(UnloadOutcome::Unloaded(None)),
+            Ok(UnloadStep::Now(loaded)) => {
+                let res = unload_loaded_lib(*loaded).map(unmaker_count);
+                trace_event!(info, path = %path.display(), result = ?res, "plugin unloaded");
+                res.map(UnloadOutcome::Unloaded)
+            }
+            // wait for calls in flight, then mark closed so the final
+            // owner will run unload on Drop
+            Ok(UnloadStep::Deferred(strong)) => strong.quiesce().map(drop).and_then(|()| {
+                self.close_deferred(path, &strong);
+                self.await_holders(strong, deadline)
+            }),
+            Err(e) => Err(e),
+        };
+        let summary = result
+            .as_ref()
+            .map(UnloadOutcome::counter)
+            .map_err(String::clone);
+        self.publish_unload(path, &summary);
+        result
+    }
+
+    /// Registration ids of the handles and proxies that keep the native
+    /// library loaded from `path` in memory, one entry per handle or proxy.
+    /// Includes libraries that are closed but wait for their last holder.
+    pub fn plugin_holders(&self, path: &Path) -> Vec<PluginId> {
+        self.libs
+            .iter()
+            .filter_map(|w| w.upgrade())
+            .filter(|l| l.path == path)
+            .flat_map(|l| l.holders.ids())
+            .collect()
+    }
+
+    /// Work out what unloading `path` takes, without blocking. Libraries
+    /// the manager alone holds are taken out of its bookkeeping here.
+    fn unload_step(&mut self, path: &Path) -> Result<UnloadStep, String> {
+        // Uses by libraries that are gone no longer keep their providers.
+        let loaded: Vec<PathBuf> = self
+            .libs
+            .iter()
+            .filter_map(|w| w.upgrade())
+            .filter(|l| !l.closed.load(Ordering::SeqCst))
+            .map(|l| l.path.clone())
+            .collect();
+        self.services
+            .retain_users(|user| loaded.iter().any(|p| p == user));
+        if let Some(dependent) = self.loaded_dependents(path).first() {
+            return Err(format!(
+                "plugin at {:?} is still required by {:?}",
+                path, dependent
+            ));
+        }
+        #[cfg(feature = "wasm")]
+        if let Some(pos) = self
+            .wasm
+            .iter()
+            .position(|w| w.upgrade().is_some_and(|p| p.path() == path))
+        {
+            // The instance is dropped with the last handle or proxy.
+            self.wasm.remove(pos);
+            self.forget_file(path);
+            trace_event!(info, path = %path.display(), "wasm plugin unloaded");
+            return Ok(UnloadStep::Forgotten);
+        }
+        let mut i = 0usize;
+        while i < self.libs.len() {
+            if let Some(strong) = self.libs[i].upgrade() {
+                // compare path
+                if strong.path == path {
+                    // if manager is the only owner, take it to unload now
+                    if Arc::strong_count(&strong) == 1 {
+                        // remove this weak entry
+                        self.libs.remove(i);
+                        self.forget_file(path);
+                        // Try to consume the Arc
+                        return Ok(match Arc::try_unwrap(strong) {
+                            Ok(loaded) => UnloadStep::Now(Box::new(loaded)),
+                            Err(_) => UnloadStep::Done(None),
+                        });
+                    } else {
+                        // keep weak entry around
+                        return Ok(UnloadStep::Deferred(strong));
+                    }
+                } else {
+                    i += 1;
+                }
+            } else {
+                // dead weak ref; remove
+                self.libs.remove(i);
+            }
+        }
+        Ok(UnloadStep::Done(None))
+    }
+
+    /// Wait for the holders of the closed library `strong` until
+    /// `deadline`, then unload it if they are gone or the deadline forces
+    /// it.
+    fn await_holders(
+        &mut self,
+        strong: Arc<LoadedLib>,
+        deadline: Deadline,
+    ) -> Result<UnloadOutcome, String> {
+        let until = std::time::Instant::now() + deadline.grace;
+        // the manager only keeps weak references; `strong` is ours
+        while Arc::strong_count(&strong) > 1 {
+            let now = std::time::Instant::now();
+            if now >= until {
+                break;
+            }
+            std::thread::sleep((until - now).min(Duration::from_millis(10)));
+        }
+        let strong = match Arc::try_unwrap(strong) {
+            Ok(loaded) => {
+                self.libs.retain(|w| w.strong_count() > 0);
+                trace_event!(info, path = %loaded.path.display(), "plugin unloaded after grace period");
+                let res = unload_loaded_lib(loaded).map(unmaker_count);
+                return res.map(UnloadOutcome::Unloaded);
+            }
+            Err(strong) => strong,
+        };
+        if !deadline.force {
+            return Ok(UnloadOutcome::Deferred(deferred_unload(&strong)));
+        }
+        // SAFETY: `Deadline::force_after` is unsafe; its caller vouched for
+        // the remaining holders.
+        let res = unsafe { strong.force_unload() };
+        if res.is_ok() {
+            self.libs
+                .retain(|w| w.upgrade().is_some_and(|l| !Arc::ptr_eq(&l, &strong)));
+        }
+        trace_event!(
+            warn,
+            path = %strong.path.display(),
+            holders = strong.holders.ids().len(),
+            result = ?res,
+            "plugin unloaded by force"
+        );
+        res.map(UnloadOutcome::Unloaded)
+    }
+
+    /// Whether the file at `path` is loaded, through `path` or any other
+    /// path to it. A loaded path counts even if the file there has since
+    /// been replaced.
+    fn is_loaded(&self, path: &Path) -> bool {
+        self.loaded_files.values().any(|p| p == path)
+            || self.loaded_files.contains_key(&FileKey::of(path))
+    }
+
+    /// Stop counting the file loaded from `path` as loaded.
+    fn forget_file(&mut self, path: &Path) {
+        self.loaded_files.retain(|_, p| p != path);
+    }
+
+    /// Finish a deferred unload once calls in flight have returned: the
+    /// last handle or proxy to go unloads the library.
+    fn close_deferred(&mut self, path: &Path, strong: &Arc<LoadedLib>) {
+        strong
+            .closed
+            .store(true, std::sync::atomic::Ordering::SeqCst);
+        self.forget_file(path);
+        trace_event!(
+            info,
+            path = %path.display(),
+            owners = Arc::strong_count(strong) - 1,
+            "plugin unload deferred to last handle"
+        );
+    }
+
+    /// A receiver of every `ManagerEvent` from now on: plugins loaded,
+    /// unloaded or reloaded, and failures doing so. Each call adds a
+    /// subscriber that gets its own copy of every event, so several
+    /// subsystems can observe the manager independently. A subscriber whose
+    /// receiver is dropped is forgotten at the next event.
+    pub fn subscribe(&mut self) -> Receiver<ManagerEvent> {
+        self.subscribers.subscribe()
+    }
+
+    /// Tell the subscribers how unloading `path` went.
+    fn publish_unload(&mut self, path: &Path, result: &Result<Option<u64>, String>) {
+        self.subscribers.send(match result {
+            Ok(counter) => ManagerEvent::Unloaded {
+                path: path.to_path_buf(),
+                counter: *counter,
+            },
+            Err(e) => ManagerEvent::Error {
+                path: Some(path.to_path_buf()),
+                error: e.clone(),
+            },
+        });
+    }
+
+    /// Tell the subscribers about a failed load or reload before returning
+    /// it. Lookups of unknown plugins tried nothing and are not reported.
+    fn publish_err<T>(
+        &mut self,
+        path: Option<&Path>,
+        result: Result<T, PluginLoadError>,
+    ) -> Result<T, PluginLoadError> {
+        match &result {
+            Err(PluginLoadError::UnknownPlugin(_)) | Ok(_) => {}
+            Err(e) => self.subscribers.send(ManagerEvent::Error {
+                path: path.map(Path::to_path_buf),
+                error: format!("{:?}", e),
+            }),
+        }
+        result
+    }
+
+    /// Unload the library at `path` together with every loaded plugin that
+    /// (transitively) depends on it. Dependents are unloaded in reverse load
+    /// order before `path` itself; the result lists each unloaded path with
+    /// the value `unload_by_path` returned for it. Fails if no plugin is
+    /// loaded from `path`.
+    pub fn unload_with_dependents(
+        &mut self,
+        path: &Path,
+    ) -> Result<Vec<(PathBuf, Option<u64>)>, String> {
+        if !self.loaded_files.values().any(|p| p == path) {
+            return Err(format!("no plugin is loaded from {:?}", path));
+        }
+        let mut doomed: Vec<PathBuf> = vec![path.to_path_buf()];
+        let mut i = 0;
+        while i < doomed.len() {
+            for dependent in self.loaded_dependents(&doomed[i]) {
+                if !doomed.contains(&dependent) {
+                    doomed.push(dependent);
+                }
+            }
+            i += 1;
+        }
+
+        // Reverse load order: later-loaded libraries go first.
+        let load_order: Vec<PathBuf> = self
+            .libs
+            .iter()
+            .filter_map(|w| w.upgrade())
+            .map(|l| l.path.clone())
+            .collect();
+        doomed.sort_by_key(|p| std::cmp::Reverse(load_order.iter().position(|l| l == p)));
+
+        let mut report = Vec::new();
+        for p in doomed {
+            let counter = self.unload_by_path(&p)?;
+            report.push((p, counter));
+        }
+        Ok(report)
+    }
+
+    /// Replace the plugin loaded from `path` with the artifact now at `path`
+    /// and return handles for the new registrations.
+    ///
+    /// The new artifact is opened and registered before anything is
+    /// swapped, so a failed reload leaves the old plugin loaded. A native
+    /// library is opened from a private copy, because the loader would
+    /// otherwise return the old image while anything still maps it. Once
+    /// the new plugin is in place, the old one is closed after its calls in
+    /// flight return. Its handles and proxies go stale, and calls through
+    /// them fail with `PluginCallError::Stale` instead of running the old
+    /// code. The old library is unloaded when the last of them is dropped.
+    /// Before the swap, each old registration's `save_state` is handed to
+    /// the new registration with the same name through `restore_state`. A
+    /// sidecar manifest next to `path` is read again; without one, the old
+    /// plugin's manifest is kept.
+    pub fn reload_by_path(&mut self, path: &Path) -> Result<Vec<PluginHandle>, PluginLoadError> {
+        let result = self.swap_reloaded(path);
+        if let Ok(handles) = &result {
+            for (kept, old) in &mut self.kept {
+                if kept == path {
+                    *old = handles.clone();
+                }
+            }
+        }
+        self.publish_err(Some(path), result)
+    }
+
+    fn swap_reloaded(&mut self, path: &Path) -> Result<Vec<PluginHandle>, PluginLoadError> {
+        let old = self
+            .libs
+            .iter()
+            .filter_map(|w| w.upgrade())
+            .find(|l| l.path == path && !l.closed.load(Ordering::SeqCst));
+        let Some(old) = old else {
+            #[cfg(feature = "wasm")]
+            if let Some(old) = self
+                .wasm
+                .iter()
+                .filter_map(|w| w.upgrade())
+                .find(|p| p.path() == path)
+            {
+                let manifest = match read_sidecar(path, old.trait_id)? {
+                    Some(m) => Some(m),
+                    None => old.manifest.clone(),
+                };
+                let Some(host) = self.prepare_candidate(path, manifest.as_ref(), old.trait_id)?
+                else {
+                    return Err(PluginLoadError::NoRegistrations);
+                };
+                let opened = open_candidate(
+                    path,
+                    manifest,
+                    old.trait_id,
+                    host,
+                    OpenOptions {
+                        shadow: false,
+                        ..self.open_options()
+                    },
+                )?
+                .keep_alive(old.dependencies.clone());
+                old.calls.mark_stale();
+                self.wasm
+                    .retain(|w| !std::ptr::eq(w.as_ptr(), Arc::as_ptr(&old)));
+                let mut handles = Vec::new();
+                self.record_opened(
+                    path.to_path_buf(),
+                    opened,
+                    old.trait_id,
+                    &mut handles,
+                    ManagerEvent::Reloaded,
+                    true,
+                )?;
+                trace_event!(info, path = %path.display(), "wasm plugin reloaded");
+                return Ok(handles);
+            }
+            return Err(PluginLoadError::UnknownPlugin(path.display().to_string()));
+        };
+
+        let manifest = match read_sidecar(path, old.trait_id)? {
+            Some(m) => Some(m),
+            None => old.manifest.clone(),
+        };
+        let Some(host) = self.prepare_candidate(path, manifest.as_ref(), old.trait_id)? else {
+            return Err(PluginLoadError::NoRegistrations);
+        };
+        let opened = open_candidate(
+            path,
+            manifest,
+            old.trait_id,
+            host,
+            OpenOptions {
+                shadow: true,
+                impl_filter: old.impl_filter.clone(),
+                ..self.open_options()
+            },
+        )?
+        .keep_alive(old.dependencies.clone());
+        if matches!(opened, Opened::Nothing) {
+            return Err(PluginLoadError::NoRegistrations);
+        }
+
+        // Swap: no call may still be running in the old library once it is
+        // flagged stale. On failure the new library is dropped and unloaded.
+        {
+            let _quiet = old.quiesce().map_err(PluginLoadError::Lib)?;
+            if let Opened::Native(new) = &opened {
+                transfer_state(&old, new);
+            }
+            old.calls.mark_stale();
+            old.closed.store(true, Ordering::SeqCst);
+        }
+        self.libs
+            .retain(|w| !std::ptr::eq(w.as_ptr(), Arc::as_ptr(&old)));
+        let trait_id = old.trait_id;
+        drop(old);
+        let mut handles = Vec::new();
+        self.record_opened(
+            path.to_path_buf(),
+            opened,
+            trait_id,
+            &mut handles,
+            ManagerEvent::Reloaded,
+            true,
+        )?;
+        trace_event!(info, path = %path.display(), "plugin reloaded");
+        Ok(handles)
+    }
+
+    /// Open and validate the artifact at `path`, which provides
+    /// `trait_id`, without routing calls to it: lookups such as
+    /// `find_by_name` and `plugin_handles` keep answering with the plugin
+    /// loaded now until `promote` swaps the new one in. The artifact goes
+    /// through the same checks as `load_library`, its register function and
+    /// `on_load` hooks run, and its dependencies must be loaded, so a
+    /// broken upgrade fails here while the old version keeps serving.
+    pub fn stage(
+        &mut self,
+        path: &Path,
+        trait_id: PluginTrait,
+    ) -> Result<StagedId, PluginLoadError> {
+        let result = self.open_staged(path, trait_id);
+        self.publish_err(Some(path), result)
+    }
+
+    fn open_staged(
+        &mut self,
+        path: &Path,
+        trait_id: PluginTrait,
+    ) -> Result<StagedId, PluginLoadError> {
+        let manifest = read_sidecar(path, trait_id)?;
+        let candidate = manifest::Candidate {
+            path: path.to_path_buf(),
+            manifest,
+        };
+        let candidate = deps::order_candidates(vec![candidate], &self.loaded_manifest_versions())
+            .map_err(PluginLoadError::Dependency)?
+            .remove(0);
+        let Some(host) = self.prepare_candidate(path, candidate.manifest.as_ref(), trait_id)?
+        else {
+            return Err(PluginLoadError::NoRegistrations);
+        };
+        // The artifact may have replaced the loaded one in place; the loader
+        // would hand back the old image for the same path.
+        let shadow = self.shadow_copies || self.is_loaded(path);
+        let opened = open_candidate(
+            path,
+            candidate.manifest,
+            trait_id,
+            host,
+            OpenOptions {
+                shadow,
+                ..self.open_options()
+            },
+        )
+        .inspect_err(|e| self.artifact_failed(path, format!("{:?}", e)))?;
+        if matches!(opened, Opened::Nothing) {
+            return Err(PluginLoadError::NoRegistrations);
+        }
+        let id = StagedId(self.next_staged);
+        self.next_staged += 1;
+        self.staged.push(Staged {
+            id,
+            path: path.to_path_buf(),
+            trait_id,
+            opened,
+        });
+        trace_event!(info, path = %path.display(), "plugin staged");
+        Ok(id)
+    }
+
+    /// Route calls to the plugin `stage` opened as `id` instead of the
+    /// loaded plugins of the same name and trait, and return handles for
+    /// its registrations.
+    ///
+    /// The swap works like `reload_by_path`: once the calls in flight into
+    /// the old plugins have returned, their state is handed to the new
+    /// registrations with the same names and they go stale, so nothing
+    /// runs the old code afterwards. They are unloaded when their last
+    /// handle or proxy is dropped. Indices `load_plugin` handed out for
+    /// them now refer to the new plugin. If the calls do not return within
+    /// the unload timeout, nothing is swapped and the plugin stays staged.
+    /// With no plugin of that name loaded, the staged one is simply loaded.
+    pub fn promote(&mut self, id: StagedId) -> Result<Vec<PluginHandle>, PluginLoadError> {
+        let path = self
+            .staged
+            .iter()
+            .find(|s| s.id == id)
+            .map(|s| s.path.clone());
+        let result = self.swap_staged(id);
+        self.publish_err(path.as_deref(), result)
+    }
+
+    fn swap_staged(&mut self, id: StagedId) -> Result<Vec<PluginHandle>, PluginLoadError> {
+        let Some(pos) = self.staged.iter().position(|s| s.id == id) else {
+            return Err(PluginLoadError::UnknownPlugin(format!("{:?}", id)));
+        };
+        let staged = &self.staged[pos];
+        let name = |path: &Path, manifest: Option<&PluginManifest>| {
+            search_path::plugin_name(path, manifest.map(|m| m.name.as_str()))
+        };
+        let new_name = match &staged.opened {
+            Opened::Native(lib) => name(&lib.path, lib.manifest.as_ref()),
+            #[cfg(feature = "wasm")]
+            Opened::Wasm(plugin) => name(plugin.path(), plugin.manifest.as_ref()),
+            Opened::Nothing => unreachable!("stage keeps only plugins with registrations"),
+        };
+        let old: Vec<Arc<LoadedLib>> = self
+            .libs
+            .iter()
+            .filter_map(|w| w.upgrade())
+            .filter(|l| !l.closed.load(Ordering::SeqCst) && l.trait_id == staged.trait_id)
+            .filter(|l| name(&l.path, l.manifest.as_ref()) == new_name)
+            .collect();
+
+        // Swap: as in `swap_reloaded`, no call may still be running in an
+        // old library once it is flagged stale.
+        {
+            let _quiet = old
+                .iter()
+                .map(|l| l.quiesce())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(PluginLoadError::Lib)?;
+            for l in &old {
+                if let Opened::Native(new) = &staged.opened {
+                    transfer_state(l, new);
+                }
+                l.calls.mark_stale();
+                l.closed.store(true, Ordering::SeqCst);
+            }
+        }
+        #[allow(unused_mut)]
+        let mut replaced: Vec<PathBuf> = old.iter().map(|l| l.path.clone()).collect();
+        self.libs
+            .retain(|w| !old.iter().any(|l| std::ptr::eq(w.as_ptr(), Arc::as_ptr(l))));
+        drop(old);
+        #[cfg(feature = "wasm")]
+        self.wasm.retain(|w| match w.upgrade() {
+            Some(p)
+                if p.trait_id == staged.trait_id
+                    && name(p.path(), p.manifest.as_ref()) == new_name =>
+            {
+                p.calls.mark_stale();
+                replaced.push(p.path().to_path_buf());
+                false
+            }
+            _ => true,
+        });
+        for path in &replaced {
+            self.forget_file(path);
+        }
+
+        let staged = self.staged.remove(pos);
+        let mut handles = Vec::new();
+        let (event, reloaded): (fn(PluginDescriptor) -> ManagerEvent, bool) = if replaced.is_empty()
+        {
+            (ManagerEvent::Loaded, false)
+        } else {
+            (ManagerEvent::Reloaded, true)
+        };
+        self.record_opened(
+            staged.path.clone(),
+            staged.opened,
+            staged.trait_id,
+            &mut handles,
+            event,
+            reloaded,
+        )?;
+        for (kept, old) in &mut self.kept {
+            if replaced.contains(kept) {
+                *kept = staged.path.clone();
+                *old = handles.clone();
+            }
+        }
+        trace_event!(info, path = %staged.path.display(), replaced = replaced.len(), "staged plugin promoted");
+        Ok(handles)
+    }
+
+    /// Drop the plugin `stage` opened as `id` without promoting it, which
+    /// unloads it again. False if there is no such staged plugin.
+    pub fn discard_staged(&mut self, id: StagedId) -> bool {
+        let before = self.staged.len();
+        self.staged.retain(|s| s.id != id);
+        self.staged.len() != before
+    }
+
+    /// Route `fraction` of the calls for the registration called `name`
+    /// to the plugin `stage` opened as `id`, and the rest to the one
+    /// `find_by_name` returns, so the two can be compared through
+    /// `TrafficSplit::stats` before `promote`. The staged plugin stays
+    /// staged; the split only holds handles to both versions.
+    pub fn split_traffic(
+        &self,
+        id: StagedId,
+        name: &str,
+        fraction: f64,
+    ) -> Result<TrafficSplit, PluginLoadError> {
+        let Some(staged) = self.staged.iter().find(|s| s.id == id) else {
+            return Err(PluginLoadError::UnknownPlugin(format!("{:?}", id)));
+        };
+        let current = self
+            .find_by_name(name)
+            .ok_or_else(|| PluginLoadError::UnknownPlugin(name.to_string()))?;
+        let candidates: Vec<PluginHandle> = match &staged.opened {
+            Opened::Nothing => Vec::new(),
+            Opened::Native(lib) => (0..unsafe { (*lib.arr_ptr).count })
+                .map(|idx| PluginHandle::new(lib.clone(), idx, staged.trait_id))
+                .collect(),
+            #[cfg(feature = "wasm")]
+            Opened::Wasm(plugin) => (0..plugin.count())
+                .map(|idx| PluginHandle::wasm(plugin.clone(), idx, staged.trait_id))
+                .collect(),
+        };
+        let candidate = candidates
+            .into_iter()
+            .find(|h| h.registration_name().as_deref() == current.registration_name().as_deref())
+            .ok_or_else(|| PluginLoadError::UnknownPlugin(format!("{} in {:?}", name, id)))?;
+        Ok(TrafficSplit::new(
+            name.to_string(),
+            current,
+            candidate,
+            fraction,
+        ))
+    }
+
+    /// Unload every plugin this manager loaded that is still loaded.
+    ///
+    /// Plugins go in reverse load order, except that a plugin is never
+    /// unloaded before the loaded plugins that depend on it. Each plugin is
+    /// unloaded like `unload_by_path` does, so libraries that handles or
+    /// proxies still use are closed and unloaded by their last owner. The
+    /// report lists every plugin in the order it was unloaded. Indexed
+    /// plugins stay indexed and are opened again on their next lookup.
+    pub fn unload_all(&mut self) -> Vec<UnloadReport> {
+        self.unload_where(|_| true)
+    }
+
+    /// Let SIGINT and SIGTERM (ctrl-c on Windows) request shutdown of this
+    /// manager instead of ending the process. The returned signal reports
+    /// the request; `run_until_shutdown` waits for it and then shuts down.
+    /// Watchers this manager started stop on their own once it arrives.
+    pub fn install_shutdown_handler(&self) -> std::io::Result<ShutdownSignal> {
+        self.shutdown.listen()?;
+        Ok(self.shutdown.clone())
+    }
+
+    /// The signal `shutdown` raises, and the process's signals once
+    /// `install_shutdown_handler` ran. A host can `trigger` it from
+    /// another thread to end `run_until_shutdown`.
+    pub fn shutdown_signal(&self) -> ShutdownSignal {
+        self.shutdown.clone()
+    }
+
+    /// Tear everything down in order: stop the watchers this manager
+    /// started, drop the plugins `stage` opened, then `unload_all`, which
+    /// waits for the calls in flight into each plugin, runs its `on_unload`
+    /// hooks and unloads dependents before their dependencies. Watchers
+    /// started after this stop at once.
+    pub fn shutdown(&mut self) -> Vec<UnloadReport> {
+        self.shutdown.trigger();
+        self.staged.clear();
+        trace_event!(info, "plugin manager shutting down");
+        self.unload_all()
+    }
+
+    /// Install the shutdown handler, block until SIGINT, SIGTERM or
+    /// `trigger` on `shutdown_signal` requests shutdown, then `shutdown`.
+    /// For hosts whose main thread has nothing else to do while other
+    /// threads use the plugins.
+    pub fn run_until_shutdown(&mut self) -> std::io::Result<Vec<UnloadReport>> {
+        self.install_shutdown_handler()?.wait();
+        Ok(self.shutdown())
+    }
+
+    /// `unload_all` for the plugins `select` picks.
+    fn unload_where(&mut self, select: impl Fn(Owner<'_>) -> bool) -> Vec<UnloadReport> {
+        let mut report = Vec::new();
+        #[cfg(feature = "wasm")]
+        {
+            let loaded: Vec<PathBuf> = self
+                .wasm
+                .iter()
+                .filter_map(|w| w.upgrade())
+                .filter(|p| select((p.path(), p.manifest.as_ref())))
+                .map(|p| p.path().to_path_buf())
+                .collect();
+            for path in loaded.into_iter().rev() {
+                let result = self.unload_by_path(&path);
+                report.push(UnloadReport { path, result });
+            }
+        }
+
+        let mut remaining: Vec<PathBuf> = self
+            .libs
+            .iter()
+            .filter_map(|w| w.upgrade())
+            .filter(|l| !l.closed.load(Ordering::SeqCst))
+            .filter(|l| select((&l.path, l.manifest.as_ref())))
+            .map(|l| l.path.clone())
+            .collect();
+        while !remaining.is_empty() {
+            // The latest-loaded plugin nothing else still depends on; a
+            // dependency cycle falls back to the latest one and reports
+            // the refusal.
+            let pos = remaining
+                .iter()
+                .rposition(|p| self.loaded_dependents(p).is_empty())
+                .unwrap_or(remaining.len() - 1);
+            let path = remaining.remove(pos);
+            let result = self.unload_by_path(&path);
+            report.push(UnloadReport { path, result });
+        }
+        self.libs.retain(|w| w.strong_count() > 0);
+        report
+    }
+
+    /// Time `iterations` calls of `handle`'s registration through each way
+    /// the host can call it (see `CallBackend`), so regressions in the
+    /// generated wrappers and the cost of each marshalling layer show up.
+    /// Greeters are called with `name`, transformers with `transform`.
+    /// Fails with the first error a call returns.
+    pub fn bench_call(
+        &self,
+        handle: &PluginHandle,
+        iterations: u32,
+    ) -> Result<CallBenchmark, PluginCallError> {
+        crate::bench::bench_call(handle, iterations)
+    }
+
+    /// Call statistics for every registration of the libraries this manager
+    /// loaded that are still alive, in load order.
+    pub fn stats(&self) -> Vec<PluginStats> {
+        #[allow(unused_mut)]
+        let mut stats: Vec<PluginStats> = self
+            .libs
+            .iter()
+            .filter_map(|w| w.upgrade())
+            .flat_map(|l| {
+                l.calls.snapshots(&l.path, |index| {
+                    PluginId::for_registration(l.arr_ptr, index)
+                })
+            })
+            .collect();
+        #[cfg(feature = "isolation")]
+        for p in self.isolated.iter().filter_map(|w| w.upgrade()) {
+            // Isolated plugins have no registration array in this process;
+            // their shared state is just as unique a key.
+            let key = Arc::as_ptr(&p) as *const RegistrationArray;
+            stats.extend(
+                p.calls
+                    .snapshots(p.path(), |index| PluginId::for_registration(key, index)),
+            );
+        }
+        #[cfg(feature = "wasm")]
+        for p in self.wasm.iter().filter_map(|w| w.upgrade()) {
+            let key = Arc::as_ptr(&p) as *const RegistrationArray;
+            stats.extend(
+                p.calls
+                    .snapshots(p.path(), |index| PluginId::for_registration(key, index)),
+            );
+        }
+        stats
+    }
+
+    /// Turn the registration `id` off or back on without unloading it.
+    /// Proxy calls to a disabled registration never reach the plugin; they
+    /// fail with `PluginCallError::Disabled` or return nothing, per
+    /// `set_disabled_policy`. Returns false if no loaded registration has
+    /// that id.
+    pub fn set_enabled(&self, id: PluginId, enabled: bool) -> bool {
+        let policy = (!enabled).then_some(self.disabled_policy);
+        self.with_call_state(id, |calls, index| calls.set_disabled(index, policy))
+            .unwrap_or(false)
+    }
+
+    /// Whether the registration `id` is enabled, or `None` if no loaded
+    /// registration has that id.
+    pub fn is_enabled(&self, id: PluginId) -> Option<bool> {
+        self.with_call_state(id, |calls, index| calls.disabled(index).is_none())
+    }
+
+    /// Choose what calls to disabled registrations do, including those
+    /// already disabled. Defaults to `DisabledPolicy::Error`.
+    pub fn set_disabled_policy(&mut self, policy: DisabledPolicy) {
+        self.disabled_policy = policy;
+        self.error_budget.set(self.error_budget.get(), policy);
+        self.for_each_call_state(|_, _, calls, index| {
+            if calls.disabled(index).is_some() {
+                calls.set_disabled(index, Some(policy));
+            }
+        });
+    }
+
+    /// Run `interceptor` around every proxy call into the plugins this
+    /// manager loaded or loads, after the interceptors added before it;
+    /// see `CallInterceptor`. Calls through proxies made earlier are
+    /// intercepted too.
+    pub fn add_interceptor(
+        &mut self,
+        interceptor: impl CallInterceptor + 'static,
+    ) -> InterceptorId {
+        self.interceptors.add(Arc::new(interceptor))
+    }
+
+    /// Stop running the interceptor `id`. Returns false if it was already
+    /// removed.
+    pub fn remove_interceptor(&mut self, id: InterceptorId) -> bool {
+        self.interceptors.remove(id)
+    }
+
+    /// Choose what loading a library does when one of its registrations
+    /// has the name of a registration another loaded library provides for
+    /// the same trait. Registrations turned off are not considered.
+    /// Defaults to `ConflictPolicy::KeepAll`.
+    pub fn set_conflict_policy(&mut self, policy: ConflictPolicy) {
+        self.conflict_policy = policy;
+    }
+
+    /// Let different versions of a plugin be loaded side by side, for
+    /// example to move callers from 1.x to 2.x gradually. `load_plugins`
+    /// then tells copies apart by manifest name and version, so a 2.0.0
+    /// copy is loaded next to a loaded 1.2.0 instead of being shadowed by
+    /// it, and their registrations do not conflict with each other. Look a
+    /// version up with `find_by_name("name@1.2.0")` or `"name@^2"`. Off by
+    /// default.
+    pub fn set_multi_version(&mut self, enabled: bool) {
+        self.multi_version = enabled;
+    }
+
+    /// Registration name conflicts found by loads since the last call,
+    /// oldest first, with what the conflict policy did about each.
+    pub fn take_conflicts(&mut self) -> Vec<RegistrationConflict> {
+        std::mem::take(&mut self.conflicts)
+    }
+
+    /// Put the plugin named `plugin` in `group`, on top of the groups its
+    /// manifest lists. `plugin` is matched like a `ConfigSource` entry:
+    /// against the manifest name, the file name, or the file name without
+    /// the platform's prefix and extension.
+    pub fn add_to_group(&mut self, group: impl Into<String>, plugin: impl Into<String>) {
+        self.groups.assign(group.into(), plugin.into());
+    }
+
+    /// `load_plugins` for the plugins on `search_path` in `group`. Their
+    /// dependencies outside the group must already be loaded.
+    pub fn load_group(
+        &mut self,
+        search_path: impl Into<PluginSearchPath>,
+        trait_id: PluginTrait,
+        group: &str,
+    ) -> Result<Vec<PluginHandle>, PluginLoadError> {
+        let policy = self.discovery.clone();
+        let groups = self.groups.clone();
+        let keep = |c: &Candidate| groups.contains(group, &c.path, c.manifest.as_ref());
+        self.load_plugins_where(search_path.into(), trait_id, &policy, &keep)
+    }
+
+    /// `unload_all` for the loaded plugins in `group`. A plugin that
+    /// plugins outside the group still depend on is not unloaded; its
+    /// entry in the report holds the refusal.
+    pub fn unload_group(&mut self, group: &str) -> Vec<UnloadReport> {
+        let groups = self.groups.clone();
+        self.unload_where(|(path, manifest)| groups.contains(group, path, manifest))
+    }
+
+    /// Turn every registration of the loaded plugins in `group` back on.
+    /// Returns how many registrations that covered.
+    pub fn enable_group(&self, group: &str) -> usize {
+        self.set_group_disabled(group, None)
+    }
+
+    /// Turn every registration of the loaded plugins in `group` off, like
+    /// `set_enabled` does. Returns how many registrations that covered.
+    pub fn disable_group(&self, group: &str) -> usize {
+        self.set_group_disabled(group, Some(self.disabled_policy))
+    }
+
+    fn set_group_disabled(&self, group: &str, policy: Option<DisabledPolicy>) -> usize {
+        let mut count = 0;
+        self.for_each_call_state(|(path, manifest), _, calls, index| {
+            if self.groups.contains(group, path, manifest) {
+                calls.set_disabled(index, policy);
+                count += 1;
+            }
+        });
+        count
+    }
+
+    /// Turn off registrations whose proxy calls fail
+    /// `ErrorBudget::max_errors` times within `ErrorBudget::window`, as
+    /// `set_enabled` does, or stop counting failures with `None`. A failed
+    /// call is one that returns an error after reaching the plugin, such as
+    /// a caught panic. Subscribers get a `ManagerEvent::ErrorBudgetExceeded`
+    /// for each registration turned off; turning it back on with
+    /// `set_enabled` gives it a full budget again. Applies to libraries
+    /// already loaded too. Off by default.
+    pub fn set_error_budget(&mut self, budget: Option<ErrorBudget>) {
+        self.error_budget.set(budget, self.disabled_policy);
+    }
+
+    /// The budget set by `set_error_budget`, if any.
+    pub fn error_budget(&self) -> Option<ErrorBudget> {
+        self.error_budget.get()
+    }
+
+    /// Choose when `check_health` quarantines a registration and what it
+    /// does to it, and how often the heartbeat of
+    /// `PluginManagerActor::start_health_checks` runs. Failures counted so
+    /// far are kept.
+    pub fn set_health_checks(&mut self, options: HealthCheckOptions) {
+        self.health = options;
+    }
+
+    /// Ask every enabled registration of the loaded libraries for its
+    /// health. One that reports `HealthStatus::Unhealthy`
+    /// `HealthCheckOptions::max_failures` times in a row is quarantined:
+    /// turned off like `set_enabled` does and, with
+    /// `QuarantineAction::Unload`, its library unloaded too. Subscribers
+    /// get a `ManagerEvent::Quarantined` for it. WebAssembly modules and
+    /// plugins in runner processes are not asked.
+    pub fn check_health(&mut self) -> Vec<HealthCheck> {
+        let mut checks = Vec::new();
+        for l in self.libs.iter().filter_map(|w| w.upgrade()) {
+            if l.closed.load(Ordering::SeqCst) || l.is_unloaded() {
+                continue;
+            }
+            for index in 0..l.calls.registrations() {
+                if l.calls.disabled(index).is_some() {
+                    continue;
+                }
+                let handle = PluginHandle::new(l.clone(), index, l.trait_id);
+                let Ok(status) = handle.health() else {
+                    continue;
+                };
+                checks.push(HealthCheck {
+                    id: handle.id(),
+                    path: l.path.clone(),
+                    index,
+                    status,
+                    failures: 0,
+                    quarantined: false,
+                });
+            }
+        }
+        // Registrations not asked this round start over when they are.
+        let mut failures = std::mem::take(&mut self.health_failures);
+        for check in &mut checks {
+            let count = failures.remove(&check.id).unwrap_or(0);
+            check.failures = if check.status == HealthStatus::Unhealthy {
+                count + 1
+            } else {
+                0
+            };
+            self.health_failures.insert(check.id, check.failures);
+        }
+
+        let HealthCheckOptions {
+            max_failures,
+            action,
+            ..
+        } = self.health;
+        let mut unload = Vec::new();
+        for check in &mut checks {
+            if max_failures == 0 || check.failures < max_failures {
+                continue;
+            }
+            self.set_enabled(check.id, false);
+            self.health_failures.remove(&check.id);
+            check.quarantined = true;
+            trace_event!(
+                warn,
+                path = %check.path.display(),
+                index = check.index,
+                failures = check.failures,
+                "plugin quarantined after failed health checks"
+            );
+            self.subscribers.send(ManagerEvent::Quarantined {
+                id: check.id,
+                path: check.path.clone(),
+                failures: check.failures,
+                action,
+            });
+            if action == QuarantineAction::Unload && !unload.contains(&check.path) {
+                unload.push(check.path.clone());
+            }
+        }
+        for path in unload {
+            // A refusal is reported to the subscribers; the registration
+            // stays disabled.
+            let _ = self.unload_by_path(&path);
+        }
+        checks
+    }
+
+    fn with_call_state<R>(
+        &self,
+        id: PluginId,
+        f: impl FnOnce(&CallState, usize) -> R,
+    ) -> Option<R> {
+        let mut f = Some(f);
+        let mut result = None;
+        self.for_each_call_state(|_, other, calls, index| {
+            if other == id {
+                if let Some(f) = f.take() {
+                    result = Some(f(calls, index));
+                }
+            }
+        });
+        result
+    }
+
+    /// Call `f` with the library, id, call state and index of every
+    /// registration of every loaded library, module and isolated plugin.
+    fn for_each_call_state(&self, mut f: impl FnMut(Owner<'_>, PluginId, &CallState, usize)) {
+        for l in self.libs.iter().filter_map(|w| w.upgrade()) {
+            let owner = (l.path.as_path(), l.manifest.as_ref());
+            for index in 0..l.calls.registrations() {
+                let id = PluginId::for_registration(l.arr_ptr, index);
+                f(owner, id, &l.calls, index);
+            }
+        }
+        #[cfg(feature = "isolation")]
+        for p in self.isolated.iter().filter_map(|w| w.upgrade()) {
+            let key = Arc::as_ptr(&p) as *const RegistrationArray;
+            for index in 0..p.calls.registrations() {
+                let id = PluginId::for_registration(key, index);
+                f((p.path(), None), id, &p.calls, index);
+            }
+        }
+        #[cfg(feature = "wasm")]
+        for p in self.wasm.iter().filter_map(|w| w.upgrade()) {
+            let key = Arc::as_ptr(&p) as *const RegistrationArray;
+            let owner = (p.path(), p.manifest.as_ref());
+            for index in 0..p.calls.registrations() {
+                let id = PluginId::for_registration(key, index);
+                f(owner, id, &p.calls, index);
+            }
+        }
+    }
+
+    /// The manager's statistics in the Prometheus text exposition format,
+    /// for a host's `/metrics` endpoint: the number of loaded plugins
+    /// (`plugin_manager_loaded_plugins`), per-registration call, error and
+    /// timeout counters and a latency histogram labelled with the plugin
+    /// name, library path and registration index (`plugin_calls_total`,
+    /// `plugin_call_errors_total`, `plugin_call_timeouts_total`,
+    /// `plugin_call_duration_seconds`), and the watcher's events by kind
+    /// (`plugin_watch_events_total`).
+    #[cfg(feature = "metrics")]
+    pub fn render_metrics(&self) -> String {
+        let mut series = Vec::new();
+        let mut loaded = std::collections::HashSet::new();
+        self.for_each_call_state(|(path, manifest), id, calls, index| {
+            loaded.insert(path.to_path_buf());
+            let Some(counters) = calls.counters.get(index) else {
+                return;
+            };
+            series.push(Series {
+                plugin: search_path::plugin_name(path, manifest.map(|m| m.name.as_str())),
+                stats: counters.snapshot(id, path.to_path_buf(), index),
+                buckets: counters.latency_buckets(),
+            });
+        });
+        metrics::render(loaded.len(), &series, &self.watch_events)
+    }
+
+    /// Paths of loaded libraries with a watchdog call that exceeded its
+    /// timeout and has not returned yet. Callers can use this to quarantine
+    /// or unload misbehaving plugins.
+    pub fn stuck_plugins(&self) -> Vec<PathBuf> {
+        #[allow(unused_mut)]
+        let mut stuck = self
+            .libs
+            .iter()
+            .filter_map(|w| w.upgrade())
+            .filter(|l| l.is_stuck())
+            .map(|l| l.path.clone())
+            .collect::<Vec<_>>();
+        #[cfg(feature = "isolation")]
+        stuck.extend(
+            self.isolated
+                .iter()
+                .filter_map(|w| w.upgrade())
+                .filter(|p| p.calls.is_stuck())
+                .map(|p| p.path().to_path_buf()),
+        );
+        #[cfg(feature = "wasm")]
+        stuck.extend(
+            self.wasm
+                .iter()
+                .filter_map(|w| w.upgrade())
+                .filter(|p| p.calls.is_stuck())
+                .map(|p| p.path().to_path_buf()),
+        );
+        stuck
+    }
+
+    /// Names of loaded plugins that were discovered through a manifest, with
+    /// the versions loaded of each.
+    fn loaded_manifest_versions(&self) -> HashMap<String, Vec<String>> {
+        let mut manifests: Vec<&PluginManifest> = Vec::new();
+        let libs: Vec<Arc<LoadedLib>> = self
+            .libs
+            .iter()
+            .filter_map(|w| w.upgrade())
+            .filter(|l| !l.closed.load(std::sync::atomic::Ordering::SeqCst))
+            .collect();
+        manifests.extend(libs.iter().filter_map(|l| l.manifest.as_ref()));
+        #[cfg(feature = "wasm")]
+        let wasm: Vec<Arc<WasmPlugin>> = self.wasm.iter().filter_map(|w| w.upgrade()).collect();
+        #[cfg(feature = "wasm")]
+        manifests.extend(wasm.iter().filter_map(|p| p.manifest.as_ref()));
+        let mut versions: HashMap<String, Vec<String>> = HashMap::new();
+        for m in manifests {
+            versions
+                .entry(m.name.clone())
+                .or_default()
+                .push(m.version.clone());
+        }
+        versions
+    }
+
+    /// Path of each loaded plugin keyed by the name search paths
+    /// deduplicate on, or with `versioned` by `name@version` (see
+    /// `search_path::plugin_key`).
+    fn loaded_plugin_paths(&self, versioned: bool) -> HashMap<String, PathBuf> {
+        #[allow(unused_mut)]
+        let mut paths: HashMap<String, PathBuf> = self
+            .libs
+            .iter()
+            .filter_map(|w| w.upgrade())
+            .filter(|l| !l.closed.load(std::sync::atomic::Ordering::SeqCst))
+            .map(|l| {
+                let key = search_path::plugin_key(&l.path, l.manifest.as_ref(), versioned);
+                (key, l.path.clone())
+            })
+            .collect();
+        #[cfg(feature = "wasm")]
+        paths.extend(self.wasm.iter().filter_map(|w| w.upgrade()).map(|p| {
+            let key = search_path::plugin_key(p.path(), p.manifest.as_ref(), versioned);
+            (key, p.path().to_path_buf())
+        }));
+        paths
+    }
+
+    /// Copies of plugins the most recent `load_plugins` call found but did
+    /// not load because another copy of the same plugin won or was already
+    /// loaded.
+    pub fn shadowed_plugins(&self) -> &[ShadowedPlugin] {
+        &self.shadowed
+    }
+
+    /// Paths of loaded plugins whose manifest depends on the plugin at
+    /// `path`, followed by those that looked up one of its services. With
+    /// several versions of the plugin loaded, a dependent only counts if
+    /// this version satisfies it and no other loaded version does.
+    fn loaded_dependents(&self, path: &Path) -> Vec<PathBuf> {
+        let loaded: Vec<Arc<LoadedLib>> = self
+            .libs
+            .iter()
+            .filter_map(|w| w.upgrade())
+            .filter(|l| !l.closed.load(std::sync::atomic::Ordering::SeqCst))
+            .collect();
+        let mut dependents: Vec<PathBuf> = match loaded
+            .iter()
+            .find(|l| l.path == path)
+            .and_then(|l| l.manifest.as_ref())
+        {
+            Some(m) => {
+                let others: Vec<&str> = loaded
+                    .iter()
+                    .filter(|l| l.path != path)
+                    .filter_map(|l| l.manifest.as_ref())
+                    .filter(|o| o.name == m.name)
+                    .map(|o| o.version.as_str())
+                    .collect();
+                let needs_this = |req: &str| {
+                    others.is_empty()
+                        || (deps::version_satisfies(&m.version, req)
+                            && !others.iter().any(|v| deps::version_satisfies(v, req)))
+                };
+                loaded
+                    .iter()
+                    .filter(|l| {
+                        l.manifest
+                            .as_ref()
+                            .and_then(|d| d.dependencies.get(&m.name))
+                            .is_some_and(|req| needs_this(req))
+                    })
+                    .map(|l| l.path.clone())
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+        for user in self.services.users_of(path) {
+            if loaded.iter().any(|l| l.path == user) && !dependents.contains(&user) {
+                dependents.push(user);
+            }
+        }
+        dependents
+    }
+
+    /// The services this manager's plugins provide to each other through
+    /// `HostContext::get_service`, and who uses them.
+    pub fn services(&self) -> &ServiceRegistry {
+        &self.services
+    }
+
+    /// The topics this manager's host and plugins publish events to and
+    /// subscribe to; see `EventBus`.
+    pub fn event_bus(&self) -> &EventBus {
+        &self.bus
+    }
+}
+
+impl PluginManager {
+    pub fn new() -> Self {
+        Self::with_host_services(HostServices::new())
+    }
+
+    /// Create a manager whose plugins log through and read configuration
+    /// from `services`.
+    pub fn with_host_services(services: HostServices) -> Self {
+        let registry = Arc::new(ServiceRegistry::default());
+        let bus = Arc::new(EventBus::default());
+        let subscribers = Subscribers::default();
+        Self {
+            libs: Vec::new(),
+            loaded_files: HashMap::new(),
+            shadowed: Vec::new(),
+            load_threads: 1,
+            unload_timeout: DEFAULT_UNLOAD_TIMEOUT,
+            shadow_copies: cfg!(windows),
+            load_flags: LoadFlags::default(),
+            self_test: false,
+            strict: false,
+            host_features: HOST_FEATURES,
+            impl_filter: None,
+            module_loaders: Vec::new(),
+            discovery: DiscoveryPolicy::default(),
+            indexed: Vec::new(),
+            kept: Vec::new(),
+            staged: Vec::new(),
+            next_staged: 0,
+            shutdown: ShutdownSignal::new(),
+            host: SharedHostContext::new(services.with_registry(&registry).with_event_bus(&bus)),
+            services: registry,
+            bus,
+            config: ConfigSource::default(),
+            disabled_policy: DisabledPolicy::default(),
+            error_budget: Arc::new(SharedBudget::new(subscribers.clone())),
+            interceptors: Arc::default(),
+            conflict_policy: ConflictPolicy::default(),
+            conflicts: Vec::new(),
+            multi_version: false,
+            groups: PluginGroups::default(),
+            health: HealthCheckOptions::default(),
+            health_failures: HashMap::new(),
+            #[cfg(feature = "metrics")]
+            watch_events: WatchEventCounts::default(),
+            #[cfg(feature = "watch")]
+            load_retries: LoadRetries::default(),
+            quarantine: Quarantine::default(),
+            #[cfg(feature = "isolation")]
+            isolated: Vec::new(),
+            #[cfg(feature = "isolation")]
+            probe: None,
+            #[cfg(feature = "isolation")]
+            probe_failures: Vec::new(),
+            #[cfg(any(feature = "isolation", feature = "remote"))]
+            shared_memory: None,
+            #[cfg(feature = "wasm")]
+            wasm: Vec::new(),
+            #[cfg(feature = "signing")]
+            trusted_keys: TrustedKeys::default(),
+            #[cfg(feature = "registry")]
+            registry: None,
+            #[cfg(feature = "pinning")]
+            digest_pins: DigestPins::default(),
+            #[cfg(feature = "pinning")]
+            digest_mismatches: Vec::new(),
+            subscribers,
+        }
+    }
+
+    /// Open up to `threads` libraries at once in `load_plugins`; `0` uses
+    /// `std::thread::available_parallelism`. The default of 1 opens them one
+    /// by one.
+    ///
+    /// Plugins are opened concurrently only within a dependency wave, so a
+    /// plugin's dependencies are always registered before it. Handles are
+    /// returned in the same order as with sequential loading.
+    pub fn set_load_parallelism(&mut self, threads: usize) {
+        self.load_threads = match threads {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        };
+    }
+
+    /// How long unloading or closing a library waits for proxy calls into
+    /// it to return before giving up with an error; the default is
+    /// `DEFAULT_UNLOAD_TIMEOUT`. Applies to libraries already loaded too.
+    pub fn set_unload_timeout(&mut self, timeout: Duration) {
+        self.unload_timeout = timeout;
+        for lib in self.libs.iter().filter_map(|w| w.upgrade()) {
+            lib.guard.set_timeout(timeout);
+        }
+    }
+
+    /// Open native libraries from a private copy in the temporary directory
+    /// instead of the artifact itself. The copy is removed once the library
+    /// is unloaded. Windows keeps a loaded DLL's file locked, so without
+    /// copies a rebuild into a watched directory fails; the option is on by
+    /// default there and off elsewhere. `reload_by_path` always uses a copy.
+    pub fn set_shadow_copies(&mut self, enabled: bool) {
+        self.shadow_copies = enabled;
+    }
+
+    /// Run the `plugin_selftest_<Trait>_v<N>` function of libraries loaded
+    /// from now on right after their register function, and refuse them
+    /// with `PluginLoadError::SelfTestFailed` if a registration fails it.
+    /// `#[plugin_aggregates]` generates the function: it makes a fresh
+    /// instance of every registration, asks its `health` hook and releases
+    /// it again. Libraries without the function load as before. Off by
+    /// default.
+    pub fn set_self_test(&mut self, enabled: bool) {
+        self.self_test = enabled;
+    }
+
+    /// Refuse libraries loaded from now on with `PluginLoadError::Anomaly`
+    /// where they would otherwise be skipped without a word: a register
+    /// function returning null or an empty array, a register function
+    /// exported without its unregister counterpart, or a manifest listing
+    /// the trait for a library that does not register it. The error names
+    /// the file, so it fails the batch like any other load error and
+    /// reaches watcher callbacks as a load error. Off by default.
+    pub fn set_strict(&mut self, enabled: bool) {
+        self.strict = enabled;
+    }
+
+    /// Offer `features` to libraries loaded from now on, instead of
+    /// `HOST_FEATURES`. Libraries exporting `plugin_handshake_v1` (see
+    /// `#[plugin_aggregates]`) are told what is offered before they
+    /// register, and refused with `PluginLoadError::MissingFeatures` if they
+    /// require something else. It only changes what plugins are told: the
+    /// host context they get is the same.
+    pub fn set_host_features(&mut self, features: Features) {
+        self.host_features = features;
+    }
+
+    pub fn host_features(&self) -> Features {
+        self.host_features
+    }
+
+    /// Open native libraries loaded from now on with `flags`: symbol
+    /// visibility and resolution on Unix, DLL search directories on
+    /// Windows. `reload_by_path` uses the flags in effect when it runs.
+    pub fn set_load_flags(&mut self, flags: LoadFlags) {
+        self.load_flags = flags;
+    }
+
+    pub fn load_flags(&self) -> LoadFlags {
+        self.load_flags
+    }
+
+    /// Let `loader` open the libraries it `accepts` from now on, such as
+    /// plugins built for another ABI; see `ModuleLoader`. Loaders added
+    /// earlier are asked first. Libraries no loader accepts are opened as
+    /// usual.
+    pub fn add_module_loader(&mut self, loader: impl ModuleLoader + 'static) {
+        self.module_loaders.push(Arc::new(loader));
+    }
+
+    /// How `open_candidate` should open libraries for this manager.
+    fn open_options(&self) -> OpenOptions {
+        OpenOptions {
+            shadow: self.shadow_copies,
+            flags: self.load_flags,
+            module_loaders: self.module_loaders.clone(),
+            self_test: self.self_test,
+            strict: self.strict,
+            host_features: self.host_features,
+            impl_filter: self.impl_filter.clone(),
+        }
+    }
+
+    /// Which files `load_plugins`, `index_plugins` and `validate` take for
+    /// plugin libraries; by default, those with the platform's extension.
+    /// The watchers use `WatchOptions::discovery` instead.
+    pub fn set_discovery_policy(&mut self, policy: DiscoveryPolicy) {
+        self.discovery = policy;
+    }
+
+    pub fn discovery_policy(&self) -> &DiscoveryPolicy {
+        &self.discovery
+    }
+
+    /// Replace the host services. Only libraries loaded afterwards see the
+    /// new services; already-loaded libraries keep the context they got.
+    pub fn set_host_services(&mut self, services: HostServices) {
+        self.host = SharedHostContext::new(
+            services
+                .with_registry(&self.services)
+                .with_event_bus(&self.bus),
+        );
+    }
+
+    /// Accept artifacts signed with the ed25519 `public_key`. Once any key is
+    /// trusted, every library or module this manager loads must have a
+    /// detached signature (see `signature_path`) from one of the trusted
+    /// keys, or loading fails with `PluginLoadError::SignatureInvalid`.
+    #[cfg(feature = "signing")]
+    pub fn trust_key(&mut self, public_key: &[u8; 32]) -> Result<(), SignatureError> {
+        self.trusted_keys.add(public_key)
+    }
+
+    /// Install plugins with `install_from_registry` from the registry
+    /// `options` describe, or stop with `None`.
+    #[cfg(feature = "registry")]
+    pub fn set_registry(&mut self, options: Option<RegistryOptions>) {
+        self.registry = options;
+    }
+
+    /// Install the newest version of the plugin `name` matching
+    /// `version_req` (a semver requirement such as `^1.2`) for this host's
+    /// target from the registry set with `set_registry`, and load it for
+    /// `trait_id`. The artifact is downloaded into the registry directory,
+    /// unless a copy with the indexed digest is there already, and must
+    /// match that digest and, while any key is trusted, carry a signature
+    /// from a trusted key. Its signature is installed next to it, so later
+    /// `load_plugins` calls on the directory accept it too.
+    #[cfg(feature = "registry")]
+    pub fn install_from_registry(
+        &mut self,
+        name: &str,
+        version_req: &str,
+        trait_id: PluginTrait,
+    ) -> Result<Vec<PluginHandle>, PluginLoadError> {
+        let installed = match &self.registry {
+            Some(opts) => registry::install(opts, &self.trusted_keys, name, version_req),
+            None => Err(PluginLoadError::Registry(RegistryError::NotConfigured)),
+        };
+        let path = self.publish_err(None, installed)?;
+        self.load_library(&path, trait_id)
+    }
+
+    #[cfg(feature = "signing")]
+    fn check_signature(&self, path: &Path) -> Result<(), PluginLoadError> {
+        self.trusted_keys.verify(path).map_err(|error| {
+            trace_event!(warn, path = %path.display(), error = %error, "rejected plugin signature");
+            PluginLoadError::SignatureInvalid {
+                path: path.to_path_buf(),
+                error,
+            }
+        })
+    }
+
+    /// Check artifacts against `pins` before they are opened. Replaces any
+    /// pins set before; libraries already loaded are not re-checked.
+    #[cfg(feature = "pinning")]
+    pub fn set_digest_pins(&mut self, pins: DigestPins) {
+        self.digest_pins = pins;
+    }
+
+    /// Artifacts that failed their digest check since the last call, oldest
+    /// first. The watcher reports them as `ManagerNotification::DigestMismatch`
+    /// when it processes notifications.
+    #[cfg(feature = "pinning")]
+    pub fn take_digest_mismatches(&mut self) -> Vec<DigestMismatch> {
+        std::mem::take(&mut self.digest_mismatches)
+    }
+
+    /// Probe every native library in a child process (see `ProbeOptions`)
+    /// before opening it in this one, or stop probing with `None`. A
+    /// library whose probe crashes, fails or times out is skipped and
+    /// recorded for `take_probe_failures`. Off by default.
+    #[cfg(feature = "isolation")]
+    pub fn set_probe(&mut self, options: Option<ProbeOptions>) {
+        self.probe = options;
+    }
+
+    /// Offer plugins loaded from now on with `load_isolated` or
+    /// `connect_remote` a shared-memory region for large string arguments
+    /// and results, or stop with `None`. Each runner or connection gets its
+    /// own region of `capacity` bytes; a peer that cannot map it, such as
+    /// a server on another machine, gets every payload inline instead. Off
+    /// by default.
+    #[cfg(any(feature = "isolation", feature = "remote"))]
+    pub fn set_shared_memory(&mut self, options: Option<SharedMemoryOptions>) {
+        self.shared_memory = options;
+    }
+
+    /// Libraries skipped because their probe failed since the last call,
+    /// oldest first. The watcher reports them as
+    /// `ManagerNotification::ProbeFailed` when it processes notifications.
+    #[cfg(feature = "isolation")]
+    pub fn take_probe_failures(&mut self) -> Vec<ProbeFailure> {
+        std::mem::take(&mut self.probe_failures)
+    }
+
+    /// Probe `path` if probing is on. Returns false when the artifact
+    /// should be skipped.
+    #[cfg(feature = "isolation")]
+    fn check_probe(&mut self, path: &Path, trait_id: PluginTrait) -> bool {
+        let Some(opts) = &self.probe else {
+            return true;
+        };
+        let Err(reason) = probe::probe(opts, path, trait_id) else {
+            return true;
+        };
+        trace_event!(warn, path = %path.display(), reason = %reason, "plugin failed its probe");
+        self.artifact_failed(path, reason.clone());
+        self.probe_failures.push(ProbeFailure {
+            path: path.to_path_buf(),
+            reason,
+        });
+        false
+    }
+
+    /// Quarantine artifacts that fail validation or loading
+    /// `max_failures` times in a row (see `QuarantineOptions`), or stop
+    /// counting failures with `None`. Scans skip quarantined artifacts, and
+    /// subscribers get a `ManagerEvent::ArtifactQuarantined` for each. Off
+    /// by default.
+    pub fn set_quarantine(&mut self, options: Option<QuarantineOptions>) {
+        self.quarantine.set_options(options);
+    }
+
+    /// Artifacts quarantined so far, oldest first.
+    pub fn quarantined_artifacts(&self) -> &[QuarantinedArtifact] {
+        self.quarantine.list()
+    }
+
+    /// Take `path` off the quarantine list so scans consider it again. An
+    /// artifact that was moved aside has to be moved back as well.
+    pub fn release_quarantined(&mut self, path: &Path) -> Option<QuarantinedArtifact> {
+        self.quarantine.release(path)
+    }
+
+    /// Count a failed validation or load of the artifact at `path`.
+    fn artifact_failed(&mut self, path: &Path, reason: String) {
+        let Some(artifact) = self.quarantine.failed(path, reason) else {
+            return;
+        };
+        trace_event!(
+            warn,
+            path = %path.display(),
+            failures = artifact.failures,
+            reason = %artifact.reason,
+            "plugin artifact quarantined"
+        );
+        self.subscribers
+            .send(ManagerEvent::ArtifactQuarantined(artifact));
+    }
+
+    /// Hash `path` and compare it with its pin. Returns `Ok(false)` when the
+    /// artifact should be skipped.
+    #[cfg(feature = "pinning")]
+    fn check_digest(
+        &mut self,
+        path: &Path,
+        manifest: Option<&PluginManifest>,
+    ) -> Result<bool, PluginLoadError> {
+        let mismatch = match self
+            .digest_pins
+            .check(path, manifest.map(|m| m.name.as_str()))
+            .map_err(PluginLoadError::Io)?
+        {
+            Ok(()) => return Ok(true),
+            Err(m) => m,
+        };
+        trace_event!(warn, path = %path.display(), mismatch = %mismatch, "plugin digest mismatch");
+        self.digest_mismatches.push(mismatch.clone());
+        if self.digest_pins.skips_mismatches() {
+            self.artifact_failed(path, mismatch.to_string());
+            Ok(false)
+        } else {
+            Err(PluginLoadError::DigestMismatch(mismatch))
+        }
+    }
+
+    /// The context handed to the library at `path`: the manager's services
+    /// plus the service tables for the capabilities its manifest requests
+    /// and the host grants.
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    fn host_for(&self, path: &Path, manifest: Option<&PluginManifest>) -> Arc<SharedHostContext> {
+        let config = self
+            .config
+            .for_library(path, manifest.map(|m| m.name.as_str()));
+        let namespace = kv::namespace_for(path, manifest.map(|m| m.name.as_str()));
+        let Some(m) = manifest else {
+            return self.host.for_library(path, namespace, config);
+        };
+        let host = self
+            .host
+            .for_plugin(Some(&m.name), &m.capabilities)
+            .for_library(path, namespace, config);
+        let granted = host.capabilities();
+        for denied in m.capabilities.iter().filter(|c| !granted.contains(c)) {
+            trace_event!(
+                warn,
+                path = %path.display(),
+                capability = denied.as_str(),
+                "capability requested by plugin was not granted"
+            );
+        }
+        host
+    }
+
+    /// Load every plugin on `search_path` that provides `trait_id`. A single
+    /// directory converts into a one-entry search path.
+    ///
+    /// Libraries described by a sidecar manifest (`<lib>.plugin.toml`) are
+    /// discovered first and their requirements are validated before the
+    /// library is opened; libraries without a manifest are loaded afterwards.
+    ///
+    /// When the same plugin appears in several directories only the copy
+    /// picked by the search path's `SearchPrecedence` is loaded, and a plugin
+    /// that is already loaded is never loaded again from elsewhere. The
+    /// copies passed over are reported by `shadowed_plugins`.
+    ///
+    /// The handles come highest priority first, then by registration name,
+    /// like `iter_by_priority`.
+    pub fn load_plugins(
+        &mut self,
+        search_path: impl Into<PluginSearchPath>,
+        trait_id: PluginTrait,
+    ) -> Result<Vec<PluginHandle>, PluginLoadError> {
+        let policy = self.discovery.clone();
+        self.load_plugins_where(search_path.into(), trait_id, &policy, &|_| true)
+    }
+
+    /// `load_plugins`, making only the registrations whose names `accept`
+    /// returns true for (unnamed ones are offered as ""). The other
+    /// implementations of a library are never constructed, so their cost
+    /// and side effects are avoided. The names come from the library's
+    /// `plugin_list_impls_<Trait>_v<N>` (see `list_impls`); libraries that
+    /// do not export it are skipped. Reloads of these libraries apply the
+    /// same filter.
+    pub fn load_plugins_filtered(
+        &mut self,
+        search_path: impl Into<PluginSearchPath>,
+        trait_id: PluginTrait,
+        accept: impl Fn(&str) -> bool + Send + Sync + 'static,
+    ) -> Result<Vec<PluginHandle>, PluginLoadError> {
+        let previous = self.impl_filter.replace(Arc::new(accept));
+        let result = self.load_plugins(search_path, trait_id);
+        self.impl_filter = previous;
+        result
+    }
+
+    /// `load_plugins`, handing each library the entry `config` has for it
+    /// (see `ConfigSource`). The plugin's registrations receive it through
+    /// the trait's `configure` hook before `on_load`. The entries are kept
+    /// and also apply to later loads and reloads of the same plugins.
+    pub fn load_plugins_with_config(
+        &mut self,
+        search_path: impl Into<PluginSearchPath>,
+        trait_id: PluginTrait,
+        config: &ConfigSource,
+    ) -> Result<Vec<PluginHandle>, PluginLoadError> {
+        self.config.merge(config);
+        self.load_plugins(search_path, trait_id)
+    }
+
+    /// `load_plugins` with the files `policy` recognizes, skipping
+    /// libraries for which `keep` returns false.
+    fn load_plugins_where(
+        &mut self,
+        search_path: PluginSearchPath,
+        trait_id: PluginTrait,
+        policy: &DiscoveryPolicy,
+        keep: &dyn Fn(&Candidate) -> bool,
+    ) -> Result<Vec<PluginHandle>, PluginLoadError> {
+        let result = self.load_batch(search_path, trait_id, policy, keep);
+        self.publish_err(None, result)
+    }
+
+    fn load_batch(
+        &mut self,
+        search_path: PluginSearchPath,
+        trait_id: PluginTrait,
+        policy: &DiscoveryPolicy,
+        keep: &dyn Fn(&Candidate) -> bool,
+    ) -> Result<Vec<PluginHandle>, PluginLoadError> {
+        let mut handles = Vec::new();
+        let ordered = self.ordered_candidates(search_path, trait_id, policy, keep)?;
+        if self.load_threads > 1 {
+            self.load_concurrently(ordered, trait_id, self.load_threads, &mut handles)?;
+        } else {
+            for candidate in ordered {
+                self.load_candidate(candidate.path, candidate.manifest, trait_id, &mut handles)?;
+            }
+        }
+
+        if handles.is_empty() {
+            return Err(PluginLoadError::NoRegistrations);
+        }
+
+        sort_by_priority(&mut handles);
+        Ok(handles)
+    }
+
+    /// The batch `load_plugins` opens: the new candidates on `search_path`
+    /// that `policy` recognizes and `keep` accepts, in dependency order.
+    fn ordered_candidates(
+        &mut self,
+        search_path: PluginSearchPath,
+        trait_id: PluginTrait,
+        policy: &DiscoveryPolicy,
+        keep: &dyn Fn(&Candidate) -> bool,
+    ) -> Result<Vec<Candidate>, PluginLoadError> {
+        let known = self.loaded_plugin_paths(self.multi_version);
+        let pending = self.discover_candidates(search_path, trait_id, &known, policy, keep)?;
+
+        // Dependencies are resolved against the batch and the plugins that
+        // are already loaded; nothing is opened if any of them is missing.
+        deps::order_candidates(pending, &self.loaded_manifest_versions())
+            .map_err(PluginLoadError::Dependency)
+    }
+
+    /// Discover the plugins on `search_path` that provide `trait_id`, keep
+    /// one copy per plugin name (and version, with `set_multi_version`) and
+    /// validate the copies that won. Plugins keyed in `known` shadow every
+    /// discovered copy; files `policy` does
+    /// not recognize and libraries `keep` rejects are not considered at all.
+    fn discover_candidates(
+        &mut self,
+        search_path: PluginSearchPath,
+        trait_id: PluginTrait,
+        known: &HashMap<String, PathBuf>,
+        policy: &DiscoveryPolicy,
+        keep: &dyn Fn(&Candidate) -> bool,
+    ) -> Result<Vec<Candidate>, PluginLoadError> {
+        let mut discovered = Vec::new();
+        for dir in search_path.dirs() {
+            match manifest::discover(dir, policy) {
+                Ok(found) => discovered.extend(found),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(PluginLoadError::Io(e)),
+            }
+        }
+
+        let mut found = Vec::new();
+        let mut seen = HashSet::new();
+        for candidate in discovered {
+            let candidate = match candidate {
+                Ok(c) => c,
+                Err((path, _)) if self.quarantine.contains(&path) => continue,
+                Err((path, error)) => {
+                    self.artifact_failed(&path, error.to_string());
+                    return Err(PluginLoadError::Manifest { path, error });
+                }
+            };
+
+            if self.is_loaded(&candidate.path) || !keep(&candidate) {
+                continue;
+            }
+            if self.quarantine.contains(&candidate.path) {
+                trace_event!(debug, path = %candidate.path.display(), "skipping quarantined plugin");
+                continue;
+            }
+            // A symlink or hard link to a file found earlier in this pass.
+            if !seen.insert(FileKey::of(&candidate.path)) {
+                trace_event!(debug, path = %candidate.path.display(), "skipping second path to the same plugin file");
+                continue;
+            }
+
+            // A manifest that doesn't list this trait simply isn't a
+            // candidate.
+            if let Some(m) = &candidate.manifest {
+                if !m.provides(trait_id.as_str()) {
+                    continue;
+                }
+            }
+            found.push(candidate);
+        }
+        let (pending, shadowed) =
+            search_path::select(found, known, search_path.precedence(), self.multi_version);
+        #[cfg(feature = "tracing")]
+        for s in &shadowed {
+            trace_event!(
+                debug,
+                path = %s.path.display(),
+                shadowed_by = %s.shadowed_by.display(),
+                "plugin shadowed by another copy"
+            );
+        }
+        self.shadowed = shadowed;
+
+        // Only the copies that won are validated, so an outdated shadowed
+        // copy cannot fail the load; requirement failures are errors.
+        for candidate in &pending {
+            if let Some(m) = &candidate.manifest {
+                if let Err(error) = m.validate(trait_id.as_str()) {
+                    self.artifact_failed(&candidate.path, error.to_string());
+                    return Err(PluginLoadError::Manifest {
+                        path: candidate.path.clone(),
+                        error,
+                    });
+                }
+            }
+        }
+        Ok(pending)
+    }
+
+    /// Record the plugins on `search_path` that provide `trait_id` without
+    /// opening them. Candidates are discovered, deduplicated and validated
+    /// like `load_plugins` does; the library is opened the first time
+    /// `plugin_handles` or `greeter` asks for it. Plugins that are already
+    /// loaded or indexed shadow new copies. Returns how many plugins were
+    /// added to the index.
+    pub fn index_plugins(
+        &mut self,
+        search_path: impl Into<PluginSearchPath>,
+        trait_id: PluginTrait,
+    ) -> Result<usize, PluginLoadError> {
+        let mut known = self.loaded_plugin_paths(self.multi_version);
+        known.extend(self.indexed.iter().map(|e| {
+            let key = search_path::plugin_key(&e.path, e.manifest.as_ref(), self.multi_version);
+            (key, e.path.clone())
+        }));
+        let policy = self.discovery.clone();
+        let pending =
+            self.discover_candidates(search_path.into(), trait_id, &known, &policy, &|_| true)?;
+        let added = pending.len();
+        self.indexed
+            .extend(pending.into_iter().map(|c| IndexedPlugin {
+                name: search_path::plugin_name(
+                    &c.path,
+                    c.manifest.as_ref().map(|m| m.name.as_str()),
+                ),
+                path: c.path,
+                manifest: c.manifest,
+                trait_id,
+            }));
+        Ok(added)
+    }
+
+    /// Plugins recorded by `index_plugins`, loaded or not.
+    pub fn indexed_plugins(&self) -> &[IndexedPlugin] {
+        &self.indexed
+    }
+
+    /// Handles for every registration of the plugin called `name` (its
+    /// manifest name, or its file name without a manifest).
+    ///
+    /// A loaded plugin answers directly. An indexed plugin that is not
+    /// loaded, including one whose previous handles were all dropped, is
+    /// opened first, together with any indexed dependencies that are not
+    /// loaded; those stay loaded for as long as the plugin does. Fails with
+    /// `PluginLoadError::UnknownPlugin` if `name` is neither loaded nor
+    /// indexed. A `name@version` lookup, as `find_by_name` takes, only
+    /// answers from the loaded plugins.
+    pub fn plugin_handles(&mut self, name: &str) -> Result<Vec<PluginHandle>, PluginLoadError> {
+        if let Some(handles) = self.live_handles(name) {
+            return Ok(handles);
+        }
+        let result = self.open_indexed(name);
+        self.publish_err(None, result)
+    }
+
+    fn open_indexed(&mut self, name: &str) -> Result<Vec<PluginHandle>, PluginLoadError> {
+        let loaded = self.loaded_plugin_paths(false);
+        let batch = lazy::with_dependencies(&self.indexed, name, &loaded);
+        if batch.is_empty() {
+            return Err(PluginLoadError::UnknownPlugin(name.to_string()));
+        }
+        let traits: HashMap<PathBuf, PluginTrait> =
+            batch.iter().map(|e| (e.path.clone(), e.trait_id)).collect();
+        let batch = batch.into_iter().map(IndexedPlugin::candidate).collect();
+        let ordered = deps::order_candidates(batch, &self.loaded_manifest_versions())
+            .map_err(PluginLoadError::Dependency)?;
+
+        // Handles of the plugins opened by this call, by name, so each one
+        // can hold on to the dependencies opened for it.
+        let mut opened_here: HashMap<String, Vec<PluginHandle>> = HashMap::new();
+        for candidate in ordered {
+            let trait_id = traits[&candidate.path];
+            let plugin = search_path::plugin_name(
+                &candidate.path,
+                candidate.manifest.as_ref().map(|m| m.name.as_str()),
+            );
+            let dependencies: Vec<PluginHandle> = candidate
+                .manifest
+                .iter()
+                .flat_map(|m| m.dependencies.keys())
+                .filter_map(|dep| opened_here.get(dep))
+                .flatten()
+                .cloned()
+                .collect();
+            let Some(host) =
+                self.prepare_candidate(&candidate.path, candidate.manifest.as_ref(), trait_id)?
+            else {
+                continue;
+            };
+            let opened = open_candidate(
+                &candidate.path,
+                candidate.manifest,
+                trait_id,
+                host,
+                self.open_options(),
+            )?;
+            let mut handles = Vec::new();
+            self.record_opened(
+                candidate.path,
+                opened.keep_alive(dependencies),
+                trait_id,
+                &mut handles,
+                ManagerEvent::Loaded,
+                false,
+            )?;
+            trace_event!(debug, plugin = %plugin, "indexed plugin opened on first use");
+            opened_here.insert(plugin, handles);
+        }
+        match opened_here.remove(name) {
+            Some(handles) if !handles.is_empty() => Ok(handles),
+            _ => Err(PluginLoadError::NoRegistrations),
+        }
+    }
+
+    /// Proxy for the first registration of the plugin called `name`,
+    /// opening it first if it is only indexed. See `plugin_handles`.
+    pub fn greeter(&mut self, name: &str) -> Result<Option<GreeterProxy>, PluginLoadError> {
+        Ok(self
+            .plugin_handles(name)?
+            .first()
+            .and_then(PluginHandle::as_greeter))
+    }
+
+    /// New handles for the loaded plugin called `name`, if there is one.
+    /// A `name@version` picks the newest loaded version that matches.
+    fn live_handles(&self, name: &str) -> Option<Vec<PluginHandle>> {
+        let plugin_name = |handles: &Vec<PluginHandle>| {
+            let first = &handles[0];
+            search_path::plugin_name(first.path(), first.manifest().map(|m| m.name.as_str()))
+        };
+        let plugins = self.live_plugins();
+        match search_path::split_versioned(name) {
+            Some((name, filter)) => search_path::newest_matching(
+                plugins.into_iter().filter(|h| plugin_name(h) == name),
+                |h| h[0].version(),
+                &filter,
+            ),
+            None => plugins.into_iter().find(|h| plugin_name(h) == name),
+        }
+    }
+
+    /// New handles for every registration of every loaded library and
+    /// module, grouped by plugin in load order.
+    fn live_plugins(&self) -> Vec<Vec<PluginHandle>> {
+        #[allow(unused_mut)]
+        let mut plugins: Vec<Vec<PluginHandle>> = self
+            .libs
+            .iter()
+            .filter_map(|w| w.upgrade())
+            .filter(|l| !l.closed.load(Ordering::SeqCst))
+            .map(|lib| {
+                let count = unsafe { (*lib.arr_ptr).count };
+                (0..count)
+                    .map(|idx| PluginHandle::new(lib.clone(), idx, lib.trait_id))
+                    .collect()
+            })
+            .collect();
+        #[cfg(feature = "wasm")]
+        plugins.extend(self.wasm.iter().filter_map(|w| w.upgrade()).map(|plugin| {
+            (0..plugin.count())
+                .map(|idx| PluginHandle::wasm(plugin.clone(), idx, plugin.trait_id))
+                .collect()
+        }));
+        plugins.retain(|handles| !handles.is_empty());
+        plugins
+    }
+
+    /// Write the loaded libraries and WebAssembly modules to `path` as
+    /// TOML, with the registrations that are disabled and the configuration
+    /// each plugin was handed, for `restore_state` to load again after a
+    /// restart. With the `pinning` feature the artifacts' SHA-256 digests
+    /// are saved too. Plugins served by runner processes are not saved.
+    pub fn save_state(&self, path: &Path) -> std::io::Result<()> {
+        let mut state = SavedState::default();
+        for handles in self.live_plugins() {
+            let Some(plugin) = PluginDescriptor::from_handles(&handles) else {
+                continue;
+            };
+            let manifest_name = plugin.manifest.as_ref().map(|m| m.name.as_str());
+            state.plugins.push(SavedPlugin {
+                config: self
+                    .config
+                    .for_library(&plugin.path, manifest_name)
+                    .map(str::to_string),
+                disabled: (0..handles.len())
+                    .filter(|&i| self.is_enabled(handles[i].id()) == Some(false))
+                    .collect(),
+                #[cfg(feature = "pinning")]
+                sha256: Some(Sha256Digest::of_file(&plugin.path)?.to_string()),
+                #[cfg(not(feature = "pinning"))]
+                sha256: None,
+                trait_name: plugin.trait_id.as_str().to_string(),
+                name: plugin.name,
+                path: plugin.path,
+            });
+        }
+        state.write(path)
+    }
+
+    /// Load the plugins `save_state` wrote to `path`, in the order they
+    /// were loaded, hand them their saved configuration and disable the
+    /// registrations that were disabled. Each artifact goes through the
+    /// same signature and digest checks as any other load; with the
+    /// `pinning` feature an artifact that changed since it was saved fails
+    /// with `PluginLoadError::DigestMismatch`. A plugin that fails is
+    /// reported in `RestoredState::failed` and the rest are restored.
+    pub fn restore_state(&mut self, path: &Path) -> Result<RestoredState, PluginLoadError> {
+        let state = SavedState::read(path)?;
+        let mut restored = RestoredState::default();
+        for saved in state.plugins {
+            let Some(trait_id) = PluginTrait::from_name(&saved.trait_name) else {
+                let error = format!("unknown trait {:?}", saved.trait_name);
+                restored
+                    .failed
+                    .push((saved.path, PluginLoadError::Lib(error)));
+                continue;
+            };
+            if let Some(config) = saved.config {
+                self.config
+                    .merge(&ConfigSource::new().with_plugin(saved.name, config));
+            }
+            if !self.is_loaded(&saved.path) {
+                #[cfg(feature = "pinning")]
+                if let Err(e) = check_saved_digest(&saved.path, saved.sha256.as_deref()) {
+                    restored.failed.push((saved.path, e));
+                    continue;
+                }
+                match self.load_library(&saved.path, trait_id) {
+                    Ok(handles) => restored.handles.extend(handles),
+                    Err(e) => {
+                        restored.failed.push((saved.path, e));
+                        continue;
+                    }
+                }
+            }
+            let Some(handles) = self
+                .live_plugins()
+                .into_iter()
+                .find(|h| h.first().is_some_and(|h| h.path() == saved.path))
+            else {
+                continue;
+            };
+            for index in saved.disabled {
+                if let Some(h) = handles.get(index) {
+                    self.set_enabled(h.id(), false);
+                }
+            }
+        }
+        Ok(restored)
+    }
+
+    /// Describe every loaded library and WebAssembly module, in load order.
+    /// Plugins served by runner processes are not included.
+    pub fn list(&self) -> Vec<PluginDescriptor> {
+        self.live_plugins()
+            .iter()
+            .filter_map(|handles| PluginDescriptor::from_handles(handles))
+            .collect()
+    }
+
+    /// The loaded registrations of `trait_id`, highest priority first (see
+    /// `PluginHandle::priority`) and by registration name among equal
+    /// priorities, for hosts where the first plugin that handles something
+    /// wins.
+    pub fn iter_by_priority(&self, trait_id: PluginTrait) -> impl Iterator<Item = PluginHandle> {
+        let mut handles: Vec<PluginHandle> = self
+            .live_plugins()
+            .into_iter()
+            .flatten()
+            .filter(|h| h.trait_id() == trait_id)
+            .collect();
+        sort_by_priority(&mut handles);
+        handles.into_iter()
+    }
+
+    /// The loaded registrations of `trait_id` boxed as `T`, such as
+    /// `collect_trait_objects::<dyn Greeter>(PluginTrait::Greeter)`, in
+    /// `iter_by_priority` order. Host code written against `dyn Greeter`
+    /// then runs the same over plugins as over built-in implementations.
+    /// Registrations `T`'s proxy cannot call are left out.
+    pub fn collect_trait_objects<T: PluginObject + ?Sized>(
+        &self,
+        trait_id: PluginTrait,
+    ) -> Vec<Box<T>> {
+        self.iter_by_priority(trait_id)
+            .filter_map(|h| h.as_proxy::<T::Proxy>())
+            .map(T::boxed)
+            .collect()
+    }
+
+    /// Handle for the first loaded registration whose registration name (see
+    /// `PluginHandle::registration_name`) is `name`, for example
+    /// `"GreeterOne"`. With a version suffix, such as `"GreeterOne@1.2.0"`
+    /// (see `PluginHandle::versioned_name`) or a requirement like
+    /// `"GreeterOne@^2"`, the registration of the newest matching plugin
+    /// version instead.
+    pub fn find_by_name(&self, name: &str) -> Option<PluginHandle> {
+        let mut handles = self.live_plugins().into_iter().flatten();
+        match search_path::split_versioned(name) {
+            Some((name, filter)) => search_path::newest_matching(
+                handles.filter(|h| h.registration_name().as_deref() == Some(name)),
+                |h| h.version(),
+                &filter,
+            ),
+            None => handles.find(|h| h.registration_name().as_deref() == Some(name)),
+        }
+    }
+
+    /// Handles for every loaded registration implementing `trait_id`.
+    pub fn find_by_trait(&self, trait_id: PluginTrait) -> Vec<PluginHandle> {
+        self.live_plugins()
+            .into_iter()
+            .flatten()
+            .filter(|h| h.trait_id() == trait_id)
+            .collect()
+    }
+
+    /// Load a single library file that provides `trait_id`.
+    ///
+    /// A sidecar manifest next to the library is validated the same way
+    /// `load_plugins` does, and its dependencies must already be loaded.
+    pub fn load_library(
+        &mut self,
+        path: &Path,
+        trait_id: PluginTrait,
+    ) -> Result<Vec<PluginHandle>, PluginLoadError> {
+        let result = self.open_library(path, trait_id);
+        self.publish_err(Some(path), result)
+    }
+
+    /// Register the plugins linked into the host that provide `trait_id`,
+    /// from the entries their `#[plugin_impl]`s submit, without opening a
+    /// library. Plugin crates are linked in as ordinary `rlib`
+    /// dependencies, at most one per trait as their exported symbols would
+    /// clash. The registrations behave like those of a loaded library and
+    /// are loaded, configured and unloaded under the path `static:<Trait>`.
+    ///
+    /// Register hooks such as `#[plugin_logging]` do not run, as the
+    /// plugins share the host's globals. Only one manager at a time should
+    /// load the linked plugins of a trait.
+    #[cfg(feature = "static-plugins")]
+    pub fn load_static(
+        &mut self,
+        trait_id: PluginTrait,
+    ) -> Result<Vec<PluginHandle>, PluginLoadError> {
+        let path = static_plugins::static_path(trait_id);
+        let result = self.open_static(&path, trait_id);
+        self.publish_err(Some(&path), result)
+    }
+
+    /// `load_static`, handing the plugins the entry `config` has for
+    /// `static:<Trait>`, like `load_plugins_with_config`.
+    #[cfg(feature = "static-plugins")]
+    pub fn load_static_with_config(
+        &mut self,
+        trait_id: PluginTrait,
+        config: &ConfigSource,
+    ) -> Result<Vec<PluginHandle>, PluginLoadError> {
+        self.config.merge(config);
+        self.load_static(trait_id)
+    }
+
+    #[cfg(feature = "static-plugins")]
+    fn open_static(
+        &mut self,
+        path: &Path,
+        trait_id: PluginTrait,
+    ) -> Result<Vec<PluginHandle>, PluginLoadError> {
+        // The makers would make a second set of the same registrations.
+        let loaded = self
+            .libs
+            .iter()
+            .filter_map(|w| w.upgrade())
+            .any(|l| l.path == path && !l.is_unloaded());
+        if loaded {
+            return Err(PluginLoadError::Lib(format!(
+                "{} is already loaded",
+                path.display()
+            )));
+        }
+        let host = self.host_for(path, None);
+        let opened = match static_plugins::open_linked(trait_id, host, path.to_path_buf())? {
+            #[allow(clippy::arc_with_non_send_sync)]
+            Some(loaded) => Opened::Native(Arc::new(loaded)),
+            None => Opened::Nothing,
+        };
+        let mut handles = Vec::new();
+        let event = ManagerEvent::Loaded;
+        self.record_opened(
+            path.to_path_buf(),
+            opened,
+            trait_id,
+            &mut handles,
+            event,
+            false,
+        )?;
+        if handles.is_empty() {
+            return Err(PluginLoadError::NoRegistrations);
+        }
+        Ok(handles)
+    }
+
+    /// Load the `trait_id` registrations of the plugin `serve_remote`
+    /// serves at `addr`, in another process or on another machine. Their
+    /// handles and proxies work like those of a loaded library: each call
+    /// is sent to the server and waits for its answer, and calls that
+    /// cannot reach it fail with `PluginCallError::Failed`. A dropped
+    /// connection is opened again on the next call. The registrations are
+    /// loaded under the path `remote:<addr>`, which `unload_by_path`
+    /// unloads. `on_load`, `on_unload` and the state hooks run on the
+    /// server's side, not through the connection.
+    #[cfg(feature = "remote")]
+    pub fn connect_remote(
+        &mut self,
+        addr: &str,
+        trait_id: PluginTrait,
+    ) -> Result<Vec<PluginHandle>, PluginLoadError> {
+        let path = remote::remote_path(addr);
+        let result = self.open_remote(addr, &path, trait_id);
+        self.publish_err(Some(&path), result)
+    }
+
+    #[cfg(feature = "remote")]
+    fn open_remote(
+        &mut self,
+        addr: &str,
+        path: &Path,
+        trait_id: PluginTrait,
+    ) -> Result<Vec<PluginHandle>, PluginLoadError> {
+        let host = self.host_for(path, None);
+        let image = loader::process_image().map_err(PluginLoadError::Lib)?;
+        let loader = remote::RemoteLoader {
+            addr: addr.to_string(),
+            shared_memory: self.shared_memory.clone(),
+        };
+        let opened =
+            match modules::open_module(&loader, image, path, trait_id, host, path.to_path_buf())
+                .map_err(PluginLoadError::Lib)?
+            {
+                #[allow(clippy::arc_with_non_send_sync)]
+                Some(loaded) => Opened::Native(Arc::new(loaded)),
+                None => Opened::Nothing,
+            };
+        let mut handles = Vec::new();
+        self.record_opened(
+            path.to_path_buf(),
+            opened,
+            trait_id,
+            &mut handles,
+            ManagerEvent::Loaded,
+            false,
+        )?;
+        if handles.is_empty() {
+            return Err(PluginLoadError::NoRegistrations);
+        }
+        Ok(handles)
+    }
+
+    /// Check every plugin candidate in `dir` without opening it: that
+    /// each library is a dynamic library for this platform and
+    /// architecture, exports register and unregister functions for
+    /// `trait_id` at an ABI level the host supports, and that its sidecar
+    /// manifest is valid. Files are found the way `load_plugins` finds
+    /// them; a broken manifest is reported under its own path.
+    pub fn validate(&self, dir: &Path, trait_id: PluginTrait) -> std::io::Result<ValidationReport> {
+        validate::validate_dir(dir, trait_id, &self.discovery)
+    }
+
+    /// The JSON document the native library at `path` exports as
+    /// `plugin_describe_v1` (see `#[plugin_aggregates]`): its package name
+    /// and version and, for each of its traits, the ABI level,
+    /// registrations and method signatures.
+    /// Reads it from the loaded library if there is one, and otherwise
+    /// opens the library with the manager's load flags just long enough to
+    /// call it, without registering anything.
+    pub fn describe(&self, path: &Path) -> Result<String, PluginLoadError> {
+        let loaded = self
+            .libs
+            .iter()
+            .filter_map(|w| w.upgrade())
+            .find(|l| l.path == path);
+        if let Some(loaded) = loaded {
+            if let Some(lib) = loaded
+                .lib
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .as_ref()
+            {
+                return unsafe { read_description(lib, path) };
+            }
+        }
+        check_format(path, path)?;
+        let (lib, _) =
+            unsafe { loader::open(path, path, self.load_flags) }.map_err(PluginLoadError::Lib)?;
+        unsafe { read_description(&lib, path) }
+    }
+
+    /// The registration names of the implementations of `trait_id` the
+    /// native library at `path` lists in `plugin_list_impls_<Trait>_v<N>`
+    /// (see `#[plugin_aggregates]`), in the order `register_all` would make
+    /// them. Like `describe`, asks the loaded library if there is one and
+    /// otherwise opens it just long enough to ask, without registering
+    /// anything. Implementations built without a name are listed as "".
+    pub fn list_impls(
+        &self,
+        path: &Path,
+        trait_id: PluginTrait,
+    ) -> Result<Vec<String>, PluginLoadError> {
+        let read = |lib: &libloading::Library| {
+            unsafe { crate::impls::library_impls(lib, trait_id, std::ptr::null()) }
+                .map(|(impls, _)| {
+                    impls
+                        .iter()
+                        .map(|i| i.name.unwrap_or_default().to_string())
+                        .collect()
+                })
+                .ok_or_else(|| {
+                    PluginLoadError::Lib(format!(
+                        "{} exports no plugin_list_impls for {}",
+                        path.display(),
+                        trait_id.as_str()
+                    ))
+                })
+        };
+        let loaded = self
+            .libs
+            .iter()
+            .filter_map(|w| w.upgrade())
+            .find(|l| l.path == path);
+        if let Some(loaded) = loaded {
+            if let Some(lib) = loaded
+                .lib
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .as_ref()
+            {
+                return read(lib);
+            }
+        }
+        check_format(path, path)?;
+        let (lib, _) =
+            unsafe { loader::open(path, path, self.load_flags) }.map_err(PluginLoadError::Lib)?;
+        read(&lib)
+    }
+
+    /// Load the Greeter library at `path` like `load_library` and keep it
+    /// loaded until `unload_plugin`, `unload_by_path` or the manager is
+    /// dropped, for hosts that address plugins by position rather than
+    /// through handles. Returns the plugin's index: plugins kept this way
+    /// are numbered in load order, and unloading one moves the later ones
+    /// down by one.
+    pub fn load_plugin<P: AsRef<Path>>(&mut self, path: P) -> Result<usize, PluginLoadError> {
+        let path = path.as_ref();
+        let handles = self.load_library(path, PluginTrait::Greeter)?;
+        self.kept.push((path.to_path_buf(), handles));
+        Ok(self.kept.len() - 1)
+    }
+
+    /// The handles `load_plugin` keeps for the plugin at `plugin_index`.
+    fn kept_plugin(&self, plugin_index: usize) -> Result<&[PluginHandle], String> {
+        self.kept
+            .get(plugin_index)
+            .map(|(_, handles)| handles.as_slice())
+            .ok_or_else(|| format!("no plugin at index {}", plugin_index))
+    }
+
+    /// Call `greet` on the first registration of the plugin `load_plugin`
+    /// returned `plugin_index` for.
+    pub fn call_greet(&self, plugin_index: usize, target: &str) -> Result<(), String> {
+        self.kept_plugin(plugin_index)?
+            .iter()
+            .find_map(PluginHandle::as_greeter)
+            .ok_or_else(|| format!("plugin {} has no Greeter registration", plugin_index))?
+            .try_greet(target)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Call the function the plugin at `plugin_index` exports as
+    /// `function_name`, outside of any registration. Like proxy calls, it
+    /// keeps the library from being unloaded until it returns, and fails
+    /// while the library is unloading.
+    ///
+    /// # Safety
+    /// The exported symbol must be an `extern "C" fn()`; nothing checks its
+    /// signature.
+    pub unsafe fn call_plugin_function(
+        &self,
+        plugin_index: usize,
+        function_name: &str,
+    ) -> Result<(), String> {
+        let handles = self.kept_plugin(plugin_index)?;
+        handles[0].call_export(function_name)
+    }
+
+    /// Stop keeping the plugin at `plugin_index` and unload it like
+    /// `unload_by_path`. Later indexes move down by one.
+    pub fn unload_plugin(&mut self, plugin_index: usize) -> Result<Option<u64>, String> {
+        self.kept_plugin(plugin_index)?;
+        let (path, handles) = self.kept.remove(plugin_index);
+        drop(handles);
+        self.unload_by_path(&path)
+    }
+
+    fn open_library(
+        &mut self,
+        path: &Path,
+        trait_id: PluginTrait,
+    ) -> Result<Vec<PluginHandle>, PluginLoadError> {
+        let manifest = read_sidecar(path, trait_id)?;
+        let candidate = manifest::Candidate {
+            path: path.to_path_buf(),
+            manifest,
+        };
+        let mut handles = Vec::new();
+        for candidate in deps::order_candidates(vec![candidate], &self.loaded_manifest_versions())
+            .map_err(PluginLoadError::Dependency)?
+        {
+            self.load_candidate(candidate.path, candidate.manifest, trait_id, &mut handles)?;
+        }
+        if handles.is_empty() {
+            return Err(PluginLoadError::NoRegistrations);
+        }
+        Ok(handles)
+    }
+
+    /// Load the library at `path` in a separate runner process.
+    ///
+    /// The returned proxies behave like in-process ones, but a plugin that
+    /// crashes only takes down its runner; calls then fail with
+    /// `PluginCallError::Crashed` and, with `auto_restart`, the next call
+    /// starts a fresh runner.
+    #[cfg(feature = "isolation")]
+    pub fn load_isolated(
+        &mut self,
+        path: &Path,
+        trait_id: PluginTrait,
+        opts: IsolationOptions,
+    ) -> Result<Vec<GreeterProxy>, PluginLoadError> {
+        match trait_id {
+            PluginTrait::Greeter => {}
+            PluginTrait::Transformer => {
+                return Err(PluginLoadError::Lib(
+                    "isolated plugins can only provide Greeter".to_string(),
+                ))
+            }
+        }
+        #[cfg(feature = "signing")]
+        self.check_signature(path)?;
+        #[cfg(feature = "pinning")]
+        if !self.check_digest(path, None)? {
+            return Err(PluginLoadError::NoRegistrations);
+        }
+        let plugin = IsolatedPlugin::spawn(path, opts, self.shared_memory.as_ref()).map_err(|e| {
+            trace_event!(warn, path = %path.display(), error = %e, "failed to start plugin runner");
+            PluginLoadError::Lib(e)
+        })?;
+        if plugin.count() == 0 {
+            return Err(PluginLoadError::NoRegistrations);
+        }
+        let plugin = Arc::new(plugin);
+        let key = Arc::as_ptr(&plugin) as *const RegistrationArray;
+        plugin
+            .calls
+            .bind_budget(BudgetBinding::new(self.error_budget.clone(), key));
+        plugin
+            .calls
+            .bind_interceptors(Binding::new(self.interceptors.clone(), key));
+        self.isolated.push(Arc::downgrade(&plugin));
+        trace_event!(info, path = %path.display(), registrations = plugin.count(), "isolated plugin loaded");
+        Ok((0..plugin.count())
+            .map(|index| GreeterProxy::isolated(plugin.clone(), index))
+            .collect())
+    }
+
+    fn load_candidate(
+        &mut self,
+        path: PathBuf,
+        manifest: Option<PluginManifest>,
+        trait_id: PluginTrait,
+        handles: &mut Vec<PluginHandle>,
+    ) -> Result<(), PluginLoadError> {
+        let Some(host) = self.prepare_candidate(&path, manifest.as_ref(), trait_id)? else {
+            return Ok(());
+        };
+        let opened = open_candidate(&path, manifest, trait_id, host, self.open_options())
+            .inspect_err(|e| self.artifact_failed(&path, format!("{:?}", e)))?;
+        self.record_opened(path, opened, trait_id, handles, ManagerEvent::Loaded, false)
+    }
 
-/// Errors when loading plugins
-#[derive(Debug)]
-pub enum PluginLoadError {
-    Io(std::io::Error),
-    Lib(String),
-    NoRegistrations,
+    /// Load a dependency-ordered batch, opening the plugins of each
+    /// dependency wave on up to `threads` scoped threads. Checks run and
+    /// handles are recorded on this thread in batch order, so the result is
+    /// the same as loading the batch one by one: plugins ordered before the
+    /// first failure stay loaded and the ones after it are released again.
+    fn load_concurrently(
+        &mut self,
+        ordered: Vec<Candidate>,
+        trait_id: PluginTrait,
+        threads: usize,
+        handles: &mut Vec<PluginHandle>,
+    ) -> Result<(), PluginLoadError> {
+        for wave in deps::waves(ordered) {
+            let (jobs, failure) = self.prepare_wave(wave, trait_id);
+            let paths: Vec<PathBuf> = jobs.iter().map(|(c, _)| c.path.clone()).collect();
+            let results = open_all(jobs, trait_id, threads, self.open_options());
+            for (path, opened) in paths.into_iter().zip(results) {
+                // Later results are dropped, which unloads them again.
+                let opened =
+                    opened.inspect_err(|e| self.artifact_failed(&path, format!("{:?}", e)))?;
+                self.record_opened(path, opened, trait_id, handles, ManagerEvent::Loaded, false)?;
+            }
+            if let Some(e) = failure {
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Run `prepare_candidate` over one dependency wave. Stops at the first
+    /// failure, which is returned with the jobs prepared before it.
+    fn prepare_wave(
+        &mut self,
+        wave: Vec<Candidate>,
+        trait_id: PluginTrait,
+    ) -> (Vec<OpenJob>, Option<PluginLoadError>) {
+        let mut jobs = Vec::new();
+        for candidate in wave {
+            match self.prepare_candidate(&candidate.path, candidate.manifest.as_ref(), trait_id) {
+                Ok(Some(host)) => jobs.push((candidate, host)),
+                Ok(None) => {}
+                Err(e) => return (jobs, Some(e)),
+            }
+        }
+        (jobs, None)
+    }
+
+    /// Checks that must pass before the artifact at `path` is opened.
+    /// Returns the host context to open it with, or `None` to skip it.
+    fn prepare_candidate(
+        &mut self,
+        path: &Path,
+        manifest: Option<&PluginManifest>,
+        trait_id: PluginTrait,
+    ) -> Result<Option<Arc<SharedHostContext>>, PluginLoadError> {
+        self.check_candidate(path, manifest, trait_id)
+            .inspect_err(|e| self.artifact_failed(path, format!("{:?}", e)))
+    }
+
+    #[cfg_attr(not(feature = "isolation"), allow(unused_variables))]
+    fn check_candidate(
+        &mut self,
+        path: &Path,
+        manifest: Option<&PluginManifest>,
+        trait_id: PluginTrait,
+    ) -> Result<Option<Arc<SharedHostContext>>, PluginLoadError> {
+        #[cfg(feature = "signing")]
+        self.check_signature(path)?;
+        #[cfg(feature = "pinning")]
+        if !self.check_digest(path, manifest)? {
+            return Ok(None);
+        }
+        #[cfg(feature = "wasm")]
+        if wasm::is_wasm_module(path) {
+            return Ok(Some(self.host.clone()));
+        }
+        #[cfg(feature = "isolation")]
+        if !self.check_probe(path, trait_id) {
+            return Ok(None);
+        }
+        Ok(Some(self.host_for(path, manifest)))
+    }
+
+    /// Track a plugin opened by `open_candidate`, hand out its handles and
+    /// tell the subscribers with `event`. Unless `reloaded`, its
+    /// registration names are checked against the loaded ones under the
+    /// conflict policy first.
+    fn record_opened(
+        &mut self,
+        path: PathBuf,
+        opened: Opened,
+        trait_id: PluginTrait,
+        handles: &mut Vec<PluginHandle>,
+        event: fn(PluginDescriptor) -> ManagerEvent,
+        reloaded: bool,
+    ) -> Result<(), PluginLoadError> {
+        let mut new: Vec<PluginHandle> = match &opened {
+            Opened::Nothing => return Ok(()),
+            Opened::Native(loaded) => {
+                let count = unsafe { (*loaded.arr_ptr).count };
+                (0..count)
+                    .map(|idx| PluginHandle::new(loaded.clone(), idx, trait_id))
+                    .collect()
+            }
+            #[cfg(feature = "wasm")]
+            Opened::Wasm(plugin) => (0..plugin.count())
+                .map(|idx| PluginHandle::wasm(plugin.clone(), idx, trait_id))
+                .collect(),
+        };
+        let (rejected, displaced) = if reloaded {
+            Default::default()
+        } else {
+            self.resolve_conflicts(trait_id, &new)?
+        };
+        if !rejected.is_empty() && rejected.len() == new.len() {
+            // Nothing of it is used; dropping it unloads it again.
+            return Ok(());
+        }
+        match opened {
+            Opened::Nothing => return Ok(()),
+            Opened::Native(loaded) => {
+                loaded.guard.set_timeout(self.unload_timeout);
+                loaded.calls.bind_budget(BudgetBinding::new(
+                    self.error_budget.clone(),
+                    loaded.arr_ptr,
+                ));
+                loaded
+                    .calls
+                    .bind_interceptors(Binding::new(self.interceptors.clone(), loaded.arr_ptr));
+                self.libs.push(Arc::downgrade(&loaded));
+                self.services.add_library(&loaded);
+                if let Some(host) = &loaded.host_context {
+                    self.bus.bind(host.owner(), &loaded);
+                }
+                trace_event!(info, path = %path.display(), registrations = new.len(), abi = loaded.abi_version, "plugin loaded");
+            }
+            #[cfg(feature = "wasm")]
+            Opened::Wasm(plugin) => {
+                let key = Arc::as_ptr(&plugin) as *const RegistrationArray;
+                plugin
+                    .calls
+                    .bind_budget(BudgetBinding::new(self.error_budget.clone(), key));
+                plugin
+                    .calls
+                    .bind_interceptors(Binding::new(self.interceptors.clone(), key));
+                self.wasm.push(Arc::downgrade(&plugin));
+                trace_event!(info, path = %path.display(), registrations = new.len(), "wasm plugin loaded");
+            }
+        }
+        self.quarantine.succeeded(&path);
+        self.loaded_files.insert(FileKey::of(&path), path);
+        let policy = Some(self.disabled_policy);
+        for id in displaced.iter().chain(&rejected) {
+            self.with_call_state(*id, |calls, index| calls.set_disabled(index, policy));
+        }
+        new.retain(|h| !rejected.contains(&h.id()));
+        let first = handles.len();
+        handles.extend(new);
+        if !self.subscribers.is_empty() {
+            if let Some(descriptor) = PluginDescriptor::from_handles(&handles[first..]) {
+                self.subscribers.send(event(descriptor));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the names of the registrations `new` against the enabled
+    /// `trait_id` registrations already loaded, recording the conflicts.
+    /// Returns the ids of the new registrations that lost and of the loaded
+    /// ones that did, or the error `ConflictPolicy::Error` asks for.
+    fn resolve_conflicts(
+        &mut self,
+        trait_id: PluginTrait,
+        new: &[PluginHandle],
+    ) -> Result<(Vec<PluginId>, Vec<PluginId>), PluginLoadError> {
+        let (mut rejected, mut displaced) = (Vec::new(), Vec::new());
+        if new.iter().all(|h| h.registration_name().is_none()) {
+            return Ok((rejected, displaced));
+        }
+        let existing = self.enabled_registrations(trait_id);
+        for incoming in new.iter().filter_map(Registered::of) {
+            for (id, found) in conflict::resolve(
+                self.conflict_policy,
+                &existing,
+                &incoming,
+                self.multi_version,
+            ) {
+                trace_event!(
+                    warn,
+                    name = %found.name,
+                    existing = %found.existing.display(),
+                    incoming = %found.incoming.display(),
+                    resolution = ?found.resolution,
+                    "registration name already in use"
+                );
+                match found.resolution {
+                    ConflictResolution::Refused => {
+                        self.conflicts.push(found.clone());
+                        return Err(PluginLoadError::Conflict(found));
+                    }
+                    ConflictResolution::KeptExisting if !rejected.contains(&incoming.id) => {
+                        rejected.push(incoming.id)
+                    }
+                    ConflictResolution::KeptIncoming => displaced.push(id),
+                    _ => {}
+                }
+                self.conflicts.push(found);
+            }
+        }
+        Ok((rejected, displaced))
+    }
+
+    /// The named, enabled `trait_id` registrations of the open libraries
+    /// and modules.
+    fn enabled_registrations(&self, trait_id: PluginTrait) -> Vec<Registered> {
+        let mut found = Vec::new();
+        for l in self.libs.iter().filter_map(|w| w.upgrade()) {
+            if l.trait_id != trait_id || l.closed.load(Ordering::SeqCst) {
+                continue;
+            }
+            for index in 0..l.calls.registrations() {
+                if l.calls.disabled(index).is_none() {
+                    found.extend(Registered::of(&PluginHandle::new(
+                        l.clone(),
+                        index,
+                        trait_id,
+                    )));
+                }
+            }
+        }
+        #[cfg(feature = "wasm")]
+        for p in self.wasm.iter().filter_map(|w| w.upgrade()) {
+            if p.trait_id != trait_id {
+                continue;
+            }
+            for index in 0..p.calls.registrations() {
+                if p.calls.disabled(index).is_none() {
+                    found.extend(Registered::of(&PluginHandle::wasm(
+                        p.clone(),
+                        index,
+                        trait_id,
+                    )));
+                }
+            }
+        }
+        found
+    }
 }
 
-/// Errors when unloading
-#[derive(Debug)]
-pub enum PluginUnloadError {
-    Lib(String),
+/// Compare the artifact at `path` with the digest `save_state` recorded for
+/// it, if it recorded one.
+#[cfg(feature = "pinning")]
+fn check_saved_digest(path: &Path, saved: Option<&str>) -> Result<(), PluginLoadError> {
+    let Some(saved) = saved else {
+        return Ok(());
+    };
+    let actual = Sha256Digest::of_file(path).map_err(PluginLoadError::Io)?;
+    let expected = Sha256Digest::from_hex(saved);
+    if expected == Some(actual) {
+        return Ok(());
+    }
+    Err(PluginLoadError::DigestMismatch(DigestMismatch {
+        path: path.to_path_buf(),
+        expected,
+        actual,
+    }))
 }
 
-pub struct PluginManager {
-    // Weak refs to loaded libs; handles own the strong Arcs so unload can occur
-    libs: Vec<Weak<LoadedLib>>,
-    // track file paths we've already loaded to avoid duplicates
-    loaded_paths: HashSet<std::path::PathBuf>,
+/// The validated sidecar manifest next to `path`, if there is one.
+fn read_sidecar(
+    path: &Path,
+    trait_id: PluginTrait,
+) -> Result<Option<PluginManifest>, PluginLoadError> {
+    let manifest_path = manifest::sidecar_path(path);
+    if !manifest_path.exists() {
+        return Ok(None);
+    }
+    let m =
+        PluginManifest::from_file(&manifest_path).map_err(|error| PluginLoadError::Manifest {
+            path: path.to_path_buf(),
+            error,
+        })?;
+    m.validate(trait_id.as_str())
+        .map_err(|error| PluginLoadError::Manifest {
+            path: path.to_path_buf(),
+            error,
+        })?;
+    Ok(Some(m))
 }
 
-impl Default for PluginManager {
-    fn default() -> Self {
-        Self::new()
+/// What `unload_by_path` has to do for a path, decided without blocking.
+enum UnloadStep {
+    /// Nothing is left to do; the result to return.
+    Done(Option<u64>),
+    /// The manager let go of a WebAssembly module; its last handle drops
+    /// it.
+    #[cfg(feature = "wasm")]
+    Forgotten,
+    /// The manager held the only reference; unload the library now.
+    Now(Box<LoadedLib>),
+    /// Handles still use the library: wait for calls in flight, then close.
+    Deferred(Arc<LoadedLib>),
+}
+
+/// A plugin that has been opened and registered but not yet recorded by the
+/// manager.
+enum Opened {
+    /// The library's register function returned no registrations.
+    Nothing,
+    Native(Arc<LoadedLib>),
+    #[cfg(feature = "wasm")]
+    Wasm(Arc<WasmPlugin>),
+}
+
+impl Opened {
+    /// Make the plugin hold `dependencies` until it is unloaded. Must run
+    /// before the plugin is shared.
+    fn keep_alive(self, dependencies: Vec<PluginHandle>) -> Self {
+        if dependencies.is_empty() {
+            return self;
+        }
+        match self {
+            Opened::Nothing => Opened::Nothing,
+            Opened::Native(mut lib) => {
+                Arc::get_mut(&mut lib)
+                    .expect("freshly opened library is not shared")
+                    .dependencies = dependencies;
+                Opened::Native(lib)
+            }
+            #[cfg(feature = "wasm")]
+            Opened::Wasm(mut plugin) => {
+                Arc::get_mut(&mut plugin)
+                    .expect("freshly opened module is not shared")
+                    .dependencies = dependencies;
+                Opened::Wasm(plugin)
+            }
+        }
     }
 }
 
-impl PluginManager {
-    /// Attempt to unload the library previously loaded from `path`.
-    /// If the manager is the only owner (strong_count == 1) this will
-    /// perform the unload immediately and return the plugin unmaker counter
-    /// if available. If there are other owners the manager will mark the
-    /// LoadedLib as closed so the final owner will perform the unload on Drop
-    /// and return None.
-    pub fn unload_by_path(&mut self, path: &std::path::Path) -> Result<Option<u64>, String> {
-        let mut i = 0usize;
-        while i < self.libs.len() {
-            if let Some(strong) = self.libs[i].upgrade() {
-                // compare path
-                if strong.path == path {
-                    // if manager is the only owner, try to take it and unload now
-                    if Arc::strong_count(&strong) == 1 {
-                        // remove this weak entry
-                        self.libs.remove(i);
-                        self.loaded_paths.remove(path);
-                        // Try to consume the Arc
-                        match Arc::try_unwrap(strong) {
-                            Ok(loaded) => return unload_loaded_lib(loaded),
-                            Err(_) => return Ok(None),
-                        }
-                    } else {
-                        // mark closed so the final owner will run unload on Drop
-                        strong
-                            .closed
-                            .store(true, std::sync::atomic::Ordering::SeqCst);
-                        self.loaded_paths.remove(path);
-                        // keep weak entry around; advance
-                        return Ok(None);
-                    }
-                } else {
-                    i += 1;
+/// Order `handles` highest priority first, then by registration name.
+fn sort_by_priority(handles: &mut [PluginHandle]) {
+    handles.sort_by_cached_key(|h| (std::cmp::Reverse(h.priority()), h.registration_name()));
+}
+
+/// How `open_candidate` opens a native library.
+#[derive(Clone)]
+struct OpenOptions {
+    /// Open a private copy that lives as long as the library.
+    shadow: bool,
+    flags: LoadFlags,
+    module_loaders: Vec<Arc<dyn ModuleLoader>>,
+    /// Run the library's self-test, see `PluginManager::set_self_test`.
+    self_test: bool,
+    /// Refuse instead of skipping, see `PluginManager::set_strict`.
+    strict: bool,
+    /// Offered in the handshake, see `PluginManager::set_host_features`.
+    host_features: Features,
+    /// Make only the registrations whose names this accepts, see
+    /// `PluginManager::load_plugins_filtered`.
+    impl_filter: Option<ImplFilter>,
+}
+
+/// Open the artifact at `path` and run its register function with `host`.
+/// Touches no manager state, so it can run on a loader thread.
+#[allow(clippy::arc_with_non_send_sync)]
+fn open_candidate(
+    path: &Path,
+    manifest: Option<PluginManifest>,
+    trait_id: PluginTrait,
+    host: Arc<SharedHostContext>,
+    opts: OpenOptions,
+) -> Result<Opened, PluginLoadError> {
+    #[cfg(feature = "wasm")]
+    if wasm::is_wasm_module(path) {
+        let mut plugin = WasmPlugin::load(path, trait_id, host).map_err(|e| {
+            trace_event!(warn, path = %path.display(), error = %e, "failed to instantiate wasm plugin");
+            PluginLoadError::Lib(e)
+        })?;
+        plugin.manifest = manifest;
+        return Ok(Opened::Wasm(Arc::new(plugin)));
+    }
+
+    let shadow = if opts.shadow {
+        Some(ShadowCopy::create(path).map_err(PluginLoadError::Io)?)
+    } else {
+        None
+    };
+    let open_path = shadow.as_ref().map_or(path, |s| s.path());
+    check_format(open_path, path)?;
+    let module_loader = opts.module_loaders.iter().find(|l| l.accepts(open_path));
+
+    // Try to open the library
+    let (lib, own_namespace) =
+        unsafe { loader::open(open_path, path, opts.flags) }.map_err(|e| {
+            trace_event!(warn, path = %path.display(), error = %e, "failed to open plugin");
+            PluginLoadError::Lib(e)
+        })?;
+
+    if let Some(module_loader) = module_loader {
+        let opened = modules::open_module(
+            &**module_loader,
+            lib,
+            open_path,
+            trait_id,
+            host,
+            path.to_path_buf(),
+        )
+        .map_err(|e| {
+            trace_event!(warn, path = %path.display(), error = %e, "failed to open module");
+            PluginLoadError::Lib(e)
+        })?;
+        return Ok(match opened {
+            Some(mut loaded) => {
+                loaded.own_namespace = own_namespace;
+                loaded.manifest = manifest;
+                loaded.shadow = shadow;
+                Opened::Native(Arc::new(loaded))
+            }
+            None => Opened::Nothing,
+        });
+    }
+
+    unsafe { handshake(&lib, path, opts.host_features)? };
+
+    if let Some(accept) = &opts.impl_filter {
+        let Some(mut loaded) = open_filtered(
+            lib,
+            path,
+            trait_id,
+            &host,
+            opts.self_test,
+            opts.strict,
+            &**accept,
+        )?
+        else {
+            return Ok(Opened::Nothing);
+        };
+        loaded.own_namespace = own_namespace;
+        loaded.manifest = manifest;
+        loaded.host_context = Some(host);
+        loaded.shadow = shadow;
+        loaded.impl_filter = Some(accept.clone());
+        return Ok(Opened::Native(Arc::new(loaded)));
+    }
+
+    // Negotiate the newest ABI level the library exports, preferring the
+    // aggregated register_all.
+    unsafe {
+        if let Some((f_all, abi)) =
+            find_versioned_symbol::<RegisterAllFn>(&lib, symbols::REGISTER_ALL, trait_id.as_str())
+        {
+            let unregister_all =
+                symbols::versioned_c(symbols::UNREGISTER_ALL, trait_id.as_str(), abi);
+            if opts.strict && lib.get::<*const ()>(unregister_all.as_bytes()).is_err() {
+                return skipped(
+                    path,
+                    true,
+                    format!(
+                        "exports no {} to release its registrations",
+                        symbol_name(&unregister_all)
+                    ),
+                );
+            }
+            let arr_ptr = call_register_all(*f_all, abi, host.as_ptr());
+            if arr_ptr.is_null() {
+                if let Some(reason) = registration_error(&lib) {
+                    trace_event!(warn, path = %path.display(), error = %reason, "plugin refused to register");
+                    return Err(PluginLoadError::Refused {
+                        path: path.to_path_buf(),
+                        reason,
+                    });
+                }
+                return skipped(
+                    path,
+                    opts.strict,
+                    "register function returned null".to_string(),
+                );
+            }
+            if opts.strict && (*arr_ptr).count == 0 {
+                if let Ok(f) = lib.get::<unsafe extern "C" fn(*const RegistrationArray)>(
+                    unregister_all.as_bytes(),
+                ) {
+                    f(arr_ptr);
+                }
+                return skipped(
+                    path,
+                    true,
+                    "register function returned no registrations".to_string(),
+                );
+            }
+            if let Err(reason) = check_layouts(arr_ptr, trait_id, abi) {
+                trace_event!(warn, path = %path.display(), error = %reason, "refused plugin layout");
+                if let Ok(f) = lib.get::<unsafe extern "C" fn(*const RegistrationArray)>(
+                    unregister_all.as_bytes(),
+                ) {
+                    f(arr_ptr);
                 }
+                return Err(PluginLoadError::IncompatibleLayout {
+                    path: path.to_path_buf(),
+                    reason,
+                });
+            }
+            let failed = if opts.self_test {
+                self_test_failures(&lib, trait_id, abi)
             } else {
-                // dead weak ref; remove
-                self.libs.remove(i);
+                0
+            };
+            if failed > 0 {
+                trace_event!(warn, path = %path.display(), failed, "plugin failed its self-test");
+                if let Ok(f) = lib.get::<unsafe extern "C" fn(*const RegistrationArray)>(
+                    unregister_all.as_bytes(),
+                ) {
+                    f(arr_ptr);
+                }
+                return Err(PluginLoadError::SelfTestFailed {
+                    path: path.to_path_buf(),
+                    failed,
+                });
+            }
+            notify_loaded(arr_ptr, trait_id, HostInfo::current());
+            let mut loaded = LoadedLib::new_with_lib(lib, arr_ptr, trait_id, path.to_path_buf());
+            loaded.abi_version = abi;
+            loaded.own_namespace = own_namespace;
+            loaded.manifest = manifest;
+            loaded.host_context = Some(host);
+            loaded.shadow = shadow;
+            return Ok(Opened::Native(Arc::new(loaded)));
+        }
+
+        // Fallback: single registration symbol
+        if let Some((f_single, abi)) = find_versioned_symbol::<
+            unsafe extern "C" fn() -> *const std::ffi::c_void,
+        >(&lib, symbols::REGISTER, trait_id.as_str())
+        {
+            let unregister = symbols::versioned_c(symbols::UNREGISTER, trait_id.as_str(), abi);
+            if opts.strict && lib.get::<*const ()>(unregister.as_bytes()).is_err() {
+                return skipped(
+                    path,
+                    true,
+                    format!(
+                        "exports no {} to release its registrations",
+                        symbol_name(&unregister)
+                    ),
+                );
             }
+            let reg_ptr = f_single();
+            if reg_ptr.is_null() {
+                return skipped(
+                    path,
+                    opts.strict,
+                    "register function returned null".to_string(),
+                );
+            }
+            if let Err(reason) = check_registration(reg_ptr, trait_id) {
+                trace_event!(warn, path = %path.display(), error = %reason, "refused plugin layout");
+                if let Ok(f) =
+                    lib.get::<unsafe extern "C" fn(*const std::ffi::c_void)>(unregister.as_bytes())
+                {
+                    f(reg_ptr);
+                }
+                return Err(PluginLoadError::IncompatibleLayout {
+                    path: path.to_path_buf(),
+                    reason,
+                });
+            }
+            // Build a host-owned RegistrationArray for the single registration.
+            let erased: Vec<*const std::ffi::c_void> = vec![reg_ptr];
+            let boxed_slice = erased.into_boxed_slice();
+            let regs_ptr = Box::into_raw(boxed_slice) as *const *const std::ffi::c_void;
+            let arr = Box::new(RegistrationArray::new(1, regs_ptr, std::ptr::null()));
+            let arr_ptr = Box::into_raw(arr);
+            notify_loaded(arr_ptr, trait_id, HostInfo::current());
+            let mut loaded = LoadedLib::new_host_owned(lib, arr_ptr, trait_id, path.to_path_buf());
+            loaded.abi_version = abi;
+            loaded.own_namespace = own_namespace;
+            loaded.manifest = manifest;
+            loaded.shadow = shadow;
+            return Ok(Opened::Native(Arc::new(loaded)));
         }
-        Ok(None)
     }
+    if manifest.is_some() {
+        return skipped(
+            path,
+            opts.strict,
+            format!(
+                "manifest lists {} but the library exports no register function for it",
+                trait_id.as_str()
+            ),
+        );
+    }
+    Ok(Opened::Nothing)
 }
 
-impl PluginManager {
-    pub fn new() -> Self {
-        Self {
-            libs: Vec::new(),
-            loaded_paths: HashSet::new(),
-        }
+/// Tell the library what the host `offers` through its `plugin_handshake_v1`
+/// and refuse it if it requires more. Libraries built before the handshake
+/// existed do not export it and are taken as requiring nothing.
+///
+/// # Safety
+/// `lib`'s `plugin_handshake_v1`, if any, must have the signature
+/// `#[plugin_aggregates]` gives it.
+unsafe fn handshake(
+    lib: &libloading::Library,
+    path: &Path,
+    offers: Features,
+) -> Result<(), PluginLoadError> {
+    let Ok(handshake) =
+        lib.get::<unsafe extern "C" fn(u64) -> u64>(symbols::c_name(symbols::HANDSHAKE).as_bytes())
+    else {
+        return Ok(());
+    };
+    let missing = Features(handshake(offers.bits())).difference(offers);
+    if missing.is_empty() {
+        return Ok(());
     }
+    trace_event!(warn, path = %path.display(), missing = %missing, "plugin requires features the host lacks");
+    Err(PluginLoadError::MissingFeatures {
+        path: path.to_path_buf(),
+        missing,
+    })
+}
 
-    #[allow(clippy::arc_with_non_send_sync)]
-    pub fn load_plugins(
-        &mut self,
-        dir: &Path,
-        trait_id: PluginTrait,
-    ) -> Result<Vec<PluginHandle>, PluginLoadError> {
-        let mut handles = Vec::new();
-        let read_dir = dir.read_dir().map_err(PluginLoadError::Io)?;
-        for entry in read_dir.flatten() {
-            let path = entry.path();
-            if !is_dynamic_library(path.as_path()) {
-                continue;
+/// What `open_candidate` returns for a library it would skip: nothing, or
+/// in strict mode `PluginLoadError::Anomaly` saying why.
+fn skipped(path: &Path, strict: bool, reason: String) -> Result<Opened, PluginLoadError> {
+    if !strict {
+        return Ok(Opened::Nothing);
+    }
+    trace_event!(warn, path = %path.display(), error = %reason, "refused plugin in strict mode");
+    Err(PluginLoadError::Anomaly {
+        path: path.to_path_buf(),
+        reason,
+    })
+}
+
+/// `symbol` without the trailing nul the lookups use.
+fn symbol_name(symbol: &str) -> &str {
+    symbol.trim_end_matches('\0')
+}
+
+/// The `open_candidate` path for `load_plugins_filtered`: make the
+/// registrations of the implementations `lib` lists whose names `accept`
+/// takes, without running its register function.
+fn open_filtered(
+    lib: libloading::Library,
+    path: &Path,
+    trait_id: PluginTrait,
+    host: &SharedHostContext,
+    self_test: bool,
+    strict: bool,
+    accept: &dyn Fn(&str) -> bool,
+) -> Result<Option<LoadedLib>, PluginLoadError> {
+    unsafe {
+        let Some((impls, abi)) = crate::impls::library_impls(&lib, trait_id, host.as_ptr()) else {
+            trace_event!(warn, path = %path.display(), "plugin lists no implementations to filter");
+            return skipped(
+                path,
+                strict,
+                "lists no implementations to filter".to_string(),
+            )
+            .map(|_| None);
+        };
+        if impls.is_empty() {
+            if let Some(reason) = registration_error(&lib) {
+                trace_event!(warn, path = %path.display(), error = %reason, "plugin refused to register");
+                return Err(PluginLoadError::Refused {
+                    path: path.to_path_buf(),
+                    reason,
+                });
             }
+        }
+        let arr_ptr = crate::impls::make_accepted(&impls, accept);
+        if arr_ptr.is_null() {
+            return Ok(None);
+        }
+        if let Err(reason) = check_layouts(arr_ptr, trait_id, abi) {
+            trace_event!(warn, path = %path.display(), error = %reason, "refused plugin layout");
+            crate::impls::release_accepted(arr_ptr);
+            return Err(PluginLoadError::IncompatibleLayout {
+                path: path.to_path_buf(),
+                reason,
+            });
+        }
+        let failed = if self_test {
+            self_test_failures(&lib, trait_id, abi)
+        } else {
+            0
+        };
+        if failed > 0 {
+            trace_event!(warn, path = %path.display(), failed, "plugin failed its self-test");
+            crate::impls::release_accepted(arr_ptr);
+            return Err(PluginLoadError::SelfTestFailed {
+                path: path.to_path_buf(),
+                failed,
+            });
+        }
+        notify_loaded(arr_ptr, trait_id, HostInfo::current());
+        let mut loaded = LoadedLib::new_host_owned(lib, arr_ptr, trait_id, path.to_path_buf());
+        loaded.abi_version = abi;
+        Ok(Some(loaded))
+    }
+}
 
-            if self.loaded_paths.contains(&path) {
-                continue;
+/// How many registrations failed the library's
+/// `plugin_selftest_<Trait>_v<abi>`; 0 if it exports none.
+unsafe fn self_test_failures(lib: &libloading::Library, trait_id: PluginTrait, abi: u32) -> u32 {
+    let symbol = symbols::versioned_c(symbols::SELFTEST, trait_id.as_str(), abi);
+    lib.get::<unsafe extern "C" fn() -> u32>(symbol.as_bytes())
+        .map_or(0, |self_test| self_test())
+}
+
+/// Call the library's `plugin_describe_v1` and copy the document out.
+unsafe fn read_description(
+    lib: &libloading::Library,
+    path: &Path,
+) -> Result<String, PluginLoadError> {
+    let describe = lib
+        .get::<unsafe extern "C" fn() -> *const std::os::raw::c_char>(
+            symbols::c_name(symbols::DESCRIBE).as_bytes(),
+        )
+        .map_err(|_| {
+            PluginLoadError::Lib(format!(
+                "{} exports no {}",
+                path.display(),
+                symbols::DESCRIBE
+            ))
+        })?;
+    let json = describe();
+    if json.is_null() {
+        return Err(PluginLoadError::Lib(format!(
+            "{} returned no description",
+            path.display()
+        )));
+    }
+    Ok(std::ffi::CStr::from_ptr(json)
+        .to_string_lossy()
+        .into_owned())
+}
+
+/// Fail with a descriptive error, rather than the loader's, if the file at
+/// `open_path` is not a dynamic library for this platform. Only the
+/// headers are read. Errors name `path`, the artifact it was copied from.
+fn check_format(open_path: &Path, path: &Path) -> Result<(), PluginLoadError> {
+    let file = std::fs::File::open(open_path).map_err(PluginLoadError::Io)?;
+    let cache = object::ReadCache::new(file);
+    let problem = match validate::sniff(&cache) {
+        Ok(_) => return Ok(()),
+        Err(problem) => problem,
+    };
+    trace_event!(warn, path = %path.display(), problem = %problem, "refused to open plugin");
+    let path = path.to_path_buf();
+    Err(match problem {
+        ValidationProblem::WrongArchitecture { expected, found } => {
+            PluginLoadError::WrongArchitecture {
+                path,
+                expected,
+                found,
             }
+        }
+        ValidationProblem::NotADynamicLibrary(found) => {
+            PluginLoadError::NotADynamicLibrary { path, found }
+        }
+        other => PluginLoadError::Lib(other.to_string()),
+    })
+}
 
-            // Try to open the library
-            let lib =
-                unsafe { Library::new(&path) }.map_err(|e| PluginLoadError::Lib(e.to_string()))?;
+/// A candidate that passed its checks, with the context to open it with.
+/// The library a registration belongs to and its manifest.
+type Owner<'a> = (&'a Path, Option<&'a PluginManifest>);
+type OpenJob = (Candidate, Arc<SharedHostContext>);
+type OpenResult = Result<Opened, PluginLoadError>;
 
-            // Build symbol name for aggregated register_all
-            let sym = format!("plugin_register_all_{}_v1\0", trait_id.as_str());
-            unsafe {
-                if let Ok(f_all) =
-                    lib.get::<unsafe extern "C" fn() -> *const RegistrationArray>(sym.as_bytes())
-                {
-                    let arr_ptr = f_all();
-                    if arr_ptr.is_null() {
-                        continue;
-                    }
-                    let loaded = Arc::new(LoadedLib::new_with_lib(
-                        lib,
-                        arr_ptr,
-                        trait_id,
-                        path.clone(),
-                    ));
-                    let count = (&*arr_ptr).count;
-                    for idx in 0..count {
-                        let h = PluginHandle::new(loaded.clone(), idx, trait_id);
-                        handles.push(h);
-                    }
-                    self.libs.push(Arc::downgrade(&loaded));
-                    self.loaded_paths.insert(path.clone());
-                    continue;
-                }
+/// Run `open_candidate` for every job on up to `threads` scoped threads and
+/// return the results in job order.
+fn open_all(
+    jobs: Vec<OpenJob>,
+    trait_id: PluginTrait,
+    threads: usize,
+    opts: OpenOptions,
+) -> Vec<OpenResult> {
+    if threads <= 1 || jobs.len() <= 1 {
+        return jobs
+            .into_iter()
+            .map(|(c, host)| open_candidate(&c.path, c.manifest, trait_id, host, opts.clone()))
+            .collect();
+    }
 
-                // Fallback: single registration symbol
-                let single_sym = format!("plugin_register_{}_v1\0", trait_id.as_str());
-                if let Ok(f_single) = lib
-                    .get::<unsafe extern "C" fn() -> *const std::ffi::c_void>(single_sym.as_bytes())
-                {
-                    let reg_ptr = f_single();
-                    if reg_ptr.is_null() {
-                        continue;
-                    }
-                    // Build a host-owned RegistrationArray for the single registration.
-                    let erased: Vec<*const std::ffi::c_void> = vec![reg_ptr];
-                    let boxed_slice = erased.into_boxed_slice();
-                    let regs_ptr = Box::into_raw(boxed_slice) as *const *const std::ffi::c_void;
-                    let arr = Box::new(RegistrationArray {
-                        count: 1,
-                        registrations: regs_ptr,
-                        factories: std::ptr::null(),
-                    });
-                    let arr_ptr = Box::into_raw(arr);
-                    let loaded = Arc::new(LoadedLib::new_host_owned(
-                        lib,
-                        arr_ptr,
-                        trait_id,
-                        path.clone(),
-                    ));
-                    let h = PluginHandle::new(loaded.clone(), 0, trait_id);
-                    handles.push(h);
-                    self.libs.push(Arc::downgrade(&loaded));
-                    self.loaded_paths.insert(path.clone());
-                    continue;
-                }
+    let slots: Vec<Mutex<Option<OpenJob>>> =
+        jobs.into_iter().map(|job| Mutex::new(Some(job))).collect();
+    let results: Vec<Mutex<Option<AssertSend<OpenResult>>>> =
+        slots.iter().map(|_| Mutex::new(None)).collect();
+    let next = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        for _ in 0..threads.min(slots.len()) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                let Some(slot) = slots.get(i) else {
+                    break;
+                };
+                let (c, host) = lock(slot).take().expect("each job is taken once");
+                let opened = open_candidate(&c.path, c.manifest, trait_id, host, opts.clone());
+                *lock(&results[i]) = Some(AssertSend(opened));
+            });
+        }
+    });
+    results
+        .into_iter()
+        .map(|r| {
+            r.into_inner()
+                .unwrap_or_else(|e| e.into_inner())
+                .expect("every job ran")
+                .0
+        })
+        .collect()
+}
+
+fn lock<T>(m: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(feature = "async")]
+impl PluginManager {
+    /// `load_plugins` for async hosts. Discovery and the pre-open checks
+    /// run on the caller; opening the libraries and running their register
+    /// functions, which can block for a while, runs on tokio's blocking
+    /// pool, one dependency wave at a time with up to
+    /// `set_load_parallelism` threads.
+    ///
+    /// The future is not `Send`, because neither the manager nor its handles
+    /// are; await it on the task that owns the manager, or use
+    /// `AsyncPluginManager`. Must be called within a tokio runtime.
+    pub async fn load_plugins_async(
+        &mut self,
+        search_path: impl Into<PluginSearchPath>,
+        trait_id: PluginTrait,
+    ) -> Result<Vec<PluginHandle>, PluginLoadError> {
+        let result = self.load_batch_async(search_path.into(), trait_id).await;
+        self.publish_err(None, result)
+    }
+
+    async fn load_batch_async(
+        &mut self,
+        search_path: PluginSearchPath,
+        trait_id: PluginTrait,
+    ) -> Result<Vec<PluginHandle>, PluginLoadError> {
+        let mut handles = Vec::new();
+        let policy = self.discovery.clone();
+        let ordered = self.ordered_candidates(search_path, trait_id, &policy, &|_| true)?;
+        for wave in deps::waves(ordered) {
+            let (jobs, failure) = self.prepare_wave(wave, trait_id);
+            let paths: Vec<PathBuf> = jobs.iter().map(|(c, _)| c.path.clone()).collect();
+            let (threads, opts) = (self.load_threads, self.open_options());
+            let jobs = AssertSend(jobs);
+            let results = run_blocking(move || {
+                let jobs = jobs;
+                AssertSend(open_all(jobs.0, trait_id, threads, opts))
+            })
+            .await
+            .0;
+            for (path, opened) in paths.into_iter().zip(results) {
+                // Later results are dropped, which unloads them again.
+                let opened =
+                    opened.inspect_err(|e| self.artifact_failed(&path, format!("{:?}", e)))?;
+                self.record_opened(
+                    path,
+                    opened,
+                    trait_id,
+                    &mut handles,
+                    ManagerEvent::Loaded,
+                    false,
+                )?;
+            }
+            if let Some(e) = failure {
+                return Err(e);
             }
         }
 
         if handles.is_empty() {
             return Err(PluginLoadError::NoRegistrations);
         }
-
         Ok(handles)
     }
+
+    /// `unload_by_path` for async hosts. Waiting for calls in flight and
+    /// closing the library run on tokio's blocking pool. Like
+    /// `load_plugins_async`, the future is not `Send`.
+    pub async fn unload_by_path_async(&mut self, path: &Path) -> Result<Option<u64>, String> {
+        let result = match self.unload_step(path) {
+            Ok(UnloadStep::Done(res)) => return Ok(res),
+            #[cfg(feature = "wasm")]
+            Ok(UnloadStep::Forgotten) => Ok(None),
+            Ok(UnloadStep::Now(loaded)) => {
+                let loaded = AssertSend(loaded);
+                let res = run_blocking(move || {
+                    let loaded = loaded;
+                    unload_loaded_lib(*loaded.0).map(unmaker_count)
+                })
+                .await;
+                trace_event!(info, path = %path.display(), result = ?res, "plugin unloaded");
+                res
+            }
+            Ok(UnloadStep::Deferred(strong)) => {
+                let waiting = AssertSend(strong.clone());
+                run_blocking(move || {
+                    let waiting = waiting;
+                    waiting.0.quiesce().map(drop)
+                })
+                .await
+                .map(|()| {
+                    self.close_deferred(path, &strong);
+                    None
+                })
+            }
+            Err(e) => Err(e),
+        };
+        self.publish_unload(path, &result);
+        result
+    }
+}
+
+/// Run `f` on tokio's blocking pool; a panic in `f` resumes on the caller.
+#[cfg(feature = "async")]
+async fn run_blocking<T, F>(f: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(value) => value,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
 }
 
 #[cfg(feature = "watch")]
@@ -191,13 +3852,41 @@ pub enum PluginEvent {
 }
 
 #[cfg(feature = "watch")]
-/// Event delivered to the synchronous watcher callback. Either raw
-/// PluginHandle values or typed GreeterProxy wrappers (when available)
-/// are delivered depending on `WatchOptions`.
+/// What happened to one plugin file, as delivered to watcher callbacks.
+/// Every variant names the file and carries the handles that result from
+/// the manager's reaction to it; use `PluginHandle::as_greeter` for typed
+/// proxies.
 #[derive(Debug)]
 pub enum WatchEvent {
-    Handles(Vec<PluginHandle>, Vec<PathBuf>),
-    Proxies(Vec<crate::GreeterProxy>, Vec<PathBuf>),
+    /// A new plugin file appeared. `handles` are what `auto_load` loaded
+    /// from it, empty when it is off or loading failed.
+    Added {
+        path: PathBuf,
+        handles: Vec<PluginHandle>,
+    },
+    /// A known plugin file changed and was not reloaded. `handles` are the
+    /// plugins loaded from it, if any: the unchanged ones when `auto_reload`
+    /// is off, or freshly loaded ones when an earlier load had failed.
+    Modified {
+        path: PathBuf,
+        handles: Vec<PluginHandle>,
+    },
+    /// A plugin file was removed. `handles` are the plugins still loaded
+    /// from it, empty once `auto_unload` has unloaded it. `counter` is the
+    /// unmaker counter when that unload happened immediately.
+    Removed {
+        path: PathBuf,
+        handles: Vec<PluginHandle>,
+        counter: Option<u64>,
+    },
+    /// A loaded plugin's artifact changed and `auto_reload` replaced it via
+    /// `reload_by_path`. `old` are handles to the replaced version, which
+    /// are stale; `new` are handles to the version now loaded.
+    Reloaded {
+        path: PathBuf,
+        old: Vec<PluginHandle>,
+        new: Vec<PluginHandle>,
+    },
 }
 
 #[cfg(feature = "watch")]
@@ -206,11 +3895,18 @@ impl PluginManager {
     /// a `PluginEvent::NewPlugin(PathBuf)` for each new file found. This is
     /// implemented with a simple polling loop to avoid adding heavy
     /// platform-specific watcher dependencies. The polling loop runs in a
-    /// background thread and returns a Receiver to receive events; caller
-    /// should drop the Receiver to stop listening (the thread will continue
-    /// until the process exits).
-    pub fn watch_plugins(&mut self, dir: PathBuf, _trait_id: PluginTrait) -> Receiver<PluginEvent> {
+    /// background thread and returns a Receiver to receive events, and a
+    /// guard that stops the thread when dropped. The thread also exits when
+    /// it finds a new file after the Receiver was dropped.
+    pub fn watch_plugins(
+        &mut self,
+        dir: PathBuf,
+        _trait_id: PluginTrait,
+    ) -> (Receiver<PluginEvent>, WatchGuard) {
         let (tx, rx) = mpsc::channel();
+        let control = WatchControl::new(self.shutdown.clone());
+        let state = control.state.clone();
+        let policy = self.discovery.clone();
 
         // build a thread-local seen set to avoid notifying for files that
         // already exist when the watcher starts
@@ -218,44 +3914,46 @@ impl PluginManager {
         if let Ok(read_dir) = dir.read_dir() {
             for e in read_dir.flatten() {
                 let p = e.path();
-                if is_dynamic_library(p.as_path()) {
+                if policy.matches(&p) {
                     seen.insert(p);
                 }
             }
         }
 
-        let tx_clone = tx.clone();
-        thread::spawn(move || {
+        let handle = thread::spawn(move || {
             let mut seen = seen;
-            loop {
+            while !state.is_stopped() {
                 if let Ok(read_dir) = dir.read_dir() {
                     for e in read_dir.flatten() {
                         let p = e.path();
-                        if !is_dynamic_library(p.as_path()) {
+                        if !policy.matches(&p) {
                             continue;
                         }
                         if seen.contains(&p) {
                             continue;
                         }
                         seen.insert(p.clone());
-                        // try to send for new files
-                        let _ = tx_clone.send(PluginEvent::NewPlugin(p.clone()));
+                        // the receiver is gone: nobody is listening
+                        if tx.send(PluginEvent::NewPlugin(p.clone())).is_err() {
+                            return;
+                        }
                     }
                 }
                 thread::sleep(Duration::from_millis(500));
             }
         });
 
-        rx
+        (rx, WatchGuard::new(control, handle))
     }
 
     // ...existing code...
 
     /// Watch `dir` and call `load_plugins` internally when new dynamic
     /// libraries appear. The provided callback is invoked on the same thread
-    /// that called this method; it receives a Vec of loaded `PluginHandle`s
-    /// (may be empty on error or when `auto_load` is false) and a Vec of the
-    /// file paths that triggered the event. Return `true` from the callback
+    /// that called this method with one `WatchEvent` per added, modified,
+    /// removed or reloaded file. Failed loads and reloads are traced and
+    /// still produce an event for the file; failed loads are retried as
+    /// `WatchOptions::load_retries` says. Return `true` from the callback
     /// to continue watching, or `false` to stop.
     pub fn watch_and_load_blocking<F>(
         &mut self,
@@ -268,12 +3966,20 @@ impl PluginManager {
     {
         use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
 
+        let filter = match PathFilter::new(&opts.include, &opts.exclude) {
+            Ok(f) => f,
+            Err(e) => {
+                eprintln!("watcher error: {}", e);
+                return;
+            }
+        };
+
         // initial seen set
         let mut seen: HashSet<PathBuf> = HashSet::new();
         if let Ok(read_dir) = dir.read_dir() {
             for e in read_dir.flatten() {
                 let p = e.path();
-                if is_dynamic_library(p.as_path()) {
+                if opts.discovery.matches(&p) {
                     seen.insert(p);
                 }
             }
@@ -305,46 +4011,35 @@ impl PluginManager {
             return;
         }
 
-        let mut debounce_map: std::collections::HashMap<PathBuf, std::time::Instant> =
-            std::collections::HashMap::new();
+        let mut pending = PendingFiles::new(opts.debounce_ms, opts.stable_ms);
 
         loop {
             match raw_rx.recv_timeout(Duration::from_millis(100)) {
                 Ok(Ok(event)) => {
-                    // handle create/modify as potential new plugin candidates
+                    // create/modify: new files are added, known ones modified
                     if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
                         for path in event.paths.iter() {
-                            if !is_dynamic_library(path) {
-                                continue;
-                            }
-                            if seen.contains(path) {
+                            if !opts.discovery.matches(path) || !filter.allows(&dir, path) {
                                 continue;
                             }
-                            debounce_map.insert(path.clone(), std::time::Instant::now());
+                            pending.touch(path.clone(), std::time::Instant::now());
                         }
                     }
 
                     // handle remove events: attempt to unload if requested and notify via callback
                     if matches!(event.kind, EventKind::Remove(_)) {
                         for path in event.paths.iter() {
-                            if !is_dynamic_library(path) {
+                            if !opts.discovery.matches(path) || !filter.allows(&dir, path) {
                                 continue;
                             }
-                            // if requested, attempt to unload now on this same thread
-                            if opts.auto_unload {
-                                let _ = self.unload_by_path(path);
-                            }
-                            // inform callback of removal; send empty Handles or Proxies
-                            if opts.emit_proxies && trait_id == PluginTrait::Greeter {
-                                let cont =
-                                    callback(WatchEvent::Proxies(Vec::new(), vec![path.clone()]));
-                                if !cont {
-                                    return;
-                                }
-                            } else {
-                                let cont =
-                                    callback(WatchEvent::Handles(Vec::new(), vec![path.clone()]));
-                                if !cont {
+                            trace_event!(debug, path = %path.display(), "watch: plugin file removed");
+                            seen.remove(path);
+                            for event in self
+                                .watch_removed(path.clone(), &opts)
+                                .into_iter()
+                                .flatten()
+                            {
+                                if !callback(event) {
                                     return;
                                 }
                             }
@@ -353,74 +4048,28 @@ impl PluginManager {
                 }
                 Ok(Err(_)) => {}
                 Err(mpsc::RecvTimeoutError::Timeout) => {
-                    let now = std::time::Instant::now();
-                    let mut ready: Vec<PathBuf> = Vec::new();
-                    let debounce_ms = opts.debounce_ms;
-                    debounce_map.retain(|p, t| {
-                        if now.duration_since(*t).as_millis() as u64 >= debounce_ms {
-                            ready.push(p.clone());
-                            false
-                        } else {
-                            true
-                        }
-                    });
+                    let ready = pending.ready(std::time::Instant::now());
 
                     if !ready.is_empty() {
-                        // mark seen and either auto-load or just report paths
-                        for p in ready.iter() {
-                            seen.insert(p.clone());
-                        }
-
-                        if opts.auto_load {
-                            // attempt to load plugins from dir; ignore errors and
-                            // pass empty handles on error.
-                            match self.load_plugins(&dir, trait_id) {
-                                Ok(handles) => {
-                                    if opts.emit_proxies && trait_id == PluginTrait::Greeter {
-                                        let proxies: Vec<crate::GreeterProxy> =
-                                            handles.iter().filter_map(|h| h.as_greeter()).collect();
-                                        let cont =
-                                            callback(WatchEvent::Proxies(proxies, ready.clone()));
-                                        if !cont {
-                                            break;
-                                        }
-                                    } else {
-                                        let cont =
-                                            callback(WatchEvent::Handles(handles, ready.clone()));
-                                        if !cont {
-                                            break;
-                                        }
-                                    }
-                                }
-                                Err(_) => {
-                                    if opts.emit_proxies && trait_id == PluginTrait::Greeter {
-                                        let cont = callback(WatchEvent::Proxies(
-                                            Vec::new(),
-                                            ready.clone(),
-                                        ));
-                                        if !cont {
-                                            break;
-                                        }
-                                    } else {
-                                        let cont = callback(WatchEvent::Handles(
-                                            Vec::new(),
-                                            ready.clone(),
-                                        ));
-                                        if !cont {
-                                            break;
-                                        }
-                                    }
-                                }
-                            }
-                        } else if opts.emit_proxies && trait_id == PluginTrait::Greeter {
-                            let cont = callback(WatchEvent::Proxies(Vec::new(), ready.clone()));
-                            if !cont {
-                                break;
+                        trace_event!(debug, paths = ?ready, "watch: changed plugin files");
+                        let (modified, added): (Vec<PathBuf>, Vec<PathBuf>) =
+                            ready.into_iter().partition(|p| seen.contains(p));
+                        seen.extend(added.iter().cloned());
+                        for event in self
+                            .watch_events(&dir, trait_id, &opts, &filter, added, modified)
+                            .into_iter()
+                            .flatten()
+                        {
+                            if !callback(event) {
+                                return;
                             }
-                        } else {
-                            let cont = callback(WatchEvent::Handles(Vec::new(), ready.clone()));
-                            if !cont {
-                                break;
+                        }
+                    }
+                    let retried = self.retry_loads(&dir, trait_id, &opts, &filter);
+                    for notification in retried {
+                        if let ManagerNotification::Event(event) = notification {
+                            if !callback(event) {
+                                return;
                             }
                         }
                     }
@@ -438,8 +4087,11 @@ impl PluginManager {
 /// safely transmitted across thread boundaries.
 #[derive(Debug)]
 pub enum WatchNotification {
-    /// One or more discovered paths that passed the debounce window.
+    /// One or more new paths that passed the debounce and stability windows.
     Paths(Vec<PathBuf>),
+    /// One or more paths the watcher already knew that changed and passed
+    /// the debounce and stability windows.
+    Modified(Vec<PathBuf>),
     /// A library path was removed (or otherwise considered removed) and the
     /// watcher observed it; the optional counter is the result of attempting
     /// to deterministically unload the library (manager must perform unload).
@@ -448,11 +4100,142 @@ pub enum WatchNotification {
     Error(String),
 }
 
+#[cfg(feature = "watch")]
+/// A `WatchNotification` from a session watching several directories,
+/// tagged with the watched directory it came from.
+#[derive(Debug)]
+pub struct DirNotification {
+    pub dir: PathBuf,
+    pub notification: WatchNotification,
+}
+
+#[cfg(feature = "watch")]
+/// Controls a background watcher thread started by `start_watch_background`
+/// or `start_watch_background_dirs`. Clones control the same thread.
+#[derive(Debug, Clone)]
+pub struct WatchControl {
+    state: Arc<WatchControlState>,
+}
+
+#[cfg(feature = "watch")]
+#[derive(Debug, Default)]
+struct WatchControlState {
+    paused: std::sync::atomic::AtomicBool,
+    stopped: std::sync::atomic::AtomicBool,
+    /// The signal of the manager that started the thread, which stops it
+    /// too.
+    shutdown: ShutdownSignal,
+}
+
+#[cfg(feature = "watch")]
+impl WatchControlState {
+    fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst) || self.shutdown.is_requested()
+    }
+}
+
+#[cfg(feature = "watch")]
+impl WatchControl {
+    fn new(shutdown: ShutdownSignal) -> Self {
+        Self {
+            state: Arc::new(WatchControlState {
+                shutdown,
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// Hold back notifications, for example during a bulk deployment. The
+    /// thread keeps recording filesystem events and coalesces them per
+    /// file: a file removed and written again while paused is reported as
+    /// modified, not as removed and added.
+    pub fn pause(&self) {
+        self.state.paused.store(true, Ordering::SeqCst);
+    }
+
+    /// Send what was held back while paused. Removals go out at once;
+    /// changed files once they have settled like any other.
+    pub fn resume(&self) {
+        self.state.paused.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.paused.load(Ordering::SeqCst)
+    }
+
+    /// Stop the thread; it exits within about 100 ms and closes the
+    /// notification channel.
+    pub fn stop(&self) {
+        self.state.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+#[cfg(feature = "watch")]
+/// Owns a background watcher thread. Dropping the guard stops the thread
+/// and waits for it to exit, so keep it for as long as the watcher should
+/// run.
+#[derive(Debug)]
+#[must_use = "dropping the guard stops the watcher"]
+pub struct WatchGuard {
+    control: WatchControl,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+#[cfg(feature = "watch")]
+impl WatchGuard {
+    pub(crate) fn new(control: WatchControl, thread: std::thread::JoinHandle<()>) -> Self {
+        Self {
+            control,
+            thread: Some(thread),
+        }
+    }
+
+    /// The thread's `WatchControl`, to pause or resume it from elsewhere.
+    pub fn control(&self) -> &WatchControl {
+        &self.control
+    }
+
+    /// Whether the thread has exited, for example after a watch error or
+    /// once nobody was left to receive its notifications.
+    pub fn is_finished(&self) -> bool {
+        self.thread.as_ref().is_none_or(|t| t.is_finished())
+    }
+
+    /// Stop the thread and wait for it, returning its panic if it had one.
+    pub fn stop(mut self) -> std::thread::Result<()> {
+        self.control.stop();
+        self.thread.take().map_or(Ok(()), |t| t.join())
+    }
+}
+
+#[cfg(feature = "watch")]
+impl Drop for WatchGuard {
+    fn drop(&mut self) {
+        self.control.stop();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(feature = "watch")]
+/// Per-directory state of a background watcher thread.
+struct WatchedDir {
+    dir: PathBuf,
+    opts: WatchOptions,
+    seen: HashSet<PathBuf>,
+    pending: PendingFiles,
+    /// Files removed while paused, in the order they went.
+    removed: Vec<PathBuf>,
+}
+
 #[cfg(feature = "watch")]
 impl PluginManager {
     /// Start watching `dir` in a background thread for filesystem events and
-    /// return a Receiver of conservative notifications plus the JoinHandle for
-    /// the spawned thread. The background watcher does NOT attempt to call
+    /// return a Receiver of conservative notifications and a `WatchGuard`
+    /// that owns the thread: its `control()` pauses and resumes it, and
+    /// dropping it stops the thread and joins it. The thread also exits once
+    /// the Receiver is dropped. The background watcher does NOT attempt to call
     /// `load_plugins` or `unload_by_path` on the manager because the manager
     /// may not be Send/Sync; instead it emits path-level notifications which
     /// the caller can handle on the thread owning the manager (for example by
@@ -462,144 +4245,409 @@ impl PluginManager {
         &mut self,
         dir: PathBuf,
         opts: WatchOptions,
-    ) -> (
-        Receiver<WatchNotification>,
-        std::sync::mpsc::Sender<()>,
-        std::thread::JoinHandle<()>,
-    ) {
+    ) -> (Receiver<WatchNotification>, WatchGuard) {
         let (tx, rx) = mpsc::channel::<WatchNotification>();
-        let (stop_tx, stop_rx) = mpsc::channel::<()>();
+        let guard = spawn_watch_thread(
+            vec![(dir, opts)],
+            self.shutdown.clone(),
+            move |_, notification| tx.send(notification).is_ok(),
+        );
+        (rx, guard)
+    }
 
-        // build a thread-local seen set to avoid notifying for files that
-        // already exist when the watcher starts
-        let mut seen: HashSet<PathBuf> = HashSet::new();
-        if let Ok(read_dir) = dir.read_dir() {
-            for e in read_dir.flatten() {
-                let p = e.path();
-                if is_dynamic_library(&p) {
-                    seen.insert(p);
+    /// Like `start_watch_background`, but one thread and one channel serve
+    /// every directory in `dirs`, each with its own options. Notifications
+    /// are tagged with the directory they concern; a file under several of
+    /// the directories belongs to the deepest one. Pass the receiver and
+    /// the same `dirs` to `process_dir_notifications_blocking`.
+    pub fn start_watch_background_dirs(
+        &mut self,
+        dirs: Vec<(PathBuf, WatchOptions)>,
+    ) -> (Receiver<DirNotification>, WatchGuard) {
+        let (tx, rx) = mpsc::channel::<DirNotification>();
+        let guard = spawn_watch_thread(dirs, self.shutdown.clone(), move |dir, notification| {
+            tx.send(DirNotification {
+                dir: dir.to_path_buf(),
+                notification,
+            })
+            .is_ok()
+        });
+        (rx, guard)
+    }
+}
+
+#[cfg(feature = "watch")]
+/// Spawn the thread behind the background watcher APIs. It watches every
+/// directory in `dirs` with one platform watcher and hands each
+/// notification to `send` with the directory it concerns. The thread exits
+/// when `send` returns false because nobody is receiving any more, or once
+/// `shutdown` is requested.
+pub(crate) fn spawn_watch_thread<S>(
+    dirs: Vec<(PathBuf, WatchOptions)>,
+    shutdown: ShutdownSignal,
+    send: S,
+) -> WatchGuard
+where
+    S: Fn(&Path, WatchNotification) -> bool + Send + 'static,
+{
+    let control = WatchControl::new(shutdown);
+    let state = control.state.clone();
+
+    // build the seen sets here, not on the thread, to avoid notifying for
+    // files that already exist when the watcher starts
+    let mut watched: Vec<WatchedDir> = dirs
+        .into_iter()
+        .map(|(dir, opts)| {
+            let mut seen: HashSet<PathBuf> = HashSet::new();
+            if let Ok(read_dir) = dir.read_dir() {
+                for e in read_dir.flatten() {
+                    let p = e.path();
+                    if opts.discovery.matches(&p) {
+                        seen.insert(p);
+                    }
                 }
             }
-        }
+            let pending = PendingFiles::new(opts.debounce_ms, opts.stable_ms);
+            WatchedDir {
+                dir,
+                opts,
+                seen,
+                pending,
+                removed: Vec::new(),
+            }
+        })
+        .collect();
 
-        // Spawn the watcher thread. The thread only sends conservative
-        // notifications back to the caller via the channel.
-        let thread_dir = dir.clone();
-        let handle = thread::spawn(move || {
-            use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
-
-            let (raw_tx, raw_rx) = mpsc::channel();
-            let mut watcher: RecommendedWatcher = match RecommendedWatcher::new(
-                move |res: Result<notify::Event, notify::Error>| {
-                    let _ = raw_tx.send(res);
-                },
-                notify::Config::default(),
-            ) {
-                Ok(w) => w,
+    // Spawn the watcher thread. The thread only sends conservative
+    // notifications back to the caller.
+    let handle = thread::spawn(move || {
+        use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+        let mut filters = Vec::with_capacity(watched.len());
+        for w in &watched {
+            match PathFilter::new(&w.opts.include, &w.opts.exclude) {
+                Ok(f) => filters.push(f),
                 Err(e) => {
-                    let _ = tx.send(WatchNotification::Error(format!(
-                        "failed to create watcher: {}",
-                        e
-                    )));
+                    let _ = send(&w.dir, WatchNotification::Error(e));
                     return;
                 }
-            };
+            }
+        }
+
+        let (raw_tx, raw_rx) = mpsc::channel();
+        let mut watcher: RecommendedWatcher = match RecommendedWatcher::new(
+            move |res: Result<notify::Event, notify::Error>| {
+                let _ = raw_tx.send(res);
+            },
+            notify::Config::default(),
+        ) {
+            Ok(w) => w,
+            Err(e) => {
+                for w in &watched {
+                    let _ = send(
+                        &w.dir,
+                        WatchNotification::Error(format!("failed to create watcher: {}", e)),
+                    );
+                }
+                return;
+            }
+        };
 
-            let mode = if opts.recursive {
+        for w in &watched {
+            let mode = if w.opts.recursive {
                 RecursiveMode::Recursive
             } else {
                 RecursiveMode::NonRecursive
             };
-
-            if let Err(e) = watcher.watch(&thread_dir, mode) {
-                let _ = tx.send(WatchNotification::Error(format!(
-                    "failed to watch dir {:?}: {}",
-                    thread_dir, e
-                )));
+            if let Err(e) = watcher.watch(&w.dir, mode) {
+                let _ = send(
+                    &w.dir,
+                    WatchNotification::Error(format!("failed to watch dir {:?}: {}", w.dir, e)),
+                );
                 return;
             }
+        }
 
-            let mut debounce_map: std::collections::HashMap<PathBuf, std::time::Instant> =
-                std::collections::HashMap::new();
-
-            loop {
-                if stop_rx.try_recv().is_ok() {
-                    break;
+        let mut was_paused = false;
+        loop {
+            if state.is_stopped() {
+                break;
+            }
+            let paused = state.paused.load(Ordering::SeqCst);
+            if was_paused && !paused {
+                for w in watched.iter_mut() {
+                    for path in std::mem::take(&mut w.removed) {
+                        w.seen.remove(&path);
+                        let sent = send(
+                            &w.dir,
+                            WatchNotification::Unloaded {
+                                path,
+                                counter: None,
+                            },
+                        );
+                        if !sent {
+                            return;
+                        }
+                    }
                 }
-                match raw_rx.recv_timeout(Duration::from_millis(100)) {
-                    Ok(Ok(event)) => {
+            }
+            was_paused = paused;
+            match raw_rx.recv_timeout(Duration::from_millis(100)) {
+                Ok(Ok(event)) => {
+                    for path in event.paths.iter() {
+                        // the deepest watched directory containing the file
+                        let Some(i) = (0..watched.len())
+                            .filter(|&i| path.starts_with(&watched[i].dir))
+                            .max_by_key(|&i| watched[i].dir.components().count())
+                        else {
+                            continue;
+                        };
+                        let w = &mut watched[i];
+                        if !w.opts.discovery.matches(path) || !filters[i].allows(&w.dir, path) {
+                            continue;
+                        }
+
                         if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
-                            for path in event.paths.iter() {
-                                if !is_dynamic_library(path.as_path()) {
-                                    continue;
-                                }
-                                if seen.contains(path) {
-                                    continue;
-                                }
-                                debounce_map.insert(path.clone(), std::time::Instant::now());
-                            }
+                            // written again after a removal held back by a
+                            // pause: a change to a known file
+                            w.removed.retain(|p| p != path);
+                            w.pending.touch(path.clone(), std::time::Instant::now());
                         }
 
                         if matches!(event.kind, EventKind::Remove(_)) {
-                            for path in event.paths.iter() {
-                                if !is_dynamic_library(path.as_path()) {
-                                    continue;
+                            trace_event!(debug, path = %path.display(), "watch: plugin file removed");
+                            w.pending.forget(path);
+                            if paused {
+                                // files never reported need no removal
+                                if w.seen.contains(path) && !w.removed.contains(path) {
+                                    w.removed.push(path.clone());
                                 }
-                                // report removal to caller; caller may call
-                                // `unload_by_path` on the manager if desired.
-                                let _ = tx.send(WatchNotification::Unloaded {
+                                continue;
+                            }
+                            w.seen.remove(path);
+                            // report removal to caller; caller may call
+                            // `unload_by_path` on the manager if desired.
+                            let sent = send(
+                                &w.dir,
+                                WatchNotification::Unloaded {
                                     path: path.clone(),
                                     counter: None,
-                                });
+                                },
+                            );
+                            if !sent {
+                                return;
                             }
                         }
                     }
-                    Ok(Err(_)) => {}
-                    Err(mpsc::RecvTimeoutError::Timeout) => {
-                        let now = std::time::Instant::now();
-                        let mut ready: Vec<PathBuf> = Vec::new();
-                        let debounce_ms = opts.debounce_ms;
-                        debounce_map.retain(|p, t| {
-                            if now.duration_since(*t).as_millis() as u64 >= debounce_ms {
-                                ready.push(p.clone());
-                                false
-                            } else {
-                                true
-                            }
-                        });
-
-                        if !ready.is_empty() {
-                            for p in ready.iter() {
-                                seen.insert(p.clone());
+                }
+                Ok(Err(_)) => {}
+                Err(mpsc::RecvTimeoutError::Timeout) if paused => {}
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    let now = std::time::Instant::now();
+                    for w in watched.iter_mut() {
+                        let ready = w.pending.ready(now);
+                        if ready.is_empty() {
+                            continue;
+                        }
+                        trace_event!(debug, paths = ?ready, "watch: changed plugin files");
+                        let (modified, added): (Vec<PathBuf>, Vec<PathBuf>) =
+                            ready.into_iter().partition(|p| w.seen.contains(p));
+                        if !added.is_empty() {
+                            w.seen.extend(added.iter().cloned());
+                            if !send(&w.dir, WatchNotification::Paths(added)) {
+                                return;
                             }
-                            let _ = tx.send(WatchNotification::Paths(ready));
+                        }
+                        if !modified.is_empty()
+                            && !send(&w.dir, WatchNotification::Modified(modified))
+                        {
+                            return;
                         }
                     }
-                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
                 }
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
             }
-        });
+        }
+    });
 
-        (rx, stop_tx, handle)
+    WatchGuard::new(control, handle)
+}
+
+#[cfg(feature = "watch")]
+/// The next message on `rx`, waiting at most `timeout` if there is one.
+fn receive<T>(rx: &Receiver<T>, timeout: Option<Duration>) -> Result<T, mpsc::RecvTimeoutError> {
+    match timeout {
+        Some(timeout) => rx.recv_timeout(timeout),
+        None => rx.recv().map_err(|_| mpsc::RecvTimeoutError::Disconnected),
     }
 }
 
 #[cfg(feature = "watch")]
 /// Notifications emitted by manager when it processes watch events.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum ManagerNotification {
     Event(WatchEvent),
-    Unloaded { path: PathBuf, counter: Option<u64> },
+    /// A load, reload or unload failed. The file involved still gets an
+    /// `Event`.
     Error(String),
+    /// An artifact failed its digest check while loading new plugins.
+    #[cfg(feature = "pinning")]
+    DigestMismatch(DigestMismatch),
+    /// A library failed its probe (see `PluginManager::set_probe`) and was
+    /// not loaded.
+    #[cfg(feature = "isolation")]
+    ProbeFailed(ProbeFailure),
 }
 
 #[cfg(feature = "watch")]
 impl PluginManager {
+    /// Handles for every registration loaded from `path`.
+    fn handles_for(&self, path: &Path) -> Vec<PluginHandle> {
+        self.live_plugins()
+            .into_iter()
+            .find(|handles| handles.first().is_some_and(|h| h.path() == path))
+            .unwrap_or_default()
+    }
+
+    /// Events for files that passed the watch windows: `added` ones the
+    /// watcher had not seen before, `modified` ones it had. Loads and
+    /// reloads as `opts` asks; a failure is returned as an error message
+    /// ahead of the event for its file.
+    fn watch_events(
+        &mut self,
+        dir: &Path,
+        trait_id: PluginTrait,
+        opts: &WatchOptions,
+        filter: &PathFilter,
+        added: Vec<PathBuf>,
+        modified: Vec<PathBuf>,
+    ) -> Vec<Result<WatchEvent, String>> {
+        let mut results = Vec::new();
+        let mut changed = Vec::new();
+        for path in modified {
+            if !(opts.auto_reload && self.is_loaded(&path)) {
+                changed.push(path);
+                continue;
+            }
+            let old = self.handles_for(&path);
+            match self.reload_by_path(&path) {
+                Ok(new) => results.push(Ok(WatchEvent::Reloaded { path, old, new })),
+                Err(e) => {
+                    trace_event!(warn, path = %path.display(), error = ?e, "watch: reload failed");
+                    results.push(Err(format!("reload error: {:?}", e)));
+                    changed.push(path);
+                }
+            }
+        }
+
+        // A changed file that is not loaded may be a fixed version of one
+        // that failed to load before, so it is loaded like a new one.
+        let mut loaded = Vec::new();
+        let unloaded: Vec<PathBuf> = added
+            .iter()
+            .chain(&changed)
+            .filter(|p| !self.is_loaded(p))
+            .cloned()
+            .collect();
+        if opts.auto_load && !unloaded.is_empty() {
+            let keep = |c: &Candidate| filter.allows(dir, &c.path);
+            let failed = match self.load_plugins_where(dir.into(), trait_id, &opts.discovery, &keep)
+            {
+                Ok(handles) => {
+                    loaded = handles;
+                    false
+                }
+                Err(e) => {
+                    trace_event!(warn, error = ?e, "watch: loading new plugins failed");
+                    results.push(Err(format!("load error: {:?}", e)));
+                    true
+                }
+            };
+            // The files the failed load left out may just not be ready yet.
+            let now = std::time::Instant::now();
+            for path in unloaded {
+                if !failed || self.is_loaded(&path) {
+                    self.load_retries.forget(&path);
+                } else if opts.load_retries == 0 {
+                    continue;
+                } else if let Some(failures) =
+                    self.load_retries.failed(dir, path.clone(), opts, now)
+                {
+                    results.push(Err(format!(
+                        "giving up on {:?} after {} failed loads",
+                        path, failures
+                    )));
+                }
+            }
+        }
+
+        fn take_for(handles: &mut Vec<PluginHandle>, path: &Path) -> Vec<PluginHandle> {
+            let (mine, rest) = std::mem::take(handles)
+                .into_iter()
+                .partition(|h| h.path() == path);
+            *handles = rest;
+            mine
+        }
+        for path in changed {
+            let mut handles = take_for(&mut loaded, &path);
+            if handles.is_empty() {
+                handles = self.handles_for(&path);
+            }
+            results.push(Ok(WatchEvent::Modified { path, handles }));
+        }
+        for path in added {
+            let handles = take_for(&mut loaded, &path);
+            results.push(Ok(WatchEvent::Added { path, handles }));
+        }
+        // load_plugins also picks up files no event named, such as ones the
+        // watcher missed; report those as added too.
+        while let Some(path) = loaded.first().map(|h| h.path().to_path_buf()) {
+            let handles = take_for(&mut loaded, &path);
+            results.push(Ok(WatchEvent::Added { path, handles }));
+        }
+        #[cfg(feature = "metrics")]
+        self.watch_events.count(&results);
+        results
+    }
+
+    /// The event for a removed file, unloading it first under
+    /// `auto_unload`; an unload failure comes first as an error message.
+    fn watch_removed(
+        &mut self,
+        path: PathBuf,
+        opts: &WatchOptions,
+    ) -> Vec<Result<WatchEvent, String>> {
+        let mut results = Vec::new();
+        let mut counter = None;
+        self.load_retries.forget(&path);
+        if opts.auto_unload {
+            match self.unload_by_path(&path) {
+                Ok(c) => counter = c,
+                Err(e) => {
+                    trace_event!(warn, path = %path.display(), error = %e, "watch: unload failed");
+                    results.push(Err(e));
+                }
+            }
+        }
+        let handles = self.handles_for(&path);
+        results.push(Ok(WatchEvent::Removed {
+            path,
+            handles,
+            counter,
+        }));
+        #[cfg(feature = "metrics")]
+        self.watch_events.count(&results);
+        results
+    }
+
     /// Process watch notifications produced by `start_watch_background`.
     /// This method runs on the caller's thread and calls `load_plugins` and
     /// `unload_by_path` on the manager as events arrive. The provided
     /// callback is invoked with `ManagerNotification` for each manager action;
-    /// return false from the callback to stop processing and return.
+    /// return false from the callback to stop processing and return. Files
+    /// that failed to load are retried in between notifications, as
+    /// `WatchOptions::load_retries` says.
     pub fn process_watch_notifications_blocking<F>(
         &mut self,
         dir: &Path,
@@ -610,99 +4658,162 @@ impl PluginManager {
     ) where
         F: FnMut(ManagerNotification) -> bool,
     {
+        let dirs = [(dir.to_path_buf(), opts)];
+        self.process_notifications(
+            &dirs,
+            trait_id,
+            |timeout| {
+                receive(&rx, timeout).map(|notification| DirNotification {
+                    dir: dir.to_path_buf(),
+                    notification,
+                })
+            },
+            |_, notification| callback(notification),
+        );
+    }
+
+    /// `process_watch_notifications_blocking` for a session started with
+    /// `start_watch_background_dirs`. `dirs` are the directories and
+    /// options that session was started with; each directory's options
+    /// govern the loads and unloads for its notifications. The callback
+    /// also receives the directory a notification concerns.
+    pub fn process_dir_notifications_blocking<F>(
+        &mut self,
+        dirs: &[(PathBuf, WatchOptions)],
+        rx: Receiver<DirNotification>,
+        trait_id: PluginTrait,
+        callback: F,
+    ) where
+        F: FnMut(&Path, ManagerNotification) -> bool,
+    {
+        self.process_notifications(dirs, trait_id, |timeout| receive(&rx, timeout), callback);
+    }
+
+    /// Handle what `next` receives, waiting at most the given time for it,
+    /// and retry failed loads when they are due.
+    fn process_notifications<F>(
+        &mut self,
+        dirs: &[(PathBuf, WatchOptions)],
+        trait_id: PluginTrait,
+        mut next: impl FnMut(Option<Duration>) -> Result<DirNotification, mpsc::RecvTimeoutError>,
+        mut callback: F,
+    ) where
+        F: FnMut(&Path, ManagerNotification) -> bool,
+    {
+        let mut filters = Vec::with_capacity(dirs.len());
+        for (dir, opts) in dirs {
+            match PathFilter::new(&opts.include, &opts.exclude) {
+                Ok(f) => filters.push(f),
+                Err(e) => {
+                    callback(dir, ManagerNotification::Error(e));
+                    return;
+                }
+            }
+        }
         loop {
-            match rx.recv() {
-                Ok(WatchNotification::Paths(paths)) => {
-                    if opts.auto_load {
-                        match self.load_plugins(dir, trait_id) {
-                            Ok(handles) => {
-                                if opts.emit_proxies && trait_id == PluginTrait::Greeter {
-                                    let proxies: Vec<crate::GreeterProxy> =
-                                        handles.iter().filter_map(|h| h.as_greeter()).collect();
-                                    if !callback(ManagerNotification::Event(WatchEvent::Proxies(
-                                        proxies,
-                                        paths.clone(),
-                                    ))) {
-                                        return;
-                                    }
-                                } else if !callback(ManagerNotification::Event(
-                                    WatchEvent::Handles(handles, paths.clone()),
-                                )) {
-                                    return;
-                                }
-                            }
-                            Err(e) => {
-                                if !callback(ManagerNotification::Error(format!(
-                                    "load error: {:?}",
-                                    e
-                                ))) {
-                                    return;
-                                }
-                            }
-                        }
-                    } else {
-                        // Auto-load disabled: just notify empty events
-                        if opts.emit_proxies && trait_id == PluginTrait::Greeter {
-                            if !callback(ManagerNotification::Event(WatchEvent::Proxies(
-                                Vec::new(),
-                                paths.clone(),
-                            ))) {
+            let timeout = self
+                .next_load_retry()
+                .map(|due| due.saturating_duration_since(std::time::Instant::now()));
+            let DirNotification { dir, notification } = match next(timeout) {
+                Ok(received) => received,
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    for (i, (dir, opts)) in dirs.iter().enumerate() {
+                        for notification in self.retry_loads(dir, trait_id, opts, &filters[i]) {
+                            if !callback(dir, notification) {
                                 return;
                             }
-                        } else if !callback(ManagerNotification::Event(WatchEvent::Handles(
-                            Vec::new(),
-                            paths.clone(),
-                        ))) {
-                            return;
-                        }
-                    }
-                }
-                Ok(WatchNotification::Unloaded { path, .. }) => {
-                    // manager performs unload when requested
-                    if opts.auto_unload {
-                        match self.unload_by_path(&path) {
-                            Ok(counter) => {
-                                if !callback(ManagerNotification::Unloaded {
-                                    path: path.clone(),
-                                    counter,
-                                }) {
-                                    return;
-                                }
-                            }
-                            Err(e) => {
-                                if !callback(ManagerNotification::Error(e)) {
-                                    return;
-                                }
-                            }
                         }
-                    } else if !callback(ManagerNotification::Unloaded {
-                        path: path.clone(),
-                        counter: None,
-                    }) {
-                        return;
                     }
+                    continue;
                 }
-                Ok(WatchNotification::Error(e)) => {
-                    if !callback(ManagerNotification::Error(e)) {
-                        return;
-                    }
+                Err(mpsc::RecvTimeoutError::Disconnected) => return,
+            };
+            let Some(i) = dirs.iter().position(|(d, _)| *d == dir) else {
+                continue;
+            };
+            let reaction = self.react(&dir, trait_id, &dirs[i].1, &filters[i], notification);
+            for notification in reaction {
+                if !callback(&dir, notification) {
+                    return;
                 }
-                Err(_) => break,
             }
         }
     }
-}
 
-fn is_dynamic_library(path: &Path) -> bool {
-    if let Some(ext) = path.extension().and_then(|s| s.to_str()) {
-        #[cfg(target_os = "windows")]
-        return ext.eq_ignore_ascii_case("dll");
-        #[cfg(target_os = "macos")]
-        return ext.eq_ignore_ascii_case("dylib");
-        #[cfg(all(unix, not(target_os = "macos")))]
-        return ext.eq_ignore_ascii_case("so");
+    /// Load, reload or unload as `opts` asks for one notification about
+    /// `dir`, and describe what happened.
+    pub(crate) fn react(
+        &mut self,
+        dir: &Path,
+        trait_id: PluginTrait,
+        opts: &WatchOptions,
+        filter: &PathFilter,
+        notification: WatchNotification,
+    ) -> Vec<ManagerNotification> {
+        let results = match notification {
+            WatchNotification::Paths(paths) => {
+                self.watch_events(dir, trait_id, opts, filter, paths, Vec::new())
+            }
+            WatchNotification::Modified(paths) => {
+                self.watch_events(dir, trait_id, opts, filter, Vec::new(), paths)
+            }
+            WatchNotification::Unloaded { path, .. } => self.watch_removed(path, opts),
+            WatchNotification::Error(e) => vec![Err(e)],
+        };
+        self.notifications(results)
+    }
+
+    /// Load the files under `dir` whose retry is due, as `react` would load
+    /// new ones. See `WatchOptions::load_retries`.
+    pub(crate) fn retry_loads(
+        &mut self,
+        dir: &Path,
+        trait_id: PluginTrait,
+        opts: &WatchOptions,
+        filter: &PathFilter,
+    ) -> Vec<ManagerNotification> {
+        let due = self.load_retries.due(dir, std::time::Instant::now());
+        if due.is_empty() {
+            return Vec::new();
+        }
+        trace_event!(debug, paths = ?due, "watch: retrying failed loads");
+        let results = self.watch_events(dir, trait_id, opts, filter, due, Vec::new());
+        self.notifications(results)
+    }
+
+    /// When `retry_loads` next has something to do, if ever.
+    pub(crate) fn next_load_retry(&self) -> Option<std::time::Instant> {
+        self.load_retries.next_due()
+    }
+
+    /// `results` as notifications, followed by the digest mismatches and
+    /// probe failures they ran into.
+    fn notifications(
+        &mut self,
+        results: Vec<Result<WatchEvent, String>>,
+    ) -> Vec<ManagerNotification> {
+        #[allow(unused_mut)]
+        let mut reaction: Vec<ManagerNotification> = results
+            .into_iter()
+            .map(|result| match result {
+                Ok(event) => ManagerNotification::Event(event),
+                Err(e) => ManagerNotification::Error(e),
+            })
+            .collect();
+        #[cfg(feature = "pinning")]
+        reaction.extend(
+            self.take_digest_mismatches()
+                .into_iter()
+                .map(ManagerNotification::DigestMismatch),
+        );
+        #[cfg(feature = "isolation")]
+        reaction.extend(
+            self.take_probe_failures()
+                .into_iter()
+                .map(ManagerNotification::ProbeFailed),
+        );
+        reaction
     }
-    false
 }
 
 #[cfg(feature = "watch")]
@@ -711,21 +4822,46 @@ fn is_dynamic_library(path: &Path) -> bool {
 pub struct WatchOptions {
     /// Debounce window in milliseconds to coalesce rapid events.
     pub debounce_ms: u64,
+    /// After the debounce window, how long in milliseconds a file's size
+    /// and modification time must stay unchanged before it is loaded, so
+    /// artifacts that are still being copied are skipped. On Windows the
+    /// file must also open for exclusive access. Zero disables the check.
+    pub stable_ms: u64,
+    /// Glob patterns a plugin file must match one of to be acted on, such
+    /// as `libmyapp_*`; empty means every library. A pattern without a `/`
+    /// matches the file name, one with a `/` the path relative to the
+    /// watched directory.
+    pub include: Vec<String>,
+    /// Glob patterns, matched like `include`, for files to ignore even when
+    /// included, such as build intermediates. Ignored files are neither
+    /// reported nor loaded when another file triggers a load.
+    pub exclude: Vec<String>,
     /// Whether to watch directories recursively.
     pub recursive: bool,
-    /// If true, call `load_plugins` internally and send PluginHandle values
-    /// to the callback; if false, the callback will receive an empty
-    /// handles vec and the discovered paths.
+    /// If true, call `load_plugins` internally when files are added and
+    /// report the loaded handles; if false, events carry no new handles.
     pub auto_load: bool,
     /// If true, attempt to automatically unload plugins when files are
     /// removed or updated. The manager will call `unload_by_path` on remove
     /// events if enabled.
     pub auto_unload: bool,
-    /// If true, the watcher will prefer emitting typed proxies (where
-    /// possible) instead of raw PluginHandle values when calling the
-    /// synchronous callback. Note: proxies may not be Send/Sync and are
-    /// therefore not used in the background watcher API.
-    pub emit_proxies: bool,
+    /// If true, a change to the artifact of a loaded plugin reloads it with
+    /// `reload_by_path` and emits `WatchEvent::Reloaded`. Otherwise the
+    /// change is reported as `WatchEvent::Modified`.
+    pub auto_reload: bool,
+    /// Which files the watcher acts on and, with `auto_load`, loads. Set
+    /// it to the manager's `discovery_policy` to watch the files
+    /// `load_plugins` finds.
+    pub discovery: DiscoveryPolicy,
+    /// How many times `auto_load` tries again to load a file whose load
+    /// failed, for example because it was locked or only partly copied,
+    /// before giving up until the file changes. Zero disables retries.
+    pub load_retries: u32,
+    /// Delay in milliseconds before the first retry; it doubles with every
+    /// further failure.
+    pub retry_delay_ms: u64,
+    /// Upper bound in milliseconds for the delay between two retries.
+    pub max_retry_delay_ms: u64,
 }
 
 #[cfg(feature = "watch")]
@@ -733,10 +4869,17 @@ impl Default for WatchOptions {
     fn default() -> Self {
         Self {
             debounce_ms: 300,
+            stable_ms: 200,
+            include: Vec::new(),
+            exclude: Vec::new(),
             recursive: false,
             auto_load: true,
             auto_unload: false,
-            emit_proxies: false,
+            auto_reload: false,
+            discovery: DiscoveryPolicy::default(),
+            load_retries: 5,
+            retry_delay_ms: 500,
+            max_retry_delay_ms: 30_000,
         }
     }
 }
@@ -0,0 +1,245 @@
+use crate::Capability;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+
+/// File-name suffix used for sidecar manifests. A library `libfoo.so` is
+/// described by `libfoo.plugin.toml` in the same directory.
+pub const MANIFEST_SUFFIX: &str = "plugin.toml";
+
+/// Parsed contents of a plugin's sidecar `*.plugin.toml` manifest.
+///
+/// Example:
+///
+/// ```toml
+/// name = "plugin-multi"
+/// version = "0.1.0"
+/// traits = ["Greeter"]
+/// min_host_abi = 1
+///
+/// capabilities = ["filesystem"]
+/// groups = ["editor"]
+///
+/// [dependencies]
+/// plugin-a = "0.1"
+/// ```
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub struct PluginManifest {
+    /// Human-readable plugin name.
+    pub name: String,
+    /// Plugin version string (semver recommended).
+    pub version: String,
+    /// Trait names this plugin provides registrations for, e.g. `["Greeter"]`.
+    #[serde(default)]
+    pub traits: Vec<String>,
+    /// Lowest host ABI version the plugin can run against.
+    #[serde(default = "default_min_host_abi")]
+    pub min_host_abi: u32,
+    /// Optional library file name, relative to the manifest. When omitted the
+    /// library is expected next to the manifest with the same stem. Paths
+    /// that could leave the manifest's directory are rejected with
+    /// `ManifestError::InvalidLibrary`.
+    #[serde(default)]
+    pub library: Option<String>,
+    /// Other plugins this plugin depends on, keyed by name with a version
+    /// requirement string as value.
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, String>,
+    /// Host capabilities the plugin asks for (`filesystem`, `network`,
+    /// `spawn-threads`). Only the ones the host grants are usable.
+    #[serde(default)]
+    pub capabilities: Vec<Capability>,
+    /// Groups the plugin belongs to, e.g. `["editor", "experimental"]`;
+    /// see `PluginManager::load_group`.
+    #[serde(default)]
+    pub groups: Vec<String>,
+    /// Priority of all the plugin's registrations, replacing the one their
+    /// `#[plugin_impl]` declares; see `PluginHandle::priority`.
+    #[serde(default)]
+    pub priority: Option<i32>,
+}
+
+fn default_min_host_abi() -> u32 {
+    1
+}
+
+/// Errors produced while reading or validating a manifest.
+#[derive(Debug)]
+pub enum ManifestError {
+    Io(std::io::Error),
+    Parse(String),
+    /// The manifest does not list the trait the host asked for.
+    TraitNotProvided(String),
+    /// The plugin requires a newer host ABI than this host implements.
+    HostAbiTooOld {
+        required: u32,
+        host: u32,
+    },
+    /// The `library` entry is not a plain file name: it is absolute, has
+    /// path separators or names a parent directory.
+    InvalidLibrary(String),
+}
+
+impl std::fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ManifestError::Io(e) => write!(f, "failed to read manifest: {}", e),
+            ManifestError::Parse(e) => write!(f, "invalid manifest: {}", e),
+            ManifestError::TraitNotProvided(t) => {
+                write!(f, "manifest does not provide trait {}", t)
+            }
+            ManifestError::HostAbiTooOld { required, host } => write!(
+                f,
+                "plugin requires host ABI {} but host implements {}",
+                required, host
+            ),
+            ManifestError::InvalidLibrary(lib) => write!(
+                f,
+                "manifest library {:?} is not a file name next to the manifest",
+                lib
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ManifestError {}
+
+impl PluginManifest {
+    /// Parse a manifest from its TOML source.
+    pub fn parse(src: &str) -> Result<Self, ManifestError> {
+        toml::from_str(src).map_err(|e| ManifestError::Parse(e.to_string()))
+    }
+
+    /// Read and parse the manifest at `path`.
+    pub fn from_file(path: &Path) -> Result<Self, ManifestError> {
+        let src = std::fs::read_to_string(path).map_err(ManifestError::Io)?;
+        Self::parse(&src)
+    }
+
+    /// Check that this plugin can be loaded by the current host for `trait_name`.
+    /// Called before the library is opened so incompatible artifacts are
+    /// never mapped into the process.
+    pub fn validate(&self, trait_name: &str) -> Result<(), ManifestError> {
+        if self.min_host_abi > crate::HOST_ABI_VERSION {
+            return Err(ManifestError::HostAbiTooOld {
+                required: self.min_host_abi,
+                host: crate::HOST_ABI_VERSION,
+            });
+        }
+        if !self.provides(trait_name) {
+            return Err(ManifestError::TraitNotProvided(trait_name.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Returns true if the manifest lists `trait_name` among its traits.
+    pub fn provides(&self, trait_name: &str) -> bool {
+        self.traits.iter().any(|t| t == trait_name)
+    }
+}
+
+/// Path of the sidecar manifest that describes the library at `lib_path`.
+pub fn sidecar_path(lib_path: &Path) -> PathBuf {
+    lib_path.with_extension(MANIFEST_SUFFIX)
+}
+
+/// Returns true if `path` looks like a sidecar manifest file.
+pub(crate) fn is_manifest_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .map(|n| n.ends_with(&format!(".{}", MANIFEST_SUFFIX)))
+        .unwrap_or(false)
+}
+
+/// Resolve the library a manifest at `manifest_path` refers to: either the
+/// explicit `library` entry or the sibling file with the same stem and the
+/// platform's dynamic library extension. A `library` entry that could point
+/// outside the manifest's directory is rejected.
+pub(crate) fn library_for_manifest(
+    manifest_path: &Path,
+    manifest: &PluginManifest,
+) -> Result<PathBuf, ManifestError> {
+    match &manifest.library {
+        Some(lib) => {
+            let mut components = Path::new(lib).components();
+            match (components.next(), components.next()) {
+                (Some(Component::Normal(_)), None) if !lib.contains(['/', '\\']) => {
+                    Ok(manifest_path.with_file_name(lib))
+                }
+                _ => Err(ManifestError::InvalidLibrary(lib.clone())),
+            }
+        }
+        None => Ok(default_library_path(manifest_path)),
+    }
+}
+
+fn default_library_path(manifest_path: &Path) -> PathBuf {
+    let file_name = manifest_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default();
+    let stem = file_name
+        .strip_suffix(&format!(".{}", MANIFEST_SUFFIX))
+        .unwrap_or(file_name);
+    manifest_path.with_file_name(format!("{}.{}", stem, std::env::consts::DLL_EXTENSION))
+}
+
+/// A library discovered in a plugin directory together with its manifest, if any.
+#[derive(Debug, Clone)]
+pub(crate) struct Candidate {
+    pub path: PathBuf,
+    pub manifest: Option<PluginManifest>,
+}
+
+/// Discover loadable candidates in `dir`. Manifest-described libraries come
+/// first (sorted by path) followed by bare libraries without a manifest, so
+/// manifest-driven discovery takes precedence.
+pub(crate) fn discover(
+    dir: &Path,
+    policy: &crate::DiscoveryPolicy,
+) -> std::io::Result<Vec<Result<Candidate, (PathBuf, ManifestError)>>> {
+    let mut manifests = Vec::new();
+    let mut libs = Vec::new();
+    for entry in dir.read_dir()?.flatten() {
+        let path = entry.path();
+        if is_manifest_file(&path) {
+            manifests.push(path);
+        } else if policy.matches(&path) {
+            libs.push(path);
+        }
+    }
+    manifests.sort();
+    libs.sort();
+
+    let mut out = Vec::new();
+    let mut claimed = std::collections::HashSet::new();
+    for m in manifests {
+        let found = PluginManifest::from_file(&m)
+            .and_then(|manifest| Ok((library_for_manifest(&m, &manifest)?, manifest)));
+        match found {
+            Ok((lib, manifest)) => {
+                claimed.insert(lib.clone());
+                out.push(Ok(Candidate {
+                    path: lib,
+                    manifest: Some(manifest),
+                }));
+            }
+            Err(e) => {
+                // Claim the default sibling so a broken manifest is not
+                // silently bypassed by bare-library discovery.
+                claimed.insert(default_library_path(&m));
+                out.push(Err((m, e)));
+            }
+        }
+    }
+    for lib in libs {
+        if claimed.contains(&lib) {
+            continue;
+        }
+        out.push(Ok(Candidate {
+            path: lib,
+            manifest: None,
+        }));
+    }
+    Ok(out)
+}
@@ -0,0 +1,213 @@
+//! Prometheus text-format export of `PluginManager` statistics, rendered by
+//! `PluginManager::render_metrics`.
+
+use crate::stats::PluginStats;
+use std::fmt::Write;
+
+/// Upper bounds, in seconds, of the call latency histogram buckets.
+pub(crate) const LATENCY_BUCKETS: [f64; 10] = [
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.05, 0.25, 1.0,
+];
+
+/// Watcher events the manager produced, by kind.
+#[derive(Debug, Default)]
+pub(crate) struct WatchEventCounts {
+    added: u64,
+    modified: u64,
+    removed: u64,
+    reloaded: u64,
+    errors: u64,
+}
+
+#[cfg(feature = "watch")]
+impl WatchEventCounts {
+    pub(crate) fn count(&mut self, results: &[Result<crate::manager::WatchEvent, String>]) {
+        use crate::manager::WatchEvent;
+        for result in results {
+            let kind = match result {
+                Ok(WatchEvent::Added { .. }) => &mut self.added,
+                Ok(WatchEvent::Modified { .. }) => &mut self.modified,
+                Ok(WatchEvent::Removed { .. }) => &mut self.removed,
+                Ok(WatchEvent::Reloaded { .. }) => &mut self.reloaded,
+                Err(_) => &mut self.errors,
+            };
+            *kind += 1;
+        }
+    }
+}
+
+/// One registration's statistics and latency histogram.
+pub(crate) struct Series {
+    /// Manifest name, or file name for plugins without a manifest.
+    pub(crate) plugin: String,
+    pub(crate) stats: PluginStats,
+    /// Calls per `LATENCY_BUCKETS` bucket, not cumulative.
+    pub(crate) buckets: Vec<u64>,
+}
+
+/// The exposition of `loaded` plugins, their registrations' `series` and
+/// the `watch` event counts.
+pub(crate) fn render(loaded: usize, series: &[Series], watch: &WatchEventCounts) -> String {
+    let mut out = String::new();
+    header(
+        &mut out,
+        "plugin_manager_loaded_plugins",
+        "gauge",
+        "Plugins currently loaded.",
+    );
+    let _ = writeln!(out, "plugin_manager_loaded_plugins {}", loaded);
+
+    counter(
+        &mut out,
+        series,
+        "plugin_calls_total",
+        "Proxy calls made to a plugin registration.",
+        |s| s.calls,
+    );
+    counter(
+        &mut out,
+        series,
+        "plugin_call_errors_total",
+        "Calls the plugin reported as failed.",
+        |s| s.errors,
+    );
+    counter(
+        &mut out,
+        series,
+        "plugin_call_timeouts_total",
+        "Watchdog calls that exceeded their timeout.",
+        |s| s.timeouts,
+    );
+
+    let name = "plugin_call_duration_seconds";
+    header(
+        &mut out,
+        name,
+        "histogram",
+        "Latency of proxy calls to a plugin registration.",
+    );
+    for s in series {
+        let labels = labels(s);
+        let mut cumulative = 0;
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(&s.buckets) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{},le=\"{}\"}} {}",
+                name, labels, bound, cumulative
+            );
+        }
+        // The counters are updated one by one, so a call in flight may be
+        // in a bucket but not yet in the total.
+        let calls = s.stats.calls.max(cumulative);
+        let _ = writeln!(out, "{}_bucket{{{},le=\"+Inf\"}} {}", name, labels, calls);
+        let _ = writeln!(
+            out,
+            "{}_sum{{{}}} {}",
+            name,
+            labels,
+            s.stats.total_latency.as_secs_f64()
+        );
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, calls);
+    }
+
+    let name = "plugin_watch_events_total";
+    header(
+        &mut out,
+        name,
+        "counter",
+        "Events the directory watcher reported, by kind.",
+    );
+    for (kind, count) in [
+        ("added", watch.added),
+        ("modified", watch.modified),
+        ("removed", watch.removed),
+        ("reloaded", watch.reloaded),
+        ("error", watch.errors),
+    ] {
+        let _ = writeln!(out, "{}{{kind=\"{}\"}} {}", name, kind, count);
+    }
+    out
+}
+
+/// A counter with one sample per registration.
+fn counter(
+    out: &mut String,
+    series: &[Series],
+    name: &str,
+    help: &str,
+    value: fn(&PluginStats) -> u64,
+) {
+    header(out, name, "counter", help);
+    for s in series {
+        let _ = writeln!(out, "{}{{{}}} {}", name, labels(s), value(&s.stats));
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn labels(s: &Series) -> String {
+    format!(
+        "plugin=\"{}\",path=\"{}\",registration=\"{}\"",
+        escape(&s.plugin),
+        escape(&s.stats.path.to_string_lossy()),
+        s.stats.index
+    )
+}
+
+/// `value` as the inside of a quoted label value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handle::PluginId;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    #[test]
+    fn histograms_are_cumulative() {
+        let series = Series {
+            plugin: "say \"hi\"".to_string(),
+            stats: PluginStats {
+                id: PluginId(1),
+                path: PathBuf::from("/plugins/libhi.so"),
+                index: 0,
+                calls: 3,
+                errors: 1,
+                timeouts: 0,
+                total_latency: Duration::from_millis(3),
+                mean_latency: Duration::from_millis(1),
+                last_call: None,
+            },
+            buckets: vec![1, 0, 0, 2, 0, 0, 0, 0, 0, 0],
+        };
+        let text = render(1, &[series], &WatchEventCounts::default());
+        let labels = r#"plugin="say \"hi\"",path="/plugins/libhi.so",registration="0""#;
+
+        assert!(text.contains("plugin_manager_loaded_plugins 1\n"));
+        assert!(text.contains(&format!("plugin_calls_total{{{}}} 3\n", labels)));
+        assert!(text.contains(&format!("plugin_call_errors_total{{{}}} 1\n", labels)));
+        assert!(text.contains(&format!(
+            "plugin_call_duration_seconds_bucket{{{},le=\"0.0005\"}} 1\n",
+            labels
+        )));
+        assert!(text.contains(&format!(
+            "plugin_call_duration_seconds_bucket{{{},le=\"0.001\"}} 3\n",
+            labels
+        )));
+        assert!(text.contains(&format!(
+            "plugin_call_duration_seconds_count{{{}}} 3\n",
+            labels
+        )));
+        assert!(text.contains("plugin_watch_events_total{kind=\"error\"} 0\n"));
+    }
+}
@@ -0,0 +1,407 @@
+//! Libraries built for another ABI. A `ModuleLoader` added with
+//! `PluginManager::add_module_loader` claims the libraries it knows how to
+//! talk to and hands the manager Rust implementations of the built-in
+//! traits. The host makes their vtables, so their handles, proxies,
+//! statistics and unloading work as for any other library. The
+//! `plugin-abi-stable` crate is such a loader.
+
+use crate::handle::{notify_loaded, LoadedLib};
+use crate::host::SharedHostContext;
+use crate::{
+    export_string, Greeter, GreeterContext, GreeterOptional, GreeterRegistration, GreeterVTable,
+    HostInfo, OwnedStr, PluginTrait, RegistrationArray, RegistrationFactory, StateSink, StrRef,
+    Transformer, TransformerContext, TransformerOptional, TransformerRegistration,
+    TransformerVTable, Uncounted, CONTEXT_VTABLE_ABI, MAX_PLUGIN_ABI,
+};
+use libloading::Library;
+use std::ffi::{c_void, CStr, CString};
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// An implementation of a built-in trait made by a `ModuleLoader`.
+pub enum ModulePlugin {
+    Greeter(Box<dyn Greeter + Send + Sync>),
+    Transformer(Box<dyn Transformer + Send + Sync>),
+}
+
+impl ModulePlugin {
+    pub fn trait_id(&self) -> PluginTrait {
+        match self {
+            ModulePlugin::Greeter(_) => PluginTrait::Greeter,
+            ModulePlugin::Transformer(_) => PluginTrait::Transformer,
+        }
+    }
+}
+
+impl std::fmt::Debug for ModulePlugin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("ModulePlugin")
+            .field(&self.trait_id())
+            .finish()
+    }
+}
+
+/// One registration of a module, under the name `registration_name`
+/// reports.
+#[derive(Debug)]
+pub struct ModuleRegistration {
+    pub name: String,
+    pub plugin: ModulePlugin,
+}
+
+/// Opens libraries that do not export this crate's registration symbols.
+/// The manager asks every loader, in the order they were added, whether it
+/// `accepts` a library before opening it, and hands the first one that
+/// does the library instead of looking for register functions.
+pub trait ModuleLoader: Send + Sync {
+    /// Whether the library at `path` is one of this loader's modules.
+    /// Called for every library the manager is about to open, before it
+    /// opens it; `exports_symbol` tells without running any of its code.
+    fn accepts(&self, path: &Path) -> bool;
+
+    /// The module's registrations for `trait_id`, in the order their
+    /// handles should have. Registrations for other traits are left out.
+    /// The manager opens the library itself as well, and keeps it open
+    /// until the registrations are released.
+    fn open(&self, path: &Path, trait_id: PluginTrait) -> Result<Vec<ModuleRegistration>, String>;
+}
+
+/// Whether the library at `path` exports `symbol`, read from its export
+/// table without opening it. For `ModuleLoader::accepts`.
+pub fn exports_symbol(path: &Path, symbol: &str) -> bool {
+    use object::{BinaryFormat, Object};
+    let Ok(file) = std::fs::File::open(path) else {
+        return false;
+    };
+    let cache = object::ReadCache::new(file);
+    let Ok(file) = crate::validate::sniff(&cache) else {
+        return false;
+    };
+    let macho = file.format() == BinaryFormat::MachO;
+    file.exports().unwrap_or_default().iter().any(|e| {
+        let name = e.name();
+        // Mach-O prefixes C symbols with an underscore.
+        let name = match name.strip_prefix(b"_") {
+            Some(stripped) if macho => stripped,
+            _ => name,
+        };
+        name == symbol.as_bytes()
+    })
+}
+
+/// Make `loader`'s registrations for `trait_id` from the module at
+/// `open_path`, a copy of `path` or `path` itself, and send them `on_load`.
+/// `Ok(None)` if it has none.
+pub(crate) fn open_module(
+    loader: &dyn ModuleLoader,
+    lib: Library,
+    open_path: &Path,
+    trait_id: PluginTrait,
+    host: Arc<SharedHostContext>,
+    path: PathBuf,
+) -> Result<Option<LoadedLib>, String> {
+    let config = host.context().plugin_config().map(str::to_string);
+    let mut regs: Vec<*const c_void> = Vec::new();
+    let mut factories: Vec<*const RegistrationFactory> = Vec::new();
+    for registration in loader.open(open_path, trait_id)? {
+        if registration.plugin.trait_id() != trait_id {
+            continue;
+        }
+        regs.push(make_registration(registration, config.as_deref()));
+        factories.push(match trait_id {
+            PluginTrait::Greeter => &GREETER_FACTORY,
+            PluginTrait::Transformer => &TRANSFORMER_FACTORY,
+        });
+    }
+    if regs.is_empty() {
+        return Ok(None);
+    }
+    let arr_ptr = Box::into_raw(Box::new(RegistrationArray::new(
+        regs.len(),
+        Box::into_raw(regs.into_boxed_slice()) as *const *const c_void,
+        Box::into_raw(factories.into_boxed_slice()) as *const *const RegistrationFactory,
+    )));
+    unsafe { notify_loaded(arr_ptr, trait_id, HostInfo::current()) };
+    let mut loaded = LoadedLib::new_host_owned(lib, arr_ptr, trait_id, path);
+    // Returned strings are allocated with the host's allocator.
+    loaded.abi_version = MAX_PLUGIN_ABI;
+    loaded.module = true;
+    loaded.host_context = Some(host);
+    Ok(Some(loaded))
+}
+
+/// Release the registrations `open_module` made and free the array.
+///
+/// # Safety
+/// `arr_ptr` must come from `open_module` and not be used again; no calls
+/// may be running in its registrations.
+pub(crate) unsafe fn release(arr_ptr: *const RegistrationArray) {
+    let arr = Box::from_raw(arr_ptr as *mut RegistrationArray);
+    let regs: Box<[*const c_void]> = Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+        arr.registrations as *mut *const c_void,
+        arr.count,
+    ));
+    let factories: Box<[*const RegistrationFactory]> =
+        Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+            arr.factories as *mut *const RegistrationFactory,
+            arr.count,
+        ));
+    for (&r, &factory) in regs.iter().zip(factories.iter()) {
+        ((*factory).unmaker)(r);
+    }
+}
+
+/// Module registrations have no maker: `PluginHandle::instantiate` makes
+/// no instances of them.
+extern "C" fn no_instance() -> *const c_void {
+    std::ptr::null()
+}
+
+static GREETER_FACTORY: RegistrationFactory =
+    RegistrationFactory::new(no_instance, release_greeter, c"Greeter".as_ptr());
+
+static TRANSFORMER_FACTORY: RegistrationFactory =
+    RegistrationFactory::new(no_instance, release_transformer, c"Transformer".as_ptr());
+
+fn make_registration(registration: ModuleRegistration, config: Option<&str>) -> *const c_void {
+    let name = CString::new(registration.name.replace('\0', ""))
+        .unwrap_or_default()
+        .into_raw();
+    match registration.plugin {
+        ModulePlugin::Greeter(plugin) => {
+            let vtable = Box::into_raw(Box::new(greeter_vtable(Boxed(plugin), config)));
+            Box::into_raw(Box::new(GreeterRegistration { name, vtable })) as *const c_void
+        }
+        ModulePlugin::Transformer(plugin) => {
+            let vtable = Box::into_raw(Box::new(transformer_vtable(Boxed(plugin), config)));
+            Box::into_raw(Box::new(TransformerRegistration { name, vtable })) as *const c_void
+        }
+    }
+}
+
+extern "C" fn release_greeter(reg: *const c_void) {
+    if reg.is_null() {
+        return;
+    }
+    unsafe {
+        let reg = Box::from_raw(reg as *mut GreeterRegistration);
+        let vtable = Box::from_raw(reg.vtable as *mut GreeterVTable);
+        (vtable.drop)(vtable.user_data);
+        drop(CString::from_raw(reg.name as *mut c_char));
+    }
+}
+
+extern "C" fn release_transformer(reg: *const c_void) {
+    if reg.is_null() {
+        return;
+    }
+    unsafe {
+        let reg = Box::from_raw(reg as *mut TransformerRegistration);
+        let vtable = Box::from_raw(reg.vtable as *mut TransformerVTable);
+        (vtable.drop)(vtable.user_data);
+        drop(CString::from_raw(reg.name as *mut c_char));
+    }
+}
+
+/// A module's trait object, sized so the entries below and the
+/// `<Trait>Optional::for_impl` and `<Trait>Context::for_impl` ones can
+/// point at it.
+struct Boxed<T: ?Sized>(Box<T>);
+
+impl Greeter for Boxed<dyn Greeter + Send + Sync> {
+    fn name(&self) -> &str {
+        self.0.name()
+    }
+    fn greet(&self, target: &str) {
+        self.0.greet(target)
+    }
+    fn on_load(&self, host: &HostInfo) {
+        self.0.on_load(host)
+    }
+    fn on_unload(&self) {
+        self.0.on_unload()
+    }
+    fn save_state(&self) -> Vec<u8> {
+        self.0.save_state()
+    }
+    fn restore_state(&mut self, bytes: &[u8]) {
+        self.0.restore_state(bytes)
+    }
+    fn configure(&mut self, config: &str) {
+        self.0.configure(config)
+    }
+    fn health(&self) -> crate::HealthStatus {
+        self.0.health()
+    }
+}
+
+impl Transformer for Boxed<dyn Transformer + Send + Sync> {
+    fn transform(&self, input: &str) -> String {
+        self.0.transform(input)
+    }
+    fn describe(&self) -> String {
+        self.0.describe()
+    }
+    fn health(&self) -> crate::HealthStatus {
+        self.0.health()
+    }
+    fn on_load(&self, host: &HostInfo) {
+        self.0.on_load(host)
+    }
+    fn on_unload(&self) {
+        self.0.on_unload()
+    }
+    fn save_state(&self) -> Vec<u8> {
+        self.0.save_state()
+    }
+    fn restore_state(&mut self, bytes: &[u8]) {
+        self.0.restore_state(bytes)
+    }
+    fn configure(&mut self, config: &str) {
+        self.0.configure(config)
+    }
+}
+
+/// Run `f` on the instance at `u`, which must be a `T`, catching panics.
+fn with<T, R>(u: *mut c_void, f: impl FnOnce(&T) -> R) -> Option<R> {
+    if u.is_null() {
+        return None;
+    }
+    let instance = unsafe { &*(u as *const T) };
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| f(instance))).ok()
+}
+
+fn c_str<'a>(arg: *const c_char) -> &'a str {
+    if arg.is_null() {
+        return "";
+    }
+    unsafe { CStr::from_ptr(arg) }.to_str().unwrap_or("")
+}
+
+fn returned(s: Option<String>) -> *const c_char {
+    match s {
+        Some(s) => export_string(crate::default_host_context().allocator(), &s),
+        None => std::ptr::null(),
+    }
+}
+
+fn owned(s: Option<String>) -> OwnedStr {
+    s.map_or_else(OwnedStr::null, OwnedStr::new)
+}
+
+extern "C" fn drop_entry<T>(u: *mut c_void) {
+    if !u.is_null() {
+        drop(unsafe { Box::from_raw(u as *mut T) });
+    }
+}
+
+fn save_state_entry<T>(u: *mut c_void, out: *mut c_void, sink: StateSink, save: fn(&T) -> Vec<u8>) {
+    if let Some(bytes) = with(u, save) {
+        sink(out, bytes.as_ptr(), bytes.len());
+    }
+}
+
+fn restore_state_entry<T>(u: *mut c_void, data: *const u8, len: usize, restore: fn(&mut T, &[u8])) {
+    if u.is_null() || data.is_null() {
+        return;
+    }
+    // The host restores state before any other call is made.
+    let instance = unsafe { &mut *(u as *mut T) };
+    let bytes = unsafe { std::slice::from_raw_parts(data, len) };
+    let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| restore(instance, bytes)));
+}
+
+/// A `GreeterVTable` calling `plugin`, configured with `config` first.
+fn greeter_vtable<T: Greeter>(mut plugin: T, config: Option<&str>) -> GreeterVTable {
+    extern "C" fn name<T: Greeter>(u: *mut c_void) -> *const c_char {
+        returned(with(u, |t: &T| t.name().to_string()))
+    }
+    extern "C" fn greet<T: Greeter>(u: *mut c_void, target: *const c_char) {
+        with(u, |t: &T| t.greet(c_str(target)));
+    }
+    extern "C" fn name_str<T: Greeter>(u: *mut c_void) -> OwnedStr {
+        owned(with(u, |t: &T| t.name().to_string()))
+    }
+    extern "C" fn greet_str<T: Greeter>(u: *mut c_void, target: StrRef) {
+        with(u, |t: &T| t.greet(unsafe { target.as_str() }));
+    }
+    extern "C" fn on_load<T: Greeter>(u: *mut c_void, host: *const HostInfo) {
+        if !host.is_null() {
+            with(u, |t: &T| t.on_load(unsafe { &*host }));
+        }
+    }
+    extern "C" fn on_unload<T: Greeter>(u: *mut c_void) {
+        with(u, |t: &T| t.on_unload());
+    }
+    extern "C" fn save_state<T: Greeter>(u: *mut c_void, out: *mut c_void, sink: StateSink) {
+        save_state_entry::<T>(u, out, sink, T::save_state)
+    }
+    extern "C" fn restore_state<T: Greeter>(u: *mut c_void, data: *const u8, len: usize) {
+        restore_state_entry::<T>(u, data, len, T::restore_state)
+    }
+
+    if let Some(config) = config {
+        let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| plugin.configure(config)));
+    }
+    GreeterVTable {
+        abi_version: CONTEXT_VTABLE_ABI,
+        user_data: Box::into_raw(Box::new(plugin)) as *mut c_void,
+        name: name::<T>,
+        greet: greet::<T>,
+        on_load: on_load::<T>,
+        on_unload: on_unload::<T>,
+        drop: drop_entry::<T>,
+        save_state: save_state::<T>,
+        restore_state: restore_state::<T>,
+        name_str: name_str::<T>,
+        greet_str: greet_str::<T>,
+        struct_size: std::mem::offset_of!(GreeterVTable, context),
+        flags: 0,
+        optional: GreeterOptional::for_impl::<T, Uncounted>(),
+        context: GreeterContext::for_impl::<T, Uncounted>(),
+    }
+}
+
+/// A `TransformerVTable` calling `plugin`, configured with `config` first.
+fn transformer_vtable<T: Transformer>(mut plugin: T, config: Option<&str>) -> TransformerVTable {
+    extern "C" fn transform<T: Transformer>(u: *mut c_void, input: *const c_char) -> *const c_char {
+        returned(with(u, |t: &T| t.transform(c_str(input))))
+    }
+    extern "C" fn transform_str<T: Transformer>(u: *mut c_void, input: StrRef) -> OwnedStr {
+        owned(with(u, |t: &T| t.transform(unsafe { input.as_str() })))
+    }
+    extern "C" fn on_load<T: Transformer>(u: *mut c_void, host: *const HostInfo) {
+        if !host.is_null() {
+            with(u, |t: &T| t.on_load(unsafe { &*host }));
+        }
+    }
+    extern "C" fn on_unload<T: Transformer>(u: *mut c_void) {
+        with(u, |t: &T| t.on_unload());
+    }
+    extern "C" fn save_state<T: Transformer>(u: *mut c_void, out: *mut c_void, sink: StateSink) {
+        save_state_entry::<T>(u, out, sink, T::save_state)
+    }
+    extern "C" fn restore_state<T: Transformer>(u: *mut c_void, data: *const u8, len: usize) {
+        restore_state_entry::<T>(u, data, len, T::restore_state)
+    }
+
+    if let Some(config) = config {
+        let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| plugin.configure(config)));
+    }
+    TransformerVTable {
+        abi_version: CONTEXT_VTABLE_ABI,
+        user_data: Box::into_raw(Box::new(plugin)) as *mut c_void,
+        transform: transform::<T>,
+        on_load: on_load::<T>,
+        on_unload: on_unload::<T>,
+        drop: drop_entry::<T>,
+        save_state: save_state::<T>,
+        restore_state: restore_state::<T>,
+        transform_str: transform_str::<T>,
+        struct_size: std::mem::offset_of!(TransformerVTable, context),
+        flags: 0,
+        optional: TransformerOptional::for_impl::<T, Uncounted>(),
+        context: TransformerContext::for_impl::<T, Uncounted>(),
+    }
+}
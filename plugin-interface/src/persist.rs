@@ -0,0 +1,58 @@
+//! The file `PluginManager::save_state` writes and `restore_state` reads:
+//! which plugins were loaded, which of their registrations were disabled
+//! and the configuration each was handed, as TOML.
+
+use crate::{PluginHandle, PluginLoadError};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct SavedState {
+    #[serde(default)]
+    pub(crate) plugins: Vec<SavedPlugin>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct SavedPlugin {
+    /// Manifest name, or file name for plugins without a manifest; the key
+    /// `config` is restored under.
+    pub(crate) name: String,
+    pub(crate) path: PathBuf,
+    #[serde(rename = "trait")]
+    pub(crate) trait_name: String,
+    /// Indexes of the registrations that were disabled.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) disabled: Vec<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) config: Option<String>,
+    /// SHA-256 of the artifact when it was saved, written with the
+    /// `pinning` feature.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) sha256: Option<String>,
+}
+
+impl SavedState {
+    pub(crate) fn read(path: &Path) -> Result<Self, PluginLoadError> {
+        let text = std::fs::read_to_string(path).map_err(PluginLoadError::Io)?;
+        toml::from_str(&text).map_err(|e| PluginLoadError::InvalidState {
+            path: path.to_path_buf(),
+            error: e.to_string(),
+        })
+    }
+
+    pub(crate) fn write(&self, path: &Path) -> std::io::Result<()> {
+        let text = toml::to_string(self).map_err(std::io::Error::other)?;
+        std::fs::write(path, text)
+    }
+}
+
+/// What `PluginManager::restore_state` brought back.
+#[derive(Debug, Default)]
+pub struct RestoredState {
+    /// Handles of the plugins it loaded. Plugins that were already loaded
+    /// are left alone and have none here.
+    pub handles: Vec<PluginHandle>,
+    /// Plugins that could not be restored, with the reason. The others are
+    /// restored regardless.
+    pub failed: Vec<(PathBuf, PluginLoadError)>,
+}
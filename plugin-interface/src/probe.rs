@@ -0,0 +1,102 @@
+//! Crash-safe probing: before a library is opened in the host process, a
+//! short-lived `plugin-runner --probe` child opens it, registers and
+//! unregisters it, and exits. A plugin that crashes or hangs in its
+//! constructors takes down the child instead of the host.
+
+use crate::{PluginManager, PluginTrait};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// How `PluginManager::set_probe` probes libraries.
+#[derive(Debug, Clone)]
+pub struct ProbeOptions {
+    /// The runner executable (the `plugin-runner` binary built with the
+    /// `isolation` feature).
+    pub runner: PathBuf,
+    /// How long the child may take before it is killed and the library
+    /// counts as failed. Defaults to 10 seconds.
+    pub timeout: Duration,
+}
+
+impl Default for ProbeOptions {
+    fn default() -> Self {
+        Self {
+            runner: crate::isolated::default_runner_path(),
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// A library the probe child could not register and unregister; see
+/// `PluginManager::take_probe_failures`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProbeFailure {
+    pub path: PathBuf,
+    /// How the child failed: its exit status or signal and the last line
+    /// it wrote to stderr, or that it timed out.
+    pub reason: String,
+}
+
+impl std::fmt::Display for ProbeFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "probe of {:?} failed: {}", self.path, self.reason)
+    }
+}
+
+/// Probe the library at `path` for `trait_id` in a child process.
+pub(crate) fn probe(opts: &ProbeOptions, path: &Path, trait_id: PluginTrait) -> Result<(), String> {
+    let mut child = Command::new(&opts.runner)
+        .arg("--probe")
+        .arg(trait_id.as_str())
+        .arg(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to start runner {:?}: {}", opts.runner, e))?;
+    // Drain stderr on the side so a chatty plugin cannot block the child.
+    let mut stderr = child.stderr.take().expect("piped stderr");
+    let output = std::thread::spawn(move || {
+        let mut text = String::new();
+        let _ = stderr.read_to_string(&mut text);
+        text
+    });
+
+    let started = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if started.elapsed() >= opts.timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("timed out after {:?}", opts.timeout));
+            }
+            Ok(None) => std::thread::sleep(Duration::from_millis(10)),
+            Err(e) => return Err(format!("runner lost: {}", e)),
+        }
+    };
+    if status.success() {
+        return Ok(());
+    }
+    let output = output.join().unwrap_or_default();
+    match output.lines().rev().find(|l| !l.trim().is_empty()) {
+        Some(last) => Err(format!("runner exited with {}: {}", status, last.trim())),
+        None => Err(format!("runner exited with {}", status)),
+    }
+}
+
+/// Entry point of `plugin-runner --probe <trait> <library>`: load the
+/// library at `lib_path` for the trait named `trait_name`, then unload it.
+pub fn serve_probe(trait_name: &str, lib_path: &Path) -> Result<(), String> {
+    let trait_id = PluginTrait::from_name(trait_name)
+        .ok_or_else(|| format!("unknown trait {:?}", trait_name))?;
+    let mut mgr = PluginManager::new();
+    let handles = mgr
+        .load_library(lib_path, trait_id)
+        .map_err(|e| format!("failed to load {:?}: {:?}", lib_path, e))?;
+    // The last handle unregisters the plugin and unloads the library.
+    drop(handles);
+    Ok(())
+}
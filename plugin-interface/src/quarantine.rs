@@ -0,0 +1,166 @@
+//! Quarantine of artifacts that keep failing validation or loading, so a
+//! broken file in a plugin directory is set aside instead of failing every
+//! scan that finds it.
+
+use crate::instrument::trace_event;
+use crate::manifest::sidecar_path;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// How `PluginManager::set_quarantine` deals with bad artifacts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuarantineOptions {
+    /// Failed loads in a row after which an artifact is quarantined; 0
+    /// never quarantines. Defaults to 3.
+    pub max_failures: u32,
+    /// Directory, relative to the artifact's own, that a quarantined
+    /// artifact and its sidecar manifest are moved into, e.g.
+    /// `.quarantine`. Defaults to `None`, which leaves the file in place.
+    pub subdirectory: Option<PathBuf>,
+}
+
+impl Default for QuarantineOptions {
+    fn default() -> Self {
+        Self {
+            max_failures: 3,
+            subdirectory: None,
+        }
+    }
+}
+
+/// An artifact that is no longer loaded by scans; see
+/// `PluginManager::quarantined_artifacts`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuarantinedArtifact {
+    /// Where the artifact was found.
+    pub path: PathBuf,
+    /// Failed loads in a row that got it quarantined.
+    pub failures: u32,
+    /// Why the last load failed.
+    pub reason: String,
+    /// Where it was moved, when `QuarantineOptions::subdirectory` is set
+    /// and the move worked.
+    pub moved_to: Option<PathBuf>,
+}
+
+/// The manager's failure counts and quarantine list.
+#[derive(Default)]
+pub(crate) struct Quarantine {
+    options: Option<QuarantineOptions>,
+    failures: HashMap<PathBuf, u32>,
+    quarantined: Vec<QuarantinedArtifact>,
+}
+
+impl Quarantine {
+    pub(crate) fn set_options(&mut self, options: Option<QuarantineOptions>) {
+        if options.is_none() {
+            self.failures.clear();
+        }
+        self.options = options;
+    }
+
+    /// Count a failed load of `path`. Returns the artifact once this
+    /// failure quarantines it, after moving it aside if asked to.
+    pub(crate) fn failed(&mut self, path: &Path, reason: String) -> Option<QuarantinedArtifact> {
+        let options = self.options.as_ref()?;
+        if options.max_failures == 0 || self.contains(path) {
+            return None;
+        }
+        let failures = self.failures.entry(path.to_path_buf()).or_insert(0);
+        *failures += 1;
+        if *failures < options.max_failures {
+            return None;
+        }
+        let failures = self.failures.remove(path).unwrap_or_default();
+        let moved_to = options
+            .subdirectory
+            .as_deref()
+            .and_then(|sub| move_aside(path, sub));
+        let artifact = QuarantinedArtifact {
+            path: path.to_path_buf(),
+            failures,
+            reason,
+            moved_to,
+        };
+        self.quarantined.push(artifact.clone());
+        Some(artifact)
+    }
+
+    /// `path` loaded, so its earlier failures no longer count.
+    pub(crate) fn succeeded(&mut self, path: &Path) {
+        self.failures.remove(path);
+    }
+
+    pub(crate) fn contains(&self, path: &Path) -> bool {
+        self.quarantined.iter().any(|a| a.path == path)
+    }
+
+    pub(crate) fn list(&self) -> &[QuarantinedArtifact] {
+        &self.quarantined
+    }
+
+    pub(crate) fn release(&mut self, path: &Path) -> Option<QuarantinedArtifact> {
+        let i = self.quarantined.iter().position(|a| a.path == path)?;
+        Some(self.quarantined.remove(i))
+    }
+}
+
+/// Move `path`, and its sidecar manifest if it has one, into `sub` next to
+/// it. Returns the new path of the artifact, or `None` if it stayed put.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+fn move_aside(path: &Path, sub: &Path) -> Option<PathBuf> {
+    let dir = path.parent()?.join(sub);
+    let target = dir.join(path.file_name()?);
+    let moved = std::fs::create_dir_all(&dir).and_then(|()| std::fs::rename(path, &target));
+    if let Err(e) = moved {
+        trace_event!(warn, path = %path.display(), error = %e, "failed to move quarantined plugin");
+        return None;
+    }
+    let sidecar = sidecar_path(path);
+    if sidecar.exists() {
+        let _ = std::fs::rename(&sidecar, sidecar_path(&target));
+    }
+    Some(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_failures_quarantine_and_move_the_artifact() {
+        let dir = tempfile::tempdir().unwrap();
+        let lib = dir.path().join("libbroken.so");
+        std::fs::write(&lib, b"not a library").unwrap();
+        std::fs::write(sidecar_path(&lib), b"name = \"broken\"").unwrap();
+
+        let mut quarantine = Quarantine::default();
+        assert_eq!(
+            quarantine.failed(&lib, "bad".into()),
+            None,
+            "off by default"
+        );
+        quarantine.set_options(Some(QuarantineOptions {
+            max_failures: 2,
+            subdirectory: Some(".quarantine".into()),
+        }));
+        assert_eq!(quarantine.failed(&lib, "bad".into()), None);
+        quarantine.succeeded(&lib);
+        assert_eq!(quarantine.failed(&lib, "bad".into()), None);
+
+        let artifact = quarantine
+            .failed(&lib, "worse".into())
+            .expect("quarantined");
+        let moved = dir.path().join(".quarantine").join("libbroken.so");
+        assert_eq!(artifact.failures, 2);
+        assert_eq!(artifact.reason, "worse");
+        assert_eq!(artifact.moved_to.as_deref(), Some(moved.as_path()));
+        assert!(moved.exists() && sidecar_path(&moved).exists());
+        assert!(!lib.exists());
+        assert!(quarantine.contains(&lib));
+        assert_eq!(quarantine.failed(&lib, "again".into()), None);
+
+        assert_eq!(quarantine.release(&lib), Some(artifact));
+        assert!(quarantine.list().is_empty());
+    }
+}
@@ -0,0 +1,42 @@
+//! Descriptions of loaded plugins returned by `PluginManager::list`.
+
+use crate::{PluginHandle, PluginManifest, PluginTrait};
+use std::path::PathBuf;
+
+/// A loaded plugin library or module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PluginDescriptor {
+    /// Manifest name, or file name for plugins without a manifest.
+    pub name: String,
+    pub path: PathBuf,
+    pub trait_id: PluginTrait,
+    /// `PluginHandle::registration_name` of each registration, in
+    /// registration order. Empty if a registration has none.
+    pub registrations: Vec<String>,
+    /// Registration ABI level negotiated when the plugin was loaded.
+    pub abi_version: u32,
+    pub manifest: Option<PluginManifest>,
+}
+
+impl PluginDescriptor {
+    /// Describe the plugin `handles` (all registrations of one plugin, in
+    /// order) belong to.
+    pub(crate) fn from_handles(handles: &[PluginHandle]) -> Option<Self> {
+        let first = handles.first()?;
+        let manifest = first.manifest().cloned();
+        Some(Self {
+            name: crate::search_path::plugin_name(
+                first.path(),
+                manifest.as_ref().map(|m| m.name.as_str()),
+            ),
+            path: first.path().to_path_buf(),
+            trait_id: first.trait_id(),
+            registrations: handles
+                .iter()
+                .map(|h| h.registration_name().unwrap_or_default())
+                .collect(),
+            abi_version: first.abi_version(),
+            manifest,
+        })
+    }
+}
@@ -0,0 +1,176 @@
+//! Recording of proxy calls to a file, one JSON object per line, and a
+//! harness replaying a recording against a plugin build, for reproducing
+//! problems reported from the field.
+
+use crate::intercept::{CallContext, CallInterceptor, CallOutcome};
+use crate::{PluginCallError, PluginHandle};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// One proxy call as `CallRecorder` wrote it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedCall {
+    /// Library the registration came from.
+    pub path: PathBuf,
+    /// Index of the registration in its library.
+    pub index: usize,
+    /// The proxy method, e.g. `greet` or `transform`.
+    pub method: String,
+    /// The call's arguments; see `CallContext::args`.
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// What the call returned, `None` for methods that return nothing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<String>,
+    /// Why the call failed, as the `PluginCallError` reads; `None` if it
+    /// succeeded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// How long the call took, in microseconds.
+    pub duration_us: u64,
+}
+
+impl RecordedCall {
+    /// Every call in the recording at `path`, in the order they returned.
+    pub fn read_all(path: impl AsRef<Path>) -> std::io::Result<Vec<RecordedCall>> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut calls = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            calls.push(serde_json::from_str(&line).map_err(std::io::Error::other)?);
+        }
+        Ok(calls)
+    }
+
+    fn outcome(&self) -> (Option<&str>, Option<&str>) {
+        (self.result.as_deref(), self.error.as_deref())
+    }
+}
+
+thread_local! {
+    /// When the calls this thread is inside of started, innermost last.
+    static STARTED: RefCell<Vec<Instant>> = const { RefCell::new(Vec::new()) };
+}
+
+/// An interceptor writing every proxy call it sees to a file, for
+/// `PluginManager::add_interceptor`. Each call is written, and flushed, as
+/// one line of JSON once it returns; `RecordedCall::read_all` reads them
+/// back. Add it first so the calls other interceptors skip are recorded
+/// with the outcome they were given.
+pub struct CallRecorder {
+    out: Mutex<BufWriter<File>>,
+}
+
+impl std::fmt::Debug for CallRecorder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CallRecorder").finish_non_exhaustive()
+    }
+}
+
+impl CallRecorder {
+    /// Record to `path`, replacing anything in it.
+    pub fn create(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Ok(Self {
+            out: Mutex::new(BufWriter::new(File::create(path)?)),
+        })
+    }
+}
+
+impl CallInterceptor for CallRecorder {
+    fn before(&self, _ctx: &CallContext<'_>) -> ControlFlow<CallOutcome> {
+        STARTED.with(|s| s.borrow_mut().push(Instant::now()));
+        ControlFlow::Continue(())
+    }
+
+    fn after(&self, ctx: &CallContext<'_>, outcome: &CallOutcome) {
+        let started = STARTED.with(|s| s.borrow_mut().pop());
+        let elapsed = started.map(|s| s.elapsed()).unwrap_or_default();
+        let (result, error) = match outcome {
+            Ok(result) => (result.clone(), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        let call = RecordedCall {
+            path: ctx.path.to_path_buf(),
+            index: ctx.index,
+            method: ctx.method.to_string(),
+            args: ctx.args.iter().map(|a| a.to_string()).collect(),
+            result,
+            error,
+            duration_us: elapsed.as_micros() as u64,
+        };
+        let Ok(line) = serde_json::to_string(&call) else {
+            return;
+        };
+        let mut out = self.out.lock().unwrap_or_else(|e| e.into_inner());
+        // A recording is best effort; the call itself already returned.
+        let _ = writeln!(out, "{}", line).and_then(|()| out.flush());
+    }
+}
+
+/// A recorded call made again by `replay_calls`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayedCall {
+    pub recorded: RecordedCall,
+    /// What the call returned this time. `PluginCallError::Unsupported`
+    /// for calls to a registration index none of the handles has, and for
+    /// methods that cannot be replayed, such as `call_dynamic`.
+    pub outcome: CallOutcome,
+    pub duration: Duration,
+}
+
+impl ReplayedCall {
+    /// True if the call returned what it did when it was recorded, or
+    /// failed the same way.
+    pub fn matches(&self) -> bool {
+        let now = match &self.outcome {
+            Ok(result) => (result.as_deref(), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        (now.0, now.1.as_deref()) == self.recorded.outcome()
+    }
+}
+
+/// Make the recorded `calls` again, in order, through proxies of the
+/// registration with the same index among `handles`, for example the
+/// handles `PluginManager::load_library` returned for the build to check.
+/// The recorded library paths are not looked at.
+pub fn replay_calls(calls: &[RecordedCall], handles: &[PluginHandle]) -> Vec<ReplayedCall> {
+    calls
+        .iter()
+        .map(|call| {
+            let start = Instant::now();
+            let outcome = handles
+                .iter()
+                .find(|h| h.index() == call.index)
+                .ok_or(PluginCallError::Unsupported)
+                .and_then(|handle| replay_one(handle, call));
+            ReplayedCall {
+                recorded: call.clone(),
+                outcome,
+                duration: start.elapsed(),
+            }
+        })
+        .collect()
+}
+
+fn replay_one(handle: &PluginHandle, call: &RecordedCall) -> CallOutcome {
+    let arg = || call.args.first().map(String::as_str).unwrap_or_default();
+    let greeter = || handle.as_greeter().ok_or(PluginCallError::Unsupported);
+    let transformer = || handle.as_transformer().ok_or(PluginCallError::Unsupported);
+    match call.method.as_str() {
+        "name" => greeter()?.try_name().map(Some),
+        "greet" => greeter()?.try_greet(arg()).map(|()| None),
+        "transform" => transformer()?.try_transform(arg()).map(Some),
+        "describe" => transformer()?.try_describe().map(Some),
+        _ => Err(PluginCallError::Unsupported),
+    }
+}
@@ -0,0 +1,462 @@
+//! Installing plugins from a registry: a JSON index listing, for each
+//! plugin version and target triple, where its artifact is and the
+//! SHA-256 digest and optional ed25519 signature it must have.
+//!
+//! ```json
+//! {"plugins": [{"name": "upper", "version": "1.2.0",
+//!               "target": "x86_64-unknown-linux-gnu",
+//!               "url": "libplugin_upper.so", "sha256": "<64 hex digits>",
+//!               "signature": "<128 hex digits>"}]}
+//! ```
+//!
+//! URLs without a scheme are relative to the index. The built-in fetcher
+//! reads `file://` URLs and plain paths, and `http://` URLs over a bare
+//! HTTP/1.0 connection; hosts that need `https` or authentication supply
+//! their own `RegistryFetcher`.
+
+use crate::digest::{DigestMismatch, Sha256Digest};
+use crate::manager::PluginLoadError;
+use crate::signing::{signature_path, TrustedKeys};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// One artifact listed in a registry index.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegistryEntry {
+    pub name: String,
+    /// Semantic version of this build of the plugin.
+    pub version: String,
+    /// Target triple the artifact was built for, e.g.
+    /// `x86_64-unknown-linux-gnu`.
+    pub target: String,
+    /// Where to download the artifact, absolute or relative to the index.
+    pub url: String,
+    /// SHA-256 digest of the artifact as 64 hex digits.
+    pub sha256: String,
+    /// Detached ed25519 signature over the artifact as 128 hex digits.
+    /// Required while the manager trusts any key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+/// The contents of a registry index.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegistryIndex {
+    pub plugins: Vec<RegistryEntry>,
+}
+
+impl RegistryIndex {
+    /// Parse an index from its JSON text.
+    pub fn parse(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|e| e.to_string())
+    }
+
+    /// The newest entry for `name` built for `target` whose version
+    /// matches `req`. Entries with versions that do not parse are ignored.
+    pub fn find(
+        &self,
+        name: &str,
+        req: &semver::VersionReq,
+        target: &str,
+    ) -> Option<&RegistryEntry> {
+        self.plugins
+            .iter()
+            .filter(|e| e.name == name && e.target == target)
+            .filter_map(|e| Some((semver::Version::parse(&e.version).ok()?, e)))
+            .filter(|(version, _)| req.matches(version))
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, e)| e)
+    }
+}
+
+/// Downloads registry indexes and artifacts.
+pub trait RegistryFetcher: Send + Sync {
+    /// The bytes at `url`.
+    fn fetch(&self, url: &str) -> Result<Vec<u8>, String>;
+}
+
+/// The built-in fetcher: `file://` URLs and plain paths, and `http://`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultFetcher;
+
+impl RegistryFetcher for DefaultFetcher {
+    fn fetch(&self, url: &str) -> Result<Vec<u8>, String> {
+        if let Some(rest) = url.strip_prefix("http://") {
+            http_get(rest)
+        } else if let Some(path) = url.strip_prefix("file://") {
+            std::fs::read(path).map_err(|e| e.to_string())
+        } else if let Some((scheme, _)) = url.split_once("://") {
+            Err(format!(
+                "{} URLs need a RegistryFetcher that supports them",
+                scheme
+            ))
+        } else {
+            std::fs::read(url).map_err(|e| e.to_string())
+        }
+    }
+}
+
+/// Where `PluginManager::install_from_registry` finds and installs plugins.
+#[derive(Clone)]
+pub struct RegistryOptions {
+    /// URL of the index.
+    pub index_url: String,
+    /// Directory artifacts are installed into, with their signatures.
+    pub dir: PathBuf,
+    /// Target triple to install artifacts for; `host_target()` by default.
+    pub target: String,
+    /// How the index and artifacts are downloaded.
+    pub fetcher: Arc<dyn RegistryFetcher>,
+}
+
+impl std::fmt::Debug for RegistryOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RegistryOptions")
+            .field("index_url", &self.index_url)
+            .field("dir", &self.dir)
+            .field("target", &self.target)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RegistryOptions {
+    /// Install from the index at `index_url` into `dir`, for this host's
+    /// target, with the `DefaultFetcher`.
+    pub fn new(index_url: impl Into<String>, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            index_url: index_url.into(),
+            dir: dir.into(),
+            target: host_target(),
+            fetcher: Arc::new(DefaultFetcher),
+        }
+    }
+
+    pub fn with_target(mut self, target: impl Into<String>) -> Self {
+        self.target = target.into();
+        self
+    }
+
+    pub fn with_fetcher(mut self, fetcher: impl RegistryFetcher + 'static) -> Self {
+        self.fetcher = Arc::new(fetcher);
+        self
+    }
+}
+
+/// The target triple of the running host, as registry entries name it:
+/// `<arch>-unknown-linux-gnu`, `<arch>-apple-darwin`,
+/// `<arch>-pc-windows-msvc` and so on.
+pub fn host_target() -> String {
+    let arch = std::env::consts::ARCH;
+    let rest = match std::env::consts::OS {
+        "linux" if cfg!(target_env = "musl") => "unknown-linux-musl",
+        "linux" => "unknown-linux-gnu",
+        "macos" => "apple-darwin",
+        "windows" if cfg!(target_env = "gnu") => "pc-windows-gnu",
+        "windows" => "pc-windows-msvc",
+        "freebsd" => "unknown-freebsd",
+        os => return format!("{}-unknown-{}", arch, os),
+    };
+    format!("{}-{}", arch, rest)
+}
+
+/// Why `PluginManager::install_from_registry` found nothing to install.
+/// Artifacts that fail their digest or signature check are reported as
+/// `PluginLoadError::DigestMismatch` and `PluginLoadError::SignatureInvalid`.
+#[derive(Debug)]
+pub enum RegistryError {
+    /// `PluginManager::set_registry` was not called.
+    NotConfigured,
+    /// The index or an artifact could not be downloaded.
+    Fetch { url: String, reason: String },
+    /// The index is not valid JSON in the index format.
+    InvalidIndex { url: String, reason: String },
+    /// The version requirement does not parse.
+    InvalidVersionReq(String),
+    /// No entry has the name, a matching version and the target.
+    NotFound {
+        name: String,
+        version_req: String,
+        target: String,
+    },
+    /// The chosen entry has a malformed digest, signature or URL.
+    InvalidEntry { name: String, reason: String },
+}
+
+impl std::fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RegistryError::NotConfigured => write!(f, "no registry is configured"),
+            RegistryError::Fetch { url, reason } => write!(f, "cannot fetch {}: {}", url, reason),
+            RegistryError::InvalidIndex { url, reason } => {
+                write!(f, "invalid registry index {}: {}", url, reason)
+            }
+            RegistryError::InvalidVersionReq(req) => {
+                write!(f, "invalid version requirement {:?}", req)
+            }
+            RegistryError::NotFound {
+                name,
+                version_req,
+                target,
+            } => write!(
+                f,
+                "no {} {} for {} in the registry",
+                name, version_req, target
+            ),
+            RegistryError::InvalidEntry { name, reason } => {
+                write!(f, "registry entry for {} is invalid: {}", name, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+/// Find `name` matching `version_req` in the index, download its artifact
+/// into `opts.dir` unless a copy with the right digest is there already,
+/// check it and write its signature next to it. Returns the artifact's
+/// path.
+pub(crate) fn install(
+    opts: &RegistryOptions,
+    keys: &TrustedKeys,
+    name: &str,
+    version_req: &str,
+) -> Result<PathBuf, PluginLoadError> {
+    let req = semver::VersionReq::parse(version_req)
+        .map_err(|_| RegistryError::InvalidVersionReq(version_req.to_string()))
+        .map_err(PluginLoadError::Registry)?;
+    let index = fetch(opts, &opts.index_url)?;
+    let index = std::str::from_utf8(&index)
+        .map_err(|e| e.to_string())
+        .and_then(RegistryIndex::parse)
+        .map_err(|reason| RegistryError::InvalidIndex {
+            url: opts.index_url.clone(),
+            reason,
+        })
+        .map_err(PluginLoadError::Registry)?;
+    let entry = index
+        .find(name, &req, &opts.target)
+        .ok_or_else(|| RegistryError::NotFound {
+            name: name.to_string(),
+            version_req: version_req.to_string(),
+            target: opts.target.clone(),
+        })
+        .map_err(PluginLoadError::Registry)?;
+    let invalid = |reason: &str| {
+        PluginLoadError::Registry(RegistryError::InvalidEntry {
+            name: name.to_string(),
+            reason: reason.to_string(),
+        })
+    };
+    let expected = Sha256Digest::from_hex(&entry.sha256).ok_or_else(|| invalid("bad sha256"))?;
+    let signature = match &entry.signature {
+        Some(hex) => Some(decode_hex(hex).ok_or_else(|| invalid("bad signature"))?),
+        None => None,
+    };
+    let url = resolve(&opts.index_url, &entry.url);
+    let file_name = url
+        .rsplit('/')
+        .next()
+        .filter(|n| !n.is_empty() && *n != "." && *n != "..")
+        .ok_or_else(|| invalid("URL has no file name"))?;
+    let path = opts.dir.join(file_name);
+
+    let installed = std::fs::read(&path)
+        .ok()
+        .filter(|bytes| Sha256Digest::of_bytes(bytes) == expected);
+    let bytes = match installed {
+        Some(bytes) => bytes,
+        None => {
+            let bytes = fetch(opts, &url)?;
+            let actual = Sha256Digest::of_bytes(&bytes);
+            if actual != expected {
+                return Err(PluginLoadError::DigestMismatch(DigestMismatch {
+                    path,
+                    expected: Some(expected),
+                    actual,
+                }));
+            }
+            bytes
+        }
+    };
+    keys.verify_bytes(&bytes, signature.as_deref())
+        .map_err(|error| PluginLoadError::SignatureInvalid {
+            path: path.clone(),
+            error,
+        })?;
+
+    std::fs::create_dir_all(&opts.dir).map_err(PluginLoadError::Io)?;
+    if let Some(signature) = &signature {
+        std::fs::write(signature_path(&path), signature).map_err(PluginLoadError::Io)?;
+    }
+    // Written under another name and renamed, so a watcher or another
+    // process never sees half an artifact.
+    let partial = opts.dir.join(format!(".{}.partial", file_name));
+    std::fs::write(&partial, &bytes)
+        .and_then(|()| std::fs::rename(&partial, &path))
+        .map_err(PluginLoadError::Io)?;
+    Ok(path)
+}
+
+fn fetch(opts: &RegistryOptions, url: &str) -> Result<Vec<u8>, PluginLoadError> {
+    opts.fetcher.fetch(url).map_err(|reason| {
+        PluginLoadError::Registry(RegistryError::Fetch {
+            url: url.to_string(),
+            reason,
+        })
+    })
+}
+
+/// `url` as written in the index at `index_url`: unchanged if it has a
+/// scheme or is an absolute path, else relative to the index's directory.
+fn resolve(index_url: &str, url: &str) -> String {
+    if url.contains("://") || Path::new(url).is_absolute() {
+        return url.to_string();
+    }
+    match index_url.rfind('/') {
+        Some(i) => format!("{}{}", &index_url[..=i], url),
+        None => url.to_string(),
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    let hex = hex.trim().as_bytes();
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    let nibble = |c: u8| (c as char).to_digit(16).map(|d| d as u8);
+    hex.chunks(2)
+        .map(|pair| Some(nibble(pair[0])? << 4 | nibble(pair[1])?))
+        .collect()
+}
+
+/// GET `http://<rest>` over HTTP/1.0, so the body is never chunked and
+/// ends with the connection.
+fn http_get(rest: &str) -> Result<Vec<u8>, String> {
+    let (host, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let addr = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{}:80", host)
+    };
+    let mut stream = TcpStream::connect(&addr).map_err(|e| e.to_string())?;
+    stream
+        .set_read_timeout(Some(Duration::from_secs(30)))
+        .map_err(|e| e.to_string())?;
+    let request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: plugin-interface\r\n\r\n",
+        path, host
+    );
+    stream
+        .write_all(request.as_bytes())
+        .map_err(|e| e.to_string())?;
+    let mut response = Vec::new();
+    stream
+        .read_to_end(&mut response)
+        .map_err(|e| e.to_string())?;
+    let head_end = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or("response has no header")?;
+    let head = String::from_utf8_lossy(&response[..head_end]);
+    let status = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse::<u16>().ok())
+        .ok_or("response has no status")?;
+    if !(200..300).contains(&status) {
+        return Err(format!("HTTP status {}", status));
+    }
+    Ok(response.split_off(head_end + 4))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    fn entry(version: &str, target: &str) -> RegistryEntry {
+        RegistryEntry {
+            name: "upper".to_string(),
+            version: version.to_string(),
+            target: target.to_string(),
+            url: format!("upper-{}.so", version),
+            sha256: String::new(),
+            signature: None,
+        }
+    }
+
+    #[test]
+    fn the_newest_matching_entry_for_the_target_is_found() {
+        let index = RegistryIndex {
+            plugins: vec![
+                entry("1.0.0", "t"),
+                entry("1.4.0", "t"),
+                entry("1.9.0", "other"),
+                entry("2.0.0", "t"),
+                entry("not a version", "t"),
+            ],
+        };
+        let req = semver::VersionReq::parse("^1").unwrap();
+        assert_eq!(index.find("upper", &req, "t").unwrap().version, "1.4.0");
+        assert!(index.find("lower", &req, "t").is_none());
+        let req = semver::VersionReq::parse(">=3").unwrap();
+        assert!(index.find("upper", &req, "t").is_none());
+
+        let json = serde_json::to_string(&index).unwrap();
+        assert_eq!(RegistryIndex::parse(&json).unwrap(), index);
+        assert!(RegistryIndex::parse("{}").is_err());
+    }
+
+    #[test]
+    fn urls_resolve_against_the_index() {
+        assert_eq!(
+            resolve("http://host/plugins/index.json", "libupper.so"),
+            "http://host/plugins/libupper.so"
+        );
+        assert_eq!(
+            resolve("http://host/index.json", "file:///srv/libupper.so"),
+            "file:///srv/libupper.so"
+        );
+        assert_eq!(resolve("index.json", "libupper.so"), "libupper.so");
+        assert_eq!(decode_hex("0aFf"), Some(vec![0x0a, 0xff]));
+        assert_eq!(decode_hex("abc"), None);
+        assert_eq!(decode_hex("zz"), None);
+    }
+
+    #[test]
+    fn http_urls_are_fetched_and_error_statuses_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            for reply in [
+                "HTTP/1.0 200 OK\r\nContent-Length: 5\r\n\r\nhello",
+                "HTTP/1.0 404 Not Found\r\n\r\n",
+            ] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = Vec::new();
+                let mut byte = [0u8];
+                while !request.ends_with(b"\r\n\r\n") {
+                    stream.read_exact(&mut byte).unwrap();
+                    request.push(byte[0]);
+                }
+                assert!(request.starts_with(b"GET /index.json HTTP/1.0\r\n"));
+                stream.write_all(reply.as_bytes()).unwrap();
+            }
+        });
+        let url = format!("http://{}/index.json", addr);
+        assert_eq!(DefaultFetcher.fetch(&url).unwrap(), b"hello");
+        assert_eq!(DefaultFetcher.fetch(&url).unwrap_err(), "HTTP status 404");
+        server.join().unwrap();
+        assert!(DefaultFetcher
+            .fetch("https://example.com/index.json")
+            .unwrap_err()
+            .contains("RegistryFetcher"));
+    }
+}
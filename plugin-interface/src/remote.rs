@@ -0,0 +1,524 @@
+//! Plugins in another process or on another machine. `serve_remote` loads
+//! a library there and answers calls over TCP; `PluginManager::connect_remote`
+//! makes a registration for each of its registrations whose methods send
+//! the call and wait for the answer. Requests and responses are JSON
+//! objects, each preceded by its length as a big-endian `u32`. With
+//! `PluginManager::set_shared_memory`, a server on the same machine is
+//! asked to attach to a shared region first, and large strings go there.
+
+use crate::modules::{ModuleLoader, ModulePlugin, ModuleRegistration};
+use crate::shared_memory::{Attach, Payload, SharedMemoryOptions, SharedRegion};
+use crate::{
+    Greeter, GreeterProxy, HealthStatus, PluginHandle, PluginManager, PluginTrait, Transformer,
+    TransformerProxy,
+};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+
+/// Frames longer than this are refused rather than allocated.
+const MAX_FRAME: usize = 16 << 20;
+
+/// Request sent from the host to the server, one per frame.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Request {
+    Attach(Attach),
+    Registrations { trait_name: String },
+    Greet { index: usize, target: Payload },
+    Transform { index: usize, input: Payload },
+    Describe { index: usize },
+    Health { trait_name: String, index: usize },
+}
+
+/// Server reply to a `Request`, one per frame.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "reply", rename_all = "snake_case")]
+enum Response {
+    Registrations { registrations: Vec<RemoteEntry> },
+    Text { text: Payload },
+    Health { status: u8 },
+    Done,
+    Error { message: String },
+}
+
+/// A registration of the served library: its registration name, and for
+/// greeters the name `Greeter::name` returns, which is asked for once.
+#[derive(Debug, Serialize, Deserialize)]
+struct RemoteEntry {
+    name: String,
+    greeter_name: Option<String>,
+}
+
+fn write_frame(out: &mut impl Write, value: &impl Serialize) -> Result<(), String> {
+    let bytes = serde_json::to_vec(value).map_err(|e| e.to_string())?;
+    let len = u32::try_from(bytes.len())
+        .ok()
+        .filter(|&len| len as usize <= MAX_FRAME)
+        .ok_or_else(|| format!("frame of {} bytes is too long", bytes.len()))?;
+    out.write_all(&len.to_be_bytes())
+        .and_then(|_| out.write_all(&bytes))
+        .and_then(|_| out.flush())
+        .map_err(|e| e.to_string())
+}
+
+/// The next frame, or `Ok(None)` when the peer closed the connection
+/// between frames.
+fn read_frame<T: DeserializeOwned>(input: &mut impl Read) -> Result<Option<T>, String> {
+    let mut len = [0u8; 4];
+    match input.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.to_string()),
+    }
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME {
+        return Err(format!("frame of {} bytes is too long", len));
+    }
+    let mut bytes = vec![0u8; len];
+    input.read_exact(&mut bytes).map_err(|e| e.to_string())?;
+    serde_json::from_slice(&bytes)
+        .map(Some)
+        .map_err(|e| format!("bad frame: {}", e))
+}
+
+fn health_code(status: HealthStatus) -> u8 {
+    status as u8
+}
+
+fn health_status(code: u8) -> HealthStatus {
+    match code {
+        0 => HealthStatus::Healthy,
+        1 => HealthStatus::Degraded,
+        _ => HealthStatus::Unhealthy,
+    }
+}
+
+/// An open connection, and whether the server attached to the shared
+/// region on it.
+struct Link {
+    reader: BufReader<TcpStream>,
+    writer: BufWriter<TcpStream>,
+    attached: bool,
+}
+
+impl Link {
+    fn roundtrip(&mut self, req: &Request) -> Result<Response, String> {
+        write_frame(&mut self.writer, req)?;
+        read_frame(&mut self.reader)?.ok_or_else(|| "server closed the connection".to_string())
+    }
+}
+
+/// The connection to a server, shared by the registrations made from it.
+/// A connection that fails is dropped, and the next call opens a new one.
+struct Connection {
+    addr: String,
+    shared: Option<(SharedRegion, Attach)>,
+    link: Mutex<Option<Link>>,
+}
+
+impl Connection {
+    /// Connect to `addr` and offer the server the shared region, if any.
+    /// A server that cannot attach gets every payload inline.
+    fn open(addr: &str, shared: Option<&(SharedRegion, Attach)>) -> Result<Link, String> {
+        let stream = TcpStream::connect(addr).map_err(|e| e.to_string())?;
+        stream.set_nodelay(true).map_err(|e| e.to_string())?;
+        let mut link = Link {
+            reader: BufReader::new(stream.try_clone().map_err(|e| e.to_string())?),
+            writer: BufWriter::new(stream),
+            attached: false,
+        };
+        if let Some((_, attach)) = shared {
+            link.attached = matches!(
+                link.roundtrip(&Request::Attach(attach.clone()))?,
+                Response::Done
+            );
+        }
+        Ok(link)
+    }
+
+    /// Send the request `make` builds, given the shared region when the
+    /// server is attached to it, and return the answer with that region.
+    fn call(
+        &self,
+        make: impl FnOnce(Option<&SharedRegion>) -> Request,
+    ) -> Result<(Response, Option<&SharedRegion>), String> {
+        let mut slot = self.link.lock().unwrap_or_else(|e| e.into_inner());
+        if slot.is_none() {
+            *slot = Some(Self::open(&self.addr, self.shared.as_ref())?);
+        }
+        let link = slot.as_mut().expect("connection present");
+        let region = self
+            .shared
+            .as_ref()
+            .map(|(region, _)| region)
+            .filter(|_| link.attached);
+        match link.roundtrip(&make(region)) {
+            Ok(Response::Error { message }) => Err(message),
+            Ok(resp) => Ok((resp, region)),
+            Err(e) => {
+                *slot = None;
+                Err(e)
+            }
+        }
+    }
+
+    /// The answer to the request, which must be text. Methods have no
+    /// error channel of their own, so failures panic; the host's vtable
+    /// catches the panic and the proxy reports `PluginCallError::Failed`.
+    fn text(&self, make: impl FnOnce(Option<&SharedRegion>) -> Request) -> String {
+        let text = match self.call(make) {
+            Ok((Response::Text { text }, region)) => text.into_string(region),
+            Ok((other, _)) => panic!("remote plugin at {}: unexpected {:?}", self.addr, other),
+            Err(e) => Err(e),
+        };
+        text.unwrap_or_else(|e| panic!("remote plugin at {}: {}", self.addr, e))
+    }
+
+    fn done(&self, make: impl FnOnce(Option<&SharedRegion>) -> Request) {
+        match self.call(make).map(|(resp, _)| resp) {
+            Ok(Response::Done) => {}
+            Ok(other) => panic!("remote plugin at {}: unexpected {:?}", self.addr, other),
+            Err(e) => panic!("remote plugin at {}: {}", self.addr, e),
+        }
+    }
+
+    fn health(&self, trait_id: PluginTrait, index: usize) -> HealthStatus {
+        match self.call(|_| Request::Health {
+            trait_name: trait_id.as_str().to_string(),
+            index,
+        }) {
+            Ok((Response::Health { status }, _)) => health_status(status),
+            _ => HealthStatus::Unhealthy,
+        }
+    }
+}
+
+struct RemoteGreeter {
+    connection: Arc<Connection>,
+    index: usize,
+    name: String,
+}
+
+impl Greeter for RemoteGreeter {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn greet(&self, target: &str) {
+        self.connection.done(|region| Request::Greet {
+            index: self.index,
+            target: Payload::new(target, region, 0),
+        })
+    }
+    fn health(&self) -> HealthStatus {
+        self.connection.health(PluginTrait::Greeter, self.index)
+    }
+}
+
+struct RemoteTransformer {
+    connection: Arc<Connection>,
+    index: usize,
+}
+
+impl Transformer for RemoteTransformer {
+    fn transform(&self, input: &str) -> String {
+        self.connection.text(|region| Request::Transform {
+            index: self.index,
+            input: Payload::new(input, region, 0),
+        })
+    }
+    fn describe(&self) -> String {
+        self.connection
+            .text(|_| Request::Describe { index: self.index })
+    }
+    fn health(&self) -> HealthStatus {
+        self.connection.health(PluginTrait::Transformer, self.index)
+    }
+}
+
+/// Makes the registrations of the server at `addr`, for
+/// `modules::open_module`.
+pub(crate) struct RemoteLoader {
+    pub(crate) addr: String,
+    pub(crate) shared_memory: Option<SharedMemoryOptions>,
+}
+
+impl ModuleLoader for RemoteLoader {
+    fn accepts(&self, _path: &Path) -> bool {
+        false
+    }
+
+    fn open(&self, _path: &Path, trait_id: PluginTrait) -> Result<Vec<ModuleRegistration>, String> {
+        // Without a region, payloads go inline; the plugin still works.
+        let shared = self
+            .shared_memory
+            .as_ref()
+            .and_then(|opts| SharedRegion::create(opts).ok());
+        let link = Connection::open(&self.addr, shared.as_ref())
+            .map_err(|e| format!("cannot connect to {}: {}", self.addr, e))?;
+        let connection = Arc::new(Connection {
+            addr: self.addr.clone(),
+            shared,
+            link: Mutex::new(Some(link)),
+        });
+        let entries = match connection.call(|_| Request::Registrations {
+            trait_name: trait_id.as_str().to_string(),
+        })? {
+            (Response::Registrations { registrations }, _) => registrations,
+            (other, _) => return Err(format!("unexpected reply {:?}", other)),
+        };
+        Ok(entries
+            .into_iter()
+            .enumerate()
+            .map(|(index, entry)| ModuleRegistration {
+                plugin: match trait_id {
+                    PluginTrait::Greeter => ModulePlugin::Greeter(Box::new(RemoteGreeter {
+                        connection: connection.clone(),
+                        index,
+                        name: entry.greeter_name.unwrap_or_else(|| entry.name.clone()),
+                    })),
+                    PluginTrait::Transformer => {
+                        ModulePlugin::Transformer(Box::new(RemoteTransformer {
+                            connection: connection.clone(),
+                            index,
+                        }))
+                    }
+                },
+                name: entry.name,
+            })
+            .collect())
+    }
+}
+
+/// The path the registrations of the server at `addr` are loaded under,
+/// e.g. `remote:127.0.0.1:7000`.
+pub(crate) fn remote_path(addr: &str) -> PathBuf {
+    PathBuf::from(format!("remote:{}", addr))
+}
+
+/// Load the library at `lib_path` for every built-in trait it provides and
+/// answer `PluginManager::connect_remote` calls to it from connections to
+/// `listener`, until the listener fails. Calls from all connections run
+/// on this thread, one at a time.
+pub fn serve_remote(listener: TcpListener, lib_path: &Path) -> Result<(), String> {
+    let mut mgr = PluginManager::new();
+    let greeters = mgr.load_library(lib_path, PluginTrait::Greeter);
+    let transformers = mgr.load_library(lib_path, PluginTrait::Transformer);
+    if let (Err(e), Err(_)) = (&greeters, &transformers) {
+        return Err(format!("failed to load {:?}: {:?}", lib_path, e));
+    }
+    let served = Served::new(
+        greeters.unwrap_or_default(),
+        transformers.unwrap_or_default(),
+    );
+
+    let (tx, rx) = mpsc::channel::<Call>();
+    let acceptor = std::thread::spawn(move || -> Result<(), String> {
+        for stream in listener.incoming() {
+            let stream = stream.map_err(|e| e.to_string())?;
+            let tx = tx.clone();
+            std::thread::spawn(move || serve_connection(stream, tx));
+        }
+        Ok(())
+    });
+    for (req, region, reply) in rx {
+        let _ = reply.send(served.answer(req, region.as_deref()));
+    }
+    acceptor
+        .join()
+        .unwrap_or_else(|_| Err("acceptor panicked".to_string()))
+}
+
+/// A request for the serving thread, the shared region of its connection
+/// and where to send the answer.
+type Call = (Request, Option<Arc<SharedRegion>>, mpsc::Sender<Response>);
+
+/// Pass the requests of one connection to the serving thread and write
+/// back its answers, until the connection closes. Attaching to the host's
+/// shared region is handled here, since it belongs to the connection.
+fn serve_connection(stream: TcpStream, calls: mpsc::Sender<Call>) {
+    let _ = stream.set_nodelay(true);
+    let Ok(read_half) = stream.try_clone() else {
+        return;
+    };
+    let mut reader = BufReader::new(read_half);
+    let mut writer = BufWriter::new(stream);
+    let mut region = None;
+    loop {
+        let resp = match read_frame::<Request>(&mut reader) {
+            Ok(None) => return,
+            Ok(Some(Request::Attach(attach))) => match SharedRegion::attach(&attach) {
+                Ok(attached) => {
+                    region = Some(Arc::new(attached));
+                    Response::Done
+                }
+                Err(message) => Response::Error { message },
+            },
+            Ok(Some(req)) => {
+                let (reply, answer) = mpsc::channel();
+                if calls.send((req, region.clone(), reply)).is_err() {
+                    return;
+                }
+                match answer.recv() {
+                    Ok(resp) => resp,
+                    Err(_) => return,
+                }
+            }
+            Err(e) => Response::Error {
+                message: format!("bad request: {}", e),
+            },
+        };
+        if write_frame(&mut writer, &resp).is_err() {
+            return;
+        }
+    }
+}
+
+/// The registrations `serve_remote` answers for.
+struct Served {
+    greeter_handles: Vec<PluginHandle>,
+    greeters: Vec<GreeterProxy>,
+    transformer_handles: Vec<PluginHandle>,
+    transformers: Vec<TransformerProxy>,
+}
+
+impl Served {
+    fn new(greeter_handles: Vec<PluginHandle>, transformer_handles: Vec<PluginHandle>) -> Self {
+        Self {
+            greeters: greeter_handles
+                .iter()
+                .filter_map(|h| h.as_greeter())
+                .collect(),
+            transformers: transformer_handles
+                .iter()
+                .filter_map(|h| h.as_transformer())
+                .collect(),
+            greeter_handles,
+            transformer_handles,
+        }
+    }
+
+    fn handles(&self, trait_name: &str) -> Option<&[PluginHandle]> {
+        if trait_name == PluginTrait::Greeter.as_str() {
+            Some(&self.greeter_handles)
+        } else if trait_name == PluginTrait::Transformer.as_str() {
+            Some(&self.transformer_handles)
+        } else {
+            None
+        }
+    }
+
+    fn answer(&self, req: Request, region: Option<&SharedRegion>) -> Response {
+        let failed = |e: crate::PluginCallError| Response::Error {
+            message: e.to_string(),
+        };
+        let bad_payload = |message| Response::Error { message };
+        match req {
+            Request::Attach(_) => Response::Error {
+                message: "attach is answered by the connection".to_string(),
+            },
+            Request::Registrations { trait_name } => {
+                let Some(handles) = self.handles(&trait_name) else {
+                    return unknown_trait(&trait_name);
+                };
+                let is_greeter = trait_name == PluginTrait::Greeter.as_str();
+                let registrations = handles
+                    .iter()
+                    .enumerate()
+                    .map(|(index, h)| RemoteEntry {
+                        name: h
+                            .registration_name()
+                            .unwrap_or_else(|| format!("#{}", index)),
+                        greeter_name: is_greeter
+                            .then(|| self.greeters.get(index)?.try_name().ok())
+                            .flatten(),
+                    })
+                    .collect();
+                Response::Registrations { registrations }
+            }
+            Request::Greet { index, target } => match self.greeters.get(index) {
+                Some(p) => match target.text(region) {
+                    Ok(target) => p.try_greet(target).map_or_else(failed, |()| Response::Done),
+                    Err(e) => bad_payload(e),
+                },
+                None => no_such_registration(index),
+            },
+            Request::Transform { index, input } => match self.transformers.get(index) {
+                Some(p) => match input.text(region) {
+                    Ok(text) => p
+                        .try_transform(text)
+                        .map_or_else(failed, |out| Response::Text {
+                            text: Payload::new(&out, region, input.end()),
+                        }),
+                    Err(e) => bad_payload(e),
+                },
+                None => no_such_registration(index),
+            },
+            Request::Describe { index } => match self.transformers.get(index) {
+                Some(p) => p.try_describe().map_or_else(failed, |text| Response::Text {
+                    text: Payload::Inline(text),
+                }),
+                None => no_such_registration(index),
+            },
+            Request::Health { trait_name, index } => {
+                let Some(handles) = self.handles(&trait_name) else {
+                    return unknown_trait(&trait_name);
+                };
+                match handles.get(index) {
+                    Some(h) => h.health().map_or_else(failed, |status| Response::Health {
+                        status: health_code(status),
+                    }),
+                    None => no_such_registration(index),
+                }
+            }
+        }
+    }
+}
+
+fn unknown_trait(trait_name: &str) -> Response {
+    Response::Error {
+        message: format!("unknown trait {}", trait_name),
+    }
+}
+
+fn no_such_registration(index: usize) -> Response {
+    Response::Error {
+        message: format!("no registration at index {}", index),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_round_trip_and_refuse_oversized_lengths() {
+        let mut buf = Vec::new();
+        write_frame(
+            &mut buf,
+            &Request::Greet {
+                index: 1,
+                target: Payload::Inline("world".to_string()),
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            u32::from_be_bytes(buf[..4].try_into().unwrap()) as usize,
+            buf.len() - 4
+        );
+        let mut input = &buf[..];
+        match read_frame::<Request>(&mut input).unwrap() {
+            Some(Request::Greet { index, target }) => {
+                assert_eq!((index, target.text(None)), (1, Ok("world")))
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(read_frame::<Request>(&mut input).unwrap().is_none());
+
+        let oversized = ((MAX_FRAME + 1) as u32).to_be_bytes();
+        assert!(read_frame::<Request>(&mut &oversized[..]).is_err());
+    }
+}
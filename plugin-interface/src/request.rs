@@ -0,0 +1,167 @@
+//! Per-call request context: a correlation ID, a deadline and a
+//! cancellation flag the host hands to plugin methods through their
+//! `<method>_ctx` vtable entries. Plugins read it with
+//! `with_current_request` while the call runs, so they can tag their logs
+//! and stop early; a plugin calling another one passes it on the same way.
+
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The request context as it crosses the plugin boundary. Built by the host
+/// from a `RequestContext`; valid for the duration of the call only.
+#[repr(C)]
+#[derive(Debug)]
+pub struct RawRequestContext {
+    /// `size_of` the struct in the host that built it, so plugins can tell
+    /// which fields appended after this one it has.
+    pub struct_size: usize,
+    /// ID tying the call to the request that caused it; 0 for none.
+    pub correlation_id: u64,
+    /// When the caller stops waiting, in milliseconds since the Unix
+    /// epoch; 0 for none.
+    pub deadline_ms: u64,
+    /// Set by the host to ask the plugin to stop; null if the call cannot
+    /// be cancelled.
+    pub cancelled: *const AtomicBool,
+}
+
+impl RawRequestContext {
+    /// The correlation ID, if the host set one.
+    pub fn correlation_id(&self) -> Option<u64> {
+        (self.correlation_id != 0).then_some(self.correlation_id)
+    }
+
+    /// The deadline, if the host set one.
+    pub fn deadline(&self) -> Option<SystemTime> {
+        (self.deadline_ms != 0).then(|| UNIX_EPOCH + Duration::from_millis(self.deadline_ms))
+    }
+
+    /// True once the host cancelled the request or its deadline passed.
+    /// Long-running plugin methods check this and return early.
+    pub fn is_cancelled(&self) -> bool {
+        if !self.cancelled.is_null() && unsafe { &*self.cancelled }.load(Ordering::Acquire) {
+            return true;
+        }
+        self.deadline().is_some_and(|d| SystemTime::now() >= d)
+    }
+}
+
+/// Host-side builder for the context a proxy passes along with a call, as
+/// in `GreeterProxy::greet_in`.
+///
+/// ```no_run
+/// # use plugin_interface::RequestContext;
+/// # use std::sync::{atomic::AtomicBool, Arc};
+/// # use std::time::Duration;
+/// let cancel = Arc::new(AtomicBool::new(false));
+/// let ctx = RequestContext::new()
+///     .with_correlation_id(42)
+///     .with_timeout(Duration::from_secs(2))
+///     .with_cancel_flag(cancel.clone());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct RequestContext {
+    correlation_id: u64,
+    deadline: Option<SystemTime>,
+    cancelled: Option<Arc<AtomicBool>>,
+}
+
+impl RequestContext {
+    /// A context with no correlation ID, deadline or cancellation flag.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tag the call with `id`, which plugins see as `correlation_id`.
+    pub fn with_correlation_id(mut self, id: u64) -> Self {
+        self.correlation_id = id;
+        self
+    }
+
+    /// Stop waiting at `deadline`.
+    pub fn with_deadline(mut self, deadline: SystemTime) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Stop waiting `timeout` from now.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        self.with_deadline(SystemTime::now() + timeout)
+    }
+
+    /// Share `flag` with the plugins the context is handed to; storing
+    /// `true` in it asks them to stop.
+    pub fn with_cancel_flag(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.cancelled = Some(flag);
+        self
+    }
+
+    /// The correlation ID, if one was set.
+    pub fn correlation_id(&self) -> Option<u64> {
+        (self.correlation_id != 0).then_some(self.correlation_id)
+    }
+
+    /// The deadline, if one was set.
+    pub fn deadline(&self) -> Option<SystemTime> {
+        self.deadline
+    }
+
+    /// True once the cancellation flag is set or the deadline passed.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled
+            .as_ref()
+            .is_some_and(|c| c.load(Ordering::Acquire))
+            || self.deadline.is_some_and(|d| SystemTime::now() >= d)
+    }
+
+    /// The struct handed to the plugin; borrows the cancellation flag, so
+    /// it must not outlive `self`.
+    pub(crate) fn raw(&self) -> RawRequestContext {
+        let deadline_ms = self.deadline.map_or(0, |d| {
+            // A deadline at the epoch itself would read as "none".
+            d.duration_since(UNIX_EPOCH)
+                .map_or(1, |since| since.as_millis().max(1) as u64)
+        });
+        RawRequestContext {
+            struct_size: std::mem::size_of::<RawRequestContext>(),
+            correlation_id: self.correlation_id,
+            deadline_ms,
+            cancelled: self
+                .cancelled
+                .as_ref()
+                .map_or(std::ptr::null(), Arc::as_ptr),
+        }
+    }
+}
+
+thread_local! {
+    /// The context of the `<method>_ctx` call this thread is inside of.
+    static CURRENT: Cell<*const RawRequestContext> = const { Cell::new(std::ptr::null()) };
+}
+
+/// Run `f` with the context of the call this thread is inside of, if the
+/// host passed one. For plugin code; outside a `<method>_ctx` call it
+/// returns `None`.
+pub fn with_current_request<R>(f: impl FnOnce(&RawRequestContext) -> R) -> Option<R> {
+    let ctx = CURRENT.with(Cell::get);
+    if ctx.is_null() {
+        return None;
+    }
+    Some(f(unsafe { &*ctx }))
+}
+
+/// Make `ctx` the current request while `f` runs. Called by the generated
+/// `<method>_ctx` entries.
+#[doc(hidden)]
+pub fn enter_request<R>(ctx: *const RawRequestContext, f: impl FnOnce() -> R) -> R {
+    struct Restore(*const RawRequestContext);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            CURRENT.with(|c| c.set(self.0));
+        }
+    }
+    let _restore = Restore(CURRENT.with(|c| c.replace(ctx)));
+    f()
+}
@@ -0,0 +1,113 @@
+//! Retrying watcher loads that failed, for files that were locked or only
+//! half written when the watcher got to them.
+
+use crate::manager::WatchOptions;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+struct Retry {
+    /// Watched directory the file belongs to.
+    dir: PathBuf,
+    /// Failed loads so far.
+    failures: u32,
+    due: Instant,
+}
+
+/// Files whose load failed, with when to try each again.
+#[derive(Default)]
+pub(crate) struct LoadRetries {
+    files: HashMap<PathBuf, Retry>,
+}
+
+impl LoadRetries {
+    /// Note that loading `path`, watched under `dir`, failed at `now`, and
+    /// schedule the next attempt `retry_delay_ms` later, doubling with
+    /// every failure up to `max_retry_delay_ms`. Returns the failures so
+    /// far once `load_retries` retries are used up; the file is forgotten
+    /// then.
+    pub(crate) fn failed(
+        &mut self,
+        dir: &Path,
+        path: PathBuf,
+        opts: &WatchOptions,
+        now: Instant,
+    ) -> Option<u32> {
+        let failures = self.files.get(&path).map_or(0, |r| r.failures) + 1;
+        if failures > opts.load_retries {
+            self.files.remove(&path);
+            return Some(failures);
+        }
+        let delay = Duration::from_millis(opts.retry_delay_ms)
+            .saturating_mul(1 << (failures - 1).min(31))
+            .min(Duration::from_millis(opts.max_retry_delay_ms));
+        self.files.insert(
+            path,
+            Retry {
+                dir: dir.to_path_buf(),
+                failures,
+                due: now + delay,
+            },
+        );
+        None
+    }
+
+    /// Stop retrying `path`, which loaded or went away.
+    pub(crate) fn forget(&mut self, path: &Path) {
+        self.files.remove(path);
+    }
+
+    /// Files under `dir` due for another attempt at `now`.
+    pub(crate) fn due(&self, dir: &Path, now: Instant) -> Vec<PathBuf> {
+        let mut due: Vec<PathBuf> = self
+            .files
+            .iter()
+            .filter(|(_, r)| r.dir == dir && r.due <= now)
+            .map(|(path, _)| path.clone())
+            .collect();
+        due.sort();
+        due
+    }
+
+    /// When the next attempt is due, if any file is waiting for one.
+    pub(crate) fn next_due(&self) -> Option<Instant> {
+        self.files.values().map(|r| r.due).min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delays_double_up_to_the_cap_and_give_up() {
+        let opts = WatchOptions {
+            load_retries: 3,
+            retry_delay_ms: 100,
+            max_retry_delay_ms: 150,
+            ..WatchOptions::default()
+        };
+        let (dir, path) = (Path::new("/plugins"), PathBuf::from("/plugins/liba.so"));
+        let mut retries = LoadRetries::default();
+        let start = Instant::now();
+
+        assert_eq!(retries.failed(dir, path.clone(), &opts, start), None);
+        assert_eq!(retries.next_due(), Some(start + Duration::from_millis(100)));
+        assert!(retries.due(dir, start).is_empty());
+        let due = retries.due(dir, start + Duration::from_millis(100));
+        assert_eq!(due, vec![path.clone()]);
+        assert!(retries
+            .due(Path::new("/other"), start + Duration::from_secs(1))
+            .is_empty());
+
+        assert_eq!(retries.failed(dir, path.clone(), &opts, start), None);
+        assert_eq!(retries.next_due(), Some(start + Duration::from_millis(150)));
+        assert_eq!(retries.failed(dir, path.clone(), &opts, start), None);
+        assert_eq!(retries.failed(dir, path.clone(), &opts, start), Some(4));
+        assert_eq!(retries.next_due(), None);
+
+        retries.failed(dir, path.clone(), &opts, start);
+        retries.forget(&path);
+        assert_eq!(retries.next_due(), None);
+    }
+}
@@ -0,0 +1,338 @@
+//! Ordered plugin directories and how copies of the same plugin found in
+//! several of them are resolved.
+
+use crate::manifest::{Candidate, PluginManifest};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Environment variable read by `PluginSearchPath::with_env`, in the
+/// platform's `PATH` syntax.
+pub const PLUGIN_PATH_ENV: &str = "PLUGIN_PATH";
+
+/// Which copy wins when the same plugin is found in several directories.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SearchPrecedence {
+    /// The copy in the earliest directory of the search path.
+    #[default]
+    FirstDirectory,
+    /// The copy whose manifest has the highest semver version; ties and
+    /// copies without a parsable version fall back to directory order.
+    HighestVersion,
+}
+
+/// Ordered list of directories `PluginManager::load_plugins` searches.
+///
+/// A plugin is identified by its manifest name, or by its file name when it
+/// has no manifest. Directories that do not exist are skipped.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PluginSearchPath {
+    dirs: Vec<PathBuf>,
+    precedence: SearchPrecedence,
+}
+
+impl PluginSearchPath {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A search path made of the directories listed in `PLUGIN_PATH`.
+    pub fn from_env() -> Self {
+        Self::new().with_env()
+    }
+
+    /// Append `dir`; directories added earlier take precedence.
+    pub fn with_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dirs.push(dir.into());
+        self
+    }
+
+    /// Append the directories listed in `PLUGIN_PATH`, if it is set.
+    pub fn with_env(mut self) -> Self {
+        if let Some(value) = std::env::var_os(PLUGIN_PATH_ENV) {
+            self.dirs
+                .extend(std::env::split_paths(&value).filter(|p| !p.as_os_str().is_empty()));
+        }
+        self
+    }
+
+    pub fn with_precedence(mut self, precedence: SearchPrecedence) -> Self {
+        self.precedence = precedence;
+        self
+    }
+
+    pub fn dirs(&self) -> &[PathBuf] {
+        &self.dirs
+    }
+
+    pub fn precedence(&self) -> SearchPrecedence {
+        self.precedence
+    }
+}
+
+impl From<&Path> for PluginSearchPath {
+    fn from(dir: &Path) -> Self {
+        Self::new().with_dir(dir)
+    }
+}
+
+impl From<&PathBuf> for PluginSearchPath {
+    fn from(dir: &PathBuf) -> Self {
+        Self::new().with_dir(dir)
+    }
+}
+
+impl From<PathBuf> for PluginSearchPath {
+    fn from(dir: PathBuf) -> Self {
+        Self::new().with_dir(dir)
+    }
+}
+
+impl From<Vec<PathBuf>> for PluginSearchPath {
+    fn from(dirs: Vec<PathBuf>) -> Self {
+        Self {
+            dirs,
+            ..Self::default()
+        }
+    }
+}
+
+/// A copy of a plugin that was not loaded because another copy of the same
+/// plugin won.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShadowedPlugin {
+    /// Manifest name, or file name for plugins without a manifest.
+    pub name: String,
+    pub path: PathBuf,
+    /// The copy that was loaded (or was already loaded) instead.
+    pub shadowed_by: PathBuf,
+}
+
+/// Name a candidate is deduplicated by.
+pub(crate) fn plugin_name(path: &Path, manifest_name: Option<&str>) -> String {
+    match manifest_name {
+        Some(name) => name.to_string(),
+        None => path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    }
+}
+
+/// Key a candidate is deduplicated by: its name, or with `versioned` (see
+/// `PluginManager::set_multi_version`) `name@version` for plugins with a
+/// manifest, so different versions of a plugin do not shadow each other.
+pub(crate) fn plugin_key(
+    path: &Path,
+    manifest: Option<&PluginManifest>,
+    versioned: bool,
+) -> String {
+    let name = plugin_name(path, manifest.map(|m| m.name.as_str()));
+    match manifest {
+        Some(m) if versioned => format!("{}@{}", name, m.version),
+        _ => name,
+    }
+}
+
+/// The version part of a `name@version` lookup.
+pub(crate) enum VersionFilter {
+    /// `@1.2.0`: exactly that version, as `PluginHandle::versioned_name`
+    /// spells it.
+    Exact(semver::Version),
+    /// Any other semver requirement, such as `@^1` or `@>=2, <3`.
+    Req(semver::VersionReq),
+}
+
+impl VersionFilter {
+    fn matches(&self, version: &semver::Version) -> bool {
+        match self {
+            VersionFilter::Exact(v) => v == version,
+            VersionFilter::Req(req) => req.matches(version),
+        }
+    }
+}
+
+/// Split `lookup` into a name and the version it asks for, or `None` if it
+/// has no `@` followed by a version or requirement.
+pub(crate) fn split_versioned(lookup: &str) -> Option<(&str, VersionFilter)> {
+    let (name, version) = lookup.rsplit_once('@')?;
+    let filter = match semver::Version::parse(version) {
+        Ok(v) => VersionFilter::Exact(v),
+        Err(_) => VersionFilter::Req(semver::VersionReq::parse(version).ok()?),
+    };
+    Some((name, filter))
+}
+
+/// The item of `items` with the highest version `filter` accepts; the
+/// first one on ties. Items without a parsable version never match.
+pub(crate) fn newest_matching<T>(
+    items: impl IntoIterator<Item = T>,
+    version: impl Fn(&T) -> Option<&str>,
+    filter: &VersionFilter,
+) -> Option<T> {
+    let mut best: Option<(semver::Version, T)> = None;
+    for item in items {
+        let Some(v) = version(&item).and_then(|v| semver::Version::parse(v).ok()) else {
+            continue;
+        };
+        if filter.matches(&v) && best.as_ref().is_none_or(|(b, _)| v > *b) {
+            best = Some((v, item));
+        }
+    }
+    best.map(|(_, item)| item)
+}
+
+fn version_of(candidate: &Candidate) -> Option<semver::Version> {
+    let m = candidate.manifest.as_ref()?;
+    semver::Version::parse(&m.version).ok()
+}
+
+/// Keep one candidate per `plugin_key`. `candidates` must be in search-path
+/// order; `loaded` maps keys of already-loaded plugins to their path, and
+/// those always win so a plugin is never loaded twice.
+pub(crate) fn select(
+    candidates: Vec<Candidate>,
+    loaded: &HashMap<String, PathBuf>,
+    precedence: SearchPrecedence,
+    versioned: bool,
+) -> (Vec<Candidate>, Vec<ShadowedPlugin>) {
+    let mut chosen: Vec<(String, Candidate)> = Vec::new();
+    let mut shadowed = Vec::new();
+    for candidate in candidates {
+        let name = plugin_key(&candidate.path, candidate.manifest.as_ref(), versioned);
+        if let Some(path) = loaded.get(&name) {
+            shadowed.push(ShadowedPlugin {
+                name,
+                path: candidate.path,
+                shadowed_by: path.clone(),
+            });
+            continue;
+        }
+        let Some(pos) = chosen.iter().position(|(n, _)| *n == name) else {
+            chosen.push((name, candidate));
+            continue;
+        };
+        let replaces = precedence == SearchPrecedence::HighestVersion
+            && version_of(&candidate) > version_of(&chosen[pos].1);
+        let (loser, winner) = if replaces {
+            let previous = std::mem::replace(&mut chosen[pos].1, candidate);
+            (previous.path, chosen[pos].1.path.clone())
+        } else {
+            (candidate.path, chosen[pos].1.path.clone())
+        };
+        // Copies shadowed by a copy that has now been replaced point at the
+        // new winner.
+        for s in shadowed.iter_mut().filter(|s| s.name == name) {
+            s.shadowed_by = winner.clone();
+        }
+        shadowed.push(ShadowedPlugin {
+            name,
+            path: loser,
+            shadowed_by: winner,
+        });
+    }
+    (chosen.into_iter().map(|(_, c)| c).collect(), shadowed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PluginManifest;
+
+    fn candidate(path: &str, version: Option<&str>) -> Candidate {
+        Candidate {
+            path: PathBuf::from(path),
+            manifest: version.map(|v| {
+                PluginManifest::parse(&format!(
+                    "name = \"foo\"\nversion = \"{}\"\ntraits = [\"Greeter\"]",
+                    v
+                ))
+                .unwrap()
+            }),
+        }
+    }
+
+    #[test]
+    fn first_directory_wins_by_default() {
+        let (kept, shadowed) = select(
+            vec![
+                candidate("/a/libfoo.so", Some("1.0.0")),
+                candidate("/b/libfoo.so", Some("2.0.0")),
+            ],
+            &HashMap::new(),
+            SearchPrecedence::FirstDirectory,
+            false,
+        );
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].path, Path::new("/a/libfoo.so"));
+        assert_eq!(shadowed[0].path, Path::new("/b/libfoo.so"));
+        assert_eq!(shadowed[0].shadowed_by, Path::new("/a/libfoo.so"));
+    }
+
+    #[test]
+    fn highest_version_wins_when_requested() {
+        let (kept, shadowed) = select(
+            vec![
+                candidate("/a/libfoo.so", Some("1.0.0")),
+                candidate("/b/libfoo.so", Some("2.0.0")),
+                candidate("/c/libfoo.so", Some("1.5.0")),
+            ],
+            &HashMap::new(),
+            SearchPrecedence::HighestVersion,
+            false,
+        );
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].path, Path::new("/b/libfoo.so"));
+        assert_eq!(shadowed.len(), 2);
+        assert!(shadowed
+            .iter()
+            .all(|s| s.shadowed_by == Path::new("/b/libfoo.so")));
+    }
+
+    #[test]
+    fn loaded_plugins_shadow_new_copies() {
+        let loaded = HashMap::from([("foo".to_string(), PathBuf::from("/old/libfoo.so"))]);
+        let (kept, shadowed) = select(
+            vec![candidate("/a/libfoo.so", Some("9.0.0"))],
+            &loaded,
+            SearchPrecedence::HighestVersion,
+            false,
+        );
+        assert!(kept.is_empty());
+        assert_eq!(shadowed[0].shadowed_by, Path::new("/old/libfoo.so"));
+    }
+
+    #[test]
+    fn versions_coexist_when_keyed_by_version() {
+        let loaded = HashMap::from([("foo@1.0.0".to_string(), PathBuf::from("/old/libfoo.so"))]);
+        let (kept, shadowed) = select(
+            vec![
+                candidate("/a/libfoo.so", Some("1.0.0")),
+                candidate("/b/libfoo.so", Some("2.0.0")),
+                candidate("/c/libfoo.so", Some("2.0.0")),
+            ],
+            &loaded,
+            SearchPrecedence::FirstDirectory,
+            true,
+        );
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].path, Path::new("/b/libfoo.so"));
+        assert_eq!(shadowed[0].name, "foo@1.0.0");
+        assert_eq!(shadowed[1].shadowed_by, Path::new("/b/libfoo.so"));
+    }
+
+    #[test]
+    fn versioned_lookups_pick_the_newest_match() {
+        let versions = ["1.2.0", "2.0.0", "1.4.1", "junk"];
+        let newest = |lookup: &str| {
+            let (name, filter) = split_versioned(lookup)?;
+            assert_eq!(name, "foo");
+            newest_matching(versions, |v| Some(*v), &filter)
+        };
+        assert_eq!(newest("foo@^1"), Some("1.4.1"));
+        assert_eq!(newest("foo@1.2.0"), Some("1.2.0"));
+        assert_eq!(newest("foo@>=1.3"), Some("2.0.0"));
+        assert_eq!(newest("foo@3"), None);
+        assert!(split_versioned("foo").is_none());
+        assert!(split_versioned("foo@bar").is_none());
+    }
+}
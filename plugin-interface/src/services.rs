@@ -0,0 +1,142 @@
+//! Plugin-to-plugin composition: every registration a `PluginManager`
+//! loads is offered as a service, which other plugins look up by trait
+//! and name through `HostContext::get_service`.
+
+use crate::handle::{registration_services, LoadedLib};
+use std::ffi::c_void;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+
+/// A registration other plugins can look up with `HostContext::get_service`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceProvider {
+    /// Trait the registration implements, e.g. `"Transformer"`.
+    pub trait_name: String,
+    /// The registration's name.
+    pub name: String,
+    /// Name from the library's sidecar manifest, if it has one.
+    pub plugin: Option<String>,
+    /// Library the registration lives in.
+    pub path: PathBuf,
+}
+
+/// The services a `PluginManager`'s plugins provide, and which plugins use
+/// which. A plugin that looked up another's service keeps the provider
+/// loaded: the manager refuses to unload the provider before it, and
+/// closing the provider's handles only defers its unload.
+#[derive(Debug, Default)]
+pub struct ServiceRegistry {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    providers: Vec<Entry>,
+    /// The library that looked a service up, and the provider's library.
+    uses: Vec<(PathBuf, Arc<LoadedLib>)>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    provider: ServiceProvider,
+    lib: Weak<LoadedLib>,
+    index: usize,
+}
+
+// The libraries are only read under the mutex, to find registrations;
+// handles share them across threads the same way.
+unsafe impl Send for ServiceRegistry {}
+unsafe impl Sync for ServiceRegistry {}
+
+impl ServiceRegistry {
+    /// The registrations that can currently be looked up.
+    pub fn providers(&self) -> Vec<ServiceProvider> {
+        self.lock()
+            .providers
+            .iter()
+            .filter(|e| e.lib.upgrade().is_some_and(|l| is_available(&l)))
+            .map(|e| e.provider.clone())
+            .collect()
+    }
+
+    /// Libraries that looked up a service of the library at `path`, in the
+    /// order they first did.
+    pub fn users_of(&self, path: &Path) -> Vec<PathBuf> {
+        let mut users: Vec<PathBuf> = Vec::new();
+        for (user, lib) in &self.lock().uses {
+            if lib.path == path && !users.contains(user) {
+                users.push(user.clone());
+            }
+        }
+        users
+    }
+
+    /// Offer every registration of the freshly loaded `lib`.
+    pub(crate) fn add_library(&self, lib: &Arc<LoadedLib>) {
+        let plugin = lib.manifest.as_ref().map(|m| m.name.clone());
+        let mut inner = self.lock();
+        inner.providers.retain(|e| e.lib.strong_count() > 0);
+        for (index, name) in registration_services(lib).into_iter().enumerate() {
+            inner.providers.push(Entry {
+                provider: ServiceProvider {
+                    trait_name: lib.trait_id.as_str().to_string(),
+                    name,
+                    plugin: plugin.clone(),
+                    path: lib.path.clone(),
+                },
+                lib: Arc::downgrade(lib),
+                index,
+            });
+        }
+    }
+
+    /// The vtable of the first available `trait_name` registration whose
+    /// name or plugin name is `name` (any, for `None`), remembering that
+    /// `user` looked it up. Null when there is none.
+    pub(crate) fn lookup(
+        &self,
+        user: Option<&Path>,
+        trait_name: &str,
+        name: Option<&str>,
+    ) -> *const c_void {
+        let mut inner = self.lock();
+        let found = inner.providers.iter().find_map(|e| {
+            let p = &e.provider;
+            if p.trait_name != trait_name
+                || name.is_some_and(|n| p.name != n && p.plugin.as_deref() != Some(n))
+            {
+                return None;
+            }
+            let lib = e.lib.upgrade().filter(|l| is_available(l))?;
+            Some((lib, e.index))
+        });
+        let Some((lib, index)) = found else {
+            return std::ptr::null();
+        };
+        let vtable = crate::handle::registration_vtable(&lib, index);
+        if let Some(user) = user.filter(|u| *u != lib.path) {
+            if !inner
+                .uses
+                .iter()
+                .any(|(u, l)| u == user && Arc::ptr_eq(l, &lib))
+            {
+                inner.uses.push((user.to_path_buf(), lib));
+            }
+        }
+        vtable
+    }
+
+    /// Forget the uses of libraries `is_loaded` says are gone, letting go
+    /// of their providers.
+    pub(crate) fn retain_users(&self, is_loaded: impl Fn(&Path) -> bool) {
+        self.lock().uses.retain(|(user, _)| is_loaded(user));
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn is_available(lib: &LoadedLib) -> bool {
+    !lib.closed.load(std::sync::atomic::Ordering::SeqCst) && !lib.is_unloaded()
+}
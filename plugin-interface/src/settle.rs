@@ -0,0 +1,149 @@
+//! Waiting for watched files to settle before the watcher reports them, so
+//! an artifact that is still being written is not loaded half-copied.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Size and modification time of a file, compared between polls.
+type Snapshot = (u64, Option<SystemTime>);
+
+struct Pending {
+    last_event: Instant,
+    snapshot: Option<Snapshot>,
+    unchanged_since: Instant,
+}
+
+/// Paths with filesystem events that are not yet ready to report. A path is
+/// ready once no event arrived for the debounce window and, when a
+/// stability window is set, its size and mtime then stayed the same for
+/// that long.
+pub(crate) struct PendingFiles {
+    debounce: Duration,
+    stable: Duration,
+    paths: HashMap<PathBuf, Pending>,
+}
+
+impl PendingFiles {
+    pub(crate) fn new(debounce_ms: u64, stable_ms: u64) -> Self {
+        Self {
+            debounce: Duration::from_millis(debounce_ms),
+            stable: Duration::from_millis(stable_ms),
+            paths: HashMap::new(),
+        }
+    }
+
+    /// Record an event for `path`, restarting its windows.
+    pub(crate) fn touch(&mut self, path: PathBuf, now: Instant) {
+        self.paths.insert(
+            path,
+            Pending {
+                last_event: now,
+                snapshot: None,
+                unchanged_since: now,
+            },
+        );
+    }
+
+    /// Stop waiting for `path`, which was removed.
+    pub(crate) fn forget(&mut self, path: &Path) {
+        self.paths.remove(path);
+    }
+
+    /// Take out the paths that are ready at `now`. Paths that disappeared
+    /// while waiting are dropped.
+    pub(crate) fn ready(&mut self, now: Instant) -> Vec<PathBuf> {
+        let mut ready = Vec::new();
+        let (debounce, stable) = (self.debounce, self.stable);
+        self.paths.retain(|path, pending| {
+            if now.duration_since(pending.last_event) < debounce {
+                return true;
+            }
+            if stable.is_zero() {
+                ready.push(path.clone());
+                return false;
+            }
+            let Some(snapshot) = snapshot(path) else {
+                return false;
+            };
+            if pending.snapshot != Some(snapshot) || open_elsewhere(path) {
+                pending.snapshot = Some(snapshot);
+                pending.unchanged_since = now;
+                return true;
+            }
+            if now.duration_since(pending.unchanged_since) < stable {
+                return true;
+            }
+            ready.push(path.clone());
+            false
+        });
+        ready
+    }
+}
+
+fn snapshot(path: &Path) -> Option<Snapshot> {
+    let meta = std::fs::metadata(path).ok()?;
+    Some((meta.len(), meta.modified().ok()))
+}
+
+/// Whether another process still has `path` open, probed by asking for
+/// exclusive access. Only Windows can answer this; elsewhere the size and
+/// mtime checks have to do.
+#[cfg(windows)]
+fn open_elsewhere(path: &Path) -> bool {
+    use std::os::windows::fs::OpenOptionsExt;
+    std::fs::OpenOptions::new()
+        .read(true)
+        .share_mode(0)
+        .open(path)
+        .is_err()
+}
+
+#[cfg(not(windows))]
+fn open_elsewhere(_path: &Path) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waits_until_the_file_stops_changing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("libgrowing.so");
+        std::fs::write(&path, b"part").unwrap();
+
+        let mut pending = PendingFiles::new(10, 50);
+        let start = Instant::now();
+        pending.touch(path.clone(), start);
+        assert!(pending.ready(start).is_empty(), "still debouncing");
+
+        // The first poll after the debounce only takes a snapshot.
+        let t = start + Duration::from_millis(20);
+        assert!(pending.ready(t).is_empty());
+        // A write without a new event still restarts the stability window.
+        std::fs::write(&path, b"part and the rest").unwrap();
+        assert!(pending.ready(t + Duration::from_millis(60)).is_empty());
+        assert!(pending.ready(t + Duration::from_millis(90)).is_empty());
+        assert_eq!(pending.ready(t + Duration::from_millis(120)), [path]);
+        assert!(pending.ready(t + Duration::from_millis(200)).is_empty());
+    }
+
+    #[test]
+    fn zero_stability_reports_after_the_debounce_and_drops_vanished_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("libplugin.so");
+        let gone = dir.path().join("libgone.so");
+
+        let mut pending = PendingFiles::new(10, 0);
+        let start = Instant::now();
+        pending.touch(path.clone(), start);
+        assert_eq!(pending.ready(start + Duration::from_millis(10)), [path]);
+
+        let mut pending = PendingFiles::new(10, 50);
+        pending.touch(gone, start);
+        assert!(pending.ready(start + Duration::from_millis(10)).is_empty());
+        assert!(pending.paths.is_empty());
+    }
+}
@@ -0,0 +1,71 @@
+//! Private copies of plugin artifacts, opened instead of the artifact itself
+//! when the loader could otherwise hand back an image that is still mapped.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+/// A copy of an artifact in the temporary directory, removed on drop. Keep it
+/// alive until the library opened from it has been closed.
+#[derive(Debug)]
+pub(crate) struct ShadowCopy {
+    path: PathBuf,
+}
+
+impl ShadowCopy {
+    /// Copy `original` to a name no other load in this process uses. The
+    /// file name keeps the original extension so the loader accepts it.
+    pub(crate) fn create(original: &Path) -> io::Result<Self> {
+        let stem = original
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut name = format!(
+            "{}-shadow-{}-{}",
+            stem,
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        );
+        if let Some(ext) = original.extension() {
+            name.push('.');
+            name.push_str(&ext.to_string_lossy());
+        }
+        let path = std::env::temp_dir().join(name);
+        std::fs::copy(original, &path)?;
+        Ok(Self { path })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ShadowCopy {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copies_are_unique_and_removed_on_drop() {
+        let dir = tempfile::tempdir().unwrap();
+        let original = dir.path().join("libfoo.so");
+        std::fs::write(&original, b"image").unwrap();
+
+        let a = ShadowCopy::create(&original).unwrap();
+        let b = ShadowCopy::create(&original).unwrap();
+        assert_ne!(a.path(), b.path());
+        assert_eq!(a.path().extension(), original.extension());
+        assert_eq!(std::fs::read(a.path()).unwrap(), b"image");
+
+        let copy = a.path().to_path_buf();
+        drop(a);
+        assert!(!copy.exists());
+    }
+}
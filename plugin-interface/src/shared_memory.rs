@@ -0,0 +1,340 @@
+//! Shared-memory payloads for the out-of-process transports. The host maps
+//! a file it creates, the runner or server maps the same file when asked
+//! to attach, and string arguments and results above a threshold are
+//! written there instead of into the message stream, which then only says
+//! where they are. Calls on a connection are made one at a time, so each
+//! argument is written at the start of the region and the peer writes the
+//! result right after it. Anything that does not fit, and everything on a
+//! connection that could not attach (a server on another machine, or a
+//! platform without `mmap`), is sent inline as before.
+
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::path::PathBuf;
+
+/// Bytes at the start of the region holding the token the peer checks, so
+/// it cannot attach to an unrelated file of the same name.
+const HEADER: usize = 8;
+
+/// How `PluginManager::set_shared_memory` passes large payloads to
+/// isolated and remote plugins.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedMemoryOptions {
+    /// Size of the region mapped for each runner or connection. An argument
+    /// and its result have to fit together; larger ones are sent inline.
+    pub capacity: usize,
+    /// Strings shorter than this are sent inline, where a copy costs less
+    /// than the bookkeeping.
+    pub threshold: usize,
+}
+
+impl Default for SharedMemoryOptions {
+    fn default() -> Self {
+        Self {
+            capacity: 64 << 20,
+            threshold: 64 << 10,
+        }
+    }
+}
+
+/// What the host sends to ask a peer to map its region.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Attach {
+    pub(crate) path: PathBuf,
+    pub(crate) capacity: usize,
+    pub(crate) threshold: usize,
+    pub(crate) token: u64,
+}
+
+/// A string argument or result: in the message, or in the region at
+/// `offset`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Payload {
+    Inline(String),
+    Shared { offset: usize, len: usize },
+}
+
+impl Payload {
+    /// `text`, written to `region` at or after `after` when there is a
+    /// region, `text` reaches its threshold and it fits.
+    pub(crate) fn new(text: &str, region: Option<&SharedRegion>, after: usize) -> Self {
+        let placed = region
+            .filter(|r| text.len() >= r.threshold)
+            .and_then(|r| Some((r, r.place(after, text.len())?)));
+        match placed {
+            Some((region, offset)) => {
+                region.write(offset, text.as_bytes());
+                Payload::Shared {
+                    offset,
+                    len: text.len(),
+                }
+            }
+            None => Payload::Inline(text.to_string()),
+        }
+    }
+
+    /// Where a result written after this payload can start.
+    #[cfg_attr(not(feature = "remote"), allow(dead_code))]
+    pub(crate) fn end(&self) -> usize {
+        match self {
+            Payload::Inline(_) => HEADER,
+            Payload::Shared { offset, len } => offset + len,
+        }
+    }
+
+    /// The text, borrowed from the message or the region. Only the peer
+    /// reads the region in place; the host copies results out with
+    /// `into_string`.
+    pub(crate) fn text<'a>(&'a self, region: Option<&'a SharedRegion>) -> Result<&'a str, String> {
+        match self {
+            Payload::Inline(text) => Ok(text),
+            Payload::Shared { offset, len } => {
+                let bytes = region
+                    .ok_or("payload in shared memory, but none is attached")?
+                    .bytes(*offset, *len)?;
+                std::str::from_utf8(bytes).map_err(|e| e.to_string())
+            }
+        }
+    }
+
+    /// The text, copied out of the region if it is there.
+    #[cfg_attr(not(feature = "remote"), allow(dead_code))]
+    pub(crate) fn into_string(self, region: Option<&SharedRegion>) -> Result<String, String> {
+        match self {
+            Payload::Inline(text) => Ok(text),
+            shared => shared.text(region).map(str::to_string),
+        }
+    }
+}
+
+/// A mapping of the shared file. The host's is the owner and removes the
+/// file when dropped.
+pub(crate) struct SharedRegion {
+    ptr: *mut u8,
+    len: usize,
+    threshold: usize,
+    path: PathBuf,
+    owner: bool,
+}
+
+// The mapping is plain memory. Each side only touches it while the other
+// waits for its message, so sharing the pointer between threads is no
+// different from sharing it between processes.
+unsafe impl Send for SharedRegion {}
+unsafe impl Sync for SharedRegion {}
+
+impl std::fmt::Debug for SharedRegion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedRegion")
+            .field("path", &self.path)
+            .field("len", &self.len)
+            .field("owner", &self.owner)
+            .finish()
+    }
+}
+
+impl SharedRegion {
+    /// Create and map a fresh file for `opts`, and the `Attach` message
+    /// that lets a peer map it too.
+    pub(crate) fn create(opts: &SharedMemoryOptions) -> Result<(Self, Attach), String> {
+        let token = RandomState::new().build_hasher().finish();
+        let path =
+            std::env::temp_dir().join(format!("plugin-shm-{}-{:016x}", std::process::id(), token));
+        let len = opts
+            .capacity
+            .checked_add(HEADER)
+            .ok_or("capacity too large")?;
+        let ptr = map(&path, len, true)?;
+        let region = Self {
+            ptr,
+            len,
+            threshold: opts.threshold,
+            path: path.clone(),
+            owner: true,
+        };
+        region.write(0, &token.to_le_bytes());
+        let attach = Attach {
+            path,
+            capacity: opts.capacity,
+            threshold: opts.threshold,
+            token,
+        };
+        Ok((region, attach))
+    }
+
+    /// Map the file a host created, refusing it unless it starts with the
+    /// host's token.
+    pub(crate) fn attach(attach: &Attach) -> Result<Self, String> {
+        let len = attach
+            .capacity
+            .checked_add(HEADER)
+            .ok_or("capacity too large")?;
+        let region = Self {
+            ptr: map(&attach.path, len, false)?,
+            len,
+            threshold: attach.threshold,
+            path: attach.path.clone(),
+            owner: false,
+        };
+        if region.bytes(0, HEADER)? != attach.token.to_le_bytes() {
+            return Err(format!(
+                "{} is not the host's region",
+                attach.path.display()
+            ));
+        }
+        Ok(region)
+    }
+
+    /// Where `len` bytes go: at `after` if they fit there, else at the
+    /// start of the data area.
+    fn place(&self, after: usize, len: usize) -> Option<usize> {
+        let start = after.max(HEADER);
+        if start.checked_add(len)? <= self.len {
+            Some(start)
+        } else if HEADER.checked_add(len)? <= self.len {
+            Some(HEADER)
+        } else {
+            None
+        }
+    }
+
+    fn bytes(&self, offset: usize, len: usize) -> Result<&[u8], String> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.len => {
+                Ok(unsafe { std::slice::from_raw_parts(self.ptr.add(offset), len) })
+            }
+            _ => Err(format!(
+                "payload at {}+{} is outside the shared region",
+                offset, len
+            )),
+        }
+    }
+
+    fn write(&self, offset: usize, bytes: &[u8]) {
+        assert!(offset + bytes.len() <= self.len, "write outside the region");
+        unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), self.ptr.add(offset), bytes.len()) }
+    }
+}
+
+impl Drop for SharedRegion {
+    fn drop(&mut self) {
+        unmap(self.ptr, self.len);
+        if self.owner {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn map(path: &std::path::Path, len: usize, create: bool) -> Result<*mut u8, String> {
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::os::unix::io::AsRawFd;
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(create)
+        .mode(0o600)
+        .open(path)
+        .map_err(|e| format!("cannot open {}: {}", path.display(), e))?;
+    if create {
+        file.set_len(len as u64).map_err(|e| e.to_string())?;
+    } else if file.metadata().map_err(|e| e.to_string())?.len() < len as u64 {
+        return Err(format!("{} is smaller than the region", path.display()));
+    }
+    let ptr = unsafe {
+        libc::mmap(
+            std::ptr::null_mut(),
+            len,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_SHARED,
+            file.as_raw_fd(),
+            0,
+        )
+    };
+    if ptr == libc::MAP_FAILED {
+        if create {
+            let _ = std::fs::remove_file(path);
+        }
+        return Err(format!(
+            "cannot map {}: {}",
+            path.display(),
+            std::io::Error::last_os_error()
+        ));
+    }
+    Ok(ptr.cast())
+}
+
+#[cfg(unix)]
+fn unmap(ptr: *mut u8, len: usize) {
+    unsafe {
+        libc::munmap(ptr.cast(), len);
+    }
+}
+
+#[cfg(not(unix))]
+fn map(_path: &std::path::Path, _len: usize, _create: bool) -> Result<*mut u8, String> {
+    Err("shared memory is only supported on unix".to_string())
+}
+
+#[cfg(not(unix))]
+fn unmap(_ptr: *mut u8, _len: usize) {}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payloads_pass_through_the_region_or_inline() {
+        let opts = SharedMemoryOptions {
+            capacity: 64,
+            threshold: 4,
+        };
+        let (host, attach) = SharedRegion::create(&opts).unwrap();
+        let peer = SharedRegion::attach(&attach).unwrap();
+
+        let arg = Payload::new("argument", Some(&host), 0);
+        assert!(matches!(
+            arg,
+            Payload::Shared {
+                offset: HEADER,
+                len: 8
+            }
+        ));
+        assert_eq!(arg.text(Some(&peer)), Ok("argument"));
+        let result = Payload::new("RESULT", Some(&peer), arg.end());
+        assert!(matches!(result, Payload::Shared { offset: 16, .. }));
+        assert_eq!(result.into_string(Some(&host)).as_deref(), Ok("RESULT"));
+
+        // Below the threshold, too large, or with no region: inline.
+        assert!(matches!(
+            Payload::new("abc", Some(&host), 0),
+            Payload::Inline(_)
+        ));
+        let large = "x".repeat(65);
+        assert!(matches!(
+            Payload::new(&large, Some(&host), 0),
+            Payload::Inline(_)
+        ));
+        assert!(matches!(Payload::new(&large, None, 0), Payload::Inline(_)));
+        // A result that does not fit after the argument starts over.
+        let wrapped = Payload::new(&"y".repeat(60), Some(&peer), arg.end());
+        assert!(matches!(wrapped, Payload::Shared { offset: HEADER, .. }));
+
+        let outside = Payload::Shared {
+            offset: 70,
+            len: 10,
+        };
+        assert!(outside.text(Some(&peer)).is_err());
+        assert!(outside.text(None).is_err());
+
+        let forged = Attach {
+            token: attach.token ^ 1,
+            ..attach.clone()
+        };
+        assert!(SharedRegion::attach(&forged).is_err());
+        drop((peer, host));
+        assert!(!attach.path.exists());
+    }
+}
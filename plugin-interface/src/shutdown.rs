@@ -0,0 +1,174 @@
+//! Graceful shutdown: a flag SIGINT and SIGTERM (ctrl-c, ctrl-break and
+//! closing the console on Windows) raise, which
+//! `PluginManager::run_until_shutdown` waits for before unloading
+//! everything in order.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Set by the handlers `ShutdownSignal::listen` puts in place.
+static SIGNALLED: AtomicBool = AtomicBool::new(false);
+
+/// How often `wait` looks at the flag; signal handlers cannot wake a
+/// waiting thread directly.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A request to shut down, from the program through `trigger` or, once it
+/// `listen`s, from the operating system. Clones share the request.
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownSignal {
+    state: Arc<SignalState>,
+}
+
+#[derive(Debug, Default)]
+struct SignalState {
+    requested: AtomicBool,
+    /// Whether the process's signals count as a request too.
+    signals: AtomicBool,
+}
+
+impl ShutdownSignal {
+    /// A signal only `trigger` raises until it `listen`s.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A signal that `listen`s from the start.
+    pub fn install() -> std::io::Result<Self> {
+        let signal = Self::new();
+        signal.listen()?;
+        Ok(signal)
+    }
+
+    /// Count SIGINT and SIGTERM, or on Windows ctrl-c, ctrl-break and
+    /// closing the console, as a request to shut down, including one that
+    /// arrived already. The handlers are put in place on first use and
+    /// stay for the rest of the process; a second signal after the first
+    /// ends the process the usual way, so a shutdown that hangs can still
+    /// be interrupted.
+    pub fn listen(&self) -> std::io::Result<()> {
+        static INSTALLED: OnceLock<Result<(), std::io::ErrorKind>> = OnceLock::new();
+        INSTALLED
+            .get_or_init(|| imp::install().map_err(|e| e.kind()))
+            .map_err(std::io::Error::from)?;
+        self.state.signals.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Request shutdown from the program itself.
+    pub fn trigger(&self) {
+        self.state.requested.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.state.requested.load(Ordering::SeqCst)
+            || (self.state.signals.load(Ordering::SeqCst) && SIGNALLED.load(Ordering::SeqCst))
+    }
+
+    /// Block until shutdown is requested.
+    pub fn wait(&self) {
+        while !self.is_requested() {
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Block until shutdown is requested or `timeout` passes; true if it
+    /// was requested.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if self.is_requested() {
+                return true;
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return false;
+            }
+            std::thread::sleep(left.min(POLL_INTERVAL));
+        }
+    }
+}
+
+#[cfg(unix)]
+mod imp {
+    use super::SIGNALLED;
+    use std::sync::atomic::Ordering;
+
+    extern "C" fn on_signal(signal: libc::c_int) {
+        if SIGNALLED.swap(true, Ordering::SeqCst) {
+            // Asked twice: stop waiting for the graceful path. Both calls
+            // are async-signal-safe.
+            unsafe {
+                libc::signal(signal, libc::SIG_DFL);
+                libc::raise(signal);
+            }
+        }
+    }
+
+    pub(super) fn install() -> std::io::Result<()> {
+        for signal in [libc::SIGINT, libc::SIGTERM] {
+            let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            if unsafe { libc::signal(signal, handler) } == libc::SIG_ERR {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use super::SIGNALLED;
+    use std::sync::atomic::Ordering;
+    use windows_sys::Win32::Foundation::{BOOL, FALSE, TRUE};
+    use windows_sys::Win32::System::Console::{
+        SetConsoleCtrlHandler, CTRL_BREAK_EVENT, CTRL_CLOSE_EVENT, CTRL_C_EVENT,
+    };
+
+    unsafe extern "system" fn on_ctrl(ctrl_type: u32) -> BOOL {
+        match ctrl_type {
+            // Asked twice: let the default handler end the process.
+            CTRL_C_EVENT | CTRL_BREAK_EVENT | CTRL_CLOSE_EVENT => {
+                if SIGNALLED.swap(true, Ordering::SeqCst) {
+                    FALSE
+                } else {
+                    TRUE
+                }
+            }
+            _ => FALSE,
+        }
+    }
+
+    pub(super) fn install() -> std::io::Result<()> {
+        if unsafe { SetConsoleCtrlHandler(Some(on_ctrl), TRUE) } == 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod imp {
+    pub(super) fn install() -> std::io::Result<()> {
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn triggering_wakes_waiters() {
+        let signal = ShutdownSignal::new();
+        assert!(!signal.wait_timeout(Duration::from_millis(10)));
+        let clone = signal.clone();
+        let waiter = std::thread::spawn(move || clone.wait());
+        signal.trigger();
+        waiter.join().unwrap();
+        assert!(signal.is_requested());
+        // Until it listens, the process's signals do not count.
+        assert!(!ShutdownSignal::new().is_requested());
+    }
+}
@@ -0,0 +1,106 @@
+//! Detached ed25519 signatures for plugin artifacts.
+//!
+//! A signed artifact ships next to a `<file>.sig` file holding the raw
+//! 64-byte ed25519 signature over the artifact's bytes. Once a manager
+//! trusts at least one public key, every artifact must carry a signature
+//! from one of them.
+
+use ed25519_dalek::{Signature, VerifyingKey};
+use std::path::{Path, PathBuf};
+
+/// Extension appended to an artifact's file name to find its signature.
+pub const SIGNATURE_SUFFIX: &str = "sig";
+
+/// Path of the detached signature for the artifact at `lib_path`
+/// (`libfoo.so` -> `libfoo.so.sig`).
+pub fn signature_path(lib_path: &Path) -> PathBuf {
+    let mut name = lib_path.as_os_str().to_owned();
+    name.push(".");
+    name.push(SIGNATURE_SUFFIX);
+    PathBuf::from(name)
+}
+
+/// Why an artifact's signature was not accepted.
+#[derive(Debug)]
+pub enum SignatureError {
+    /// The artifact or its signature file could not be read.
+    Io(std::io::Error),
+    /// No signature file exists next to the artifact.
+    Missing,
+    /// The signature file is not a 64-byte ed25519 signature.
+    Malformed,
+    /// A key passed to `PluginManager::trust_key` is not a valid ed25519 public key.
+    InvalidKey,
+    /// The signature does not verify against any trusted key.
+    Untrusted,
+}
+
+impl std::fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignatureError::Io(e) => write!(f, "failed to read artifact or signature: {}", e),
+            SignatureError::Missing => write!(f, "artifact is not signed"),
+            SignatureError::Malformed => write!(f, "signature file is malformed"),
+            SignatureError::InvalidKey => write!(f, "invalid ed25519 public key"),
+            SignatureError::Untrusted => write!(f, "signature does not match any trusted key"),
+        }
+    }
+}
+
+impl std::error::Error for SignatureError {}
+
+/// The public keys a manager accepts artifact signatures from.
+#[derive(Debug, Default)]
+pub(crate) struct TrustedKeys {
+    keys: Vec<VerifyingKey>,
+}
+
+impl TrustedKeys {
+    pub(crate) fn add(&mut self, public_key: &[u8; 32]) -> Result<(), SignatureError> {
+        let key = VerifyingKey::from_bytes(public_key).map_err(|_| SignatureError::InvalidKey)?;
+        if !self.keys.contains(&key) {
+            self.keys.push(key);
+        }
+        Ok(())
+    }
+
+    /// Check the detached signature of the artifact at `path`. Always
+    /// succeeds while no key is trusted.
+    pub(crate) fn verify(&self, path: &Path) -> Result<(), SignatureError> {
+        if self.keys.is_empty() {
+            return Ok(());
+        }
+        let sig_bytes = match std::fs::read(signature_path(path)) {
+            Ok(b) => b,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(SignatureError::Missing)
+            }
+            Err(e) => return Err(SignatureError::Io(e)),
+        };
+        let artifact = std::fs::read(path).map_err(SignatureError::Io)?;
+        self.verify_bytes(&artifact, Some(&sig_bytes))
+    }
+
+    /// Check `signature` over the artifact bytes `artifact`, the way
+    /// `verify` checks a file and its signature file.
+    pub(crate) fn verify_bytes(
+        &self,
+        artifact: &[u8],
+        signature: Option<&[u8]>,
+    ) -> Result<(), SignatureError> {
+        if self.keys.is_empty() {
+            return Ok(());
+        }
+        let signature = signature.ok_or(SignatureError::Missing)?;
+        let signature = Signature::from_slice(signature).map_err(|_| SignatureError::Malformed)?;
+        if self
+            .keys
+            .iter()
+            .any(|k| k.verify_strict(artifact, &signature).is_ok())
+        {
+            Ok(())
+        } else {
+            Err(SignatureError::Untrusted)
+        }
+    }
+}
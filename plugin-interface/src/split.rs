@@ -0,0 +1,127 @@
+//! A/B routing between the loaded version of a registration and a staged
+//! one (`PluginManager::split_traffic`), so hosts can compare the two on
+//! real calls before `PluginManager::promote`.
+
+use crate::handle::PluginHandle;
+use crate::stats::PluginStats;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+/// Fractions are kept in millionths.
+const SCALE: u64 = 1_000_000;
+
+/// Routes calls for one registration name between the version loaded now
+/// and a staged candidate. Ask `route` for the handle to call each time;
+/// the given fraction of them is the candidate's, spread evenly rather
+/// than at random.
+///
+/// Once the current version goes stale, for example because the candidate
+/// was promoted, every call is routed to the candidate.
+#[derive(Debug)]
+pub struct TrafficSplit {
+    name: String,
+    current: PluginHandle,
+    candidate: PluginHandle,
+    fraction: AtomicU32,
+    routed: AtomicU64,
+    to_candidate: AtomicU64,
+}
+
+/// How a `TrafficSplit` routed its calls, per version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SplitStats {
+    pub current: VersionStats,
+    pub candidate: VersionStats,
+}
+
+/// One side of `SplitStats`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionStats {
+    /// Calls `TrafficSplit::route` sent to this version.
+    pub routed: u64,
+    /// Call statistics of the registration, counting calls made through
+    /// any proxy, not only the routed ones.
+    pub stats: PluginStats,
+}
+
+impl TrafficSplit {
+    pub(crate) fn new(
+        name: String,
+        current: PluginHandle,
+        candidate: PluginHandle,
+        fraction: f64,
+    ) -> Self {
+        Self {
+            name,
+            current,
+            candidate,
+            fraction: AtomicU32::new(to_millionths(fraction)),
+            routed: AtomicU64::new(0),
+            to_candidate: AtomicU64::new(0),
+        }
+    }
+
+    /// The registration name calls are routed for.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The version loaded when the split was made.
+    pub fn current(&self) -> &PluginHandle {
+        &self.current
+    }
+
+    /// The staged version.
+    pub fn candidate(&self) -> &PluginHandle {
+        &self.candidate
+    }
+
+    /// Share of calls routed to the candidate, between 0 and 1.
+    pub fn fraction(&self) -> f64 {
+        self.fraction.load(Ordering::Relaxed) as f64 / SCALE as f64
+    }
+
+    /// Route `fraction` of the calls from now on to the candidate; values
+    /// outside 0 to 1 are clamped.
+    pub fn set_fraction(&self, fraction: f64) {
+        self.fraction
+            .store(to_millionths(fraction), Ordering::Relaxed);
+    }
+
+    /// The handle to make the next call through.
+    pub fn route(&self) -> &PluginHandle {
+        let n = self.routed.fetch_add(1, Ordering::Relaxed);
+        if self.current.is_stale() {
+            self.to_candidate.fetch_add(1, Ordering::Relaxed);
+            return &self.candidate;
+        }
+        // Call n goes to the candidate when it moves the candidate's share
+        // of the first n + 1 calls past a whole call.
+        let f = self.fraction.load(Ordering::Relaxed) as u64;
+        if (n + 1) * f / SCALE > n * f / SCALE {
+            self.to_candidate.fetch_add(1, Ordering::Relaxed);
+            &self.candidate
+        } else {
+            &self.current
+        }
+    }
+
+    /// Calls routed to each version so far, with their call statistics.
+    pub fn stats(&self) -> SplitStats {
+        let to_candidate = self.to_candidate.load(Ordering::Relaxed);
+        let routed = self.routed.load(Ordering::Relaxed);
+        SplitStats {
+            current: VersionStats {
+                routed: routed.saturating_sub(to_candidate),
+                stats: self.current.stats(),
+            },
+            candidate: VersionStats {
+                routed: to_candidate,
+                stats: self.candidate.stats(),
+            },
+        }
+    }
+}
+
+fn to_millionths(fraction: f64) -> u32 {
+    (fraction.clamp(0.0, 1.0) * SCALE as f64).round() as u32
+}
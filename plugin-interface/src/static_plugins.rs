@@ -0,0 +1,89 @@
+//! Plugins linked into the host instead of loaded from a library:
+//! `PluginManager::load_static` makes their registrations with the
+//! `RegistrationFactory` entries `#[plugin_impl]` submits to `inventory`,
+//! without opening anything, so they also run under Miri and sanitizers and
+//! on platforms without dynamic loading.
+
+use crate::handle::{notify_loaded, LoadedLib};
+use crate::host::SharedHostContext;
+use crate::{
+    HostContextSlot, HostInfo, PluginLoadError, PluginTrait, RegistrationArray, RegistrationFactory,
+};
+use std::ffi::{c_void, CStr};
+use std::os::raw::c_char;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// The path the linked registrations of `trait_id` are loaded under, e.g.
+/// `static:Greeter`.
+pub(crate) fn static_path(trait_id: PluginTrait) -> PathBuf {
+    PathBuf::from(format!("static:{}", trait_id.as_str()))
+}
+
+fn provides(trait_name: *const c_char, trait_id: PluginTrait) -> bool {
+    unsafe { CStr::from_ptr(trait_name) }.to_str() == Ok(trait_id.as_str())
+}
+
+/// Hand `host` to the linked crates aggregating `trait_id`, make a
+/// registration with each of their factories and send them `on_load`, as
+/// the register function of a library would. `Ok(None)` if no linked
+/// plugin provides `trait_id`.
+pub(crate) fn open_linked(
+    trait_id: PluginTrait,
+    host: Arc<SharedHostContext>,
+    path: PathBuf,
+) -> Result<Option<LoadedLib>, PluginLoadError> {
+    let image = crate::loader::process_image().map_err(PluginLoadError::Lib)?;
+    for slot in inventory::iter::<HostContextSlot> {
+        if provides(slot.trait_name, trait_id) {
+            (slot.store)(host.as_ptr());
+        }
+    }
+    let mut regs: Vec<*const c_void> = Vec::new();
+    let mut factories: Vec<*const RegistrationFactory> = Vec::new();
+    for factory in inventory::iter::<RegistrationFactory> {
+        if !provides(factory.trait_name, trait_id) {
+            continue;
+        }
+        let r = (factory.maker)();
+        if !r.is_null() {
+            regs.push(r);
+            factories.push(factory);
+        }
+    }
+    if regs.is_empty() {
+        return Ok(None);
+    }
+    let arr_ptr = Box::into_raw(Box::new(RegistrationArray::new(
+        regs.len(),
+        Box::into_raw(regs.into_boxed_slice()) as *const *const c_void,
+        Box::into_raw(factories.into_boxed_slice()) as *const *const RegistrationFactory,
+    )));
+    unsafe { notify_loaded(arr_ptr, trait_id, HostInfo::current()) };
+    let mut loaded = LoadedLib::new_host_owned(image, arr_ptr, trait_id, path);
+    loaded.linked = true;
+    loaded.host_context = Some(host);
+    Ok(Some(loaded))
+}
+
+/// Release the registrations `open_linked` made through their factories
+/// and free the array.
+///
+/// # Safety
+/// `arr_ptr` must come from `open_linked` and not be used again; no calls
+/// may be running in its registrations.
+pub(crate) unsafe fn release(arr_ptr: *const RegistrationArray) {
+    let arr = Box::from_raw(arr_ptr as *mut RegistrationArray);
+    let regs: Box<[*const c_void]> = Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+        arr.registrations as *mut *const c_void,
+        arr.count,
+    ));
+    let factories: Box<[*const RegistrationFactory]> =
+        Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+            arr.factories as *mut *const RegistrationFactory,
+            arr.count,
+        ));
+    for (&r, &factory) in regs.iter().zip(factories.iter()) {
+        ((*factory).unmaker)(r);
+    }
+}
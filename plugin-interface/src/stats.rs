@@ -0,0 +1,276 @@
+use crate::budget::{BudgetBinding, ErrorWindow};
+use crate::call::{DisabledPolicy, PluginCallError};
+use crate::handle::PluginId;
+use crate::intercept::Binding;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Lock-free call counters kept for every registration of a loaded library
+/// and updated by the proxies on each call.
+#[derive(Debug, Default)]
+pub(crate) struct CallCounters {
+    calls: AtomicU64,
+    errors: AtomicU64,
+    timeouts: AtomicU64,
+    total_ns: AtomicU64,
+    /// Milliseconds since the Unix epoch of the last call; 0 = never called.
+    last_call_ms: AtomicU64,
+    /// Calls per `metrics::LATENCY_BUCKETS` bucket; slower calls are only
+    /// in `calls`.
+    #[cfg(feature = "metrics")]
+    latency_buckets: [AtomicU64; crate::metrics::LATENCY_BUCKETS.len()],
+}
+
+impl CallCounters {
+    pub(crate) fn for_count(count: usize) -> Vec<CallCounters> {
+        (0..count).map(|_| CallCounters::default()).collect()
+    }
+
+    pub(crate) fn record(&self, elapsed: Duration, failed: bool) {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        if failed {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        self.total_ns
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+        let now_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        self.last_call_ms.store(now_ms, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        if let Some(bucket) = crate::metrics::LATENCY_BUCKETS
+            .iter()
+            .position(|&bound| elapsed.as_secs_f64() <= bound)
+        {
+            self.latency_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Calls per latency histogram bucket, not cumulative.
+    #[cfg(feature = "metrics")]
+    pub(crate) fn latency_buckets(&self) -> Vec<u64> {
+        self.latency_buckets
+            .iter()
+            .map(|b| b.load(Ordering::Relaxed))
+            .collect()
+    }
+
+    pub(crate) fn record_timeout(&self) {
+        self.timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self, id: PluginId, path: PathBuf, index: usize) -> PluginStats {
+        let calls = self.calls.load(Ordering::Relaxed);
+        let total_ns = self.total_ns.load(Ordering::Relaxed);
+        let mean_latency = Duration::from_nanos(total_ns.checked_div(calls).unwrap_or(0));
+        let last_ms = self.last_call_ms.load(Ordering::Relaxed);
+        PluginStats {
+            id,
+            path,
+            index,
+            calls,
+            errors: self.errors.load(Ordering::Relaxed),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+            total_latency: Duration::from_nanos(total_ns),
+            mean_latency,
+            last_call: (last_ms != 0).then(|| UNIX_EPOCH + Duration::from_millis(last_ms)),
+        }
+    }
+}
+
+/// Per-library call bookkeeping shared by every proxy backend: one set of
+/// counters and an enabled flag per registration, plus the number of
+/// overdue watchdog calls.
+#[derive(Debug, Default)]
+pub(crate) struct CallState {
+    pub(crate) counters: Vec<CallCounters>,
+    /// Watchdog calls that timed out and have not returned yet.
+    overdue_calls: AtomicUsize,
+    /// Set once `PluginManager::reload_by_path` replaced the plugin.
+    stale: AtomicBool,
+    /// Per registration: `ENABLED`, or the `DisabledPolicy` its calls follow.
+    disabled: Vec<AtomicU8>,
+    /// Per registration: recent failed calls, against the error budget.
+    error_windows: Vec<ErrorWindow>,
+    /// The error budget of the manager that loaded the library.
+    budget: OnceLock<BudgetBinding>,
+    /// The interceptors of the manager that loaded the library.
+    interceptors: OnceLock<Binding>,
+}
+
+const ENABLED: u8 = 0;
+const DISABLED_ERROR: u8 = 1;
+const DISABLED_SKIP: u8 = 2;
+
+impl CallState {
+    pub(crate) fn for_count(count: usize) -> Self {
+        Self {
+            counters: CallCounters::for_count(count),
+            overdue_calls: AtomicUsize::new(0),
+            stale: AtomicBool::new(false),
+            disabled: (0..count).map(|_| AtomicU8::new(ENABLED)).collect(),
+            error_windows: (0..count).map(|_| ErrorWindow::default()).collect(),
+            budget: OnceLock::new(),
+            interceptors: OnceLock::new(),
+        }
+    }
+
+    /// Run calls through the interceptors of `binding` from now on. A
+    /// library keeps the first manager's.
+    pub(crate) fn bind_interceptors(&self, binding: Binding) {
+        let _ = self.interceptors.set(binding);
+    }
+
+    pub(crate) fn interceptors(&self) -> Option<&Binding> {
+        self.interceptors.get()
+    }
+
+    /// Number of registrations tracked.
+    pub(crate) fn registrations(&self) -> usize {
+        self.disabled.len()
+    }
+
+    /// Turn the registration at `index` off, with calls following `policy`,
+    /// or back on for `None` with its error budget restored. False if there
+    /// is no such registration.
+    pub(crate) fn set_disabled(&self, index: usize, policy: Option<DisabledPolicy>) -> bool {
+        let Some(flag) = self.disabled.get(index) else {
+            return false;
+        };
+        let value = match policy {
+            None => {
+                self.error_windows[index].clear();
+                ENABLED
+            }
+            Some(DisabledPolicy::Error) => DISABLED_ERROR,
+            Some(DisabledPolicy::Skip) => DISABLED_SKIP,
+        };
+        flag.store(value, Ordering::SeqCst);
+        true
+    }
+
+    /// The policy calls to the registration at `index` follow, if it is
+    /// turned off.
+    pub(crate) fn disabled(&self, index: usize) -> Option<DisabledPolicy> {
+        match self.disabled.get(index)?.load(Ordering::SeqCst) {
+            DISABLED_ERROR => Some(DisabledPolicy::Error),
+            DISABLED_SKIP => Some(DisabledPolicy::Skip),
+            _ => None,
+        }
+    }
+
+    /// What a proxy call to the registration at `index` returns instead of
+    /// calling the plugin, if the registration is turned off.
+    pub(crate) fn disabled_result<R: Default>(
+        &self,
+        index: usize,
+    ) -> Option<Result<R, PluginCallError>> {
+        match self.disabled(index)? {
+            DisabledPolicy::Error => Some(Err(PluginCallError::Disabled)),
+            DisabledPolicy::Skip => Some(Ok(R::default())),
+        }
+    }
+
+    /// The recent failed calls of the registration at `index`.
+    pub(crate) fn error_window(&self, index: usize) -> Option<&ErrorWindow> {
+        self.error_windows.get(index)
+    }
+
+    /// Spend the error budget of the manager that loaded the library; the
+    /// first binding wins.
+    pub(crate) fn bind_budget(&self, budget: BudgetBinding) {
+        let _ = self.budget.set(budget);
+    }
+
+    /// Count a failed call of the registration at `index` against the
+    /// error budget, if the library was bound to one.
+    pub(crate) fn spend_error(&self, index: usize, path: &Path) {
+        if let Some(budget) = self.budget.get() {
+            budget.spend(self, index, path);
+        }
+    }
+
+    pub(crate) fn is_stale(&self) -> bool {
+        self.stale.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn mark_stale(&self) {
+        self.stale.store(true, Ordering::SeqCst);
+    }
+
+    /// True while a watchdog call is past its timeout.
+    pub(crate) fn is_stuck(&self) -> bool {
+        self.overdue_calls.load(Ordering::SeqCst) > 0
+    }
+
+    pub(crate) fn start_overdue_call(&self, index: usize) {
+        self.overdue_calls.fetch_add(1, Ordering::SeqCst);
+        if let Some(c) = self.counters.get(index) {
+            c.record_timeout();
+        }
+    }
+
+    pub(crate) fn finish_overdue_call(&self) {
+        self.overdue_calls.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn snapshots(
+        &self,
+        path: &std::path::Path,
+        id: impl Fn(usize) -> PluginId,
+    ) -> Vec<PluginStats> {
+        self.counters
+            .iter()
+            .enumerate()
+            .map(|(index, c)| c.snapshot(id(index), path.to_path_buf(), index))
+            .collect()
+    }
+}
+
+/// Point-in-time call statistics for one plugin registration, as returned by
+/// `PluginManager::stats`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PluginStats {
+    /// Id of the handle the statistics belong to (see `PluginHandle::id`).
+    pub id: PluginId,
+    /// Library the registration was loaded from.
+    pub path: PathBuf,
+    /// Index of the registration inside the library.
+    pub index: usize,
+    /// Number of proxy calls made.
+    pub calls: u64,
+    /// Calls the plugin reported as failed (for example a caught panic).
+    pub errors: u64,
+    /// Watchdog calls that exceeded their `CallOptions::timeout`.
+    pub timeouts: u64,
+    pub total_latency: Duration,
+    pub mean_latency: Duration,
+    /// Time of the most recent call, if any.
+    pub last_call: Option<SystemTime>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_aggregates_recorded_calls() {
+        let c = CallCounters::default();
+        let empty = c.snapshot(PluginId(1), PathBuf::from("lib.so"), 0);
+        assert_eq!(empty.calls, 0);
+        assert_eq!(empty.mean_latency, Duration::ZERO);
+        assert_eq!(empty.last_call, None);
+
+        c.record(Duration::from_millis(2), false);
+        c.record(Duration::from_millis(4), true);
+        let s = c.snapshot(PluginId(1), PathBuf::from("lib.so"), 0);
+        assert_eq!(s.calls, 2);
+        assert_eq!(s.errors, 1);
+        assert_eq!(s.total_latency, Duration::from_millis(6));
+        assert_eq!(s.mean_latency, Duration::from_millis(3));
+        assert!(s.last_call.is_some());
+    }
+}
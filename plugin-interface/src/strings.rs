@@ -0,0 +1,117 @@
+//! Strings passed across the plugin boundary as pointer and length, without
+//! a `CString` per call.
+
+/// A borrowed string argument. Not nul-terminated, so it may contain nul
+/// bytes. The bytes are valid UTF-8, which the sender guarantees, so the
+/// receiver does not check them again.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct StrRef {
+    pub ptr: *const u8,
+    pub len: usize,
+}
+
+impl StrRef {
+    pub fn new(s: &str) -> Self {
+        Self {
+            ptr: s.as_ptr(),
+            len: s.len(),
+        }
+    }
+
+    /// The string this refers to.
+    ///
+    /// # Safety
+    /// `ptr` and `len` must describe valid UTF-8 that outlives `'a`.
+    pub unsafe fn as_str<'a>(&self) -> &'a str {
+        if self.ptr.is_null() {
+            return "";
+        }
+        let bytes = std::slice::from_raw_parts(self.ptr, self.len);
+        debug_assert!(std::str::from_utf8(bytes).is_ok());
+        std::str::from_utf8_unchecked(bytes)
+    }
+}
+
+/// A string returned by a plugin, together with the function that frees
+/// it. The receiver calls `free` with `ptr` and `len` once it is done with
+/// the bytes, so the allocator that made them also releases them. A null
+/// `ptr` means the call failed.
+#[repr(C)]
+#[derive(Debug)]
+pub struct OwnedStr {
+    pub ptr: *mut u8,
+    pub len: usize,
+    pub free: Option<extern "C" fn(*mut u8, usize)>,
+}
+
+impl OwnedStr {
+    /// Hand `s` over without copying it.
+    pub fn new(s: String) -> Self {
+        let len = s.len();
+        let ptr = Box::into_raw(s.into_boxed_str()) as *mut u8;
+        crate::record_made(crate::Allocation::String);
+        Self {
+            ptr,
+            len,
+            free: Some(free_boxed_str),
+        }
+    }
+
+    /// The value returned for a failed call.
+    pub fn null() -> Self {
+        Self {
+            ptr: std::ptr::null_mut(),
+            len: 0,
+            free: None,
+        }
+    }
+
+    /// Copy the string out and free it. `None` for a failed call.
+    ///
+    /// # Safety
+    /// `self` must have been returned across the plugin boundary and not
+    /// been freed yet; its bytes must be valid UTF-8.
+    pub unsafe fn into_string(self) -> Option<String> {
+        if self.ptr.is_null() {
+            return None;
+        }
+        let s = StrRef {
+            ptr: self.ptr,
+            len: self.len,
+        }
+        .as_str()
+        .to_owned();
+        if let Some(free) = self.free {
+            free(self.ptr, self.len);
+        }
+        Some(s)
+    }
+}
+
+extern "C" fn free_boxed_str(ptr: *mut u8, len: usize) {
+    if ptr.is_null() {
+        return;
+    }
+    crate::record_released(crate::Allocation::String);
+    // SAFETY: `ptr` and `len` come from `Box::into_raw` in `OwnedStr::new`.
+    drop(unsafe { Box::from_raw(std::ptr::slice_from_raw_parts_mut(ptr, len) as *mut str) });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strings_with_nul_bytes_round_trip() {
+        let text = "a\0b";
+        assert_eq!(unsafe { StrRef::new(text).as_str() }, text);
+        let owned = OwnedStr::new(text.to_string());
+        assert_eq!(unsafe { owned.into_string() }.as_deref(), Some(text));
+        assert_eq!(unsafe { OwnedStr::null().into_string() }, None);
+        assert_eq!(
+            unsafe { OwnedStr::new(String::new()).into_string() }.as_deref(),
+            Some("")
+        );
+    }
+}
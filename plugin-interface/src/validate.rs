@@ -0,0 +1,302 @@
+//! Checking plugin files without opening them, for
+//! `PluginManager::validate`: the file format and architecture are read
+//! from the headers and the register functions from the export table, so
+//! nothing in the library runs.
+
+use crate::manifest::{self, Candidate};
+use crate::{symbols, DiscoveryPolicy, PluginTrait, MAX_PLUGIN_ABI, MIN_PLUGIN_ABI};
+use object::{Architecture, BinaryFormat, Object, ObjectKind, ReadRef};
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+
+/// A problem `PluginManager::validate` found with one file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ValidationProblem {
+    /// The file could not be read.
+    Unreadable(String),
+    /// The file is not a dynamic library, for example a renamed text file,
+    /// an executable or an object file. Says what was found instead.
+    NotADynamicLibrary(String),
+    /// The library was built for another platform, for example a 32-bit
+    /// DLL for a 64-bit host.
+    WrongArchitecture { expected: String, found: String },
+    /// The library exports neither `plugin_register_all_<Trait>_v<N>` nor
+    /// `plugin_register_<Trait>_v<N>` for the trait.
+    MissingRegister,
+    /// Register functions exist, but only for these ABI levels, none of
+    /// them between `MIN_PLUGIN_ABI` and `MAX_PLUGIN_ABI`.
+    UnsupportedAbi(Vec<u32>),
+    /// Neither `plugin_unregister_all_<Trait>_v<N>` nor
+    /// `plugin_unregister_<Trait>_v<N>` exists for the level the library
+    /// would be loaded at, so its registrations may not be released on
+    /// unload.
+    MissingUnregister { abi: u32 },
+    /// The sidecar manifest could not be read, or its requirements are not
+    /// met.
+    Manifest(String),
+}
+
+impl std::fmt::Display for ValidationProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationProblem::Unreadable(e) => write!(f, "cannot read file: {}", e),
+            ValidationProblem::NotADynamicLibrary(found) => {
+                write!(f, "not a dynamic library: {}", found)
+            }
+            ValidationProblem::WrongArchitecture { expected, found } => {
+                write!(f, "built for {}, host needs {}", found, expected)
+            }
+            ValidationProblem::MissingRegister => write!(f, "no register function"),
+            ValidationProblem::UnsupportedAbi(levels) => write!(
+                f,
+                "register functions only for ABI levels {:?}, host supports {} to {}",
+                levels, MIN_PLUGIN_ABI, MAX_PLUGIN_ABI
+            ),
+            ValidationProblem::MissingUnregister { abi } => {
+                write!(f, "no unregister function for ABI level {}", abi)
+            }
+            ValidationProblem::Manifest(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// What `PluginManager::validate` found for one candidate file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileReport {
+    /// The library, or the manifest if the manifest itself is broken.
+    pub path: PathBuf,
+    /// The ABI level the library would be loaded at, if it exports one
+    /// the host supports.
+    pub abi_version: Option<u32>,
+    pub problems: Vec<ValidationProblem>,
+}
+
+impl FileReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// What `PluginManager::validate` found in a directory, one entry per
+/// candidate in discovery order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    pub files: Vec<FileReport>,
+}
+
+impl ValidationReport {
+    /// True if no file has a problem.
+    pub fn is_ok(&self) -> bool {
+        self.files.iter().all(FileReport::is_ok)
+    }
+
+    /// The files with at least one problem.
+    pub fn failures(&self) -> impl Iterator<Item = &FileReport> {
+        self.files.iter().filter(|f| !f.is_ok())
+    }
+}
+
+/// Check every candidate `load_plugins` would discover in `dir`.
+pub(crate) fn validate_dir(
+    dir: &Path,
+    trait_id: PluginTrait,
+    policy: &DiscoveryPolicy,
+) -> std::io::Result<ValidationReport> {
+    let files = manifest::discover(dir, policy)?
+        .into_iter()
+        .map(|candidate| match candidate {
+            Ok(candidate) => check_candidate(&candidate, trait_id),
+            Err((path, error)) => FileReport {
+                path,
+                abi_version: None,
+                problems: vec![ValidationProblem::Manifest(error.to_string())],
+            },
+        })
+        .collect();
+    Ok(ValidationReport { files })
+}
+
+fn check_candidate(candidate: &Candidate, trait_id: PluginTrait) -> FileReport {
+    let mut report = FileReport {
+        path: candidate.path.clone(),
+        abi_version: None,
+        problems: Vec::new(),
+    };
+    if let Some(m) = &candidate.manifest {
+        if let Err(e) = m.validate(trait_id.as_str()) {
+            report
+                .problems
+                .push(ValidationProblem::Manifest(e.to_string()));
+        }
+    }
+    // Modules are checked when they are instantiated.
+    #[cfg(feature = "wasm")]
+    if crate::wasm::is_wasm_module(&candidate.path) {
+        return report;
+    }
+    match std::fs::read(&candidate.path) {
+        Ok(data) => report.abi_version = check_library(&data, trait_id, &mut report.problems),
+        Err(e) => report
+            .problems
+            .push(ValidationProblem::Unreadable(e.to_string())),
+    }
+    report
+}
+
+/// Check the headers and exports of the library in `data`. Returns the ABI
+/// level it would be loaded at.
+fn check_library(
+    data: &[u8],
+    trait_id: PluginTrait,
+    problems: &mut Vec<ValidationProblem>,
+) -> Option<u32> {
+    let file = match sniff(data) {
+        Ok(file) => file,
+        Err(problem) => {
+            problems.push(problem);
+            return None;
+        }
+    };
+    let exports: HashSet<String> = file
+        .exports()
+        .unwrap_or_default()
+        .iter()
+        .map(|e| {
+            let name = String::from_utf8_lossy(e.name());
+            // Mach-O prefixes C symbols with an underscore.
+            match file.format() {
+                BinaryFormat::MachO => name.strip_prefix('_').unwrap_or(&name).to_string(),
+                _ => name.into_owned(),
+            }
+        })
+        .collect();
+
+    let trait_name = trait_id.as_str();
+    let levels = |prefix: &str| {
+        exports
+            .iter()
+            .filter_map(|name| symbols::parse_versioned(name, prefix, trait_name))
+            .collect::<BTreeSet<u32>>()
+    };
+    let mut registered = levels(symbols::REGISTER_ALL);
+    registered.extend(levels(symbols::REGISTER));
+    let Some(abi) = registered
+        .range(MIN_PLUGIN_ABI..=MAX_PLUGIN_ABI)
+        .next_back()
+        .copied()
+    else {
+        problems.push(if registered.is_empty() {
+            ValidationProblem::MissingRegister
+        } else {
+            ValidationProblem::UnsupportedAbi(registered.into_iter().collect())
+        });
+        return None;
+    };
+    let unregister = |prefix: &str| symbols::versioned(prefix, trait_name, abi);
+    if !exports.contains(&unregister(symbols::UNREGISTER_ALL))
+        && !exports.contains(&unregister(symbols::UNREGISTER))
+    {
+        problems.push(ValidationProblem::MissingUnregister { abi });
+    }
+    Some(abi)
+}
+
+/// Parse `data` as a dynamic library this host can load. With a
+/// `ReadCache` over the open file, only the headers are read.
+pub(crate) fn sniff<'data, R: ReadRef<'data>>(
+    data: R,
+) -> Result<object::File<'data, R>, ValidationProblem> {
+    let file = object::File::parse(data)
+        .map_err(|e| ValidationProblem::NotADynamicLibrary(e.to_string()))?;
+    let (format, architecture) = (file.format(), file.architecture());
+    let native = native_architecture();
+    if format != NATIVE_FORMAT || (native != Architecture::Unknown && architecture != native) {
+        return Err(ValidationProblem::WrongArchitecture {
+            expected: format!("{:?} {:?}", NATIVE_FORMAT, native),
+            found: format!("{:?} {:?}", format, architecture),
+        });
+    }
+    if file.kind() != ObjectKind::Dynamic {
+        return Err(ValidationProblem::NotADynamicLibrary(format!(
+            "{:?} {:?} file",
+            format,
+            file.kind()
+        )));
+    }
+    Ok(file)
+}
+
+#[cfg(windows)]
+const NATIVE_FORMAT: BinaryFormat = BinaryFormat::Pe;
+#[cfg(target_vendor = "apple")]
+const NATIVE_FORMAT: BinaryFormat = BinaryFormat::MachO;
+#[cfg(not(any(windows, target_vendor = "apple")))]
+const NATIVE_FORMAT: BinaryFormat = BinaryFormat::Elf;
+
+/// The architecture this host was built for, or `Unknown` if the check
+/// should be skipped.
+fn native_architecture() -> Architecture {
+    match std::env::consts::ARCH {
+        "x86" => Architecture::I386,
+        "x86_64" => Architecture::X86_64,
+        "arm" => Architecture::Arm,
+        "aarch64" => Architecture::Aarch64,
+        "riscv64" => Architecture::Riscv64,
+        "powerpc64" => Architecture::PowerPc64,
+        "s390x" => Architecture::S390x,
+        "loongarch64" => Architecture::LoongArch64,
+        _ => Architecture::Unknown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_files_that_are_not_libraries_and_broken_manifests() {
+        let dir = tempfile::tempdir().unwrap();
+        let lib = |stem: &str| {
+            dir.path()
+                .join(format!("{}.{}", stem, std::env::consts::DLL_EXTENSION))
+        };
+        std::fs::write(lib("notes"), b"just some text").unwrap();
+        std::fs::write(dir.path().join("broken.plugin.toml"), b"name = ").unwrap();
+
+        let report = validate_dir(
+            dir.path(),
+            PluginTrait::Greeter,
+            &DiscoveryPolicy::default(),
+        )
+        .unwrap();
+        assert!(!report.is_ok());
+        assert_eq!(report.files.len(), 2);
+        let manifest = &report.files[0];
+        assert_eq!(manifest.path, dir.path().join("broken.plugin.toml"));
+        assert!(matches!(
+            manifest.problems[..],
+            [ValidationProblem::Manifest(_)]
+        ));
+        let notes = &report.files[1];
+        assert_eq!(notes.path, lib("notes"));
+        assert_eq!(notes.abi_version, None);
+        assert!(matches!(
+            notes.problems[..],
+            [ValidationProblem::NotADynamicLibrary(_)]
+        ));
+    }
+
+    #[test]
+    fn an_empty_directory_is_fine() {
+        let dir = tempfile::tempdir().unwrap();
+        let report = validate_dir(
+            dir.path(),
+            PluginTrait::Greeter,
+            &DiscoveryPolicy::default(),
+        )
+        .unwrap();
+        assert!(report.is_ok());
+        assert!(report.files.is_empty());
+    }
+}
@@ -0,0 +1,225 @@
+//! WebAssembly plugin backend. `.wasm` modules found by `load_plugins` (or
+//! the watcher) are instantiated with wasmtime and exposed through the same
+//! `PluginHandle`/`GreeterProxy` types as native libraries.
+//!
+//! A module provides a trait by exporting, with `<Trait>` being the name
+//! from `PluginTrait::as_str` (e.g. `Greeter`):
+//!
+//! - `memory`
+//! - `plugin_alloc(len: i32) -> i32`: space for host-provided strings
+//! - `plugin_<Trait>_v1_count() -> i32`: number of registrations
+//! - `plugin_<Trait>_v1_name(index: i32) -> i32`: nul-terminated UTF-8, 0 for none
+//! - `plugin_<Trait>_v1_greet(index: i32, ptr: i32, len: i32)`
+//! - optionally `plugin_<Trait>_v1_on_load(index: i32)` and
+//!   `plugin_<Trait>_v1_on_unload(index: i32)`
+//!
+//! Modules may import `plugin_host.log(level: i32, ptr: i32, len: i32)`,
+//! which forwards to the manager's `HostServices` like `HostContext::log`.
+
+use crate::call::PluginCallError;
+use crate::host::SharedHostContext;
+use crate::stats::CallState;
+use crate::{LogLevel, PluginManifest, PluginTrait};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use wasmtime::{Caller, Engine, Instance, Linker, Memory, Module, Store, TypedFunc};
+
+/// Whether `path` looks like a WebAssembly module.
+pub(crate) fn is_wasm_module(path: &Path) -> bool {
+    path.extension()
+        .and_then(|s| s.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("wasm"))
+        .unwrap_or(false)
+}
+
+fn engine() -> &'static Engine {
+    static ENGINE: OnceLock<Engine> = OnceLock::new();
+    ENGINE.get_or_init(Engine::default)
+}
+
+struct WasmState {
+    store: Store<Arc<SharedHostContext>>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    name: TypedFunc<i32, i32>,
+    greet: TypedFunc<(i32, i32, i32), ()>,
+    on_unload: Option<TypedFunc<i32, ()>>,
+}
+
+/// An instantiated WebAssembly plugin module.
+pub(crate) struct WasmPlugin {
+    path: PathBuf,
+    canonical_path: PathBuf,
+    pub(crate) manifest: Option<PluginManifest>,
+    state: Mutex<WasmState>,
+    count: usize,
+    pub(crate) trait_id: PluginTrait,
+    pub(crate) calls: CallState,
+    /// Dependencies opened together with this module by a lazy lookup.
+    pub(crate) dependencies: Vec<crate::PluginHandle>,
+}
+
+impl std::fmt::Debug for WasmPlugin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WasmPlugin")
+            .field("path", &self.path)
+            .field("count", &self.count)
+            .finish()
+    }
+}
+
+impl WasmPlugin {
+    /// Compile and instantiate the module at `path` and run its `on_load`
+    /// hooks. Fails if the module does not export the `trait_id` ABI.
+    pub(crate) fn load(
+        path: &Path,
+        trait_id: PluginTrait,
+        host: Arc<SharedHostContext>,
+    ) -> Result<Self, String> {
+        let module = Module::from_file(engine(), path).map_err(|e| e.to_string())?;
+        let mut linker = Linker::new(engine());
+        linker
+            .func_wrap(
+                "plugin_host",
+                "log",
+                |mut caller: Caller<'_, Arc<SharedHostContext>>, level: i32, ptr: i32, len: i32| {
+                    let Some(memory) = caller.get_export("memory").and_then(|e| e.into_memory())
+                    else {
+                        return;
+                    };
+                    let message = read_bytes(memory.data(&caller), ptr, len)
+                        .map(|b| String::from_utf8_lossy(b).into_owned());
+                    if let Some(message) = message {
+                        caller
+                            .data()
+                            .context()
+                            .log(LogLevel::from_u32(level as u32), &message);
+                    }
+                },
+            )
+            .map_err(|e| e.to_string())?;
+
+        let mut store = Store::new(engine(), host);
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .map_err(|e| e.to_string())?;
+
+        let sym = |method: &str| format!("plugin_{}_v1_{}", trait_id.as_str(), method);
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or("module does not export `memory`")?;
+        let alloc = typed(&instance, &mut store, "plugin_alloc")?;
+        let count_fn: TypedFunc<(), i32> = typed(&instance, &mut store, &sym("count"))?;
+        let name = typed(&instance, &mut store, &sym("name"))?;
+        let greet = typed(&instance, &mut store, &sym("greet"))?;
+        let on_load: Option<TypedFunc<i32, ()>> =
+            typed(&instance, &mut store, &sym("on_load")).ok();
+        let on_unload = typed(&instance, &mut store, &sym("on_unload")).ok();
+
+        let count = count_fn
+            .call(&mut store, ())
+            .map_err(|e| format!("{} trapped: {}", sym("count"), e))?;
+        let count = usize::try_from(count).map_err(|_| "negative registration count")?;
+        if let Some(on_load) = on_load {
+            for index in 0..count {
+                on_load
+                    .call(&mut store, index as i32)
+                    .map_err(|e| format!("{} trapped: {}", sym("on_load"), e))?;
+            }
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            canonical_path: crate::identity::canonical_path(path),
+            manifest: None,
+            state: Mutex::new(WasmState {
+                store,
+                memory,
+                alloc,
+                name,
+                greet,
+                on_unload,
+            }),
+            count,
+            trait_id,
+            calls: CallState::for_count(count),
+            dependencies: Vec::new(),
+        })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn canonical_path(&self) -> &Path {
+        &self.canonical_path
+    }
+
+    pub(crate) fn count(&self) -> usize {
+        self.count
+    }
+
+    pub(crate) fn name(&self, index: usize) -> Result<String, PluginCallError> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let st = &mut *state;
+        let ptr = st
+            .name
+            .call(&mut st.store, index as i32)
+            .map_err(|e| PluginCallError::Trapped(e.to_string()))?;
+        let data = st.memory.data(&st.store);
+        let start = usize::try_from(ptr)
+            .ok()
+            .filter(|&p| p != 0 && p < data.len())
+            .ok_or(PluginCallError::Failed)?;
+        let len = data[start..]
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or(PluginCallError::Failed)?;
+        Ok(String::from_utf8_lossy(&data[start..start + len]).into_owned())
+    }
+
+    pub(crate) fn greet(&self, index: usize, target: &str) -> Result<(), PluginCallError> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let st = &mut *state;
+        let trapped = |e: wasmtime::Error| PluginCallError::Trapped(e.to_string());
+        let len = i32::try_from(target.len()).map_err(|_| PluginCallError::Failed)?;
+        let ptr = st.alloc.call(&mut st.store, len).map_err(trapped)?;
+        st.memory
+            .write(&mut st.store, ptr as u32 as usize, target.as_bytes())
+            .map_err(|_| PluginCallError::Failed)?;
+        st.greet
+            .call(&mut st.store, (index as i32, ptr, len))
+            .map_err(trapped)
+    }
+}
+
+impl Drop for WasmPlugin {
+    fn drop(&mut self) {
+        let st = self.state.get_mut().unwrap_or_else(|e| e.into_inner());
+        if let Some(on_unload) = &st.on_unload {
+            for index in 0..self.count {
+                let _ = on_unload.call(&mut st.store, index as i32);
+            }
+        }
+    }
+}
+
+fn typed<P, R>(
+    instance: &Instance,
+    store: &mut Store<Arc<SharedHostContext>>,
+    name: &str,
+) -> Result<TypedFunc<P, R>, String>
+where
+    P: wasmtime::WasmParams,
+    R: wasmtime::WasmResults,
+{
+    instance
+        .get_typed_func(&mut *store, name)
+        .map_err(|e| format!("export `{}`: {}", name, e))
+}
+
+fn read_bytes(data: &[u8], ptr: i32, len: i32) -> Option<&[u8]> {
+    let start = usize::try_from(ptr).ok()?;
+    let end = start.checked_add(usize::try_from(len).ok()?)?;
+    data.get(start..end)
+}
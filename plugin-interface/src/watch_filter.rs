@@ -0,0 +1,86 @@
+//! The include/exclude globs of `WatchOptions`, compiled once per watcher.
+
+use glob::{MatchOptions, Pattern, PatternError};
+use std::path::Path;
+
+/// Decides which files under a watched directory the watcher acts on. A
+/// pattern without a `/` is matched against the file name; one with a `/`
+/// against the path relative to the watched directory, with `*` stopping
+/// at separators.
+#[derive(Debug, Default)]
+pub(crate) struct PathFilter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl PathFilter {
+    pub(crate) fn new(include: &[String], exclude: &[String]) -> Result<Self, String> {
+        let compile = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|p| {
+                    Pattern::new(p)
+                        .map_err(|e: PatternError| format!("bad watch pattern {:?}: {}", p, e))
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(Self {
+            include: compile(include)?,
+            exclude: compile(exclude)?,
+        })
+    }
+
+    /// Whether `path`, found under `dir`, passes: it matches an include
+    /// pattern, if there are any, and no exclude pattern.
+    pub(crate) fn allows(&self, dir: &Path, path: &Path) -> bool {
+        let matches = |pattern: &Pattern| {
+            if pattern.as_str().contains('/') {
+                let relative = path.strip_prefix(dir).unwrap_or(path);
+                let options = MatchOptions {
+                    require_literal_separator: true,
+                    ..MatchOptions::new()
+                };
+                pattern.matches_path_with(relative, options)
+            } else {
+                path.file_name()
+                    .is_some_and(|name| pattern.matches(&name.to_string_lossy()))
+            }
+        };
+        (self.include.is_empty() || self.include.iter().any(matches))
+            && !self.exclude.iter().any(matches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(list: &[&str]) -> Vec<String> {
+        list.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn includes_and_excludes_by_name_or_relative_path() {
+        let dir = Path::new("/plugins");
+        let filter = PathFilter::new(
+            &patterns(&["libmyapp_*", "vendor/*.so"]),
+            &patterns(&["*_test.so"]),
+        )
+        .unwrap();
+        assert!(filter.allows(dir, &dir.join("libmyapp_greeter.so")));
+        assert!(filter.allows(dir, &dir.join("nested/libmyapp_greeter.so")));
+        assert!(!filter.allows(dir, &dir.join("libmyapp_greeter_test.so")));
+        assert!(!filter.allows(dir, &dir.join("libother.so")));
+        assert!(filter.allows(dir, &dir.join("vendor/libother.so")));
+        assert!(!filter.allows(dir, &dir.join("vendor/deeper/libother.so")));
+
+        let everything = PathFilter::default();
+        assert!(everything.allows(dir, &dir.join("libother.so")));
+    }
+
+    #[test]
+    fn bad_patterns_are_reported() {
+        let err = PathFilter::new(&[], &patterns(&["[unclosed"])).unwrap_err();
+        assert!(err.contains("[unclosed"), "{}", err);
+    }
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use common::built_plugin;
+use plugin_interface::{PluginManagerActor, PluginTrait};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Put `lib` into `dir`, linking rather than copying where possible.
+fn place(lib: &Path, dir: &Path) -> PathBuf {
+    let dest = dir.join(lib.file_name().unwrap());
+    if fs::hard_link(lib, &dest).is_err() {
+        fs::copy(lib, &dest).expect("copy plugin");
+    }
+    dest
+}
+
+#[test]
+fn actor_loads_on_its_own_thread_for_any_caller() {
+    let Some(lib) = built_plugin("plugin_multi") else {
+        eprintln!("plugin artifacts not built; skipping");
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    let path = place(&lib, dir.path());
+
+    let actor = PluginManagerActor::new();
+    let loaded = std::thread::spawn({
+        let actor = actor.clone();
+        let dir = dir.path().to_path_buf();
+        move || actor.load(dir, PluginTrait::Greeter)
+    })
+    .join()
+    .unwrap()
+    .expect("load");
+    assert_eq!(loaded.len(), 1);
+    assert_eq!(loaded[0].path, path);
+    assert_eq!(actor.list().len(), 1, "the actor keeps the plugin loaded");
+
+    let greeted = actor.run(|m| {
+        m.find_by_name("GreeterOne")
+            .and_then(|h| h.as_greeter())
+            .map(|g| g.greet("actor"))
+            .is_some()
+    });
+    assert!(greeted);
+
+    actor.unload(&path).expect("unload");
+    assert!(actor.list().is_empty());
+}
+
+#[cfg(feature = "watch")]
+#[test]
+fn watching_actor_loads_and_unloads_by_itself() {
+    use plugin_interface::{ActorEvent, WatchOptions};
+    use std::time::Duration;
+
+    let Some(lib) = built_plugin("plugin_multi") else {
+        eprintln!("plugin artifacts not built; skipping");
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    let actor = PluginManagerActor::new();
+    let events = actor.subscribe();
+    let opts = WatchOptions {
+        debounce_ms: 100,
+        stable_ms: 100,
+        auto_unload: true,
+        ..Default::default()
+    };
+    let watcher = actor
+        .watch(vec![(dir.path().to_path_buf(), opts)], PluginTrait::Greeter)
+        .expect("watch");
+
+    // give the watcher thread time to start watching
+    std::thread::sleep(Duration::from_millis(150));
+    let path = place(&lib, dir.path());
+    match events.recv_timeout(Duration::from_secs(10)).expect("added") {
+        ActorEvent::Added { path: p, plugin } => {
+            assert_eq!(p, path);
+            assert_eq!(plugin.expect("loaded").path, path);
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(actor.list().len(), 1);
+
+    fs::remove_file(&path).unwrap();
+    match events
+        .recv_timeout(Duration::from_secs(10))
+        .expect("removed")
+    {
+        ActorEvent::Removed { path: p, .. } => assert_eq!(p, path),
+        other => panic!("unexpected {:?}", other),
+    }
+    assert!(actor.list().is_empty(), "the actor let go of its handles");
+
+    watcher.stop().unwrap();
+}
@@ -0,0 +1,77 @@
+#![cfg(feature = "async")]
+
+mod common;
+
+use common::built_plugin;
+use plugin_interface::{AsyncPluginManager, PluginManager, PluginTrait};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Put `lib` into `dir`, linking rather than copying where possible.
+fn place(lib: &Path, dir: &Path) -> PathBuf {
+    let dest = dir.join(lib.file_name().unwrap());
+    if fs::hard_link(lib, &dest).is_err() {
+        fs::copy(lib, &dest).expect("copy plugin");
+    }
+    dest
+}
+
+#[tokio::test]
+async fn manager_loads_and_unloads_without_blocking_the_runtime() {
+    let Some(lib) = built_plugin("plugin_multi") else {
+        eprintln!("plugin artifacts not built; skipping");
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    let path = place(&lib, dir.path());
+
+    let mut mgr = PluginManager::new();
+    let handles = mgr
+        .load_plugins_async(dir.path(), PluginTrait::Greeter)
+        .await
+        .expect("load");
+    assert_eq!(handles.len(), 2);
+    for h in &handles {
+        h.as_greeter().expect("greeter").greet("async");
+    }
+    assert_eq!(mgr.list().len(), 1);
+
+    drop(handles);
+    mgr.unload_by_path_async(&path).await.expect("unload");
+    assert!(mgr.list().is_empty());
+}
+
+#[tokio::test]
+async fn async_manager_is_send_and_keeps_plugins_loaded() {
+    let Some(lib) = built_plugin("plugin_multi") else {
+        eprintln!("plugin artifacts not built; skipping");
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    let path = place(&lib, dir.path());
+
+    let mgr = AsyncPluginManager::new();
+    // The futures are Send, so the work can move onto a spawned task.
+    let task = tokio::spawn({
+        let mgr = mgr.clone();
+        let dir = dir.path().to_path_buf();
+        async move { mgr.load_plugins(dir, PluginTrait::Greeter).await }
+    });
+    let loaded = task.await.unwrap().expect("load");
+    assert_eq!(loaded.len(), 1);
+    assert_eq!(loaded[0].path, path);
+    assert_eq!(mgr.list().await.len(), 1);
+
+    let greeted = mgr
+        .run(|m| {
+            m.find_by_name("GreeterOne")
+                .and_then(|h| h.as_greeter())
+                .map(|g| g.greet("owner thread"))
+                .is_some()
+        })
+        .await;
+    assert!(greeted);
+
+    mgr.unload_by_path(&path).await.expect("unload");
+    assert!(mgr.list().await.is_empty());
+}
@@ -0,0 +1,29 @@
+mod common;
+
+use common::plugin_upper;
+use plugin_interface::{CallBackend, PluginManager, PluginTrait};
+
+#[test]
+fn bench_call_times_every_backend() {
+    let Some(lib) = plugin_upper() else { return };
+
+    let mut mgr = PluginManager::new();
+    let handles = mgr
+        .load_library(&lib, PluginTrait::Transformer)
+        .expect("load as Transformer");
+    let bench = mgr.bench_call(&handles[0], 10).expect("bench");
+    assert_eq!(bench.method, "transform");
+    let backends: Vec<_> = bench.totals.iter().map(|(b, _)| *b).collect();
+    assert_eq!(
+        backends,
+        [
+            CallBackend::CStr,
+            CallBackend::StrRef,
+            CallBackend::Context,
+            CallBackend::Proxy
+        ]
+    );
+    assert!(bench.per_call(CallBackend::StrRef) <= bench.total(CallBackend::StrRef));
+    // Only the proxy calls are counted.
+    assert_eq!(mgr.stats()[0].calls, 10);
+}
@@ -0,0 +1,264 @@
+//! The C plugin in `examples/plugin-c`, built with the platform's C
+//! compiler against `include/plugin_interface.h`.
+#![cfg(unix)]
+
+mod common;
+
+use common::library_file_name;
+use plugin_interface::{
+    GreeterContext, GreeterOptional, GreeterRegistration, GreeterVTable, HealthStatus,
+    HostAllocator, HostContext, HostInfo, HostVersion, LogRecord, OwnedStr, PluginLoadError,
+    PluginManager, PluginTrait, RawRequestContext, RegistrationArray, StrRef, TransformerContext,
+    TransformerOptional, TransformerRegistration, TransformerVTable,
+};
+use std::mem::{offset_of, size_of};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Compile `source` into a shared library in `dir`. `None`, after saying
+/// why, if there is no C compiler to do it.
+fn compile(dir: &Path, source: &Path, name: &str) -> Option<PathBuf> {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let lib = dir.join(library_file_name(name));
+    let compiler = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let output = Command::new(&compiler)
+        .args(["-shared", "-fPIC", "-std=c99", "-Wall", "-Werror", "-I"])
+        .arg(manifest_dir.join("include"))
+        .arg(source)
+        .arg("-o")
+        .arg(&lib)
+        .output();
+    match output {
+        Ok(output) if output.status.success() => Some(lib),
+        Ok(output) => panic!(
+            "{} failed on {}:\n{}",
+            compiler,
+            source.display(),
+            String::from_utf8_lossy(&output.stderr)
+        ),
+        Err(e) => {
+            eprintln!("no C compiler ({}: {}); skipping", compiler, e);
+            None
+        }
+    }
+}
+
+fn example_source() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../examples/plugin-c/plugin.c")
+}
+
+/// Sizes and offsets the header must agree with, as the Rust side lays
+/// them out and as a C expression.
+fn layouts() -> Vec<(usize, &'static str)> {
+    vec![
+        (size_of::<StrRef>(), "sizeof(StrRef)"),
+        (size_of::<OwnedStr>(), "sizeof(OwnedStr)"),
+        (size_of::<HealthStatus>(), "sizeof(HealthStatus)"),
+        (size_of::<RawRequestContext>(), "sizeof(RawRequestContext)"),
+        (size_of::<HostVersion>(), "sizeof(HostVersion)"),
+        (offset_of!(HostVersion, abi), "offsetof(HostVersion, abi)"),
+        (size_of::<HostInfo>(), "sizeof(HostInfo)"),
+        (size_of::<LogRecord>(), "sizeof(LogRecord)"),
+        (size_of::<HostAllocator>(), "sizeof(HostAllocator)"),
+        (size_of::<HostContext>(), "sizeof(HostContext)"),
+        (
+            offset_of!(HostContext, allocator),
+            "offsetof(HostContext, allocator)",
+        ),
+        (
+            offset_of!(HostContext, plugin_config),
+            "offsetof(HostContext, plugin_config)",
+        ),
+        (
+            offset_of!(HostContext, kv_set),
+            "offsetof(HostContext, kv_set)",
+        ),
+        (size_of::<RegistrationArray>(), "sizeof(RegistrationArray)"),
+        (
+            offset_of!(RegistrationArray, struct_size),
+            "offsetof(RegistrationArray, struct_size)",
+        ),
+        (size_of::<GreeterOptional>(), "sizeof(GreeterOptional)"),
+        (size_of::<GreeterVTable>(), "sizeof(GreeterVTable)"),
+        (
+            offset_of!(GreeterVTable, save_state),
+            "offsetof(GreeterVTable, save_state)",
+        ),
+        (
+            offset_of!(GreeterVTable, name_str),
+            "offsetof(GreeterVTable, name_str)",
+        ),
+        (
+            offset_of!(GreeterVTable, struct_size),
+            "offsetof(GreeterVTable, struct_size)",
+        ),
+        (
+            offset_of!(GreeterVTable, optional),
+            "offsetof(GreeterVTable, optional)",
+        ),
+        (
+            offset_of!(GreeterVTable, context),
+            "offsetof(GreeterVTable, context)",
+        ),
+        (size_of::<GreeterContext>(), "sizeof(GreeterContext)"),
+        (
+            offset_of!(GreeterContext, greet_ctx),
+            "offsetof(GreeterContext, greet_ctx)",
+        ),
+        (
+            size_of::<GreeterRegistration>(),
+            "sizeof(GreeterRegistration)",
+        ),
+        (
+            size_of::<TransformerOptional>(),
+            "sizeof(TransformerOptional)",
+        ),
+        (
+            offset_of!(TransformerOptional, health),
+            "offsetof(TransformerOptional, health)",
+        ),
+        (size_of::<TransformerVTable>(), "sizeof(TransformerVTable)"),
+        (
+            offset_of!(TransformerVTable, save_state),
+            "offsetof(TransformerVTable, save_state)",
+        ),
+        (
+            offset_of!(TransformerVTable, transform_str),
+            "offsetof(TransformerVTable, transform_str)",
+        ),
+        (
+            offset_of!(TransformerVTable, optional),
+            "offsetof(TransformerVTable, optional)",
+        ),
+        (
+            offset_of!(TransformerVTable, context),
+            "offsetof(TransformerVTable, context)",
+        ),
+        (
+            size_of::<TransformerContext>(),
+            "sizeof(TransformerContext)",
+        ),
+        (
+            offset_of!(TransformerContext, describe_ctx),
+            "offsetof(TransformerContext, describe_ctx)",
+        ),
+        (
+            size_of::<TransformerRegistration>(),
+            "sizeof(TransformerRegistration)",
+        ),
+    ]
+}
+
+#[test]
+fn the_header_matches_the_rust_layouts() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let layouts = layouts();
+    let source = dir.path().join("layouts.c");
+    let values: Vec<&str> = layouts.iter().map(|(_, expr)| *expr).collect();
+    std::fs::write(
+        &source,
+        format!(
+            "#include <plugin_interface.h>\n\
+             const size_t plugin_layouts[] = {{\n    {},\n}};\n",
+            values.join(",\n    ")
+        ),
+    )
+    .expect("write probe");
+    let Some(lib) = compile(dir.path(), &source, "layouts") else {
+        return;
+    };
+    let lib = unsafe { libloading::Library::new(&lib) }.expect("open probe");
+    let found = unsafe {
+        let table = lib
+            .get::<*const usize>(b"plugin_layouts\0")
+            .expect("layout table");
+        std::slice::from_raw_parts(*table, layouts.len()).to_vec()
+    };
+    for ((expected, expr), found) in layouts.iter().zip(found) {
+        assert_eq!(found, *expected, "{}", expr);
+    }
+}
+
+#[test]
+fn plugins_written_in_c_load_and_unload() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let Some(lib) = compile(dir.path(), &example_source(), "plugin_c") else {
+        return;
+    };
+    let mut mgr = PluginManager::new();
+    let report = mgr
+        .validate(dir.path(), PluginTrait::Greeter)
+        .expect("validate");
+    assert!(report.is_ok(), "{:?}", report);
+    assert_eq!(report.files[0].abi_version, Some(4));
+
+    let handles = mgr
+        .load_library(&lib, PluginTrait::Greeter)
+        .expect("load as Greeter");
+    assert_eq!(handles.len(), 1);
+    assert_eq!(handles[0].abi_version(), 4);
+    assert_eq!(handles[0].registration_name().as_deref(), Some("hello-c"));
+    let greeter = handles[0].as_greeter().expect("greeter");
+    assert_eq!(greeter.name(), "hello-c");
+    assert_eq!(greeter.try_greet("world"), Ok(()));
+    assert_eq!(handles[0].health(), Ok(HealthStatus::Healthy));
+    drop((greeter, handles));
+    // The library keeps its static array; the host must not free it.
+    assert_eq!(mgr.unload_by_path(&lib), Ok(None));
+
+    let handles = mgr
+        .load_library(&lib, PluginTrait::Transformer)
+        .expect("load as Transformer");
+    let transformer = handles[0].as_transformer().expect("transformer");
+    assert_eq!(transformer.transform("abc"), "ABC");
+    assert_eq!(
+        transformer.try_describe().as_deref(),
+        Ok("uppercases ASCII letters, in C")
+    );
+    drop((transformer, handles));
+    assert_eq!(mgr.unload_by_path(&lib), Ok(None));
+}
+
+/// A level 5 plugin that refuses every host, naming the version it was given.
+const REFUSING_PLUGIN: &str = r#"
+#include <plugin_interface.h>
+#include <stdio.h>
+
+static char error[64];
+
+const RegistrationArray *plugin_register_all_Greeter_v5(const HostContext *host,
+                                                        const HostVersion *version) {
+    (void)host;
+    snprintf(error, sizeof error, "host %u.%u.%u/%u is too old", (unsigned)version->major,
+             (unsigned)version->minor, (unsigned)version->patch, (unsigned)version->abi);
+    return NULL;
+}
+
+void plugin_unregister_all_Greeter_v5(const RegistrationArray *array) { (void)array; }
+
+const char *plugin_register_error_v1(void) { return error[0] ? error : NULL; }
+"#;
+
+#[test]
+fn plugins_may_refuse_the_host_version() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let source = dir.path().join("refusing.c");
+    std::fs::write(&source, REFUSING_PLUGIN).expect("write source");
+    let Some(lib) = compile(dir.path(), &source, "refusing") else {
+        return;
+    };
+    let mut mgr = PluginManager::new();
+    match mgr.load_library(&lib, PluginTrait::Greeter) {
+        Err(PluginLoadError::Refused { path, reason }) => {
+            assert_eq!(path, lib);
+            let version = HostInfo::current_version();
+            assert_eq!(
+                reason,
+                format!("host {}/{} is too old", version, version.abi)
+            );
+            assert_eq!(version.to_string(), env!("CARGO_PKG_VERSION"));
+        }
+        other => panic!("expected Refused, got {:?}", other.map(|h| h.len())),
+    }
+    assert!(mgr.list().is_empty());
+}
@@ -1,11 +1,11 @@
 use plugin_interface::{PluginManager, PluginTrait};
 use std::path::PathBuf;
 
-// This test expects a plugin that exports the unmaker counter getter. If the
+// This test expects a plugin that exports the counters getter. If the
 // plugin artifact isn't present (for example when running on CI without
 // building the example plugins), the test will return early.
 #[test]
-fn close_returns_unmaker_counter_when_final_owner() {
+fn close_returns_counters_when_final_owner() {
     // Attempt to locate the example plugin built in the workspace. This mirrors
     // logic in manager_integration.rs but is defensive.
     let mut candidate = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -38,7 +38,7 @@ fn close_returns_unmaker_counter_when_final_owner() {
 
     // We expect at least one handle. Take the first, ensure it's the unique
     // owner by dropping all other handles/clones, then call close and assert
-    // the returned counter is Some(u64).
+    // the returned counters are Some(PluginCounters).
     assert!(!handles.is_empty());
     let mut first = handles.into_iter();
     let h = first.next().unwrap();
@@ -47,10 +47,14 @@ fn close_returns_unmaker_counter_when_final_owner() {
     drop(first);
 
     match h.close() {
-        Ok(Some(cnt)) => {
-            assert!(cnt > 0, "expected unmaker counter > 0");
+        Ok(Some(counters)) => {
+            assert!(
+                counters.registrations_destroyed > 0,
+                "expected destroyed registrations > 0"
+            );
+            assert_eq!(counters.live_registrations(), 0);
         }
-        Ok(None) => panic!("expected close() to return Some(counter) when final owner"),
+        Ok(None) => panic!("expected close() to return Some(counters) when final owner"),
         Err(e) => panic!("close() failed: {}", e),
     }
 }
@@ -0,0 +1,23 @@
+//! Fixtures shared by the integration tests in this directory.
+//!
+//! Each test file compiles its own copy of this module and uses only part of
+//! it, hence the `dead_code` and `unused_imports` allowances.
+
+#![allow(dead_code, unused_imports)]
+
+pub use plugin_test_support::{built_plugin, library_file_name};
+
+use std::path::{Path, PathBuf};
+
+/// The plugin-upper cdylib next to this test binary, if it was built.
+pub fn plugin_upper() -> Option<PathBuf> {
+    built_plugin("plugin_upper")
+}
+
+/// Copy the library at `lib` into `dir` under its own file name, for a
+/// manifest there to name.
+pub fn copy_into(dir: &Path, lib: &Path) -> PathBuf {
+    let copy = dir.join(lib.file_name().expect("library file name"));
+    std::fs::copy(lib, &copy).expect("copy plugin");
+    copy
+}
@@ -0,0 +1,21 @@
+mod common;
+
+use common::plugin_upper;
+use plugin_interface::{ConfigSource, PluginManager, PluginTrait};
+
+#[test]
+fn plugins_receive_their_configuration() {
+    let Some(lib) = plugin_upper() else { return };
+    // A private copy, so the plugin's statics are not shared with the
+    // other tests' loads.
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::copy(&lib, dir.path().join(lib.file_name().unwrap())).unwrap();
+    let config = ConfigSource::from_toml("[plugin_upper]\nsuffix = \"!\"\n").unwrap();
+
+    let mut mgr = PluginManager::new();
+    let handles = mgr
+        .load_plugins_with_config(dir.path(), PluginTrait::Transformer, &config)
+        .expect("load with config");
+    let proxy = handles[0].as_transformer().unwrap();
+    assert_eq!(proxy.transform("hi"), "HI!");
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use common::plugin_upper;
+use plugin_interface::{
+    sidecar_path, ConflictPolicy, ConflictResolution, PluginCallError, PluginLoadError,
+    PluginManager, PluginTrait,
+};
+use std::path::PathBuf;
+
+#[test]
+fn conflicting_registration_names_follow_the_policy() {
+    let Some(lib) = plugin_upper() else { return };
+    // Two private copies that both register "Upper", at different versions.
+    let dirs = [tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap()];
+    let copies: Vec<PathBuf> = dirs
+        .iter()
+        .zip(["0.1.0", "0.2.0"])
+        .enumerate()
+        .map(|(i, (dir, version))| {
+            let copy = dir.path().join(lib.file_name().unwrap());
+            std::fs::copy(&lib, &copy).unwrap();
+            std::fs::write(
+                sidecar_path(&copy),
+                format!(
+                    "name = \"upper-{}\"\nversion = \"{}\"\ntraits = [\"Transformer\"]\n",
+                    i, version
+                ),
+            )
+            .unwrap();
+            copy
+        })
+        .collect();
+
+    let mut mgr = PluginManager::new();
+    let old = mgr
+        .load_library(&copies[0], PluginTrait::Transformer)
+        .expect("load first copy");
+
+    mgr.set_conflict_policy(ConflictPolicy::Error);
+    let err = mgr
+        .load_library(&copies[1], PluginTrait::Transformer)
+        .unwrap_err();
+    assert!(
+        matches!(&err, PluginLoadError::Conflict(c) if c.name == "Upper"),
+        "{:?}",
+        err
+    );
+
+    mgr.set_conflict_policy(ConflictPolicy::FirstWins);
+    assert!(matches!(
+        mgr.load_library(&copies[1], PluginTrait::Transformer),
+        Err(PluginLoadError::NoRegistrations)
+    ));
+
+    mgr.set_conflict_policy(ConflictPolicy::HighestVersion);
+    let new = mgr
+        .load_library(&copies[1], PluginTrait::Transformer)
+        .expect("load newer copy");
+    assert_eq!(new[0].as_transformer().unwrap().transform("hi"), "HI");
+    assert_eq!(
+        old[0].as_transformer().unwrap().try_transform("hi"),
+        Err(PluginCallError::Disabled)
+    );
+
+    let resolutions: Vec<_> = mgr
+        .take_conflicts()
+        .into_iter()
+        .map(|c| (c.existing, c.incoming, c.resolution))
+        .collect();
+    assert_eq!(
+        resolutions,
+        [
+            (
+                copies[0].clone(),
+                copies[1].clone(),
+                ConflictResolution::Refused
+            ),
+            (
+                copies[0].clone(),
+                copies[1].clone(),
+                ConflictResolution::KeptExisting
+            ),
+            (
+                copies[0].clone(),
+                copies[1].clone(),
+                ConflictResolution::KeptIncoming
+            ),
+        ]
+    );
+    assert!(mgr.take_conflicts().is_empty());
+}
@@ -0,0 +1,28 @@
+mod common;
+
+use common::plugin_upper;
+use plugin_interface::{DisabledPolicy, PluginCallError, PluginManager, PluginTrait};
+
+#[test]
+fn disabled_plugins_are_not_called() {
+    let Some(lib) = plugin_upper() else { return };
+    let mut mgr = PluginManager::new();
+    let handles = mgr
+        .load_library(&lib, PluginTrait::Transformer)
+        .expect("load as Transformer");
+    let id = handles[0].id();
+    let proxy = handles[0].as_transformer().unwrap();
+
+    assert_eq!(mgr.is_enabled(id), Some(true));
+    assert!(mgr.set_enabled(id, false));
+    assert_eq!(mgr.is_enabled(id), Some(false));
+    assert_eq!(proxy.try_transform("hi"), Err(PluginCallError::Disabled));
+
+    mgr.set_disabled_policy(DisabledPolicy::Skip);
+    assert_eq!(proxy.try_transform("hi"), Ok(String::new()));
+
+    assert!(mgr.set_enabled(id, true));
+    assert_eq!(proxy.try_transform("hi"), Ok("HI".to_string()));
+    assert!(mgr.stats().iter().all(|s| s.calls <= 1));
+    assert_eq!(mgr.is_enabled(plugin_interface::PluginId(0)), None);
+}
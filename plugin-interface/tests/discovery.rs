@@ -0,0 +1,58 @@
+mod common;
+
+use common::built_plugin;
+use plugin_interface::{DiscoveryPolicy, PluginLoadError, PluginManager, PluginTrait};
+
+#[test]
+fn extra_extensions_are_discovered_once_the_policy_names_them() {
+    let Some(built) = built_plugin("plugin_a") else {
+        return;
+    };
+    let dir = tempfile::tempdir().expect("tmpdir");
+    std::fs::copy(&built, dir.path().join("greeter.plugin")).unwrap();
+
+    let mut mgr = PluginManager::new();
+    let default = mgr.load_plugins(dir.path(), PluginTrait::Greeter);
+    assert!(matches!(default, Err(PluginLoadError::NoRegistrations)));
+
+    mgr.set_discovery_policy(DiscoveryPolicy::new().with_extension("plugin"));
+    let handles = mgr
+        .load_plugins(dir.path(), PluginTrait::Greeter)
+        .expect("load");
+    assert_eq!(handles[0].path(), dir.path().join("greeter.plugin"));
+}
+
+#[test]
+fn versioned_names_and_prefixes() {
+    let Some(built) = built_plugin("plugin_a") else {
+        return;
+    };
+    let dir = tempfile::tempdir().expect("tmpdir");
+    let versioned = dir.path().join(format!(
+        "myapp_greeter.{}.1.2",
+        std::env::consts::DLL_EXTENSION
+    ));
+    std::fs::copy(&built, &versioned).unwrap();
+    std::fs::copy(
+        &built,
+        dir.path()
+            .join(format!("other.{}", std::env::consts::DLL_EXTENSION)),
+    )
+    .unwrap();
+
+    let mut mgr = PluginManager::new();
+    mgr.set_discovery_policy(
+        DiscoveryPolicy::new()
+            .with_prefix("myapp_")
+            .with_versioned_names(true),
+    );
+    let report = mgr.validate(dir.path(), PluginTrait::Greeter).unwrap();
+    assert_eq!(report.files.len(), 1);
+    assert!(report.is_ok(), "{:?}", report);
+
+    let handles = mgr
+        .load_plugins(dir.path(), PluginTrait::Greeter)
+        .expect("load");
+    assert_eq!(mgr.list().len(), 1);
+    assert_eq!(handles[0].path(), versioned);
+}
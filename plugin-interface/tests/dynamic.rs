@@ -0,0 +1,44 @@
+#![cfg(feature = "dynamic")]
+
+mod common;
+
+use common::plugin_upper;
+use plugin_interface::{PluginCallError, PluginManager, PluginTrait};
+use serde_json::json;
+
+#[test]
+fn calls_methods_by_name() {
+    let Some(lib) = plugin_upper() else { return };
+    let mut mgr = PluginManager::new();
+    let handles = mgr
+        .load_library(&lib, PluginTrait::Transformer)
+        .expect("load as Transformer");
+    let handle = &handles[0];
+
+    assert_eq!(
+        handle.call_dynamic("transform", json!(["abc"])),
+        Ok(json!("ABC"))
+    );
+    assert_eq!(
+        handle.call_dynamic("transform", json!({ "input": "x\u{0}y" })),
+        Ok(json!("X\u{0}Y"))
+    );
+    assert_eq!(
+        handle.call_dynamic("describe", json!(null)),
+        Ok(json!("uppercases its input"))
+    );
+    assert_eq!(
+        handle.call_dynamic("shout", json!([])),
+        Err(PluginCallError::Unsupported)
+    );
+    assert_eq!(
+        handle.call_dynamic("transform", json!([1])),
+        Err(PluginCallError::InvalidArguments(
+            "arguments must be strings".to_string()
+        ))
+    );
+
+    for h in handles {
+        h.close().expect("close failed");
+    }
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use common::built_plugin;
+use plugin_interface::{Features, PluginLoadError, PluginManager, PluginTrait, HOST_FEATURES};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[test]
+fn events_travel_between_host_and_plugins() {
+    let Some(upper) = built_plugin("plugin_upper") else {
+        return;
+    };
+    let mut mgr = PluginManager::new();
+    let replies = Arc::new(Mutex::new(Vec::new()));
+    let sink = replies.clone();
+    mgr.event_bus()
+        .subscribe("upper.reply", move |_, payload| {
+            sink.lock()
+                .unwrap()
+                .push(String::from_utf8_lossy(payload).into_owned())
+        })
+        .unwrap();
+
+    let handles = mgr
+        .load_library(&upper, PluginTrait::Transformer)
+        .expect("load plugin-upper");
+    mgr.event_bus().publish("upper.request", b"hello").unwrap();
+    assert!(mgr.event_bus().flush(Duration::from_secs(5)));
+    assert_eq!(replies.lock().unwrap().as_slice(), ["HELLO"]);
+
+    // The plugin's subscription goes away with its library.
+    drop(handles);
+    mgr.unload_by_path(&upper).expect("unload plugin-upper");
+    mgr.event_bus().publish("upper.request", b"again").unwrap();
+    assert!(mgr.event_bus().flush(Duration::from_secs(5)));
+    assert_eq!(replies.lock().unwrap().as_slice(), ["HELLO"]);
+}
+
+#[test]
+fn plugins_learn_and_require_host_features() {
+    let Some(upper) = built_plugin("plugin_upper") else {
+        return;
+    };
+    // plugin-upper requires request contexts.
+    let mut mgr = PluginManager::new();
+    mgr.set_host_features(HOST_FEATURES.difference(Features::REQUEST_CONTEXT));
+    match mgr.load_library(&upper, PluginTrait::Transformer) {
+        Err(PluginLoadError::MissingFeatures { path, missing }) => {
+            assert_eq!(path, upper);
+            assert_eq!(missing, Features::REQUEST_CONTEXT);
+        }
+        other => panic!("expected MissingFeatures, got {:?}", other.map(|h| h.len())),
+    }
+    assert!(mgr.list().is_empty());
+
+    // Without an event bus on offer it does not subscribe.
+    mgr.set_host_features(HOST_FEATURES.difference(Features::EVENT_BUS));
+    let replies = Arc::new(Mutex::new(Vec::new()));
+    let sink = replies.clone();
+    mgr.event_bus()
+        .subscribe("upper.reply", move |_, payload| {
+            sink.lock().unwrap().push(payload.to_vec())
+        })
+        .unwrap();
+    let _handles = mgr
+        .load_library(&upper, PluginTrait::Transformer)
+        .expect("load plugin-upper");
+    mgr.event_bus().publish("upper.request", b"hello").unwrap();
+    assert!(mgr.event_bus().flush(Duration::from_secs(5)));
+    assert!(replies.lock().unwrap().is_empty());
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use common::{built_plugin, library_file_name};
+use plugin_interface::{
+    ManagerEvent, PluginLoadError, PluginManager, PluginTrait, QuarantineOptions,
+};
+
+#[test]
+fn every_subscriber_sees_the_plugin_lifecycle() {
+    let Some(lib) = built_plugin("plugin_a") else {
+        eprintln!("plugin artifacts not built; skipping");
+        return;
+    };
+    let mut mgr = PluginManager::new();
+    let (ui, audit) = (mgr.subscribe(), mgr.subscribe());
+
+    let handles = mgr.load_library(&lib, PluginTrait::Greeter).expect("load");
+    let handles = {
+        let new = mgr.reload_by_path(&lib).expect("reload");
+        drop(handles);
+        new
+    };
+    // Still in use: the manager lets go and the last handle unloads it.
+    assert_eq!(mgr.unload_by_path(&lib), Ok(None));
+    drop(handles);
+
+    let dir = tempfile::tempdir().unwrap();
+    let broken = dir.path().join(lib.file_name().unwrap());
+    std::fs::write(&broken, b"not a library").unwrap();
+    assert!(mgr.load_library(&broken, PluginTrait::Greeter).is_err());
+
+    for rx in [&ui, &audit] {
+        let events: Vec<ManagerEvent> = rx.try_iter().collect();
+        assert_eq!(events.len(), 4, "{:?}", events);
+        assert!(matches!(&events[0], ManagerEvent::Loaded(d) if d.path == lib));
+        assert!(matches!(&events[1], ManagerEvent::Reloaded(d) if d.path == lib));
+        assert!(matches!(&events[2], ManagerEvent::Unloaded { path, .. } if *path == lib));
+        assert!(
+            matches!(&events[3], ManagerEvent::Error { path: Some(p), .. } if *p == broken),
+            "{:?}",
+            events[3]
+        );
+    }
+
+    // A dropped receiver does not stop the others.
+    drop(ui);
+    let _again = mgr.load_library(&lib, PluginTrait::Greeter).expect("load");
+    assert!(matches!(audit.try_recv(), Ok(ManagerEvent::Loaded(_))));
+}
+
+#[test]
+fn artifacts_that_keep_failing_are_quarantined() {
+    let dir = tempfile::tempdir().unwrap();
+    let broken = dir.path().join(library_file_name("broken"));
+    std::fs::write(&broken, b"not a library").unwrap();
+
+    let mut mgr = PluginManager::new();
+    mgr.set_quarantine(Some(QuarantineOptions {
+        max_failures: 2,
+        subdirectory: Some(".quarantine".into()),
+    }));
+    let events = mgr.subscribe();
+    assert!(mgr.load_plugins(dir.path(), PluginTrait::Greeter).is_err());
+    assert!(mgr.quarantined_artifacts().is_empty());
+    assert!(mgr.load_plugins(dir.path(), PluginTrait::Greeter).is_err());
+
+    let moved = dir
+        .path()
+        .join(".quarantine")
+        .join(broken.file_name().unwrap());
+    let [artifact] = mgr.quarantined_artifacts() else {
+        panic!("{:?}", mgr.quarantined_artifacts());
+    };
+    assert_eq!(artifact.path, broken);
+    assert_eq!(artifact.failures, 2);
+    assert_eq!(artifact.moved_to.as_ref(), Some(&moved));
+    assert!(moved.exists() && !broken.exists());
+    let quarantined: Vec<ManagerEvent> = events
+        .try_iter()
+        .filter(|e| matches!(e, ManagerEvent::ArtifactQuarantined(_)))
+        .collect();
+    assert_eq!(
+        quarantined,
+        [ManagerEvent::ArtifactQuarantined(artifact.clone())]
+    );
+
+    // Put back, it is still skipped until released.
+    std::fs::rename(&moved, &broken).unwrap();
+    assert!(matches!(
+        mgr.load_plugins(dir.path(), PluginTrait::Greeter),
+        Err(PluginLoadError::NoRegistrations)
+    ));
+    assert!(mgr.release_quarantined(&broken).is_some());
+    assert!(mgr.load_plugins(dir.path(), PluginTrait::Greeter).is_err());
+}
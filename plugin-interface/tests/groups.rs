@@ -0,0 +1,44 @@
+mod common;
+
+use common::plugin_upper;
+use plugin_interface::{
+    sidecar_path, PluginCallError, PluginLoadError, PluginManager, PluginTrait,
+};
+
+#[test]
+fn groups_are_loaded_and_switched_together() {
+    let Some(lib) = plugin_upper() else { return };
+    let dir = tempfile::tempdir().unwrap();
+    let copy = dir.path().join(lib.file_name().unwrap());
+    std::fs::copy(&lib, &copy).unwrap();
+    std::fs::write(
+        sidecar_path(&copy),
+        "name = \"upper-grouped\"\nversion = \"0.1.0\"\ntraits = [\"Transformer\"]\ngroups = [\"editor\"]\n",
+    )
+    .unwrap();
+
+    let mut mgr = PluginManager::new();
+    assert!(matches!(
+        mgr.load_group(dir.path(), PluginTrait::Transformer, "export"),
+        Err(PluginLoadError::NoRegistrations)
+    ));
+    let handles = mgr
+        .load_group(dir.path(), PluginTrait::Transformer, "editor")
+        .expect("load group");
+    let proxy = handles[0].as_transformer().unwrap();
+
+    assert_eq!(mgr.disable_group("editor"), 1);
+    assert_eq!(proxy.try_transform("hi"), Err(PluginCallError::Disabled));
+    assert_eq!(mgr.enable_group("editor"), 1);
+    assert_eq!(proxy.try_transform("hi"), Ok("HI".to_string()));
+
+    // Handles own the library; unload it while they are still around.
+    mgr.add_to_group("export", "upper-grouped");
+    let report = mgr.unload_group("export");
+    assert_eq!(report.len(), 1);
+    assert_eq!(report[0].path, copy);
+    assert!(report[0].result.is_ok());
+    assert!(mgr.unload_group("editor").is_empty());
+    drop(proxy);
+    drop(handles);
+}
@@ -0,0 +1,48 @@
+mod common;
+
+use common::plugin_upper;
+use plugin_interface::{
+    ConfigSource, HealthCheckOptions, HealthStatus, ManagerEvent, PluginCallError, PluginManager,
+    PluginTrait, QuarantineAction,
+};
+
+#[test]
+fn unhealthy_plugins_are_quarantined() {
+    let Some(lib) = plugin_upper() else { return };
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::copy(&lib, dir.path().join(lib.file_name().unwrap())).unwrap();
+    let config = ConfigSource::from_toml("[plugin_upper]\nhealthy = false\n").unwrap();
+
+    let mut mgr = PluginManager::new();
+    let handles = mgr
+        .load_plugins_with_config(dir.path(), PluginTrait::Transformer, &config)
+        .expect("load with config");
+    let events = mgr.subscribe();
+    mgr.set_health_checks(HealthCheckOptions {
+        max_failures: 2,
+        ..HealthCheckOptions::default()
+    });
+    assert_eq!(handles[0].health(), Ok(HealthStatus::Unhealthy));
+
+    let first = mgr.check_health();
+    assert_eq!((first[0].failures, first[0].quarantined), (1, false));
+    let second = mgr.check_health();
+    assert_eq!(second[0].id, handles[0].id());
+    assert_eq!((second[0].failures, second[0].quarantined), (2, true));
+    assert_eq!(
+        events.try_recv(),
+        Ok(ManagerEvent::Quarantined {
+            id: handles[0].id(),
+            path: handles[0].path().to_path_buf(),
+            failures: 2,
+            action: QuarantineAction::Disable,
+        })
+    );
+    assert_eq!(mgr.is_enabled(handles[0].id()), Some(false));
+    assert_eq!(
+        handles[0].as_transformer().unwrap().try_transform("hi"),
+        Err(PluginCallError::Disabled)
+    );
+    // Quarantined registrations are not asked again.
+    assert!(mgr.check_health().is_empty());
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use common::plugin_upper;
+use plugin_interface::{ConfigSource, PluginCallError, PluginManager, PluginTrait};
+
+#[test]
+fn instances_come_from_a_bounded_pool() {
+    let Some(lib) = plugin_upper() else { return };
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::copy(&lib, dir.path().join(lib.file_name().unwrap())).unwrap();
+    let config = ConfigSource::from_toml("[plugin_upper]\nsuffix = \"?\"\n").unwrap();
+
+    let mut mgr = PluginManager::new();
+    let handles = mgr
+        .load_plugins_with_config(dir.path(), PluginTrait::Transformer, &config)
+        .expect("load with config");
+    let handle = &handles[0];
+
+    let first = handle.instantiate().expect("first instance");
+    let second = handle.instantiate().expect("second instance");
+    assert_eq!(first.id(), handle.id());
+    assert!(first.as_greeter().is_none());
+    let proxy = first.as_transformer().unwrap();
+    assert_eq!(proxy.transform("a"), "A?", "instances are configured too");
+    assert_eq!(second.as_transformer().unwrap().transform("b"), "B?");
+    assert_eq!(
+        handle.instantiate().unwrap_err(),
+        PluginCallError::PoolExhausted
+    );
+
+    // The proxy keeps its instance out of the pool until it is dropped too.
+    drop(first);
+    assert!(handle.instantiate().is_err());
+    drop(proxy);
+    let again = handle.instantiate().expect("pooled instance");
+    assert_eq!(again.as_transformer().unwrap().transform("c"), "C?");
+    assert_eq!(
+        handle.as_transformer().unwrap().transform("shared"),
+        "SHARED?"
+    );
+}
@@ -7,7 +7,7 @@ use libloading::Library;
 #[test]
 fn lookup_local_test_getter_in_process() {
     // This test expects that the unit tests in this crate exported a
-    // `plugin_unmaker_counter_TestTrait_v1` symbol. However, depending on how
+    // `plugin_counters_TestTrait_v1` symbol. However, depending on how
     // cargo runs tests, that symbol might not be visible via Library::this().
     // We'll attempt to open the current process and gracefully skip if not
     // possible.
@@ -33,15 +33,15 @@ fn lookup_local_test_getter_in_process() {
     };
 
     // Now attempt to look up the test getter symbol that the unit tests
-    // export: `plugin_unmaker_counter_TestTrait_v1`.
-    let symbol_name = b"plugin_unmaker_counter_TestTrait_v1\0";
+    // export: `plugin_counters_TestTrait_v1`.
+    let symbol_name = b"plugin_counters_TestTrait_v1\0";
     unsafe {
-        match lib.get::<unsafe extern "C" fn() -> u64>(symbol_name) {
+        match lib.get::<unsafe extern "C" fn() -> plugin_interface::PluginCounters>(symbol_name) {
             Ok(f) => {
                 let v = f();
-                eprintln!("found test getter in process, value={}", v);
+                eprintln!("found test getter in process, value={:?}", v);
                 // basic sanity check
-                assert_eq!(v, 42u64);
+                assert_eq!(v.calls, 42u64);
             }
             Err(_) => {
                 eprintln!("test getter not present in process; skipping");
@@ -0,0 +1,64 @@
+mod common;
+
+use common::plugin_upper;
+use plugin_interface::{
+    CallContext, CallInterceptor, CallOutcome, PluginCallError, PluginManager, PluginTrait,
+};
+use std::ops::ControlFlow;
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn interceptors_run_around_calls() {
+    // Refuses one input and answers another without calling the plugin.
+    struct Gate;
+    impl CallInterceptor for Gate {
+        fn before(&self, ctx: &CallContext<'_>) -> ControlFlow<CallOutcome> {
+            match ctx.args {
+                ["secret"] => ControlFlow::Break(Err(PluginCallError::Unsupported)),
+                ["cached"] => ControlFlow::Break(Ok(Some("FROM CACHE".to_string()))),
+                _ => ControlFlow::Continue(()),
+            }
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct Log(Arc<Mutex<Vec<String>>>);
+    impl CallInterceptor for Log {
+        fn after(&self, ctx: &CallContext<'_>, outcome: &CallOutcome) {
+            let entry = format!("{} {:?}", ctx.method, outcome);
+            self.0.lock().unwrap().push(entry);
+        }
+    }
+
+    let Some(lib) = plugin_upper() else { return };
+    let mut mgr = PluginManager::new();
+    let handles = mgr
+        .load_library(&lib, PluginTrait::Transformer)
+        .expect("load as Transformer");
+    let proxy = handles[0].as_transformer().unwrap();
+    let log = Log::default();
+    mgr.add_interceptor(log.clone());
+    let gate = mgr.add_interceptor(Gate);
+
+    assert_eq!(proxy.try_transform("hi"), Ok("HI".to_string()));
+    assert_eq!(
+        proxy.try_transform("secret"),
+        Err(PluginCallError::Unsupported)
+    );
+    assert_eq!(proxy.transform("cached"), "FROM CACHE");
+    assert_eq!(
+        *log.0.lock().unwrap(),
+        [
+            "transform Ok(Some(\"HI\"))",
+            "transform Err(Unsupported)",
+            "transform Ok(Some(\"FROM CACHE\"))",
+        ]
+    );
+    let stats = mgr.stats();
+    let stats = stats.iter().find(|s| s.id == handles[0].id()).unwrap();
+    assert_eq!(stats.calls, 1, "skipped calls are not counted");
+
+    assert!(mgr.remove_interceptor(gate));
+    assert!(!mgr.remove_interceptor(gate));
+    assert_eq!(proxy.try_transform("secret"), Ok("SECRET".to_string()));
+}
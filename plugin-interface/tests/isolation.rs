@@ -0,0 +1,239 @@
+#![cfg(all(feature = "isolation", unix))]
+
+mod common;
+
+use common::built_plugin;
+use plugin_interface::{
+    ErrorBudget, IsolationOptions, ManagerEvent, PluginCallError, PluginManager, PluginTrait,
+    SharedMemoryOptions,
+};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+fn runner() -> PathBuf {
+    PathBuf::from(env!("CARGO_BIN_EXE_plugin-runner"))
+}
+
+// A stand-in runner that reports one registration and then dies on the first
+// real call, like a plugin that segfaults.
+fn crashing_runner(dir: &Path) -> PathBuf {
+    let path = dir.join("crashing-runner.sh");
+    std::fs::write(
+        &path,
+        "#!/bin/sh\n\
+         while read line; do\n\
+           case \"$line\" in\n\
+             *count*) echo '{\"status\":\"count\",\"count\":1}' ;;\n\
+             *) kill -9 $$ ;;\n\
+           esac\n\
+         done\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+// A stand-in runner that agrees to attach to the shared region and then
+// only accepts greetings whose target is in it.
+fn shared_memory_runner(dir: &Path) -> PathBuf {
+    let path = dir.join("shared-memory-runner.sh");
+    std::fs::write(
+        &path,
+        "#!/bin/sh\n\
+         while read line; do\n\
+           case \"$line\" in\n\
+             *count*) echo '{\"status\":\"count\",\"count\":1}' ;;\n\
+             *attach*) echo '{\"status\":\"done\"}' ;;\n\
+             *greet*shared*) echo '{\"status\":\"done\"}' ;;\n\
+             *) echo '{\"status\":\"error\",\"message\":\"inline\"}' ;;\n\
+           esac\n\
+         done\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+#[test]
+fn runner_rejects_files_that_are_not_plugins() {
+    let dir = tempfile::tempdir().unwrap();
+    let bogus = dir.path().join("not-a-plugin.so");
+    std::fs::write(&bogus, b"nope").unwrap();
+
+    let mut mgr = PluginManager::new();
+    let opts = IsolationOptions {
+        runner: runner(),
+        ..Default::default()
+    };
+    assert!(mgr
+        .load_isolated(&bogus, PluginTrait::Greeter, opts)
+        .is_err());
+}
+
+#[test]
+fn crashing_runner_is_restarted_up_to_the_limit() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut mgr = PluginManager::new();
+    let opts = IsolationOptions {
+        runner: crashing_runner(dir.path()),
+        auto_restart: true,
+        max_restarts: 1,
+    };
+    let proxies = mgr
+        .load_isolated(Path::new("ignored.so"), PluginTrait::Greeter, opts)
+        .expect("fake runner handshake");
+    assert_eq!(proxies.len(), 1);
+
+    // The original runner and one restart both crash; after that the proxy
+    // gives up without spawning again.
+    for _ in 0..3 {
+        match proxies[0].try_name() {
+            Err(PluginCallError::Crashed(_)) => {}
+            other => panic!("expected a crash, got {:?}", other),
+        }
+    }
+    assert_eq!(mgr.stats()[0].errors, 3);
+}
+
+#[test]
+fn crashing_calls_use_up_the_error_budget() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut mgr = PluginManager::new();
+    mgr.set_error_budget(Some(ErrorBudget {
+        max_errors: 2,
+        window: std::time::Duration::from_secs(60),
+    }));
+    let events = mgr.subscribe();
+    let opts = IsolationOptions {
+        runner: crashing_runner(dir.path()),
+        auto_restart: false,
+        max_restarts: 0,
+    };
+    let proxies = mgr
+        .load_isolated(Path::new("ignored.so"), PluginTrait::Greeter, opts)
+        .expect("fake runner handshake");
+    let id = mgr.stats()[0].id;
+
+    assert!(matches!(
+        proxies[0].try_name(),
+        Err(PluginCallError::Crashed(_))
+    ));
+    assert_eq!(mgr.is_enabled(id), Some(true));
+    assert!(matches!(
+        proxies[0].try_name(),
+        Err(PluginCallError::Crashed(_))
+    ));
+    assert_eq!(mgr.is_enabled(id), Some(false));
+    assert_eq!(proxies[0].try_name(), Err(PluginCallError::Disabled));
+    let exceeded: Vec<_> = events
+        .try_iter()
+        .filter_map(|e| match e {
+            ManagerEvent::ErrorBudgetExceeded { id, errors, .. } => Some((id, errors)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(exceeded, [(id, 2)]);
+
+    // Turned back on, it has its whole budget again.
+    assert!(mgr.set_enabled(id, true));
+    assert!(proxies[0].try_name().is_err());
+    assert_eq!(mgr.is_enabled(id), Some(true));
+}
+
+#[test]
+fn built_plugins_answer_from_the_runner() {
+    // plugin-a prints to stdout, which must not corrupt the protocol.
+    for name in ["plugin_multi", "plugin_a"] {
+        let Some(lib) = built_plugin(name) else {
+            continue;
+        };
+
+        let mut mgr = PluginManager::new();
+        let opts = IsolationOptions {
+            runner: runner(),
+            ..Default::default()
+        };
+        let proxies = mgr
+            .load_isolated(&lib, PluginTrait::Greeter, opts)
+            .unwrap_or_else(|e| panic!("load {} in a runner: {:?}", name, e));
+        assert!(!proxies.is_empty());
+        for p in &proxies {
+            assert!(!p.try_name().unwrap().is_empty());
+            p.try_greet("isolation").unwrap();
+        }
+    }
+}
+
+#[test]
+fn plugins_are_probed_before_loading() {
+    use plugin_interface::ProbeOptions;
+
+    let Some(lib) = built_plugin("plugin_upper") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    let copy = dir.path().join(lib.file_name().unwrap());
+    std::fs::copy(&lib, &copy).unwrap();
+
+    // A runner that dies like a plugin crashing in its constructor.
+    let crashing = dir.path().join("crashing-probe.sh");
+    std::fs::write(&crashing, "#!/bin/sh\nkill -9 $$\n").unwrap();
+    std::fs::set_permissions(&crashing, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let mut mgr = PluginManager::new();
+    mgr.set_probe(Some(ProbeOptions {
+        runner: crashing,
+        ..Default::default()
+    }));
+    assert!(mgr
+        .load_plugins(dir.path(), PluginTrait::Transformer)
+        .is_err());
+    let failures = mgr.take_probe_failures();
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].path, copy);
+    assert!(failures[0].reason.contains("signal"), "{}", failures[0]);
+    assert!(mgr.list().is_empty());
+
+    mgr.set_probe(Some(ProbeOptions {
+        runner: runner(),
+        ..Default::default()
+    }));
+    let handles = mgr
+        .load_plugins(dir.path(), PluginTrait::Transformer)
+        .expect("probe passes");
+    assert_eq!(handles.len(), 1);
+    assert!(mgr.take_probe_failures().is_empty());
+}
+
+#[test]
+fn large_payloads_go_through_shared_memory() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut mgr = PluginManager::new();
+    mgr.set_shared_memory(Some(SharedMemoryOptions {
+        capacity: 1 << 20,
+        threshold: 8,
+    }));
+    let opts = IsolationOptions {
+        runner: shared_memory_runner(dir.path()),
+        ..Default::default()
+    };
+    let proxies = mgr
+        .load_isolated(Path::new("ignored.so"), PluginTrait::Greeter, opts)
+        .expect("fake runner handshake");
+    assert_eq!(proxies[0].try_greet("in shared memory"), Ok(()));
+    // Below the threshold the target is sent inline.
+    assert_eq!(proxies[0].try_greet("inline"), Err(PluginCallError::Failed));
+
+    let Some(lib) = built_plugin("plugin_multi") else {
+        return;
+    };
+    let opts = IsolationOptions {
+        runner: runner(),
+        ..Default::default()
+    };
+    let proxies = mgr
+        .load_isolated(&lib, PluginTrait::Greeter, opts)
+        .expect("load plugin_multi in a runner");
+    for p in &proxies {
+        p.try_greet("shared memory").unwrap();
+    }
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use common::{built_plugin, copy_into};
+use plugin_interface::{PluginLoadError, PluginManager, PluginTrait};
+use std::fs;
+
+#[test]
+fn indexed_plugins_open_on_first_lookup_with_their_dependencies() {
+    let (Some(multi), Some(a)) = (built_plugin("plugin_multi"), built_plugin("plugin_a")) else {
+        eprintln!("plugin artifacts not built; skipping");
+        return;
+    };
+    let dir = tempfile::tempdir().expect("tmpdir");
+    let (multi, a) = (copy_into(dir.path(), &multi), copy_into(dir.path(), &a));
+    fs::write(
+        dir.path().join("multi.plugin.toml"),
+        format!(
+            "name = \"plugin-multi\"\nversion = \"0.1.0\"\ntraits = [\"Greeter\"]\nlibrary = {:?}\n\n[dependencies]\nplugin-a = \"0.1\"\n",
+            multi.file_name().unwrap()
+        ),
+    )
+    .expect("write manifest");
+    fs::write(
+        dir.path().join("a.plugin.toml"),
+        format!(
+            "name = \"plugin-a\"\nversion = \"0.1.0\"\ntraits = [\"Greeter\"]\nlibrary = {:?}\n",
+            a.file_name().unwrap()
+        ),
+    )
+    .expect("write manifest");
+
+    let mut mgr = PluginManager::new();
+    assert_eq!(
+        mgr.index_plugins(dir.path(), PluginTrait::Greeter).unwrap(),
+        2
+    );
+    assert!(mgr.stats().is_empty(), "indexing must not open anything");
+
+    let greeter = mgr.greeter("plugin-multi").unwrap().expect("greeter");
+    greeter.greet("lazy");
+    // plugin-a was opened as a dependency and stays loaded with plugin-multi.
+    assert_eq!(mgr.stats().len(), 3);
+    assert_eq!(mgr.plugin_handles("plugin-a").unwrap().len(), 1);
+
+    drop(greeter);
+    assert!(
+        mgr.stats().is_empty(),
+        "dropping the last proxy unloads both"
+    );
+
+    // A later lookup opens the plugin again.
+    assert_eq!(mgr.plugin_handles("plugin-multi").unwrap().len(), 2);
+    assert_eq!(mgr.indexed_plugins().len(), 2);
+}
+
+#[test]
+fn unknown_names_are_reported() {
+    let mut mgr = PluginManager::new();
+    assert!(matches!(
+        mgr.plugin_handles("nope"),
+        Err(PluginLoadError::UnknownPlugin(name)) if name == "nope"
+    ));
+}
@@ -0,0 +1,40 @@
+#![cfg(feature = "leak-check")]
+
+mod common;
+
+use common::built_plugin;
+use plugin_interface::{LeakReport, PluginManager, PluginTrait};
+
+#[test]
+fn unloading_a_plugin_releases_everything_it_allocated() {
+    let Some(lib) = built_plugin("plugin_a") else {
+        eprintln!("plugin artifacts not built; skipping");
+        return;
+    };
+    // A second reference keeps the library mapped after the manager closes
+    // it, so its counters can still be read.
+    let raw = unsafe { libloading::Library::new(&lib) }.expect("open");
+    let report = || unsafe {
+        let report_fn = raw
+            .get::<unsafe extern "C" fn(*mut LeakReport) -> bool>(b"plugin_leak_report_v1\0")
+            .expect("report symbol");
+        let mut report = LeakReport::default();
+        report_fn(&mut report).then_some(report)
+    };
+    if report().is_none() {
+        eprintln!("plugin built without leak-check; skipping");
+        return;
+    }
+
+    let mut mgr = PluginManager::new();
+    let handles = mgr.load_library(&lib, PluginTrait::Greeter).expect("load");
+    let proxy = handles[0].as_greeter().expect("greeter");
+    proxy.greet("leaks");
+    assert_eq!(proxy.name(), "MyGreeter");
+    drop((proxy, handles));
+    assert!(mgr.unload_all().iter().all(|r| r.result.is_ok()));
+
+    let report = report().unwrap();
+    assert!(report.registrations.made >= 1);
+    assert!(report.is_balanced(), "{report}");
+}
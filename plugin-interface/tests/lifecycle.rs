@@ -0,0 +1,33 @@
+mod common;
+
+use common::built_plugin;
+use plugin_interface::{HostServices, PluginManager, PluginTrait};
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn manager_calls_on_load_and_on_unload() {
+    let Some(lib) = built_plugin("plugin_a") else {
+        return;
+    };
+    let logged = Arc::new(Mutex::new(Vec::new()));
+    let sink = logged.clone();
+    let services =
+        HostServices::new().with_logger(move |_, msg| sink.lock().unwrap().push(msg.to_string()));
+    let mut mgr = PluginManager::with_host_services(services);
+
+    let handles = mgr
+        .load_library(&lib, PluginTrait::Greeter)
+        .expect("load plugin-a");
+    let loaded = format!(
+        "plugin-a: MyGreeter loaded by host {}",
+        env!("CARGO_PKG_VERSION")
+    );
+    assert_eq!(logged.lock().unwrap().as_slice(), [loaded.as_str()]);
+
+    drop(handles);
+    mgr.unload_by_path(&lib).expect("unload plugin-a");
+    assert_eq!(
+        logged.lock().unwrap().as_slice(),
+        [loaded.as_str(), "plugin-a: MyGreeter unloading"]
+    );
+}
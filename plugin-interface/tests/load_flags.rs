@@ -0,0 +1,81 @@
+use plugin_interface::{LoadFlags, PluginManager, PluginTrait};
+
+#[cfg(target_os = "linux")]
+#[test]
+fn global_flag_exposes_the_plugins_symbols_to_later_libraries() {
+    let exe = std::env::current_exe().expect("current exe");
+    let target_dir = exe.parent().and_then(|p| p.parent()).expect("target dir");
+    let lib = target_dir.join("libplugin_a.so");
+    if !lib.exists() {
+        eprintln!("plugin artifact not found at {:?}; skipping", lib);
+        return;
+    }
+    let visible = || {
+        let symbol = c"plugin_register_all_Greeter_v3";
+        !unsafe { libc::dlsym(libc::RTLD_DEFAULT, symbol.as_ptr()) }.is_null()
+    };
+
+    let mut mgr = PluginManager::new();
+    assert_eq!(mgr.load_flags(), LoadFlags::default());
+    let mut flags = LoadFlags {
+        lazy: false,
+        ..LoadFlags::default()
+    };
+    mgr.set_load_flags(flags);
+    let local = mgr.load_library(&lib, PluginTrait::Greeter).expect("load");
+    assert!(!visible());
+
+    flags.global = true;
+    mgr.set_load_flags(flags);
+    let global = mgr
+        .load_library(&lib, PluginTrait::Greeter)
+        .expect("load again");
+    assert!(visible());
+    assert_eq!(global[0].as_greeter().unwrap().name(), "MyGreeter");
+    drop((local, global));
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+#[test]
+fn own_namespace_gives_each_load_a_separate_copy() {
+    let exe = std::env::current_exe().expect("current exe");
+    let target_dir = exe.parent().and_then(|p| p.parent()).expect("target dir");
+    let built = target_dir.join("libplugin_a.so");
+    if !built.exists() {
+        eprintln!("plugin artifact not found at {:?}; skipping", built);
+        return;
+    }
+    // a path of its own, so the other test's loads don't count
+    let dir = tempfile::tempdir().expect("tmpdir");
+    let lib = dir.path().join("libnamespaced.so");
+    std::fs::copy(&built, &lib).unwrap();
+    let mappings = || {
+        std::fs::read_to_string("/proc/self/maps")
+            .unwrap()
+            .lines()
+            .filter(|l| {
+                l.ends_with(lib.to_str().unwrap())
+                    && l.split_whitespace().nth(2) == Some("00000000")
+            })
+            .count()
+    };
+
+    let mut mgr = PluginManager::new();
+    mgr.set_load_flags(LoadFlags {
+        own_namespace: true,
+        ..LoadFlags::default()
+    });
+    let first = mgr.load_library(&lib, PluginTrait::Greeter).expect("load");
+    let second = mgr
+        .load_library(&lib, PluginTrait::Greeter)
+        .expect("load again");
+    assert!(first[0].has_own_namespace() && second[0].has_own_namespace());
+    // the same file, mapped once per namespace
+    assert_eq!(mappings(), 2);
+    for handles in [&first, &second] {
+        let greeter = handles[0].as_greeter().unwrap();
+        assert_eq!(greeter.name(), "MyGreeter");
+        greeter.greet("namespace");
+    }
+    drop((first, second));
+}
@@ -1,4 +1,7 @@
-use plugin_interface::{PluginManager, PluginTrait};
+mod common;
+
+use common::{built_plugin, copy_into, library_file_name};
+use plugin_interface::{Greeter, PluginLoadError, PluginManager, PluginTrait, Transformer};
 use std::path::PathBuf;
 
 #[test]
@@ -31,3 +34,560 @@ fn manager_loads_plugins_and_unloads() {
         h.close().expect("close failed");
     }
 }
+
+#[test]
+fn records_negotiated_abi_level_per_plugin() {
+    // plugin-a exports ABI level 3, plugin-multi level 2 and plugin-b level
+    // 5, with the host version.
+    for (name, abi) in [("plugin_a", 3), ("plugin_multi", 2), ("plugin_b", 5)] {
+        let Some(lib) = built_plugin(name) else {
+            continue;
+        };
+
+        let mut mgr = PluginManager::new();
+        let handles = mgr
+            .load_library(&lib, PluginTrait::Greeter)
+            .unwrap_or_else(|e| panic!("load {}: {:?}", name, e));
+        for h in handles {
+            assert_eq!(h.abi_version(), abi, "{}", name);
+            h.close().expect("close failed");
+        }
+    }
+}
+
+#[test]
+fn parallel_loading_matches_sequential_order() {
+    let (Some(multi), Some(a)) = (built_plugin("plugin_multi"), built_plugin("plugin_a")) else {
+        return;
+    };
+    let sources = [multi, a];
+
+    // Each directory gets its own copies so both managers map separate
+    // images; hard links would resolve to the already-mapped library.
+    let load = |threads: usize| {
+        let dir = tempfile::tempdir().expect("tmpdir");
+        for i in 0..4 {
+            let src = &sources[i % sources.len()];
+            let dst = dir.path().join(library_file_name(&format!("copy{}", i)));
+            std::fs::copy(src, dst).expect("copy artifact");
+        }
+        let mut mgr = PluginManager::new();
+        mgr.set_load_parallelism(threads);
+        let handles = mgr
+            .load_plugins(dir.path(), PluginTrait::Greeter)
+            .expect("load");
+        let loaded: Vec<(u32, String)> = handles
+            .iter()
+            .map(|h| (h.abi_version(), h.as_greeter().unwrap().name()))
+            .collect();
+        for h in handles {
+            h.close().expect("close failed");
+        }
+        loaded
+    };
+
+    let sequential = load(1);
+    assert_eq!(sequential.len(), 6);
+    assert_eq!(load(4), sequential);
+}
+
+#[test]
+fn lists_and_finds_loaded_plugins() {
+    let Some(lib) = built_plugin("plugin_multi") else {
+        return;
+    };
+
+    let mut mgr = PluginManager::new();
+    let handles = mgr.load_library(&lib, PluginTrait::Greeter).expect("load");
+
+    let listed = mgr.list();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].path, lib);
+    assert_eq!(listed[0].trait_id, PluginTrait::Greeter);
+    // GreeterTwo's registration is renamed in its `#[plugin_impl]`. The
+    // order follows the plugin's link order, which is not fixed.
+    let mut names = listed[0].registrations.clone();
+    names.sort();
+    assert_eq!(names, ["GreeterOne", "greeter-two"]);
+    assert_eq!(listed[0].abi_version, 2);
+
+    let two = mgr.find_by_name("greeter-two").expect("greeter-two");
+    assert!(handles.iter().any(|h| h.id() == two.id()));
+    assert_eq!(two.registration_name().as_deref(), Some("greeter-two"));
+    assert!(mgr.find_by_name("GreeterTwo").is_none());
+    assert_eq!(mgr.find_by_trait(PluginTrait::Greeter).len(), 2);
+    // Listing reads names without counting them as plugin calls.
+    assert!(mgr.stats().iter().all(|s| s.calls == 0));
+    assert_eq!(two.as_greeter().unwrap().name(), "GreeterTwo");
+
+    drop(two);
+    drop(handles);
+    assert!(mgr.list().is_empty());
+}
+
+#[test]
+fn lists_implementations_without_registering_them() {
+    let Some(lib) = built_plugin("plugin_multi") else {
+        return;
+    };
+
+    let mut mgr = PluginManager::new();
+    let mut names = mgr
+        .list_impls(&lib, PluginTrait::Greeter)
+        .expect("list impls");
+    names.sort();
+    assert_eq!(names, ["GreeterOne", "greeter-two"]);
+    assert!(mgr.list().is_empty());
+    // The crate aggregates no Transformer implementations.
+    assert!(matches!(
+        mgr.list_impls(&lib, PluginTrait::Transformer),
+        Err(PluginLoadError::Lib(_))
+    ));
+
+    // A loaded library is asked directly, in register_all's order.
+    let handles = mgr.load_library(&lib, PluginTrait::Greeter).expect("load");
+    let registered: Vec<String> = handles
+        .iter()
+        .filter_map(|h| h.registration_name())
+        .collect();
+    assert_eq!(
+        mgr.list_impls(&lib, PluginTrait::Greeter)
+            .expect("list impls"),
+        registered
+    );
+}
+
+#[test]
+fn filtered_loads_make_only_the_accepted_registrations() {
+    let Some(lib) = built_plugin("plugin_multi") else {
+        return;
+    };
+    // A private copy, so the counters are not shared with other tests.
+    let dir = tempfile::tempdir().expect("tmpdir");
+    std::fs::copy(&lib, dir.path().join(lib.file_name().unwrap())).expect("copy plugin");
+
+    let mut mgr = PluginManager::new();
+    let handles = mgr
+        .load_plugins_filtered(dir.path(), PluginTrait::Greeter, |name| {
+            name == "greeter-two"
+        })
+        .expect("load filtered");
+    assert_eq!(handles.len(), 1);
+    assert_eq!(
+        handles[0].registration_name().as_deref(),
+        Some("greeter-two")
+    );
+    handles[0].as_greeter().expect("greeter").greet("filter");
+
+    let counters = handles
+        .into_iter()
+        .next()
+        .unwrap()
+        .close()
+        .expect("close")
+        .expect("counters");
+    assert_eq!(
+        (
+            counters.registrations_created,
+            counters.registrations_destroyed
+        ),
+        (1, 1)
+    );
+
+    // Nothing accepted: nothing to load.
+    let mut mgr = PluginManager::new();
+    assert!(matches!(
+        mgr.load_plugins_filtered(dir.path(), PluginTrait::Greeter, |_| false),
+        Err(PluginLoadError::NoRegistrations)
+    ));
+}
+
+#[test]
+fn handles_come_by_priority_then_name() {
+    let (Some(multi), Some(a)) = (built_plugin("plugin_multi"), built_plugin("plugin_a")) else {
+        return;
+    };
+    // greeter-two declares priority 10; plugin-a's manifest raises its
+    // registration above it.
+    let dir = tempfile::tempdir().expect("tmpdir");
+    let multi = copy_into(dir.path(), &multi);
+    let a = copy_into(dir.path(), &a);
+    std::fs::write(
+        dir.path().join("multi.plugin.toml"),
+        format!(
+            "name = \"plugin-multi\"\nversion = \"0.1.0\"\ntraits = [\"Greeter\"]\nlibrary = {:?}\n",
+            multi.file_name().unwrap()
+        ),
+    )
+    .expect("write manifest");
+    std::fs::write(
+        dir.path().join("a.plugin.toml"),
+        format!(
+            "name = \"plugin-a\"\nversion = \"0.1.0\"\ntraits = [\"Greeter\"]\nlibrary = {:?}\npriority = 20\n",
+            a.file_name().unwrap()
+        ),
+    )
+    .expect("write manifest");
+
+    let mut mgr = PluginManager::new();
+    let handles = mgr
+        .load_plugins(dir.path(), PluginTrait::Greeter)
+        .expect("load");
+    let priorities: Vec<i32> = handles.iter().map(|h| h.priority()).collect();
+    assert_eq!(priorities, [20, 10, 0]);
+    assert_eq!(
+        handles[0].manifest().map(|m| m.name.as_str()),
+        Some("plugin-a")
+    );
+    assert_eq!(
+        handles[1].registration_name().as_deref(),
+        Some("greeter-two")
+    );
+    assert_eq!(
+        handles[2].registration_name().as_deref(),
+        Some("GreeterOne")
+    );
+
+    let ordered: Vec<_> = mgr
+        .iter_by_priority(PluginTrait::Greeter)
+        .map(|h| h.id())
+        .collect();
+    assert_eq!(ordered, handles.iter().map(|h| h.id()).collect::<Vec<_>>());
+    assert_eq!(mgr.iter_by_priority(PluginTrait::Transformer).count(), 0);
+}
+
+#[test]
+fn strict_mode_reports_libraries_that_would_be_skipped() {
+    let Some(a) = built_plugin("plugin_a") else {
+        return;
+    };
+    // plugin-a only registers greeters.
+    let dir = tempfile::tempdir().expect("tmpdir");
+    let a = copy_into(dir.path(), &a);
+    std::fs::write(
+        dir.path().join("a.plugin.toml"),
+        format!(
+            "name = \"plugin-a\"\nversion = \"0.1.0\"\ntraits = [\"Transformer\"]\nlibrary = {:?}\n",
+            a.file_name().unwrap()
+        ),
+    )
+    .expect("write manifest");
+
+    let mut mgr = PluginManager::new();
+    assert!(matches!(
+        mgr.load_plugins(dir.path(), PluginTrait::Transformer),
+        Err(PluginLoadError::NoRegistrations)
+    ));
+
+    let mut mgr = PluginManager::new();
+    mgr.set_strict(true);
+    match mgr.load_plugins(dir.path(), PluginTrait::Transformer) {
+        Err(PluginLoadError::Anomaly { path, reason }) => {
+            assert_eq!(path, a);
+            assert!(reason.contains("Transformer"), "{}", reason);
+        }
+        other => panic!("expected an anomaly, got {:?}", other),
+    }
+    // Well-formed libraries load as before.
+    assert_eq!(
+        mgr.load_library(&a, PluginTrait::Greeter)
+            .expect("load")
+            .len(),
+        1
+    );
+}
+
+#[test]
+fn plugins_stand_in_for_built_in_trait_objects() {
+    let Some(lib) = built_plugin("plugin_multi") else {
+        return;
+    };
+
+    struct BuiltIn;
+    impl Greeter for BuiltIn {
+        fn name(&self) -> &str {
+            "built-in"
+        }
+        fn greet(&self, _target: &str) {}
+    }
+    // Host code that only knows the trait.
+    fn names(greeters: &[Box<dyn Greeter>]) -> Vec<&str> {
+        greeters
+            .iter()
+            .map(|g| {
+                g.greet("host");
+                g.name()
+            })
+            .collect()
+    }
+
+    let mut mgr = PluginManager::new();
+    let _handles = mgr.load_library(&lib, PluginTrait::Greeter).expect("load");
+    let mut greeters: Vec<Box<dyn Greeter>> = vec![Box::new(BuiltIn)];
+    greeters.extend(mgr.collect_trait_objects::<dyn Greeter>(PluginTrait::Greeter));
+    assert_eq!(names(&greeters), ["built-in", "GreeterTwo", "GreeterOne"]);
+
+    assert!(mgr
+        .collect_trait_objects::<dyn Transformer>(PluginTrait::Transformer)
+        .is_empty());
+    // Handles of another trait have no proxy of this one.
+    assert!(mgr
+        .collect_trait_objects::<dyn Transformer>(PluginTrait::Greeter)
+        .is_empty());
+}
+
+#[test]
+fn proxy_names_are_asked_for_once_per_registration() {
+    let Some(lib) = built_plugin("plugin_multi") else {
+        return;
+    };
+
+    let mut mgr = PluginManager::new();
+    let handles = mgr.load_library(&lib, PluginTrait::Greeter).expect("load");
+    let handle = handles
+        .iter()
+        .find(|h| h.registration_name().as_deref() == Some("greeter-two"))
+        .expect("greeter-two");
+    let first = handle.as_greeter().expect("greeter");
+    let second = handle.as_greeter().expect("greeter");
+    assert_eq!(first.name(), "GreeterTwo");
+    assert_eq!(first.name(), "GreeterTwo");
+    // The cache belongs to the library, so other proxies share it.
+    assert_eq!(second.name(), "GreeterTwo");
+    let calls = |mgr: &PluginManager| mgr.stats().iter().map(|s| s.calls).sum::<u64>();
+    assert_eq!(calls(&mgr), 1);
+
+    // `try_name` still asks the plugin.
+    assert_eq!(second.try_name().as_deref(), Ok("GreeterTwo"));
+    assert_eq!(calls(&mgr), 2);
+
+    // Turned off, the memoized name is not handed out either.
+    assert!(mgr.set_enabled(handle.id(), false));
+    assert_eq!(first.name(), "");
+    assert!(mgr.set_enabled(handle.id(), true));
+    assert_eq!(first.name(), "GreeterTwo");
+    assert_eq!(calls(&mgr), 2);
+}
+
+#[test]
+fn weak_handles_do_not_keep_plugins_loaded() {
+    let Some(lib) = built_plugin("plugin_a") else {
+        return;
+    };
+
+    let mut mgr = PluginManager::new();
+    let handles = mgr.load_library(&lib, PluginTrait::Greeter).expect("load");
+    let weak = handles[0].downgrade();
+    assert_eq!(weak.id(), handles[0].id());
+    assert!(weak.is_alive() && handles[0].is_alive());
+    {
+        let handle = weak.upgrade().expect("still loaded");
+        handle.as_greeter().expect("greeter").greet("weak");
+    }
+
+    // Closing through one handle ends the plugin for all of them.
+    handles[0].clone().close().expect("close");
+    assert!(handles[0].is_closed() && !handles[0].is_alive());
+    assert!(!weak.is_alive());
+    assert!(weak.upgrade().is_none());
+
+    // Nothing but the weak handle refers to a plugin that is loaded again:
+    // it unloads with its last handle.
+    drop(handles);
+    let handles = mgr
+        .load_library(&lib, PluginTrait::Greeter)
+        .expect("reload");
+    let weak = handles[0].downgrade();
+    drop(handles);
+    assert!(mgr.list().is_empty());
+    assert!(!weak.is_alive());
+    assert!(weak.upgrade().is_none());
+}
+
+#[test]
+fn unload_all_goes_dependents_first_and_leaves_live_proxies_working() {
+    let (Some(multi), Some(a)) = (built_plugin("plugin_multi"), built_plugin("plugin_a")) else {
+        return;
+    };
+    let dir = tempfile::tempdir().expect("tmpdir");
+    let multi = copy_into(dir.path(), &multi);
+    let a = copy_into(dir.path(), &a);
+    std::fs::write(
+        dir.path().join("multi.plugin.toml"),
+        format!(
+            "name = \"plugin-multi\"\nversion = \"0.1.0\"\ntraits = [\"Greeter\"]\nlibrary = {:?}\n\n[dependencies]\nplugin-a = \"0.1\"\n",
+            multi.file_name().unwrap()
+        ),
+    )
+    .expect("write manifest");
+    std::fs::write(
+        dir.path().join("a.plugin.toml"),
+        format!(
+            "name = \"plugin-a\"\nversion = \"0.1.0\"\ntraits = [\"Greeter\"]\nlibrary = {:?}\n",
+            a.file_name().unwrap()
+        ),
+    )
+    .expect("write manifest");
+
+    let mut mgr = PluginManager::new();
+    let handles = mgr
+        .load_plugins(dir.path(), PluginTrait::Greeter)
+        .expect("load");
+    let greeter = handles[0].as_greeter().expect("greeter");
+
+    let report = mgr.unload_all();
+    let order: Vec<&PathBuf> = report.iter().map(|r| &r.path).collect();
+    assert_eq!(order, [&multi, &a], "dependents are unloaded first");
+    assert!(report.iter().all(|r| matches!(r.result, Ok(None))));
+    assert!(mgr.list().is_empty());
+    assert!(mgr.unload_all().is_empty());
+
+    let err = mgr.unload_with_dependents(&a).unwrap_err();
+    assert!(err.contains("no plugin is loaded"), "{}", err);
+
+    // Handles outlive the manager; their libraries go with the last one.
+    drop(mgr);
+    greeter.greet("after unload_all");
+    drop(greeter);
+    drop(handles);
+}
+
+#[test]
+fn deferred_unload_names_the_remaining_holders() {
+    use plugin_interface::UnloadOutcome;
+
+    let Some(lib) = built_plugin("plugin_a") else {
+        return;
+    };
+
+    let mut mgr = PluginManager::new();
+    let handles = mgr.load_library(&lib, PluginTrait::Greeter).expect("load");
+    let id = handles[0].id();
+    let proxy = handles[0].as_greeter().expect("greeter");
+    let copy = handles[0].clone();
+    drop(handles);
+    assert_eq!(mgr.plugin_holders(&lib), [id, id]);
+
+    match mgr.unload_by_path_detailed(&lib).expect("unload") {
+        UnloadOutcome::Deferred(report) => {
+            assert_eq!(report.path, lib);
+            // the proxy and the cloned handle, oldest first
+            assert_eq!(report.holders, [id, id]);
+        }
+        other => panic!("expected a deferred unload, got {:?}", other),
+    }
+    drop(copy);
+    assert_eq!(mgr.plugin_holders(&lib), [id]);
+    drop(proxy);
+    assert!(mgr.plugin_holders(&lib).is_empty());
+}
+
+#[test]
+fn unload_with_deadline_waits_then_reports_or_forces() {
+    use plugin_interface::{Deadline, PluginCallError, UnloadOutcome};
+    use std::time::{Duration, Instant};
+
+    let Some(lib) = built_plugin("plugin_a") else {
+        return;
+    };
+
+    let mut mgr = PluginManager::new();
+    let handles = mgr.load_library(&lib, PluginTrait::Greeter).expect("load");
+    let id = handles[0].id();
+    let start = Instant::now();
+    let grace = Deadline::after(Duration::from_millis(50));
+    match mgr.unload_by_path_with(&lib, grace).expect("unload") {
+        UnloadOutcome::Deferred(report) => assert_eq!(report.holders, [id]),
+        other => panic!("expected a deferred unload, got {:?}", other),
+    }
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    drop(handles);
+
+    let handles = mgr
+        .load_library(&lib, PluginTrait::Greeter)
+        .expect("reload");
+    let proxy = handles[0].as_greeter().expect("greeter");
+    let forced = unsafe { Deadline::force_after(Duration::ZERO) };
+    let outcome = mgr
+        .unload_by_path_with(&lib, forced)
+        .expect("forced unload");
+    assert!(matches!(outcome, UnloadOutcome::Unloaded(_)));
+    assert!(mgr.list().is_empty());
+    assert!(mgr.plugin_holders(&lib).is_empty());
+    assert!(handles[0].is_stale());
+    assert_eq!(handles[0].registration_name(), None);
+    assert_eq!(proxy.try_greet("nobody"), Err(PluginCallError::Stale));
+    // the holders go away without unloading a second time
+    drop(proxy);
+    drop(handles);
+}
+
+#[test]
+fn index_based_helpers_keep_plugins_loaded_until_unloaded() {
+    let Some(lib) = built_plugin("plugin_a") else {
+        return;
+    };
+
+    let mut mgr = PluginManager::new();
+    let idx = mgr.load_plugin(&lib).expect("load");
+    assert_eq!(idx, 0);
+    // the manager keeps the plugin loaded without a handle on our side
+    assert_eq!(mgr.list().len(), 1);
+    mgr.call_greet(idx, "by index").expect("greet");
+    let missing = unsafe { mgr.call_plugin_function(idx, "no_such_function") };
+    assert!(missing.is_err());
+    assert!(mgr.call_greet(1, "nobody").is_err());
+
+    mgr.unload_plugin(idx).expect("unload");
+    assert!(mgr.list().is_empty());
+    assert!(mgr.unload_plugin(idx).is_err());
+
+    // unload_by_path lets go of kept plugins too
+    let idx = mgr.load_plugin(&lib).expect("load again");
+    mgr.unload_by_path(&lib).expect("unload by path");
+    assert!(mgr.list().is_empty());
+    assert!(mgr.call_greet(idx, "gone").is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn a_library_reached_through_several_paths_loads_once() {
+    let Some(built) = built_plugin("plugin_a") else {
+        return;
+    };
+
+    let dir = tempfile::tempdir().expect("tmpdir");
+    let lib = dir.path().join(library_file_name("plugin_a"));
+    std::fs::copy(&built, &lib).unwrap();
+    std::os::unix::fs::symlink(&lib, dir.path().join(library_file_name("alias"))).unwrap();
+    let other = tempfile::tempdir().expect("tmpdir");
+    std::fs::hard_link(&lib, other.path().join(library_file_name("linked"))).unwrap();
+
+    let mut mgr = PluginManager::new();
+    let handles = mgr
+        .load_plugins(dir.path(), PluginTrait::Greeter)
+        .expect("load");
+    assert_eq!(mgr.list().len(), 1);
+    let canonical = std::fs::canonicalize(&lib).unwrap();
+    assert!(handles.iter().all(|h| h.canonical_path() == canonical));
+
+    // the hard link in another directory is the same file
+    let again = mgr.load_plugins(other.path(), PluginTrait::Greeter);
+    assert!(matches!(again, Err(PluginLoadError::NoRegistrations)));
+    assert_eq!(mgr.list().len(), 1);
+    drop(handles);
+}
+
+#[test]
+fn strings_cross_the_boundary_as_pointer_and_length() {
+    let Some(lib) = built_plugin("plugin_a") else {
+        return;
+    };
+
+    let mut mgr = PluginManager::new();
+    let handles = mgr.load_library(&lib, PluginTrait::Greeter).expect("load");
+    let greeter = handles[0].as_greeter().unwrap();
+    assert_eq!(greeter.try_name().as_deref(), Ok("MyGreeter"));
+    // No CString on the way in, so nul bytes no longer panic.
+    assert_eq!(greeter.try_greet("nul\0byte"), Ok(()));
+}
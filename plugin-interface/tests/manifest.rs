@@ -0,0 +1,110 @@
+use plugin_interface::{
+    sidecar_path, Capability, ManifestError, PluginLoadError, PluginManager, PluginManifest,
+    PluginTrait,
+};
+use std::fs;
+
+const MANIFEST: &str = r#"
+name = "plugin-multi"
+version = "0.1.0"
+traits = ["Greeter"]
+min_host_abi = 1
+capabilities = ["filesystem", "spawn-threads"]
+
+[dependencies]
+plugin-a = "0.1"
+"#;
+
+#[test]
+fn parses_manifest_fields() {
+    let m = PluginManifest::parse(MANIFEST).expect("parse");
+    assert_eq!(m.name, "plugin-multi");
+    assert_eq!(m.version, "0.1.0");
+    assert!(m.provides("Greeter"));
+    assert_eq!(m.min_host_abi, 1);
+    assert_eq!(
+        m.dependencies.get("plugin-a").map(String::as_str),
+        Some("0.1")
+    );
+    assert_eq!(
+        m.capabilities,
+        [Capability::Filesystem, Capability::SpawnThreads]
+    );
+    assert!(m.validate("Greeter").is_ok());
+}
+
+#[test]
+fn rejects_manifest_requiring_newer_host() {
+    let m = PluginManifest::parse(
+        "name = \"future\"\nversion = \"9.0.0\"\ntraits = [\"Greeter\"]\nmin_host_abi = 99\n",
+    )
+    .expect("parse");
+    assert!(matches!(
+        m.validate("Greeter"),
+        Err(ManifestError::HostAbiTooOld { required: 99, .. })
+    ));
+}
+
+#[test]
+fn load_plugins_validates_manifest_before_dlopen() {
+    let tmpdir = tempfile::tempdir().expect("tmpdir");
+    let lib = tmpdir
+        .path()
+        .join(format!("libfuture.{}", std::env::consts::DLL_EXTENSION));
+    // Not a real library: if the manager tried to open it we'd get a
+    // `PluginLoadError::NotADynamicLibrary` instead of the manifest error.
+    fs::write(&lib, b"not a library").expect("write lib");
+    fs::write(
+        sidecar_path(&lib),
+        "name = \"future\"\nversion = \"9.0.0\"\ntraits = [\"Greeter\"]\nmin_host_abi = 99\n",
+    )
+    .expect("write manifest");
+
+    let mut mgr = PluginManager::new();
+    match mgr.load_plugins(tmpdir.path(), PluginTrait::Greeter) {
+        Err(PluginLoadError::Manifest { path, error }) => {
+            assert_eq!(path, lib);
+            assert!(matches!(error, ManifestError::HostAbiTooOld { .. }));
+        }
+        other => panic!("expected manifest error, got {:?}", other),
+    }
+}
+
+#[test]
+fn library_entries_stay_next_to_the_manifest() {
+    let tmpdir = tempfile::tempdir().expect("tmpdir");
+    let outside = tmpdir
+        .path()
+        .join(format!("outside.{}", std::env::consts::DLL_EXTENSION));
+    for library in [
+        outside.to_str().unwrap(),
+        "sub/libfoo.so",
+        "sub\\libfoo.so",
+        "../libfoo.so",
+        "..",
+    ] {
+        let dir = tempfile::tempdir().expect("tmpdir");
+        let manifest = dir.path().join("foo.plugin.toml");
+        fs::write(
+            &manifest,
+            format!(
+                "name = \"foo\"\nversion = \"0.1.0\"\ntraits = [\"Greeter\"]\nlibrary = {:?}\n",
+                library
+            ),
+        )
+        .expect("write manifest");
+
+        let mut mgr = PluginManager::new();
+        match mgr.load_plugins(dir.path(), PluginTrait::Greeter) {
+            Err(PluginLoadError::Manifest { path, error }) => {
+                assert_eq!(path, manifest);
+                assert!(
+                    matches!(&error, ManifestError::InvalidLibrary(l) if l == library),
+                    "{:?}",
+                    error
+                );
+            }
+            other => panic!("expected manifest error for {:?}, got {:?}", library, other),
+        }
+    }
+}
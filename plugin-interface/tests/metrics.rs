@@ -0,0 +1,29 @@
+#![cfg(feature = "metrics")]
+
+mod common;
+
+use common::plugin_upper;
+use plugin_interface::{PluginManager, PluginTrait};
+
+#[test]
+fn metrics_list_calls_per_registration() {
+    let Some(lib) = plugin_upper() else { return };
+    let mut mgr = PluginManager::new();
+    let handles = mgr
+        .load_library(&lib, PluginTrait::Transformer)
+        .expect("load as Transformer");
+    handles[0].as_transformer().unwrap().transform("hi");
+
+    let text = mgr.render_metrics();
+    let labels = format!(
+        "plugin=\"{}\",path=\"{}\",registration=\"0\"",
+        lib.file_name().unwrap().to_string_lossy(),
+        lib.display()
+    );
+    assert!(text.contains("# TYPE plugin_call_duration_seconds histogram\n"));
+    assert!(text.contains(&format!("plugin_calls_total{{{}}} 1\n", labels)));
+    assert!(text.contains(&format!(
+        "plugin_call_duration_seconds_count{{{}}} 1\n",
+        labels
+    )));
+}
@@ -0,0 +1,153 @@
+mod common;
+
+use common::{built_plugin, library_file_name};
+use plugin_interface::{
+    exports_symbol, ConfigSource, Greeter, HostInfo, ModuleLoader, ModulePlugin,
+    ModuleRegistration, PluginManager, PluginTrait, Transformer,
+};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+type Log = Arc<Mutex<Vec<String>>>;
+
+struct Recorder {
+    name: &'static str,
+    log: Log,
+}
+
+impl Greeter for Recorder {
+    fn name(&self) -> &str {
+        self.name
+    }
+    fn greet(&self, target: &str) {
+        self.log
+            .lock()
+            .unwrap()
+            .push(format!("{} {}", self.name, target));
+    }
+    fn on_load(&self, _host: &HostInfo) {
+        self.log.lock().unwrap().push(format!("load {}", self.name));
+    }
+    fn configure(&mut self, config: &str) {
+        self.log.lock().unwrap().push(format!("config {}", config));
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        self.log.lock().unwrap().push(format!("drop {}", self.name));
+    }
+}
+
+struct Reverse;
+
+impl Transformer for Reverse {
+    fn transform(&self, input: &str) -> String {
+        input.chars().rev().collect()
+    }
+    fn describe(&self) -> String {
+        "reverses its input".to_string()
+    }
+}
+
+/// Claims the libraries named `module_*`, whatever they export.
+struct TestLoader {
+    log: Log,
+}
+
+impl ModuleLoader for TestLoader {
+    fn accepts(&self, path: &Path) -> bool {
+        path.file_name()
+            .is_some_and(|n| n.to_string_lossy().contains("module_"))
+    }
+
+    fn open(&self, _path: &Path, trait_id: PluginTrait) -> Result<Vec<ModuleRegistration>, String> {
+        let greeter = |name: &'static str| ModuleRegistration {
+            name: name.to_string(),
+            plugin: ModulePlugin::Greeter(Box::new(Recorder {
+                name,
+                log: self.log.clone(),
+            })),
+        };
+        Ok(match trait_id {
+            PluginTrait::Greeter => vec![greeter("hello"), greeter("bye")],
+            PluginTrait::Transformer => vec![ModuleRegistration {
+                name: "reverse".to_string(),
+                plugin: ModulePlugin::Transformer(Box::new(Reverse)),
+            }],
+        })
+    }
+}
+
+/// A directory with plugin-upper copied in as `module_upper`.
+fn module_dir() -> Option<(tempfile::TempDir, PathBuf)> {
+    let lib = built_plugin("plugin_upper")?;
+    let dir = tempfile::tempdir().expect("tempdir");
+    let module = dir.path().join(library_file_name("module_upper"));
+    std::fs::copy(&lib, &module).expect("copy plugin");
+    Some((dir, module))
+}
+
+#[test]
+fn module_loaders_make_the_registrations_of_the_libraries_they_accept() {
+    let Some((dir, module)) = module_dir() else {
+        eprintln!("plugin artifacts not built; skipping");
+        return;
+    };
+    let log = Log::default();
+    let mut mgr = PluginManager::new();
+    mgr.add_module_loader(TestLoader { log: log.clone() });
+    let config = ConfigSource::from_toml("[module_upper]\ncolor = \"blue\"\n").unwrap();
+    let handles = mgr
+        .load_plugins_with_config(dir.path(), PluginTrait::Greeter, &config)
+        .expect("load module");
+    assert_eq!(handles.len(), 2);
+    // Equal priorities: ordered by name.
+    assert_eq!(handles[0].registration_name().as_deref(), Some("bye"));
+    assert!(handles[1].instantiate().is_err());
+    let proxy = handles[1].as_greeter().expect("greeter");
+    assert_eq!(proxy.name(), "hello");
+    proxy.greet("world");
+    let calls = |id| mgr.stats().iter().find(|s| s.id == id).map(|s| s.calls);
+    assert_eq!(calls(handles[1].id()), Some(2));
+
+    drop((proxy, handles));
+    mgr.unload_by_path(&module).expect("unload");
+    let log = log.lock().unwrap();
+    assert!(log[0].starts_with("config") && log[0].contains("blue"));
+    assert_eq!(
+        log[2..],
+        [
+            "load hello",
+            "load bye",
+            "hello world",
+            "drop hello",
+            "drop bye",
+        ]
+    );
+
+    let handles = mgr
+        .load_library(&module, PluginTrait::Transformer)
+        .expect("load as Transformer");
+    let proxy = handles[0].as_transformer().expect("transformer");
+    assert_eq!(proxy.transform("abc"), "cba");
+    assert_eq!(proxy.try_describe().as_deref(), Ok("reverses its input"));
+}
+
+#[test]
+fn libraries_no_loader_accepts_load_as_usual() {
+    let Some(lib) = built_plugin("plugin_upper") else {
+        eprintln!("plugin artifacts not built; skipping");
+        return;
+    };
+    assert!(exports_symbol(&lib, "plugin_describe_v1"));
+    assert!(!exports_symbol(&lib, "plugin_describe_v0"));
+    let mut mgr = PluginManager::new();
+    mgr.add_module_loader(TestLoader {
+        log: Log::default(),
+    });
+    let handles = mgr
+        .load_library(&lib, PluginTrait::Transformer)
+        .expect("load");
+    assert_eq!(handles[0].registration_name().as_deref(), Some("Upper"));
+}
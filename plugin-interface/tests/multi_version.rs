@@ -0,0 +1,102 @@
+//! Two versions of one plugin loaded side by side with
+//! `PluginManager::set_multi_version`.
+#![cfg(unix)]
+
+mod common;
+
+use common::library_file_name;
+use plugin_interface::{sidecar_path, PluginManager, PluginSearchPath, PluginTrait};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The C example plugin built into `dir` with a manifest naming it `name`
+/// at `version`. `None`, after saying why, if there is no C compiler.
+fn c_plugin(dir: &Path, name: &str, version: &str, dependencies: &str) -> Option<PathBuf> {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let lib = dir.join(library_file_name("plugin_c"));
+    let compiler = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(&compiler)
+        .args(["-shared", "-fPIC", "-std=c99", "-I"])
+        .arg(manifest_dir.join("include"))
+        .arg(manifest_dir.join("../examples/plugin-c/plugin.c"))
+        .arg("-o")
+        .arg(&lib)
+        .status();
+    match status {
+        Ok(status) if status.success() => {}
+        other => {
+            eprintln!(
+                "cannot build the C plugin with {} ({:?}); skipping",
+                compiler, other
+            );
+            return None;
+        }
+    }
+    std::fs::write(
+        sidecar_path(&lib),
+        format!(
+            "name = \"{}\"\nversion = \"{}\"\ntraits = [\"Transformer\"]\n[dependencies]\n{}",
+            name, version, dependencies
+        ),
+    )
+    .unwrap();
+    Some(lib)
+}
+
+#[test]
+fn versions_load_side_by_side_and_unload_separately() {
+    let dirs: Vec<_> = (0..3).map(|_| tempfile::tempdir().unwrap()).collect();
+    let Some(v1) = c_plugin(dirs[0].path(), "upper", "1.2.0", "") else {
+        return;
+    };
+    let v2 = c_plugin(dirs[1].path(), "upper", "2.0.0", "").unwrap();
+    let app = c_plugin(dirs[2].path(), "app", "1.0.0", "upper = \"^2\"\n").unwrap();
+    let search = PluginSearchPath::new()
+        .with_dir(dirs[0].path())
+        .with_dir(dirs[1].path());
+
+    // By default the second copy is shadowed by the first.
+    let mut mgr = PluginManager::new();
+    assert_eq!(
+        mgr.load_plugins(search.clone(), PluginTrait::Transformer)
+            .unwrap()
+            .len(),
+        1
+    );
+    assert_eq!(mgr.shadowed_plugins()[0].path, v2);
+    drop(mgr);
+
+    let mut mgr = PluginManager::new();
+    mgr.set_multi_version(true);
+    let handles = mgr
+        .load_plugins(search, PluginTrait::Transformer)
+        .expect("both versions load");
+    assert_eq!(handles.len(), 2);
+    assert!(mgr.shadowed_plugins().is_empty());
+    assert!(mgr.take_conflicts().is_empty());
+    let names: Vec<_> = handles.iter().filter_map(|h| h.versioned_name()).collect();
+    assert_eq!(names, ["upper-c@1.2.0", "upper-c@2.0.0"]);
+
+    let found =
+        |mgr: &PluginManager, name: &str| mgr.find_by_name(name).map(|h| h.path().to_path_buf());
+    assert_eq!(found(&mgr, "upper-c@^1"), Some(v1.clone()));
+    assert_eq!(found(&mgr, "upper-c@2.0.0"), Some(v2.clone()));
+    assert_eq!(found(&mgr, "upper-c@*"), Some(v2.clone()));
+    assert_eq!(found(&mgr, "upper-c@^3"), None);
+    assert_eq!(mgr.plugin_handles("upper@~1.2").unwrap()[0].path(), v1);
+    let transformer = mgr.find_by_name("upper-c@1.2.0").unwrap();
+    assert_eq!(
+        transformer.as_transformer().unwrap().transform("abc"),
+        "ABC"
+    );
+    drop(transformer);
+
+    // `app` needs 2.x, so only that version is held in place.
+    let _app = mgr
+        .load_library(&app, PluginTrait::Transformer)
+        .expect("dependency satisfied by 2.0.0");
+    assert!(mgr.unload_by_path(&v2).is_err());
+    mgr.unload_by_path(&v1).expect("1.2.0 unloads");
+    assert!(mgr.plugin_handles("upper@^1").is_err());
+    assert_eq!(mgr.plugin_handles("upper@^2").unwrap()[0].path(), v2);
+}
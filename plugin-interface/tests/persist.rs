@@ -0,0 +1,54 @@
+mod common;
+
+use common::plugin_upper;
+use plugin_interface::{ConfigSource, PluginCallError, PluginManager, PluginTrait};
+
+#[test]
+fn saved_state_restores_plugins_flags_and_config() {
+    let Some(lib) = plugin_upper() else { return };
+    let dir = tempfile::tempdir().unwrap();
+    let copy = dir.path().join(lib.file_name().unwrap());
+    std::fs::copy(&lib, &copy).unwrap();
+    let state = dir.path().join("plugins.state.toml");
+    let config = ConfigSource::from_toml("[plugin_upper]\nsuffix = \"!\"\n").unwrap();
+
+    {
+        let mut mgr = PluginManager::new();
+        let handles = mgr
+            .load_plugins_with_config(dir.path(), PluginTrait::Transformer, &config)
+            .expect("load with config");
+        assert!(mgr.set_enabled(handles[0].id(), false));
+        mgr.save_state(&state).expect("save state");
+    }
+
+    {
+        let mut mgr = PluginManager::new();
+        let restored = mgr.restore_state(&state).expect("restore state");
+        assert!(restored.failed.is_empty(), "{:?}", restored.failed);
+        let [handle] = restored.handles.as_slice() else {
+            panic!("{:?}", restored.handles.len());
+        };
+        assert_eq!(handle.path(), copy);
+        let proxy = handle.as_transformer().unwrap();
+        assert_eq!(proxy.try_transform("hi"), Err(PluginCallError::Disabled));
+        assert!(mgr.set_enabled(handle.id(), true));
+        assert_eq!(proxy.transform("hi"), "HI!");
+    }
+
+    // An artifact that changed since it was saved is not restored.
+    #[cfg(feature = "pinning")]
+    {
+        use plugin_interface::PluginLoadError;
+
+        let mut bytes = std::fs::read(&copy).unwrap();
+        bytes.push(0);
+        std::fs::write(&copy, bytes).unwrap();
+        let mut mgr = PluginManager::new();
+        let restored = mgr.restore_state(&state).expect("restore state");
+        assert!(restored.handles.is_empty());
+        assert!(matches!(
+            restored.failed.as_slice(),
+            [(path, PluginLoadError::DigestMismatch(_))] if *path == copy
+        ));
+    }
+}
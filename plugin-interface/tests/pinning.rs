@@ -0,0 +1,122 @@
+#![cfg(feature = "pinning")]
+
+mod common;
+
+use common::library_file_name;
+use plugin_interface::{DigestPins, PluginLoadError, PluginManager, PluginTrait, Sha256Digest};
+use std::path::{Path, PathBuf};
+
+// Digests are checked before the library is opened, so a file that passes
+// its check fails afterwards with `PluginLoadError::NotADynamicLibrary`.
+fn artifact(dir: &Path) -> PathBuf {
+    let path = dir.join(library_file_name("pinned"));
+    std::fs::write(&path, b"pinned contents").unwrap();
+    path
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().unwrap().to_string_lossy().into_owned()
+}
+
+#[test]
+fn matching_pins_let_the_artifact_through() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = artifact(dir.path());
+    let mut mgr = PluginManager::new();
+    mgr.set_digest_pins(
+        DigestPins::new().with_pin(file_name(&path), Sha256Digest::of_file(&path).unwrap()),
+    );
+
+    assert!(matches!(
+        mgr.load_plugins(dir.path(), PluginTrait::Greeter),
+        Err(PluginLoadError::NotADynamicLibrary { .. })
+    ));
+    assert!(mgr.take_digest_mismatches().is_empty());
+}
+
+#[test]
+fn mismatches_are_rejected_and_recorded() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = artifact(dir.path());
+    let pinned = Sha256Digest::of_bytes(b"what the host expected");
+    let mut mgr = PluginManager::new();
+    mgr.set_digest_pins(DigestPins::new().with_pin(path.to_string_lossy(), pinned));
+
+    match mgr.load_library(&path, PluginTrait::Greeter) {
+        Err(PluginLoadError::DigestMismatch(m)) => {
+            assert_eq!(m.expected, Some(pinned));
+            assert_eq!(m.actual, Sha256Digest::of_file(&path).unwrap());
+        }
+        other => panic!("expected a digest mismatch, got {:?}", other),
+    }
+    assert_eq!(mgr.take_digest_mismatches().len(), 1);
+    assert!(mgr.take_digest_mismatches().is_empty());
+}
+
+#[test]
+fn skipped_mismatches_leave_nothing_loaded() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = artifact(dir.path());
+    let mut mgr = PluginManager::new();
+    mgr.set_digest_pins(
+        DigestPins::new()
+            .with_pin(file_name(&path), Sha256Digest::of_bytes(b"other"))
+            .skip_mismatches(),
+    );
+
+    assert!(matches!(
+        mgr.load_plugins(dir.path(), PluginTrait::Greeter),
+        Err(PluginLoadError::NoRegistrations)
+    ));
+    let mismatches = mgr.take_digest_mismatches();
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(mismatches[0].path, path);
+}
+
+#[test]
+fn required_pins_turn_the_pins_into_an_allowlist() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = artifact(dir.path());
+    let mut mgr = PluginManager::new();
+    mgr.set_digest_pins(DigestPins::new().require_pins());
+
+    match mgr.load_library(&path, PluginTrait::Greeter) {
+        Err(PluginLoadError::DigestMismatch(m)) => assert_eq!(m.expected, None),
+        other => panic!("expected a digest mismatch, got {:?}", other),
+    }
+}
+
+#[cfg(feature = "watch")]
+#[test]
+fn watcher_reports_mismatches() {
+    use plugin_interface::{ManagerNotification, WatchNotification, WatchOptions};
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = artifact(dir.path());
+    let mut mgr = PluginManager::new();
+    mgr.set_digest_pins(
+        DigestPins::new()
+            .with_pin(file_name(&path), Sha256Digest::of_bytes(b"other"))
+            .skip_mismatches(),
+    );
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    tx.send(WatchNotification::Paths(vec![path.clone()]))
+        .unwrap();
+    drop(tx);
+
+    let mut reported = Vec::new();
+    mgr.process_watch_notifications_blocking(
+        dir.path(),
+        rx,
+        PluginTrait::Greeter,
+        WatchOptions::default(),
+        |n| {
+            if let ManagerNotification::DigestMismatch(m) = n {
+                reported.push(m.path);
+            }
+            true
+        },
+    );
+    assert_eq!(reported, [path]);
+}
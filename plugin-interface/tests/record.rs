@@ -0,0 +1,44 @@
+#![cfg(feature = "record")]
+
+mod common;
+
+use common::plugin_upper;
+use plugin_interface::{replay_calls, CallRecorder, PluginManager, PluginTrait, RecordedCall};
+
+#[test]
+fn recorded_calls_replay_against_a_build() {
+    let Some(lib) = plugin_upper() else { return };
+    let dir = tempfile::tempdir().unwrap();
+    let recording = dir.path().join("calls.jsonl");
+    let mut mgr = PluginManager::new();
+    let handles = mgr
+        .load_library(&lib, PluginTrait::Transformer)
+        .expect("load as Transformer");
+    let recorder = mgr.add_interceptor(CallRecorder::create(&recording).unwrap());
+    let proxy = handles[0].as_transformer().unwrap();
+    assert_eq!(proxy.transform("one"), "ONE");
+    assert_eq!(proxy.describe(), "uppercases its input");
+    mgr.remove_interceptor(recorder);
+    proxy.transform("not recorded");
+
+    let mut calls = RecordedCall::read_all(&recording).unwrap();
+    assert_eq!(calls.len(), 2);
+    assert_eq!(calls[0].method, "transform");
+    assert_eq!(calls[0].args, ["one"]);
+    assert_eq!(calls[0].result.as_deref(), Some("ONE"));
+    assert_eq!(calls[0].path, lib);
+
+    let replayed = replay_calls(&calls, &handles);
+    assert!(replayed.iter().all(|r| r.matches()), "{:?}", replayed);
+
+    // A build behaving differently shows up as a mismatch.
+    calls[0].result = Some("one".to_string());
+    calls[1].index = 7;
+    let replayed = replay_calls(&calls, &handles);
+    assert!(!replayed[0].matches());
+    assert_eq!(replayed[0].outcome, Ok(Some("ONE".to_string())));
+    assert_eq!(
+        replayed[1].outcome,
+        Err(plugin_interface::PluginCallError::Unsupported)
+    );
+}
@@ -0,0 +1,153 @@
+#![cfg(feature = "registry")]
+
+mod common;
+
+use common::library_file_name;
+use ed25519_dalek::{Signer, SigningKey};
+use plugin_interface::{
+    signature_path, PluginLoadError, PluginManager, PluginTrait, RegistryEntry, RegistryError,
+    RegistryIndex, RegistryOptions, Sha256Digest, SignatureError,
+};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The C example plugin, built into `dir`: small enough to hash and sign
+/// quickly in a debug build, unlike the Rust plugins. `None`, after saying
+/// why, if there is no C compiler.
+fn c_plugin(dir: &Path) -> Option<PathBuf> {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let lib = dir.join(library_file_name("plugin_c"));
+    let compiler = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(&compiler)
+        .args(["-shared", "-fPIC", "-std=c99", "-I"])
+        .arg(manifest_dir.join("include"))
+        .arg(manifest_dir.join("../examples/plugin-c/plugin.c"))
+        .arg("-o")
+        .arg(&lib)
+        .status();
+    match status {
+        Ok(status) if status.success() => Some(lib),
+        other => {
+            eprintln!(
+                "cannot build the C plugin with {} ({:?}); skipping",
+                compiler, other
+            );
+            None
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// A registry in `dir` serving `bytes` as `upper` 1.0.0 and 1.1.0, signed
+/// with `key`, and a 1.2.0 whose digest is wrong. Returns the index path.
+fn registry(dir: &Path, bytes: &[u8], key: &SigningKey) -> String {
+    let file_name = library_file_name("upper");
+    std::fs::write(dir.join(&file_name), bytes).unwrap();
+    let sha256 = Sha256Digest::of_bytes(bytes).to_string();
+    let signature = hex(&key.sign(bytes).to_bytes());
+    let entry = |version: &str| RegistryEntry {
+        name: "upper".to_string(),
+        version: version.to_string(),
+        target: plugin_interface::host_target(),
+        url: file_name.clone(),
+        sha256: sha256.clone(),
+        signature: Some(signature.clone()),
+    };
+    let index = RegistryIndex {
+        plugins: vec![
+            entry("1.0.0"),
+            entry("1.1.0"),
+            RegistryEntry {
+                sha256: Sha256Digest::of_bytes(b"something else").to_string(),
+                ..entry("1.2.0")
+            },
+        ],
+    };
+    let path = dir.join("index.json");
+    std::fs::write(&path, serde_json::to_string(&index).unwrap()).unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+fn manager_trusting(key: &SigningKey, index_url: String, dir: &Path) -> PluginManager {
+    let mut mgr = PluginManager::new();
+    mgr.trust_key(key.verifying_key().as_bytes()).unwrap();
+    mgr.set_registry(Some(RegistryOptions::new(index_url, dir)));
+    mgr
+}
+
+#[test]
+fn plugins_are_installed_verified_and_loaded() {
+    let build = tempfile::tempdir().unwrap();
+    let Some(lib) = c_plugin(build.path()) else {
+        return;
+    };
+    let served = tempfile::tempdir().unwrap();
+    let installed = tempfile::tempdir().unwrap();
+    let key = SigningKey::from_bytes(&[1; 32]);
+    let index = registry(served.path(), &std::fs::read(lib).unwrap(), &key);
+
+    let mut mgr = manager_trusting(&key, format!("file://{}", index), installed.path());
+    let handles = mgr
+        .install_from_registry("upper", "~1.1", PluginTrait::Transformer)
+        .expect("install");
+    let transformer = handles[0].as_transformer().expect("transformer");
+    assert_eq!(transformer.transform("abc"), "ABC");
+    let path = installed.path().join(library_file_name("upper"));
+    assert_eq!(handles[0].path(), path.as_path());
+    assert!(signature_path(&path).exists());
+}
+
+#[test]
+fn artifacts_must_match_their_digest_and_a_trusted_signature() {
+    let served = tempfile::tempdir().unwrap();
+    let installed = tempfile::tempdir().unwrap();
+    let key = SigningKey::from_bytes(&[1; 32]);
+    let index = registry(served.path(), b"not really a library", &key);
+    let mut mgr = manager_trusting(&key, index.clone(), installed.path());
+
+    // 1.2.0 is the newest match, and its digest is wrong.
+    match mgr.install_from_registry("upper", "^1", PluginTrait::Transformer) {
+        Err(PluginLoadError::DigestMismatch(m)) => {
+            assert_eq!(m.path, installed.path().join(library_file_name("upper")))
+        }
+        other => panic!("expected a digest mismatch, got {:?}", other),
+    }
+    match mgr.install_from_registry("upper", "^2", PluginTrait::Transformer) {
+        Err(PluginLoadError::Registry(RegistryError::NotFound { target, .. })) => {
+            assert_eq!(target, plugin_interface::host_target())
+        }
+        other => panic!("expected nothing to be found, got {:?}", other),
+    }
+
+    let mut mgr = manager_trusting(&SigningKey::from_bytes(&[2; 32]), index, installed.path());
+    match mgr.install_from_registry("upper", "=1.0.0", PluginTrait::Transformer) {
+        Err(PluginLoadError::SignatureInvalid { error, .. }) => {
+            assert!(matches!(error, SignatureError::Untrusted))
+        }
+        other => panic!("expected a signature error, got {:?}", other),
+    }
+    // Nothing that failed a check was installed.
+    assert!(std::fs::read_dir(installed.path())
+        .unwrap()
+        .next()
+        .is_none());
+}
+
+#[test]
+fn installing_needs_a_registry() {
+    let mut mgr = PluginManager::new();
+    assert!(matches!(
+        mgr.install_from_registry("upper", "^1", PluginTrait::Transformer),
+        Err(PluginLoadError::Registry(RegistryError::NotConfigured))
+    ));
+    mgr.set_registry(Some(RegistryOptions::new("index.json", "plugins")));
+    assert!(matches!(
+        mgr.install_from_registry("upper", "not a requirement", PluginTrait::Transformer),
+        Err(PluginLoadError::Registry(RegistryError::InvalidVersionReq(
+            _
+        )))
+    ));
+}
@@ -0,0 +1,221 @@
+mod common;
+
+use common::built_plugin;
+use plugin_interface::{PluginCallError, PluginLoadError, PluginManager, PluginTrait};
+use std::path::Path;
+
+#[test]
+fn reload_swaps_in_new_handles_and_stales_the_old_ones() {
+    let Some(lib) = built_plugin("plugin_a") else {
+        eprintln!("plugin artifacts not built; skipping");
+        return;
+    };
+    let mut mgr = PluginManager::new();
+    let old = mgr.load_library(&lib, PluginTrait::Greeter).expect("load");
+    let old_proxy = old[0].as_greeter().expect("greeter");
+    old_proxy.greet("one");
+    old_proxy.greet("two");
+    assert_eq!(old_proxy.save_state(), Some(2u64.to_le_bytes().to_vec()));
+
+    let new = mgr.reload_by_path(&lib).expect("reload");
+    assert_eq!(new.len(), 1);
+    assert_eq!(new[0].path(), lib);
+    assert_ne!(new[0].id(), old[0].id());
+    assert!(old[0].is_stale() && old_proxy.is_stale());
+    assert!(!new[0].is_stale());
+    assert_eq!(old_proxy.try_greet("old"), Err(PluginCallError::Stale));
+    assert_eq!(new[0].as_greeter().unwrap().try_greet("new"), Ok(()));
+    // The greeting count moved over through save_state / restore_state.
+    assert_eq!(
+        new[0].as_greeter().unwrap().save_state(),
+        Some(3u64.to_le_bytes().to_vec())
+    );
+    assert_eq!(old_proxy.save_state(), None);
+
+    // Only the new copy is listed, under the artifact's own path.
+    let listed = mgr.list();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].path, lib);
+    assert!(old[0].shadow_path().is_none());
+    let copy = new[0]
+        .shadow_path()
+        .expect("reloads use a copy")
+        .to_path_buf();
+    assert!(copy.exists());
+
+    // A second reload replaces the reloaded copy.
+    let newer = mgr.reload_by_path(&lib).expect("reload again");
+    assert!(new[0].is_stale());
+    drop((old, old_proxy, new));
+    assert!(!copy.exists(), "replaced copies are removed on unload");
+    let copy = newer[0].shadow_path().unwrap().to_path_buf();
+    drop(newer);
+    assert!(mgr.list().is_empty());
+    assert!(!copy.exists());
+
+    assert!(matches!(
+        mgr.reload_by_path(Path::new("not-loaded.so")),
+        Err(PluginLoadError::UnknownPlugin(_))
+    ));
+}
+
+#[test]
+fn staged_plugins_take_over_only_when_promoted() {
+    let Some(lib) = built_plugin("plugin_a") else {
+        eprintln!("plugin artifacts not built; skipping");
+        return;
+    };
+    let mut mgr = PluginManager::new();
+    let old = mgr.load_library(&lib, PluginTrait::Greeter).expect("load");
+    let old_proxy = old[0].as_greeter().expect("greeter");
+    old_proxy.greet("one");
+
+    // Staged, the new copy is opened but lookups still find the old one.
+    let staged = mgr.stage(&lib, PluginTrait::Greeter).expect("stage");
+    let found = mgr.find_by_trait(PluginTrait::Greeter);
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].id(), old[0].id());
+    old_proxy.greet("two");
+
+    let new = mgr.promote(staged).expect("promote");
+    assert!(old_proxy.is_stale());
+    assert_eq!(
+        new[0].as_greeter().unwrap().save_state(),
+        Some(2u64.to_le_bytes().to_vec())
+    );
+    let found = mgr.find_by_trait(PluginTrait::Greeter);
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].id(), new[0].id());
+    assert!(matches!(
+        mgr.promote(staged),
+        Err(PluginLoadError::UnknownPlugin(_))
+    ));
+
+    // A discarded plugin never takes over.
+    let again = mgr.stage(&lib, PluginTrait::Greeter).expect("stage again");
+    assert!(mgr.discard_staged(again));
+    assert!(!mgr.discard_staged(again));
+    assert!(!new[0].is_stale());
+    assert_eq!(mgr.list().len(), 1);
+}
+
+#[test]
+fn split_traffic_routes_a_share_of_calls_to_the_staged_plugin() {
+    let Some(lib) = built_plugin("plugin_a") else {
+        eprintln!("plugin artifacts not built; skipping");
+        return;
+    };
+    let mut mgr = PluginManager::new();
+    let old = mgr.load_library(&lib, PluginTrait::Greeter).expect("load");
+    let name = old[0].registration_name().expect("name");
+    let staged = mgr.stage(&lib, PluginTrait::Greeter).expect("stage");
+    assert!(matches!(
+        mgr.split_traffic(staged, "missing", 0.5),
+        Err(PluginLoadError::UnknownPlugin(_))
+    ));
+
+    let split = mgr.split_traffic(staged, &name, 0.25).expect("split");
+    assert_eq!(split.current().id(), old[0].id());
+    for _ in 0..8 {
+        split.route().as_greeter().unwrap().greet("ab");
+    }
+    let stats = split.stats();
+    assert_eq!((stats.current.routed, stats.candidate.routed), (6, 2));
+    assert_eq!(
+        (stats.current.stats.calls, stats.candidate.stats.calls),
+        (6, 2)
+    );
+
+    split.set_fraction(1.0);
+    assert_eq!(split.route().id(), split.candidate().id());
+    split.set_fraction(0.0);
+    assert_eq!(split.route().id(), old[0].id());
+
+    // Once promoted, every call goes to the new version.
+    mgr.promote(staged).expect("promote");
+    assert_eq!(split.route().id(), split.candidate().id());
+    assert_eq!(
+        mgr.find_by_name(&name).unwrap().id(),
+        split.candidate().id()
+    );
+}
+
+#[test]
+fn shadow_copies_leave_the_artifact_free() {
+    let Some(built) = built_plugin("plugin_multi") else {
+        eprintln!("plugin artifacts not built; skipping");
+        return;
+    };
+    let dir = tempfile::tempdir().expect("tmpdir");
+    let lib = dir.path().join(built.file_name().unwrap());
+    std::fs::copy(&built, &lib).expect("copy artifact");
+
+    let mut mgr = PluginManager::new();
+    mgr.set_shadow_copies(true);
+    let handles = mgr.load_library(&lib, PluginTrait::Greeter).expect("load");
+    assert_eq!(handles[0].path(), lib);
+    let copy = handles[0].shadow_path().expect("shadow copy").to_path_buf();
+    assert_ne!(copy, lib);
+
+    // The artifact can be replaced while the copy stays loaded.
+    std::fs::remove_file(&lib).expect("remove artifact");
+    assert_eq!(handles[1].as_greeter().unwrap().try_greet("copy"), Ok(()));
+
+    drop(handles);
+    assert!(!copy.exists());
+}
+
+#[cfg(feature = "watch")]
+#[test]
+fn watcher_auto_reload_reports_old_and_new_handles() {
+    use plugin_interface::{WatchEvent, WatchOptions};
+
+    let Some(lib) = built_plugin("plugin_a") else {
+        eprintln!("plugin artifacts not built; skipping");
+        return;
+    };
+    let tmpdir = tempfile::tempdir().expect("tmpdir");
+    let dest = tmpdir.path().join(lib.file_name().unwrap());
+    std::fs::copy(&lib, &dest).expect("copy plugin");
+
+    let mut mgr = PluginManager::new();
+    let _loaded = mgr.load_library(&dest, PluginTrait::Greeter).expect("load");
+
+    // Replace the artifact once the watcher is running. Like a linker, write
+    // a new file and rename it over the old one: truncating a mapped library
+    // in place would corrupt the running code.
+    let (src, target) = (lib.clone(), dest.clone());
+    std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(300));
+        let partial = target.with_extension("partial");
+        std::fs::copy(&src, &partial).expect("copy plugin");
+        std::fs::rename(&partial, &target).expect("replace plugin");
+    });
+
+    let opts = WatchOptions {
+        debounce_ms: 200,
+        auto_reload: true,
+        ..Default::default()
+    };
+    let mut reloaded = None;
+    mgr.watch_and_load_blocking(
+        tmpdir.path().to_path_buf(),
+        PluginTrait::Greeter,
+        opts,
+        |evt| match evt {
+            WatchEvent::Reloaded { path, old, new } => {
+                reloaded = Some((path, old, new));
+                false
+            }
+            _ => true,
+        },
+    );
+
+    let (path, old, new) = reloaded.expect("reload event");
+    assert_eq!(path, dest);
+    assert_eq!(old.len(), 1);
+    assert!(old[0].is_stale());
+    assert_eq!(new.len(), 1);
+    assert!(!new[0].is_stale());
+    assert_eq!(mgr.list().len(), 1);
+}
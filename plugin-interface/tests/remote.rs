@@ -0,0 +1,174 @@
+#![cfg(feature = "remote")]
+
+mod common;
+
+use common::built_plugin;
+use plugin_interface::{
+    serve_remote, HealthStatus, PluginCallError, PluginLoadError, PluginManager, PluginTrait,
+    SharedMemoryOptions,
+};
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+
+/// Serve the library at `lib` on a free local port, from a thread that
+/// runs until the test process exits.
+fn serve(lib: PathBuf) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+    let addr = listener.local_addr().unwrap().to_string();
+    std::thread::spawn(move || serve_remote(listener, &lib));
+    addr
+}
+
+#[test]
+fn remote_registrations_behave_like_local_ones() {
+    let (Some(multi), Some(upper)) = (built_plugin("plugin_multi"), built_plugin("plugin_upper"))
+    else {
+        eprintln!("plugin artifacts not built; skipping");
+        return;
+    };
+    let mut local = PluginManager::new();
+    let local_names: Vec<_> = local
+        .load_library(&multi, PluginTrait::Greeter)
+        .expect("load locally")
+        .iter()
+        .map(|h| h.registration_name())
+        .collect();
+
+    let greeters = serve(multi);
+    let transformers = serve(upper);
+    let mut mgr = PluginManager::new();
+    let handles = mgr
+        .connect_remote(&greeters, PluginTrait::Greeter)
+        .expect("connect greeters");
+    let names: Vec<_> = handles.iter().map(|h| h.registration_name()).collect();
+    assert_eq!(names, local_names);
+    let proxy = handles[1].as_greeter().expect("greeter");
+    assert!(!proxy.name().is_empty());
+    assert_eq!(proxy.try_greet("remote"), Ok(()));
+    assert_eq!(handles[1].health(), Ok(HealthStatus::Healthy));
+    let stats = mgr.stats();
+    let calls = stats.iter().find(|s| s.id == handles[1].id()).unwrap();
+    assert_eq!(calls.calls, 2);
+
+    let handles = mgr
+        .connect_remote(&transformers, PluginTrait::Transformer)
+        .expect("connect transformers");
+    let proxy = handles[0].as_transformer().expect("transformer");
+    assert_eq!(proxy.try_transform("abc").as_deref(), Ok("ABC"));
+    assert_eq!(proxy.try_describe().as_deref(), Ok("uppercases its input"));
+    drop((proxy, handles));
+
+    let path = PathBuf::from(format!("remote:{}", transformers));
+    mgr.unload_by_path(&path).expect("unload");
+    assert!(mgr.find_by_trait(PluginTrait::Transformer).is_empty());
+}
+
+fn write_frame(out: &mut impl Write, json: &str) {
+    out.write_all(&(json.len() as u32).to_be_bytes()).unwrap();
+    out.write_all(json.as_bytes()).unwrap();
+}
+
+fn read_frame(input: &mut impl Read) -> String {
+    let mut len = [0u8; 4];
+    input.read_exact(&mut len).unwrap();
+    let mut bytes = vec![0u8; u32::from_be_bytes(len) as usize];
+    input.read_exact(&mut bytes).unwrap();
+    String::from_utf8(bytes).unwrap()
+}
+
+#[test]
+fn calls_fail_once_the_server_is_gone() {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+    let addr = listener.local_addr().unwrap().to_string();
+    // Lists one transformer, then closes the connection and stops
+    // listening, like a server that died.
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let request = read_frame(&mut stream);
+        assert!(request.contains("\"op\":\"registrations\""), "{}", request);
+        write_frame(
+            &mut stream,
+            r#"{"reply":"registrations","registrations":[{"name":"gone","greeter_name":null}]}"#,
+        );
+    });
+
+    let mut mgr = PluginManager::new();
+    let handles = mgr
+        .connect_remote(&addr, PluginTrait::Transformer)
+        .expect("connect");
+    server.join().unwrap();
+    assert_eq!(handles[0].registration_name().as_deref(), Some("gone"));
+    let proxy = handles[0].as_transformer().expect("transformer");
+    assert_eq!(proxy.try_transform("abc"), Err(PluginCallError::Failed));
+    // Reconnecting fails as well.
+    assert_eq!(proxy.try_transform("abc"), Err(PluginCallError::Failed));
+    drop((proxy, handles));
+
+    let err = mgr
+        .connect_remote(&addr, PluginTrait::Transformer)
+        .unwrap_err();
+    assert!(matches!(err, PluginLoadError::Lib(ref e) if e.contains("cannot connect")));
+}
+
+#[test]
+fn large_payloads_go_through_shared_memory() {
+    let Some(upper) = built_plugin("plugin_upper") else {
+        eprintln!("plugin artifacts not built; skipping");
+        return;
+    };
+    let addr = serve(upper);
+    let mut mgr = PluginManager::new();
+    mgr.set_shared_memory(Some(SharedMemoryOptions {
+        capacity: 4 << 20,
+        threshold: 1024,
+    }));
+    let handles = mgr
+        .connect_remote(&addr, PluginTrait::Transformer)
+        .expect("connect");
+    let proxy = handles[0].as_transformer().expect("transformer");
+    let large = "megabyte ".repeat(1 << 17);
+    assert_eq!(proxy.try_transform(&large), Ok(large.to_uppercase()));
+    assert_eq!(proxy.try_transform("small").as_deref(), Ok("SMALL"));
+    // Too large for the region: sent inline.
+    let huge = "x".repeat(5 << 20);
+    assert_eq!(proxy.try_transform(&huge), Ok(huge.to_uppercase()));
+}
+
+#[test]
+fn servers_that_cannot_attach_get_payloads_inline() {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+    let addr = listener.local_addr().unwrap().to_string();
+    // Refuses to attach, like a server on another machine, and echoes the
+    // one transform it is sent.
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let attach = read_frame(&mut stream);
+        assert!(attach.contains("\"op\":\"attach\""), "{}", attach);
+        write_frame(&mut stream, r#"{"reply":"error","message":"no such file"}"#);
+        read_frame(&mut stream);
+        write_frame(
+            &mut stream,
+            r#"{"reply":"registrations","registrations":[{"name":"echo","greeter_name":null}]}"#,
+        );
+        let transform = read_frame(&mut stream);
+        assert!(
+            transform.contains(r#""input":{"inline":"payload"}"#),
+            "{}",
+            transform
+        );
+        write_frame(&mut stream, r#"{"reply":"text","text":{"inline":"echo"}}"#);
+    });
+
+    let mut mgr = PluginManager::new();
+    mgr.set_shared_memory(Some(SharedMemoryOptions {
+        capacity: 1024,
+        threshold: 1,
+    }));
+    let handles = mgr
+        .connect_remote(&addr, PluginTrait::Transformer)
+        .expect("connect");
+    let proxy = handles[0].as_transformer().expect("transformer");
+    assert_eq!(proxy.try_transform("payload").as_deref(), Ok("echo"));
+    server.join().unwrap();
+}
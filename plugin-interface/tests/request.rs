@@ -0,0 +1,73 @@
+mod common;
+
+use common::{built_plugin, plugin_upper};
+use plugin_interface::{
+    get_counters_for, GreeterProxy, PluginCallError, PluginManager, PluginTrait, RequestContext,
+    TransformerProxy,
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+#[test]
+fn request_context_reaches_the_plugin() {
+    let Some(lib) = plugin_upper() else { return };
+
+    let mut mgr = PluginManager::new();
+    let handles = mgr
+        .load_library(&lib, PluginTrait::Transformer)
+        .expect("load as Transformer");
+    let proxy = handles[0]
+        .as_proxy::<TransformerProxy>()
+        .expect("transformer proxy");
+
+    // The plugin tags its result with the correlation ID it was called
+    // with, and only when it was called with one.
+    let ctx = RequestContext::new().with_correlation_id(42);
+    assert_eq!(proxy.transform_in("abc", &ctx).as_deref(), Ok("ABC #42"));
+    assert_eq!(proxy.try_transform("abc").as_deref(), Ok("ABC"));
+    assert_eq!(
+        proxy.transform_in("abc", &RequestContext::new()).as_deref(),
+        Ok("ABC")
+    );
+
+    let cancel = Arc::new(AtomicBool::new(false));
+    let ctx = ctx.with_cancel_flag(cancel.clone());
+    assert!(proxy.transform_in("abc", &ctx).is_ok());
+    cancel.store(true, Ordering::Release);
+    assert_eq!(
+        proxy.transform_in("abc", &ctx),
+        Err(PluginCallError::Cancelled)
+    );
+    let expired = RequestContext::new().with_deadline(SystemTime::now() - Duration::from_secs(1));
+    assert_eq!(
+        proxy.transform_in("abc", &expired),
+        Err(PluginCallError::Cancelled)
+    );
+}
+
+#[test]
+fn context_entries_are_counted() {
+    let Some(lib) = built_plugin("plugin_pair") else {
+        return;
+    };
+
+    let mut mgr = PluginManager::new();
+    let handles = mgr
+        .load_library(&lib, PluginTrait::Greeter)
+        .expect("load as Greeter");
+    let proxy = handles[0]
+        .as_proxy::<GreeterProxy>()
+        .expect("greeter proxy");
+    let opened = unsafe { libloading::Library::new(&lib) }.expect("open plugin");
+    let calls = || {
+        get_counters_for(&opened, PluginTrait::Greeter)
+            .expect("counters")
+            .calls
+    };
+
+    let before = calls();
+    let ctx = RequestContext::new().with_correlation_id(7);
+    assert_eq!(proxy.greet_in("counted", &ctx), Ok(()));
+    assert_eq!(calls(), before + 1);
+}
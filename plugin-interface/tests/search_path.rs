@@ -0,0 +1,81 @@
+use plugin_interface::{
+    sidecar_path, PluginLoadError, PluginManager, PluginSearchPath, PluginTrait, SearchPrecedence,
+    PLUGIN_PATH_ENV,
+};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Writes a fake library with a manifest. Loading it gets as far as dlopen,
+// so which copy was picked shows up in the resulting error.
+fn fake_plugin(dir: &Path, version: &str, min_host_abi: u32) -> PathBuf {
+    let lib = dir.join(format!("libfoo.{}", std::env::consts::DLL_EXTENSION));
+    fs::write(&lib, b"not a library").expect("write lib");
+    fs::write(
+        sidecar_path(&lib),
+        format!(
+            "name = \"foo\"\nversion = \"{}\"\ntraits = [\"Greeter\"]\nmin_host_abi = {}\n",
+            version, min_host_abi
+        ),
+    )
+    .expect("write manifest");
+    lib
+}
+
+#[test]
+fn earlier_directory_shadows_later_copies() {
+    let first = tempfile::tempdir().expect("tmpdir");
+    let second = tempfile::tempdir().expect("tmpdir");
+    let kept = fake_plugin(first.path(), "1.0.0", 1);
+    // Would fail validation if it were picked.
+    let shadowed = fake_plugin(second.path(), "2.0.0", 99);
+
+    let search = PluginSearchPath::new()
+        .with_dir(first.path())
+        .with_dir(second.path());
+    let mut mgr = PluginManager::new();
+    match mgr.load_plugins(search, PluginTrait::Greeter) {
+        Err(PluginLoadError::NotADynamicLibrary { path, .. }) => assert_eq!(path, kept),
+        other => panic!("expected the first copy to be opened, got {:?}", other),
+    }
+    let report = mgr.shadowed_plugins();
+    assert_eq!(report.len(), 1);
+    assert_eq!(report[0].name, "foo");
+    assert_eq!(report[0].path, shadowed);
+    assert_eq!(report[0].shadowed_by, kept);
+}
+
+#[test]
+fn highest_version_precedence_picks_newest_copy() {
+    let first = tempfile::tempdir().expect("tmpdir");
+    let second = tempfile::tempdir().expect("tmpdir");
+    let older = fake_plugin(first.path(), "1.0.0", 1);
+    let newest = fake_plugin(second.path(), "2.0.0", 99);
+
+    let search = PluginSearchPath::new()
+        .with_dir(first.path())
+        .with_dir(second.path())
+        .with_precedence(SearchPrecedence::HighestVersion);
+    let mut mgr = PluginManager::new();
+    match mgr.load_plugins(search, PluginTrait::Greeter) {
+        Err(PluginLoadError::Manifest { path, .. }) => assert_eq!(path, newest),
+        other => panic!("expected the newest copy to be validated, got {:?}", other),
+    }
+    assert_eq!(mgr.shadowed_plugins()[0].path, older);
+}
+
+#[test]
+fn plugin_path_env_lists_directories_and_missing_ones_are_skipped() {
+    let dir = tempfile::tempdir().expect("tmpdir");
+    let missing = dir.path().join("missing");
+    let value = std::env::join_paths([missing.as_path(), dir.path()]).expect("join");
+    std::env::set_var(PLUGIN_PATH_ENV, &value);
+    let search = PluginSearchPath::from_env();
+    std::env::remove_var(PLUGIN_PATH_ENV);
+    assert_eq!(search.dirs(), [missing, dir.path().to_path_buf()]);
+
+    let mut mgr = PluginManager::new();
+    assert!(matches!(
+        mgr.load_plugins(search, PluginTrait::Greeter),
+        Err(PluginLoadError::NoRegistrations)
+    ));
+}
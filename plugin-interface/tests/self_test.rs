@@ -0,0 +1,41 @@
+mod common;
+
+use common::plugin_upper;
+use plugin_interface::{ConfigSource, PluginLoadError, PluginManager, PluginTrait};
+
+#[test]
+fn self_test_refuses_plugins_with_failing_registrations() {
+    let Some(lib) = plugin_upper() else { return };
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::copy(&lib, dir.path().join(lib.file_name().unwrap())).unwrap();
+    let unhealthy = ConfigSource::from_toml("[plugin_upper]\nhealthy = false\n").unwrap();
+
+    let mut mgr = PluginManager::new();
+    mgr.set_self_test(true);
+    let refused = mgr.load_plugins_with_config(dir.path(), PluginTrait::Transformer, &unhealthy);
+    assert!(matches!(
+        refused,
+        Err(PluginLoadError::SelfTestFailed { failed: 1, .. })
+    ));
+    assert!(mgr.list().is_empty());
+
+    // A healthy plugin passes and is loaded as usual.
+    let mut mgr = PluginManager::new();
+    mgr.set_self_test(true);
+    let handles = mgr
+        .load_library(&lib, PluginTrait::Transformer)
+        .expect("passes its self-test");
+    assert_eq!(handles[0].as_transformer().unwrap().transform("ok"), "OK");
+    // The instances the self-test made and released are not counted.
+    let handle = handles.into_iter().next().unwrap();
+    let counters = handle.close().expect("close").expect("counters");
+    assert_eq!(
+        (
+            counters.registrations_created,
+            counters.registrations_destroyed
+        ),
+        (1, 1)
+    );
+    // Its `health` check is a call all the same, besides `transform`.
+    assert_eq!((counters.calls, counters.panics), (2, 0));
+}
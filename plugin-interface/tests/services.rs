@@ -0,0 +1,74 @@
+mod common;
+
+use common::built_plugin;
+use plugin_interface::{HostServices, PluginManager, PluginTrait};
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn plugins_use_each_others_services() {
+    let (Some(upper), Some(multi)) = (built_plugin("plugin_upper"), built_plugin("plugin_multi"))
+    else {
+        return;
+    };
+    let logged = Arc::new(Mutex::new(Vec::new()));
+    let sink = logged.clone();
+    let services =
+        HostServices::new().with_logger(move |_, msg| sink.lock().unwrap().push(msg.to_string()));
+    let mut mgr = PluginManager::with_host_services(services);
+
+    let transformers = mgr
+        .load_library(&upper, PluginTrait::Transformer)
+        .expect("load plugin-upper");
+    let providers = mgr.services().providers();
+    assert_eq!(providers.len(), 1);
+    assert_eq!(providers[0].trait_name, "Transformer");
+    assert_eq!(providers[0].name, "Upper");
+
+    let greeters = mgr
+        .load_library(&multi, PluginTrait::Greeter)
+        .expect("load plugin-multi");
+    let two = greeters
+        .iter()
+        .find(|h| h.registration_name().as_deref() == Some("greeter-two"))
+        .expect("greeter-two");
+    two.as_greeter().unwrap().greet("bob");
+    assert_eq!(
+        logged.lock().unwrap().as_slice(),
+        ["HELLO, BOB FROM GREETERTWO"]
+    );
+    assert_eq!(mgr.services().users_of(&upper), vec![multi.clone()]);
+
+    // The provider stays loaded while a plugin that used it is.
+    let err = mgr.unload_by_path(&upper).unwrap_err();
+    assert!(err.contains("still required by"), "{}", err);
+    drop(transformers);
+    drop(greeters);
+    mgr.unload_by_path(&multi).expect("unload plugin-multi");
+    mgr.unload_by_path(&upper).expect("unload plugin-upper");
+    assert!(mgr.services().users_of(&upper).is_empty());
+    assert!(mgr.services().providers().is_empty());
+}
+
+#[test]
+fn missing_services_leave_plugins_to_fall_back() {
+    let Some(multi) = built_plugin("plugin_multi") else {
+        return;
+    };
+    let logged = Arc::new(Mutex::new(Vec::new()));
+    let sink = logged.clone();
+    let services =
+        HostServices::new().with_logger(move |_, msg| sink.lock().unwrap().push(msg.to_string()));
+    let mut mgr = PluginManager::with_host_services(services);
+    let greeters = mgr
+        .load_library(&multi, PluginTrait::Greeter)
+        .expect("load plugin-multi");
+    for h in &greeters {
+        h.as_greeter().unwrap().greet("bob");
+    }
+    let mut logged = logged.lock().unwrap().clone();
+    logged.sort();
+    assert_eq!(
+        logged,
+        ["Hello, bob from GreeterOne", "Hello, bob from GreeterTwo"]
+    );
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use common::built_plugin;
+use plugin_interface::{PluginManager, PluginTrait};
+use std::time::Duration;
+
+#[test]
+fn shutdown_stops_watchers_and_unloads_everything() {
+    let Some(lib) = built_plugin("plugin_a") else {
+        eprintln!("plugin artifacts not built; skipping");
+        return;
+    };
+    let mut mgr = PluginManager::new();
+    let _handles = mgr.load_library(&lib, PluginTrait::Greeter).expect("load");
+    let signal = mgr.shutdown_signal();
+    assert!(!signal.is_requested());
+
+    #[cfg(feature = "watch")]
+    let dir = tempfile::tempdir().expect("tmpdir");
+    #[cfg(feature = "watch")]
+    let (rx, watcher) = mgr.start_watch_background(dir.path().to_path_buf(), Default::default());
+
+    let report = mgr.shutdown();
+    assert!(signal.is_requested());
+    assert_eq!(report.len(), 1);
+    assert_eq!(report[0].path, lib);
+    assert!(report[0].result.is_ok());
+    assert!(mgr.list().is_empty());
+
+    #[cfg(feature = "watch")]
+    {
+        // The watcher exits on its own and closes its channel.
+        assert!(rx.recv_timeout(Duration::from_secs(5)).is_err());
+        assert!(watcher.is_finished());
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn run_until_shutdown_returns_on_sigterm() {
+    let Some(lib) = built_plugin("plugin_a") else {
+        eprintln!("plugin artifacts not built; skipping");
+        return;
+    };
+    let mut mgr = PluginManager::new();
+    let _handles = mgr.load_library(&lib, PluginTrait::Greeter).expect("load");
+
+    std::thread::spawn(|| {
+        std::thread::sleep(Duration::from_millis(200));
+        unsafe { libc::raise(libc::SIGTERM) };
+    });
+    let report = mgr.run_until_shutdown().expect("install handler");
+    assert_eq!(report.len(), 1);
+    assert!(mgr.list().is_empty());
+}
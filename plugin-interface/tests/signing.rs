@@ -0,0 +1,96 @@
+#![cfg(feature = "signing")]
+
+mod common;
+
+use common::library_file_name;
+use ed25519_dalek::{Signer, SigningKey};
+use plugin_interface::{
+    signature_path, PluginLoadError, PluginManager, PluginTrait, SignatureError,
+};
+use std::path::{Path, PathBuf};
+
+// Signature checks run before the library is opened, so the artifacts here
+// don't need to be real libraries: a correctly signed one gets past the check
+// and then fails to open with `PluginLoadError::NotADynamicLibrary`.
+fn artifact(dir: &Path) -> PathBuf {
+    let path = dir.join(library_file_name("fake"));
+    std::fs::write(&path, b"not really a library").unwrap();
+    path
+}
+
+fn sign(path: &Path, key: &SigningKey) {
+    let bytes = std::fs::read(path).unwrap();
+    std::fs::write(signature_path(path), key.sign(&bytes).to_bytes()).unwrap();
+}
+
+fn manager_trusting(key: &SigningKey) -> PluginManager {
+    let mut mgr = PluginManager::new();
+    mgr.trust_key(key.verifying_key().as_bytes()).unwrap();
+    mgr
+}
+
+fn signature_error(
+    res: Result<Vec<plugin_interface::PluginHandle>, PluginLoadError>,
+) -> SignatureError {
+    match res {
+        Err(PluginLoadError::SignatureInvalid { error, .. }) => error,
+        other => panic!("expected a signature error, got {:?}", other),
+    }
+}
+
+#[test]
+fn unsigned_artifacts_are_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    artifact(dir.path());
+    let mut mgr = manager_trusting(&SigningKey::from_bytes(&[1; 32]));
+
+    let err = signature_error(mgr.load_plugins(dir.path(), PluginTrait::Greeter));
+    assert!(matches!(err, SignatureError::Missing));
+}
+
+#[test]
+fn signatures_from_other_keys_or_tampered_artifacts_are_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = artifact(dir.path());
+    let trusted = SigningKey::from_bytes(&[1; 32]);
+    let mut mgr = manager_trusting(&trusted);
+
+    sign(&path, &SigningKey::from_bytes(&[2; 32]));
+    let err = signature_error(mgr.load_library(&path, PluginTrait::Greeter));
+    assert!(matches!(err, SignatureError::Untrusted));
+
+    sign(&path, &trusted);
+    std::fs::write(&path, b"tampered after signing").unwrap();
+    let err = signature_error(mgr.load_library(&path, PluginTrait::Greeter));
+    assert!(matches!(err, SignatureError::Untrusted));
+
+    std::fs::write(signature_path(&path), b"short").unwrap();
+    let err = signature_error(mgr.load_library(&path, PluginTrait::Greeter));
+    assert!(matches!(err, SignatureError::Malformed));
+}
+
+#[test]
+fn correctly_signed_artifacts_pass_the_check() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = artifact(dir.path());
+    let key = SigningKey::from_bytes(&[1; 32]);
+    sign(&path, &key);
+
+    let mut mgr = manager_trusting(&key);
+    match mgr.load_library(&path, PluginTrait::Greeter) {
+        Err(PluginLoadError::NotADynamicLibrary { .. }) => {}
+        other => panic!("expected the open to fail, got {:?}", other),
+    }
+}
+
+#[test]
+fn without_trusted_keys_nothing_is_checked() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = artifact(dir.path());
+
+    let mut mgr = PluginManager::new();
+    assert!(matches!(
+        mgr.load_library(&path, PluginTrait::Greeter),
+        Err(PluginLoadError::NotADynamicLibrary { .. })
+    ));
+}
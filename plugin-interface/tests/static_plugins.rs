@@ -0,0 +1,39 @@
+#![cfg(feature = "static-plugins")]
+
+use plugin_interface::{ConfigSource, PluginLoadError, PluginManager, PluginTrait};
+use std::path::Path;
+
+// Linking plugin-upper in is what submits its registrations; its counters
+// also tell when they were released.
+use plugin_upper::plugin_counters_Transformer_v4 as counters;
+
+#[test]
+fn linked_plugins_load_without_a_library() {
+    let config = ConfigSource::from_toml("[\"static:Transformer\"]\nsuffix = \"!\"\n").unwrap();
+    let mut mgr = PluginManager::new();
+    assert!(matches!(
+        mgr.load_static(PluginTrait::Greeter),
+        Err(PluginLoadError::NoRegistrations)
+    ));
+    let handles = mgr
+        .load_static_with_config(PluginTrait::Transformer, &config)
+        .expect("load linked plugins");
+    assert_eq!(handles.len(), 1);
+    assert_eq!(handles[0].path(), Path::new("static:Transformer"));
+    assert!(mgr.load_static(PluginTrait::Transformer).is_err());
+
+    let proxy = handles[0].as_transformer().expect("proxy");
+    assert_eq!(proxy.transform("linked"), "LINKED!");
+    assert_eq!(proxy.try_describe().as_deref(), Ok("uppercases its input"));
+    let instance = handles[0].instantiate().expect("instance");
+    assert_eq!(instance.as_transformer().unwrap().transform("a"), "A!");
+    let released = counters().registrations_destroyed;
+    drop((instance, proxy, handles));
+    mgr.unload_by_path(Path::new("static:Transformer"))
+        .expect("unload");
+    assert!(counters().registrations_destroyed > released);
+    let handles = mgr
+        .load_static(PluginTrait::Transformer)
+        .expect("load again");
+    assert_eq!(handles[0].as_transformer().unwrap().transform("b"), "B!");
+}
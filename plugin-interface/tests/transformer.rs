@@ -0,0 +1,108 @@
+mod common;
+
+use common::plugin_upper;
+use plugin_interface::{
+    get_counters_for, GreeterProxy, PluginManager, PluginTrait, TransformerProxy,
+};
+
+#[test]
+fn loads_and_calls_a_transformer() {
+    let Some(lib) = plugin_upper() else { return };
+
+    let mut mgr = PluginManager::new();
+    assert!(mgr.load_library(&lib, PluginTrait::Greeter).is_err());
+    let handles = mgr
+        .load_library(&lib, PluginTrait::Transformer)
+        .expect("load as Transformer");
+    assert_eq!(handles.len(), 1);
+    let handle = &handles[0];
+    assert_eq!(handle.trait_id(), PluginTrait::Transformer);
+    // Level 4 registrations record their struct sizes, checked on load.
+    assert_eq!(handle.abi_version(), 4);
+    assert_eq!(handle.registration_name().as_deref(), Some("Upper"));
+    assert!(handle.as_proxy::<GreeterProxy>().is_none());
+
+    let proxy = handle
+        .as_proxy::<TransformerProxy>()
+        .expect("transformer proxy");
+    assert_eq!(
+        proxy.try_transform("hello\0world").as_deref(),
+        Ok("HELLO\0WORLD")
+    );
+    assert_eq!(proxy.try_describe().as_deref(), Ok("uppercases its input"));
+    assert_eq!(handle.health(), Ok(plugin_interface::HealthStatus::Healthy));
+    // The optional `describe` and `health` count like `transform`.
+    let opened = unsafe { libloading::Library::new(&lib) }.expect("open plugin");
+    let counters = get_counters_for(&opened, PluginTrait::Transformer).expect("counters");
+    assert_eq!(counters.calls, 3);
+    drop(opened);
+
+    let description: serde_json::Value =
+        serde_json::from_str(&mgr.describe(&lib).expect("describe")).expect("valid JSON");
+    assert_eq!(description["plugin"]["name"], "plugin-upper");
+    assert_eq!(description["abi_version"], 4);
+    let transformer = &description["traits"][0];
+    assert_eq!(transformer["name"], "Transformer");
+    assert_eq!(transformer["abi_version"], 4);
+    assert_eq!(transformer["registrations"], serde_json::json!(["Upper"]));
+    assert_eq!(
+        transformer["methods"][0]["signature"],
+        "fn transform(&self, input: &str) -> String"
+    );
+    assert_eq!(
+        transformer["methods"][0]["deprecated"],
+        serde_json::Value::Null
+    );
+    assert_eq!(transformer["methods"][1]["name"], "describe");
+    assert_eq!(transformer["methods"][1]["optional"], true);
+    assert_eq!(mgr.find_by_trait(PluginTrait::Transformer).len(), 1);
+    assert!(mgr.find_by_trait(PluginTrait::Greeter).is_empty());
+
+    drop(proxy);
+    for h in handles {
+        h.close().expect("close failed");
+    }
+    // Not loaded any more: opened just to read the document.
+    assert!(mgr.describe(&lib).expect("describe").contains("\"Upper\""));
+}
+
+#[cfg(feature = "watch")]
+#[test]
+fn watcher_loads_transformers() {
+    use plugin_interface::{ManagerNotification, WatchEvent, WatchOptions};
+
+    let Some(lib) = plugin_upper() else { return };
+    let dir = tempfile::tempdir().unwrap();
+    let opts = WatchOptions {
+        debounce_ms: 100,
+        stable_ms: 50,
+        auto_load: true,
+        ..Default::default()
+    };
+
+    let mut mgr = PluginManager::new();
+    let (rx, _watcher) = mgr.start_watch_background(dir.path().to_path_buf(), opts.clone());
+    let dest = dir.path().join(lib.file_name().unwrap());
+    std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(150));
+        std::fs::copy(&lib, &dest).expect("copy plugin");
+    });
+
+    let mut transformed = None;
+    mgr.process_watch_notifications_blocking(
+        dir.path(),
+        rx,
+        PluginTrait::Transformer,
+        opts,
+        |notification| match notification {
+            ManagerNotification::Event(WatchEvent::Added { handles, .. })
+                if !handles.is_empty() =>
+            {
+                transformed = handles[0].as_transformer().map(|t| t.transform("watched"));
+                false
+            }
+            _ => true,
+        },
+    );
+    assert_eq!(transformed.as_deref(), Some("WATCHED"));
+}
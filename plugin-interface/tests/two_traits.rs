@@ -0,0 +1,104 @@
+mod common;
+
+use common::built_plugin;
+use plugin_interface::{
+    get_counters_for, GreeterProxy, PluginManager, PluginTrait, TransformerProxy,
+};
+
+#[test]
+fn one_crate_aggregates_two_traits() {
+    let Some(lib) = built_plugin("plugin_pair") else {
+        return;
+    };
+
+    let mut mgr = PluginManager::new();
+    let greeters = mgr
+        .load_library(&lib, PluginTrait::Greeter)
+        .expect("load as Greeter");
+    let transformers = mgr
+        .load_library(&lib, PluginTrait::Transformer)
+        .expect("load as Transformer");
+    assert_eq!(greeters.len(), 1);
+    assert_eq!(transformers.len(), 1);
+    assert_eq!(greeters[0].abi_version(), 3);
+    assert_eq!(transformers[0].abi_version(), 5);
+
+    let greeter = greeters[0]
+        .as_proxy::<GreeterProxy>()
+        .expect("greeter proxy");
+    assert_eq!(greeter.try_name().as_deref(), Ok("Hello"));
+    assert_eq!(greeter.try_greet("pair"), Ok(()));
+    let transformer = transformers[0]
+        .as_proxy::<TransformerProxy>()
+        .expect("transformer proxy");
+    assert_eq!(transformer.try_transform("abc").as_deref(), Ok("cba"));
+    assert_eq!(transformer.try_transform("xyz").as_deref(), Ok("zyx"));
+
+    // Each trait keeps its own counters.
+    let opened = unsafe { libloading::Library::new(&lib) }.expect("open plugin");
+    let greeter_counters = get_counters_for(&opened, PluginTrait::Greeter).expect("counters");
+    let transformer_counters =
+        get_counters_for(&opened, PluginTrait::Transformer).expect("counters");
+    assert_eq!(greeter_counters.calls, 2);
+    assert_eq!(transformer_counters.calls, 2);
+
+    // One document covers both traits, each at its own level.
+    let description: serde_json::Value =
+        serde_json::from_str(&mgr.describe(&lib).expect("describe")).expect("valid JSON");
+    assert_eq!(description["plugin"]["name"], "plugin-pair");
+    assert_eq!(description["abi_version"], 5);
+    let traits = description["traits"].as_array().expect("traits");
+    assert_eq!(traits.len(), 2);
+    let greeter_doc = traits
+        .iter()
+        .find(|t| t["name"] == "Greeter")
+        .expect("Greeter");
+    assert_eq!(greeter_doc["abi_version"], 3);
+    assert_eq!(greeter_doc["registrations"], serde_json::json!(["Hello"]));
+    let transformer_doc = traits
+        .iter()
+        .find(|t| t["name"] == "Transformer")
+        .expect("Transformer");
+    assert_eq!(transformer_doc["abi_version"], 5);
+    assert_eq!(
+        transformer_doc["registrations"],
+        serde_json::json!(["Reverse"])
+    );
+
+    drop(opened);
+    drop(greeter);
+    drop(transformer);
+    for h in greeters.into_iter().chain(transformers) {
+        h.close().expect("close failed");
+    }
+}
+
+#[cfg(feature = "dynamic")]
+#[test]
+fn calls_each_trait_by_name() {
+    use serde_json::json;
+
+    let Some(lib) = built_plugin("plugin_pair") else {
+        return;
+    };
+    let mut mgr = PluginManager::new();
+    let greeters = mgr
+        .load_library(&lib, PluginTrait::Greeter)
+        .expect("load as Greeter");
+    let transformers = mgr
+        .load_library(&lib, PluginTrait::Transformer)
+        .expect("load as Transformer");
+
+    assert_eq!(
+        greeters[0].call_dynamic("name", json!([])),
+        Ok(json!("Hello"))
+    );
+    assert_eq!(
+        transformers[0].call_dynamic("transform", json!(["abc"])),
+        Ok(json!("cba"))
+    );
+
+    for h in greeters.into_iter().chain(transformers) {
+        h.close().expect("close failed");
+    }
+}
@@ -0,0 +1,107 @@
+mod common;
+
+use common::{built_plugin, copy_into};
+use plugin_interface::{PluginManager, PluginTrait, ValidationProblem};
+use std::fs;
+
+#[test]
+fn validate_checks_libraries_and_manifests_without_loading() {
+    let (Some(a), Some(multi)) = (built_plugin("plugin_a"), built_plugin("plugin_multi")) else {
+        eprintln!("plugin artifacts not built; skipping");
+        return;
+    };
+    let dir = tempfile::tempdir().expect("tmpdir");
+    let multi = copy_into(dir.path(), &multi);
+    // A manifest asking for a host ABI from the future.
+    fs::write(
+        dir.path().join("future.plugin.toml"),
+        format!(
+            "name = \"future\"\nversion = \"0.1.0\"\ntraits = [\"Greeter\"]\nmin_host_abi = 999\nlibrary = {:?}\n",
+            multi.file_name().unwrap()
+        ),
+    )
+    .expect("write manifest");
+    let a_copy = dir.path().join(a.file_name().unwrap());
+    fs::copy(&a, &a_copy).expect("copy plugin");
+
+    let mgr = PluginManager::new();
+    let report = mgr
+        .validate(dir.path(), PluginTrait::Greeter)
+        .expect("validate");
+    assert_eq!(report.files.len(), 2);
+    let future = &report.files[0];
+    assert_eq!(future.path, multi);
+    assert!(future.abi_version.is_some());
+    assert!(matches!(
+        future.problems[..],
+        [ValidationProblem::Manifest(_)]
+    ));
+    let plugin_a = &report.files[1];
+    assert_eq!(plugin_a.path, a_copy);
+    assert_eq!(plugin_a.abi_version, Some(3));
+    assert!(plugin_a.is_ok(), "{:?}", plugin_a.problems);
+    assert_eq!(report.failures().count(), 1);
+    // nothing was loaded
+    assert!(mgr.list().is_empty());
+}
+
+/// The 64-byte header of an ELF shared object for `machine`, with no
+/// sections or segments.
+#[cfg(all(unix, not(target_vendor = "apple")))]
+fn elf_header(machine: u16) -> Vec<u8> {
+    let mut h = vec![0x7f, b'E', b'L', b'F', 2, 1, 1];
+    h.resize(16, 0);
+    h.extend_from_slice(&3u16.to_le_bytes()); // ET_DYN
+    h.extend_from_slice(&machine.to_le_bytes());
+    h.extend_from_slice(&1u32.to_le_bytes());
+    h.resize(52, 0);
+    h.extend_from_slice(&64u16.to_le_bytes()); // e_ehsize
+    h.extend_from_slice(&56u16.to_le_bytes()); // e_phentsize
+    h.extend_from_slice(&0u16.to_le_bytes());
+    h.extend_from_slice(&64u16.to_le_bytes()); // e_shentsize
+    h.resize(64, 0);
+    h
+}
+
+#[test]
+#[cfg(all(unix, not(target_vendor = "apple")))]
+fn foreign_and_fake_libraries_are_refused_before_opening() {
+    use plugin_interface::PluginLoadError;
+
+    // EM_AARCH64, or EM_X86_64 on an aarch64 host
+    let machine = if cfg!(target_arch = "aarch64") {
+        62
+    } else {
+        183
+    };
+    let dir = tempfile::tempdir().expect("tmpdir");
+    let foreign = dir.path().join("libforeign.so");
+    fs::write(&foreign, elf_header(machine)).expect("write library");
+    let text = dir.path().join("libtext.so");
+    fs::write(&text, b"#!/bin/sh\necho not a library\n").expect("write script");
+
+    let mut mgr = PluginManager::new();
+    match mgr.load_library(&foreign, PluginTrait::Greeter) {
+        Err(PluginLoadError::WrongArchitecture { path, found, .. }) => {
+            assert_eq!(path, foreign);
+            assert!(found.starts_with("Elf"), "{}", found);
+        }
+        other => panic!("expected a wrong architecture error, got {:?}", other),
+    }
+    match mgr.load_library(&text, PluginTrait::Greeter) {
+        Err(PluginLoadError::NotADynamicLibrary { path, .. }) => assert_eq!(path, text),
+        other => panic!("expected a not-a-library error, got {:?}", other),
+    }
+
+    let report = mgr
+        .validate(dir.path(), PluginTrait::Greeter)
+        .expect("validate");
+    assert!(matches!(
+        report.files[0].problems[..],
+        [ValidationProblem::WrongArchitecture { .. }]
+    ));
+    assert!(matches!(
+        report.files[1].problems[..],
+        [ValidationProblem::NotADynamicLibrary(_)]
+    ));
+}
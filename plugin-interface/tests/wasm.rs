@@ -0,0 +1,117 @@
+#![cfg(feature = "wasm")]
+
+use plugin_interface::{HostServices, LogLevel, PluginCallError, PluginManager, PluginTrait};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+// Two greeters; `greet` logs the target through the host at info level.
+const GREETERS: &str = r#"
+(module
+  (import "plugin_host" "log" (func $log (param i32 i32 i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 16) "wasm-one\00")
+  (data (i32.const 32) "wasm-two\00")
+  (global $next (mut i32) (i32.const 1024))
+  (func (export "plugin_alloc") (param $len i32) (result i32)
+    (local $p i32)
+    (local.set $p (global.get $next))
+    (global.set $next (i32.add (global.get $next) (local.get $len)))
+    (local.get $p))
+  (func (export "plugin_Greeter_v1_count") (result i32) (i32.const 2))
+  (func (export "plugin_Greeter_v1_name") (param $i i32) (result i32)
+    (select (i32.const 16) (i32.const 32) (i32.eqz (local.get $i))))
+  (func (export "plugin_Greeter_v1_greet") (param $i i32) (param $p i32) (param $len i32)
+    (call $log (i32.const 3) (local.get $p) (local.get $len))))
+"#;
+
+// A greeter whose `greet` always traps.
+const TRAPPING: &str = r#"
+(module
+  (memory (export "memory") 1)
+  (data (i32.const 16) "trapper\00")
+  (func (export "plugin_alloc") (param i32) (result i32) (i32.const 1024))
+  (func (export "plugin_Greeter_v1_count") (result i32) (i32.const 1))
+  (func (export "plugin_Greeter_v1_name") (param i32) (result i32) (i32.const 16))
+  (func (export "plugin_Greeter_v1_greet") (param i32 i32 i32) unreachable))
+"#;
+
+fn write_module(dir: &Path, file: &str, wat: &str) {
+    std::fs::write(dir.join(file), wat::parse_str(wat).unwrap()).unwrap();
+}
+
+#[test]
+fn wasm_modules_load_as_greeters() {
+    let dir = tempfile::tempdir().unwrap();
+    write_module(dir.path(), "greeters.wasm", GREETERS);
+
+    let logs = Arc::new(Mutex::new(Vec::new()));
+    let sink = logs.clone();
+    let mut mgr = PluginManager::with_host_services(
+        HostServices::new()
+            .with_logger(move |level, msg| sink.lock().unwrap().push((level, msg.to_string()))),
+    );
+    let handles = mgr.load_plugins(dir.path(), PluginTrait::Greeter).unwrap();
+    let proxies: Vec<_> = handles.iter().filter_map(|h| h.as_greeter()).collect();
+    let names: Vec<_> = proxies.iter().map(|p| p.name()).collect();
+    assert_eq!(names, ["wasm-one", "wasm-two"]);
+
+    proxies[1].try_greet("world").unwrap();
+    assert_eq!(
+        *logs.lock().unwrap(),
+        [(LogLevel::Info, "world".to_string())]
+    );
+    assert_eq!(mgr.stats().len(), 2);
+}
+
+#[test]
+fn traps_are_reported_and_contained() {
+    let dir = tempfile::tempdir().unwrap();
+    write_module(dir.path(), "trapping.wasm", TRAPPING);
+
+    let mut mgr = PluginManager::new();
+    let handles = mgr.load_plugins(dir.path(), PluginTrait::Greeter).unwrap();
+    let proxy = handles[0].as_greeter().unwrap();
+
+    assert!(matches!(
+        proxy.try_greet("boom"),
+        Err(PluginCallError::Trapped(_))
+    ));
+    // The instance survives the trap.
+    assert_eq!(proxy.name(), "trapper");
+    assert_eq!(mgr.stats()[0].errors, 1);
+}
+
+#[test]
+fn modules_without_the_trait_exports_are_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    write_module(
+        dir.path(),
+        "empty.wasm",
+        "(module (memory (export \"memory\") 1))",
+    );
+
+    let mut mgr = PluginManager::new();
+    assert!(mgr.load_plugins(dir.path(), PluginTrait::Greeter).is_err());
+}
+
+#[test]
+fn unloading_a_module_forgets_it() {
+    let dir = tempfile::tempdir().unwrap();
+    write_module(dir.path(), "greeters.wasm", GREETERS);
+    let path = dir.path().join("greeters.wasm");
+
+    let mut mgr = PluginManager::new();
+    let handles = mgr.load_plugins(dir.path(), PluginTrait::Greeter).unwrap();
+    assert_eq!(mgr.unload_by_path(&path).unwrap(), None);
+    assert!(mgr.stats().is_empty());
+    // Existing handles keep the instance alive.
+    assert_eq!(handles[0].as_greeter().unwrap().name(), "wasm-one");
+    drop(handles);
+    // The directory can be loaded again afterwards.
+    assert_eq!(
+        mgr.load_plugins(dir.path(), PluginTrait::Greeter)
+            .unwrap()
+            .len(),
+        2
+    );
+}
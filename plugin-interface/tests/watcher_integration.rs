@@ -35,10 +35,12 @@ fn watcher_auto_loads_new_plugin() {
     // record that and return false to stop watching.
     let opts = WatchOptions {
         debounce_ms: 200,
+        stable_ms: 100,
         recursive: false,
         auto_load: true,
         auto_unload: false,
-        emit_proxies: false,
+        auto_reload: false,
+        ..Default::default()
     };
 
     // Copy the plugin into the temp dir after starting the watcher in another
@@ -57,20 +59,11 @@ fn watcher_auto_loads_new_plugin() {
     });
 
     mgr.watch_and_load_blocking(dir, PluginTrait::Greeter, opts, move |evt| {
-        match evt {
-            plugin_interface::WatchEvent::Handles(handles, _paths) => {
-                if !handles.is_empty() {
-                    let mut locked = saw_handles_clone.lock().unwrap();
-                    *locked = true;
-                    return false; // stop watching
-                }
-            }
-            plugin_interface::WatchEvent::Proxies(proxies, _paths) => {
-                if !proxies.is_empty() {
-                    let mut locked = saw_handles_clone.lock().unwrap();
-                    *locked = true;
-                    return false;
-                }
+        if let plugin_interface::WatchEvent::Added { handles, .. } = evt {
+            if !handles.is_empty() {
+                let mut locked = saw_handles_clone.lock().unwrap();
+                *locked = true;
+                return false; // stop watching
             }
         }
         true
@@ -1,6 +1,11 @@
 #![cfg(feature = "watch")]
 
-use plugin_interface::{ManagerNotification, PluginManager, PluginTrait, WatchEvent, WatchOptions};
+mod common;
+
+use common::built_plugin;
+use plugin_interface::{
+    ManagerNotification, PluginManager, PluginTrait, WatchEvent, WatchNotification, WatchOptions,
+};
 use std::fs;
 use std::path::PathBuf;
 
@@ -33,14 +38,16 @@ fn manager_background_watcher_loads_plugins() {
 
     let opts_bg = WatchOptions {
         debounce_ms: 200,
+        stable_ms: 100,
         recursive: false,
         auto_load: true,
         auto_unload: false,
-        emit_proxies: false,
+        auto_reload: false,
+        ..Default::default()
     };
 
     // start background watcher (emits conservative WatchNotification)
-    let (rx, stop_tx, handle) = mgr.start_watch_background(dir.clone(), opts_bg);
+    let (rx, watcher) = mgr.start_watch_background(dir.clone(), opts_bg);
 
     // spawn copier thread to add the plugin after a short delay
     let copy_path = candidate.clone();
@@ -54,35 +61,320 @@ fn manager_background_watcher_loads_plugins() {
     // process notifications on manager-owned thread; stop when we see handles
     let opts_proc = WatchOptions {
         debounce_ms: 200,
+        stable_ms: 100,
         recursive: false,
         auto_load: true,
         auto_unload: false,
-        emit_proxies: false,
+        auto_reload: false,
+        ..Default::default()
     };
 
     let mut saw = false;
     mgr.process_watch_notifications_blocking(&dir, rx, PluginTrait::Greeter, opts_proc, |not| {
-        match not {
-            ManagerNotification::Event(WatchEvent::Handles(handles, _paths)) => {
-                if !handles.is_empty() {
-                    saw = true;
-                    return false; // stop processing
-                }
-            }
-            ManagerNotification::Event(WatchEvent::Proxies(proxies, _paths)) => {
-                if !proxies.is_empty() {
-                    saw = true;
-                    return false;
-                }
+        if let ManagerNotification::Event(WatchEvent::Added { handles, .. }) = not {
+            if !handles.is_empty() {
+                saw = true;
+                return false; // stop processing
             }
-            _ => {}
         }
         true
     });
 
     // stop background watcher and join
-    let _ = stop_tx.send(());
-    let _ = handle.join();
+    watcher.stop().unwrap();
 
     assert!(saw, "manager background watcher did not load plugins");
 }
+
+#[test]
+fn manager_reports_added_modified_and_removed_files() {
+    let Some(lib) = built_plugin("plugin_multi") else {
+        return;
+    };
+    let tmpdir = tempfile::tempdir().expect("tmpdir");
+    let dest = tmpdir.path().join(lib.file_name().unwrap());
+    fs::copy(&lib, &dest).expect("copy plugin");
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    tx.send(WatchNotification::Paths(vec![dest.clone()]))
+        .unwrap();
+    tx.send(WatchNotification::Modified(vec![dest.clone()]))
+        .unwrap();
+    tx.send(WatchNotification::Unloaded {
+        path: dest.clone(),
+        counter: None,
+    })
+    .unwrap();
+    drop(tx);
+
+    let mut mgr = PluginManager::new();
+    let opts = WatchOptions {
+        auto_unload: true,
+        ..Default::default()
+    };
+    let mut events = Vec::new();
+    mgr.process_watch_notifications_blocking(tmpdir.path(), rx, PluginTrait::Greeter, opts, |n| {
+        if let ManagerNotification::Event(event) = n {
+            events.push(event);
+        }
+        true
+    });
+
+    assert_eq!(events.len(), 3, "{:?}", events);
+    assert!(
+        matches!(&events[0], WatchEvent::Added { path, handles } if *path == dest && handles.len() == 2)
+    );
+    // Without auto_reload the change is only reported, with the plugins
+    // still loaded from the file.
+    assert!(
+        matches!(&events[1], WatchEvent::Modified { path, handles } if *path == dest && handles.len() == 2)
+    );
+    // The handles above keep the library mapped, so the unload is deferred
+    // but nothing is reported as loaded from the file any more.
+    assert!(matches!(
+        &events[2],
+        WatchEvent::Removed { path, handles, counter: None } if *path == dest && handles.is_empty()
+    ));
+    assert!(mgr.list().is_empty());
+}
+
+#[test]
+fn excluded_files_are_not_loaded_alongside_others() {
+    let (Some(multi), Some(greeter)) = (built_plugin("plugin_multi"), built_plugin("plugin_a"))
+    else {
+        return;
+    };
+    let tmpdir = tempfile::tempdir().expect("tmpdir");
+    let link = |from: &PathBuf, name: &str| {
+        let to = tmpdir
+            .path()
+            .join(format!("{}.{}", name, std::env::consts::DLL_EXTENSION));
+        fs::hard_link(from, &to)
+            .or_else(|_| fs::copy(from, &to).map(|_| ()))
+            .expect("place plugin");
+        to
+    };
+    let wanted = link(&multi, "libplugin_multi");
+    link(&greeter, "libplugin_a_scratch");
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    tx.send(WatchNotification::Paths(vec![wanted.clone()]))
+        .unwrap();
+    drop(tx);
+
+    let mut mgr = PluginManager::new();
+    let opts = WatchOptions {
+        exclude: vec!["*_scratch.*".to_string()],
+        ..Default::default()
+    };
+    let mut events = Vec::new();
+    mgr.process_watch_notifications_blocking(tmpdir.path(), rx, PluginTrait::Greeter, opts, |n| {
+        events.push(n);
+        true
+    });
+
+    assert_eq!(events.len(), 1, "{:?}", events);
+    assert!(matches!(
+        &events[0],
+        ManagerNotification::Event(WatchEvent::Added { path, handles }) if *path == wanted && handles.len() == 2
+    ));
+    let listed = mgr.list();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].path, wanted);
+}
+
+#[test]
+fn bad_patterns_are_reported_before_processing() {
+    let (_tx, rx) = std::sync::mpsc::channel();
+    let opts = WatchOptions {
+        include: vec!["[unclosed".to_string()],
+        ..Default::default()
+    };
+    let mut errors = Vec::new();
+    PluginManager::new().process_watch_notifications_blocking(
+        std::path::Path::new("."),
+        rx,
+        PluginTrait::Greeter,
+        opts,
+        |n| {
+            if let ManagerNotification::Error(e) = n {
+                errors.push(e);
+            }
+            true
+        },
+    );
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].contains("[unclosed"), "{}", errors[0]);
+}
+
+#[test]
+fn one_session_watches_several_directories() {
+    let (Some(multi), Some(greeter)) = (built_plugin("plugin_multi"), built_plugin("plugin_a"))
+    else {
+        return;
+    };
+    let (system, user) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+    let opts = WatchOptions {
+        debounce_ms: 100,
+        stable_ms: 0,
+        ..Default::default()
+    };
+    // The user directory only takes plugin_a, so plugin_multi placed there
+    // is ignored.
+    let dirs = vec![
+        (system.path().to_path_buf(), opts.clone()),
+        (
+            user.path().to_path_buf(),
+            WatchOptions {
+                include: vec!["*plugin_a*".to_string()],
+                ..opts
+            },
+        ),
+    ];
+
+    let mut mgr = PluginManager::new();
+    let (rx, watcher) = mgr.start_watch_background_dirs(dirs.clone());
+
+    let places = [
+        (
+            multi.clone(),
+            system.path().join(multi.file_name().unwrap()),
+        ),
+        (multi.clone(), user.path().join(multi.file_name().unwrap())),
+        (
+            greeter.clone(),
+            user.path().join(greeter.file_name().unwrap()),
+        ),
+    ];
+    std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(150));
+        for (from, to) in places {
+            fs::hard_link(&from, &to)
+                .or_else(|_| fs::copy(&from, &to).map(|_| ()))
+                .expect("place plugin");
+        }
+    });
+
+    let (mut added, mut kept) = (Vec::new(), Vec::new());
+    mgr.process_dir_notifications_blocking(&dirs, rx, PluginTrait::Greeter, |dir, n| {
+        if let ManagerNotification::Event(WatchEvent::Added { path, handles }) = n {
+            added.push((dir.to_path_buf(), path, handles.len()));
+            kept.extend(handles);
+        }
+        added.len() < 2
+    });
+    watcher.stop().unwrap();
+
+    added.sort();
+    let mut expected = vec![
+        (
+            system.path().to_path_buf(),
+            system.path().join(multi.file_name().unwrap()),
+            2,
+        ),
+        (
+            user.path().to_path_buf(),
+            user.path().join(greeter.file_name().unwrap()),
+            1,
+        ),
+    ];
+    expected.sort();
+    assert_eq!(added, expected);
+    assert_eq!(mgr.list().len(), 2);
+}
+
+#[test]
+fn paused_watcher_holds_back_and_coalesces_events() {
+    use std::sync::mpsc::RecvTimeoutError;
+    use std::time::Duration;
+
+    // The background thread only looks at file names, so placeholder files
+    // stand in for plugins.
+    let tmpdir = tempfile::tempdir().expect("tmpdir");
+    let lib = |name: &str| {
+        tmpdir
+            .path()
+            .join(format!("{}.{}", name, std::env::consts::DLL_EXTENSION))
+    };
+    let (replaced, added) = (lib("libreplaced"), lib("libadded"));
+    fs::write(&replaced, b"v1").unwrap();
+
+    let mut mgr = PluginManager::new();
+    let opts = WatchOptions {
+        debounce_ms: 100,
+        stable_ms: 0,
+        ..Default::default()
+    };
+    let (rx, watcher) = mgr.start_watch_background(tmpdir.path().to_path_buf(), opts);
+    let control = watcher.control().clone();
+    control.pause();
+    assert!(control.is_paused());
+
+    std::thread::sleep(Duration::from_millis(150));
+    fs::remove_file(&replaced).unwrap();
+    fs::write(&replaced, b"v2").unwrap();
+    fs::write(&added, b"new").unwrap();
+    assert_eq!(
+        rx.recv_timeout(Duration::from_millis(500)).unwrap_err(),
+        RecvTimeoutError::Timeout,
+        "nothing is sent while paused"
+    );
+
+    control.resume();
+    let mut got = Vec::new();
+    while got.len() < 2 {
+        got.push(
+            rx.recv_timeout(Duration::from_secs(5))
+                .expect("notification"),
+        );
+    }
+    watcher.stop().unwrap();
+
+    // The replaced file comes back as modified, without a removal.
+    assert!(
+        got.iter()
+            .any(|n| matches!(n, WatchNotification::Modified(p) if *p == [replaced.clone()])),
+        "{:?}",
+        got
+    );
+    assert!(
+        got.iter()
+            .any(|n| matches!(n, WatchNotification::Paths(p) if *p == [added.clone()])),
+        "{:?}",
+        got
+    );
+    assert!(rx.try_recv().is_err());
+}
+
+#[test]
+fn watcher_threads_exit_with_their_receiver_or_guard() {
+    use std::time::{Duration, Instant};
+
+    let tmpdir = tempfile::tempdir().expect("tmpdir");
+    let dir = tmpdir.path().to_path_buf();
+    let mut mgr = PluginManager::new();
+    let opts = WatchOptions {
+        debounce_ms: 50,
+        stable_ms: 0,
+        ..Default::default()
+    };
+
+    // Nobody receives: the thread notices on its next notification.
+    let (rx, watcher) = mgr.start_watch_background(dir.clone(), opts);
+    drop(rx);
+    std::thread::sleep(Duration::from_millis(150));
+    let placeholder = dir.join(format!("libgone.{}", std::env::consts::DLL_EXTENSION));
+    fs::write(&placeholder, b"x").unwrap();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !watcher.is_finished() && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    assert!(watcher.is_finished(), "thread outlived its receiver");
+
+    // Dropping the guard stops the thread and waits for it.
+    let (_rx, watcher) = mgr.watch_plugins(dir, PluginTrait::Greeter);
+    let control = watcher.control().clone();
+    drop(watcher);
+    control.pause();
+    assert!(control.is_paused(), "controls outlive the guard");
+}
@@ -0,0 +1,30 @@
+//! Fixtures shared by the integration tests and benches of the workspace
+//! crates.
+
+use std::path::PathBuf;
+
+/// The cdylib `name` next to the running test binary, if it was built.
+///
+/// Workspace builds put the cdylib plugins in target/<profile>, one level
+/// above the test binary in target/<profile>/deps.
+pub fn built_plugin(name: &str) -> Option<PathBuf> {
+    let exe = std::env::current_exe().expect("current exe");
+    let target_dir = exe.parent().and_then(|p| p.parent()).expect("target dir");
+    let lib = target_dir.join(library_file_name(name));
+    if !lib.exists() {
+        eprintln!("plugin artifact not found at {:?}; skipping", lib);
+        return None;
+    }
+    Some(lib)
+}
+
+/// The file name of a cdylib called `name` on this platform, such as
+/// `libname.so` or `name.dll`.
+pub fn library_file_name(name: &str) -> String {
+    format!(
+        "{}{}.{}",
+        std::env::consts::DLL_PREFIX,
+        name,
+        std::env::consts::DLL_EXTENSION
+    )
+}
@@ -1,13 +1,20 @@
-use plugin_annotations::{plugin_aggregates, plugin_impl};
-use plugin_interface::Greeter;
+use plugin_annotations::{plugin_aggregates, plugin_impl, plugin_logging};
+use plugin_interface::{Greeter, HostInfo};
+use std::sync::atomic::{AtomicU64, Ordering};
 
-#[plugin_aggregates(Greeter)]
+#[plugin_aggregates(Greeter, abi = 3)]
+#[plugin_logging]
 
-pub struct MyGreeter;
+pub struct MyGreeter {
+    // carried over to the new version on hot reload
+    greeted: AtomicU64,
+}
 
 impl Default for MyGreeter {
     fn default() -> Self {
-        MyGreeter
+        MyGreeter {
+            greeted: AtomicU64::new(0),
+        }
     }
 }
 
@@ -17,6 +24,21 @@ impl Greeter for MyGreeter {
         "MyGreeter"
     }
     fn greet(&self, target: &str) {
+        self.greeted.fetch_add(1, Ordering::Relaxed);
         println!("Hello, {}! from MyGreeter", target);
     }
+    fn on_load(&self, host: &HostInfo) {
+        log::info!("MyGreeter loaded by host {}", host.host_version());
+    }
+    fn on_unload(&self) {
+        log::info!("MyGreeter unloading");
+    }
+    fn save_state(&self) -> Vec<u8> {
+        self.greeted.load(Ordering::Relaxed).to_le_bytes().to_vec()
+    }
+    fn restore_state(&mut self, bytes: &[u8]) {
+        if let Ok(bytes) = bytes.try_into() {
+            *self.greeted.get_mut() = u64::from_le_bytes(bytes);
+        }
+    }
 }
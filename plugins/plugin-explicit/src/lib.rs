@@ -0,0 +1,39 @@
+//! Two greeters registered through the explicit registration backend
+//! instead of `inventory`.
+
+use plugin_annotations::{collect_registrations, plugin_aggregates, plugin_impl};
+use plugin_interface::{Greeter, LogLevel};
+
+collect_registrations!(Greeter: Hello, Goodbye);
+
+// Level 2 for the host context its greeters log through.
+#[plugin_aggregates(Greeter, abi = 2)]
+#[derive(Default)]
+struct Hello;
+
+#[plugin_impl(Greeter)]
+impl Greeter for Hello {
+    fn name(&self) -> &str {
+        "Hello"
+    }
+    fn greet(&self, target: &str) {
+        if let Some(host) = crate::host_context() {
+            host.log(LogLevel::Info, &format!("Hello, {}", target));
+        }
+    }
+}
+
+#[derive(Default)]
+struct Goodbye;
+
+#[plugin_impl(Greeter, name = "goodbye")]
+impl Greeter for Goodbye {
+    fn name(&self) -> &str {
+        "Goodbye"
+    }
+    fn greet(&self, target: &str) {
+        if let Some(host) = crate::host_context() {
+            host.log(LogLevel::Info, &format!("Goodbye, {}", target));
+        }
+    }
+}
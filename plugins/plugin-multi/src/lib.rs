@@ -1,7 +1,8 @@
 use plugin_annotations::{plugin_aggregates, plugin_impl};
-use plugin_interface::Greeter;
+use plugin_interface::{Greeter, LogLevel, STR_VTABLE_ABI, StrRef, TransformerVTable};
 
-#[plugin_aggregates(Greeter)]
+// Level 2 for the host context its greeters log through.
+#[plugin_aggregates(Greeter, abi = 2)]
 #[derive(Default)]
 struct GreeterOne;
 
@@ -11,19 +12,42 @@ impl Greeter for GreeterOne {
         "GreeterOne"
     }
     fn greet(&self, target: &str) {
-        println!("Hello, {} from GreeterOne", target);
+        let msg = format!("Hello, {} from GreeterOne", target);
+        match crate::host_context() {
+            Some(host) => host.log(LogLevel::Info, &msg),
+            None => println!("{}", msg),
+        }
     }
 }
 
 #[derive(Default)]
 struct GreeterTwo;
 
-#[plugin_impl(Greeter)]
+#[plugin_impl(Greeter, name = "greeter-two", priority = 10)]
 impl Greeter for GreeterTwo {
     fn name(&self) -> &str {
         "GreeterTwo"
     }
     fn greet(&self, target: &str) {
-        println!("Hello, {} from GreeterTwo", target);
+        let msg = transformed(format!("Hello, {} from GreeterTwo", target));
+        match crate::host_context() {
+            Some(host) => host.log(LogLevel::Info, &msg),
+            None => println!("{}", msg),
+        }
+    }
+}
+
+/// `msg` run through the first `Transformer` another loaded plugin provides,
+/// if there is one.
+fn transformed(msg: String) -> String {
+    let Some(vtable) = crate::host_context().and_then(|h| h.get_service("Transformer", None))
+    else {
+        return msg;
+    };
+    let transformer = unsafe { &*(vtable as *const TransformerVTable) };
+    if transformer.abi_version < STR_VTABLE_ABI {
+        return msg;
     }
+    unsafe { (transformer.transform_str)(transformer.user_data, StrRef::new(&msg)).into_string() }
+        .unwrap_or(msg)
 }
@@ -0,0 +1,35 @@
+//! A greeter and a transformer from one crate, which aggregates both
+//! traits at different ABI levels.
+
+use plugin_annotations::{plugin_aggregates, plugin_impl};
+use plugin_interface::{Greeter, Transformer};
+
+#[plugin_aggregates(Greeter, abi = 3)]
+#[plugin_aggregates(Transformer, abi = 5)]
+#[derive(Default)]
+struct Hello;
+
+#[plugin_impl(Greeter)]
+impl Greeter for Hello {
+    fn name(&self) -> &str {
+        "Hello"
+    }
+    fn greet(&self, target: &str) {
+        if let Some(host) = crate::host_context() {
+            host.log(
+                plugin_interface::LogLevel::Info,
+                &format!("Hello, {}", target),
+            );
+        }
+    }
+}
+
+#[derive(Default)]
+struct Reverse;
+
+#[plugin_impl(Transformer)]
+impl Transformer for Reverse {
+    fn transform(&self, input: &str) -> String {
+        input.chars().rev().collect()
+    }
+}
@@ -0,0 +1,74 @@
+use plugin_annotations::{plugin_aggregates, plugin_impl};
+use plugin_interface::{with_current_request, Features, HealthStatus, HostInfo, Transformer};
+use std::ffi::c_void;
+use std::os::raw::c_char;
+
+// Tagging results with the caller's correlation ID needs hosts that pass a
+// request context.
+#[plugin_aggregates(Transformer, abi = 4, requires = Features::REQUEST_CONTEXT)]
+#[derive(Default)]
+struct Upper {
+    /// Appended to every result; set with `suffix = "..."` in the plugin's
+    /// configuration.
+    suffix: String,
+    /// Reported by `health`; set with `healthy = false` to try out
+    /// quarantine.
+    unhealthy: bool,
+}
+
+#[plugin_impl(Transformer, instances = 2)]
+impl Transformer for Upper {
+    /// Called with a request context carrying a correlation ID, tags the
+    /// result with it so the caller can tell which request it answers.
+    fn transform(&self, input: &str) -> String {
+        let out = input.to_uppercase() + &self.suffix;
+        match with_current_request(|r| r.correlation_id()).flatten() {
+            Some(id) => format!("{} #{}", out, id),
+            None => out,
+        }
+    }
+
+    #[optional]
+    fn describe(&self) -> String {
+        "uppercases its input".to_string()
+    }
+
+    #[optional]
+    fn health(&self) -> HealthStatus {
+        if self.unhealthy {
+            HealthStatus::Unhealthy
+        } else {
+            HealthStatus::Healthy
+        }
+    }
+
+    fn configure(&mut self, config: &str) {
+        if let Ok(table) = config.parse::<toml::Table>() {
+            if let Some(suffix) = table.get("suffix").and_then(|s| s.as_str()) {
+                self.suffix = suffix.to_string();
+            }
+            if let Some(healthy) = table.get("healthy").and_then(|h| h.as_bool()) {
+                self.unhealthy = !healthy;
+            }
+        }
+    }
+
+    fn on_load(&self, _host: &HostInfo) {
+        // Hosts that predate the handshake have an event bus or ignore
+        // subscriptions.
+        let bus = crate::host_features().is_none_or(|f| f.contains(Features::EVENT_BUS));
+        if let Some(host) = crate::host_context().filter(|_| bus) {
+            host.subscribe("upper.request", on_request, std::ptr::null_mut());
+        }
+    }
+}
+
+/// Answer every `upper.request` event with its text uppercased on
+/// `upper.reply`.
+extern "C" fn on_request(_: *mut c_void, _: *const c_char, payload: *const u8, len: usize) {
+    let text = unsafe { std::slice::from_raw_parts(payload, len) };
+    let reply = String::from_utf8_lossy(text).to_uppercase();
+    if let Some(host) = crate::host_context() {
+        let _ = host.publish("upper.reply", reply.as_bytes());
+    }
+}